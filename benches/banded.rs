@@ -0,0 +1,67 @@
+//!
+//! Drawing a 4K wallpaper-like frame on the CPU in one go, against in
+//! bands on four threads (see [draw_in_bands]), as for
+//! [avy_render::app::AvySurfaceHandle::render_parallel].
+//!
+//! The speedup needs four free cores to show: compare the two with
+//! `cargo bench --bench banded`.
+//!
+
+use avy_render::{
+    graphics::static_buffer::draw_in_bands,
+    util::{SizeSnapshot, Transform},
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use skia_safe::{gradient_shader, Canvas, Color, Paint, TileMode};
+
+const WIDTH: i32 = 3840;
+const HEIGHT: i32 = 2160;
+
+/// A gradient, with a scattering of anti-aliased circles over it.
+fn wallpaper(canvas: &Canvas) {
+    let shader = gradient_shader::linear(
+        ((0.0, 0.0), (WIDTH as f32, HEIGHT as f32)),
+        [Color::from_rgb(20, 40, 90), Color::from_rgb(230, 140, 60)].as_ref(),
+        None,
+        TileMode::Clamp,
+        None,
+        None,
+    );
+    let mut paint = Paint::default();
+    paint.set_shader(shader).set_dither(true);
+    canvas.draw_paint(&paint);
+
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    for i in 0..400 {
+        let x = (i * 787 % WIDTH) as f32;
+        let y = (i * 389 % HEIGHT) as f32;
+        paint.set_color(Color::from_argb(60, 255, (i % 256) as u8, 200));
+        canvas.draw_circle((x, y), 40.0 + (i % 7) as f32 * 20.0, &paint);
+    }
+}
+
+fn banded(c: &mut Criterion) {
+    let size = SizeSnapshot {
+        logical: (WIDTH as u32, HEIGHT as u32),
+        physical: (WIDTH as f64, HEIGHT as f64),
+        scale: 1.0,
+        transform: Transform::Normal,
+        generation: 0,
+    };
+    let mut pixels = vec![0; (WIDTH * HEIGHT * 4) as usize];
+
+    let mut group = c.benchmark_group("4K wallpaper");
+    group.sample_size(20);
+
+    for bands in [1, 4] {
+        group.bench_function(format!("{bands} bands"), |b| {
+            b.iter(|| draw_in_bands(&mut pixels, (WIDTH, HEIGHT), bands, &size, &wallpaper))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, banded);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+//!
+//! [RenderContext::blur_region] at sigma 20 over a 600×400 region of a 1080p
+//! frame, blurred again every time (as when what's behind it changes).
+//!
+//! This is on the CPU, with a raster surface: the budget of 1ms is for the
+//! GPU (an integrated one will do), where blurs are drawn in practice.
+//!
+
+use avy_render::{
+    graphics::{RenderContext, SharedContext},
+    util::{SizeSnapshot, Transform},
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use skia_safe::{surfaces, Color, Paint, Rect};
+
+fn blur_region(c: &mut Criterion) {
+    let size = SizeSnapshot {
+        logical: (1920, 1080),
+        physical: (1920.0, 1080.0),
+        scale: 1.0,
+        transform: Transform::Normal,
+        generation: 0,
+    };
+    let context = RenderContext::new(1, &size, &SharedContext::default());
+    let mut surface = surfaces::raster_n32_premul((1920, 1080)).unwrap();
+
+    // Something with edges to blur.
+    let canvas = surface.canvas();
+    let mut paint = Paint::default();
+    for i in 0..48 {
+        paint.set_color(Color::from_rgb((i * 5) as u8, 120, 255 - (i * 5) as u8));
+        canvas.draw_rect(Rect::from_xywh(i as f32 * 40.0, 0.0, 20.0, 1080.0), &paint);
+    }
+
+    c.bench_function("blur 600x400 at sigma 20", |b| {
+        b.iter(|| {
+            let canvas = surface.canvas();
+            canvas.save();
+            context.blur_region(canvas, Rect::from_xywh(660.0, 340.0, 600.0, 400.0), 20.0);
+            canvas.restore();
+        })
+    });
+}
+
+criterion_group!(benches, blur_region);
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+//!
+//! Drawing a row of 50 squircle icon outlines from a [PathCache] once their
+//! device-space paths are built (hits), against building them every frame
+//! and against the cache rebuilding them for a changed scale (misses).
+//!
+
+use avy_render::{
+    graphics::{path_cache::squircle, PathCache, RenderContext, SharedContext},
+    util::{SizeSnapshot, Transform},
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use skia_safe::{surfaces, Color, Paint, Path, Rect};
+
+const SHAPES: u64 = 50;
+
+fn context(scale: f64) -> RenderContext {
+    let size = SizeSnapshot {
+        logical: (1000, 40),
+        physical: (1000.0 * scale, 40.0 * scale),
+        scale,
+        transform: Transform::Normal,
+        generation: 0,
+    };
+
+    RenderContext::new(1, &size, &SharedContext::default())
+}
+
+fn shape(id: u64) -> Path {
+    squircle(Rect::from_xywh(id as f32 * 20.0, 2.0, 16.0, 16.0), 4.5)
+}
+
+fn path_cache(c: &mut Criterion) {
+    let (context, rescaled) = (context(2.0), context(1.5));
+    let mut surface = surfaces::raster_n32_premul((2000, 80)).unwrap();
+
+    let mut paint = Paint::default();
+    paint
+        .set_anti_alias(true)
+        .set_color(Color::from_rgb(97, 175, 239));
+
+    let mut cache = PathCache::new();
+    for id in 0..SHAPES {
+        cache.insert(id, shape(id));
+    }
+
+    let mut group = c.benchmark_group("50 shapes");
+
+    group.bench_function("cache hits", |b| {
+        b.iter(|| {
+            let canvas = surface.canvas();
+            canvas.clear(Color::TRANSPARENT);
+            canvas.save();
+            canvas.scale((2.0, 2.0));
+            for id in 0..SHAPES {
+                cache.draw(canvas, &context, &id, &paint);
+            }
+            canvas.restore();
+        })
+    });
+
+    group.bench_function("built every frame", |b| {
+        b.iter(|| {
+            let canvas = surface.canvas();
+            canvas.clear(Color::TRANSPARENT);
+            canvas.save();
+            canvas.scale((2.0, 2.0));
+            for id in 0..SHAPES {
+                canvas.draw_path(&shape(id), &paint);
+            }
+            canvas.restore();
+        })
+    });
+
+    // Every other frame at another scale, so every draw rebuilds its device path.
+    let mut frame = 0;
+    group.bench_function("cache misses (scale changes)", |b| {
+        b.iter(|| {
+            frame += 1;
+            let context = if frame % 2 == 0 { &context } else { &rescaled };
+            let scale = context.scale as f32;
+
+            let canvas = surface.canvas();
+            canvas.clear(Color::TRANSPARENT);
+            canvas.save();
+            canvas.scale((scale, scale));
+            for id in 0..SHAPES {
+                cache.draw(canvas, context, &id, &paint);
+            }
+            canvas.restore();
+        })
+    });
+
+    let mut masked = PathCache::new().with_mask_threshold(Some(0));
+    for id in 0..SHAPES {
+        masked.insert(id, shape(id));
+    }
+
+    group.bench_function("masked cache hits", |b| {
+        b.iter(|| {
+            let canvas = surface.canvas();
+            canvas.clear(Color::TRANSPARENT);
+            canvas.save();
+            canvas.scale((2.0, 2.0));
+            for id in 0..SHAPES {
+                masked.draw(canvas, &context, &id, &paint);
+            }
+            canvas.restore();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, path_cache);
+criterion_main!(benches);
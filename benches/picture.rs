@@ -0,0 +1,99 @@
+//!
+//! Replaying a [CachedPicture] against drawing the same element from scratch,
+//! for a row of 100 tray icons.
+//!
+
+use avy_render::{
+    graphics::{picture::CachedPicture, RenderContext, SharedContext},
+    util::{SizeSnapshot, Transform},
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use skia_safe::{surfaces, Canvas, Color, Paint, PaintStyle, Point, RRect, Rect};
+
+const ELEMENTS: usize = 100;
+
+/// Something like a tray icon: a few shapes, with anti-aliased edges.
+fn icon(canvas: &Canvas) {
+    let mut paint = Paint::default();
+    paint
+        .set_anti_alias(true)
+        .set_color(Color::from_rgb(40, 44, 52));
+    canvas.draw_rrect(
+        RRect::new_rect_xy(Rect::from_wh(16.0, 16.0), 4.0, 4.0),
+        &paint,
+    );
+
+    paint.set_color(Color::from_rgb(97, 175, 239));
+    canvas.draw_circle((8.0, 8.0), 4.5, &paint);
+
+    paint
+        .set_style(PaintStyle::Stroke)
+        .set_stroke_width(1.5)
+        .set_color(Color::WHITE);
+    canvas.draw_line((4.0, 12.0), (12.0, 4.0), &paint);
+}
+
+fn positions() -> Vec<Point> {
+    (0..ELEMENTS)
+        .map(|i| Point::new((i % 50) as f32 * 20.0, (i / 50) as f32 * 20.0))
+        .collect()
+}
+
+fn repeated_elements(c: &mut Criterion) {
+    let size = SizeSnapshot {
+        logical: (1000, 40),
+        physical: (2000.0, 80.0),
+        scale: 2.0,
+        transform: Transform::Normal,
+        generation: 0,
+    };
+    let context = RenderContext::new(1, &size, &SharedContext::default());
+    let positions = positions();
+    let mut surface = surfaces::raster_n32_premul((2000, 80)).unwrap();
+
+    let mut group = c.benchmark_group("100 elements");
+
+    group.bench_function("drawn every time", |b| {
+        b.iter(|| {
+            let canvas = surface.canvas();
+            canvas.clear(Color::TRANSPARENT);
+            for position in &positions {
+                canvas.save();
+                canvas.scale((2.0, 2.0));
+                canvas.translate(*position);
+                icon(canvas);
+                canvas.restore();
+            }
+        })
+    });
+
+    let mut picture = CachedPicture::new(Rect::from_wh(16.0, 16.0), icon);
+    group.bench_function("replayed picture", |b| {
+        b.iter(|| {
+            let canvas = surface.canvas();
+            canvas.clear(Color::TRANSPARENT);
+            canvas.save();
+            canvas.scale((2.0, 2.0));
+            picture.draw_at(canvas, &context, &positions, None);
+            canvas.restore();
+        })
+    });
+
+    let mut rasterized =
+        CachedPicture::new(Rect::from_wh(16.0, 16.0), icon).with_raster_threshold(Some(0));
+    group.bench_function("rasterized picture", |b| {
+        b.iter(|| {
+            let canvas = surface.canvas();
+            canvas.clear(Color::TRANSPARENT);
+            canvas.save();
+            canvas.scale((2.0, 2.0));
+            rasterized.draw_at(canvas, &context, &positions, None);
+            canvas.restore();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, repeated_elements);
+criterion_main!(benches);
@@ -0,0 +1,119 @@
+//!
+//! A panel echoing the pointer, keyboard and touch input it gets, through
+//! [AvyLayerController::set_on_input]. Click it to give it the keyboard.
+//!
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use avy_render::{
+    graphics::vulkan::Vulkan,
+    run::{run, RunOptions},
+    timer::TimerAction,
+    wayland::surface::layer::{AvyLayerController, LayerInput},
+    Avy,
+};
+use skia_safe::{Color4f, Font, Paint};
+use smithay_client_toolkit::{
+    seat::pointer::PointerEventKind,
+    shell::wlr_layer::{Anchor, KeyboardInteractivity},
+};
+use vulkano::Version;
+
+const HEIGHT: u32 = 160;
+const LINES: usize = 6;
+const FRAME: Duration = Duration::from_millis(16);
+
+fn describe(input: &LayerInput) -> Option<String> {
+    let line = match input {
+        LayerInput::Pointer { event, drawing } => {
+            let (x, y) = (drawing.x, drawing.y);
+            match &event.kind {
+                PointerEventKind::Motion { .. } => return None,
+                PointerEventKind::Press { button, .. } => {
+                    format!("Button {button:#x} pressed at {x:.0}, {y:.0}")
+                }
+                PointerEventKind::Release { button, .. } => {
+                    format!("Button {button:#x} released at {x:.0}, {y:.0}")
+                }
+                PointerEventKind::Axis { vertical, .. } => {
+                    format!("Scrolled by {:.1}", vertical.absolute)
+                }
+                PointerEventKind::Enter { .. } => "Pointer entered".to_owned(),
+                PointerEventKind::Leave { .. } => "Pointer left".to_owned(),
+            }
+        }
+        LayerInput::KeyboardEnter => "Got the keyboard".to_owned(),
+        LayerInput::KeyboardLeave => "Lost the keyboard".to_owned(),
+        LayerInput::KeyPress(event) => format!(
+            "Pressed {:?} ({:?})",
+            event.keysym,
+            event.utf8.as_deref().unwrap_or("")
+        ),
+        LayerInput::KeyRelease(_) => return None,
+        LayerInput::TouchDown { id, drawing, .. } => {
+            format!("Touch #{id} down at {:.0}, {:.0}", drawing.x, drawing.y)
+        }
+        LayerInput::TouchMotion { .. } => return None,
+        LayerInput::TouchUp { id } => format!("Touch #{id} up"),
+        LayerInput::TouchCancel => "Touches cancelled".to_owned(),
+    };
+
+    Some(line)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = RunOptions::from_args()?;
+    let vulkan = Vulkan::new("input_echo", Version::major_minor(0, 1))?;
+    let mut avy = Avy::connect((1920, HEIGHT))?;
+
+    let mut params = options.bar(&avy, "input-echo", Anchor::BOTTOM, HEIGHT)?;
+    params.keyboard_interactivity = KeyboardInteractivity::OnDemand;
+    let registered = avy.create_layer(params)?;
+    let layer: AvyLayerController = registered.layer_controller().unwrap();
+    let surface = registered.make_backend(&vulkan)?;
+
+    let lines = Arc::new(Mutex::new(VecDeque::from([
+        "Waiting for input...".to_owned()
+    ])));
+    let (echoed, dirty) = (lines.clone(), surface.dirty_flag());
+    layer.set_on_input(move |input| {
+        if let Some(line) = describe(&input) {
+            let mut lines = echoed.lock().unwrap();
+            lines.push_front(line);
+            lines.truncate(LINES);
+            dirty.mark();
+        }
+    });
+
+    let mut font = Font::default();
+    font.set_size(18.0);
+    let text = Paint::new(Color4f::new(0.9, 0.9, 0.9, 1.0), None);
+    surface.mark_dirty();
+
+    run(avy, move |app| {
+        app.add_timer(Duration::ZERO, move |_| {
+            if surface.is_dirty() {
+                let rendered = options.backend.render(&surface, |canvas, _| {
+                    canvas.clear(Color4f::new(0.1, 0.1, 0.1, 0.9));
+                    for (i, line) in lines.lock().unwrap().iter().enumerate() {
+                        canvas.draw_str(line, (16, 32 + 24 * i as i32), &font, &text);
+                    }
+                });
+
+                if let Err(err) = rendered {
+                    eprintln!("Could not render: {err}");
+                }
+            }
+
+            TimerAction::Repeat(FRAME)
+        })?;
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//!
+//! A clock in the corner of every output (or just the one given with
+//! `--output`), each its own layer surface, labelled with its output's name.
+//!
+//! The time is set in tabular figures, so it doesn't jitter as digits change.
+//!
+
+use std::time::{Duration, SystemTime};
+
+use avy_render::{
+    graphics::{
+        text::{system_fonts, FontFeatures, TextLayout, TextSpan},
+        vulkan::Vulkan,
+    },
+    run::{run, RunOptions},
+    timer::TimerAction,
+    util::Size,
+    wayland::surface::layer::AvyLayerParams,
+    Avy,
+};
+use skia_safe::{Color4f, Font, Paint};
+use smithay_client_toolkit::shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer};
+use vulkano::Version;
+
+const SIZE: (u32, u32) = (220, 56);
+
+///
+/// The time of day, in UTC.
+///
+fn now() -> String {
+    let since_epoch = SystemTime::UNIX_EPOCH.elapsed().unwrap_or_default();
+    let seconds = since_epoch.as_secs() % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = RunOptions::from_args()?;
+    let vulkan = Vulkan::new("per_output_clock", Version::major_minor(0, 1))?;
+    let mut avy = Avy::connect(SIZE)?;
+
+    let mut clocks = Vec::new();
+    for output in options.outputs(&avy)? {
+        let name = avy.output_state.info(&output).and_then(|info| info.name);
+        let surface = avy
+            .create_layer(AvyLayerParams {
+                layer: Layer::Overlay,
+                namespace: Some("clock"),
+                output: Some(output),
+                anchor: Anchor::TOP | Anchor::RIGHT,
+                size: Size::new(SIZE),
+                margin: Some((16, 16, 0, 0)),
+                exclusive_zone: None,
+                keyboard_interactivity: KeyboardInteractivity::None,
+                manual_configure_ack: false,
+            })?
+            .make_backend(&vulkan)?;
+
+        clocks.push((name.unwrap_or_else(|| "Unnamed output".to_owned()), surface));
+    }
+
+    let mut font = Font::default();
+    font.set_size(20.0);
+    let white = Color4f::new(1.0, 1.0, 1.0, 1.0);
+    let text = Paint::new(white, None);
+    let fonts = system_fonts();
+    let figures = FontFeatures::new().tabular_figures().lining_figures();
+
+    run(avy, move |app| {
+        app.add_timer(Duration::ZERO, move |_| {
+            let time = now();
+            for (name, surface) in &clocks {
+                let rendered = options.backend.render(surface, |canvas, context| {
+                    canvas.clear(Color4f::new(0.1, 0.1, 0.1, 0.85));
+
+                    let span = TextSpan::new(&time, 20.0)
+                        .with_color(white)
+                        .with_features(figures.clone());
+                    let layout = TextLayout::new(&[span], &fonts, &context.paragraph_style());
+                    layout.draw(canvas, (12.0, 24.0 - layout.baseline()));
+
+                    canvas.draw_str(name, (12, 46), &font, &text);
+                });
+
+                if let Err(err) = rendered {
+                    eprintln!("Could not render the clock on {name}: {err}");
+                }
+            }
+
+            TimerAction::Repeat(Duration::from_secs(1))
+        })?;
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
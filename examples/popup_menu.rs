@@ -0,0 +1,168 @@
+//!
+//! A bar with a [Button] summoning a menu of more buttons, shown and
+//! dismissed (with a fade, or with Escape) by an [OverlayController].
+//!
+//! Needs the `controls` feature: `cargo run --example popup_menu --features controls`.
+//!
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use avy_render::{
+    graphics::vulkan::Vulkan,
+    run::{run, RunOptions},
+    timer::TimerAction,
+    util::{GlobalRect, OutputGeometry, Size},
+    wayland::surface::layer::{AvyLayerController, AvyLayerParams, LayerInput},
+    widgets::{Button, Control, OverlayController, OverlayOptions},
+    Avy,
+};
+use skia_safe::{Color4f, Rect};
+use smithay_client_toolkit::shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer};
+use vulkano::Version;
+
+const BAR_HEIGHT: u32 = 48;
+const ITEM_HEIGHT: u32 = 40;
+const MENU_WIDTH: u32 = 200;
+const ITEMS: [&str; 3] = ["Settings", "Lock", "Log out"];
+const FRAME: Duration = Duration::from_millis(16);
+
+///
+/// Hand `layer`'s pointer and touch input to `buttons`.
+///
+fn forward(layer: &AvyLayerController, buttons: Arc<Mutex<Vec<Button>>>) {
+    layer.set_on_input(move |input| {
+        for button in buttons.lock().unwrap().iter_mut() {
+            match &input {
+                LayerInput::Pointer { event, .. } => {
+                    button.pointer_event(event);
+                }
+                LayerInput::TouchDown { id, position, .. } => {
+                    button.touch_down(*id, *position);
+                }
+                LayerInput::TouchMotion { id, position, .. } => {
+                    button.touch_motion(*id, *position);
+                }
+                LayerInput::TouchUp { id } => {
+                    button.touch_up(*id);
+                }
+                LayerInput::TouchCancel => button.touch_cancel(),
+                _ => (),
+            }
+        }
+    });
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = RunOptions::from_args()?;
+    let vulkan = Vulkan::new("popup_menu", Version::major_minor(0, 1))?;
+    let mut avy = Avy::connect((1920, BAR_HEIGHT))?;
+
+    let params = options.bar(&avy, "menu-bar", Anchor::BOTTOM, BAR_HEIGHT)?;
+    let bar = avy.create_layer(params)?;
+    let bar_layer = bar.layer_controller().unwrap();
+    let bar = bar.make_backend(&vulkan)?;
+
+    // In the output's bottom-left corner, moved up out of the bar's way.
+    let menu_height = ITEM_HEIGHT * ITEMS.len() as u32;
+    let output = options.outputs(&avy)?.into_iter().next();
+    let placed = output.as_ref().and_then(|output| {
+        let info = avy.output_state.info(output)?;
+        let geometry = OutputGeometry::from_info(&info)?;
+        let corner = GlobalRect::new(
+            (geometry.position.0 + 8) as f64,
+            (geometry.position.1 + geometry.size.1 - menu_height as i32 - 8) as f64,
+            MENU_WIDTH as f64,
+            menu_height as f64,
+        );
+        avy.place_popup(output, corner)
+    });
+    let (anchor, margin) = match placed {
+        Some(margin) => (Anchor::TOP | Anchor::LEFT, (margin.top, 0, 0, margin.left)),
+        // The output's geometry isn't known (yet), so go by the margins alone.
+        None => (Anchor::BOTTOM | Anchor::LEFT, (0, 0, 8, 8)),
+    };
+
+    let menu = avy.create_layer(AvyLayerParams {
+        layer: Layer::Overlay,
+        namespace: Some("menu"),
+        output,
+        anchor,
+        size: Size::new((MENU_WIDTH, menu_height)),
+        margin: Some(margin),
+        exclusive_zone: None,
+        keyboard_interactivity: KeyboardInteractivity::None,
+        manual_configure_ack: false,
+    })?;
+    let menu_layer = menu.layer_controller().unwrap();
+    let menu = menu.make_backend(&vulkan)?;
+
+    run(avy, move |app| {
+        let overlay = OverlayController::new(app, &menu, OverlayOptions::default());
+
+        let toggle = overlay.clone();
+        let summon = Button::new("Menu")
+            .track_dirty(bar.dirty_flag())
+            .on_click(move || toggle.toggle());
+        let summon = Arc::new(Mutex::new(vec![summon]));
+        forward(&bar_layer, summon.clone());
+
+        let items = ITEMS.map(|item| {
+            let overlay = overlay.clone();
+            Button::new(item)
+                .track_dirty(menu.dirty_flag())
+                .on_click(move || {
+                    println!("Picked {item}.");
+                    overlay.dismiss();
+                })
+        });
+        let items = Arc::new(Mutex::new(Vec::from(items)));
+        forward(&menu_layer, items.clone());
+        bar.mark_dirty();
+
+        app.add_timer(Duration::ZERO, move |_| {
+            if bar.is_dirty() {
+                let rendered = options.backend.render(&bar, |canvas, context| {
+                    canvas.clear(Color4f::new(0.1, 0.1, 0.1, 1.0));
+                    let bounds = Rect::from_xywh(8.0, 6.0, 100.0, BAR_HEIGHT as f32 - 12.0);
+                    summon.lock().unwrap()[0].draw(canvas, context, bounds);
+                });
+
+                if let Err(err) = rendered {
+                    eprintln!("Could not render the bar: {err}");
+                }
+            }
+
+            let opacity = overlay.opacity();
+            if menu.is_dirty() && (opacity > 0.0 || overlay.state().is_shown()) {
+                let rendered = options.backend.render(&menu, |canvas, context| {
+                    canvas.save_layer_alpha_f(None, opacity);
+                    canvas.clear(Color4f::new(0.15, 0.15, 0.15, 1.0));
+                    for (i, item) in items.lock().unwrap().iter_mut().enumerate() {
+                        let top = (i as u32 * ITEM_HEIGHT) as f32;
+                        let bounds = Rect::from_xywh(
+                            4.0,
+                            top + 4.0,
+                            MENU_WIDTH as f32 - 8.0,
+                            ITEM_HEIGHT as f32 - 8.0,
+                        );
+                        item.draw(canvas, context, bounds);
+                    }
+                    canvas.restore();
+                });
+
+                if let Err(err) = rendered {
+                    eprintln!("Could not render the menu: {err}");
+                }
+            }
+
+            TimerAction::Repeat(FRAME)
+        })?;
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
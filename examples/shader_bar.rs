@@ -0,0 +1,86 @@
+//!
+//! A bar along the bottom of the screen, filled with an animated shader
+//! through [EffectGraph].
+//!
+//! Shader by @notargs, from https://x.com/notargs/status/1250468645030858753 -- Thank you!
+//!
+
+use std::time::Duration;
+
+use avy_render::{
+    graphics::{vulkan::Vulkan, EffectGraph},
+    run::{run, RunOptions},
+    timer::TimerAction,
+    Avy,
+};
+use skia_safe::Paint;
+use smithay_client_toolkit::shell::wlr_layer::Anchor;
+use vulkano::Version;
+
+const HEIGHT: u32 = 60;
+const FRAME: Duration = Duration::from_millis(16);
+
+const SHADER: &str = r#"
+uniform float iTime;
+uniform float2 iResolution;
+float f(vec3 p) {
+    p.z -= iTime * 10.;
+    float a = p.z * .1;
+    p.xy *= mat2(cos(a), sin(a), -sin(a), cos(a));
+    return .1 - length(cos(p.xy) + sin(p.yz));
+}
+
+half4 main(vec2 fragcoord) {
+    vec3 d = .5 - fragcoord.xy1 / iResolution.y;
+    vec3 p = vec3(0);
+    for (int i = 0; i < 32; i++) {
+      p += f(p) * d;
+    }
+    return ((sin(p) + vec3(2, 5, 12)) / length(p)).xyz1;
+}
+"#;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = RunOptions::from_args()?;
+    let vulkan = Vulkan::new("shader_bar", Version::major_minor(0, 1))?;
+    let mut avy = Avy::connect((1920, HEIGHT))?;
+
+    let params = options.bar(&avy, "shader-bar", Anchor::BOTTOM, HEIGHT)?;
+    let surface = avy.create_layer(params)?.make_backend(&vulkan)?;
+
+    let effect = EffectGraph::compile(SHADER, &[])?;
+    let started = avy.clock().now();
+
+    run(avy, move |app| {
+        app.add_timer(Duration::ZERO, move |_| {
+            let rendered = options.backend.render(&surface, |canvas, context| {
+                let (width, height) = context.drawing_size;
+                let time = context.now().saturating_duration_since(started);
+                let shader = effect
+                    .clone()
+                    .uniform("iResolution", &[width as f32, height as f32])
+                    .uniform("iTime", &[time.as_secs_f32() / 15.0])
+                    .build();
+
+                match shader {
+                    Ok(shader) => {
+                        let mut paint = Paint::default();
+                        paint.set_shader(shader);
+                        canvas.draw_paint(&paint);
+                    }
+                    Err(err) => eprintln!("Could not build the shader: {err}"),
+                }
+            });
+
+            if let Err(err) = rendered {
+                eprintln!("Could not render: {err}");
+            }
+
+            TimerAction::Repeat(FRAME)
+        })?;
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
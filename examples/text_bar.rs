@@ -0,0 +1,89 @@
+//!
+//! A bar of text: a [StripLayout] of differently painted segments, and a
+//! [Label] too long for its slot, which scrolls through. Only redrawn
+//! whilst something changed, see [Label::track_dirty].
+//!
+
+use std::time::Duration;
+
+use avy_render::{
+    graphics::{vulkan::Vulkan, Label, OverflowBehavior, Segment, StripLayout},
+    run::{run, RunOptions},
+    timer::TimerAction,
+    Avy,
+};
+use skia_safe::{Color4f, Font, FontMgr, FontStyle, Paint, Rect};
+use smithay_client_toolkit::shell::wlr_layer::Anchor;
+use vulkano::Version;
+
+const HEIGHT: u32 = 48;
+const MARQUEE_WIDTH: f32 = 320.0;
+const FRAME: Duration = Duration::from_millis(16);
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = RunOptions::from_args()?;
+    let vulkan = Vulkan::new("text_bar", Version::major_minor(0, 1))?;
+    let mut avy = Avy::connect((1920, HEIGHT))?;
+
+    let params = options.bar(&avy, "text-bar", Anchor::TOP, HEIGHT)?;
+    let surface = avy.create_layer(params)?.make_backend(&vulkan)?;
+
+    let font = match FontMgr::new().match_family_style("Inter", FontStyle::bold()) {
+        Some(inter) => Font::from_typeface(inter, Some(28.0)),
+        None => {
+            let mut font = Font::default();
+            font.set_size(28.0);
+            font
+        }
+    };
+    let text = Paint::new(Color4f::new(0.9, 0.9, 0.9, 1.0), None);
+    let accent = Paint::new(Color4f::new(0.4, 0.6, 1.0, 1.0), None);
+
+    let mut marquee = Label::new("Now playing: a rather long song title", &font, &text)
+        .with_overflow(OverflowBehavior::Marquee {
+            speed: 80.0,
+            pause: Duration::from_secs(1),
+        })
+        .track_dirty(surface.dirty_flag());
+    surface.mark_dirty();
+
+    run(avy, move |app| {
+        app.add_timer(Duration::ZERO, move |_| {
+            if !surface.is_dirty() {
+                return TimerAction::Repeat(FRAME);
+            }
+
+            let rendered = options.backend.render(&surface, |canvas, context| {
+                canvas.clear(Color4f::new(0.1, 0.1, 0.1, 1.0));
+
+                let (width, height) = context.drawing_size;
+                let (width, height) = (width as f32, height as f32);
+                let marquee_rect =
+                    Rect::from_xywh(width - MARQUEE_WIDTH - 16.0, 0.0, MARQUEE_WIDTH, height);
+
+                StripLayout::for_context(context)
+                    .with(Segment::text(0, "Text ", &font, &text))
+                    .with(Segment::text(1, "in", &font, &accent))
+                    .with(Segment::text(2, " a strip, at ", &font, &text))
+                    .with(Segment::text(3, "Avy", &font, &accent))
+                    .with(Segment::text(4, ".", &font, &text))
+                    .draw(
+                        canvas,
+                        Rect::from_xywh(16.0, 0.0, marquee_rect.left - 32.0, height),
+                    );
+
+                marquee.draw(canvas, context, marquee_rect, context.now());
+            });
+
+            if let Err(err) = rendered {
+                eprintln!("Could not render: {err}");
+            }
+
+            TimerAction::Repeat(FRAME)
+        })?;
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
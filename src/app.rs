@@ -1,326 +1,3954 @@
 #![allow(unused)]
 use std::{
+    any::Any,
     collections::HashMap,
     marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
     process::id,
-    sync::{Arc, Mutex, RwLock},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, MutexGuard, RwLock, TryLockError,
+    },
+    time::{Duration, Instant},
 };
 
+use skia_safe::{BlendMode, ClipOp, Color, PixelGeometry, RRect, Rect};
 use smithay_client_toolkit::{
-    compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_pointer,
-    delegate_registry, delegate_relative_pointer, delegate_seat, delegate_shm, delegate_touch,
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_compositor, delegate_keyboard, delegate_output, delegate_pointer,
+    delegate_primary_selection, delegate_registry, delegate_relative_pointer, delegate_seat,
+    delegate_shm, delegate_subcompositor, delegate_touch,
+    globals::GlobalData,
     output::{OutputHandler, OutputState},
+    primary_selection::PrimarySelectionManagerState,
     reexports::{
+        calloop::LoopHandle,
         client::{
+            delegate_dispatch,
             globals::GlobalList,
             protocol::{
-                wl_display::WlDisplay, wl_keyboard::WlKeyboard, wl_pointer::WlPointer,
-                wl_surface::WlSurface, wl_touch::WlTouch,
+                wl_compositor::WlCompositor, wl_display::WlDisplay, wl_keyboard::WlKeyboard,
+                wl_output::WlOutput, wl_pointer::WlPointer, wl_shm::WlShm, wl_surface::WlSurface,
+                wl_touch::WlTouch,
             },
-            Connection, EventQueue, Proxy, QueueHandle,
+            Connection, Dispatch, EventQueue, Proxy, QueueHandle,
         },
         protocols::wp::{
             relative_pointer::zv1::client::zwp_relative_pointer_v1::ZwpRelativePointerV1,
             viewporter::client::wp_viewport::WpViewport,
         },
+        protocols_wlr::layer_shell::v1::client::{
+            zwlr_layer_shell_v1::ZwlrLayerShellV1,
+            zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+        },
     },
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
     seat::{
-        keyboard::{KeyboardData, KeyboardHandler},
-        pointer::{PointerData, PointerHandler},
+        keyboard::{KeyEvent, KeyboardData, KeyboardHandler, Keymap},
+        pointer::{
+            cursor_shape::CursorShapeManager, PointerData, PointerEventKind, PointerHandler,
+        },
         relative_pointer::{RelativePointerHandler, RelativePointerState},
         touch::{TouchData, TouchHandler},
         Capability, SeatHandler, SeatState,
     },
     shell::{
-        wlr_layer::{LayerShell, LayerShellHandler},
+        wlr_layer::{LayerShell, LayerShellHandler, LayerSurface, LayerSurfaceData},
         WaylandSurface,
     },
     shm::{Shm, ShmHandler},
+    subcompositor::SubcompositorState,
 };
+use thiserror::Error;
 use wayland_backend::client::ObjectId;
 
 use crate::{
-    delegate_fractional_scale, delegate_viewporter,
-    graphics::{GraphicsBackend, GraphicsSurface},
-    util::Size,
+    cursor::Cursor,
+    debug::{
+        self,
+        trace::{FrameScope, WAYLAND_TARGET},
+        IncidentKind, IncidentLog, RenderIncident,
+    },
+    delegate_fractional_scale, delegate_hyprland_global_shortcuts, delegate_idle_notify,
+    delegate_viewporter,
+    graphics::{
+        blur::BlurCache,
+        draw::CornerRadii,
+        draw_and_present,
+        filter::{PostFilter, PostFilterState},
+        pixel_geometry_for,
+        quality::{frame_cost, AdaptiveQuality, QualityController},
+        raw_window::AvySurfaceWindow,
+        snapshot::{FrameSnapshot, Snapshots},
+        static_buffer::{StaticBuffer, StaticBufferError},
+        CallbackPanic, ClearBehavior, DeviceLock, Frame, FrameOptions, FrameTimings,
+        GraphicsBackend, GraphicsSurface, RenderContext, SharedContext, SurfaceSuspended,
+        TrimLevel,
+    },
+    idle::IdleWatches,
+    locale::LanguageIdentifier,
+    memory::{self, DEFAULT_IDLE_TRIM},
+    metrics::{LoopMetrics, MetricsConfig, MetricsRecorder},
+    proxy::ProxyQueue,
+    selection::PrimarySelection,
+    shortcuts::GlobalShortcuts,
+    theme::{AccessibilityOptions, Appearance},
+    timer::{TimerAction, TimerToken, Timers},
+    util::{
+        animation::Easing,
+        dpi::{FontScale, OutputDpi},
+        Clock, DirtyFlag, ScaleMode, SharedClock, Size, SizeSnapshot, Transform,
+    },
     wayland::{
+        error::{FatalErrorCallback, ObjectRegistry},
+        keymap::KeymapInfo,
+        pointer::{coalesce, MotionCoalescing},
         protocol::{
             fractional_scale::{FractionalScaleHandler, FractionalScaleManager, ScaleFactor},
+            hyprland_global_shortcuts::GlobalShortcutsManager,
+            idle_notify::IdleNotifier,
             viewporter::{Viewport, Viewporter},
         },
-        surface::AvySurface,
+        seat::{release_device, Capabilities, InputDevice},
+        serial::{SerialKind, Serials},
+        surface::{
+            configure::{ConfigureAck, PendingConfigure},
+            deferred::{defer, DeferredEvents, DeferredKeyboardEvent},
+            destroy::DestroyRequests,
+            events::{Subscription, SurfaceEvent, SurfaceEvents},
+            layer::{AvyLayer, AvyLayerController},
+            layout::EdgeLayout,
+            registry::{NameTaken, SurfaceRegistry},
+            subsurface::{AvySubsurfaceController, SubsurfaceStacks},
+            AvySurface, SurfaceController, SurfaceKind, WrongKind,
+        },
     },
+    widgets::overlay::Overlays,
+};
+#[cfg(feature = "hyprland-surface")]
+use crate::{
+    delegate_hyprland_surface,
+    wayland::protocol::hyprland::{HyprlandSurface, HyprlandSurfaceManager},
+};
+#[cfg(feature = "input-latency")]
+use crate::{
+    delegate_presentation,
+    wayland::protocol::presentation::{
+        InputLatency, LatencyError, LatencyReport, PresentationManager,
+    },
+};
+#[cfg(feature = "virtual-keyboard")]
+use crate::{
+    delegate_virtual_keyboard, wayland::protocol::virtual_keyboard::VirtualKeyboardManager,
+};
+#[cfg(feature = "workspaces")]
+use crate::{
+    integrations::workspaces::Workspaces, wayland::protocol::ext_workspace::ExtWorkspaceState,
 };
 
+///
+/// Renders a registered surface, from any thread.
+///
+/// Handles are cheap to clone and can be sent and shared between threads.
+/// Renders into the same surface are serialized, as are renders into surfaces
+/// sharing a device (see [crate::graphics::DeviceLock]): concurrent calls to
+/// [AvySurfaceHandle::render] wait their turn, whilst
+/// [AvySurfaceHandle::try_render] gives up instead.
+///
 pub struct AvySurfaceHandle<G> {
     __: PhantomData<G>,
+    wl_surface: WlSurface,
+    /// For [AvySurfaceHandle::render_static].
+    wl_shm: WlShm,
     size: Arc<RwLock<Size>>,
     backend: Arc<Mutex<dyn GraphicsSurface>>,
+    device_lock: DeviceLock,
+    shared: Arc<RwLock<SharedContext>>,
+    state: Arc<SurfaceShared>,
 }
 
-impl<G: GraphicsBackend> AvySurfaceHandle<G> {
-    pub fn render(&self, mut callback: impl FnMut(&skia_safe::Canvas)) -> Result<(), G::Error>
-    where
-        G::Error: 'static,
-    {
-        self.backend
-            .lock()
-            .unwrap()
-            .render(&self.size.read().unwrap(), &mut callback)
-            .map_err(|err| *err.downcast::<G::Error>().unwrap())
+impl<G> Clone for AvySurfaceHandle<G> {
+    fn clone(&self) -> Self {
+        Self {
+            __: PhantomData,
+            wl_surface: self.wl_surface.clone(),
+            wl_shm: self.wl_shm.clone(),
+            size: self.size.clone(),
+            backend: self.backend.clone(),
+            device_lock: self.device_lock.clone(),
+            shared: self.shared.clone(),
+            state: self.state.clone(),
+        }
     }
 }
 
-pub struct RegisteredSurface<'a>(&'a mut AvyClient, ObjectId);
-
-impl<'a> RegisteredSurface<'a> {
-    pub fn make_backend<G: GraphicsBackend>(
-        self,
-        backend: &G,
-    ) -> Result<AvySurfaceHandle<G>, G::Error>
-    where
-        G::Surface: 'static,
-    {
-        let id = self.1;
-        let surface = self.0.surfaces.get(&id).unwrap().as_ref();
-        let backend = backend.for_surface(&self.0.wl_display, surface)?;
-
-        let backend = Arc::new(Mutex::new(backend));
-        self.0.surface_backends.insert(id.clone(), backend.clone());
+///
+/// A frame which failed to render, with the backend's error.
+///
+#[derive(Debug, Error)]
+#[error("Frame {frame} failed: {error}")]
+pub struct RenderError<E: std::error::Error + 'static> {
+    /// Its number, see [RenderContext::frame].
+    pub frame: u64,
+    #[source]
+    pub error: E,
+}
 
-        Ok(AvySurfaceHandle {
-            __: PhantomData,
-            size: surface.size().clone(),
-            backend,
-        })
+impl<E: std::error::Error + 'static> RenderError<E> {
+    pub fn into_inner(self) -> E {
+        self.error
     }
 }
-pub struct AvyClient {
-    pub wl_display: WlDisplay,
-    pub registry_state: RegistryState,
-    pub compositor_state: CompositorState,
-    pub output_state: OutputState,
-    pub shm_state: Shm,
-    pub layer_state: LayerShell,
-    pub fractional_scale: FractionalScaleManager,
-    pub viewporter: Viewporter,
-    pub seat_state: SeatState,
-    pub relative_pointer_state: RelativePointerState,
 
-    pub surfaces: HashMap<ObjectId, Box<dyn AvySurface>>,
-    pub surface_backends: HashMap<ObjectId, Arc<Mutex<dyn GraphicsSurface>>>,
+///
+/// Why [AvyClient::rebind_backend] failed.
+///
+#[derive(Debug, Error)]
+pub enum RebindError<E: std::error::Error + 'static> {
+    #[error("There's no surface {0} with a backend to rebind.")]
+    UnknownSurface(ObjectId),
 
-    pub pointer: Option<WlPointer>,
-    pub relative_pointer: Option<ZwpRelativePointerV1>,
+    #[error(transparent)]
+    Backend(#[from] E),
+}
 
-    pub keyboard: Option<WlKeyboard>,
-    pub keyboard_focus: Option<ObjectId>,
+///
+/// Why [AvySurfaceHandle::try_render] didn't render.
+///
+#[derive(Debug, Error)]
+pub enum TryRenderError<E: std::error::Error + 'static> {
+    #[error("Another thread is rendering on this surface (or device).")]
+    WouldBlock,
 
-    pub touch: Option<WlTouch>,
-    pub active_touches: HashMap<i32, ObjectId>,
+    #[error(transparent)]
+    Render(#[from] RenderError<E>),
+}
 
-    pub running: bool,
+///
+/// Why [AvySurfaceHandle::render_static] failed.
+///
+#[derive(Debug, Error)]
+pub enum StaticRenderError<E: std::error::Error + 'static> {
+    #[error(transparent)]
+    Buffer(#[from] StaticBufferError),
+
+    #[error(transparent)]
+    Render(RenderError<E>),
 }
 
-impl AvyClient {
-    pub fn new(
-        global_list: &GlobalList,
-        queue_handle: &QueueHandle<Self>,
-        logical_size: (u32, u32),
-        wl_display: WlDisplay,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self {
-            wl_display,
-            registry_state: RegistryState::new(global_list),
-            compositor_state: CompositorState::bind(global_list, queue_handle)?,
-            output_state: OutputState::new(global_list, queue_handle),
-            shm_state: Shm::bind(global_list, queue_handle)?,
-            layer_state: LayerShell::bind(global_list, queue_handle)?,
-            fractional_scale: FractionalScaleManager::new(global_list, queue_handle)?,
-            viewporter: Viewporter::new(global_list, queue_handle)?,
-            seat_state: SeatState::new(global_list, queue_handle),
-            relative_pointer_state: RelativePointerState::bind(global_list, queue_handle),
+///
+/// Per-surface state shared between [AvyClient] (on the event loop)
+/// and the surface's [AvySurfaceHandle] (on the render thread).
+///
+#[derive(Default)]
+pub struct SurfaceShared {
+    pub dirty: DirtyFlag,
+    /// The client's, see [AvyClient::clock].
+    pub clock: SharedClock,
+    pub pixel_geometry_override: Mutex<Option<PixelGeometry>>,
+    /// The subpixel layout of the output the surface is (first) shown on.
+    pub output_pixel_geometry: Mutex<PixelGeometry>,
 
-            surfaces: HashMap::new(),
-            surface_backends: HashMap::new(),
+    /// When the surface left its last output, if it's not on any.
+    pub hidden_since: Mutex<Option<Instant>>,
+    /// How long to wait after being hidden before suspending, if at all.
+    pub auto_suspend_grace: Mutex<Option<Duration>>,
+    pub auto_suspend_timer: Mutex<Option<TimerToken>>,
+    pub auto_suspended: AtomicBool,
+    /// Suspended by [AvySurfaceHandle::suspend], until [AvySurfaceHandle::resume].
+    pub suspended: AtomicBool,
+    /// Unmapped by a suspend, waiting for the compositor to configure us again.
+    pub awaiting_configure: AtomicBool,
+    /// Whether a buffer is attached, i.e. a frame was presented since
+    /// the surface was created (or last unmapped).
+    pub mapped: AtomicBool,
+    /// Make sure the frame which maps the surface is completely drawn
+    /// before handing it over, see [AvySurfaceHandle::map_after_first_frame].
+    pub map_after_first_frame: AtomicBool,
 
-            pointer: None,
-            relative_pointer: None,
-            keyboard: None,
-            keyboard_focus: None,
-            touch: None,
-            active_touches: HashMap::new(),
+    /// Refresh rate of the surface's (first) output, in mHz, or 0 if unknown.
+    pub refresh_rate: AtomicU32,
+    /// See [AvySurfaceHandle::set_target_fps].
+    pub target_fps: Mutex<Option<f64>>,
+    /// See [AvySurfaceHandle::set_fallback_cadence].
+    pub fallback_cadence: Mutex<FallbackCadence>,
+    /// See [AvySurfaceHandle::set_motion_coalescing].
+    pub motion_coalescing: Mutex<MotionCoalescing>,
+    /// See [AvySurfaceHandle::set_adaptive_quality].
+    pub quality: Mutex<Option<QualityController>>,
+    /// Pixel density of the surface's (first) output, if known.
+    pub output_dpi: Mutex<Option<OutputDpi>>,
 
-            running: true,
-        })
+    /// Number of the last presented frame, and when it was presented.
+    pub last_presented: Mutex<Option<(u64, Instant)>>,
+    /// How long after the last frame to trim the backend's caches, if at all.
+    pub idle_trim_after: Mutex<Option<Duration>>,
+    /// Trimmed since the last frame, so that idle surfaces are only trimmed once.
+    pub idle_trimmed: AtomicBool,
+    /// The last rendering error, cleared by the next successful frame.
+    pub last_error: Mutex<Option<RenderFailure>>,
+    pub incidents: Mutex<IncidentLog>,
+    pub metrics: Mutex<Option<MetricsRecorder>>,
+
+    pub panic_policy: Mutex<PanicPolicy>,
+    pub clear: Mutex<ClearBehavior>,
+    /// See [AvySurfaceHandle::set_post_filter].
+    pub post_filter: Mutex<PostFilterState>,
+
+    pub viewport: Mutex<ViewportSync>,
+    /// See [AvySurfaceHandle::hyprland].
+    #[cfg(feature = "hyprland-surface")]
+    pub hyprland: HyprlandSurface,
+    /// See [AvySurfaceHandle::set_measure_input_latency].
+    #[cfg(feature = "input-latency")]
+    pub input_latency: Arc<InputLatency>,
+    /// See [AvySurfaceHandle::set_corner_radius].
+    pub corner_radii: Mutex<CornerRadii>,
+    pub input_shape: Mutex<InputShapeSync>,
+    /// Set when the surface acknowledges configures by hand.
+    pub configure_ack: Mutex<Option<ConfigureAck>>,
+    /// See [AvySurfaceHandle::kind].
+    pub controller: Mutex<SurfaceController>,
+    /// Whether the surface has keyboard focus, cleared as soon as it gives it
+    /// back (see [AvySurfaceHandle::release_keyboard_focus]), before the
+    /// compositor gets round to saying so.
+    pub keyboard_focused: AtomicBool,
+    /// Changes the event loop made to the backend whilst it was busy
+    /// rendering, see [AvyClient::with_surface_backend].
+    pub(crate) backend_ops: Mutex<Vec<BackendOp>>,
+
+    /// What's on screen after [AvySurfaceHandle::render_static], with the backend
+    /// suspended. Kept until the next frame drawn by the backend replaces it.
+    pub(crate) static_buffer: Mutex<Option<StaticBuffer>>,
+    /// See [AvySurfaceHandle::snapshot].
+    pub snapshots: Snapshots,
+    /// See [RenderContext::blur_region].
+    pub(crate) blurs: BlurCache,
+    /// When the source drew the frame the surface last showed, if it's a
+    /// mirror, see [AvySurfaceHandle::mirror_lag].
+    pub mirrored: Mutex<Option<Instant>>,
+
+    pub events: SurfaceEvents,
+}
+
+///
+/// Keeps a surface's viewport in step with the buffers attached to it.
+///
+/// The viewport's source rectangle must never be larger than the attached
+/// buffer, so rather than updating it as soon as the size or scale changes,
+/// it's updated right before presenting a buffer drawn at the new size.
+///
+/// The sizes only count as applied once that buffer was committed (see
+/// [ViewportSync::presented]): a failed present leaves the requests pending
+/// until whichever commit comes next, so they're sent again for that one.
+///
+#[derive(Default)]
+pub struct ViewportSync {
+    viewport: Option<WpViewport>,
+    /// Logical and physical size the viewport is currently set up for.
+    applied: Option<ViewportSizes>,
+    /// Sent for the buffer being presented, not yet committed.
+    pending: Option<ViewportSizes>,
+}
+
+type ViewportSizes = ((u32, u32), (f64, f64));
+
+impl ViewportSync {
+    pub fn new(viewport: WpViewport) -> Self {
+        Self {
+            viewport: Some(viewport),
+            applied: None,
+            pending: None,
+        }
     }
 
-    pub fn register_surface<S: AvySurface + 'static>(
-        &mut self,
-        surface: S,
-        event_queue: &mut EventQueue<Self>,
-    ) -> RegisteredSurface {
-        let id = surface.wl_surface().id();
+    ///
+    /// Set the viewport up for a buffer drawn with `context`, about to be attached.
+    ///
+    pub(crate) fn sync(&mut self, logical_size: (u32, u32), physical_size: (f64, f64)) {
+        let Some(viewport) = self.viewport.clone() else {
+            return;
+        };
 
-        self.surfaces.insert(id.clone(), Box::new(surface));
+        if !self.stage((logical_size, physical_size)) {
+            return;
+        }
 
-        {
-            let surface = self
-                .surfaces
-                .get(&id)
-                .unwrap()
-                .as_any_ref()
-                .downcast_ref::<S>()
-                .unwrap();
+        let ((logical_width, logical_height), (physical_width, physical_height)) =
+            (logical_size, physical_size);
+        viewport.set_destination(logical_width as _, logical_height as _);
+        viewport.set_source(0.0, 0.0, physical_width, physical_height);
+    }
 
-            surface.wl_surface().commit();
+    ///
+    /// Note `sizes` as pending, returning whether the viewport must be
+    /// set up for them (it isn't already, as far as the compositor knows).
+    ///
+    fn stage(&mut self, sizes: ViewportSizes) -> bool {
+        if self.applied == Some(sizes) {
+            self.pending.take();
+            return false;
         }
 
-        event_queue.roundtrip(self).unwrap();
+        self.pending.replace(sizes);
+        true
+    }
 
-        RegisteredSurface(self, id)
+    ///
+    /// Once the buffer it was synced for was presented, or failed to be:
+    /// whatever was sent for it is then either applied, or unknown (the
+    /// requests land with the next commit, whatever it attaches).
+    ///
+    pub(crate) fn presented(&mut self, committed: bool) {
+        let Some(sizes) = self.pending.take() else {
+            return;
+        };
+
+        self.applied = committed.then_some(sizes);
+    }
+
+    ///
+    /// Stop touching the viewport, once it's destroyed along with its surface.
+    ///
+    pub(crate) fn forget(&mut self) {
+        self.viewport.take();
+        self.applied.take();
+        self.pending.take();
     }
 }
 
-impl ShmHandler for AvyClient {
-    fn shm_state(&mut self) -> &mut Shm {
-        &mut self.shm_state
+///
+/// Keeps a surface's input region in step with its rounded corners (see
+/// [AvySurfaceHandle::set_corner_radius]), so clicks in the transparent
+/// corners fall through to whatever is below.
+///
+/// Like [ViewportSync], it's updated right before presenting, so the
+/// region always matches the size of the buffer being attached.
+///
+#[derive(Default)]
+pub struct InputShapeSync {
+    surface: Option<(WlSurface, WlCompositor)>,
+    /// Logical size and radii the input region is currently set up for.
+    applied: Option<((u32, u32), CornerRadii)>,
+}
+
+impl InputShapeSync {
+    pub fn new(wl_surface: WlSurface, compositor: WlCompositor) -> Self {
+        Self {
+            surface: Some((wl_surface, compositor)),
+            applied: None,
+        }
+    }
+
+    ///
+    /// Set the input region up for a buffer of `logical_size`, about to be attached.
+    ///
+    fn sync(&mut self, logical_size: (u32, u32), radii: CornerRadii) {
+        let Some((wl_surface, compositor)) = &self.surface else {
+            return;
+        };
+
+        let shape = (logical_size, radii);
+        if self.applied == Some(shape) {
+            return;
+        }
+
+        let was_rounded = self.applied.is_some_and(|(_, applied)| !applied.is_zero());
+
+        if radii.is_zero() {
+            // Back to taking input everywhere, the default.
+            if was_rounded {
+                wl_surface.set_input_region(None);
+            }
+
+            self.applied.replace(shape);
+            return;
+        }
+
+        let region = match Region::new(compositor) {
+            Ok(region) => region,
+            Err(err) => {
+                log::warn!("Could not shape the input region: {err}");
+                return;
+            }
+        };
+        debug::objects::created(&region.wl_region().id(), Some(&wl_surface.id()));
+
+        region.add(0, 0, logical_size.0 as i32, logical_size.1 as i32);
+        for (x, y, width, height) in corner_cutouts(logical_size, radii) {
+            region.subtract(x, y, width, height);
+        }
+
+        // The region is copied into the surface's pending state, and can go right away.
+        wl_surface.set_input_region(Some(region.wl_region()));
+        debug::objects::destroyed(&region.wl_region().id());
+        self.applied.replace(shape);
     }
 }
 
-delegate_shm!(AvyClient);
+///
+/// The squares cut out of a surface's input region for its rounded corners
+/// (as `x, y, width, height`), each covering its corner's radius.
+///
+fn corner_cutouts(logical_size: (u32, u32), radii: CornerRadii) -> [(i32, i32, i32, i32); 4] {
+    let (width, height) = (logical_size.0 as i32, logical_size.1 as i32);
+    let corner = |radius: f32| (radius.ceil() as i32).clamp(0, width.min(height) / 2);
 
-impl ProvidesRegistryState for AvyClient {
-    fn registry(&mut self) -> &mut RegistryState {
-        &mut self.registry_state
+    let top_left = corner(radii.top_left);
+    let top_right = corner(radii.top_right);
+    let bottom_right = corner(radii.bottom_right);
+    let bottom_left = corner(radii.bottom_left);
+
+    [
+        (0, 0, top_left, top_left),
+        (width - top_right, 0, top_right, top_right),
+        (
+            width - bottom_right,
+            height - bottom_right,
+            bottom_right,
+            bottom_right,
+        ),
+        (0, height - bottom_left, bottom_left, bottom_left),
+    ]
+}
+
+///
+/// What an [AvySurfaceHandle] does when a render callback panics.
+///
+/// Either way, the frame being drawn is discarded rather than presented.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Keep unwinding, out of the render call.
+    Propagate,
+    /// Return the panic as the backend's error (see [CallbackPanic]).
+    #[default]
+    Report,
+}
+
+///
+/// What a surface in a render group does when the compositor stops sending
+/// it frame callbacks, as some do for surfaces which are covered up.
+///
+/// Drawing anyway keeps it up to date (a clock keeps ticking), so nothing
+/// stale shows when the compositor shows it again.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackCadence {
+    /// Wait for frame callbacks, however long they take.
+    Never,
+    ///
+    /// Once no frame callback has come for this many seconds, draw (if dirty)
+    /// every this many seconds, until one comes.
+    ///
+    Secs(u64),
+}
+
+impl Default for FallbackCadence {
+    fn default() -> Self {
+        Self::Secs(1)
     }
+}
 
-    registry_handlers!(OutputState);
+impl FallbackCadence {
+    pub fn interval(&self) -> Option<Duration> {
+        match self {
+            Self::Never | Self::Secs(0) => None,
+            Self::Secs(secs) => Some(Duration::from_secs(*secs)),
+        }
+    }
 }
 
-impl CompositorHandler for AvyClient {
-    fn scale_factor_changed(
-        &mut self,
-        conn: &Connection,
-        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
-        surface: &WlSurface,
-        new_factor: i32,
-    ) {
+///
+/// A frame which failed to render.
+///
+#[derive(Debug, Clone)]
+pub struct RenderFailure {
+    pub frame: u64,
+    pub message: String,
+    pub at: Instant,
+}
+
+impl std::fmt::Display for RenderFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "frame {} failed: {}", self.frame, self.message)
     }
+}
 
-    fn transform_changed(
-        &mut self,
-        conn: &Connection,
-        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
-        surface: &WlSurface,
-        new_transform: smithay_client_toolkit::reexports::client::protocol::wl_output::Transform,
-    ) {
+///
+/// A change to a surface's backend, waiting for the render holding it.
+///
+pub(crate) type BackendOp = Box<dyn FnOnce(&mut dyn GraphicsSurface) + Send>;
+
+impl SurfaceShared {
+    ///
+    /// Make the changes to `backend` which were put off whilst it was locked.
+    ///
+    pub(crate) fn run_backend_ops(&self, backend: &mut dyn GraphicsSurface) {
+        let ops = std::mem::take(&mut *self.backend_ops.lock().unwrap());
+        for op in ops {
+            op(backend);
+        }
     }
 
-    fn frame(
-        &mut self,
-        conn: &Connection,
-        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
-        surface: &WlSurface,
-        time: u32,
-    ) {
-        println!("WAYLAND@Compositor: Frame requested!");
+    pub fn is_visible(&self) -> bool {
+        self.hidden_since.lock().unwrap().is_none()
     }
 
-    fn surface_enter(
-        &mut self,
-        conn: &Connection,
-        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
-        surface: &WlSurface,
-        output: &smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
-    ) {
+    ///
+    /// See [AvySurfaceHandle::release_keyboard_focus].
+    ///
+    fn release_keyboard_focus(&self) -> Result<(), WrongKind> {
+        let controller = self.controller.lock().unwrap().layer()?;
+        if !self.keyboard_focused.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        if controller.release_keyboard_focus() {
+            self.keyboard_focused.store(false, Ordering::Release);
+            self.events.emit(SurfaceEvent::KeyboardFocusReleased);
+        }
+
+        Ok(())
     }
 
-    fn surface_leave(
-        &mut self,
-        conn: &Connection,
-        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
-        surface: &WlSurface,
-        output: &smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
+    ///
+    /// Refresh rate of the output the surface is drawn in step with, in Hz.
+    ///
+    pub fn refresh_rate(&self) -> Option<f32> {
+        match self.refresh_rate.load(Ordering::Acquire) {
+            0 => None,
+            millihertz => Some(millihertz as f32 / 1000.0),
+        }
+    }
+
+    pub fn target_fps(&self) -> Option<f64> {
+        *self.target_fps.lock().unwrap()
+    }
+
+    pub fn fallback_cadence(&self) -> FallbackCadence {
+        *self.fallback_cadence.lock().unwrap()
+    }
+
+    ///
+    /// How long a frame may take to keep up: the refresh interval of the
+    /// surface's output (assumed 60 Hz if unknown), or longer at a lower target fps.
+    ///
+    pub fn frame_budget(&self) -> Duration {
+        let refresh_rate = self.refresh_rate().map_or(60.0, f64::from);
+        let fps = match self.target_fps() {
+            Some(target_fps) => target_fps.min(refresh_rate),
+            None => refresh_rate,
+        };
+
+        Duration::from_secs_f64(1.0 / fps.max(1.0))
+    }
+
+    ///
+    /// Draw the rest of the frame through the post filter, if there is one,
+    /// returning the save count to restore to once done.
+    ///
+    /// Keeps the surface redrawing whilst the filter is easing to another.
+    ///
+    fn begin_post_filter(&self, canvas: &skia_safe::Canvas) -> Option<usize> {
+        Some(self.current_post_filter()?.begin(canvas))
+    }
+
+    ///
+    /// The post filter as it is now, keeping the surface redrawing
+    /// whilst it's easing to another.
+    ///
+    fn current_post_filter(&self) -> Option<PostFilter> {
+        let now = self.clock.now();
+        let post_filter = self.post_filter.lock().unwrap();
+        if post_filter.is_animating(now) {
+            self.dirty.mark();
+        }
+
+        post_filter.value(now)
+    }
+
+    ///
+    /// Get the surface ready for a buffer drawn with `context`, about to be
+    /// attached and committed: set up the viewport for it, and acknowledge
+    /// the configure it was drawn for.
+    ///
+    fn prepare_present(&self, context: &RenderContext) {
+        self.prepare_present_at(context.logical_size, context.physical_size);
+    }
+
+    pub(crate) fn prepare_present_at(&self, logical_size: (u32, u32), physical_size: (f64, f64)) {
+        self.viewport
+            .lock()
+            .unwrap()
+            .sync(logical_size, physical_size);
+
+        let radii = *self.corner_radii.lock().unwrap();
+        self.input_shape.lock().unwrap().sync(logical_size, radii);
+
+        if let Some(configure_ack) = &*self.configure_ack.lock().unwrap() {
+            configure_ack.ack_if_fits(logical_size);
+        }
+
+        #[cfg(feature = "input-latency")]
+        self.input_latency
+            .before_present(self.metrics.lock().unwrap().clone());
+    }
+
+    ///
+    /// The surface's outline for a frame of `logical_size`, if its corners are rounded.
+    ///
+    fn rounded_outline(&self, logical_size: (u32, u32)) -> Option<RRect> {
+        let radii = *self.corner_radii.lock().unwrap();
+        let (width, height) = logical_size;
+
+        (!radii.is_zero()).then(|| radii.rrect(Rect::from_wh(width as f32, height as f32)))
+    }
+
+    ///
+    /// Whether the frame about to be presented must be finished first.
+    ///
+    fn finish_before_present(&self) -> bool {
+        self.map_after_first_frame.load(Ordering::Acquire) && !self.mapped.load(Ordering::Acquire)
+    }
+
+    fn record_render<T, E: std::fmt::Display>(
+        &self,
+        frame: u64,
+        presented: bool,
+        result: &Result<T, E>,
     ) {
+        self.viewport.lock().unwrap().presented(presented);
+
+        match result {
+            Ok(_) if presented => {
+                self.mapped.store(true, Ordering::Release);
+                self.idle_trimmed.store(false, Ordering::Release);
+                self.static_buffer.lock().unwrap().take();
+                self.last_presented
+                    .lock()
+                    .unwrap()
+                    .replace((frame, self.clock.now()));
+                if let Some(failure) = self.last_error.lock().unwrap().take() {
+                    self.record_incident(
+                        IncidentKind::Recovered,
+                        frame,
+                        format_args!("last failure was frame {}", failure.frame),
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(err) => self.record_error(frame, err),
+        }
+    }
+
+    fn record_error(&self, frame: u64, err: &impl std::fmt::Display) {
+        let failure = RenderFailure {
+            frame,
+            message: err.to_string(),
+            at: self.clock.now(),
+        };
+
+        log::debug!("Surface {failure}");
+        self.record_incident(
+            IncidentKind::Error,
+            frame,
+            format_args!("{}", failure.message),
+        );
+        self.last_error.lock().unwrap().replace(failure);
+    }
+
+    pub fn record_incident(&self, kind: IncidentKind, frame: u64, message: std::fmt::Arguments) {
+        self.incidents
+            .lock()
+            .unwrap()
+            .push(kind, frame, self.clock.now(), message);
+    }
+
+    fn record_metrics(&self, timings: &FrameTimings) {
+        if let Some(metrics) = &*self.metrics.lock().unwrap() {
+            metrics.record_frame(timings);
+        }
+    }
+
+    ///
+    /// The frame being drawn now, or next.
+    ///
+    pub(crate) fn current_frame(&self) -> u64 {
+        self.last_presented
+            .lock()
+            .unwrap()
+            .map_or(1, |(frame, _)| frame + 1)
     }
 }
 
-impl OutputHandler for AvyClient {
-    fn output_state(&mut self) -> &mut OutputState {
-        &mut self.output_state
-    }
+///
+/// How long a surface must stay hidden before it's automatically suspended.
+///
+pub const DEFAULT_AUTO_SUSPEND_GRACE: Duration = Duration::from_secs(2);
+
+///
+/// How long drawing on the event loop's thread may keep input waiting: render
+/// group members which aren't drawn by then wait for the group's next tick,
+/// and render callbacks can check [RenderContext::over_input_budget].
+///
+pub const INPUT_BUDGET: Duration = Duration::from_millis(8);
+
+impl<G: GraphicsBackend> AvySurfaceHandle<G>
+where
+    G::Error: 'static,
+{
+    pub fn render(
+        &self,
+        callback: impl FnMut(&skia_safe::Canvas, &RenderContext),
+    ) -> Result<(), RenderError<G::Error>> {
+        self.render_with(&self.frame_options(), callback)
+    }
+
+    ///
+    /// Like [AvySurfaceHandle::render], but with `options` for this frame
+    /// only, e.g. to skip clearing when the callback covers every pixel.
+    ///
+    pub fn render_with(
+        &self,
+        options: &FrameOptions,
+        mut callback: impl FnMut(&skia_safe::Canvas, &RenderContext),
+    ) -> Result<(), RenderError<G::Error>> {
+        if !self.prepare_render()? {
+            return Ok(());
+        }
+
+        let device = self.device_lock.lock().unwrap();
+        let backend = self.backend.lock().unwrap();
+
+        self.render_locked(device, backend, options, &mut callback)
+    }
+
+    ///
+    /// Like [AvySurfaceHandle::render], but returns [TryRenderError::WouldBlock]
+    /// rather than waiting for another thread to finish rendering.
+    ///
+    pub fn try_render(
+        &self,
+        mut callback: impl FnMut(&skia_safe::Canvas, &RenderContext),
+    ) -> Result<(), TryRenderError<G::Error>> {
+        if !self.prepare_render()? {
+            return Ok(());
+        }
+
+        let Some(device) = try_lock(&self.device_lock) else {
+            return Err(TryRenderError::WouldBlock);
+        };
+
+        let Some(backend) = try_lock(&self.backend) else {
+            return Err(TryRenderError::WouldBlock);
+        };
+
+        Ok(self.render_locked(device, backend, &self.frame_options(), &mut callback)?)
+    }
+
+    fn render_locked(
+        &self,
+        device: MutexGuard<'_, ()>,
+        mut backend: MutexGuard<'_, dyn GraphicsSurface>,
+        options: &FrameOptions,
+        callback: &mut dyn FnMut(&skia_safe::Canvas, &RenderContext),
+    ) -> Result<(), RenderError<G::Error>> {
+        // Taken once, so that the whole frame agrees on the size.
+        let size = self.size.read().unwrap().snapshot();
+        let frame = backend.presented_frames() + 1;
+        let context = self
+            .context(frame, &size)
+            .with_damage(options.damage.clone());
+        let _trace_scope = FrameScope::enter(&self.id(), frame);
+
+        self.state.dirty.take();
+        self.state.run_backend_ops(&mut *backend);
+
+        let result = backend.begin_frame(&size, options).and_then(|acquired| {
+            let Some(mut acquired) = acquired else {
+                return Ok(());
+            };
+
+            if self.state.finish_before_present() {
+                acquired.finish_before_present();
+            }
+
+            let outline = self.state.rounded_outline(context.logical_size);
+            let mut draw = |canvas: &skia_safe::Canvas| {
+                let clip = FrameClip::apply(canvas, &size, outline);
+                self.state.begin_post_filter(canvas);
+                callback(canvas, &context);
+                clip.finish(canvas, &size);
+
+                self.state
+                    .snapshots
+                    .capture(canvas, frame, context.physical_size, context.now());
+                context.draw_unclipped(canvas);
+            };
+
+            draw_and_present(acquired, &mut draw, || self.state.prepare_present(&context)).map(
+                |timings| {
+                    self.state.record_metrics(&timings);
+                    self.adapt_quality(&timings);
+                },
+            )
+        });
+        let presented = backend.presented_frames() >= frame;
+        self.state.run_backend_ops(&mut *backend);
+
+        // Unlock before (possibly) resuming a panic,
+        // so that the mutexes aren't poisoned.
+        drop(backend);
+        drop(device);
+        let result = result.map_err(|err| self.render_error(err));
+
+        self.state.record_render(frame, presented, &result);
+        result.map_err(|error| RenderError { frame, error })
+    }
+
+    ///
+    /// The surface's last [crate::debug::INCIDENT_HISTORY] render errors
+    /// and other incidents (suspends, recoveries...), oldest first.
+    ///
+    pub fn recent_errors(&self) -> Vec<RenderIncident> {
+        self.state
+            .incidents
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    pub fn latest_incident(&self) -> Option<RenderIncident> {
+        self.state.incidents.lock().unwrap().latest().copied()
+    }
+
+    pub fn clear_incidents(&self) {
+        self.state.incidents.lock().unwrap().clear();
+    }
+
+    ///
+    /// Number of the last frame presented, and when.
+    ///
+    /// Useful to detect a stalled render loop.
+    ///
+    pub fn last_presented_frame(&self) -> Option<(u64, Instant)> {
+        *self.state.last_presented.lock().unwrap()
+    }
+
+    ///
+    /// The most recent render failure, unless a frame was presented since.
+    ///
+    pub fn last_error(&self) -> Option<RenderFailure> {
+        self.state.last_error.lock().unwrap().clone()
+    }
+
+    ///
+    /// What to do when a render callback panics, see [PanicPolicy].
+    ///
+    pub fn set_panic_policy(&self, policy: PanicPolicy) {
+        *self.state.panic_policy.lock().unwrap() = policy;
+    }
+
+    pub fn panic_policy(&self) -> PanicPolicy {
+        *self.state.panic_policy.lock().unwrap()
+    }
+
+    ///
+    /// Start recording the surface's frame times, latencies and incidents
+    /// (see [MetricsRecorder]), replacing any recorder set up before.
+    ///
+    pub fn metrics_recorder(&self, config: MetricsConfig) -> MetricsRecorder {
+        let recorder = MetricsRecorder::new(config).with_clock(self.state.clock.clone());
+        self.state.metrics.lock().unwrap().replace(recorder.clone());
+
+        recorder
+    }
+
+    ///
+    /// Keep the surface off screen until its first frame is completely drawn,
+    /// rather than let the compositor map it with whatever the swapchain
+    /// image held at the time (usually a white or garbage flash).
+    ///
+    /// Nothing is attached to the surface before the first render, so this
+    /// only concerns the frame which maps it (again after [AvySurfaceHandle::suspend]
+    /// unmaps it), see [crate::graphics::Frame::finish_before_present]. Vulkan
+    /// already presents only once the GPU is done drawing, so it costs nothing there.
+    ///
+    /// On by default for layers, see [AvySurface::map_after_first_frame].
+    ///
+    pub fn map_after_first_frame(&self, enabled: bool) {
+        self.state
+            .map_after_first_frame
+            .store(enabled, Ordering::Release);
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.state.mapped.load(Ordering::Acquire)
+    }
+
+    fn render_error(&self, err: Box<dyn Any>) -> G::Error {
+        match err.downcast::<CallbackPanic>() {
+            Ok(panic) => self.callback_panicked(*panic),
+            Err(err) => downcast_error::<G>(err),
+        }
+    }
+
+    fn callback_panicked(&self, panic: CallbackPanic) -> G::Error {
+        if let Some(metrics) = &*self.state.metrics.lock().unwrap() {
+            metrics.record_panic();
+        }
+
+        if self.panic_policy() == PanicPolicy::Propagate {
+            panic::resume_unwind(panic.into_payload());
+        }
+
+        log::warn!("Render callback panicked, discarded the frame: {panic}");
+        G::Error::from(panic)
+    }
+
+    ///
+    /// Whether something changed since the last frame which
+    /// warrants redrawing (e.g. the desktop appearance).
+    ///
+    pub fn is_dirty(&self) -> bool {
+        self.state.dirty.is_dirty()
+    }
+
+    pub fn mark_dirty(&self) {
+        self.state.dirty.mark()
+    }
+
+    ///
+    /// Hyprland's per-surface properties (e.g. [HyprlandSurface::set_opacity]),
+    /// which return [crate::wayland::protocol::hyprland::HyprlandError::Unsupported]
+    /// unless the compositor advertises `hyprland_surface_manager_v1`.
+    ///
+    #[cfg(feature = "hyprland-surface")]
+    pub fn hyprland(&self) -> &HyprlandSurface {
+        &self.state.hyprland
+    }
+
+    ///
+    /// Measure input-to-photon latency: from each pointer button press on
+    /// the surface to the next frame presented after it being on screen,
+    /// see [crate::wayland::protocol::presentation].
+    ///
+    /// Samples go to [AvySurfaceHandle::latency_report], and to the surface's
+    /// [MetricsRecorder] if it has one. Fails with [LatencyError::Unsupported]
+    /// unless the compositor advertises `wp_presentation`.
+    ///
+    #[cfg(feature = "input-latency")]
+    pub fn set_measure_input_latency(&self, enabled: bool) -> Result<(), LatencyError> {
+        self.state.input_latency.set_enabled(enabled)
+    }
+
+    ///
+    /// The last input-to-photon latency measured, and percentiles over every
+    /// sample, see [AvySurfaceHandle::set_measure_input_latency].
+    ///
+    #[cfg(feature = "input-latency")]
+    pub fn latency_report(&self) -> LatencyReport {
+        self.state.input_latency.report()
+    }
+
+    ///
+    /// Round the surface's corners: every frame is clipped to a rounded rect
+    /// of the surface's size (anti-aliased, leaving the corners transparent),
+    /// and the input region leaves the corners out, so clicks there fall
+    /// through to what's below.
+    ///
+    /// Both follow resizes by themselves, and take effect from the next frame
+    /// on. [CornerRadii::ZERO] squares the corners off again.
+    ///
+    pub fn set_corner_radius(&self, radii: CornerRadii) {
+        let mut current = self.state.corner_radii.lock().unwrap();
+        if *current != radii {
+            *current = radii;
+            self.state.dirty.mark();
+        }
+    }
+
+    pub fn corner_radius(&self) -> CornerRadii {
+        *self.state.corner_radii.lock().unwrap()
+    }
+
+    ///
+    /// A copy of the surface's most recent frame (at its physical size), for
+    /// drawing into other surfaces, e.g. with [skia_safe::Canvas::draw_image_rect].
+    ///
+    /// Frames are only copied whilst asked for: if the latest copy is out of
+    /// date (or there's none yet), the next frame is copied, and this returns
+    /// the older copy (or `None`) in the meantime. To follow every frame, see
+    /// [AvySurfaceHandle::on_new_frame].
+    ///
+    /// Copies are taken on this surface's render thread, and drawing them never
+    /// waits on it.
+    ///
+    pub fn snapshot(&self) -> Option<skia_safe::Image> {
+        let presented = self
+            .state
+            .last_presented
+            .lock()
+            .unwrap()
+            .map(|(frame, _)| frame);
+        let snapshot = self.state.snapshots.latest(presented);
+
+        if snapshot.is_none() {
+            self.state.dirty.mark();
+        }
+
+        snapshot.map(|snapshot| snapshot.image)
+    }
+
+    ///
+    /// Call `callback` with a copy of every frame drawn, until the returned
+    /// [Subscription] is dropped.
+    ///
+    /// It's called on this surface's render thread, right after each frame is
+    /// drawn, so should be quick (e.g. storing the snapshot and marking the
+    /// surface drawing it dirty).
+    ///
+    pub fn on_new_frame(
+        &self,
+        callback: impl FnMut(FrameSnapshot) + Send + 'static,
+    ) -> Subscription {
+        self.state.snapshots.subscribe(callback)
+    }
+
+    ///
+    /// The latest copy of the surface's frames, if any, without asking for a fresh one.
+    ///
+    pub(crate) fn snapshot_frame(&self) -> Option<FrameSnapshot> {
+        self.state.snapshots.latest(None)
+    }
+
+    ///
+    /// For mirrors (see [AvyClient::mirror_surface]), how long after the source
+    /// drew the frame the mirror last showed, the mirror presented it.
+    ///
+    /// Both are timed on the client, as frames are handed to the compositor.
+    ///
+    pub fn mirror_lag(&self) -> Option<Duration> {
+        let captured = (*self.state.mirrored.lock().unwrap())?;
+        let (_, presented) = (*self.state.last_presented.lock().unwrap())?;
+
+        Some(presented.saturating_duration_since(captured))
+    }
+
+    pub(crate) fn set_mirrored(&self, captured: Instant) {
+        self.state.mirrored.lock().unwrap().replace(captured);
+    }
+
+    ///
+    /// Draw every frame through `filter` (e.g. [PostFilter::Temperature] for a
+    /// night light), applied to whatever the render callback drew.
+    ///
+    pub fn set_post_filter(&self, filter: Option<PostFilter>) {
+        self.state.post_filter.lock().unwrap().set(filter);
+        self.mark_dirty();
+    }
+
+    ///
+    /// Like [AvySurfaceHandle::set_post_filter], but easing from the
+    /// current filter to `filter` over `duration`.
+    ///
+    /// The surface keeps redrawing until the transition is over.
+    ///
+    pub fn transition_post_filter(
+        &self,
+        filter: Option<PostFilter>,
+        duration: Duration,
+        easing: Easing,
+    ) {
+        let now = self.state.clock.now();
+        self.state
+            .post_filter
+            .lock()
+            .unwrap()
+            .transition(filter, duration, easing, now);
+        self.mark_dirty();
+    }
+
+    ///
+    /// The filter set, or being transitioned to.
+    ///
+    pub fn post_filter(&self) -> Option<PostFilter> {
+        self.state.post_filter.lock().unwrap().target()
+    }
+
+    ///
+    /// What frames are cleared to before drawing, unless
+    /// overridden with [AvySurfaceHandle::render_with].
+    ///
+    pub fn set_clear_behavior(&self, clear: ClearBehavior) {
+        *self.state.clear.lock().unwrap() = clear;
+    }
+
+    pub fn clear_behavior(&self) -> ClearBehavior {
+        *self.state.clear.lock().unwrap()
+    }
+
+    fn frame_options(&self) -> FrameOptions {
+        FrameOptions::clear(self.clear_behavior())
+    }
+
+    ///
+    /// Refresh rate (in Hz) of the output the surface is (first) shown on,
+    /// for pacing animations. Follows mode changes, and `None` until the
+    /// surface is on an output which reports its refresh rate.
+    ///
+    pub fn refresh_rate(&self) -> Option<f32> {
+        self.state.refresh_rate()
+    }
+
+    ///
+    /// Have render groups draw the surface at most `fps` frames per second
+    /// (roughly), along with everything drawn in step with it. `None` leaves
+    /// it to the group (see [RenderGroupMut::set_target_fps]).
+    ///
+    pub fn set_target_fps(&self, fps: Option<f64>) {
+        *self.state.target_fps.lock().unwrap() = fps.filter(|fps| *fps > 0.0);
+    }
+
+    pub fn target_fps(&self) -> Option<f64> {
+        self.state.target_fps()
+    }
+
+    ///
+    /// Whether (and how often) render groups draw the surface without waiting
+    /// for frame callbacks, whilst the compositor holds them back, see
+    /// [FallbackCadence]. Frames drawn this way are counted in the surface's
+    /// [crate::metrics::Metrics::fallback_frames].
+    ///
+    pub fn set_fallback_cadence(&self, cadence: FallbackCadence) {
+        *self.state.fallback_cadence.lock().unwrap() = cadence;
+    }
+
+    pub fn fallback_cadence(&self) -> FallbackCadence {
+        self.state.fallback_cadence()
+    }
+
+    ///
+    /// Have runs of pointer motion (and scroll) events in each frame delivered
+    /// as one, see [MotionCoalescing]. Off by default.
+    ///
+    pub fn set_motion_coalescing(&self, coalescing: MotionCoalescing) {
+        *self.state.motion_coalescing.lock().unwrap() = coalescing;
+    }
+
+    pub fn motion_coalescing(&self) -> MotionCoalescing {
+        *self.state.motion_coalescing.lock().unwrap()
+    }
+
+    ///
+    /// Render at a fraction of the surface's scale (for the viewport to scale
+    /// back up) whilst frames keep taking longer than the output's refresh
+    /// interval, within the bounds of `adaptive`, rather than drop frames.
+    /// `None` goes back to the full scale.
+    ///
+    /// Only frames drawn with [AvySurfaceHandle::render] (and its variants) count.
+    ///
+    pub fn set_adaptive_quality(&self, adaptive: Option<AdaptiveQuality>) {
+        let controller = adaptive.map(QualityController::new);
+        let quality = controller.as_ref().map_or(1.0, QualityController::quality);
+
+        *self.state.quality.lock().unwrap() = controller;
+        self.set_render_quality(quality, adaptive.is_some());
+    }
+
+    ///
+    /// The fraction of the surface's scale it renders at, see
+    /// [AvySurfaceHandle::set_adaptive_quality].
+    ///
+    pub fn render_quality(&self) -> f64 {
+        self.size.read().unwrap().quality()
+    }
+
+    fn set_render_quality(&self, quality: f64, adaptive: bool) {
+        let rescaled = {
+            let mut size = self.size.write().unwrap();
+            let generation = size.generation();
+            size.set_quality(quality);
+            size.generation() != generation
+        };
+
+        if let Some(metrics) = &*self.state.metrics.lock().unwrap() {
+            metrics.record_quality(adaptive.then_some(quality));
+        }
+
+        if rescaled {
+            self.state.dirty.mark();
+        }
+    }
+
+    ///
+    /// Count a presented frame towards the surface's render quality, if adaptive.
+    ///
+    fn adapt_quality(&self, timings: &FrameTimings) {
+        let Some(cost) = frame_cost(timings) else {
+            return;
+        };
+
+        let quality = {
+            let mut controller = self.state.quality.lock().unwrap();
+            let Some(controller) = controller.as_mut() else {
+                return;
+            };
+
+            controller.record(cost, self.state.frame_budget())
+        };
+
+        if let Some(quality) = quality {
+            log::debug!(
+                "Rendering {} at {:.0}% of its scale.",
+                self.id(),
+                quality * 100.0
+            );
+            self.set_render_quality(quality, true);
+        }
+    }
+
+    ///
+    /// Change how the surface's scale follows the compositor's, redrawing
+    /// at the new scale if it changed.
+    ///
+    pub fn set_scale_mode(&self, scale_mode: ScaleMode) {
+        let rescaled = {
+            let mut size = self.size.write().unwrap();
+            let generation = size.generation();
+            size.set_scale_mode(scale_mode);
+            size.generation() != generation
+        };
+
+        if rescaled {
+            self.mark_dirty();
+        }
+    }
+
+    pub fn scale_mode(&self) -> ScaleMode {
+        self.size.read().unwrap().scale_mode()
+    }
+
+    ///
+    /// Turn the surface's drawing space, redrawing if it changed. Buffers
+    /// stay in the surface's orientation; only the canvas (and the positions
+    /// handed to input handlers) are turned.
+    ///
+    pub fn set_transform(&self, transform: Transform) {
+        let turned = {
+            let mut size = self.size.write().unwrap();
+            let generation = size.generation();
+            size.set_transform(transform);
+            size.generation() != generation
+        };
+
+        if turned {
+            self.mark_dirty();
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.size.read().unwrap().transform()
+    }
+
+    ///
+    /// The surface's ID, as used by [AvyClient]'s methods.
+    ///
+    pub fn id(&self) -> ObjectId {
+        self.wl_surface.id()
+    }
+
+    ///
+    /// What sort of surface this is, see [AvySurfaceHandle::as_layer_controller].
+    ///
+    pub fn kind(&self) -> SurfaceKind {
+        self.state.controller.lock().unwrap().kind()
+    }
+
+    ///
+    /// This surface's controller, if it's a layer.
+    ///
+    pub fn as_layer_controller(&self) -> Result<AvyLayerController, WrongKind> {
+        self.state.controller.lock().unwrap().layer()
+    }
+
+    ///
+    /// This surface's controller, if it's a subsurface.
+    ///
+    pub fn as_subsurface_controller(&self) -> Result<AvySubsurfaceController, WrongKind> {
+        self.state.controller.lock().unwrap().subsurface()
+    }
+
+    pub fn has_keyboard_focus(&self) -> bool {
+        self.state.keyboard_focused.load(Ordering::Acquire)
+    }
+
+    ///
+    /// Give keyboard focus back to whatever had it before this layer took it
+    /// on demand (e.g. once a bar's search field is done with), see
+    /// [AvyLayerController::release_keyboard_focus], emitting
+    /// [SurfaceEvent::KeyboardFocusReleased].
+    ///
+    /// Does nothing unless the layer is on-demand and has focus. Key events
+    /// stop going to it straight away, rather than once the compositor moves
+    /// focus, see [AvyClient::release_keyboard_focus] for the event loop.
+    ///
+    pub fn release_keyboard_focus(&self) -> Result<(), WrongKind> {
+        self.state.release_keyboard_focus()
+    }
+
+    ///
+    /// This surface's dirty flag, for things which repaint it on their own
+    /// (e.g. [crate::graphics::CachedPicture::track_dirty]).
+    ///
+    pub fn dirty_flag(&self) -> DirtyFlag {
+        self.state.dirty.clone()
+    }
+
+    ///
+    /// Override the subpixel layout used for text anti-aliasing.
+    ///
+    /// `None` goes back to following the output the surface is on.
+    ///
+    pub fn set_pixel_geometry(&self, geometry: Option<PixelGeometry>) {
+        *self.state.pixel_geometry_override.lock().unwrap() = geometry;
+
+        let geometry =
+            geometry.unwrap_or_else(|| *self.state.output_pixel_geometry.lock().unwrap());
+        self.backend.lock().unwrap().set_pixel_geometry(geometry);
+
+        self.mark_dirty();
+    }
+
+    ///
+    /// Call `callback` with each of the surface's [SurfaceEvent]s, on the
+    /// event loop and in the order they happen, until the returned
+    /// [Subscription] is dropped.
+    ///
+    pub fn on_event(&self, callback: impl FnMut(SurfaceEvent) + Send + 'static) -> Subscription {
+        self.state.events.subscribe(callback)
+    }
+
+    fn context(&self, frame: u64, size: &SizeSnapshot) -> RenderContext {
+        let now = self.state.clock.now();
+        let post_filtered = self.state.post_filter.lock().unwrap().value(now);
+
+        RenderContext::new(frame, size, &self.shared.read().unwrap())
+            .with_output_dpi(*self.state.output_dpi.lock().unwrap())
+            .with_blurs(self.state.blurs.clone(), post_filtered.is_some())
+    }
+
+    ///
+    /// Free the swapchain and GPU caches whilst the surface isn't needed.
+    ///
+    /// Rendering fails with the backend's "suspended" error (see [SurfaceSuspended])
+    /// until [AvySurfaceHandle::resume]. With `unmap`, the surface is also
+    /// removed from the screen.
+    ///
+    pub fn suspend(&self, unmap: bool) -> Result<(), G::Error> {
+        self.backend
+            .lock()
+            .unwrap()
+            .suspend()
+            .map_err(downcast_error::<G>)?;
+        self.state.suspended.store(true, Ordering::Release);
+        self.state.blurs.clear();
+
+        if unmap {
+            self.wl_surface.attach(None, 0, 0);
+            self.wl_surface.commit();
+            self.state.static_buffer.lock().unwrap().take();
+            self.state.awaiting_configure.store(true, Ordering::Release);
+            self.state.mapped.store(false, Ordering::Release);
+        }
+
+        let frame = self.state.current_frame();
+        self.state.record_incident(
+            IncidentKind::Suspended,
+            frame,
+            format_args!("{}", if unmap { "unmapped" } else { "" }),
+        );
+
+        Ok(())
+    }
+
+    pub fn resume(&self) -> Result<(), G::Error> {
+        self.state.suspended.store(false, Ordering::Release);
+        self.state.auto_suspended.store(false, Ordering::Release);
+
+        // An unmapped surface needs a fresh configure before any
+        // buffer may be attached: ask for one with an empty commit.
+        if self.state.awaiting_configure.load(Ordering::Acquire) {
+            self.wl_surface.commit();
+        }
+
+        let size = self.size.read().unwrap().snapshot();
+        self.backend
+            .lock()
+            .unwrap()
+            .resume(&size)
+            .map_err(downcast_error::<G>)?;
+
+        let frame = self.state.current_frame();
+        self.state
+            .record_incident(IncidentKind::Resumed, frame, format_args!(""));
+
+        self.mark_dirty();
+
+        Ok(())
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.backend.lock().unwrap().is_suspended()
+    }
+
+    ///
+    /// Suspend the surface when it's been off-screen for a while,
+    /// and resume it when it's back.
+    ///
+    pub fn auto_suspend_when_hidden(&self, enabled: bool) {
+        self.set_auto_suspend_grace(enabled.then_some(DEFAULT_AUTO_SUSPEND_GRACE));
+    }
+
+    pub fn set_auto_suspend_grace(&self, grace: Option<Duration>) {
+        *self.state.auto_suspend_grace.lock().unwrap() = grace;
+    }
+
+    ///
+    /// Give back memory the backend has cached, e.g. when the system is low on it.
+    ///
+    /// Surfaces are trimmed by themselves once they've been idle for a while,
+    /// see [AvySurfaceHandle::set_idle_trim].
+    ///
+    pub fn trim_memory(&self, level: TrimLevel) {
+        let mut backend = self.backend.lock().unwrap();
+        memory::trim_memory(&self.state, &mut *backend, level, "asked");
+    }
+
+    ///
+    /// How long after its last frame the surface's caches are trimmed
+    /// (see [crate::memory::DEFAULT_IDLE_TRIM]), or `None` not to.
+    ///
+    pub fn set_idle_trim(&self, after: Option<Duration>) {
+        *self.state.idle_trim_after.lock().unwrap() = after;
+    }
+
+    ///
+    /// Whether rendering should go ahead: it's skipped whilst the surface is
+    /// automatically suspended, or waiting to be configured after an unmap,
+    /// and fails whilst it's suspended by [AvySurfaceHandle::suspend].
+    ///
+    /// After [AvySurfaceHandle::render_static], the backend is resumed first.
+    ///
+    fn prepare_render(&self) -> Result<bool, RenderError<G::Error>> {
+        // Numbered as the frame which would have been drawn.
+        let failed = |error| RenderError {
+            frame: self.state.current_frame(),
+            error,
+        };
+
+        if self.state.suspended.load(Ordering::Acquire) {
+            return Err(failed(G::Error::from(SurfaceSuspended)));
+        }
+
+        if !self.can_render() {
+            return Ok(false);
+        }
+
+        if self.is_static() && self.is_suspended() {
+            let size = self.size.read().unwrap().snapshot();
+            self.backend
+                .lock()
+                .unwrap()
+                .resume(&size)
+                .map_err(|err| failed(downcast_error::<G>(err)))?;
+
+            let frame = self.state.current_frame();
+            self.state
+                .record_incident(IncidentKind::Resumed, frame, format_args!("from static"));
+        }
+
+        Ok(true)
+    }
+
+    fn can_render(&self) -> bool {
+        !self.state.auto_suspended.load(Ordering::Acquire)
+            && !self.state.awaiting_configure.load(Ordering::Acquire)
+    }
+
+    ///
+    /// Render a frame which won't change (e.g. a wallpaper) once, and free the
+    /// backend's swapchain whilst it's shown.
+    ///
+    /// The frame is drawn on the CPU into shared memory, which stays attached
+    /// to the surface in place of the swapchain's images. The next
+    /// [AvySurfaceHandle::render] brings the backend back, and the shared
+    /// memory goes once it has presented a frame.
+    ///
+    /// Images drawn must be raster (CPU-side) ones.
+    ///
+    pub fn render_static(
+        &self,
+        mut callback: impl FnMut(&skia_safe::Canvas, &RenderContext),
+    ) -> Result<(), StaticRenderError<G::Error>> {
+        self.render_static_with(|size, context| {
+            let options = self.frame_options();
+            let outline = self.state.rounded_outline(context.logical_size);
+
+            let mut panicked = None;
+            let buffer = StaticBuffer::draw(&self.wl_shm, size, &mut |canvas| {
+                options.apply(canvas);
+
+                let clip = FrameClip::apply(canvas, size, outline);
+                self.state.begin_post_filter(canvas);
+                let drawn = panic::catch_unwind(AssertUnwindSafe(|| callback(canvas, context)));
+                clip.finish(canvas, size);
+
+                match drawn {
+                    Ok(()) => {
+                        self.state.snapshots.capture(
+                            canvas,
+                            context.frame,
+                            context.physical_size,
+                            context.now(),
+                        );
+                        context.draw_unclipped(canvas);
+                    }
+                    Err(payload) => panicked = Some(payload),
+                }
+            });
+
+            (buffer, panicked)
+        })
+    }
+
+    ///
+    /// [AvySurfaceHandle::render_static], drawn in horizontal bands on as many
+    /// threads as there are cores, for large surfaces (e.g. a wallpaper at 4K),
+    /// see [crate::graphics::static_buffer::draw_in_bands].
+    ///
+    /// `callback` is called once for each band, on the band's own thread,
+    /// with the canvas clipped to it. Drawing is in surface coordinates, as
+    /// ever, and whatever falls outside of the band is dropped, so `callback`
+    /// should draw the same thing every time, and only skip what it knows to
+    /// be outside of the canvas' clip bounds.
+    ///
+    /// Surfaces too small to be worth splitting are drawn in one go.
+    ///
+    pub fn render_parallel(
+        &self,
+        callback: impl Fn(&skia_safe::Canvas, &RenderContext) + Sync,
+    ) -> Result<(), StaticRenderError<G::Error>> {
+        self.render_static_with(|size, context| {
+            let options = self.frame_options();
+            let outline = self.state.rounded_outline(context.logical_size);
+            // The same for every band, even mid-transition.
+            let post_filter = self.state.current_post_filter();
+
+            let panicked = Mutex::new(None);
+            let buffer = StaticBuffer::draw_banded(
+                &self.wl_shm,
+                size,
+                &|canvas| {
+                    options.apply(canvas);
+
+                    let clip = FrameClip::apply(canvas, size, outline);
+                    if let Some(post_filter) = &post_filter {
+                        post_filter.begin(canvas);
+                    }
+                    let drawn = panic::catch_unwind(AssertUnwindSafe(|| callback(canvas, context)));
+                    clip.finish(canvas, size);
+
+                    if let Err(payload) = drawn {
+                        panicked.lock().unwrap().get_or_insert(payload);
+                    }
+                },
+                &mut |canvas| {
+                    if panicked.lock().unwrap().is_some() {
+                        return;
+                    }
+
+                    self.state.snapshots.capture(
+                        canvas,
+                        context.frame,
+                        context.physical_size,
+                        context.now(),
+                    );
+                    context.draw_unclipped(canvas);
+                },
+            );
+
+            (buffer, panicked.into_inner().unwrap())
+        })
+    }
+
+    ///
+    /// Draw a static frame with `draw`, which returns the buffer and the
+    /// payload of the render callback's panic, if it panicked, then
+    /// present it in place of the swapchain.
+    ///
+    fn render_static_with(
+        &self,
+        draw: impl FnOnce(
+            &SizeSnapshot,
+            &RenderContext,
+        ) -> (
+            Result<StaticBuffer, StaticBufferError>,
+            Option<Box<dyn Any + Send>>,
+        ),
+    ) -> Result<(), StaticRenderError<G::Error>> {
+        if !self.can_render() {
+            return Ok(());
+        }
+
+        let device = self.device_lock.lock().unwrap();
+        let mut backend = self.backend.lock().unwrap();
+
+        let size = self.size.read().unwrap().snapshot();
+        let frame = backend.presented_frames() + 1;
+        let context = self.context(frame, &size);
+        let _trace_scope = FrameScope::enter(&self.id(), frame);
+
+        self.state.dirty.take();
+        self.state.run_backend_ops(&mut *backend);
+
+        let (buffer, panicked) = draw(&size, &context);
+
+        if let Some(payload) = panicked {
+            drop(backend);
+            drop(device);
+
+            let error = self.callback_panicked(CallbackPanic(payload));
+            self.state.record_error(frame, &error);
+            return Err(StaticRenderError::Render(RenderError { frame, error }));
+        }
+
+        let failed = |err| {
+            let error = downcast_error::<G>(err);
+            StaticRenderError::Render(RenderError { frame, error })
+        };
+
+        let result = buffer.map_err(StaticRenderError::from).and_then(|buffer| {
+            // Let the frames already submitted land before the static one.
+            backend.finish().map_err(failed)?;
+
+            self.state.prepare_present(&context);
+            if let Err(err) = buffer.attach(&self.wl_surface) {
+                self.state.viewport.lock().unwrap().presented(false);
+                return Err(err.into());
+            }
+            self.wl_surface.commit();
+            self.state.viewport.lock().unwrap().presented(true);
+
+            // The compositor no longer needs the swapchain's images.
+            if let Err(err) = backend.suspend() {
+                log::warn!("Could not free the swapchain behind a static frame.");
+                return Err(failed(err));
+            }
+
+            Ok(buffer)
+        });
+
+        drop(backend);
+        drop(device);
+
+        match result {
+            Ok(buffer) => {
+                self.state.mapped.store(true, Ordering::Release);
+                self.state.static_buffer.lock().unwrap().replace(buffer);
+                self.state
+                    .record_incident(IncidentKind::Suspended, frame, format_args!("static"));
+
+                Ok(())
+            }
+            Err(err) => {
+                match &err {
+                    StaticRenderError::Render(failed) => {
+                        self.state.record_error(frame, &failed.error)
+                    }
+                    StaticRenderError::Buffer(buffer) => self.state.record_error(frame, buffer),
+                }
+                Err(err)
+            }
+        }
+    }
+
+    ///
+    /// [AvySurfaceHandle::render_static] with `image` stretched over the surface.
+    ///
+    pub fn render_static_image(
+        &self,
+        image: &skia_safe::Image,
+    ) -> Result<(), StaticRenderError<G::Error>> {
+        self.render_static(|canvas, context| {
+            let (width, height) = context.drawing_size;
+            canvas.draw_image_rect_with_sampling_options(
+                image,
+                None,
+                skia_safe::Rect::from_wh(width as f32, height as f32),
+                skia_safe::SamplingOptions::new(
+                    skia_safe::FilterMode::Linear,
+                    skia_safe::MipmapMode::None,
+                ),
+                &skia_safe::Paint::default(),
+            );
+        })
+    }
+
+    ///
+    /// Whether what's on screen came from [AvySurfaceHandle::render_static].
+    ///
+    pub fn is_static(&self) -> bool {
+        self.state.static_buffer.lock().unwrap().is_some()
+    }
+
+    ///
+    /// Phased alternative to [AvySurfaceHandle::render]:
+    /// the callback receives the acquired frame (if any), and can
+    /// draw into it, then present or drop it.
+    ///
+    /// Panics in the callback are handled as set by [AvySurfaceHandle::set_panic_policy].
+    ///
+    pub fn with_frame<R>(
+        &self,
+        callback: impl FnOnce(SurfaceFrame<'_, G>) -> R,
+    ) -> Result<Option<R>, RenderError<G::Error>> {
+        if !self.prepare_render()? {
+            return Ok(None);
+        }
+
+        let device = self.device_lock.lock().unwrap();
+        let mut backend = self.backend.lock().unwrap();
+        let size = self.size.read().unwrap().snapshot();
+        let number = backend.presented_frames() + 1;
+        let context = self.context(number, &size);
+
+        self.state.dirty.take();
+        self.state.run_backend_ops(&mut *backend);
+
+        let mut frame = match backend.begin_frame(&size, &self.frame_options()) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                let error = downcast_error::<G>(err);
+                self.state.record_error(number, &error);
+                return Err(RenderError {
+                    frame: number,
+                    error,
+                });
+            }
+        };
+
+        if self.state.finish_before_present() {
+            frame.finish_before_present();
+        }
+
+        let outline = self.state.rounded_outline(context.logical_size);
+        let clip = FrameClip::apply(frame.canvas(), &size, outline);
+        let post_filter = self.state.begin_post_filter(frame.canvas());
+
+        // The frame is dropped (discarded) whilst unwinding.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            callback(SurfaceFrame {
+                __: PhantomData,
+                frame,
+                context,
+                size,
+                clip,
+                post_filter,
+                state: &self.state,
+            })
+        }));
+
+        self.state.run_backend_ops(&mut *backend);
+        drop(backend);
+        drop(device);
+
+        result.map(Some).map_err(|payload| {
+            let error = self.callback_panicked(CallbackPanic(payload));
+            self.state.record_error(number, &error);
+            RenderError {
+                frame: number,
+                error,
+            }
+        })
+    }
+}
+
+///
+/// A [Frame] acquired through an [AvySurfaceHandle].
+///
+pub struct SurfaceFrame<'a, G> {
+    __: PhantomData<G>,
+    frame: Frame<'a>,
+    context: RenderContext,
+    /// What the frame was begun at.
+    size: SizeSnapshot,
+    /// Undone before presenting.
+    clip: FrameClip,
+    /// Save count to restore to before presenting, if drawing through a post filter.
+    post_filter: Option<usize>,
+    state: &'a SurfaceShared,
+}
+
+impl<'a, G: GraphicsBackend> SurfaceFrame<'a, G>
+where
+    G::Error: 'static,
+{
+    pub fn canvas(&mut self) -> &skia_safe::Canvas {
+        self.frame.canvas()
+    }
+
+    pub fn gr_context(&mut self) -> Option<&mut skia_safe::gpu::DirectContext> {
+        self.frame.gr_context()
+    }
+
+    pub fn context(&self) -> &RenderContext {
+        &self.context
+    }
+
+    pub fn timings(&self) -> &FrameTimings {
+        self.frame.timings()
+    }
+
+    pub fn mark_drawn(&mut self) {
+        self.frame.mark_drawn()
+    }
+
+    pub fn present(mut self) -> Result<FrameTimings, RenderError<G::Error>> {
+        let frame = self.context.frame;
+        if let Some(save_count) = self.post_filter {
+            self.frame.canvas().restore_to_count(save_count);
+        }
+        self.clip.finish(self.frame.canvas(), &self.size);
+        self.state.prepare_present(&self.context);
+
+        let result = self.frame.present().map_err(downcast_error::<G>);
+        if let Ok(timings) = &result {
+            self.state.record_metrics(timings);
+        }
+
+        self.state.record_render(frame, result.is_ok(), &result);
+        result.map_err(|error| RenderError { frame, error })
+    }
+}
+
+///
+/// Where key events go: to the `exclusive` surface whilst there is one, and
+/// otherwise to the surface with keyboard `focus`, or the one the compositor
+/// focused before it was registered (`pending`).
+///
+fn key_target<K: Clone>(
+    exclusive: Option<&K>,
+    focus: Option<&K>,
+    pending: Option<&K>,
+) -> Option<K> {
+    exclusive.or(focus).or(pending).cloned()
+}
+
+///
+/// Lock `mutex` if nobody else has, panicking if it's poisoned (like `lock().unwrap()`).
+///
+fn try_lock<T: ?Sized>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::WouldBlock) => None,
+        Err(TryLockError::Poisoned(err)) => panic!("{err}"),
+    }
+}
+
+fn downcast_error<G: GraphicsBackend>(err: Box<dyn Any>) -> G::Error
+where
+    G::Error: 'static,
+{
+    *err.downcast::<G::Error>().unwrap()
+}
+
+///
+/// How a frame's canvas was clipped before drawing into it, so that whatever
+/// way the frame's drawn (see [AvySurfaceHandle::render] and
+/// [AvySurfaceHandle::with_frame]), the result is cut to the same shape.
+///
+#[derive(Debug, Clone, Copy)]
+struct FrameClip {
+    /// Save count to restore to once drawn.
+    save_count: usize,
+    /// The surface's outline, in its own logical pixels, if its corners are rounded.
+    outline: Option<RRect>,
+}
+
+impl FrameClip {
+    ///
+    /// Clip `canvas` (set up for a frame of `size`) to the buffer, and to
+    /// `outline` (see [SurfaceShared::rounded_outline]).
+    ///
+    fn apply(canvas: &skia_safe::Canvas, size: &SizeSnapshot, outline: Option<RRect>) -> Self {
+        let save_count = canvas.save();
+
+        // Keep drawing done at stale coordinates (e.g. after a smaller
+        // configure) from landing outside the viewport.
+        size.clip_canvas(canvas);
+        if let Some(outline) = &outline {
+            size.in_surface_space(canvas, |canvas| {
+                canvas.clip_rrect(outline, ClipOp::Intersect, true);
+            });
+        }
+
+        Self {
+            save_count,
+            outline,
+        }
+    }
+
+    ///
+    /// Lift the clip once drawn, and clear the corners outside of the outline.
+    ///
+    fn finish(&self, canvas: &skia_safe::Canvas, size: &SizeSnapshot) {
+        canvas.restore_to_count(self.save_count);
+
+        if let Some(outline) = &self.outline {
+            size.in_surface_space(canvas, |canvas| clear_outside(canvas, outline));
+        }
+    }
+}
+
+///
+/// Make everything outside of `outline` transparent, whatever the frame was cleared to.
+///
+fn clear_outside(canvas: &skia_safe::Canvas, outline: &RRect) {
+    canvas.save();
+    canvas.clip_rrect(outline, ClipOp::Difference, true);
+    canvas.draw_color(Color::TRANSPARENT, BlendMode::Src);
+    canvas.restore();
+}
+
+pub struct RegisteredSurface<'a>(&'a mut AvyClient, ObjectId);
+
+impl<'a> RegisteredSurface<'a> {
+    pub fn id(&self) -> &ObjectId {
+        &self.1
+    }
+
+    pub fn layer_controller(&self) -> Option<AvyLayerController> {
+        self.0.layer_controller(&self.1)
+    }
+
+    pub fn subsurface_controller(&self) -> Option<AvySubsurfaceController> {
+        self.0.subsurface_controller(&self.1)
+    }
+
+    ///
+    /// Send all key events to this surface, see [AvyClient::set_exclusive_keyboard].
+    ///
+    pub fn with_exclusive_keyboard(self) -> Self {
+        self.0.set_exclusive_keyboard(Some(self.1.clone()));
+        self
+    }
+
+    pub fn make_backend<G: GraphicsBackend>(
+        self,
+        backend: &G,
+    ) -> Result<AvySurfaceHandle<G>, G::Error>
+    where
+        G::Surface: 'static,
+    {
+        self.0
+            .attach_backend(&self.1, backend)
+            .expect("registered surfaces are in the client's surfaces")
+    }
+
+    ///
+    /// Draw into the surface with a renderer of one's own (e.g. wgpu or
+    /// OpenGL), rather than with a [GraphicsBackend], through the raw
+    /// window handle [AvySurfaceWindow] provides.
+    ///
+    /// Such surfaces don't get an [AvySurfaceHandle]. Configures, scale changes
+    /// and the viewport are still looked after by [AvyClient], and input still
+    /// reaches the surface through its handler traits as usual.
+    ///
+    pub fn into_raw_window(self) -> AvySurfaceWindow {
+        self.0.attach_raw_window(&self.1)
+    }
+}
+///
+/// Identifies a render group, see [AvyClient::create_render_group].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderGroupId(u64);
+
+///
+/// When the members of a render group are drawing a frame for.
+///
+/// Members on the same output are drawn in the same tick, so they
+/// all see the same time and animate in step.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct GroupTick {
+    pub time: Instant,
+    /// Time the group's clock has been running for, pauses excluded.
+    pub elapsed: Duration,
+}
+
+type GroupDraw = Box<dyn FnMut(GroupTick)>;
+
+struct GroupMember {
+    id: ObjectId,
+    wl_surface: WlSurface,
+    state: Arc<SurfaceShared>,
+    draw: GroupDraw,
+    /// Whether a tick is coming for the member's output anyway,
+    /// so that marking it dirty needn't wake the group up.
+    ticking: Arc<AtomicBool>,
+}
+
+///
+/// The member whose frame callbacks drive the group on one output.
+///
+struct GroupPacer {
+    surface: ObjectId,
+    /// Whether a frame callback was requested and hasn't fired yet.
+    pending: bool,
+    /// When the pending frame callback was requested.
+    requested_at: Option<Instant>,
+    last_tick: Option<Instant>,
+    /// Draws in the callback's stead if it doesn't come, see [FallbackCadence].
+    fallback_timer: Option<TimerToken>,
+}
+
+///
+/// Surfaces which are drawn together, paced by the outputs they're on
+/// rather than each by its own frame callbacks.
+///
+/// For each output, one frame callback is requested per refresh, and all
+/// dirty members on that output are drawn when it fires. Once none of them
+/// is dirty, the output stops ticking until one is marked dirty again.
+/// Members keep their [AvySurfaceHandle]s, which can still render outside
+/// of the group.
+///
+pub struct RenderGroup {
+    members: Vec<GroupMember>,
+    /// By output.
+    pacers: HashMap<ObjectId, GroupPacer>,
+    started: Instant,
+    /// Time spent paused, which the clock doesn't count.
+    paused_for: Duration,
+    paused_at: Option<Instant>,
+    target_fps: Option<f64>,
+}
+
+impl RenderGroup {
+    fn new(started: Instant) -> Self {
+        Self {
+            members: Vec::new(),
+            pacers: HashMap::new(),
+            started,
+            paused_for: Duration::ZERO,
+            paused_at: None,
+            target_fps: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn target_fps(&self) -> Option<f64> {
+        self.target_fps
+    }
+
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        let now = self.paused_at.unwrap_or(now);
+        now.saturating_duration_since(self.started)
+            .saturating_sub(self.paused_for)
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    ///
+    /// Whether it's too soon after `last_tick` to draw again at the target
+    /// rate, which defaults to the `refresh_rate` of the output, and is
+    /// capped to `limit` (e.g. whilst the user's idle).
+    ///
+    fn throttled(
+        &self,
+        last_tick: Option<Instant>,
+        now: Instant,
+        refresh_rate: Option<f32>,
+        limit: Option<f64>,
+    ) -> bool {
+        let fps = match (self.target_fps.or(refresh_rate.map(f64::from)), limit) {
+            (Some(fps), Some(limit)) => Some(fps.min(limit)),
+            (fps, limit) => fps.or(limit),
+        };
+        let (Some(fps), Some(last_tick)) = (fps, last_tick) else {
+            return false;
+        };
+
+        // Frame callbacks jitter, so allow some slack
+        // rather than skipping every other frame.
+        let interval = Duration::from_secs_f64(1.0 / fps);
+        now.saturating_duration_since(last_tick) < interval.mul_f64(0.75)
+    }
+
+    ///
+    /// How long to wait for a frame callback on `output` before drawing without
+    /// one: the shortest [FallbackCadence] of the members drawn on it.
+    ///
+    fn fallback_interval(
+        &self,
+        output: &ObjectId,
+        outputs: &HashMap<ObjectId, Vec<WlOutput>>,
+    ) -> Option<Duration> {
+        self.members
+            .iter()
+            .filter(|member| drawn_on(outputs, &member.id).as_ref() == Some(output))
+            .filter_map(|member| member.state.fallback_cadence().interval())
+            .min()
+    }
+}
+
+///
+/// A render group of an [AvyClient], see [AvyClient::render_group].
+///
+pub struct RenderGroupMut<'a>(&'a mut AvyClient, RenderGroupId);
+
+impl<'a> RenderGroupMut<'a> {
+    pub fn id(&self) -> RenderGroupId {
+        self.1
+    }
+
+    fn group(&mut self) -> &mut RenderGroup {
+        self.0.render_groups.get_mut(&self.1).unwrap()
+    }
+
+    ///
+    /// Draw `handle`'s surface with the group from now on, calling `draw`
+    /// (on the event loop) whenever it's dirty on a tick. Keep animating by
+    /// marking the surface dirty again from `draw`.
+    ///
+    /// A surface is in at most one group: it leaves any other one first.
+    ///
+    pub fn join<G: GraphicsBackend + 'static>(
+        mut self,
+        handle: &AvySurfaceHandle<G>,
+        mut draw: impl FnMut(&skia_safe::Canvas, &RenderContext, &GroupTick) + 'static,
+    ) -> Self
+    where
+        G::Error: 'static,
+    {
+        let id = handle.wl_surface.id();
+        self.0.leave_render_group(&id);
+
+        // Wake the group up when the member's marked dirty whilst its output isn't ticking.
+        let ticking = Arc::new(AtomicBool::new(false));
+        let proxy = self.0.proxy();
+        let (waiting, woken) = (ticking.clone(), id.clone());
+        handle.state.dirty.set_on_mark(Some(Box::new(move || {
+            if waiting.load(Ordering::SeqCst) {
+                return;
+            }
+
+            let woken = woken.clone();
+            // The client is gone, and the group with it.
+            let _ = proxy.invoke(move |app| app.reschedule_render_group(&woken));
+        })));
+
+        let render = handle.clone();
+        let member = GroupMember {
+            id: id.clone(),
+            wl_surface: handle.wl_surface.clone(),
+            state: handle.state.clone(),
+            ticking,
+            draw: Box::new(move |tick| {
+                // Drawn again once resumed, which marks it dirty.
+                if render.state.suspended.load(Ordering::Acquire) {
+                    return;
+                }
+
+                if let Err(err) = render.render(|canvas, context| draw(canvas, context, &tick)) {
+                    log::warn!("Could not draw a member of a render group: {err}");
+                }
+            }),
+        };
+
+        self.group().members.push(member);
+        self.0.surface_groups.insert(id, self.1);
+        self.0.schedule_render_group(self.1);
+        self
+    }
+
+    ///
+    /// Draw at most `fps` frames per second (roughly), or with `None`,
+    /// at each output's refresh rate (see [AvySurfaceHandle::refresh_rate]).
+    ///
+    pub fn set_target_fps(&mut self, fps: Option<f64>) {
+        self.group().target_fps = fps.filter(|fps| *fps > 0.0);
+    }
+
+    ///
+    /// Stop drawing and stop the clock, until [RenderGroupMut::resume].
+    ///
+    pub fn pause(&mut self) {
+        let now = self.0.clock.now();
+        self.group().paused_at.get_or_insert(now);
+    }
+
+    pub fn resume(&mut self) {
+        let now = self.0.clock.now();
+        let group = self.group();
+        if let Some(paused_at) = group.paused_at.take() {
+            group.paused_for += now.saturating_duration_since(paused_at);
+        }
+
+        self.0.schedule_render_group(self.1);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.render_groups[&self.1].is_paused()
+    }
+
+    ///
+    /// Time the group's clock has been running for, pauses excluded.
+    ///
+    pub fn elapsed(&self) -> Duration {
+        self.0.render_groups[&self.1].elapsed(self.0.clock.now())
+    }
+}
+
+pub struct AvyClient {
+    pub wl_display: WlDisplay,
+    pub registry_state: RegistryState,
+    pub compositor_state: CompositorState,
+    pub subcompositor: Option<SubcompositorState>,
+    pub output_state: OutputState,
+    pub shm_state: Shm,
+    pub layer_state: LayerShell,
+    pub fractional_scale: FractionalScaleManager,
+    pub viewporter: Viewporter,
+    pub seat_state: SeatState,
+    pub relative_pointer_state: RelativePointerState,
+
+    pub surfaces: HashMap<ObjectId, Box<dyn AvySurface>>,
+    pub surface_backends: HashMap<ObjectId, Arc<Mutex<dyn GraphicsSurface>>>,
+    pub surface_shared: HashMap<ObjectId, Arc<SurfaceShared>>,
+    pub surface_outputs: HashMap<ObjectId, Vec<WlOutput>>,
+    pub surface_names: HashMap<ObjectId, String>,
+    /// See [AvyClient::surface_by_name].
+    pub surface_registry: SurfaceRegistry,
+    /// See [AvyClient::join_edge_layout].
+    pub edge_layout: EdgeLayout,
+    pub subsurface_stacks: SubsurfaceStacks,
+    pub shared_context: Arc<RwLock<SharedContext>>,
+    /// See [AvyClient::set_clock].
+    pub clock: SharedClock,
+
+    pub render_groups: HashMap<RenderGroupId, RenderGroup>,
+    pub surface_groups: HashMap<ObjectId, RenderGroupId>,
+    next_render_group: u64,
+    /// See [AvyClient::mirror_surface].
+    pub(crate) mirror_group: Option<RenderGroupId>,
+
+    pub pointer: Option<WlPointer>,
+    pub relative_pointer: Option<ZwpRelativePointerV1>,
+    pub cursor_shape: Option<CursorShapeManager>,
+    /// See [AvyClient::set_cursor_state].
+    pub(crate) cursor: Cursor,
+
+    pub keyboard: Option<WlKeyboard>,
+    pub keyboard_focus: Option<ObjectId>,
+    /// Surface all key events go to regardless of focus, see [AvyClient::set_exclusive_keyboard].
+    pub exclusive_keyboard: Option<ObjectId>,
+    pub keymap: Option<KeymapInfo>,
+    /// Keys currently held down, by raw code.
+    pub pressed_keys: HashMap<u32, KeyEvent>,
+    pub deferred_keyboard_events: HashMap<ObjectId, DeferredEvents>,
+    /// A surface the compositor gave keyboard focus before it was
+    /// registered, which takes it (see [AvyClient::keyboard_focus]) once it is.
+    pub pending_keyboard_focus: Option<ObjectId>,
+    /// Surfaces to destroy once the input event being dispatched is handled.
+    pub destroy_requests: DestroyRequests,
+
+    pub touch: Option<WlTouch>,
+    pub active_touches: HashMap<i32, ObjectId>,
+
+    /// See [AvyClient::last_serial].
+    pub(crate) serials: Serials,
+
+    pub loop_handle: Option<LoopHandle<'static, AvyClient>>,
+    /// See [AvyClient::add_timer].
+    pub(crate) timers: Timers,
+    pub queue_handle: QueueHandle<AvyClient>,
+    pub(crate) proxy_queue: ProxyQueue,
+    /// See [AvyClient::loop_metrics].
+    pub(crate) loop_metrics: LoopMetrics,
+
+    pub global_shortcuts: GlobalShortcuts,
+
+    pub idle: IdleWatches,
+    pub primary_selection: PrimarySelection,
+    pub(crate) overlays: Overlays,
+
+    #[cfg(feature = "workspaces")]
+    pub workspaces: Workspaces,
+
+    /// Bound if the compositor supports `hyprland_surface_v1`, see [AvySurfaceHandle::hyprland].
+    #[cfg(feature = "hyprland-surface")]
+    pub hyprland_surfaces: Option<HyprlandSurfaceManager>,
+
+    /// Bound if the compositor supports virtual keyboards, see [crate::wayland::surface::osk::OskLayer].
+    #[cfg(feature = "virtual-keyboard")]
+    pub virtual_keyboard: Option<VirtualKeyboardManager>,
+
+    /// Bound if the compositor supports `wp_presentation`, see [AvySurfaceHandle::set_measure_input_latency].
+    #[cfg(feature = "input-latency")]
+    pub presentation: Option<PresentationManager>,
+
+    /// Objects made for registered surfaces, to trace protocol errors back to them.
+    pub(crate) objects: ObjectRegistry,
+    /// See [AvyClient::on_fatal_error].
+    pub(crate) fatal_error: Option<FatalErrorCallback>,
+
+    pub running: bool,
+    /// Set by [AvyClient::shutdown], so that dropping doesn't tear down again.
+    pub(crate) shut_down: bool,
+}
+
+impl AvyClient {
+    pub fn new(
+        global_list: &GlobalList,
+        queue_handle: &QueueHandle<Self>,
+        logical_size: (u32, u32),
+        wl_display: WlDisplay,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let compositor_state = CompositorState::bind(global_list, queue_handle)?;
+        let subcompositor = SubcompositorState::bind(
+            compositor_state.wl_compositor().clone(),
+            global_list,
+            queue_handle,
+        )
+        .ok();
+        let clock = SharedClock::default();
+        let loop_metrics = LoopMetrics::new(clock.now());
+
+        Ok(Self {
+            wl_display,
+            registry_state: RegistryState::new(global_list),
+            compositor_state,
+            subcompositor,
+            output_state: OutputState::new(global_list, queue_handle),
+            shm_state: Shm::bind(global_list, queue_handle)?,
+            layer_state: LayerShell::bind(global_list, queue_handle)?,
+            fractional_scale: FractionalScaleManager::new(global_list, queue_handle)?,
+            viewporter: Viewporter::new(global_list, queue_handle)?,
+            seat_state: SeatState::new(global_list, queue_handle),
+            relative_pointer_state: RelativePointerState::bind(global_list, queue_handle),
+
+            surfaces: HashMap::new(),
+            surface_backends: HashMap::new(),
+            surface_shared: HashMap::new(),
+            surface_outputs: HashMap::new(),
+            surface_names: HashMap::new(),
+            surface_registry: SurfaceRegistry::default(),
+            edge_layout: EdgeLayout::default(),
+            subsurface_stacks: SubsurfaceStacks::new(),
+            shared_context: Arc::new(RwLock::new(SharedContext {
+                appearance: Appearance::from_env(),
+                accessibility: AccessibilityOptions::default(),
+                locale: LanguageIdentifier::from_env(),
+                font_scale: FontScale::default(),
+                clock: clock.clone(),
+            })),
+            clock,
+
+            render_groups: HashMap::new(),
+            surface_groups: HashMap::new(),
+            next_render_group: 0,
+            mirror_group: None,
+
+            pointer: None,
+            relative_pointer: None,
+            cursor_shape: CursorShapeManager::bind(global_list, queue_handle).ok(),
+            cursor: Cursor::default(),
+            keyboard: None,
+            keyboard_focus: None,
+            exclusive_keyboard: None,
+            keymap: None,
+            pressed_keys: HashMap::new(),
+            deferred_keyboard_events: HashMap::new(),
+            pending_keyboard_focus: None,
+            destroy_requests: DestroyRequests::default(),
+            touch: None,
+            active_touches: HashMap::new(),
+            serials: Serials::default(),
+
+            loop_handle: None,
+            timers: Timers::default(),
+            queue_handle: queue_handle.clone(),
+            proxy_queue: ProxyQueue::new(),
+            loop_metrics,
+
+            global_shortcuts: GlobalShortcuts::new(
+                GlobalShortcutsManager::new(global_list, queue_handle)
+                    .ok()
+                    .map(|manager| (manager, queue_handle.clone())),
+            ),
+
+            idle: IdleWatches::new(IdleNotifier::new(global_list, queue_handle).ok()),
+            primary_selection: PrimarySelection::new(
+                PrimarySelectionManagerState::bind(global_list, queue_handle).ok(),
+            ),
+            overlays: Overlays::new(),
+
+            #[cfg(feature = "workspaces")]
+            workspaces: Workspaces::new(ExtWorkspaceState::bind(global_list, queue_handle).ok()),
+
+            #[cfg(feature = "hyprland-surface")]
+            hyprland_surfaces: HyprlandSurfaceManager::new(global_list, queue_handle).ok(),
+
+            #[cfg(feature = "virtual-keyboard")]
+            virtual_keyboard: VirtualKeyboardManager::new(global_list, queue_handle).ok(),
+
+            #[cfg(feature = "input-latency")]
+            presentation: PresentationManager::new(global_list, queue_handle).ok(),
+
+            objects: ObjectRegistry::default(),
+            fatal_error: None,
+
+            running: true,
+            shut_down: false,
+        })
+    }
+
+    ///
+    /// Start dispatching events to `surface`.
+    ///
+    /// `name` identifies the surface when debugging (see [AvyClient::dump_state]),
+    /// defaulting to [AvySurface::debug_name], and for other components to find
+    /// it by (see [AvyClient::surface_by_name]). Fails, registering nothing,
+    /// if another surface has that name.
+    ///
+    pub fn register_surface<S: AvySurface + 'static>(
+        &mut self,
+        surface: S,
+        name: Option<&str>,
+        event_queue: &mut EventQueue<Self>,
+    ) -> Result<RegisteredSurface, NameTaken> {
+        // Checked before anything is made, so that nothing's left to undo.
+        if let Some(name) = name.filter(|name| self.surface_registry.contains(name)) {
+            return Err(NameTaken(name.to_owned()));
+        }
+
+        Ok(self.register(surface, name, event_queue))
+    }
+
+    ///
+    /// [AvyClient::register_surface], for surfaces whose name can't be taken:
+    /// layers and subsurfaces made by the crate go by their default name.
+    ///
+    pub(crate) fn register<S: AvySurface + 'static>(
+        &mut self,
+        surface: S,
+        name: Option<&str>,
+        event_queue: &mut EventQueue<Self>,
+    ) -> RegisteredSurface {
+        let id = surface.wl_surface().id();
+
+        if let Some(name) = name.map(str::to_owned).or_else(|| surface.debug_name()) {
+            self.surface_names.insert(id.clone(), name);
+        }
+
+        self.surfaces.insert(id.clone(), Box::new(surface));
+        if let Err(NameTaken(name)) = self.register_name(&id, name) {
+            // Explicit names were checked by [AvyClient::register_surface], so this is
+            // a default one, which are shared on purpose (e.g. by a bar on every
+            // output): the first surface keeps it.
+            log::debug!("{name:?} is taken, so {id} goes unnamed.");
+        }
+
+        {
+            let surface = self.surfaces.get_mut(&id).unwrap();
+            self.objects.track(surface.viewport().id(), id.clone());
+
+            surface.wl_surface().commit();
+        }
+
+        self.replay_deferred_events(&id, &event_queue.handle());
+
+        // A protocol error here (e.g. the surface was set up wrong) is
+        // reported, rather than torn down with a panic.
+        if let Err(error) = event_queue.roundtrip(self) {
+            self.dispatch_failed(error);
+        }
+
+        RegisteredSurface(self, id)
+    }
+
+    ///
+    /// Move a registered surface over to another graphics backend,
+    /// without recreating (and so remapping) the Wayland surface.
+    ///
+    /// Once the new backend is made, the old one is detached, so renders
+    /// through old handles fail with the old backend's error from then on.
+    /// If making the new backend fails, the old one carries on.
+    ///
+    pub fn rebind_backend<G: GraphicsBackend>(
+        &mut self,
+        id: &ObjectId,
+        backend: &G,
+    ) -> Result<AvySurfaceHandle<G>, RebindError<G::Error>>
+    where
+        G::Surface: 'static,
+    {
+        let unknown = || RebindError::UnknownSurface(id.clone());
+        let old = self.surface_backends.get(id).cloned().ok_or_else(unknown)?;
+        let size = self.surfaces.get(id).ok_or_else(unknown)?.size().clone();
+
+        // Waits for any render in progress on the old backend, and keeps
+        // renders through old handles waiting until it's swapped out.
+        let mut old = old.lock().unwrap();
+
+        // A Wayland surface only takes one swapchain at a time: free the
+        // old one, and bring it back if the new backend can't be made.
+        if old.suspend().is_err() {
+            log::warn!("Could not suspend the old backend of {id}.");
+        }
+
+        let handle = match self.attach_backend(id, backend) {
+            Some(Ok(handle)) => handle,
+            Some(Err(err)) => {
+                let size = size.read().unwrap().snapshot();
+                if old.resume(&size).is_err() {
+                    log::warn!("Could not resume the old backend of {id}.");
+                }
+
+                return Err(RebindError::Backend(err));
+            }
+            None => return Err(unknown()),
+        };
+
+        if old.detach().is_err() {
+            log::warn!("Could not cleanly detach the old backend of {id}.");
+        }
+        drop(old);
+
+        // The new backend has nothing on screen yet, nor the old one's blurs.
+        handle.state.dirty.mark();
+        handle.state.blurs.clear();
+        handle.state.events.emit(SurfaceEvent::BackendRecreated);
+        handle.state.record_incident(
+            IncidentKind::BackendRecreated,
+            handle.state.current_frame(),
+            format_args!("{}", handle.backend.lock().unwrap().backend_name()),
+        );
+
+        Ok(handle)
+    }
+
+    ///
+    /// Draw into surface `id` with a new backend made by `backend`, replacing
+    /// any it had, or `None` if there's no such surface.
+    ///
+    fn attach_backend<G: GraphicsBackend>(
+        &mut self,
+        id: &ObjectId,
+        backend: &G,
+    ) -> Option<Result<AvySurfaceHandle<G>, G::Error>>
+    where
+        G::Surface: 'static,
+    {
+        let surface = self.surfaces.get(id)?.as_ref();
+        let wl_surface = surface.wl_surface().clone();
+        let size = surface.size().clone();
+        let viewport = surface.viewport().clone();
+        let configure_ack = surface.configure_ack().cloned();
+        let map_after_first_frame = surface.map_after_first_frame();
+
+        let mut backend = match backend.for_surface(&self.wl_display, surface) {
+            Ok(backend) => backend,
+            Err(err) => return Some(Err(err)),
+        };
+        backend.set_clock(self.clock.clone());
+        let device_lock = backend.device_lock();
+        let backend = Arc::new(Mutex::new(backend));
+        self.surface_backends.insert(id.clone(), backend.clone());
+
+        let state = self.share_surface(id, viewport, configure_ack, map_after_first_frame);
+
+        Some(Ok(AvySurfaceHandle {
+            __: PhantomData,
+            wl_surface,
+            wl_shm: self.shm_state.wl_shm().clone(),
+            size,
+            backend,
+            device_lock,
+            shared: self.shared_context.clone(),
+            state,
+        }))
+    }
+
+    ///
+    /// Set up the state shared with whatever draws into the surface.
+    ///
+    fn share_surface(
+        &mut self,
+        id: &ObjectId,
+        viewport: WpViewport,
+        configure_ack: Option<ConfigureAck>,
+        map_after_first_frame: bool,
+    ) -> Arc<SurfaceShared> {
+        #[cfg(feature = "config")]
+        let created = !self.surface_shared.contains_key(id);
+
+        // Keep visibility, suspension etc. when rebinding.
+        let state = self
+            .surface_shared
+            .entry(id.clone())
+            .or_insert_with(|| {
+                let state = SurfaceShared {
+                    clock: self.clock.clone(),
+                    ..SurfaceShared::default()
+                };
+                state
+                    .map_after_first_frame
+                    .store(map_after_first_frame, Ordering::Release);
+                *state.idle_trim_after.lock().unwrap() = Some(DEFAULT_IDLE_TRIM);
+                Arc::new(state)
+            })
+            .clone();
+        *state.viewport.lock().unwrap() = ViewportSync::new(viewport);
+
+        let wl_surface = self.surfaces[id].wl_surface().clone();
+        let compositor = self.compositor_state.wl_compositor().clone();
+        *state.input_shape.lock().unwrap() = InputShapeSync::new(wl_surface, compositor);
+        *state.configure_ack.lock().unwrap() = configure_ack;
+        *state.controller.lock().unwrap() = self.surface_controller(id).unwrap_or_default();
+        state
+            .keyboard_focused
+            .store(self.keyboard_focus.as_ref() == Some(id), Ordering::Release);
+
+        #[cfg(feature = "hyprland-surface")]
+        if let Some(manager) = self.hyprland_surfaces.as_ref() {
+            // One per surface, kept across rebinds.
+            if !state.hyprland.is_supported() {
+                let object =
+                    manager.get_surface(self.surfaces[id].wl_surface(), &self.queue_handle);
+                self.objects.track(object.id(), id.clone());
+                state.hyprland.bind(object, state.dirty.clone());
+            }
+        }
+
+        #[cfg(feature = "input-latency")]
+        if let Some(manager) = self.presentation.as_ref() {
+            let wl_surface = self.surfaces[id].wl_surface().clone();
+            state
+                .input_latency
+                .bind(manager, wl_surface, self.queue_handle.clone());
+        }
+
+        self.update_pixel_geometry(id);
+        self.update_refresh_rate(id);
+        self.update_output_dpi(id);
+
+        #[cfg(feature = "config")]
+        if created {
+            self.apply_target_fps_rule(id, &state);
+        }
+
+        state
+    }
+
+    ///
+    /// Hand a registered surface over to a renderer of its own, see
+    /// [RegisteredSurface::into_raw_window].
+    ///
+    fn attach_raw_window(&mut self, id: &ObjectId) -> AvySurfaceWindow {
+        let surface = self.surfaces.get_mut(id).unwrap().as_mut();
+        let wl_surface = surface.wl_surface().clone();
+        let size = surface.size().clone();
+        let viewport = surface.viewport().clone();
+        let configure_ack = surface.configure_ack().cloned();
+
+        // Nothing here knows when the renderer's first frame is done.
+        let state = self.share_surface(id, viewport, configure_ack, false);
+
+        AvySurfaceWindow::new(self.wl_display.clone(), wl_surface, size, state)
+    }
+
+    ///
+    /// Get a controller for a registered layer surface.
+    ///
+    pub fn layer_controller(&self, id: &ObjectId) -> Option<AvyLayerController> {
+        self.layer(id).map(AvyLayer::controller)
+    }
+
+    pub fn appearance(&self) -> Appearance {
+        self.shared_context.read().unwrap().appearance
+    }
+
+    pub fn set_appearance(&mut self, appearance: Appearance) {
+        let previous = std::mem::replace(
+            &mut self.shared_context.write().unwrap().appearance,
+            appearance,
+        );
+
+        if previous != appearance {
+            self.mark_all_dirty();
+        }
+    }
+
+    pub fn accessibility(&self) -> AccessibilityOptions {
+        self.shared_context.read().unwrap().accessibility
+    }
+
+    ///
+    /// Switch high-contrast or forced-colors rendering on or off,
+    /// redrawing every surface if anything changed.
+    ///
+    pub fn set_accessibility(&mut self, options: AccessibilityOptions) {
+        let previous = std::mem::replace(
+            &mut self.shared_context.write().unwrap().accessibility,
+            options,
+        );
+
+        if previous != options {
+            self.mark_all_dirty();
+        }
+    }
+
+    pub fn locale(&self) -> LanguageIdentifier {
+        self.shared_context.read().unwrap().locale.clone()
+    }
+
+    ///
+    /// Set the locale surfaces are drawn for (by default, from `LANG`),
+    /// redrawing every surface if it changed.
+    ///
+    /// Its [crate::locale::TextDirection] is handed to render callbacks
+    /// through [RenderContext::direction].
+    ///
+    pub fn set_locale(&mut self, locale: LanguageIdentifier) {
+        let previous = std::mem::replace(
+            &mut self.shared_context.write().unwrap().locale,
+            locale.clone(),
+        );
+
+        if previous != locale {
+            self.mark_all_dirty();
+        }
+    }
+
+    pub fn font_scale(&self) -> FontScale {
+        self.shared_context.read().unwrap().font_scale
+    }
+
+    ///
+    /// Set how font sizes are resolved (see [RenderContext::font_size]),
+    /// redrawing every surface if it changed.
+    ///
+    pub fn set_font_scale(&mut self, font_scale: FontScale) {
+        let previous = std::mem::replace(
+            &mut self.shared_context.write().unwrap().font_scale,
+            font_scale,
+        );
+
+        if previous != font_scale {
+            self.mark_all_dirty();
+        }
+    }
+
+    pub fn mark_all_dirty(&self) {
+        self.surface_shared
+            .values()
+            .for_each(|state| state.dirty.mark());
+    }
+
+    ///
+    /// Match the surface's text anti-aliasing to the subpixel
+    /// layout of the output it's (first) shown on.
+    ///
+    fn update_pixel_geometry(&mut self, id: &ObjectId) {
+        let Some(state) = self.surface_shared.get(id) else {
+            return;
+        };
+
+        let geometry = self
+            .surface_outputs
+            .get(id)
+            .and_then(|outputs| outputs.first())
+            .and_then(|output| self.output_state.info(output))
+            .map(|info| pixel_geometry_for(info.subpixel, info.transform))
+            .unwrap_or_default();
+
+        // Kept for when the override is cleared, see [AvySurfaceHandle::set_pixel_geometry].
+        *state.output_pixel_geometry.lock().unwrap() = geometry;
+        if state.pixel_geometry_override.lock().unwrap().is_some() {
+            return;
+        }
+
+        self.with_surface_backend(id, move |backend| backend.set_pixel_geometry(geometry));
+        state.dirty.mark();
+    }
+
+    ///
+    /// Run `op` on surface `id`'s backend, now if it's free, or else as
+    /// soon as whatever holds it (a render, usually) is done with it,
+    /// rather than blocking the event loop, and input, until then.
+    ///
+    /// Changes are made in the order asked for either way.
+    ///
+    pub(crate) fn with_surface_backend(
+        &self,
+        id: &ObjectId,
+        op: impl FnOnce(&mut dyn GraphicsSurface) + Send + 'static,
+    ) {
+        let (Some(state), Some(backend)) =
+            (self.surface_shared.get(id), self.surface_backends.get(id))
+        else {
+            return;
+        };
+
+        match try_lock(backend) {
+            Some(mut backend) => {
+                state.run_backend_ops(&mut *backend);
+                op(&mut *backend);
+            }
+            None => state.backend_ops.lock().unwrap().push(Box::new(op)),
+        }
+    }
+
+    ///
+    /// [AvySurfaceHandle::release_keyboard_focus], from the event loop.
+    ///
+    pub fn release_keyboard_focus(&mut self, id: &ObjectId) -> Result<(), WrongKind> {
+        let Some(state) = self.surface_shared.get(id) else {
+            return Ok(());
+        };
+
+        state.release_keyboard_focus()?;
+        self.forget_released_focus();
+        Ok(())
+    }
+
+    ///
+    /// Stop sending key events to a surface which gave keyboard focus back
+    /// (from its handle), ahead of the compositor's leave.
+    ///
+    pub(crate) fn forget_released_focus(&mut self) {
+        let Some(id) = &self.keyboard_focus else {
+            return;
+        };
+
+        let released = self
+            .surface_shared
+            .get(id)
+            .is_some_and(|state| !state.keyboard_focused.load(Ordering::Acquire));
+        if released {
+            self.lose_keyboard_focus();
+        }
+    }
+
+    fn lose_keyboard_focus(&mut self) {
+        if let Some(id) = self.keyboard_focus.take() {
+            if let Some(state) = self.surface_shared.get(&id) {
+                state.keyboard_focused.store(false, Ordering::Release);
+            }
+        }
+
+        self.pressed_keys.clear();
+    }
+
+    ///
+    /// Make the changes put off by [AvyClient::with_surface_backend]
+    /// to backends which are free by now.
+    ///
+    pub(crate) fn flush_backend_ops(&self) {
+        for (id, backend) in &self.surface_backends {
+            let Some(state) = self.surface_shared.get(id) else {
+                continue;
+            };
+
+            if state.backend_ops.lock().unwrap().is_empty() {
+                continue;
+            }
+
+            if let Some(mut backend) = try_lock(backend) {
+                state.run_backend_ops(&mut *backend);
+            }
+        }
+    }
+
+    ///
+    /// Suspend a surface which just went off-screen, once
+    /// its grace period is up (if it's still hidden then).
+    ///
+    fn schedule_auto_suspend(&self, id: &ObjectId) {
+        let Some(state) = self.surface_shared.get(id) else {
+            return;
+        };
+
+        let Some(grace) = *state.auto_suspend_grace.lock().unwrap() else {
+            return;
+        };
+
+        let timer_id = id.clone();
+        let token = self.add_timer(grace, move |app| {
+            app.auto_suspend(&timer_id);
+            TimerAction::Drop
+        });
+
+        match token {
+            Ok(token) => {
+                if let Some(previous) = state.auto_suspend_timer.lock().unwrap().replace(token) {
+                    self.cancel_timer(previous);
+                }
+            }
+            Err(err) => log::warn!("Cannot auto-suspend surface {id}: {err}"),
+        }
+    }
+
+    fn auto_suspend(&mut self, id: &ObjectId) {
+        let Some(state) = self.surface_shared.get(id) else {
+            return;
+        };
+
+        state.auto_suspend_timer.lock().unwrap().take();
+
+        if state.is_visible() || state.auto_suspended.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        // Recorded once it's happened, which may be after a render holding the backend.
+        let (suspended, state) = (id.clone(), state.clone());
+        self.with_surface_backend(id, move |backend| {
+            if backend.suspend().is_err() {
+                log::warn!("Failed to auto-suspend surface {suspended}");
+                return;
+            }
+
+            let frame = state.current_frame();
+            state.record_incident(IncidentKind::Suspended, frame, format_args!("hidden"));
+        });
+    }
+
+    fn auto_resume(&mut self, id: &ObjectId) {
+        let (Some(state), Some(surface)) = (self.surface_shared.get(id), self.surfaces.get(id))
+        else {
+            return;
+        };
+
+        if let Some(token) = state.auto_suspend_timer.lock().unwrap().take() {
+            self.cancel_timer(token);
+        }
+
+        if !state.auto_suspended.swap(false, Ordering::AcqRel) {
+            return;
+        }
+
+        state.dirty.mark();
+
+        let size = surface.size_ref().snapshot();
+        let (resumed, state) = (id.clone(), state.clone());
+        self.with_surface_backend(id, move |backend| {
+            if backend.resume(&size).is_err() {
+                log::warn!("Failed to auto-resume surface {resumed}");
+                return;
+            }
+
+            let frame = state.current_frame();
+            state.record_incident(IncidentKind::Resumed, frame, format_args!("visible again"));
+        });
+    }
+
+    ///
+    /// Follow the refresh rate of the output the surface is (first) shown on.
+    ///
+    fn update_refresh_rate(&self, id: &ObjectId) {
+        let Some(state) = self.surface_shared.get(id) else {
+            return;
+        };
+
+        let millihertz = self
+            .surface_outputs
+            .get(id)
+            .and_then(|outputs| outputs.first())
+            .and_then(|output| self.output_refresh_rate(output))
+            .map_or(0, |refresh_rate| (refresh_rate * 1000.0).round() as u32);
+
+        if state.refresh_rate.swap(millihertz, Ordering::AcqRel) != millihertz {
+            let refresh_rate = state.refresh_rate();
+            self.emit_surface_event(id, SurfaceEvent::RefreshRateChanged { refresh_rate });
+        }
+    }
+
+    ///
+    /// Follow the pixel density of the output the surface is (first) shown on,
+    /// redrawing it if that changed (and fonts are sized physically).
+    ///
+    fn update_output_dpi(&self, id: &ObjectId) {
+        let Some(state) = self.surface_shared.get(id) else {
+            return;
+        };
+
+        let dpi = self
+            .surface_outputs
+            .get(id)
+            .and_then(|outputs| outputs.first())
+            .and_then(|output| self.output_dpi(output));
+
+        let previous = std::mem::replace(&mut *state.output_dpi.lock().unwrap(), dpi);
+        if previous != dpi && self.font_scale() != FontScale::LogicalPoints {
+            state.dirty.mark();
+        }
+    }
+
+    pub fn output_dpi(&self, output: &WlOutput) -> Option<OutputDpi> {
+        OutputDpi::from_info(&self.output_state.info(output)?)
+    }
+
+    ///
+    /// Refresh rate of the output's current mode, in Hz.
+    ///
+    pub fn output_refresh_rate(&self, output: &WlOutput) -> Option<f32> {
+        let info = self.output_state.info(output)?;
+        let mode = info.modes.iter().find(|mode| mode.current)?;
+
+        // Zero when the compositor doesn't know, e.g. for virtual outputs.
+        (mode.refresh_rate > 0).then(|| mode.refresh_rate as f32 / 1000.0)
+    }
+
+    fn emit_surface_event(&self, id: &ObjectId, event: SurfaceEvent) {
+        if let Some(state) = self.surface_shared.get(id) {
+            state.events.emit(event);
+        }
+    }
+
+    pub(crate) fn output_name(&self, output: &WlOutput) -> Option<String> {
+        self.output_state.info(output)?.name
+    }
+
+    ///
+    /// The output the compositor calls `name` (e.g. `"DP-1"`), if there is one.
+    ///
+    pub fn output_by_name(&self, name: &str) -> Option<WlOutput> {
+        self.output_state
+            .outputs()
+            .find(|output| self.output_name(output).as_deref() == Some(name))
+    }
+
+    ///
+    /// The names of all outputs which advertise one.
+    ///
+    pub fn output_names(&self) -> Vec<String> {
+        self.output_state
+            .outputs()
+            .filter_map(|output| self.output_name(&output))
+            .collect()
+    }
+
+    ///
+    /// The clock animations, render groups and other time-based behavior go
+    /// by, shared with every surface (and their [RenderContext]s).
+    ///
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    ///
+    /// Go by `clock` from now on, e.g. a [crate::util::TestClock] in tests.
+    /// Best set before anything's started animating, as time may jump.
+    ///
+    /// With a clock which doesn't keep up with the system's, see
+    /// [AvyClient::catch_up_timers] for timers to run once it's moved on.
+    ///
+    pub fn set_clock(&self, clock: impl Clock + 'static) {
+        self.clock.replace(clock);
+
+        // The earliest timer is as far off as the new clock says.
+        self.disarm_timer_wakeup();
+        if let Err(err) = self.arm_timer_wakeup() {
+            log::warn!("Could not wake up for the next timer: {err}");
+        }
+    }
+
+    pub fn connection(&self) -> Connection {
+        Connection::from_backend(
+            self.wl_display
+                .backend()
+                .upgrade()
+                .expect("Wayland connection closed"),
+        )
+    }
+
+    ///
+    /// Send all key and modifier events to `surface`, whichever surface the
+    /// compositor says has keyboard focus. Other surfaces still get enter
+    /// and leave events, but nothing in between.
+    ///
+    /// `None` goes back to following the compositor's focus.
+    ///
+    pub fn set_exclusive_keyboard(&mut self, surface: Option<ObjectId>) {
+        self.exclusive_keyboard = surface;
+    }
+
+    ///
+    /// The surface key events should be delivered to.
+    ///
+    /// A layer which took every key event as it was built with
+    /// exclusive keyboard interactivity lets go once that changes.
+    ///
+    fn keyboard_target(&mut self) -> Option<ObjectId> {
+        let released = self
+            .exclusive_keyboard
+            .as_ref()
+            .and_then(|id| self.layer(id))
+            .is_some_and(AvyLayer::take_exclusive_keyboard_released);
+        if released {
+            self.exclusive_keyboard.take();
+        }
+
+        key_target(
+            self.exclusive_keyboard.as_ref(),
+            self.keyboard_focus.as_ref(),
+            self.pending_keyboard_focus.as_ref(),
+        )
+    }
+
+    fn notify_capabilities(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        before: Capabilities,
+    ) {
+        let capabilities = self.capabilities();
+        if capabilities == before {
+            return;
+        }
+
+        log::debug!("Input capabilities changed: {capabilities:?}");
+        for surface in self.surfaces.values_mut() {
+            surface.capabilities_changed(conn, qh, capabilities);
+        }
+    }
+
+    ///
+    /// Keep hold of a keyboard event for a surface we don't know about (yet).
+    ///
+    fn defer_keyboard_event(&mut self, id: ObjectId, event: DeferredKeyboardEvent) {
+        log::debug!("Deferring keyboard event for unregistered surface {id}");
+
+        let now = self.clock.now();
+        defer(&mut self.deferred_keyboard_events, id, event, now);
+    }
+
+    ///
+    /// Deliver any events which arrived for this surface before it was registered.
+    ///
+    fn replay_deferred_events(&mut self, id: &ObjectId, qh: &QueueHandle<Self>) {
+        if self.pending_keyboard_focus.as_ref() == Some(id) {
+            self.keyboard_focus = self.pending_keyboard_focus.take();
+        }
+
+        let Some(DeferredEvents { events, .. }) = self.deferred_keyboard_events.remove(id) else {
+            return;
+        };
+
+        let conn = self.connection();
+        let surface = self.surfaces.get_mut(id).unwrap().as_mut();
+
+        for event in events {
+            event.deliver(surface, &conn, qh);
+        }
+    }
+}
+
+impl ShmHandler for AvyClient {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm_state
+    }
+}
+
+delegate_shm!(AvyClient);
+
+impl ProvidesRegistryState for AvyClient {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+
+    registry_handlers!(OutputState);
+}
+
+impl CompositorHandler for AvyClient {
+    fn scale_factor_changed(
+        &mut self,
+        conn: &Connection,
+        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
+        surface: &WlSurface,
+        new_factor: i32,
+    ) {
+    }
+
+    fn transform_changed(
+        &mut self,
+        conn: &Connection,
+        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
+        surface: &WlSurface,
+        new_transform: smithay_client_toolkit::reexports::client::protocol::wl_output::Transform,
+    ) {
+    }
+
+    fn frame(
+        &mut self,
+        conn: &Connection,
+        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
+        surface: &WlSurface,
+        time: u32,
+    ) {
+        let id = surface.id();
+        debug::objects::callback_done(&id);
+
+        if self.cursor_frame(&id) {
+            return;
+        }
+
+        if let Some(group) = self.surface_groups.get(&id).copied() {
+            self.tick_render_group(group, &id);
+        }
+
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.frame(conn, qh, time);
+        }
+    }
+
+    fn surface_enter(
+        &mut self,
+        conn: &Connection,
+        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
+        surface: &WlSurface,
+        output: &smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
+    ) {
+        let id = surface.id();
+        if self.is_cursor_surface(&id) {
+            return;
+        }
+
+        self.surface_outputs
+            .entry(id.clone())
+            .or_default()
+            .push(output.clone());
+
+        let became_visible = self
+            .surface_shared
+            .get(&id)
+            .is_some_and(|state| state.hidden_since.lock().unwrap().take().is_some());
+
+        self.auto_resume(&id);
+
+        self.update_pixel_geometry(&id);
+        self.update_refresh_rate(&id);
+        self.update_output_dpi(&id);
+        self.reschedule_render_group(&id);
+
+        self.update_named_surface(&id);
+        self.update_edge_layout(&id);
+
+        let name = self.output_name(output);
+        self.emit_surface_event(&id, SurfaceEvent::EnteredOutput { name });
+        if became_visible {
+            self.emit_surface_event(&id, SurfaceEvent::VisibilityChanged { visible: true });
+        }
+    }
+
+    fn surface_leave(
+        &mut self,
+        conn: &Connection,
+        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
+        surface: &WlSurface,
+        output: &smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
+    ) {
+        let id = surface.id();
+        let mut became_hidden = false;
+        if let Some(outputs) = self.surface_outputs.get_mut(&id) {
+            outputs.retain(|o| o != output);
+
+            if let (true, Some(state)) = (outputs.is_empty(), self.surface_shared.get(&id)) {
+                let now = state.clock.now();
+                state.hidden_since.lock().unwrap().replace(now);
+                self.schedule_auto_suspend(&id);
+                became_hidden = true;
+            }
+        }
+
+        self.update_pixel_geometry(&id);
+        self.update_refresh_rate(&id);
+        self.update_output_dpi(&id);
+        self.reschedule_render_group(&id);
+        self.update_named_surface(&id);
+        self.update_edge_layout(&id);
+
+        let name = self.output_name(output);
+        self.emit_surface_event(&id, SurfaceEvent::LeftOutput { name });
+        if became_hidden {
+            self.emit_surface_event(&id, SurfaceEvent::VisibilityChanged { visible: false });
+        }
+    }
+}
+
+impl OutputHandler for AvyClient {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        conn: &Connection,
+        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
+        output: smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
+    ) {
+    }
+
+    fn update_output(
+        &mut self,
+        conn: &Connection,
+        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
+        output: smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
+    ) {
+        // E.g. the mode changed, from 144 to 60Hz.
+        let on_output = self
+            .surface_outputs
+            .iter()
+            .filter(|(_, outputs)| outputs.first() == Some(&output))
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+
+        for id in on_output {
+            self.update_refresh_rate(&id);
+            self.update_output_dpi(&id);
+        }
+    }
+
+    fn output_destroyed(
+        &mut self,
+        conn: &Connection,
+        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
+        output: smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
+    ) {
+    }
+}
+
+delegate_compositor!(AvyClient);
+delegate_subcompositor!(AvyClient);
+delegate_output!(AvyClient);
+delegate_registry!(AvyClient);
+
+delegate_dispatch!(AvyClient: [ZwlrLayerShellV1: GlobalData] => LayerShell);
+
+///
+/// Layer surface events normally go straight to sctk, which acknowledges
+/// every configure as soon as it arrives. Surfaces which ack by hand
+/// (see [AvySurface::configure_ack]) skip that.
+///
+impl Dispatch<ZwlrLayerSurfaceV1, LayerSurfaceData> for AvyClient {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        data: &LayerSurfaceData,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let zwlr_layer_surface_v1::Event::Configure {
+            serial,
+            width,
+            height,
+        } = event
+        {
+            if let Some(layer) = LayerSurface::from_wlr_surface(proxy) {
+                let id = layer.wl_surface().id();
+                let manual_ack = state
+                    .surfaces
+                    .get(&id)
+                    .is_some_and(|surface| surface.configure_ack().is_some());
+
+                if manual_ack {
+                    state.configure_surface(conn, qh, &id, (width, height), serial);
+                    return;
+                }
+            }
+        }
+
+        <LayerShell as Dispatch<ZwlrLayerSurfaceV1, LayerSurfaceData, Self>>::event(
+            state, proxy, event, data, conn, qh,
+        )
+    }
+}
+
+impl LayerShellHandler for AvyClient {
+    fn closed(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        layer: &smithay_client_toolkit::shell::wlr_layer::LayerSurface,
+    ) {
+        let id = layer.wl_surface().id();
+        log::debug!(target: WAYLAND_TARGET, "{id} closed by the compositor");
+
+        self.emit_surface_event(&id, SurfaceEvent::Closed);
+    }
+
+    fn configure(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        layer: &smithay_client_toolkit::shell::wlr_layer::LayerSurface,
+        configure: smithay_client_toolkit::shell::wlr_layer::LayerSurfaceConfigure,
+        serial: u32,
+    ) {
+        let id = layer.wl_surface().id();
+        self.configure_surface(conn, qh, &id, configure.new_size, serial);
+    }
+}
+
+impl AvyClient {
+    fn configure_surface(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        id: &ObjectId,
+        size: (u32, u32),
+        serial: u32,
+    ) {
+        log::debug!(target: WAYLAND_TARGET, "Configure {serial} of {id}: {size:?}");
+
+        // The compositor may still have sent it for a surface destroyed since.
+        let Some(surface) = self.surfaces.get_mut(id) else {
+            log::warn!(target: WAYLAND_TARGET, "Ignoring configure {serial} of unknown surface {id}");
+            return;
+        };
+        let surface = surface.as_mut();
+
+        if let Some(state) = self.surface_shared.get(id) {
+            state.awaiting_configure.store(false, Ordering::Release);
+            state.dirty.mark();
+        }
+
+        // The viewport is updated once a buffer of the new size is
+        // presented, see [ViewportSync]. Resizing only when the size
+        // actually changed spares the backend recreating its swapchain.
+        let configured = surface.configured_size(size);
+        if surface.size_ref().logical_size() != configured {
+            surface.size_mut().resize(configured);
+        }
+
+        // With manual acks, the ack waits for that buffer too (see
+        // [ConfigureAck::ack_if_fits]), so the compositor never pairs
+        // this configure with a frame drawn at the old size.
+        let configure = PendingConfigure { serial, size };
+        if let Some(configure_ack) = surface.configure_ack() {
+            configure_ack.stash(configure);
+        }
+
+        surface.configure(conn, qh, configure);
+
+        let size = surface.size_ref().logical_size();
+        self.emit_surface_event(id, SurfaceEvent::Configured { size });
+        self.update_named_surface(id);
+        self.update_edge_layout(id);
+    }
+}
+
+impl AvyClient {
+    ///
+    /// Start a new, empty [RenderGroup].
+    ///
+    pub fn create_render_group(&mut self) -> RenderGroupMut {
+        let id = RenderGroupId(self.next_render_group);
+        self.next_render_group += 1;
+
+        self.render_groups
+            .insert(id, RenderGroup::new(self.clock.now()));
+        RenderGroupMut(self, id)
+    }
+
+    pub fn render_group(&mut self, id: RenderGroupId) -> Option<RenderGroupMut> {
+        self.render_groups
+            .contains_key(&id)
+            .then(|| RenderGroupMut(self, id))
+    }
+
+    ///
+    /// Stop drawing a group's members with it: they're left to their handles.
+    ///
+    pub fn remove_render_group(&mut self, id: RenderGroupId) {
+        if let Some(group) = self.render_groups.remove(&id) {
+            for member in group.members {
+                member.state.dirty.set_on_mark(None);
+                self.surface_groups.remove(&member.id);
+            }
+
+            for pacer in group.pacers.into_values() {
+                if let Some(token) = pacer.fallback_timer {
+                    self.cancel_timer(token);
+                }
+            }
+        }
+    }
+
+    ///
+    /// Take the surface `id` out of its render group, if it's in one.
+    ///
+    pub fn leave_render_group(&mut self, id: &ObjectId) {
+        let Some(group) = self.surface_groups.remove(id) else {
+            return;
+        };
+
+        if let Some(render_group) = self.render_groups.get_mut(&group) {
+            render_group.members.retain(|member| {
+                let keep = &member.id != id;
+                if !keep {
+                    member.state.dirty.set_on_mark(None);
+                }
+                keep
+            });
+        }
+
+        self.schedule_render_group(group);
+    }
+
+    fn reschedule_render_group(&mut self, id: &ObjectId) {
+        if let Some(group) = self.surface_groups.get(id).copied() {
+            self.schedule_render_group(group);
+        }
+    }
+
+    ///
+    /// Make sure a frame callback is pending for every output with a dirty
+    /// member of the group on it, and draw the dirty members which aren't on
+    /// any output (e.g. before they're first mapped), as nothing would pace them.
+    ///
+    /// Members of outputs left without a callback wake the group up again
+    /// when they're marked dirty, see [RenderGroupMut::join].
+    ///
+    fn schedule_render_group(&mut self, id: RenderGroupId) {
+        let Some(group) = self.render_groups.get_mut(&id) else {
+            return;
+        };
+
+        if group.is_paused() {
+            return;
+        }
+
+        let outputs = &self.surface_outputs;
+
+        // The first member on each output paces it.
+        let mut pacers = HashMap::new();
+        for member in &group.members {
+            if let Some(output) = drawn_on(outputs, &member.id) {
+                pacers.entry(output).or_insert(member);
+            }
+        }
+
+        let mut stale_timers = Vec::new();
+        group.pacers.retain(|output, pacer| {
+            let keep = pacers.contains_key(output);
+            if !keep {
+                stale_timers.extend(pacer.fallback_timer.take());
+            }
+            keep
+        });
+
+        for (output, member) in pacers {
+            let pacer = group.pacers.entry(output).or_insert_with(|| GroupPacer {
+                surface: member.id.clone(),
+                pending: false,
+                requested_at: None,
+                last_tick: None,
+                fallback_timer: None,
+            });
+
+            // The old pacer's callback is ignored when it fires.
+            if pacer.surface != member.id {
+                pacer.surface = member.id.clone();
+                pacer.pending = false;
+            }
+
+            let on_output: Vec<&GroupMember> = group
+                .members
+                .iter()
+                .filter(|other| drawn_on(outputs, &other.id).as_ref() == Some(&output))
+                .collect();
+
+            // Give up on ticking before checking for dirty members, so
+            // that one marked meanwhile wakes the group up instead.
+            if !pacer.pending {
+                for member in &on_output {
+                    member.ticking.store(false, Ordering::SeqCst);
+                }
+            }
+
+            let wanted = on_output.iter().any(|member| member.state.dirty.is_dirty());
+            if !pacer.pending && wanted {
+                let wl_surface = &member.wl_surface;
+                let callback = wl_surface.frame(&self.queue_handle, wl_surface.clone());
+                debug::objects::created(&callback.id(), Some(&wl_surface.id()));
+                wl_surface.commit();
+                pacer.pending = true;
+                pacer.requested_at = Some(self.clock.now());
+            }
+
+            if pacer.pending {
+                for member in &on_output {
+                    member.ticking.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+
+        let now = self.clock.now();
+        let tick = GroupTick {
+            time: now,
+            elapsed: group.elapsed(now),
+        };
+
+        for member in &mut group.members {
+            if drawn_on(outputs, &member.id).is_none() && member.state.dirty.is_dirty() {
+                // Marking itself dirty from `draw` mustn't wake the group straight back up.
+                member.ticking.store(true, Ordering::SeqCst);
+                (member.draw)(tick);
+                member.ticking.store(false, Ordering::SeqCst);
+            }
+        }
+
+        for token in stale_timers {
+            self.cancel_timer(token);
+        }
+
+        self.arm_fallback_timers(id);
+    }
+
+    ///
+    /// Watch for frame callbacks which don't come on each of the group's
+    /// outputs, unless already watching, see [FallbackCadence].
+    ///
+    fn arm_fallback_timers(&mut self, id: RenderGroupId) {
+        let Some(group) = self.render_groups.get(&id) else {
+            return;
+        };
+
+        let unwatched: Vec<(ObjectId, Duration)> = group
+            .pacers
+            .iter()
+            .filter(|(_, pacer)| pacer.fallback_timer.is_none())
+            .filter_map(|(output, _)| {
+                let interval = group.fallback_interval(output, &self.surface_outputs)?;
+                Some((output.clone(), interval))
+            })
+            .collect();
+
+        for (output, interval) in unwatched {
+            let watched = output.clone();
+            // Without an event loop, there's nothing to fall back on.
+            let Ok(token) = self.add_timer(interval, move |app| app.fallback_tick(id, &watched))
+            else {
+                return;
+            };
+
+            if let Some(pacer) = self
+                .render_groups
+                .get_mut(&id)
+                .and_then(|group| group.pacers.get_mut(&output))
+            {
+                pacer.fallback_timer.replace(token);
+            }
+        }
+    }
+
+    ///
+    /// The group's frame callback on `output` may be overdue: if it's been
+    /// waited on for longer than the members' [FallbackCadence], draw
+    /// them as if it came, and keep waiting on it.
+    ///
+    fn fallback_tick(&mut self, id: RenderGroupId, output: &ObjectId) -> TimerAction {
+        let outputs = &self.surface_outputs;
+        let Some(group) = self.render_groups.get_mut(&id) else {
+            return TimerAction::Drop;
+        };
+
+        let interval = group.fallback_interval(output, outputs);
+        let now = self.clock.now();
+        let Some(pacer) = group.pacers.get_mut(output) else {
+            return TimerAction::Drop;
+        };
+
+        let Some(interval) = interval else {
+            pacer.fallback_timer = None;
+            return TimerAction::Drop;
+        };
+
+        let waited = pacer
+            .requested_at
+            .filter(|_| pacer.pending)
+            .map(|requested_at| now.saturating_duration_since(requested_at));
+
+        match waited {
+            Some(waited) if waited >= interval => {}
+            // Callbacks are coming: check again once this one's overdue.
+            Some(waited) => return TimerAction::Repeat(interval - waited),
+            None => return TimerAction::Repeat(interval),
+        }
+
+        if group.is_paused() {
+            return TimerAction::Repeat(interval);
+        }
+
+        let now = self.clock.now();
+        let tick = GroupTick {
+            time: now,
+            elapsed: group.elapsed(now),
+        };
 
-    fn new_output(
-        &mut self,
-        conn: &Connection,
-        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
-        output: smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
-    ) {
-    }
+        for member in &mut group.members {
+            if drawn_on(outputs, &member.id).as_ref() == Some(output)
+                && member.state.dirty.is_dirty()
+            {
+                if let Some(metrics) = &*member.state.metrics.lock().unwrap() {
+                    metrics.record_fallback_frame();
+                }
 
-    fn update_output(
-        &mut self,
-        conn: &Connection,
-        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
-        output: smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
-    ) {
-    }
+                (member.draw)(tick);
+            }
+        }
 
-    fn output_destroyed(
-        &mut self,
-        conn: &Connection,
-        qh: &smithay_client_toolkit::reexports::client::QueueHandle<Self>,
-        output: smithay_client_toolkit::reexports::client::protocol::wl_output::WlOutput,
-    ) {
+        TimerAction::Repeat(interval)
     }
-}
 
-delegate_compositor!(AvyClient);
-delegate_output!(AvyClient);
-delegate_registry!(AvyClient);
+    ///
+    /// The frame callback requested on `surface` for its group fired:
+    /// draw the group's dirty members on the pacer's output.
+    ///
+    fn tick_render_group(&mut self, id: RenderGroupId, surface: &ObjectId) {
+        let Some(group) = self.render_groups.get_mut(&id) else {
+            return;
+        };
 
-delegate_layer!(AvyClient);
+        let Some((output, pacer)) = group
+            .pacers
+            .iter_mut()
+            .find(|(_, pacer)| &pacer.surface == surface && pacer.pending)
+        else {
+            // Someone else's frame callback, e.g. a margin animation.
+            return;
+        };
 
-impl LayerShellHandler for AvyClient {
-    fn closed(
-        &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        layer: &smithay_client_toolkit::shell::wlr_layer::LayerSurface,
-    ) {
+        pacer.pending = false;
+        let output = output.clone();
+        let last_tick = pacer.last_tick;
+
+        if group.is_paused() {
+            return;
+        }
+
+        self.draw_render_group_tick(id, surface, &output, last_tick);
+
+        // After drawing, so that the output only ticks again if a member
+        // is still dirty, e.g. as it's animating and marked itself dirty
+        // again from its `draw`, or was throttled.
+        self.schedule_render_group(id);
     }
 
-    fn configure(
+    ///
+    /// Draw the group's dirty members on `output`, unless that's too soon after
+    /// `last_tick`, for the frame callback requested on `surface`.
+    ///
+    fn draw_render_group_tick(
         &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        layer: &smithay_client_toolkit::shell::wlr_layer::LayerSurface,
-        configure: smithay_client_toolkit::shell::wlr_layer::LayerSurfaceConfigure,
-        serial: u32,
+        id: RenderGroupId,
+        surface: &ObjectId,
+        output: &ObjectId,
+        last_tick: Option<Instant>,
     ) {
-        let surface = self
-            .surfaces
-            .get_mut(&layer.wl_surface().id())
-            .expect("Surface not registered!")
-            .as_mut();
+        let Some(group) = self.render_groups.get_mut(&id) else {
+            return;
+        };
 
-        surface.size_mut().resize(configure.new_size);
+        let refresh_rate = group
+            .members
+            .iter()
+            .find(|member| &member.id == surface)
+            .and_then(|member| member.state.refresh_rate());
 
-        // Update viewport.
-        let size = surface.size_ref().clone();
+        // Members drawn in step go at the pace of the slowest one.
+        let limit = group
+            .members
+            .iter()
+            .filter(|member| drawn_on(&self.surface_outputs, &member.id).as_ref() == Some(output))
+            .filter_map(|member| member.state.target_fps())
+            .chain(self.idle.throttle())
+            .reduce(f64::min);
 
-        let (width, height) = size.logical_size();
-        surface.viewport().set_destination(width as _, height as _);
+        let now = self.clock.now();
+        if group.throttled(last_tick, now, refresh_rate, limit) {
+            return;
+        }
 
-        let (width, height) = size.physical_size();
-        surface.viewport().set_source(0.0, 0.0, width, height);
+        if let Some(pacer) = group.pacers.get_mut(output) {
+            pacer.last_tick = Some(now);
+        }
+
+        // One time for every member, so that they animate in step.
+        let tick = GroupTick {
+            time: now,
+            elapsed: group.elapsed(now),
+        };
+
+        let due: Vec<_> = (0..group.members.len())
+            .filter(|&i| {
+                let member = &group.members[i];
+                drawn_on(&self.surface_outputs, &member.id).as_ref() == Some(output)
+                    && member.state.dirty.is_dirty()
+            })
+            .collect();
+
+        for (drawn, &i) in due.iter().enumerate() {
+            // Input waits whilst the group draws, so leave the rest (which
+            // are still dirty) for the next tick, and draw them first then.
+            if drawn > 0 && over_input_budget(now, self.clock.now()) {
+                log::trace!(
+                    "Render group {id:?} is over its input budget, putting off {} members.",
+                    due.len() - drawn
+                );
+                group.members.rotate_left(i);
+                break;
+            }
+
+            (group.members[i].draw)(tick);
+        }
     }
 }
 
+///
+/// Whether drawing which started at `started` has kept input waiting for too
+/// long by `now`, see [INPUT_BUDGET].
+///
+pub(crate) fn over_input_budget(started: Instant, now: Instant) -> bool {
+    now.saturating_duration_since(started) >= INPUT_BUDGET
+}
+
+///
+/// The output a surface is drawn in step with: the first one it's on.
+///
+fn drawn_on(outputs: &HashMap<ObjectId, Vec<WlOutput>>, id: &ObjectId) -> Option<ObjectId> {
+    outputs.get(id)?.first().map(Proxy::id)
+}
+
 delegate_fractional_scale!(AvyClient);
 
 impl FractionalScaleHandler for AvyClient {
@@ -331,23 +3959,54 @@ impl FractionalScaleHandler for AvyClient {
         surface: &WlSurface,
         factor: ScaleFactor,
     ) {
-        let surface = self.surfaces.get_mut(&surface.id()).unwrap().as_mut();
+        let id = surface.id();
+        log::debug!(target: WAYLAND_TARGET, "Scale of {id} changed to {factor:?}");
+
+        // The compositor may still have sent it for a surface destroyed since.
+        let Some(surface) = self.surfaces.get_mut(&id) else {
+            log::debug!(target: WAYLAND_TARGET, "Ignoring the scale of unknown surface {id}");
+            return;
+        };
 
-        surface.size_mut().rescale(factor);
+        if let Err(err) = surface.as_mut().size_mut().rescale(factor) {
+            log::warn!(target: WAYLAND_TARGET, "Ignoring the scale of {id}: {err}");
+            return;
+        }
 
-        // Update viewport.
-        let size = surface.size_ref().clone();
+        // The viewport is updated once a buffer at the new scale is
+        // presented, see [ViewportSync].
+        if let Some(state) = self.surface_shared.get(&id) {
+            state.dirty.mark();
+        }
 
-        let (width, height) = size.logical_size();
-        surface.viewport().set_destination(width as _, height as _);
+        self.emit_surface_event(&id, SurfaceEvent::ScaleChanged { factor });
+    }
 
-        let (width, height) = size.physical_size();
-        surface.viewport().set_source(0.0, 0.0, width, height);
+    fn now(&self) -> Instant {
+        self.clock.now()
     }
 }
 
 delegate_viewporter!(AvyClient);
 
+delegate_hyprland_global_shortcuts!(AvyClient);
+
+delegate_idle_notify!(AvyClient);
+
+delegate_primary_selection!(AvyClient);
+
+#[cfg(feature = "workspaces")]
+crate::delegate_ext_workspace!(AvyClient);
+
+#[cfg(feature = "virtual-keyboard")]
+delegate_virtual_keyboard!(AvyClient);
+
+#[cfg(feature = "hyprland-surface")]
+delegate_hyprland_surface!(AvyClient);
+
+#[cfg(feature = "input-latency")]
+delegate_presentation!(AvyClient);
+
 impl SeatHandler for AvyClient {
     fn seat_state(&mut self) -> &mut smithay_client_toolkit::seat::SeatState {
         &mut self.seat_state
@@ -359,6 +4018,8 @@ impl SeatHandler for AvyClient {
         qh: &QueueHandle<Self>,
         seat: smithay_client_toolkit::reexports::client::protocol::wl_seat::WlSeat,
     ) {
+        self.add_idle_seat(&seat);
+        self.add_selection_seat(&seat);
     }
 
     fn new_capability(
@@ -368,7 +4029,17 @@ impl SeatHandler for AvyClient {
         seat: smithay_client_toolkit::reexports::client::protocol::wl_seat::WlSeat,
         capability: Capability,
     ) {
-        if capability == Capability::Pointer && self.pointer.is_none() {
+        let before = self.capabilities();
+
+        let device = match InputDevice::try_from(capability) {
+            Ok(device) => device,
+            Err(err) => {
+                log::debug!("{err}");
+                return;
+            }
+        };
+
+        if device == InputDevice::Pointer && self.pointer.is_none() {
             let pointer = seat.get_pointer(qh, PointerData::new(seat.clone()));
             if let Ok(rel_pointer) = self
                 .relative_pointer_state
@@ -376,17 +4047,22 @@ impl SeatHandler for AvyClient {
             {
                 self.relative_pointer.replace(rel_pointer);
             }
+
+            self.cursor_pointer_added(&pointer, qh);
+            self.pointer.replace(pointer);
         }
 
-        if capability == Capability::Keyboard && self.keyboard.is_none() {
+        if device == InputDevice::Keyboard && self.keyboard.is_none() {
             self.keyboard
                 .replace(seat.get_keyboard(qh, KeyboardData::new(seat.clone())));
         }
 
-        if capability == Capability::Touch {
+        if device == InputDevice::Touch && self.touch.is_none() {
             self.touch
                 .replace(seat.get_touch(qh, TouchData::new(seat.clone())));
         }
+
+        self.notify_capabilities(conn, qh, before);
     }
 
     fn remove_capability(
@@ -396,18 +4072,30 @@ impl SeatHandler for AvyClient {
         seat: smithay_client_toolkit::reexports::client::protocol::wl_seat::WlSeat,
         capability: Capability,
     ) {
-        if capability == Capability::Keyboard {
-            self.keyboard.take();
-        }
+        let before = self.capabilities();
 
-        if capability == Capability::Pointer {
-            self.pointer.take();
-            self.relative_pointer.take();
+        match InputDevice::try_from(capability) {
+            Ok(InputDevice::Keyboard) => {
+                release_device(&mut self.keyboard);
+                self.lose_keyboard_focus();
+            }
+            Ok(InputDevice::Pointer) => {
+                release_device(&mut self.relative_pointer);
+                release_device(&mut self.pointer);
+                self.cursor_left();
+            }
+            Ok(InputDevice::Touch) => {
+                release_device(&mut self.touch);
+                self.active_touches.clear();
+            }
+            Ok(InputDevice::RelativePointer) => {}
+            Err(err) => {
+                log::debug!("{err}");
+                return;
+            }
         }
 
-        if capability == Capability::Touch {
-            self.touch.take();
-        }
+        self.notify_capabilities(conn, qh, before);
     }
 
     fn remove_seat(
@@ -416,9 +4104,19 @@ impl SeatHandler for AvyClient {
         qh: &QueueHandle<Self>,
         seat: smithay_client_toolkit::reexports::client::protocol::wl_seat::WlSeat,
     ) {
-        self.keyboard.take();
-        self.pointer.take();
-        self.relative_pointer.take();
+        let before = self.capabilities();
+
+        release_device(&mut self.keyboard);
+        self.lose_keyboard_focus();
+        release_device(&mut self.relative_pointer);
+        release_device(&mut self.pointer);
+        release_device(&mut self.touch);
+        self.active_touches.clear();
+        self.remove_idle_seat(&seat);
+        self.remove_selection_seat(&seat);
+        self.remove_serial_seat(&seat);
+
+        self.notify_capabilities(conn, qh, before);
     }
 }
 
@@ -432,12 +4130,62 @@ impl PointerHandler for AvyClient {
         pointer: &smithay_client_toolkit::reexports::client::protocol::wl_pointer::WlPointer,
         events: &[smithay_client_toolkit::seat::pointer::PointerEvent],
     ) {
-        // TODO: Check the performance of this section.
-        for event in events.as_chunks::<1>().0 {
-            if let Some(surface) = self.surfaces.get_mut(&event[0].surface.id()) {
-                surface.pointer_frame(conn, qh, pointer, event);
+        // Whatever happens to the surfaces, the cursor over them is ours to set.
+        for event in events {
+            if let PointerEventKind::Motion { time }
+            | PointerEventKind::Press { time, .. }
+            | PointerEventKind::Release { time, .. }
+            | PointerEventKind::Axis { time, .. } = event.kind
+            {
+                self.record_input(time);
+            }
+
+            match event.kind {
+                PointerEventKind::Enter { serial } => self.cursor_entered(pointer, serial),
+                PointerEventKind::Leave { .. } => self.cursor_left(),
+                PointerEventKind::Press { serial, .. } => {
+                    if let Some(data) = pointer.data::<PointerData>() {
+                        self.record_serial(data.seat(), SerialKind::PointerButton, serial);
+                    }
+
+                    // Before the surface gets to respond.
+                    #[cfg(feature = "input-latency")]
+                    if let Some(state) = self.surface_shared.get(&event.surface.id()) {
+                        state.input_latency.press();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Consecutive events for the same surface go to it together,
+        // rather than one at a time.
+        for run in events.chunk_by(|a, b| a.surface == b.surface) {
+            let id = run[0].surface.id();
+
+            // The rest of the frame is dropped for surfaces
+            // which asked to be destroyed part-way through.
+            if self.is_destroying(&id) {
+                continue;
+            }
+
+            let coalescing = self
+                .surface_shared
+                .get(&id)
+                .map(|state| *state.motion_coalescing.lock().unwrap())
+                .unwrap_or_default();
+
+            if let Some(surface) = self.surfaces.get_mut(&id) {
+                match coalescing {
+                    MotionCoalescing::Off => surface.pointer_frame(conn, qh, pointer, run),
+                    MotionCoalescing::PerFrame => {
+                        surface.pointer_frame(conn, qh, pointer, &coalesce(run))
+                    }
+                }
             }
         }
+
+        self.destroy_requested_surfaces();
     }
 }
 
@@ -470,11 +4218,55 @@ impl KeyboardHandler for AvyClient {
         raw: &[u32],
         keysyms: &[smithay_client_toolkit::seat::keyboard::Keysym],
     ) {
-        self.keyboard_focus.replace(surface.id());
-        self.surfaces
-            .get_mut(&surface.id())
-            .unwrap()
-            .enter(conn, qh, keyboard, surface, serial, raw, keysyms)
+        let id = surface.id();
+        if self.is_destroying(&id) {
+            return;
+        }
+
+        // Unregistered surfaces only take focus once they're registered.
+        if self.surfaces.contains_key(&id) {
+            self.keyboard_focus.replace(id.clone());
+            self.pending_keyboard_focus.take();
+        } else {
+            self.lose_keyboard_focus();
+            self.pending_keyboard_focus.replace(id.clone());
+        }
+        if let Some(state) = self.surface_shared.get(&id) {
+            state.keyboard_focused.store(true, Ordering::Release);
+        }
+
+        self.pressed_keys = raw
+            .iter()
+            .zip(keysyms)
+            .map(|(&raw_code, &keysym)| {
+                let event = KeyEvent {
+                    time: 0,
+                    raw_code,
+                    keysym,
+                    utf8: None,
+                };
+
+                (raw_code, event)
+            })
+            .collect();
+
+        match self.surfaces.get_mut(&id) {
+            Some(avy_surface) => {
+                avy_surface.enter(conn, qh, keyboard, surface, serial, raw, keysyms)
+            }
+            None => self.defer_keyboard_event(
+                id,
+                DeferredKeyboardEvent::Enter {
+                    keyboard: keyboard.clone(),
+                    surface: surface.clone(),
+                    serial,
+                    raw: raw.to_vec(),
+                    keysyms: keysyms.to_vec(),
+                },
+            ),
+        }
+
+        self.destroy_requested_surfaces();
     }
 
     fn leave(
@@ -486,12 +4278,31 @@ impl KeyboardHandler for AvyClient {
         serial: u32,
     ) {
         let id = surface.id();
-        self.surfaces
-            .get_mut(&id)
-            .unwrap()
-            .leave(conn, qh, keyboard, surface, serial);
 
-        self.keyboard_focus.take();
+        if let Some(data) = keyboard.data::<KeyboardData<AvyClient>>() {
+            self.record_focus_lost(data.seat(), serial);
+        }
+
+        if self.keyboard_focus.as_ref() == Some(&id) {
+            self.lose_keyboard_focus();
+        }
+        if self.pending_keyboard_focus.as_ref() == Some(&id) {
+            self.pending_keyboard_focus.take();
+        }
+
+        self.overlay_focus_lost(&id);
+
+        match self.surfaces.get_mut(&id) {
+            Some(avy_surface) => avy_surface.leave(conn, qh, keyboard, surface, serial),
+            None => {
+                // Either already destroyed, or never registered:
+                // anything we were holding for it is now moot.
+                log::debug!("Dropping keyboard leave for unknown surface {id}");
+                self.deferred_keyboard_events.remove(&id);
+            }
+        }
+
+        self.destroy_requested_surfaces();
     }
 
     fn press_key(
@@ -502,12 +4313,38 @@ impl KeyboardHandler for AvyClient {
         serial: u32,
         event: smithay_client_toolkit::seat::keyboard::KeyEvent,
     ) {
-        if let Some(focus) = &self.keyboard_focus {
-            self.surfaces
-                .get_mut(focus)
-                .unwrap()
-                .press_key(conn, qh, keyboard, serial, event)
+        self.record_input(event.time);
+
+        if let Some(data) = keyboard.data::<KeyboardData<AvyClient>>() {
+            self.record_serial(data.seat(), SerialKind::KeyPress, serial);
+        }
+
+        let Some(focus) = self
+            .keyboard_target()
+            .filter(|focus| !self.is_destroying(focus))
+        else {
+            return;
+        };
+
+        if self.overlay_key_pressed(&focus, &event) {
+            return;
+        }
+
+        self.pressed_keys.insert(event.raw_code, event.clone());
+
+        match self.surfaces.get_mut(&focus) {
+            Some(surface) => surface.press_key(conn, qh, keyboard, serial, event),
+            None => self.defer_keyboard_event(
+                focus,
+                DeferredKeyboardEvent::PressKey {
+                    keyboard: keyboard.clone(),
+                    serial,
+                    event,
+                },
+            ),
         }
+
+        self.destroy_requested_surfaces();
     }
 
     fn release_key(
@@ -518,12 +4355,33 @@ impl KeyboardHandler for AvyClient {
         serial: u32,
         event: smithay_client_toolkit::seat::keyboard::KeyEvent,
     ) {
-        if let Some(focus) = &self.keyboard_focus {
-            self.surfaces
-                .get_mut(focus)
-                .unwrap()
-                .release_key(conn, qh, keyboard, serial, event)
+        self.record_input(event.time);
+
+        let Some(focus) = self
+            .keyboard_target()
+            .filter(|focus| !self.is_destroying(focus))
+        else {
+            return;
+        };
+
+        // Already released when the keymap changed.
+        if self.pressed_keys.remove(&event.raw_code).is_none() {
+            return;
         }
+
+        match self.surfaces.get_mut(&focus) {
+            Some(surface) => surface.release_key(conn, qh, keyboard, serial, event),
+            None => self.defer_keyboard_event(
+                focus,
+                DeferredKeyboardEvent::ReleaseKey {
+                    keyboard: keyboard.clone(),
+                    serial,
+                    event,
+                },
+            ),
+        }
+
+        self.destroy_requested_surfaces();
     }
 
     fn update_modifiers(
@@ -535,12 +4393,59 @@ impl KeyboardHandler for AvyClient {
         modifiers: smithay_client_toolkit::seat::keyboard::Modifiers,
         layout: u32,
     ) {
-        if let Some(focus) = &self.keyboard_focus {
-            self.surfaces
-                .get_mut(focus)
-                .unwrap()
-                .update_modifiers(conn, qh, keyboard, serial, modifiers, layout)
+        let Some(focus) = self
+            .keyboard_target()
+            .filter(|focus| !self.is_destroying(focus))
+        else {
+            return;
+        };
+
+        match self.surfaces.get_mut(&focus) {
+            Some(surface) => {
+                surface.update_modifiers(conn, qh, keyboard, serial, modifiers, layout)
+            }
+            None => self.defer_keyboard_event(
+                focus,
+                DeferredKeyboardEvent::UpdateModifiers {
+                    keyboard: keyboard.clone(),
+                    serial,
+                    modifiers,
+                    layout,
+                },
+            ),
+        }
+
+        self.destroy_requested_surfaces();
+    }
+
+    fn update_keymap(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        keymap: Keymap<'_>,
+    ) {
+        let keymap = KeymapInfo::new(&keymap);
+        log::debug!("Keymap changed, layouts: {:?}", keymap.layouts());
+
+        // Release held keys as they were translated under the old keymap,
+        // rather than leaving them stuck down. Their real releases are dropped.
+        let pressed = std::mem::take(&mut self.pressed_keys);
+        if let Some(surface) = self
+            .keyboard_target()
+            .and_then(|focus| self.surfaces.get_mut(&focus))
+        {
+            for (_, event) in pressed {
+                surface.release_key(conn, qh, keyboard, 0, event);
+            }
+        }
+
+        for surface in self.surfaces.values_mut() {
+            surface.keymap_changed(conn, qh, keyboard, &keymap);
         }
+
+        self.keymap.replace(keymap);
+        self.destroy_requested_surfaces();
     }
 }
 delegate_keyboard!(AvyClient);
@@ -557,13 +4462,25 @@ impl TouchHandler for AvyClient {
         id: i32,
         position: (f64, f64),
     ) {
+        self.record_input(time);
+
+        if let Some(data) = touch.data::<TouchData>() {
+            self.record_serial(data.seat(), SerialKind::TouchDown, serial);
+        }
+
         let surface_id = surface.id();
-        self.surfaces
-            .get_mut(&surface_id)
-            .unwrap()
-            .down(conn, qh, touch, serial, time, surface, id, position);
+        if self.is_destroying(&surface_id) {
+            return;
+        }
 
+        let Some(avy_surface) = self.surfaces.get_mut(&surface_id) else {
+            return;
+        };
+
+        avy_surface.down(conn, qh, touch, serial, time, surface, id, position);
         self.active_touches.insert(id, surface_id);
+
+        self.destroy_requested_surfaces();
     }
 
     fn up(
@@ -575,11 +4492,18 @@ impl TouchHandler for AvyClient {
         time: u32,
         id: i32,
     ) {
-        let surface = self.active_touches.remove(&id).unwrap();
-        self.surfaces
-            .get_mut(&surface)
-            .unwrap()
-            .up(conn, qh, touch, serial, time, id);
+        self.record_input(time);
+
+        // Unknown if its surface was destroyed since.
+        let Some(surface) = self.active_touches.remove(&id) else {
+            return;
+        };
+
+        if let Some(surface) = self.touch_target(&surface) {
+            surface.up(conn, qh, touch, serial, time, id);
+        }
+
+        self.destroy_requested_surfaces();
     }
 
     fn motion(
@@ -591,10 +4515,13 @@ impl TouchHandler for AvyClient {
         id: i32,
         position: (f64, f64),
     ) {
-        self.surfaces
-            .get_mut(self.active_touches.get(&id).unwrap())
-            .unwrap()
-            .motion(conn, qh, touch, time, id, position)
+        self.record_input(time);
+
+        if let Some(surface) = self.touch_surface(id) {
+            surface.motion(conn, qh, touch, time, id, position);
+        }
+
+        self.destroy_requested_surfaces();
     }
 
     fn shape(
@@ -606,10 +4533,11 @@ impl TouchHandler for AvyClient {
         major: f64,
         minor: f64,
     ) {
-        self.surfaces
-            .get_mut(self.active_touches.get(&id).unwrap())
-            .unwrap()
-            .shape(conn, qh, touch, id, major, minor)
+        if let Some(surface) = self.touch_surface(id) {
+            surface.shape(conn, qh, touch, id, major, minor);
+        }
+
+        self.destroy_requested_surfaces();
     }
 
     fn orientation(
@@ -620,10 +4548,11 @@ impl TouchHandler for AvyClient {
         id: i32,
         orientation: f64,
     ) {
-        self.surfaces
-            .get_mut(self.active_touches.get(&id).unwrap())
-            .unwrap()
-            .orientation(conn, qh, touch, id, orientation)
+        if let Some(surface) = self.touch_surface(id) {
+            surface.orientation(conn, qh, touch, id, orientation);
+        }
+
+        self.destroy_requested_surfaces();
     }
 
     fn cancel(
@@ -634,14 +4563,291 @@ impl TouchHandler for AvyClient {
     ) {
         // BUG: This may cause unintended effects, but this
         //      can be fixed later.
-        let surface = self.active_touches.values().next().unwrap();
-        self.surfaces
-            .get_mut(surface)
-            .unwrap()
-            .cancel(conn, qh, touch);
+        let surface = self.active_touches.values().next().cloned();
+        if let Some(surface) = surface.and_then(|surface| self.touch_target(&surface)) {
+            surface.cancel(conn, qh, touch);
+        }
 
         self.active_touches.clear();
+        self.destroy_requested_surfaces();
+    }
+}
+
+impl AvyClient {
+    ///
+    /// The surface touch point `id` went down on, unless it's (being) destroyed.
+    ///
+    fn touch_surface(&mut self, id: i32) -> Option<&mut Box<dyn AvySurface>> {
+        let surface = self.active_touches.get(&id)?.clone();
+        self.touch_target(&surface)
+    }
+
+    fn touch_target(&mut self, surface: &ObjectId) -> Option<&mut Box<dyn AvySurface>> {
+        if self.is_destroying(surface) {
+            return None;
+        }
+
+        self.surfaces.get_mut(surface)
     }
 }
 
 delegate_touch!(AvyClient);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_exclusive_surface_gets_every_key_through_focus_churn() {
+        // The compositor focusing the other surface, nothing, the exclusive
+        // one, and one which isn't registered yet.
+        let churn = [
+            (Some(2), None),
+            (None, None),
+            (Some(1), None),
+            (None, Some(3)),
+            (Some(2), Some(3)),
+        ];
+
+        for (focus, pending) in churn {
+            assert_eq!(
+                key_target(Some(&1), focus.as_ref(), pending.as_ref()),
+                Some(1)
+            );
+        }
+    }
+
+    #[test]
+    fn keys_follow_focus_without_an_exclusive_surface() {
+        assert_eq!(key_target(None, Some(&2), Some(&3)), Some(2));
+        assert_eq!(key_target(None, None, Some(&3)), Some(3));
+        assert_eq!(key_target::<u32>(None, None, None), None);
+    }
+
+    const SMALL: ViewportSizes = ((100, 50), (200.0, 100.0));
+    const LARGE: ViewportSizes = ((200, 100), (400.0, 200.0));
+
+    #[test]
+    fn the_viewport_is_only_applied_once_presented() {
+        let mut viewport = ViewportSync::default();
+
+        assert!(viewport.stage(SMALL));
+        viewport.presented(true);
+        assert!(!viewport.stage(SMALL));
+        viewport.presented(true);
+
+        assert!(viewport.stage(LARGE));
+        assert_eq!(viewport.applied, Some(SMALL));
+    }
+
+    #[test]
+    fn a_failed_present_sets_the_viewport_up_again() {
+        let mut viewport = ViewportSync::default();
+        assert!(viewport.stage(SMALL));
+        viewport.presented(true);
+
+        // Out of date: the requests sent for it land with the next commit.
+        assert!(viewport.stage(LARGE));
+        viewport.presented(false);
+
+        assert!(viewport.stage(SMALL));
+        viewport.presented(true);
+        assert!(!viewport.stage(SMALL));
+    }
+
+    #[test]
+    fn frames_which_never_reached_present_change_nothing() {
+        let mut viewport = ViewportSync::default();
+        assert!(viewport.stage(SMALL));
+        viewport.presented(true);
+
+        // No buffer, or the callback panicked.
+        viewport.presented(false);
+        assert!(!viewport.stage(SMALL));
+    }
+
+    ///
+    /// Configures arriving faster than frames are drawn: every other one
+    /// lands while a frame is being drawn at the size before it.
+    ///
+    #[test]
+    fn rapid_resizes_never_stretch_a_buffer() {
+        let acked = Arc::new(Mutex::new(Vec::new()));
+        let configure_ack = ConfigureAck::new({
+            let acked = acked.clone();
+            move |serial| acked.lock().unwrap().push(serial)
+        });
+
+        let configure = |size: &mut Size, serial: u32| {
+            let logical_size = (100 + 40 * serial, 50);
+            size.resize(logical_size);
+            configure_ack.stash(PendingConfigure {
+                serial,
+                size: logical_size,
+            });
+        };
+
+        let mut size = Size::new((100, 50));
+        size.rescale(ScaleFactor::from_raw(180)).unwrap();
+        let mut viewport = ViewportSync::default();
+        // What the compositor scales the attached buffer to.
+        let mut destination = None;
+
+        let mut present = |frame: SizeSnapshot| {
+            let sizes = (frame.logical_size(), frame.physical_size());
+            if viewport.stage(sizes) {
+                destination = Some(sizes);
+            }
+
+            let acked = configure_ack.ack_if_fits(frame.logical_size());
+            viewport.presented(true);
+
+            assert_eq!(destination, Some(sizes), "A buffer was stretched");
+            if let Some(configure) = acked {
+                assert_eq!(configure.size, frame.logical_size());
+            }
+        };
+
+        for serial in 1..=8 {
+            if serial % 2 == 0 {
+                let frame = size.snapshot();
+                configure(&mut size, serial);
+                present(frame);
+            } else {
+                configure(&mut size, serial);
+                present(size.snapshot());
+            }
+        }
+
+        // The last configure is only acked by a frame drawn for it.
+        assert!(!acked.lock().unwrap().contains(&8));
+        present(size.snapshot());
+        assert_eq!(*acked.lock().unwrap(), [1, 3, 5, 7, 8]);
+    }
+
+    ///
+    /// Whether a click at `(x, y)` (in logical pixels) lands on the surface,
+    /// as the compositor would work it out from the input region.
+    ///
+    fn takes_input(logical_size: (u32, u32), radii: CornerRadii, (x, y): (i32, i32)) -> bool {
+        let (width, height) = (logical_size.0 as i32, logical_size.1 as i32);
+        let inside = |(left, top, w, h): (i32, i32, i32, i32)| {
+            (left..left + w).contains(&x) && (top..top + h).contains(&y)
+        };
+
+        inside((0, 0, width, height))
+            && !corner_cutouts(logical_size, radii).into_iter().any(inside)
+    }
+
+    #[test]
+    fn clicks_in_a_rounded_corner_fall_through() {
+        let size = (200, 40);
+        let radii = CornerRadii::new(12.0, 8.5, 0.0, 4.0);
+
+        // Right in each corner.
+        assert!(!takes_input(size, radii, (0, 0)));
+        assert!(!takes_input(size, radii, (199, 0)));
+        assert!(takes_input(size, radii, (199, 39)));
+        assert!(!takes_input(size, radii, (0, 39)));
+
+        // Just past each square, which is rounded up to whole pixels.
+        assert!(takes_input(size, radii, (12, 0)));
+        assert!(!takes_input(size, radii, (191, 8)));
+        assert!(takes_input(size, radii, (190, 0)));
+        assert!(takes_input(size, radii, (4, 39)));
+
+        assert!(takes_input(size, radii, (100, 20)));
+    }
+
+    #[test]
+    fn corners_never_cut_past_the_middle() {
+        let cutouts = corner_cutouts((30, 10), CornerRadii::uniform(50.0));
+        assert!(cutouts
+            .iter()
+            .all(|&(_, _, width, height)| width == 5 && height == 5));
+
+        assert!(takes_input((30, 10), CornerRadii::uniform(50.0), (15, 5)));
+    }
+
+    #[test]
+    fn square_corners_cut_nothing() {
+        let cutouts = corner_cutouts((30, 10), CornerRadii::ZERO);
+        assert!(cutouts
+            .iter()
+            .all(|&(_, _, width, height)| width == 0 && height == 0));
+    }
+
+    ///
+    /// A frame of `size`, cleared white (as a swapchain image may be), drawn
+    /// solid red through a [FrameClip] with `radii`.
+    ///
+    fn masked_frame(size: SizeSnapshot, radii: CornerRadii) -> skia_safe::Image {
+        let (width, height) = size.physical_size();
+        let mut surface =
+            skia_safe::surfaces::raster_n32_premul((width as i32, height as i32)).unwrap();
+        let canvas = surface.canvas();
+        canvas.clear(Color::WHITE);
+        size.apply_to_canvas(canvas);
+
+        let (logical_width, logical_height) = size.logical_size();
+        let outline = radii.rrect(Rect::from_wh(logical_width as f32, logical_height as f32));
+        let clip = FrameClip::apply(canvas, &size, Some(outline));
+        canvas.draw_color(Color::RED, BlendMode::Src);
+        clip.finish(canvas, &size);
+
+        surface.image_snapshot()
+    }
+
+    fn snapshot(transform: Transform) -> SizeSnapshot {
+        SizeSnapshot {
+            logical: (40, 24),
+            physical: (60.0, 36.0),
+            scale: 1.5,
+            transform,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn rounded_corners_are_cleared_at_fractional_scales() {
+        let image = masked_frame(snapshot(Transform::Normal), CornerRadii::uniform(8.0));
+        let pixmap = image.peek_pixels().unwrap();
+
+        for corner in [(0, 0), (59, 0), (59, 35), (0, 35)] {
+            assert_eq!(pixmap.get_color(corner), Color::TRANSPARENT, "{corner:?}");
+        }
+
+        // Along the edges, past the radius (12 physical pixels), and within.
+        for inside in [(30, 0), (59, 18), (30, 35), (0, 18), (30, 18)] {
+            assert_eq!(pixmap.get_color(inside), Color::RED, "{inside:?}");
+        }
+
+        // Anti-aliased along the curve, rather than stepped.
+        assert!((0..12)
+            .map(|i| pixmap.get_color((i, i)).a())
+            .any(|alpha| alpha > 0 && alpha < 255));
+    }
+
+    #[test]
+    fn corners_follow_the_surface_not_the_drawing() {
+        let radii = CornerRadii::new(10.0, 0.0, 0.0, 0.0);
+
+        for transform in Transform::ALL {
+            let image = masked_frame(snapshot(transform), radii);
+            let pixmap = image.peek_pixels().unwrap();
+
+            assert_eq!(
+                pixmap.get_color((0, 0)),
+                Color::TRANSPARENT,
+                "{transform:?}"
+            );
+            for square in [(59, 0), (59, 35), (0, 35)] {
+                assert_eq!(
+                    pixmap.get_color(square),
+                    Color::RED,
+                    "{transform:?} {square:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//!
+//! [Avy]: the connection, its event queue and the [AvyClient] in one place,
+//! for applications which don't need to juggle them separately.
+//!
+//! The split API ([AvyClient::new], [AvyLayer::build] with an explicit
+//! [EventQueue], ...) stays available for anything more involved.
+//!
+
+use std::ops::{Deref, DerefMut};
+
+use smithay_client_toolkit::reexports::{
+    calloop::{self, LoopHandle},
+    calloop_wayland_source::WaylandSource,
+    client::{
+        globals::{registry_queue_init, GlobalError},
+        protocol::wl_output::WlOutput,
+        ConnectError, Connection, DispatchError, EventQueue, QueueHandle,
+    },
+};
+use thiserror::Error;
+
+use crate::{
+    app::RegisteredSurface,
+    wayland::{
+        error::ProtocolError,
+        surface::{
+            layer::{AvyLayer, AvyLayerParams, LayerError},
+            mirror::MirrorError,
+            registry::NameTaken,
+            AvySurface,
+        },
+    },
+    AvyClient,
+};
+
+#[derive(Debug, Error)]
+pub enum AvyError {
+    #[error("Could not connect to the compositor: {0}")]
+    Connect(#[from] ConnectError),
+    #[error("Could not list the compositor's globals: {0}")]
+    Globals(#[from] GlobalError),
+    #[error("Could not set the client up: {0}")]
+    Client(Box<dyn std::error::Error>),
+    #[error("Could not dispatch events: {0}")]
+    Dispatch(#[from] DispatchError),
+    #[error("Could not insert the Wayland source into the event loop.")]
+    Insert,
+    #[error("The compositor closed the connection: {0}")]
+    Protocol(ProtocolError),
+    #[error("Lost the connection to the compositor: {0}")]
+    Disconnected(std::io::Error),
+    #[error("Could not run the event loop: {0}")]
+    EventLoop(#[from] calloop::Error),
+}
+
+///
+/// An [AvyClient] together with its [Connection] and [EventQueue].
+///
+/// Derefs to the client. Once set up, hand it to an event loop with
+/// [Avy::insert_into] (or [Avy::into_calloop_source]).
+///
+pub struct Avy {
+    connection: Connection,
+    event_queue: EventQueue<AvyClient>,
+    client: AvyClient,
+}
+
+impl Avy {
+    ///
+    /// Connect to the compositor named by the environment, bind everything, and
+    /// wait for the initial state (outputs, seats, ...) to come in.
+    ///
+    /// `logical_size` is the client's default surface size, see [AvyClient::new].
+    ///
+    pub fn connect(logical_size: (u32, u32)) -> Result<Self, AvyError> {
+        Self::with_connection(Connection::connect_to_env()?, logical_size)
+    }
+
+    ///
+    /// Like [Avy::connect], over an existing connection.
+    ///
+    pub fn with_connection(
+        connection: Connection,
+        logical_size: (u32, u32),
+    ) -> Result<Self, AvyError> {
+        let (globals, event_queue) = registry_queue_init::<AvyClient>(&connection)?;
+        let client = AvyClient::new(
+            &globals,
+            &event_queue.handle(),
+            logical_size,
+            connection.display(),
+        )
+        .map_err(AvyError::Client)?;
+
+        let mut avy = Self::from_parts(connection, event_queue, client);
+        avy.roundtrip()?;
+
+        Ok(avy)
+    }
+
+    ///
+    /// Wrap a client set up through the split API.
+    ///
+    pub fn from_parts(
+        connection: Connection,
+        event_queue: EventQueue<AvyClient>,
+        client: AvyClient,
+    ) -> Self {
+        Self {
+            connection,
+            event_queue,
+            client,
+        }
+    }
+
+    pub fn into_parts(self) -> (Connection, EventQueue<AvyClient>, AvyClient) {
+        (self.connection, self.event_queue, self.client)
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn queue_handle(&self) -> QueueHandle<AvyClient> {
+        self.event_queue.handle()
+    }
+
+    pub fn client(&self) -> &AvyClient {
+        &self.client
+    }
+
+    pub fn client_mut(&mut self) -> &mut AvyClient {
+        &mut self.client
+    }
+
+    ///
+    /// Send all pending requests, and dispatch every event the compositor sent
+    /// in reply to them.
+    ///
+    /// A protocol error goes to [AvyClient::on_fatal_error], and is returned
+    /// as [AvyError::Protocol].
+    ///
+    pub fn roundtrip(&mut self) -> Result<usize, AvyError> {
+        self.event_queue
+            .roundtrip(&mut self.client)
+            .map_err(|error| self.client.dispatch_failed(error))
+    }
+
+    ///
+    /// Create a layer surface and register it, see [AvyLayer::build].
+    ///
+    pub fn create_layer(
+        &mut self,
+        params: AvyLayerParams,
+    ) -> Result<RegisteredSurface<'_>, LayerError> {
+        AvyLayer::build(&mut self.client, &mut self.event_queue, params)
+    }
+
+    ///
+    /// Rebuild the layers of a saved layout, see [AvyClient::restore_layout].
+    ///
+    #[cfg(feature = "config")]
+    pub fn restore_layout<G: crate::graphics::GraphicsBackend>(
+        &mut self,
+        snapshot: &crate::config::LayoutSnapshot,
+        backend: &G,
+    ) -> crate::config::LayoutRestore<G>
+    where
+        G::Surface: 'static,
+    {
+        self.client
+            .restore_layout(snapshot, &mut self.event_queue, backend)
+    }
+
+    ///
+    /// Show what `source` draws on `target_output` too, see [AvyClient::mirror_surface].
+    ///
+    pub fn mirror_surface<G: crate::graphics::GraphicsBackend + 'static>(
+        &mut self,
+        source: &crate::app::AvySurfaceHandle<G>,
+        target_output: &WlOutput,
+        backend: &G,
+    ) -> Result<crate::app::AvySurfaceHandle<G>, MirrorError<G::Error>>
+    where
+        G::Surface: 'static,
+    {
+        self.client
+            .mirror_surface(source, target_output, &mut self.event_queue, backend)
+    }
+
+    ///
+    /// Register a surface made by hand, see [AvyClient::register_surface].
+    ///
+    pub fn register_surface<S: AvySurface + 'static>(
+        &mut self,
+        surface: S,
+        name: Option<&str>,
+    ) -> Result<RegisteredSurface<'_>, NameTaken> {
+        self.client
+            .register_surface(surface, name, &mut self.event_queue)
+    }
+
+    ///
+    /// The event queue as a calloop source, and the client to run the loop with.
+    ///
+    /// Remember to [AvyClient::set_loop_handle] once it's inserted, or use
+    /// [Avy::insert_into], which does both.
+    ///
+    pub fn into_calloop_source(self) -> (WaylandSource<AvyClient>, AvyClient) {
+        (
+            WaylandSource::new(self.connection, self.event_queue),
+            self.client,
+        )
+    }
+
+    ///
+    /// Dispatch the event queue on `handle`'s loop, returning the client to run it with.
+    ///
+    pub fn insert_into(
+        self,
+        handle: &LoopHandle<'static, AvyClient>,
+    ) -> Result<AvyClient, AvyError> {
+        let (source, mut client) = self.into_calloop_source();
+        source
+            .insert(handle.clone())
+            .map_err(|_| AvyError::Insert)?;
+        client.set_loop_handle(handle.clone());
+
+        Ok(client)
+    }
+}
+
+impl Deref for Avy {
+    type Target = AvyClient;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl DerefMut for Avy {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
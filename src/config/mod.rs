@@ -0,0 +1,377 @@
+//!
+//! Typed TOML configuration for layer surfaces (which output, which edge,
+//! how big) and their theme, so that apps don't each reinvent loading it.
+//!
+//! ```toml
+//! [surface]
+//! namespace = "panel"
+//! layer = "top"
+//! anchor = ["top", "left", "right"]
+//! height = 40
+//! exclusive_zone = 40
+//! output = "DP-1"
+//!
+//! [theme]
+//! color_scheme = "dark"
+//! accent = "#3584e4"
+//! ```
+//!
+//! Per-machine tweaks to surfaces built into an app go in [rules].
+//!
+
+pub mod rules;
+pub mod session;
+
+pub use rules::{explain, MatchedRule, RuleOverrides, SurfaceRule, SurfaceRules};
+pub use session::{LayoutRestore, LayoutSnapshot, RestoreWarning, SurfaceLayout};
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use skia_safe::Color4f;
+use smithay_client_toolkit::{reexports::client::protocol::wl_output::WlOutput, shell::wlr_layer};
+use thiserror::Error;
+
+use crate::{
+    theme::{Appearance, ColorScheme},
+    timer::{self, TimerAction, TimerToken},
+    util::{Insets, Size},
+    wayland::surface::layer::{AvyLayerParams, LayerError},
+    AvyClient,
+};
+
+///
+/// How often [AvyClient::watch_config] checks whether the file changed.
+///
+pub const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not read the configuration: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid configuration: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("There's no output called {name:?} (available: {})", available.join(", "))]
+    UnknownOutput {
+        name: String,
+        available: Vec<String>,
+    },
+
+    #[error("Invalid color {0:?}, expected #rrggbb or #rrggbbaa.")]
+    InvalidColor(String),
+
+    #[error("{0}")]
+    Unsupported(#[from] LayerError),
+}
+
+///
+/// Read and parse a TOML configuration file.
+///
+pub fn load<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, ConfigError> {
+    Ok(toml::from_str(&fs::read_to_string(path)?)?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    Background,
+    Bottom,
+    #[default]
+    Top,
+    Overlay,
+}
+
+impl From<Layer> for wlr_layer::Layer {
+    fn from(layer: Layer) -> Self {
+        match layer {
+            Layer::Background => Self::Background,
+            Layer::Bottom => Self::Bottom,
+            Layer::Top => Self::Top,
+            Layer::Overlay => Self::Overlay,
+        }
+    }
+}
+
+impl From<wlr_layer::Layer> for Layer {
+    fn from(layer: wlr_layer::Layer) -> Self {
+        match layer {
+            wlr_layer::Layer::Background => Self::Background,
+            wlr_layer::Layer::Bottom => Self::Bottom,
+            wlr_layer::Layer::Overlay => Self::Overlay,
+            _ => Self::Top,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyboardInteractivity {
+    #[default]
+    None,
+    Exclusive,
+    OnDemand,
+}
+
+impl From<KeyboardInteractivity> for wlr_layer::KeyboardInteractivity {
+    fn from(interactivity: KeyboardInteractivity) -> Self {
+        match interactivity {
+            KeyboardInteractivity::None => Self::None,
+            KeyboardInteractivity::Exclusive => Self::Exclusive,
+            KeyboardInteractivity::OnDemand => Self::OnDemand,
+        }
+    }
+}
+
+impl From<wlr_layer::KeyboardInteractivity> for KeyboardInteractivity {
+    fn from(interactivity: wlr_layer::KeyboardInteractivity) -> Self {
+        match interactivity {
+            wlr_layer::KeyboardInteractivity::Exclusive => Self::Exclusive,
+            wlr_layer::KeyboardInteractivity::OnDemand => Self::OnDemand,
+            _ => Self::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Margin {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+impl From<Margin> for Insets {
+    fn from(margin: Margin) -> Self {
+        Insets::new(margin.top, margin.right, margin.bottom, margin.left)
+    }
+}
+
+impl From<Insets> for Margin {
+    fn from(insets: Insets) -> Self {
+        Self {
+            top: insets.top,
+            right: insets.right,
+            bottom: insets.bottom,
+            left: insets.left,
+        }
+    }
+}
+
+///
+/// Where a layer surface goes and how big it is, see [AvyLayerParams].
+///
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurfaceConfig {
+    pub namespace: Option<String>,
+    pub layer: Layer,
+    /// Edges the surface is anchored to.
+    pub anchor: Vec<Edge>,
+    /// In logical pixels. Zero stretches the surface between
+    /// the edges it's anchored to on that axis.
+    pub width: u32,
+    pub height: u32,
+    pub margin: Margin,
+    pub exclusive_zone: Option<i32>,
+    pub keyboard_interactivity: KeyboardInteractivity,
+    /// Name of the output to show the surface on (e.g. `DP-1`),
+    /// or the compositor's choice.
+    pub output: Option<String>,
+}
+
+impl SurfaceConfig {
+    pub fn anchor(&self) -> wlr_layer::Anchor {
+        anchor(&self.anchor)
+    }
+
+    ///
+    /// Turn this into parameters for [crate::wayland::surface::layer::AvyLayer::build],
+    /// looking the output up by name amongst the client's current outputs.
+    ///
+    /// Fails if the compositor's layer shell doesn't support them.
+    ///
+    pub fn into_params(&self, app: &AvyClient) -> Result<AvyLayerParams<'_>, ConfigError> {
+        let output = self
+            .output
+            .as_deref()
+            .map(|name| find_output(app, name))
+            .transpose()?;
+
+        let margin = Insets::from(self.margin);
+
+        let params = AvyLayerParams {
+            layer: self.layer.into(),
+            namespace: self.namespace.as_deref(),
+            output,
+            anchor: self.anchor(),
+            size: Size::new((self.width, self.height)),
+            margin: Some((margin.top, margin.right, margin.bottom, margin.left)),
+            exclusive_zone: self.exclusive_zone,
+            keyboard_interactivity: self.keyboard_interactivity.into(),
+            manual_configure_ack: false,
+        };
+
+        params.validate(app.layer_shell_version())?;
+        Ok(params)
+    }
+}
+
+///
+/// The edges `anchor` is anchored to, the other way around from [SurfaceConfig::anchor].
+///
+fn edges(anchor: wlr_layer::Anchor) -> Vec<Edge> {
+    [
+        (wlr_layer::Anchor::TOP, Edge::Top),
+        (wlr_layer::Anchor::BOTTOM, Edge::Bottom),
+        (wlr_layer::Anchor::LEFT, Edge::Left),
+        (wlr_layer::Anchor::RIGHT, Edge::Right),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| anchor.contains(*flag))
+    .map(|(_, edge)| edge)
+    .collect()
+}
+
+fn anchor(edges: &[Edge]) -> wlr_layer::Anchor {
+    edges
+        .iter()
+        .fold(wlr_layer::Anchor::empty(), |anchor, edge| {
+            anchor
+                | match edge {
+                    Edge::Top => wlr_layer::Anchor::TOP,
+                    Edge::Bottom => wlr_layer::Anchor::BOTTOM,
+                    Edge::Left => wlr_layer::Anchor::LEFT,
+                    Edge::Right => wlr_layer::Anchor::RIGHT,
+                }
+        })
+}
+
+fn find_output(app: &AvyClient, name: &str) -> Result<WlOutput, ConfigError> {
+    app.output_by_name(name)
+        .ok_or_else(|| ConfigError::UnknownOutput {
+            name: name.to_owned(),
+            available: app.output_names(),
+        })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorSchemeConfig {
+    NoPreference,
+    Dark,
+    Light,
+}
+
+impl From<ColorSchemeConfig> for ColorScheme {
+    fn from(scheme: ColorSchemeConfig) -> Self {
+        match scheme {
+            ColorSchemeConfig::NoPreference => Self::NoPreference,
+            ColorSchemeConfig::Dark => Self::Dark,
+            ColorSchemeConfig::Light => Self::Light,
+        }
+    }
+}
+
+///
+/// Overrides for the desktop's [Appearance]: anything left
+/// out keeps following the desktop.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub color_scheme: Option<ColorSchemeConfig>,
+    /// `#rrggbb` or `#rrggbbaa`.
+    pub accent: Option<String>,
+}
+
+impl ThemeConfig {
+    ///
+    /// `appearance`, with this configuration's overrides applied.
+    ///
+    pub fn apply(&self, appearance: Appearance) -> Result<Appearance, ConfigError> {
+        let mut appearance = appearance;
+
+        if let Some(color_scheme) = self.color_scheme {
+            appearance.color_scheme = color_scheme.into();
+        }
+
+        if let Some(accent) = &self.accent {
+            appearance.accent = Some(parse_color(accent)?);
+        }
+
+        Ok(appearance)
+    }
+}
+
+fn parse_color(color: &str) -> Result<Color4f, ConfigError> {
+    let invalid = || ConfigError::InvalidColor(color.to_owned());
+
+    let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+    let (rgb, alpha) = match hex.len() {
+        6 => (value, 0xff),
+        _ => (value >> 8, value & 0xff),
+    };
+
+    let component = |value: u32| (value & 0xff) as f32 / 255.0;
+
+    Ok(Color4f::new(
+        component(rgb >> 16),
+        component(rgb >> 8),
+        component(rgb),
+        component(alpha),
+    ))
+}
+
+impl AvyClient {
+    ///
+    /// Load the configuration at `path`, then again whenever it changes,
+    /// handing each result to `callback` (e.g. to rebuild layers).
+    ///
+    /// The file is checked every [CONFIG_POLL_INTERVAL]. Cancel watching
+    /// with [AvyClient::cancel_timer].
+    ///
+    pub fn watch_config<T: DeserializeOwned + 'static>(
+        &self,
+        path: impl Into<PathBuf>,
+        mut callback: impl FnMut(&mut AvyClient, Result<T, ConfigError>) + 'static,
+    ) -> Result<TimerToken, timer::Error> {
+        let path = path.into();
+        // `None` until the first check, which always loads the file.
+        let mut last_modified: Option<Option<SystemTime>> = None;
+
+        self.add_timer(Duration::ZERO, move |app| {
+            // A missing file is reported once, as an error.
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+
+            if last_modified != Some(modified) {
+                last_modified = Some(modified);
+                callback(app, load(&path));
+            }
+
+            TimerAction::Repeat(CONFIG_POLL_INTERVAL)
+        })
+    }
+}
@@ -0,0 +1,619 @@
+//!
+//! Per-surface overrides, much like window rules: which output each
+//! component lands on, its exclusive zone and so on, tweaked per machine
+//! without rebuilding the app.
+//!
+//! ```toml
+//! [[rule]]
+//! match = "panel"
+//! output = "DP-2"
+//! exclusive_zone = 48
+//!
+//! [[rule]]
+//! match = "osd-*"
+//! layer = "overlay"
+//! scale_mode = "integer"
+//! target_fps = 30
+//! ```
+//!
+//! A rule matches a surface when its glob (`*` for any run of characters,
+//! `?` for any one) matches the surface's layer namespace, or its name (see
+//! [AvyClient::register_surface]). When several matching rules set the same
+//! override, the most specific rule (the one with the most literal characters)
+//! wins, then the last one.
+//!
+//! Rules apply to layers as they're built ([crate::wayland::surface::layer::AvyLayer::build]),
+//! and again to every surface whenever they change, see [AvyClient::set_surface_rules].
+//!
+
+use std::{path::PathBuf, sync::RwLock};
+
+use serde::{Deserialize, Serialize};
+use smithay_client_toolkit::reexports::client::{backend::ObjectId, Proxy};
+
+use crate::{
+    app::SurfaceShared,
+    timer::{self, TimerToken},
+    util::{self, Insets},
+    wayland::surface::layer::{AvyLayer, AvyLayerParams, LayerTransaction},
+    AvyClient,
+};
+
+use super::{anchor, find_output, ConfigError, Edge, Layer, Margin};
+
+///
+/// The rules in effect, see [AvyClient::set_surface_rules].
+///
+static ACTIVE_RULES: RwLock<SurfaceRules> = RwLock::new(SurfaceRules { rules: Vec::new() });
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleMode {
+    Fractional,
+    Integer,
+}
+
+impl From<ScaleMode> for util::ScaleMode {
+    fn from(scale_mode: ScaleMode) -> Self {
+        match scale_mode {
+            ScaleMode::Fractional => Self::Fractional,
+            ScaleMode::Integer => Self::Integer,
+        }
+    }
+}
+
+impl From<util::ScaleMode> for ScaleMode {
+    fn from(scale_mode: util::ScaleMode) -> Self {
+        match scale_mode {
+            util::ScaleMode::Fractional => Self::Fractional,
+            util::ScaleMode::Integer => Self::Integer,
+        }
+    }
+}
+
+///
+/// What a rule changes about the surfaces it matches. Anything left out is
+/// up to the app.
+///
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleOverrides {
+    /// Name of the output to show the surface on, e.g. `DP-1`.
+    pub output: Option<String>,
+    pub layer: Option<Layer>,
+    pub anchor: Option<Vec<Edge>>,
+    pub margin: Option<Margin>,
+    pub exclusive_zone: Option<i32>,
+    pub scale_mode: Option<ScaleMode>,
+    pub target_fps: Option<f64>,
+}
+
+impl RuleOverrides {
+    ///
+    /// Take on every override `other` sets.
+    ///
+    fn merge(&mut self, other: &RuleOverrides) {
+        fn take<T: Clone>(this: &mut Option<T>, other: &Option<T>) {
+            if let Some(other) = other {
+                this.replace(other.clone());
+            }
+        }
+
+        take(&mut self.output, &other.output);
+        take(&mut self.layer, &other.layer);
+        take(&mut self.anchor, &other.anchor);
+        take(&mut self.margin, &other.margin);
+        take(&mut self.exclusive_zone, &other.exclusive_zone);
+        take(&mut self.scale_mode, &other.scale_mode);
+        take(&mut self.target_fps, &other.target_fps);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SurfaceRule {
+    /// Glob matched against the surface's namespace and name.
+    #[serde(rename = "match")]
+    pub pattern: String,
+    #[serde(flatten)]
+    pub overrides: RuleOverrides,
+}
+
+impl SurfaceRule {
+    ///
+    /// How many literal (non-wildcard) characters the pattern has:
+    /// more specific rules win over less specific ones.
+    ///
+    pub fn specificity(&self) -> usize {
+        self.pattern
+            .chars()
+            .filter(|c| !matches!(c, '*' | '?'))
+            .count()
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        glob_match(&self.pattern, name)
+    }
+}
+
+///
+/// A rule which matched a surface, see [explain].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedRule {
+    /// Position of the rule in its file.
+    pub index: usize,
+    pub pattern: String,
+    pub specificity: usize,
+    pub overrides: RuleOverrides,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurfaceRules {
+    #[serde(rename = "rule")]
+    pub rules: Vec<SurfaceRule>,
+}
+
+impl SurfaceRules {
+    ///
+    /// The rules matching any of `names`, from the one which wins to the one
+    /// which loses.
+    ///
+    pub fn matching(&self, names: &[&str]) -> Vec<MatchedRule> {
+        let mut matched: Vec<_> = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| names.iter().any(|name| rule.matches(name)))
+            .map(|(index, rule)| MatchedRule {
+                index,
+                pattern: rule.pattern.clone(),
+                specificity: rule.specificity(),
+                overrides: rule.overrides.clone(),
+            })
+            .collect();
+
+        matched.sort_by_key(|rule| std::cmp::Reverse((rule.specificity, rule.index)));
+        matched
+    }
+
+    ///
+    /// The overrides for a surface called any of `names`, or `None` if no rule matches.
+    ///
+    pub fn resolve(&self, names: &[&str]) -> Option<RuleOverrides> {
+        let matched = self.matching(names);
+        if matched.is_empty() {
+            return None;
+        }
+
+        // Weakest first, so that stronger rules overwrite them.
+        let mut overrides = RuleOverrides::default();
+        for rule in matched.iter().rev() {
+            overrides.merge(&rule.overrides);
+        }
+
+        Some(overrides)
+    }
+}
+
+///
+/// Which of the rules in effect match `surface_name` (a namespace or surface
+/// name), from the one which wins to the one which loses, for debugging.
+///
+pub fn explain(surface_name: &str) -> Vec<MatchedRule> {
+    ACTIVE_RULES.read().unwrap().matching(&[surface_name])
+}
+
+///
+/// The rules in effect.
+///
+pub fn active() -> SurfaceRules {
+    ACTIVE_RULES.read().unwrap().clone()
+}
+
+///
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters (including none), and `?` for any one character.
+///
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // The last `*` seen, and where in the name it started matching.
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Backtrack: have the `*` take one more character.
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+///
+/// The names a layer with `namespace` is matched by when built: its
+/// namespace, and the name it's registered under by default.
+///
+fn layer_names(namespace: &str) -> [String; 2] {
+    [namespace.to_owned(), format!("layer:{namespace}")]
+}
+
+///
+/// `params`, with the overrides of the rules in effect applied.
+///
+/// Outputs which aren't connected are left out (with a warning).
+///
+pub(crate) fn apply_to_params<'a>(
+    app: &AvyClient,
+    mut params: AvyLayerParams<'a>,
+) -> AvyLayerParams<'a> {
+    let Some(namespace) = params.namespace else {
+        return params;
+    };
+
+    let names = layer_names(namespace);
+    let names = names.each_ref().map(String::as_str);
+    let Some(overrides) = ACTIVE_RULES.read().unwrap().resolve(&names) else {
+        return params;
+    };
+
+    if let Some(name) = &overrides.output {
+        match find_output(app, name) {
+            Ok(output) => params.output = Some(output),
+            Err(err) => log::warn!("Ignoring the output a rule sets for {namespace:?}: {err}"),
+        }
+    }
+
+    if let Some(layer) = overrides.layer {
+        params.layer = layer.into();
+    }
+
+    if let Some(edges) = &overrides.anchor {
+        params.anchor = anchor(edges);
+    }
+
+    if let Some(margin) = overrides.margin {
+        let margin = Insets::from(margin);
+        params.margin = Some((margin.top, margin.right, margin.bottom, margin.left));
+    }
+
+    if let Some(exclusive_zone) = overrides.exclusive_zone {
+        params.exclusive_zone = Some(exclusive_zone);
+    }
+
+    if let Some(scale_mode) = overrides.scale_mode {
+        params.size = params.size.with_scale_mode(scale_mode.into());
+    }
+
+    params
+}
+
+impl AvyClient {
+    ///
+    /// The overrides for a registered surface, from the rules in effect.
+    ///
+    pub fn surface_overrides(&self, id: &ObjectId) -> Option<RuleOverrides> {
+        if !self.surfaces.contains_key(id) {
+            return None;
+        }
+
+        let namespace = self.layer(id).and_then(AvyLayer::namespace);
+
+        let names: Vec<&str> = namespace
+            .into_iter()
+            .chain(self.surface_names.get(id).map(String::as_str))
+            .collect();
+
+        ACTIVE_RULES.read().unwrap().resolve(&names)
+    }
+
+    ///
+    /// Put `rules` in effect, and apply them to every registered surface.
+    ///
+    /// Returns the layers which need rebuilding for the rules to apply: those
+    /// which should move to another output, or another layer where the layer
+    /// shell can't move them. Overrides which a rule no longer sets are left
+    /// as they are until the surface is rebuilt.
+    ///
+    pub fn set_surface_rules(&mut self, rules: SurfaceRules) -> Vec<ObjectId> {
+        *ACTIVE_RULES.write().unwrap() = rules;
+
+        let ids: Vec<ObjectId> = self.surfaces.keys().cloned().collect();
+        ids.into_iter()
+            .filter(|id| self.reapply_surface_rules(id))
+            .collect()
+    }
+
+    ///
+    /// Load surface rules from `path`, then again whenever it changes,
+    /// applying them with [AvyClient::set_surface_rules]. `rebuild` is
+    /// handed the layers which need rebuilding, if any.
+    ///
+    /// Invalid files are reported (with a warning), keeping the rules in effect.
+    ///
+    pub fn watch_surface_rules(
+        &self,
+        path: impl Into<PathBuf>,
+        mut rebuild: impl FnMut(&mut AvyClient, Vec<ObjectId>) + 'static,
+    ) -> Result<TimerToken, timer::Error> {
+        self.watch_config(
+            path,
+            move |app, rules: Result<SurfaceRules, ConfigError>| {
+                let rules = match rules {
+                    Ok(rules) => rules,
+                    Err(err) => {
+                        log::warn!("Keeping the surface rules in effect: {err}");
+                        return;
+                    }
+                };
+
+                let stale = app.set_surface_rules(rules);
+                if !stale.is_empty() {
+                    rebuild(app, stale);
+                }
+            },
+        )
+    }
+
+    ///
+    /// Start a surface which just got something to draw into it off at
+    /// the frame rate its rules set.
+    ///
+    pub(crate) fn apply_target_fps_rule(&self, id: &ObjectId, state: &SurfaceShared) {
+        if let Some(target_fps) = self.surface_overrides(id).and_then(|o| o.target_fps) {
+            *state.target_fps.lock().unwrap() = Some(target_fps).filter(|fps| *fps > 0.0);
+        }
+    }
+
+    ///
+    /// Apply the rules in effect to the surface `id`, through its runtime
+    /// setters. Returns whether it needs rebuilding for all of them to apply.
+    ///
+    fn reapply_surface_rules(&mut self, id: &ObjectId) -> bool {
+        let Some(overrides) = self.surface_overrides(id) else {
+            return false;
+        };
+
+        let mut rebuild = false;
+
+        if let Some(controller) = self.layer_controller(id) {
+            let mut transaction = LayerTransaction::new();
+            if let Some(edges) = &overrides.anchor {
+                transaction = transaction.anchor(anchor(edges));
+            }
+            if let Some(margin) = overrides.margin {
+                transaction = transaction.margin(margin.into());
+            }
+            if let Some(exclusive_zone) = overrides.exclusive_zone {
+                transaction = transaction.exclusive_zone(exclusive_zone);
+            }
+
+            if let Err(err) = controller.commit(transaction) {
+                log::warn!("Could not apply the surface rules for {id}: {err}");
+            }
+
+            if let Some(layer) = overrides.layer {
+                rebuild |= controller.set_layer(layer.into()).is_err();
+            }
+
+            if let Some(name) = &overrides.output {
+                match find_output(self, name) {
+                    Ok(output) => {
+                        rebuild |= controller.output().map(Proxy::id) != Some(output.id())
+                    }
+                    Err(err) => log::warn!("Ignoring the output a rule sets for {id}: {err}"),
+                }
+            }
+        }
+
+        if let Some(scale_mode) = overrides.scale_mode {
+            let rescaled = {
+                let mut size = self.surfaces.get_mut(id).unwrap().size_mut();
+                let generation = size.generation();
+                size.set_scale_mode(scale_mode.into());
+                size.generation() != generation
+            };
+
+            if let Some(state) = self.surface_shared.get(id).filter(|_| rescaled) {
+                state.dirty.mark();
+            }
+        }
+
+        // Surfaces without anything drawing into them yet pick this up when
+        // they get something, see [AvyClient::apply_target_fps_rule].
+        if let Some(state) = self.surface_shared.get(id) {
+            if let Some(target_fps) = overrides.target_fps {
+                *state.target_fps.lock().unwrap() = Some(target_fps).filter(|fps| *fps > 0.0);
+            }
+        }
+
+        rebuild
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, overrides: RuleOverrides) -> SurfaceRule {
+        SurfaceRule {
+            pattern: pattern.to_owned(),
+            overrides,
+        }
+    }
+
+    fn fps(target_fps: f64) -> RuleOverrides {
+        RuleOverrides {
+            target_fps: Some(target_fps),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn globs_match_literally_and_with_wildcards() {
+        assert!(glob_match("panel", "panel"));
+        assert!(!glob_match("panel", "panels"));
+        assert!(!glob_match("panel", "pane"));
+
+        assert!(glob_match("osd-*", "osd-"));
+        assert!(glob_match("osd-*", "osd-volume"));
+        assert!(!glob_match("osd-*", "osd"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("**", "anything"));
+
+        assert!(glob_match("dock-?", "dock-1"));
+        assert!(!glob_match("dock-?", "dock-"));
+        assert!(!glob_match("dock-?", "dock-12"));
+        assert!(glob_match("?ä?", "bär"));
+
+        assert!(!glob_match("", "panel"));
+        assert!(glob_match("", ""));
+    }
+
+    #[test]
+    fn stars_backtrack() {
+        // The first `-` the star stops at isn't the one that matches.
+        assert!(glob_match("*-bar", "top-left-bar"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(glob_match("*layer:*", "layer:panel"));
+    }
+
+    #[test]
+    fn specificity_counts_literal_characters() {
+        assert_eq!(rule("panel", fps(1.0)).specificity(), 5);
+        assert_eq!(rule("osd-*", fps(1.0)).specificity(), 4);
+        assert_eq!(rule("dock-?", fps(1.0)).specificity(), 5);
+        assert_eq!(rule("*", fps(1.0)).specificity(), 0);
+    }
+
+    #[test]
+    fn more_specific_rules_win_then_later_ones() {
+        let rules = SurfaceRules {
+            rules: vec![
+                rule("osd-volume", fps(10.0)),
+                rule("*", fps(20.0)),
+                rule("osd-*", fps(30.0)),
+                rule("osd-?olume", fps(40.0)),
+            ],
+        };
+
+        let order: Vec<_> = rules
+            .matching(&["osd-volume"])
+            .into_iter()
+            .map(|rule| rule.index)
+            .collect();
+        assert_eq!(order, [0, 3, 2, 1]);
+        assert_eq!(
+            rules.resolve(&["osd-volume"]).unwrap().target_fps,
+            Some(10.0)
+        );
+
+        // As specific as each other: the last one wins.
+        let ties = SurfaceRules {
+            rules: vec![rule("osd-*", fps(1.0)), rule("*-osd", fps(2.0))],
+        };
+        assert_eq!(ties.resolve(&["osd-osd"]).unwrap().target_fps, Some(2.0));
+    }
+
+    #[test]
+    fn overrides_merge_across_matching_rules() {
+        let rules = SurfaceRules {
+            rules: vec![
+                rule(
+                    "*",
+                    RuleOverrides {
+                        layer: Some(Layer::Bottom),
+                        target_fps: Some(30.0),
+                        ..Default::default()
+                    },
+                ),
+                rule(
+                    "panel",
+                    RuleOverrides {
+                        layer: Some(Layer::Overlay),
+                        exclusive_zone: Some(48),
+                        ..Default::default()
+                    },
+                ),
+            ],
+        };
+
+        let overrides = rules.resolve(&["panel"]).unwrap();
+        assert_eq!(overrides.layer, Some(Layer::Overlay));
+        assert_eq!(overrides.exclusive_zone, Some(48));
+        assert_eq!(overrides.target_fps, Some(30.0));
+        assert_eq!(overrides.output, None);
+    }
+
+    #[test]
+    fn layers_match_by_namespace_or_surface_name() {
+        let rules = SurfaceRules {
+            rules: vec![rule("layer:dock", fps(60.0))],
+        };
+
+        let names = layer_names("dock");
+        let names = names.each_ref().map(String::as_str);
+        assert_eq!(rules.resolve(&names).unwrap().target_fps, Some(60.0));
+
+        assert!(rules.resolve(&["dock"]).is_none());
+    }
+
+    #[test]
+    fn no_matching_rule_resolves_to_none() {
+        let rules = SurfaceRules {
+            rules: vec![rule("panel", fps(60.0))],
+        };
+
+        assert!(rules.resolve(&["dock"]).is_none());
+        assert!(rules.matching(&["dock"]).is_empty());
+    }
+
+    #[test]
+    fn parses_the_documented_format() {
+        let rules: SurfaceRules = toml::from_str(
+            r#"
+            [[rule]]
+            match = "panel"
+            output = "DP-2"
+            exclusive_zone = 48
+
+            [[rule]]
+            match = "osd-*"
+            layer = "overlay"
+            scale_mode = "integer"
+            target_fps = 30
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(rules.rules.len(), 2);
+        assert_eq!(rules.rules[0].overrides.output.as_deref(), Some("DP-2"));
+        assert_eq!(rules.rules[1].overrides.layer, Some(Layer::Overlay));
+        assert_eq!(
+            rules.rules[1].overrides.scale_mode,
+            Some(ScaleMode::Integer)
+        );
+        assert_eq!(rules.rules[1].overrides.target_fps, Some(30.0));
+
+        assert!(toml::from_str::<SurfaceRules>("[[rules]]\nmatch = \"panel\"").is_err());
+    }
+}
@@ -0,0 +1,251 @@
+//!
+//! Session restore: where every named layer is, as it is now (margins an
+//! auto-hide moved, layers changed at runtime and all), saved so that a
+//! restarted shell puts its layers back exactly where they were.
+//!
+//! ```no_run
+//! # use avy_render::{config::LayoutSnapshot, AvyClient};
+//! # fn save(app: &AvyClient) -> Result<(), Box<dyn std::error::Error>> {
+//! let snapshot = app.export_layout();
+//! std::fs::write("layout.toml", toml::to_string(&snapshot)?)?;
+//! # Ok(()) }
+//! ```
+//!
+//! Surfaces are named by [AvyClient::register_surface] (a layer's name
+//! defaults to `layer:{namespace}`), and restored under the same name.
+//!
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use smithay_client_toolkit::reexports::client::{protocol::wl_output::WlOutput, EventQueue};
+
+use crate::{
+    app::AvySurfaceHandle,
+    graphics::GraphicsBackend,
+    util::{Insets, Size},
+    wayland::surface::{
+        layer::{AvyLayer, AvyLayerParams},
+        registry::NameTaken,
+    },
+    AvyClient,
+};
+
+use super::{anchor, edges, rules::ScaleMode, Edge, KeyboardInteractivity, Layer, Margin};
+
+///
+/// A layer surface, as it was when [AvyClient::export_layout] was called.
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceLayout {
+    pub name: String,
+    pub namespace: Option<String>,
+    /// Name of the output the surface was created on, or `None` if it was
+    /// left to the compositor.
+    pub output: Option<String>,
+    pub layer: Layer,
+    pub anchor: Vec<Edge>,
+    /// The logical size asked for, zero on axes left to the compositor.
+    pub width: u32,
+    pub height: u32,
+    pub margin: Margin,
+    pub exclusive_zone: i32,
+    pub keyboard_interactivity: KeyboardInteractivity,
+    pub scale_mode: ScaleMode,
+    pub target_fps: Option<f64>,
+    /// See [crate::wayland::protocol::hyprland::HyprlandSurface::set_opacity].
+    pub opacity: Option<f32>,
+}
+
+impl SurfaceLayout {
+    fn params(&self, output: Option<WlOutput>) -> AvyLayerParams<'_> {
+        let margin = Insets::from(self.margin);
+
+        AvyLayerParams {
+            layer: self.layer.into(),
+            namespace: self.namespace.as_deref(),
+            output,
+            anchor: anchor(&self.anchor),
+            size: Size::new((self.width, self.height)).with_scale_mode(self.scale_mode.into()),
+            margin: Some((margin.top, margin.right, margin.bottom, margin.left)),
+            exclusive_zone: Some(self.exclusive_zone),
+            keyboard_interactivity: self.keyboard_interactivity.into(),
+            manual_configure_ack: false,
+        }
+    }
+}
+
+///
+/// Every named layer surface of a client, see [AvyClient::export_layout].
+///
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutSnapshot {
+    #[serde(rename = "surface")]
+    pub surfaces: Vec<SurfaceLayout>,
+}
+
+///
+/// Something which didn't go back quite as it was, see [AvyClient::restore_layout].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestoreWarning {
+    /// The surface's output is gone, so it went on `fallback` instead
+    /// (or wherever the compositor chose, if there are no outputs).
+    OutputGone {
+        surface: String,
+        output: String,
+        fallback: Option<String>,
+    },
+    /// The surface couldn't be restored at all.
+    Failed { surface: String, error: String },
+}
+
+///
+/// The surfaces [AvyClient::restore_layout] rebuilt, by name.
+///
+pub struct LayoutRestore<G> {
+    pub surfaces: HashMap<String, AvySurfaceHandle<G>>,
+    pub warnings: Vec<RestoreWarning>,
+}
+
+impl AvyClient {
+    ///
+    /// Where every named layer surface is right now, for [AvyClient::restore_layout].
+    ///
+    /// Surfaces are listed by name, so that snapshots of the same
+    /// layout compare (and serialize) the same.
+    ///
+    pub fn export_layout(&self) -> LayoutSnapshot {
+        let mut surfaces: Vec<SurfaceLayout> = self
+            .surface_registry
+            .iter()
+            .filter_map(|(name, id)| {
+                let surface = self.surfaces.get(id)?;
+                let layer = self.layer(id)?;
+                let controller = layer.controller();
+                let state = self.surface_shared.get(id);
+
+                let (width, height) = controller.requested_size();
+                let output = controller
+                    .output()
+                    .and_then(|output| self.output_name(output));
+
+                #[cfg(feature = "hyprland-surface")]
+                let opacity = state.and_then(|state| state.hyprland.opacity());
+                #[cfg(not(feature = "hyprland-surface"))]
+                let opacity = None;
+
+                Some(SurfaceLayout {
+                    name: name.to_owned(),
+                    namespace: layer.namespace().map(str::to_owned),
+                    output,
+                    layer: controller.layer().into(),
+                    anchor: edges(controller.anchor()),
+                    width,
+                    height,
+                    margin: controller.margin().into(),
+                    exclusive_zone: controller.exclusive_zone(),
+                    keyboard_interactivity: controller.keyboard_interactivity().into(),
+                    scale_mode: surface.size_ref().scale_mode().into(),
+                    target_fps: state.and_then(|state| *state.target_fps.lock().unwrap()),
+                    opacity,
+                })
+            })
+            .collect();
+
+        surfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        LayoutSnapshot { surfaces }
+    }
+
+    ///
+    /// Rebuild the surfaces of `snapshot` (see [AvyClient::export_layout]),
+    /// drawn with `backend`.
+    ///
+    /// Surfaces whose output is gone go on the first output there is, with
+    /// a warning. Surfaces which can't be rebuilt are left out, with a warning.
+    ///
+    pub fn restore_layout<G: GraphicsBackend>(
+        &mut self,
+        snapshot: &LayoutSnapshot,
+        event_queue: &mut EventQueue<AvyClient>,
+        backend: &G,
+    ) -> LayoutRestore<G>
+    where
+        G::Surface: 'static,
+    {
+        let mut restore = LayoutRestore {
+            surfaces: HashMap::new(),
+            warnings: Vec::new(),
+        };
+
+        for layout in &snapshot.surfaces {
+            let output = match layout.output.as_deref() {
+                None => None,
+                Some(name) => match self.output_by_name(name) {
+                    Some(output) => Some(output),
+                    None => {
+                        let fallback = self.output_state.outputs().next();
+                        let fallback_name =
+                            fallback.as_ref().and_then(|output| self.output_name(output));
+
+                        log::warn!(
+                            "Output {name:?} of {:?} is gone, restoring it on {fallback_name:?}.",
+                            layout.name
+                        );
+                        restore.warnings.push(RestoreWarning::OutputGone {
+                            surface: layout.name.clone(),
+                            output: name.to_owned(),
+                            fallback: fallback_name,
+                        });
+
+                        fallback
+                    }
+                },
+            };
+
+            let failed = |error: &dyn std::error::Error| RestoreWarning::Failed {
+                surface: layout.name.clone(),
+                error: error.to_string(),
+            };
+
+            if self.surface_registry.contains(&layout.name) {
+                let taken = NameTaken(layout.name.clone());
+                restore.warnings.push(failed(&taken));
+                continue;
+            }
+
+            let registered = match AvyLayer::build(self, event_queue, layout.params(output)) {
+                Ok(registered) => registered,
+                Err(err) => {
+                    restore.warnings.push(failed(&err));
+                    continue;
+                }
+            };
+
+            let id = registered.id().clone();
+            let handle = match registered.make_backend(backend) {
+                Ok(handle) => handle,
+                Err(err) => {
+                    restore.warnings.push(failed(&err));
+                    continue;
+                }
+            };
+
+            // Checked above, and nothing else could take it since.
+            let _ = self.name_surface(&id, &layout.name);
+            handle.set_target_fps(layout.target_fps);
+
+            #[cfg(feature = "hyprland-surface")]
+            if let Some(opacity) = layout.opacity {
+                if let Err(err) = handle.hyprland().set_opacity(opacity) {
+                    log::warn!("Could not restore the opacity of {:?}: {err}", layout.name);
+                }
+            }
+
+            restore.surfaces.insert(layout.name.clone(), handle);
+        }
+
+        restore
+    }
+}
@@ -0,0 +1,353 @@
+//!
+//! The pointer's cursor over the client's surfaces: the compositor's arrow,
+//! a busy spinner whilst the shell is blocked on something, or an image of
+//! the app's own.
+//!
+//! The cursor is set on every pointer enter (it's only ours whilst the
+//! pointer is over one of our surfaces), so the state is kept on the client
+//! and reapplied with each enter's serial.
+//!
+
+use std::{f32::consts::TAU, time::Instant};
+
+use skia_safe::{
+    surfaces, AlphaType, Color, ColorType, Image, ImageInfo, Paint, PaintCap, PaintStyle, Rect,
+    SamplingOptions,
+};
+use smithay_client_toolkit::{
+    reexports::{
+        client::{
+            protocol::{wl_pointer::WlPointer, wl_shm, wl_surface::WlSurface},
+            Proxy, QueueHandle,
+        },
+        protocols::wp::cursor_shape::v1::client::wp_cursor_shape_device_v1::{
+            Shape, WpCursorShapeDeviceV1,
+        },
+    },
+    shm::slot::{Buffer, SlotPool},
+};
+use wayland_backend::client::ObjectId;
+
+use crate::{debug, AvyClient};
+
+///
+/// Logical size of the cursors drawn by the client (busy spinner included).
+///
+pub const CURSOR_SIZE: i32 = 24;
+
+///
+/// How long the busy spinner takes to go round once, in seconds.
+///
+const SPINNER_PERIOD: f32 = 1.0;
+
+///
+/// An image to use as the cursor, see [CursorState::Custom].
+///
+#[derive(Debug, Clone)]
+pub struct CustomCursor {
+    /// Drawn at [CURSOR_SIZE] logical pixels square.
+    pub image: Image,
+    /// The point (in logical pixels, from the top-left) which clicks land on.
+    pub hotspot: (i32, i32),
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum CursorState {
+    /// The compositor's default arrow.
+    #[default]
+    Default,
+    /// A spinning busy indicator, animated for as long as it's shown.
+    Busy,
+    Custom(CustomCursor),
+}
+
+///
+/// The surface a [CursorState::Busy] or [CursorState::Custom] cursor is drawn into.
+///
+struct CursorSurface {
+    wl_surface: WlSurface,
+    pool: SlotPool,
+    /// Attached to the surface, kept until the next one replaces it.
+    buffer: Option<Buffer>,
+    scale: i32,
+    /// Whether a frame callback is on its way, driving the spinner.
+    frame_requested: bool,
+}
+
+impl Drop for CursorSurface {
+    fn drop(&mut self) {
+        debug::objects::surface_destroyed(&self.wl_surface.id());
+        self.wl_surface.destroy();
+    }
+}
+
+#[derive(Default)]
+pub struct Cursor {
+    state: CursorState,
+    /// Set through `wp_cursor_shape_v1`, if the compositor supports it.
+    shape_device: Option<WpCursorShapeDeviceV1>,
+    surface: Option<CursorSurface>,
+    /// The pointer, and the serial of the enter, whilst it's over one of our surfaces.
+    entered: Option<(WlPointer, u32)>,
+    busy_since: Option<Instant>,
+}
+
+impl Cursor {
+    fn is_surface(&self, id: &ObjectId) -> bool {
+        self.surface
+            .as_ref()
+            .is_some_and(|surface| &surface.wl_surface.id() == id)
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        if let Some(device) = self.shape_device.take() {
+            device.destroy();
+        }
+    }
+}
+
+impl AvyClient {
+    ///
+    /// Set the cursor shown over all of the client's surfaces.
+    ///
+    /// Going back to [CursorState::Default] stops the spinner and
+    /// frees the memory the cursor was drawn into.
+    ///
+    pub fn set_cursor_state(&mut self, state: CursorState) {
+        if matches!(state, CursorState::Busy) {
+            // Keep spinning from where it was, if it already is.
+            let now = self.clock.now();
+            self.cursor.busy_since.get_or_insert(now);
+        } else {
+            self.cursor.busy_since.take();
+        }
+
+        if matches!(state, CursorState::Default) {
+            self.cursor.surface.take();
+        }
+
+        self.cursor.state = state;
+
+        if let Some((pointer, serial)) = self.cursor.entered.clone() {
+            self.apply_cursor(&pointer, serial);
+        }
+    }
+
+    pub fn cursor_state(&self) -> &CursorState {
+        &self.cursor.state
+    }
+
+    pub(crate) fn is_cursor_surface(&self, id: &ObjectId) -> bool {
+        self.cursor.is_surface(id)
+    }
+
+    pub(crate) fn cursor_pointer_added(&mut self, pointer: &WlPointer, qh: &QueueHandle<Self>) {
+        if let Some(manager) = &self.cursor_shape {
+            self.cursor
+                .shape_device
+                .replace(manager.get_shape_device(pointer, qh));
+        }
+    }
+
+    ///
+    /// The pointer entered one of our surfaces, with `serial`.
+    ///
+    pub(crate) fn cursor_entered(&mut self, pointer: &WlPointer, serial: u32) {
+        self.cursor.entered.replace((pointer.clone(), serial));
+        self.apply_cursor(pointer, serial);
+    }
+
+    pub(crate) fn cursor_left(&mut self) {
+        self.cursor.entered.take();
+
+        // Compositors may hold back frame callbacks whilst the cursor isn't
+        // shown, so ask again for the spinner once it's back.
+        if let Some(surface) = self.cursor.surface.as_mut() {
+            surface.frame_requested = false;
+        }
+    }
+
+    fn apply_cursor(&mut self, pointer: &WlPointer, serial: u32) {
+        let hotspot = match &self.cursor.state {
+            CursorState::Default => {
+                // Without the protocol, the compositor keeps whichever
+                // cursor was last shown, which is usually its own.
+                if let Some(device) = &self.cursor.shape_device {
+                    device.set_shape(serial, Shape::Default);
+                }
+
+                return;
+            }
+            CursorState::Busy => (CURSOR_SIZE / 2, CURSOR_SIZE / 2),
+            CursorState::Custom(custom) => custom.hotspot,
+        };
+
+        if self.cursor.surface.is_none() {
+            match self.create_cursor_surface() {
+                Some(surface) => {
+                    self.cursor.surface.replace(surface);
+                }
+                None => return,
+            }
+        }
+
+        self.draw_cursor();
+
+        if let Some(surface) = &self.cursor.surface {
+            pointer.set_cursor(serial, Some(&surface.wl_surface), hotspot.0, hotspot.1);
+        }
+    }
+
+    fn create_cursor_surface(&self) -> Option<CursorSurface> {
+        let scale = self
+            .output_state
+            .outputs()
+            .filter_map(|output| self.output_state.info(&output))
+            .map(|info| info.scale_factor)
+            .max()
+            .unwrap_or(1)
+            .max(1);
+
+        let side = (CURSOR_SIZE * scale) as usize;
+        // Room for two buffers, so that one can be drawn whilst the other is shown.
+        let pool = match SlotPool::new(side * side * 4 * 2, &self.shm_state) {
+            Ok(pool) => pool,
+            Err(err) => {
+                log::warn!("Could not allocate memory for the cursor: {err}");
+                return None;
+            }
+        };
+
+        let wl_surface = self.compositor_state.create_surface(&self.queue_handle);
+        wl_surface.set_buffer_scale(scale);
+
+        Some(CursorSurface {
+            wl_surface,
+            pool,
+            buffer: None,
+            scale,
+            frame_requested: false,
+        })
+    }
+
+    ///
+    /// Draw the cursor for the current state, and (for the
+    /// spinner) ask to be called back for the next frame.
+    ///
+    fn draw_cursor(&mut self) {
+        let busy_since = self.cursor.busy_since;
+        let now = self.clock.now();
+        let qh = self.queue_handle.clone();
+
+        let Some(surface) = self.cursor.surface.as_mut() else {
+            return;
+        };
+
+        let side = CURSOR_SIZE * surface.scale;
+        let (buffer, pixels) =
+            match surface
+                .pool
+                .create_buffer(side, side, side * 4, wl_shm::Format::Argb8888)
+            {
+                Ok(buffer) => buffer,
+                Err(err) => {
+                    log::warn!("Could not create a buffer for the cursor: {err}");
+                    return;
+                }
+            };
+
+        // Argb8888 is stored little-endian.
+        let info = ImageInfo::new((side, side), ColorType::BGRA8888, AlphaType::Premul, None);
+
+        let Some(mut skia) = surfaces::wrap_pixels(&info, pixels, side as usize * 4, None) else {
+            return;
+        };
+
+        let canvas = skia.canvas();
+        canvas.clear(Color::TRANSPARENT);
+        canvas.scale((surface.scale as f32, surface.scale as f32));
+
+        match &self.cursor.state {
+            CursorState::Default => return,
+            CursorState::Busy => {
+                let elapsed = busy_since.map_or(0.0, |since| {
+                    now.saturating_duration_since(since).as_secs_f32()
+                });
+                draw_spinner(canvas, elapsed);
+            }
+            CursorState::Custom(custom) => {
+                let size = CURSOR_SIZE as f32;
+                canvas.draw_image_rect_with_sampling_options(
+                    &custom.image,
+                    None,
+                    Rect::from_wh(size, size),
+                    SamplingOptions::default(),
+                    &Paint::default(),
+                );
+            }
+        }
+
+        drop(skia);
+
+        if let Err(err) = buffer.attach_to(&surface.wl_surface) {
+            log::warn!("Could not attach the cursor's buffer: {err}");
+            return;
+        }
+
+        surface.wl_surface.damage_buffer(0, 0, side, side);
+
+        if busy_since.is_some() && !surface.frame_requested {
+            let callback = surface.wl_surface.frame(&qh, surface.wl_surface.clone());
+            debug::objects::created(&callback.id(), Some(&surface.wl_surface.id()));
+            surface.frame_requested = true;
+        }
+
+        surface.wl_surface.commit();
+        surface.buffer.replace(buffer);
+    }
+
+    ///
+    /// A frame callback came in for `id`: if it's the cursor's,
+    /// draw the next frame of the spinner.
+    ///
+    /// Returns whether it was.
+    ///
+    pub(crate) fn cursor_frame(&mut self, id: &ObjectId) -> bool {
+        if !self.is_cursor_surface(id) {
+            return false;
+        }
+
+        if let Some(surface) = self.cursor.surface.as_mut() {
+            surface.frame_requested = false;
+        }
+
+        if matches!(self.cursor.state, CursorState::Busy) {
+            self.draw_cursor();
+        }
+
+        true
+    }
+}
+
+fn draw_spinner(canvas: &skia_safe::Canvas, elapsed: f32) {
+    let size = CURSOR_SIZE as f32;
+    let stroke = size / 8.0;
+    let bounds = Rect::from_wh(size, size).with_inset((stroke, stroke));
+
+    let mut paint = Paint::default();
+    paint
+        .set_anti_alias(true)
+        .set_style(PaintStyle::Stroke)
+        .set_stroke_width(stroke)
+        .set_stroke_cap(PaintCap::Round);
+
+    // A dark track with a light outline, to show up on any background.
+    paint.set_color(Color::from_argb(160, 0, 0, 0));
+    canvas.draw_oval(bounds, &paint);
+
+    let turn = (elapsed / SPINNER_PERIOD).fract();
+    paint.set_color(Color::WHITE);
+    canvas.draw_arc(bounds, turn * TAU.to_degrees(), 270.0, false, &paint);
+}
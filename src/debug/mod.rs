@@ -0,0 +1,513 @@
+//!
+//! Introspection, for working out what a (possibly hung) client is up to.
+//!
+
+pub mod objects;
+pub mod trace;
+
+use std::{
+    fmt::{self, Write},
+    sync::{atomic::Ordering, OnceLock},
+    time::Instant,
+};
+
+use smithay_client_toolkit::reexports::calloop::{
+    signals::{Signal, Signals},
+    LoopHandle,
+};
+use wayland_backend::client::ObjectId;
+
+use crate::{app::RenderFailure, util::SharedClock, AvyClient};
+
+///
+/// Set to `1` to print [AvyClient::dump_state] whenever the process receives `SIGUSR1`.
+///
+pub const DUMP_ON_SIGUSR1_ENV: &str = "AVY_DUMP_ON_SIGUSR1";
+
+///
+/// Set to `1` to print how long each stage of bringing up a graphics
+/// backend takes, up to its first presented frame (see [StartupTimer]).
+///
+pub const TIMING_ENV: &str = "AVY_TIMING";
+
+///
+/// Number of [RenderIncident]s kept per surface.
+///
+pub const INCIDENT_HISTORY: usize = 64;
+
+///
+/// Longest incident message kept, in bytes. Longer ones are cut short.
+///
+pub const INCIDENT_MESSAGE_LEN: usize = 160;
+
+///
+/// Reports startup stages to stderr, relative to when the timer was started.
+///
+/// Goes by the clock it was started on, which stages' start times
+/// must be read from too.
+///
+#[derive(Debug, Clone)]
+pub struct StartupTimer {
+    origin: Instant,
+    clock: SharedClock,
+}
+
+impl StartupTimer {
+    ///
+    /// A timer starting now on `clock`, if enabled through [TIMING_ENV].
+    ///
+    pub fn start(clock: &SharedClock) -> Option<Self> {
+        timing_enabled().then(|| Self {
+            origin: clock.now(),
+            clock: clock.clone(),
+        })
+    }
+
+    ///
+    /// Report that `stage`, begun at `started`, is done.
+    ///
+    pub fn stage(&self, stage: &str, started: Instant) {
+        let now = self.clock.now();
+        eprintln!(
+            "[Avy] [timing] {stage}: {:.1}ms (at {:.1}ms)",
+            now.saturating_duration_since(started).as_secs_f64() * 1000.0,
+            now.saturating_duration_since(self.origin).as_secs_f64() * 1000.0,
+        );
+    }
+}
+
+fn timing_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| std::env::var(TIMING_ENV).as_deref() == Ok("1"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncidentKind {
+    /// A frame failed to render.
+    Error,
+    /// A frame was presented after one or more errors.
+    Recovered,
+    Suspended,
+    Resumed,
+    /// The surface moved to a new graphics backend.
+    BackendRecreated,
+    /// Cached memory was given back, see [crate::memory].
+    Trimmed,
+}
+
+///
+/// Something worth knowing about which happened to a surface's rendering.
+///
+/// Incidents are plain values (the message lives inline), so recording
+/// one never allocates.
+///
+#[derive(Clone, Copy)]
+pub struct RenderIncident {
+    pub kind: IncidentKind,
+    /// The frame being drawn at the time, see [crate::graphics::GraphicsSurface::presented_frames].
+    pub frame: u64,
+    pub at: Instant,
+    message: [u8; INCIDENT_MESSAGE_LEN],
+    message_len: usize,
+}
+
+impl RenderIncident {
+    fn new(kind: IncidentKind, frame: u64, at: Instant, message: fmt::Arguments) -> Self {
+        let mut incident = Self {
+            kind,
+            frame,
+            at,
+            message: [0; INCIDENT_MESSAGE_LEN],
+            message_len: 0,
+        };
+
+        let _ = fmt::write(&mut incident, message);
+        incident
+    }
+
+    pub fn message(&self) -> &str {
+        // Only ever cut at character boundaries.
+        std::str::from_utf8(&self.message[..self.message_len]).unwrap_or_default()
+    }
+}
+
+///
+/// Fills the inline message, dropping whatever doesn't fit.
+///
+impl Write for RenderIncident {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let space = INCIDENT_MESSAGE_LEN - self.message_len;
+
+        let mut len = s.len().min(space);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.message[self.message_len..self.message_len + len]
+            .copy_from_slice(&s.as_bytes()[..len]);
+        self.message_len += len;
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for RenderIncident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenderIncident")
+            .field("kind", &self.kind)
+            .field("frame", &self.frame)
+            .field("at", &self.at)
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl fmt::Display for RenderIncident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "frame {}: {:?}", self.frame, self.kind)?;
+
+        if !self.message().is_empty() {
+            write!(f, ": {}", self.message())?;
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// The last [INCIDENT_HISTORY] incidents on a surface, oldest first.
+///
+/// Space for all of them is allocated up front.
+///
+pub struct IncidentLog {
+    incidents: Vec<RenderIncident>,
+    /// Where the next incident goes, once the log is full.
+    next: usize,
+}
+
+impl Default for IncidentLog {
+    fn default() -> Self {
+        Self {
+            incidents: Vec::with_capacity(INCIDENT_HISTORY),
+            next: 0,
+        }
+    }
+}
+
+impl IncidentLog {
+    pub fn push(&mut self, kind: IncidentKind, frame: u64, at: Instant, message: fmt::Arguments) {
+        let incident = RenderIncident::new(kind, frame, at, message);
+
+        if self.incidents.len() < INCIDENT_HISTORY {
+            self.incidents.push(incident);
+        } else {
+            self.incidents[self.next] = incident;
+        }
+
+        self.next = (self.next + 1) % INCIDENT_HISTORY;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RenderIncident> {
+        let (newer, older) = self.incidents.split_at(self.next.min(self.incidents.len()));
+
+        // Until the log wraps, `older` is empty.
+        older.iter().chain(newer)
+    }
+
+    pub fn latest(&self) -> Option<&RenderIncident> {
+        if self.incidents.is_empty() {
+            return None;
+        }
+
+        let len = self.incidents.len();
+        self.incidents.get((self.next + len - 1) % len)
+    }
+
+    pub fn len(&self) -> usize {
+        self.incidents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.incidents.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.incidents.clear();
+        self.next = 0;
+    }
+}
+
+///
+/// A snapshot of one registered surface.
+///
+#[derive(Debug, Clone)]
+pub struct SurfaceInfo {
+    pub id: ObjectId,
+    pub name: Option<String>,
+    /// Concrete type of the [crate::wayland::surface::AvySurface].
+    pub kind: &'static str,
+    pub logical_size: (u32, u32),
+    pub physical_size: (f64, f64),
+    pub scale: f64,
+    /// Fraction of the scale rendered at, see [crate::app::AvySurfaceHandle::set_adaptive_quality].
+    pub quality: f64,
+    /// Of the output the surface is drawn in step with, in Hz.
+    pub refresh_rate: Option<f32>,
+    pub visible: bool,
+    pub suspended: bool,
+    /// See [crate::graphics::GraphicsSurface::backend_name].
+    pub backend: Option<&'static str>,
+    /// Number of the last presented frame, and when it was presented.
+    pub last_presented: Option<(u64, Instant)>,
+    pub last_error: Option<RenderFailure>,
+    pub latest_incident: Option<RenderIncident>,
+}
+
+///
+/// How rendering is going on every surface, see [AvyClient::health_report].
+///
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    pub surfaces: Vec<SurfaceHealth>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SurfaceHealth {
+    pub id: ObjectId,
+    pub name: Option<String>,
+    /// Number of the last presented frame, and when it was presented.
+    pub last_presented: Option<(u64, Instant)>,
+    pub last_error: Option<RenderFailure>,
+    /// Oldest first.
+    pub incidents: Vec<RenderIncident>,
+}
+
+impl HealthReport {
+    ///
+    /// Whether every surface presented its last frame successfully.
+    ///
+    pub fn is_healthy(&self) -> bool {
+        self.surfaces
+            .iter()
+            .all(|surface| surface.last_error.is_none())
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for surface in &self.surfaces {
+            match &surface.name {
+                Some(name) => writeln!(f, "{name} ({}):", surface.id)?,
+                None => writeln!(f, "{}:", surface.id)?,
+            }
+
+            if let Some(error) = &surface.last_error {
+                writeln!(f, "  failing: {error}")?;
+            }
+
+            for incident in &surface.incidents {
+                writeln!(f, "  {incident}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl AvyClient {
+    ///
+    /// The name a surface was registered under, if any.
+    ///
+    pub fn surface_name(&self, id: &ObjectId) -> Option<&str> {
+        self.surface_names.get(id).map(String::as_str)
+    }
+
+    pub fn surface_info(&self, id: &ObjectId) -> Option<SurfaceInfo> {
+        let surface = self.surfaces.get(id)?;
+        let size = surface.size_ref();
+        let shared = self.surface_shared.get(id);
+
+        let backend = self.surface_backends.get(id).map(|backend| {
+            // Don't get stuck behind a render thread holding the lock.
+            backend
+                .try_lock()
+                .map(|backend| (backend.backend_name(), backend.is_suspended()))
+                .unwrap_or(("<locked>", false))
+        });
+
+        Some(SurfaceInfo {
+            id: id.clone(),
+            name: self.surface_names.get(id).cloned(),
+            kind: surface.type_name(),
+            logical_size: size.logical_size(),
+            physical_size: size.physical_size(),
+            scale: size.scale_factor(),
+            quality: size.quality(),
+            refresh_rate: shared.and_then(|shared| shared.refresh_rate()),
+            visible: shared.map(|shared| shared.is_visible()).unwrap_or(true),
+            suspended: backend.map(|(_, suspended)| suspended).unwrap_or(false)
+                || shared
+                    .map(|shared| shared.auto_suspended.load(Ordering::Acquire))
+                    .unwrap_or(false),
+            backend: backend.map(|(name, _)| name),
+            last_presented: shared.and_then(|shared| *shared.last_presented.lock().unwrap()),
+            last_error: shared.and_then(|shared| shared.last_error.lock().unwrap().clone()),
+            latest_incident: shared
+                .and_then(|shared| shared.incidents.lock().unwrap().latest().copied()),
+        })
+    }
+
+    ///
+    /// Recent errors and other incidents on every surface with a backend.
+    ///
+    pub fn health_report(&self) -> HealthReport {
+        let mut ids = self.surface_shared.keys().collect::<Vec<_>>();
+        ids.sort_by_key(|id| id.protocol_id());
+
+        let surfaces = ids
+            .into_iter()
+            .map(|id| {
+                let shared = &self.surface_shared[id];
+
+                SurfaceHealth {
+                    id: id.clone(),
+                    name: self.surface_names.get(id).cloned(),
+                    last_presented: *shared.last_presented.lock().unwrap(),
+                    last_error: shared.last_error.lock().unwrap().clone(),
+                    incidents: shared.incidents.lock().unwrap().iter().copied().collect(),
+                }
+            })
+            .collect();
+
+        HealthReport { surfaces }
+    }
+
+    ///
+    /// Human-readable description of every registered surface,
+    /// and where input is currently going.
+    ///
+    pub fn dump_state(&self) -> String {
+        let mut out = String::new();
+        let now = self.clock.now();
+        let label = |id: &ObjectId| match self.surface_name(id) {
+            Some(name) => format!("{name} ({id})"),
+            None => id.to_string(),
+        };
+
+        let _ = writeln!(out, "Avy client, {} surface(s):", self.surfaces.len());
+
+        let mut ids = self.surfaces.keys().collect::<Vec<_>>();
+        ids.sort_by_key(|id| id.protocol_id());
+
+        for info in ids.into_iter().filter_map(|id| self.surface_info(id)) {
+            let _ = writeln!(out, "  {}", label(&info.id));
+            let _ = writeln!(out, "    type:     {}", info.kind);
+            let _ = writeln!(
+                out,
+                "    size:     {}x{} logical, {}x{} physical @ {:.3}",
+                info.logical_size.0,
+                info.logical_size.1,
+                info.physical_size.0,
+                info.physical_size.1,
+                info.scale
+            );
+            if info.quality < 1.0 {
+                let _ = writeln!(out, "    quality:  {:.0}%", info.quality * 100.0);
+            }
+            if let Some(refresh_rate) = info.refresh_rate {
+                let _ = writeln!(out, "    refresh:  {refresh_rate:.2} Hz");
+            }
+            let _ = writeln!(
+                out,
+                "    state:    {}{}",
+                if info.visible { "visible" } else { "hidden" },
+                if info.suspended { ", suspended" } else { "" }
+            );
+            let _ = writeln!(out, "    backend:  {}", info.backend.unwrap_or("<none>"));
+
+            match info.last_presented {
+                Some((frame, at)) => {
+                    let _ = writeln!(
+                        out,
+                        "    rendered: frame {frame}, {:.1?} ago",
+                        now.saturating_duration_since(at)
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "    rendered: never");
+                }
+            }
+
+            if let Some(error) = &info.last_error {
+                let _ = writeln!(out, "    error:    {error}");
+            }
+
+            if let Some(incident) = &info.latest_incident {
+                let _ = writeln!(
+                    out,
+                    "    incident: {incident}, {:.1?} ago",
+                    now.saturating_duration_since(incident.at)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "Keyboard focus: {}",
+            self.keyboard_focus
+                .as_ref()
+                .map(label)
+                .unwrap_or_else(|| "<none>".to_owned())
+        );
+
+        let _ = writeln!(out, "Active touches: {}", self.active_touches.len());
+        let mut touches = self.active_touches.iter().collect::<Vec<_>>();
+        touches.sort_by_key(|(touch, _)| **touch);
+        for (touch, id) in touches {
+            let _ = writeln!(out, "  #{touch} -> {}", label(id));
+        }
+
+        out
+    }
+
+    ///
+    /// Print [AvyClient::dump_state] to stderr on `SIGUSR1`,
+    /// if enabled through [DUMP_ON_SIGUSR1_ENV].
+    ///
+    pub(crate) fn install_dump_signal(handle: &LoopHandle<'static, AvyClient>) {
+        if std::env::var(DUMP_ON_SIGUSR1_ENV).as_deref() != Ok("1") {
+            return;
+        }
+
+        let signals = match Signals::new(&[Signal::SIGUSR1]) {
+            Ok(signals) => signals,
+            Err(err) => {
+                log::warn!("Could not listen for SIGUSR1: {err}");
+                return;
+            }
+        };
+
+        if let Err(err) = handle.insert_source(signals, |_, _, app| {
+            eprintln!("{}", app.dump_state());
+        }) {
+            log::warn!("Could not listen for SIGUSR1: {err}");
+        }
+    }
+}
+
+impl fmt::Debug for AvyClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let surfaces = self
+            .surfaces
+            .keys()
+            .filter_map(|id| self.surface_info(id))
+            .collect::<Vec<_>>();
+
+        f.debug_struct("AvyClient")
+            .field("surfaces", &surfaces)
+            .field("keyboard_focus", &self.keyboard_focus)
+            .field("active_touches", &self.active_touches)
+            .field("running", &self.running)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,170 @@
+//!
+//! Leak detection for the protocol objects the crate makes on behalf of
+//! surfaces: viewports, fractional scales, frame callbacks, input regions
+//! and selection sources.
+//!
+//! With the `object-tracker` feature, each is recorded as it's made (with a
+//! backtrace, if `RUST_BACKTRACE` is set) until it's destroyed, see
+//! [AvyClient::object_report]. Destroying a surface, and [AvyClient::shutdown],
+//! check (in debug builds) that nothing made for it is left. Without the
+//! feature, recording does nothing.
+//!
+
+#[cfg(feature = "object-tracker")]
+use std::{
+    backtrace::Backtrace,
+    collections::BTreeMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use wayland_backend::client::ObjectId;
+
+#[cfg(feature = "object-tracker")]
+use crate::AvyClient;
+
+///
+/// A protocol object made by the crate, and not yet destroyed.
+///
+#[cfg(feature = "object-tracker")]
+#[derive(Debug, Clone)]
+pub struct LiveObject {
+    pub id: ObjectId,
+    pub interface: &'static str,
+    /// The surface it was made for, if any.
+    pub surface: Option<ObjectId>,
+    pub backtrace: Arc<Backtrace>,
+}
+
+///
+/// Live protocol objects, see [AvyClient::object_report].
+///
+#[cfg(feature = "object-tracker")]
+#[derive(Debug, Clone, Default)]
+pub struct ObjectReport {
+    /// Live objects, by interface.
+    pub counts: BTreeMap<&'static str, usize>,
+    /// Live objects whose surface is gone: leaks.
+    pub orphans: Vec<LiveObject>,
+}
+
+#[cfg(feature = "object-tracker")]
+impl std::fmt::Display for ObjectReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (interface, count) in &self.counts {
+            writeln!(f, "{interface}: {count}")?;
+        }
+
+        for orphan in &self.orphans {
+            writeln!(f, "orphaned {} {}, made at:", orphan.interface, orphan.id)?;
+            writeln!(f, "{}", orphan.backtrace)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "object-tracker")]
+fn live() -> &'static Mutex<Vec<LiveObject>> {
+    static LIVE: OnceLock<Mutex<Vec<LiveObject>>> = OnceLock::new();
+    LIVE.get_or_init(Default::default)
+}
+
+///
+/// Record `object`, made for `surface` (if any).
+///
+#[allow(unused_variables)]
+pub(crate) fn created(object: &ObjectId, surface: Option<&ObjectId>) {
+    #[cfg(feature = "object-tracker")]
+    live().lock().unwrap().push(LiveObject {
+        id: object.clone(),
+        interface: object.interface().name,
+        surface: surface.cloned(),
+        backtrace: Arc::new(Backtrace::capture()),
+    });
+}
+
+///
+/// Forget `object`, now that it's destroyed.
+///
+#[allow(unused_variables)]
+pub(crate) fn destroyed(object: &ObjectId) {
+    #[cfg(feature = "object-tracker")]
+    live().lock().unwrap().retain(|live| &live.id != object);
+}
+
+///
+/// Forget the oldest frame callback of `surface`, which just fired
+/// (and so was destroyed by the compositor).
+///
+#[allow(unused_variables)]
+pub(crate) fn callback_done(surface: &ObjectId) {
+    #[cfg(feature = "object-tracker")]
+    {
+        let mut live = live().lock().unwrap();
+        if let Some(index) = live.iter().position(|live| {
+            live.interface == "wl_callback" && live.surface.as_ref() == Some(surface)
+        }) {
+            live.remove(index);
+        }
+    }
+}
+
+///
+/// Check (in debug builds) that nothing made for `surface` outlived it.
+///
+/// Frame callbacks which hadn't fired go with the surface.
+///
+#[allow(unused_variables)]
+pub(crate) fn surface_destroyed(surface: &ObjectId) {
+    #[cfg(feature = "object-tracker")]
+    {
+        let mut live = live().lock().unwrap();
+        live.retain(|live| {
+            live.interface != "wl_callback" || live.surface.as_ref() != Some(surface)
+        });
+
+        let leaked: Vec<_> = live
+            .iter()
+            .filter(|live| live.surface.as_ref() == Some(surface))
+            .map(|live| live.interface)
+            .collect();
+
+        debug_assert!(leaked.is_empty(), "{surface} was destroyed, leaking {leaked:?}");
+    }
+}
+
+#[cfg(feature = "object-tracker")]
+impl AvyClient {
+    ///
+    /// Live counts of the protocol objects the crate made, by interface, and
+    /// those whose surface is gone.
+    ///
+    pub fn object_report(&self) -> ObjectReport {
+        let connection = self.connection();
+        let live = live().lock().unwrap();
+
+        let mut report = ObjectReport::default();
+        for object in live.iter() {
+            *report.counts.entry(object.interface).or_default() += 1;
+
+            let orphaned = object
+                .surface
+                .as_ref()
+                .is_some_and(|surface| connection.object_info(surface.clone()).is_err());
+
+            if orphaned {
+                report.orphans.push(object.clone());
+            }
+        }
+
+        report
+    }
+
+    ///
+    /// Check (in debug builds) that nothing outlived the surface it was made for.
+    ///
+    pub(crate) fn assert_no_orphans(&self) {
+        let report = self.object_report();
+        debug_assert!(report.orphans.is_empty(), "Leaked protocol objects:\n{report}");
+    }
+}
@@ -0,0 +1,319 @@
+//!
+//! Capturing Vulkan validation output, swapchain events and Wayland events
+//! into a single file, for attaching to bug reports:
+//!
+//! ```sh
+//! AVY_VK_TRACE=/tmp/avy.log my-shell
+//! ```
+//!
+//! Each line is stamped with the surface and frame being drawn on the thread
+//! which logged it, so validation errors line up with the frames they hit.
+//!
+
+use std::{
+    cell::RefCell,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use wayland_backend::client::ObjectId;
+
+use crate::util::SharedClock;
+
+///
+/// Set to a file path to enable validation layers (if installed), and trace
+/// into that file.
+///
+pub const VK_TRACE_ENV: &str = "AVY_VK_TRACE";
+
+///
+/// Log target of the Vulkan backend (swapchain events, device selection, ...).
+///
+pub const VULKAN_TARGET: &str = "avy::vulkan";
+
+///
+/// Log target of Wayland events (configures, scale changes, ...).
+///
+pub const WAYLAND_TARGET: &str = "avy::wayland";
+
+///
+/// Once the trace grows past this, it's moved to `<path>.1` (replacing any
+/// older one) and started over.
+///
+pub const MAX_TRACE_BYTES: u64 = 32 * 1024 * 1024;
+
+static TRACE: OnceLock<Option<Mutex<TraceFile>>> = OnceLock::new();
+
+thread_local! {
+    /// The surface, and frame of it, being drawn on this thread.
+    static CURRENT_FRAME: RefCell<Option<(ObjectId, u64)>> = const { RefCell::new(None) };
+}
+
+struct TraceFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    written: u64,
+    started: Instant,
+    /// What lines are stamped by, relative to `started`.
+    clock: SharedClock,
+    /// Header lines describing the devices in use, repeated after rotating.
+    devices: Vec<String>,
+}
+
+impl TraceFile {
+    fn open(path: PathBuf, clock: SharedClock) -> io::Result<Self> {
+        let mut trace = Self {
+            writer: BufWriter::new(File::create(&path)?),
+            path,
+            written: 0,
+            started: clock.now(),
+            clock,
+            devices: Vec::new(),
+        };
+
+        trace.write_header()?;
+        Ok(trace)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut header = format!(
+            "# {} {} trace, started at {} (UNIX time)\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            since_epoch.as_secs(),
+        );
+
+        for device in &self.devices {
+            header.push_str("# device: ");
+            header.push_str(device);
+            header.push('\n');
+        }
+
+        self.write(header.as_bytes())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn write_line(&mut self, target: &str, level: &str, message: fmt::Arguments) -> io::Result<()> {
+        let elapsed = self
+            .clock
+            .now()
+            .saturating_duration_since(self.started)
+            .as_secs_f64();
+        let frame = CURRENT_FRAME.with_borrow(|frame| match frame {
+            Some((surface, frame)) => format!("{surface} #{frame}"),
+            None => "-".to_string(),
+        });
+
+        let line = format!("{elapsed:>10.4} [{frame}] [{target}] [{level}] {message}\n");
+        self.write(line.as_bytes())?;
+
+        if self.written > MAX_TRACE_BYTES {
+            self.rotate()?;
+        }
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+
+        self.writer = BufWriter::new(File::create(&self.path)?);
+        self.written = 0;
+        self.write_header()
+    }
+}
+
+///
+/// Start tracing into the file named by [VK_TRACE_ENV], if set, stamping lines
+/// with the time on `clock`. Only the first call does anything;
+/// [crate::graphics::vulkan::Vulkan::new] makes it.
+///
+/// Also installs [TraceLogger] as the global logger (when the application hasn't
+/// installed one of its own, see [TraceLogger::wrap]), and a panic hook which
+/// flushes the trace.
+///
+pub fn init_from_env(clock: &SharedClock) -> bool {
+    TRACE
+        .get_or_init(|| {
+            let path = std::env::var_os(VK_TRACE_ENV).filter(|path| !path.is_empty())?;
+            start(Path::new(&path), clock.clone())
+        })
+        .is_some()
+}
+
+fn start(path: &Path, clock: SharedClock) -> Option<Mutex<TraceFile>> {
+    let trace = match TraceFile::open(path.to_path_buf(), clock) {
+        Ok(trace) => trace,
+        Err(err) => {
+            eprintln!("[Avy] Could not open {}: {err}", path.display());
+            return None;
+        }
+    };
+
+    if log::set_logger(Box::leak(Box::new(TraceLogger::default()))).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // The panic may have happened whilst writing, so don't wait on the trace.
+        if let Some(Ok(mut trace)) = TRACE.get().and_then(Option::as_ref).map(Mutex::try_lock) {
+            let _ = trace.write_line("panic", "ERROR", format_args!("{info}"));
+            let _ = trace.writer.flush();
+        }
+
+        previous(info)
+    }));
+
+    Some(Mutex::new(trace))
+}
+
+///
+/// Whether a trace is being written, see [init_from_env].
+///
+pub fn is_enabled() -> bool {
+    TRACE.get().is_some_and(Option::is_some)
+}
+
+///
+/// Write a line to the trace, if enabled.
+///
+pub fn write(target: &str, level: &str, message: fmt::Arguments) {
+    let Some(trace) = TRACE.get().and_then(Option::as_ref) else {
+        return;
+    };
+
+    let mut trace = trace
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Err(err) = trace.write_line(target, level, message) {
+        eprintln!("[Avy] Could not write to the trace: {err}");
+    }
+}
+
+///
+/// Describe a device in use in the trace's header (e.g. its name and driver version).
+///
+pub fn record_device(description: String) {
+    let Some(trace) = TRACE.get().and_then(Option::as_ref) else {
+        return;
+    };
+
+    let mut trace = trace
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if trace.devices.contains(&description) {
+        return;
+    }
+
+    let line = format!("# device: {description}\n");
+    trace.devices.push(description);
+
+    if let Err(err) = trace.write(line.as_bytes()) {
+        eprintln!("[Avy] Could not write to the trace: {err}");
+    }
+}
+
+///
+/// Flush what's been traced so far out to the file.
+///
+pub fn flush() {
+    if let Some(trace) = TRACE.get().and_then(Option::as_ref) {
+        let _ = trace
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .writer
+            .flush();
+    }
+}
+
+///
+/// Stamps what's traced on this thread with `frame` of `surface`, until dropped.
+///
+pub(crate) struct FrameScope {
+    previous: Option<(ObjectId, u64)>,
+}
+
+impl FrameScope {
+    pub(crate) fn enter(surface: &ObjectId, frame: u64) -> Self {
+        let previous = CURRENT_FRAME.replace(Some((surface.clone(), frame)));
+        Self { previous }
+    }
+}
+
+impl Drop for FrameScope {
+    fn drop(&mut self) {
+        CURRENT_FRAME.set(self.previous.take());
+    }
+}
+
+///
+/// Captures [VULKAN_TARGET] and [WAYLAND_TARGET] log records into the trace,
+/// passing every record on to the wrapped logger (if any).
+///
+#[derive(Default)]
+pub struct TraceLogger {
+    inner: Option<Box<dyn Log>>,
+}
+
+impl TraceLogger {
+    ///
+    /// For applications with a logger of their own: install this in its place
+    /// (with `log::set_boxed_logger`), before creating the graphics backend.
+    ///
+    pub fn wrap(inner: Box<dyn Log>) -> Self {
+        Self { inner: Some(inner) }
+    }
+
+    fn is_traced(target: &str) -> bool {
+        [VULKAN_TARGET, WAYLAND_TARGET]
+            .iter()
+            .any(|traced| target.starts_with(traced))
+    }
+}
+
+impl Log for TraceLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        (is_enabled() && Self::is_traced(metadata.target()))
+            || self
+                .inner
+                .as_ref()
+                .is_some_and(|inner| inner.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if is_enabled() && Self::is_traced(record.target()) {
+            write(record.target(), record.level().as_str(), *record.args());
+        }
+
+        if let Some(inner) = &self.inner {
+            inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        flush();
+
+        if let Some(inner) = &self.inner {
+            inner.flush();
+        }
+    }
+}
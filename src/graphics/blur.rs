@@ -0,0 +1,368 @@
+//!
+//! Blurring what's already been drawn into a surface, for frosted panels
+//! without a compositor blur protocol, see [super::RenderContext::blur_region].
+//!
+//! Rather than blurring the region at full resolution, it's copied out at a
+//! fraction of it (as little as a blur that wide still hides), blurred there,
+//! and scaled back up over itself. The offscreen surfaces it goes through are
+//! kept from frame to frame, as is the blurred result, which is reused in the
+//! next frame as long as its damage (see [super::FrameOptions::damage]) leaves
+//! it be.
+//!
+//! Skia's GPU surfaces can't be shared between threads, so each thread keeps
+//! the blurs it made (see [BLURS]), and only ever touches those, from render
+//! callbacks, with the device lock held (see [super::DeviceLock]).
+//!
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+};
+
+use skia_safe::{
+    canvas::SaveLayerRec, image_filters, BlendMode, Canvas, FilterMode, IRect, Image, MipmapMode,
+    Paint, Rect, SamplingOptions, Surface, TileMode,
+};
+
+///
+/// Blur sigma (in physical pixels) the region is scaled down to, at most.
+///
+const DOWNSCALED_SIGMA: f32 = 4.0;
+
+///
+/// Most the region is scaled down by: past that, too many of its pixels are
+/// skipped over, and it shimmers as what's behind moves.
+///
+const MAX_DOWNSCALE: f32 = 4.0;
+
+///
+/// How far beyond the region what's behind is blurred in, in sigmas.
+///
+const SAMPLED_SIGMAS: f32 = 3.0;
+
+///
+/// Frames a blur's surfaces are kept for without being drawn again.
+///
+const KEPT_FRAMES: u64 = 2;
+
+///
+/// The area read back (in physical pixels), the blur sigma's bits, and
+/// whether it's on the GPU, as surfaces aren't shared between the two.
+///
+type BlurKey = (i32, i32, i32, i32, u32, bool);
+
+///
+/// Where a region is blurred, and the last blur of it.
+///
+struct Blurred {
+    /// The region as read back, scaled down.
+    copy: Surface,
+    /// `copy`, blurred.
+    blurred: Surface,
+    image: Option<Image>,
+    /// The last frame it was drawn in.
+    used: u64,
+}
+
+///
+/// One cache's blurs, made on the thread they're kept on.
+///
+struct ThreadBlurs {
+    cache: Weak<CacheState>,
+    /// The cache's generation they were made in, see [BlurCache::clear].
+    generation: u64,
+    blurs: HashMap<BlurKey, Blurred>,
+}
+
+thread_local! {
+    ///
+    /// The blurs made on this thread, by cache. Those of caches which were
+    /// dropped or cleared since go the next time the thread blurs, as it
+    /// holds the device lock then, or with the thread.
+    ///
+    static BLURS: RefCell<Vec<ThreadBlurs>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// Goes up with every [BlurCache::clear].
+    generation: AtomicU64,
+}
+
+///
+/// A surface's blurred regions, kept from frame to frame (on the
+/// threads which drew them, see [BLURS]).
+///
+#[derive(Clone, Default)]
+pub(crate) struct BlurCache(Arc<CacheState>);
+
+impl fmt::Debug for BlurCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BlurCache")
+            .field(&self.0.generation.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+///
+/// How [BlurCache::blur_region] may read back what's been drawn.
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlurFrame {
+    pub frame: u64,
+    /// See [super::FrameOptions::damage].
+    pub damage: Option<Vec<Rect>>,
+    /// Whether drawing goes into a layer (e.g. for the surface's post
+    /// filter), rather than the surface itself.
+    pub layered: bool,
+}
+
+impl BlurCache {
+    ///
+    /// Drop every blur, e.g. as the GPU resources behind them go. They're
+    /// never drawn again, and freed by the threads which made them, see [BLURS].
+    ///
+    pub(crate) fn clear(&self) {
+        self.0.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    ///
+    /// Run `f` with the blurs this thread made for the cache, after dropping
+    /// any the thread kept for caches which were dropped or cleared since.
+    ///
+    fn with_blurs<R>(&self, f: impl FnOnce(&mut HashMap<BlurKey, Blurred>) -> R) -> R {
+        BLURS.with_borrow_mut(|caches| {
+            caches.retain(|blurs| {
+                blurs.cache.upgrade().is_some_and(|cache| {
+                    blurs.generation == cache.generation.load(Ordering::Acquire)
+                })
+            });
+
+            let index = match caches
+                .iter()
+                .position(|blurs| blurs.cache.as_ptr() == Arc::as_ptr(&self.0))
+            {
+                Some(index) => index,
+                None => {
+                    caches.push(ThreadBlurs {
+                        cache: Arc::downgrade(&self.0),
+                        generation: self.0.generation.load(Ordering::Acquire),
+                        blurs: HashMap::new(),
+                    });
+                    caches.len() - 1
+                }
+            };
+
+            f(&mut caches[index].blurs)
+        })
+    }
+
+    ///
+    /// See [super::RenderContext::blur_region].
+    ///
+    pub(crate) fn blur_region(&self, canvas: &Canvas, rect: Rect, sigma: f32, frame: &BlurFrame) {
+        if sigma <= 0.0 || rect.is_empty() {
+            return;
+        }
+
+        let matrix = canvas.local_to_device_as_3x3();
+        let Some(device_sigma) = matrix.map_radius(sigma) else {
+            return blur_backdrop(canvas, rect, sigma);
+        };
+
+        // SAFETY: The surface is only read from, and dropped before the canvas.
+        let surface = match frame.layered {
+            true => None,
+            false => unsafe { canvas.surface() },
+        };
+        // Layers (or recordings) can't be read back: Skia blurs those as a
+        // backdrop, at full resolution.
+        let Some(mut surface) = surface else {
+            return blur_backdrop(canvas, rect, sigma);
+        };
+
+        let margin = (device_sigma * SAMPLED_SIGMAS).ceil() as i32;
+        let area = matrix
+            .map_rect(rect)
+            .0
+            .round_out()
+            .with_outset((margin, margin));
+        let bounds = IRect::from_wh(surface.width(), surface.height());
+        let Some(area) = IRect::intersect(&area, &bounds) else {
+            return;
+        };
+
+        let on_gpu = canvas.recording_context().is_some();
+        let key = (
+            area.left,
+            area.top,
+            area.right,
+            area.bottom,
+            device_sigma.to_bits(),
+            on_gpu,
+        );
+
+        // What's behind is only known not to have changed if the frame says where it did.
+        let sampled = rect.with_outset((sigma * SAMPLED_SIGMAS, sigma * SAMPLED_SIGMAS));
+        let unchanged = frame
+            .damage
+            .as_ref()
+            .is_some_and(|damage| !damage.iter().any(|rect| rect.intersects(sampled)));
+
+        let image = self.with_blurs(|blurs| {
+            blurs.retain(|_, blurred| frame.frame.saturating_sub(blurred.used) <= KEPT_FRAMES);
+
+            if !blurs.contains_key(&key) {
+                let downscale = (device_sigma / DOWNSCALED_SIGMA).clamp(1.0, MAX_DOWNSCALE);
+                let size = (
+                    ((area.width() as f32 / downscale).ceil() as i32).max(1),
+                    ((area.height() as f32 / downscale).ceil() as i32).max(1),
+                );
+
+                let info = surface.image_info().with_dimensions(size);
+                let (Some(copy), Some(blurred)) = (
+                    canvas.new_surface(&info, None),
+                    canvas.new_surface(&info, None),
+                ) else {
+                    return Err(());
+                };
+
+                blurs.insert(
+                    key,
+                    Blurred {
+                        copy,
+                        blurred,
+                        image: None,
+                        used: 0,
+                    },
+                );
+            }
+
+            let blurred = blurs.get_mut(&key).unwrap();
+            let reused = blurred.image.is_some() && reusable(blurred.used, frame.frame, unchanged);
+            blurred.used = frame.frame;
+            if !reused {
+                blurred.image = surface
+                    .image_snapshot_with_bounds(area)
+                    .and_then(|behind| blur(blurred, &behind, device_sigma));
+            }
+
+            Ok(blurred.image.clone())
+        });
+
+        let image = match image {
+            Ok(Some(image)) => image,
+            Ok(None) => return,
+            Err(()) => return blur_backdrop(canvas, rect, sigma),
+        };
+
+        let mut paint = Paint::default();
+        paint.set_blend_mode(BlendMode::Src);
+
+        canvas.save();
+        canvas.clip_rect(rect, None, true);
+        canvas.reset_matrix();
+        canvas.draw_image_rect_with_sampling_options(
+            &image,
+            None,
+            Rect::from(area),
+            SamplingOptions::new(FilterMode::Linear, MipmapMode::None),
+            &paint,
+        );
+        canvas.restore();
+    }
+}
+
+///
+/// Whether the blur of a region last drawn in frame `used` still holds in
+/// `frame`, whose damage leaves what's behind the region `unchanged`.
+///
+/// Only if it was drawn in the frame before: the damage of any frame in
+/// between (which didn't draw it) wasn't checked.
+///
+fn reusable(used: u64, frame: u64, unchanged: bool) -> bool {
+    unchanged && used + 1 == frame
+}
+
+///
+/// Scale `behind` down into `blurred`'s copy, and blur that at `sigma`
+/// (in `behind`'s pixels).
+///
+fn blur(blurred: &mut Blurred, behind: &Image, sigma: f32) -> Option<Image> {
+    let (width, height) = (blurred.copy.width(), blurred.copy.height());
+    let scale = width as f32 / behind.width() as f32;
+
+    let canvas = blurred.copy.canvas();
+    canvas.draw_image_rect_with_sampling_options(
+        behind,
+        None,
+        Rect::from_iwh(width, height),
+        SamplingOptions::new(FilterMode::Linear, MipmapMode::None),
+        Paint::default().set_blend_mode(BlendMode::Src),
+    );
+    let copy = blurred.copy.image_snapshot();
+
+    let sigma = sigma * scale;
+    let filter = image_filters::blur((sigma, sigma), TileMode::Clamp, None, None)?;
+    let mut paint = Paint::default();
+    paint.set_image_filter(filter);
+
+    let canvas = blurred.blurred.canvas();
+    canvas.clear(skia_safe::Color::TRANSPARENT);
+    canvas.draw_image(&copy, (0, 0), Some(&paint));
+
+    Some(blurred.blurred.image_snapshot())
+}
+
+///
+/// Blur what's behind `rect` through a layer's backdrop, for canvases which can't be read back.
+///
+fn blur_backdrop(canvas: &Canvas, rect: Rect, sigma: f32) {
+    let Some(filter) = image_filters::blur((sigma, sigma), TileMode::Clamp, None, None) else {
+        return;
+    };
+
+    canvas.save();
+    canvas.clip_rect(rect, None, true);
+    canvas.save_layer(&SaveLayerRec::default().bounds(&rect).backdrop(&filter));
+    canvas.restore();
+    canvas.restore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blurs_are_reused_for_the_next_frame_only() {
+        assert!(reusable(4, 5, true));
+        assert!(!reusable(4, 5, false));
+
+        // Damage in frame 5, which didn't draw the region, went unseen.
+        assert!(!reusable(4, 6, true));
+        // Drawn twice in the same frame (e.g. after it failed to present).
+        assert!(!reusable(5, 5, true));
+    }
+
+    #[test]
+    fn cleared_and_dropped_caches_let_go_of_their_blurs() {
+        let cache = BlurCache::default();
+        cache.with_blurs(|blurs| assert!(blurs.is_empty()));
+        let count = || BLURS.with_borrow(Vec::len);
+        assert_eq!(count(), 1);
+
+        cache.clear();
+        let other = BlurCache::default();
+        other.with_blurs(|_| ());
+        assert_eq!(count(), 1);
+
+        drop(other);
+        cache.with_blurs(|_| ());
+        assert_eq!(count(), 1);
+    }
+}
@@ -0,0 +1,375 @@
+//!
+//! Colors by what they're for, rather than what they are, so that
+//! themes and accessibility settings can swap them out consistently.
+//!
+
+use skia_safe::Color4f;
+
+use crate::theme::{AccessibilityOptions, Appearance};
+
+///
+/// What a color is used for.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SemanticRole {
+    /// Behind everything else.
+    Background,
+    /// Raised areas on top of the background (cards, popups...).
+    Surface,
+    Text,
+    /// Less important text, like hints and captions.
+    MutedText,
+    Accent,
+    /// Text and icons drawn on top of the accent color.
+    OnAccent,
+    Border,
+    Error,
+}
+
+impl SemanticRole {
+    ///
+    /// The role this one is drawn on top of, if it's a foreground role.
+    ///
+    pub fn background(&self) -> Option<SemanticRole> {
+        match self {
+            Self::Background | Self::Surface | Self::Accent => None,
+            Self::OnAccent => Some(Self::Accent),
+            Self::Text | Self::MutedText | Self::Border | Self::Error => Some(Self::Background),
+        }
+    }
+}
+
+///
+/// A color for every [SemanticRole].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub background: Color4f,
+    pub surface: Color4f,
+    pub text: Color4f,
+    pub muted_text: Color4f,
+    pub accent: Color4f,
+    pub on_accent: Color4f,
+    pub border: Color4f,
+    pub error: Color4f,
+}
+
+impl Palette {
+    pub const LIGHT: Self = Self {
+        background: Color4f::new(0.98, 0.98, 0.98, 1.0),
+        surface: Color4f::new(1.0, 1.0, 1.0, 1.0),
+        text: Color4f::new(0.1, 0.1, 0.1, 1.0),
+        muted_text: Color4f::new(0.4, 0.4, 0.4, 1.0),
+        accent: Color4f::new(0.21, 0.52, 0.89, 1.0),
+        on_accent: Color4f::new(1.0, 1.0, 1.0, 1.0),
+        border: Color4f::new(0.82, 0.82, 0.82, 1.0),
+        error: Color4f::new(0.75, 0.11, 0.16, 1.0),
+    };
+
+    pub const DARK: Self = Self {
+        background: Color4f::new(0.12, 0.12, 0.12, 1.0),
+        surface: Color4f::new(0.19, 0.19, 0.19, 1.0),
+        text: Color4f::new(0.95, 0.95, 0.95, 1.0),
+        muted_text: Color4f::new(0.65, 0.65, 0.65, 1.0),
+        accent: Color4f::new(0.21, 0.52, 0.89, 1.0),
+        on_accent: Color4f::new(1.0, 1.0, 1.0, 1.0),
+        border: Color4f::new(0.3, 0.3, 0.3, 1.0),
+        error: Color4f::new(1.0, 0.48, 0.39, 1.0),
+    };
+
+    ///
+    /// Pure black and white, with a yellow accent.
+    ///
+    pub const HIGH_CONTRAST: Self = Self {
+        background: Color4f::new(0.0, 0.0, 0.0, 1.0),
+        surface: Color4f::new(0.0, 0.0, 0.0, 1.0),
+        text: Color4f::new(1.0, 1.0, 1.0, 1.0),
+        muted_text: Color4f::new(1.0, 1.0, 1.0, 1.0),
+        accent: Color4f::new(1.0, 1.0, 0.0, 1.0),
+        on_accent: Color4f::new(0.0, 0.0, 0.0, 1.0),
+        border: Color4f::new(1.0, 1.0, 1.0, 1.0),
+        error: Color4f::new(1.0, 0.4, 0.4, 1.0),
+    };
+
+    ///
+    /// The palette matching the desktop's color scheme and accent.
+    ///
+    pub fn for_appearance(appearance: &Appearance) -> Self {
+        let mut palette = if appearance.color_scheme.is_dark() {
+            Self::DARK
+        } else {
+            Self::LIGHT
+        };
+
+        if let Some(accent) = appearance.accent {
+            palette.accent = accent;
+            palette.on_accent = best_contrast(accent);
+        }
+
+        palette
+    }
+
+    pub fn get(&self, role: SemanticRole) -> Color4f {
+        match role {
+            SemanticRole::Background => self.background,
+            SemanticRole::Surface => self.surface,
+            SemanticRole::Text => self.text,
+            SemanticRole::MutedText => self.muted_text,
+            SemanticRole::Accent => self.accent,
+            SemanticRole::OnAccent => self.on_accent,
+            SemanticRole::Border => self.border,
+            SemanticRole::Error => self.error,
+        }
+    }
+}
+
+///
+/// Resolves [SemanticRole]s (and explicitly chosen colors) to the colors
+/// to draw with, taking [AccessibilityOptions] into account:
+///
+/// * Normally, roles come from the appearance's palette and explicit colors are kept.
+/// * In high-contrast mode with a forced palette, every role (explicit color or not)
+///   comes from the forced palette.
+/// * In high-contrast mode without one, foreground colors which don't contrast enough
+///   with their background are pushed towards black or white until they do.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorResolver {
+    palette: Palette,
+    accessibility: AccessibilityOptions,
+}
+
+impl ColorResolver {
+    pub fn new(appearance: &Appearance, accessibility: AccessibilityOptions) -> Self {
+        let palette = match accessibility.forced_palette {
+            Some(forced) if accessibility.high_contrast => forced,
+            _ => Palette::for_appearance(appearance),
+        };
+
+        Self {
+            palette,
+            accessibility,
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn resolve(&self, role: SemanticRole) -> Color4f {
+        self.adjust(role, self.palette.get(role))
+    }
+
+    ///
+    /// The color to draw with, when `color` was asked for explicitly in `role`.
+    ///
+    pub fn resolve_explicit(&self, role: SemanticRole, color: Color4f) -> Color4f {
+        if self.is_forced() {
+            return self.palette.get(role);
+        }
+
+        self.adjust(role, color)
+    }
+
+    fn is_forced(&self) -> bool {
+        self.accessibility.high_contrast && self.accessibility.forced_palette.is_some()
+    }
+
+    fn adjust(&self, role: SemanticRole, color: Color4f) -> Color4f {
+        if !self.accessibility.high_contrast {
+            return color;
+        }
+
+        match role.background() {
+            Some(background) => ensure_contrast(
+                color,
+                self.palette.get(background),
+                self.accessibility.min_contrast_ratio,
+            ),
+            None => color,
+        }
+    }
+}
+
+///
+/// WCAG relative luminance of an sRGB color, from 0 (black) to 1 (white).
+///
+/// Alpha is ignored.
+///
+pub fn relative_luminance(color: Color4f) -> f32 {
+    let linear = |channel: f32| {
+        if channel <= 0.04045 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+}
+
+///
+/// WCAG contrast ratio between two colors, from 1 (none) to 21 (black on white).
+///
+pub fn contrast_ratio(a: Color4f, b: Color4f) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    let (lighter, darker) = if a > b { (a, b) } else { (b, a) };
+
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+///
+/// Black or white, whichever contrasts more with `background`.
+///
+pub fn best_contrast(background: Color4f) -> Color4f {
+    let black = Color4f::new(0.0, 0.0, 0.0, 1.0);
+    let white = Color4f::new(1.0, 1.0, 1.0, 1.0);
+
+    if contrast_ratio(black, background) >= contrast_ratio(white, background) {
+        black
+    } else {
+        white
+    }
+}
+
+///
+/// `color`, mixed with black or white just enough to contrast
+/// with `background` by at least `ratio` (if that's possible).
+///
+/// A color lighter than `background` is made lighter, and a darker one darker,
+/// so it keeps its side. If even white (or black) doesn't contrast enough,
+/// whichever of the two contrasts more is used instead.
+///
+pub fn ensure_contrast(color: Color4f, background: Color4f, ratio: f32) -> Color4f {
+    if contrast_ratio(color, background) >= ratio {
+        return color;
+    }
+
+    let target = if relative_luminance(color) >= relative_luminance(background) {
+        Color4f::new(1.0, 1.0, 1.0, 1.0)
+    } else {
+        Color4f::new(0.0, 0.0, 0.0, 1.0)
+    };
+    let mix = |t: f32| {
+        Color4f::new(
+            color.r + (target.r - color.r) * t,
+            color.g + (target.g - color.g) * t,
+            color.b + (target.b - color.b) * t,
+            color.a,
+        )
+    };
+
+    if contrast_ratio(mix(1.0), background) < ratio {
+        let best = best_contrast(background);
+        return Color4f::new(best.r, best.g, best.b, color.a);
+    }
+
+    // Every channel moves towards the target, so the luminance moves away from
+    // the background's and the contrast only grows: look for the least mixing which does.
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..16 {
+        let t = (low + high) / 2.0;
+
+        if contrast_ratio(mix(t), background) >= ratio {
+            high = t;
+        } else {
+            low = t;
+        }
+    }
+
+    mix(high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Color4f = Color4f::new(0.0, 0.0, 0.0, 1.0);
+    const WHITE: Color4f = Color4f::new(1.0, 1.0, 1.0, 1.0);
+
+    fn gray(value: f32) -> Color4f {
+        Color4f::new(value, value, value, 1.0)
+    }
+
+    #[test]
+    fn luminance_of_known_colors() {
+        assert_eq!(relative_luminance(BLACK), 0.0);
+        assert!((relative_luminance(WHITE) - 1.0).abs() < 1e-6);
+        // sRGB 50% gray is about 21% as bright as white.
+        assert!((relative_luminance(gray(0.5)) - 0.214).abs() < 1e-3);
+        // Green counts for most, blue the least.
+        let red = relative_luminance(Color4f::new(1.0, 0.0, 0.0, 1.0));
+        let green = relative_luminance(Color4f::new(0.0, 1.0, 0.0, 1.0));
+        let blue = relative_luminance(Color4f::new(0.0, 0.0, 1.0, 1.0));
+        assert!(green > red && red > blue);
+        // Alpha is ignored.
+        assert_eq!(
+            relative_luminance(Color4f::new(0.5, 0.5, 0.5, 0.2)),
+            relative_luminance(gray(0.5))
+        );
+    }
+
+    #[test]
+    fn contrast_ratio_bounds_and_symmetry() {
+        assert!((contrast_ratio(BLACK, WHITE) - 21.0).abs() < 1e-4);
+        assert_eq!(contrast_ratio(BLACK, WHITE), contrast_ratio(WHITE, BLACK));
+        assert_eq!(contrast_ratio(gray(0.3), gray(0.3)), 1.0);
+
+        let mid = contrast_ratio(gray(0.5), WHITE);
+        assert!(mid > 1.0 && mid < 21.0);
+    }
+
+    #[test]
+    fn enough_contrast_is_kept() {
+        let text = Color4f::new(0.1, 0.2, 0.3, 1.0);
+
+        assert_eq!(ensure_contrast(text, WHITE, 4.5), text);
+    }
+
+    #[test]
+    fn lighter_colors_get_lighter() {
+        // Black contrasts more with this background, but the text is lighter than it.
+        let background = gray(0.55);
+        let text = gray(0.6);
+        assert_eq!(best_contrast(background), BLACK);
+
+        let adjusted = ensure_contrast(text, background, 2.0);
+
+        assert!(adjusted.r > text.r);
+        assert!(contrast_ratio(adjusted, background) >= 2.0);
+    }
+
+    #[test]
+    fn darker_colors_get_darker() {
+        let background = gray(0.5);
+        let text = gray(0.45);
+
+        let adjusted = ensure_contrast(text, background, 3.0);
+
+        assert!(adjusted.r < text.r);
+        assert!(contrast_ratio(adjusted, background) >= 3.0);
+    }
+
+    #[test]
+    fn mixes_no_more_than_needed() {
+        let background = gray(0.2);
+        let text = Color4f::new(0.4, 0.3, 0.3, 0.5);
+
+        let adjusted = ensure_contrast(text, background, 4.5);
+
+        assert!((contrast_ratio(adjusted, background) - 4.5).abs() < 0.01);
+        assert_eq!(adjusted.a, text.a);
+    }
+
+    #[test]
+    fn impossible_ratios_use_the_best_of_black_and_white() {
+        // Nothing contrasts 7:1 with mid gray, but black comes closest, even for lighter text.
+        let background = gray(0.5);
+        let text = gray(0.55);
+
+        let adjusted = ensure_contrast(text, background, 7.0);
+
+        assert_eq!((adjusted.r, adjusted.g, adjusted.b), (0.0, 0.0, 0.0));
+        assert_eq!(adjusted.a, text.a);
+    }
+}
@@ -0,0 +1,297 @@
+//!
+//! Per-frame information handed to render callbacks.
+//!
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use skia_safe::{
+    textlayout::{ParagraphStyle, TextAlign},
+    Canvas, Color4f, Rect, M44,
+};
+
+use crate::{
+    locale::{LanguageIdentifier, TextDirection},
+    theme::{AccessibilityOptions, Appearance},
+    util::{
+        dpi::{FontScale, OutputDpi},
+        SharedClock, SizeSnapshot,
+    },
+};
+
+use super::{
+    blur::{BlurCache, BlurFrame},
+    color::{ColorResolver, SemanticRole},
+};
+
+///
+/// Client-wide state every [RenderContext] is built from.
+///
+#[derive(Debug, Clone, Default)]
+pub struct SharedContext {
+    pub appearance: Appearance,
+    pub accessibility: AccessibilityOptions,
+    pub locale: LanguageIdentifier,
+    pub font_scale: FontScale,
+    /// See [crate::AvyClient::clock].
+    pub clock: SharedClock,
+}
+
+///
+/// Everything a render callback may need to know about the frame it's drawing.
+///
+#[derive(Debug, Clone)]
+pub struct RenderContext {
+    /// Number of the frame being drawn, see [super::GraphicsSurface::presented_frames].
+    pub frame: u64,
+
+    /// Surface size, in logical pixels.
+    pub logical_size: (u32, u32),
+
+    /// Size of the canvas being drawn on, in logical pixels: the surface
+    /// size, turned by its [crate::util::Transform].
+    pub drawing_size: (u32, u32),
+
+    /// Buffer size, in physical pixels.
+    pub physical_size: (f64, f64),
+
+    /// Ratio of physical to logical pixels.
+    pub scale: f64,
+
+    /// Desktop appearance (the theme slot).
+    pub appearance: Appearance,
+
+    pub accessibility: AccessibilityOptions,
+
+    pub locale: LanguageIdentifier,
+
+    /// The locale's, for layouts to mirror themselves by.
+    pub direction: TextDirection,
+
+    /// How [RenderContext::font_size] resolves sizes.
+    pub font_scale: FontScale,
+
+    /// Density of the output the surface is (first) on, if known.
+    pub output_dpi: Option<OutputDpi>,
+
+    colors: ColorResolver,
+
+    clock: SharedClock,
+
+    /// When drawing the frame started, by `clock`.
+    began: Instant,
+
+    unclipped: UnclippedDraws,
+
+    blurs: BlurCache,
+    blur_frame: BlurFrame,
+}
+
+type UnclippedDraw = Box<dyn FnOnce(&Canvas) + Send>;
+
+///
+/// Drawing queued with [RenderContext::unclipped], with the transform it was queued under.
+///
+#[derive(Clone, Default)]
+struct UnclippedDraws(Arc<Mutex<Vec<(M44, UnclippedDraw)>>>);
+
+impl fmt::Debug for UnclippedDraws {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("UnclippedDraws")
+            .field(&self.0.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl RenderContext {
+    pub fn new(frame: u64, size: &SizeSnapshot, shared: &SharedContext) -> Self {
+        Self {
+            frame,
+            logical_size: size.logical_size(),
+            drawing_size: size.drawing_size(),
+            physical_size: size.physical_size(),
+            scale: size.scale_factor(),
+            appearance: shared.appearance,
+            accessibility: shared.accessibility,
+            locale: shared.locale.clone(),
+            direction: shared.locale.direction(),
+            font_scale: shared.font_scale,
+            output_dpi: None,
+            colors: ColorResolver::new(&shared.appearance, shared.accessibility),
+            clock: shared.clock.clone(),
+            began: shared.clock.now(),
+            unclipped: UnclippedDraws::default(),
+            blurs: BlurCache::default(),
+            blur_frame: BlurFrame {
+                frame,
+                ..BlurFrame::default()
+            },
+        }
+    }
+
+    ///
+    /// The color to draw `role` with, see [ColorResolver].
+    ///
+    pub fn resolve_color(&self, role: SemanticRole) -> Color4f {
+        self.colors.resolve(role)
+    }
+
+    ///
+    /// The color to draw with when the app picked `color` for `role`: in
+    /// forced-colors mode it's replaced, and in high-contrast mode it's
+    /// adjusted to stand out enough from its background.
+    ///
+    pub fn resolve_explicit_color(&self, role: SemanticRole, color: Color4f) -> Color4f {
+        self.colors.resolve_explicit(role, color)
+    }
+
+    pub fn colors(&self) -> &ColorResolver {
+        &self.colors
+    }
+
+    ///
+    /// The time, as animations should see it, see [crate::AvyClient::clock].
+    ///
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    ///
+    /// Whether drawing this frame has taken long enough to keep input waiting,
+    /// if it's drawn on the event loop's thread, see [crate::app::INPUT_BUDGET].
+    ///
+    /// A callback drawing something expensive bit by bit can stop once this
+    /// is `true`, mark the surface dirty, and carry on in the next frame.
+    ///
+    pub fn over_input_budget(&self) -> bool {
+        crate::app::over_input_budget(self.began, self.clock.now())
+    }
+
+    pub fn with_output_dpi(mut self, output_dpi: Option<OutputDpi>) -> Self {
+        self.output_dpi = output_dpi;
+        self
+    }
+
+    ///
+    /// Keep [RenderContext::blur_region]'s blurs in `blurs`, drawing
+    /// into a layer (rather than the surface) if `layered`.
+    ///
+    pub(crate) fn with_blurs(mut self, blurs: BlurCache, layered: bool) -> Self {
+        self.blurs = blurs;
+        self.blur_frame.layered = layered;
+        self
+    }
+
+    ///
+    /// The frame's damage, see [super::FrameOptions::damage].
+    ///
+    pub(crate) fn with_damage(mut self, damage: Option<Vec<Rect>>) -> Self {
+        self.blur_frame.damage = damage;
+        self
+    }
+
+    ///
+    /// The font size to hand Skia for `size`, as set by [FontScale].
+    ///
+    pub fn font_size(&self, size: f32) -> f32 {
+        self.font_scale.resolve(size, self.output_dpi)
+    }
+
+    pub fn is_rtl(&self) -> bool {
+        self.direction.is_rtl()
+    }
+
+    ///
+    /// A paragraph style in the locale's direction, aligned to the start
+    /// of the line (the right, for right-to-left locales).
+    ///
+    pub fn paragraph_style(&self) -> ParagraphStyle {
+        let mut style = ParagraphStyle::new();
+        style
+            .set_text_direction(self.direction.to_skia())
+            .set_text_align(TextAlign::Start);
+        style
+    }
+
+    ///
+    /// Draw outside the surface's bounds, for intentional bleed: render
+    /// callbacks are otherwise clipped to the surface's size.
+    ///
+    /// Clips can't be lifted part-way through a frame, so `draw` runs once
+    /// the render callback has returned, on top of everything else. It
+    /// keeps the transform `canvas` has now, but none of its clips.
+    ///
+    pub fn unclipped(&self, canvas: &Canvas, draw: impl FnOnce(&Canvas) + Send + 'static) {
+        self.unclipped
+            .0
+            .lock()
+            .unwrap()
+            .push((canvas.local_to_device(), Box::new(draw)));
+    }
+
+    ///
+    /// Blur what's been drawn behind `rect` (in the canvas' coordinates) so far
+    /// this frame, with a Gaussian blur of `sigma` (in logical pixels), for a
+    /// frosted panel to be drawn over.
+    ///
+    /// It's blurred at a fraction of its resolution, on the surface's own GPU
+    /// context, and the blur is kept for the next frame: drawn with damage (see
+    /// [super::FrameOptions::damage]) which leaves `rect` be, it isn't blurred
+    /// again. What's read back is the surface itself, so drawing in layers the
+    /// callback saved isn't part of it yet, and with a post filter set (see
+    /// [crate::app::AvySurfaceHandle::set_post_filter]), Skia blurs it as a
+    /// layer's backdrop instead, at full resolution.
+    ///
+    pub fn blur_region(&self, canvas: &Canvas, rect: Rect, sigma: f32) {
+        self.blurs
+            .blur_region(canvas, rect, sigma, &self.blur_frame);
+    }
+
+    ///
+    /// Run the drawing queued with [RenderContext::unclipped], once the
+    /// clip applied around the render callback has been restored.
+    ///
+    pub(crate) fn draw_unclipped(&self, canvas: &Canvas) {
+        let draws = std::mem::take(&mut *self.unclipped.0.lock().unwrap());
+
+        for (matrix, draw) in draws {
+            canvas.save();
+            canvas.set_matrix(&matrix);
+            draw(canvas);
+            canvas.restore();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::util::{Size, TestClock};
+
+    use super::*;
+
+    #[test]
+    fn a_slow_draw_goes_over_the_input_budget() {
+        let clock = TestClock::new();
+        let shared = SharedContext {
+            clock: SharedClock::new(clock.clone()),
+            ..SharedContext::default()
+        };
+        let context = RenderContext::new(1, &Size::new((100, 100)).snapshot(), &shared);
+
+        clock.advance(Duration::from_millis(7));
+        assert!(!context.over_input_budget());
+
+        // E.g. a 30ms callback, checking in along the way.
+        clock.advance(Duration::from_millis(1));
+        assert!(context.over_input_budget());
+    }
+}
@@ -0,0 +1,305 @@
+//!
+//! Drawing helpers which line shapes up with the physical pixel grid,
+//! so that thin lines stay crisp at fractional scales.
+//!
+//! All coordinates are logical, as with the canvas handed to render callbacks.
+//!
+
+use skia_safe::{paint, Canvas, Paint, Point, RRect, Rect, Vector};
+
+use super::RenderContext;
+
+///
+/// Radii of each corner of a rounded rectangle, in logical pixels.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CornerRadii {
+    pub top_left: f32,
+    pub top_right: f32,
+    pub bottom_right: f32,
+    pub bottom_left: f32,
+}
+
+impl CornerRadii {
+    pub const ZERO: Self = Self::uniform(0.0);
+
+    pub const fn new(top_left: f32, top_right: f32, bottom_right: f32, bottom_left: f32) -> Self {
+        Self {
+            top_left,
+            top_right,
+            bottom_right,
+            bottom_left,
+        }
+    }
+
+    pub const fn uniform(radius: f32) -> Self {
+        Self::new(radius, radius, radius, radius)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        [
+            self.top_left,
+            self.top_right,
+            self.bottom_right,
+            self.bottom_left,
+        ]
+        .iter()
+        .all(|radius| *radius <= 0.0)
+    }
+
+    ///
+    /// `rect`, with its corners rounded by these radii.
+    ///
+    pub fn rrect(self, rect: impl AsRef<Rect>) -> RRect {
+        RRect::new_rect_radii(rect.as_ref(), &self.to_vectors())
+    }
+
+    fn to_vectors(self) -> [Vector; 4] {
+        // Skia's order: upper-left, upper-right, lower-right, lower-left.
+        [
+            Vector::new(self.top_left, self.top_left),
+            Vector::new(self.top_right, self.top_right),
+            Vector::new(self.bottom_right, self.bottom_right),
+            Vector::new(self.bottom_left, self.bottom_left),
+        ]
+    }
+}
+
+///
+/// Round a logical coordinate to the nearest physical pixel boundary.
+///
+pub fn snap(context: &RenderContext, value: f32) -> f32 {
+    let scale = context.scale as f32;
+    (value * scale).round() / scale
+}
+
+pub fn snap_point(context: &RenderContext, point: impl Into<Point>) -> Point {
+    let point = point.into();
+    Point::new(snap(context, point.x), snap(context, point.y))
+}
+
+///
+/// Snap each edge of `rect` to the nearest physical pixel boundary.
+///
+pub fn snap_rect(context: &RenderContext, rect: impl AsRef<Rect>) -> Rect {
+    let rect = rect.as_ref();
+    Rect::from_ltrb(
+        snap(context, rect.left),
+        snap(context, rect.top),
+        snap(context, rect.right),
+        snap(context, rect.bottom),
+    )
+}
+
+///
+/// Logical stroke width covering a whole number of physical pixels (at least one).
+///
+/// A width of zero (a Skia "hairline") counts as one physical pixel.
+///
+pub fn snap_stroke_width(context: &RenderContext, width: f32) -> f32 {
+    let scale = context.scale as f32;
+    physical_stroke_width(scale, width) / scale
+}
+
+fn physical_stroke_width(scale: f32, width: f32) -> f32 {
+    (width * scale).round().max(1.0)
+}
+
+///
+/// Snap the centre line of a stroke, so that its edges fall on pixel boundaries:
+/// odd (physical) widths are centred on a pixel, even widths between two.
+///
+fn snap_stroke_centre(context: &RenderContext, value: f32, width: f32) -> f32 {
+    let scale = context.scale as f32;
+    let physical_width = physical_stroke_width(scale, width);
+
+    let centre = if physical_width as u32 % 2 == 1 {
+        (value * scale).floor() + 0.5
+    } else {
+        (value * scale).round()
+    };
+
+    centre / scale
+}
+
+fn snap_stroke_rect(context: &RenderContext, rect: &Rect, width: f32) -> Rect {
+    Rect::from_ltrb(
+        snap_stroke_centre(context, rect.left, width),
+        snap_stroke_centre(context, rect.top, width),
+        snap_stroke_centre(context, rect.right, width),
+        snap_stroke_centre(context, rect.bottom, width),
+    )
+}
+
+fn stroke_paint(context: &RenderContext, paint: &Paint) -> Paint {
+    let mut paint = paint.clone();
+    paint.set_style(paint::Style::Stroke);
+    paint.set_stroke_width(snap_stroke_width(context, paint.stroke_width()));
+    paint.set_stroke_cap(paint::Cap::Butt);
+    paint
+}
+
+///
+/// Draw a horizontal line at `y`, from `extent.0` to `extent.1`,
+/// with the paint's stroke width.
+///
+pub fn hline(canvas: &Canvas, context: &RenderContext, y: f32, extent: (f32, f32), paint: &Paint) {
+    let y = snap_stroke_centre(context, y, paint.stroke_width());
+    let (start, end) = (snap(context, extent.0), snap(context, extent.1));
+
+    canvas.draw_line((start, y), (end, y), &stroke_paint(context, paint));
+}
+
+///
+/// Draw a vertical line at `x`, from `extent.0` to `extent.1`,
+/// with the paint's stroke width.
+///
+pub fn vline(canvas: &Canvas, context: &RenderContext, x: f32, extent: (f32, f32), paint: &Paint) {
+    let x = snap_stroke_centre(context, x, paint.stroke_width());
+    let (start, end) = (snap(context, extent.0), snap(context, extent.1));
+
+    canvas.draw_line((x, start), (x, end), &stroke_paint(context, paint));
+}
+
+///
+/// Outline `rect` with the paint's stroke width, centred on its (snapped) edges.
+///
+pub fn stroke_rect_snapped(
+    canvas: &Canvas,
+    context: &RenderContext,
+    rect: impl AsRef<Rect>,
+    paint: &Paint,
+) {
+    let rect = snap_stroke_rect(context, rect.as_ref(), paint.stroke_width());
+    canvas.draw_rect(rect, &stroke_paint(context, paint));
+}
+
+///
+/// Draw a rectangle with rounded corners, with its edges snapped to the pixel grid.
+///
+/// Filled or stroked, depending on `paint`. Strokes are snapped as
+/// with [stroke_rect_snapped].
+///
+pub fn rounded_rect(
+    canvas: &Canvas,
+    context: &RenderContext,
+    rect: impl AsRef<Rect>,
+    radii: CornerRadii,
+    paint: &Paint,
+) {
+    let rect = rect.as_ref();
+
+    let (rect, paint) = match paint.style() {
+        paint::Style::Stroke => (
+            snap_stroke_rect(context, rect, paint.stroke_width()),
+            stroke_paint(context, paint),
+        ),
+        _ => (snap_rect(context, rect), paint.clone()),
+    };
+
+    canvas.draw_rrect(radii.rrect(rect), &paint);
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::{surfaces, Color};
+
+    use super::*;
+    use crate::{
+        graphics::SharedContext,
+        util::{SizeSnapshot, Transform},
+    };
+
+    fn context(scale: f64) -> RenderContext {
+        let size = SizeSnapshot {
+            logical: (16, 16),
+            physical: (16.0 * scale, 16.0 * scale),
+            scale,
+            transform: Transform::Normal,
+            generation: 0,
+        };
+
+        RenderContext::new(1, &size, &SharedContext::default())
+    }
+
+    /// Where `value` lands on the physical grid.
+    fn physical(context: &RenderContext, value: f32) -> f32 {
+        value * context.scale as f32
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{actual} isn't close to {expected}"
+        );
+    }
+
+    #[test]
+    fn snaps_to_physical_pixel_boundaries() {
+        let context = context(1.5);
+
+        // 3.3 logical is 4.95 physical.
+        assert_close(physical(&context, snap(&context, 3.3)), 5.0);
+        assert_close(physical(&context, snap(&context, 3.0)), 5.0);
+
+        let rect = snap_rect(&context, Rect::from_ltrb(0.2, 0.5, 10.1, 7.7));
+        assert_close(physical(&context, rect.left), 0.0);
+        assert_close(physical(&context, rect.top), 1.0);
+        assert_close(physical(&context, rect.right), 15.0);
+        assert_close(physical(&context, rect.bottom), 12.0);
+    }
+
+    #[test]
+    fn stroke_widths_cover_whole_pixels() {
+        let context = context(1.25);
+
+        assert_close(physical(&context, snap_stroke_width(&context, 0.0)), 1.0);
+        assert_close(physical(&context, snap_stroke_width(&context, 0.1)), 1.0);
+        assert_close(physical(&context, snap_stroke_width(&context, 1.0)), 1.0);
+        assert_close(physical(&context, snap_stroke_width(&context, 2.0)), 3.0);
+    }
+
+    #[test]
+    fn odd_strokes_are_centred_on_a_pixel_and_even_ones_between_two() {
+        let context = context(2.0);
+
+        // One logical pixel is two physical ones: an even width.
+        assert_close(
+            physical(&context, snap_stroke_centre(&context, 3.3, 1.0)),
+            7.0,
+        );
+        // Half a logical pixel is one physical pixel: an odd width.
+        assert_close(
+            physical(&context, snap_stroke_centre(&context, 3.3, 0.5)),
+            6.5,
+        );
+        // Hairlines are one physical pixel wide.
+        assert_close(
+            physical(&context, snap_stroke_centre(&context, 3.3, 0.0)),
+            6.5,
+        );
+    }
+
+    #[test]
+    fn a_one_pixel_line_covers_exactly_one_row() {
+        let context = context(1.0);
+        let mut surface = surfaces::raster_n32_premul((16, 16)).unwrap();
+        surface.canvas().clear(Color::TRANSPARENT);
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true).set_color(Color::BLACK);
+        paint.set_stroke_width(1.0);
+        hline(surface.canvas(), &context, 4.3, (2.0, 12.0), &paint);
+
+        let image = surface.image_snapshot();
+        let pixmap = image.peek_pixels().unwrap();
+        for y in 0..16 {
+            let expected = if y == 4 {
+                Color::BLACK
+            } else {
+                Color::TRANSPARENT
+            };
+            assert_eq!(pixmap.get_color((6, y)), expected, "row {y}");
+        }
+    }
+}
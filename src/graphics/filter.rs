@@ -0,0 +1,194 @@
+//!
+//! Color filters applied over everything drawn into a surface, e.g. for a
+//! night light, see [crate::app::AvySurfaceHandle::set_post_filter].
+//!
+
+use std::time::{Duration, Instant};
+
+use skia_safe::{canvas::SaveLayerRec, color_filters, Canvas, ColorFilter, Paint};
+
+use crate::util::animation::{Animation, Easing, Lerp};
+
+///
+/// The color temperature (in kelvin) which leaves colors as they are.
+///
+pub const NEUTRAL_TEMPERATURE: f32 = 6500.0;
+
+///
+/// The color matrix which leaves colors as they are.
+///
+#[rustfmt::skip]
+pub const IDENTITY_MATRIX: [f32; 20] = [
+    1.0, 0.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.0, 1.0, 0.0,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostFilter {
+    ///
+    /// A 4×5 row-major color matrix, as taken by [color_filters::matrix_row_major].
+    ///
+    Matrix([f32; 20]),
+    ///
+    /// Tinted as if lit at this color temperature, in kelvin: lower is
+    /// warmer, and [NEUTRAL_TEMPERATURE] leaves colors as they are.
+    ///
+    Temperature(f32),
+}
+
+impl PostFilter {
+    pub fn matrix(&self) -> [f32; 20] {
+        match *self {
+            Self::Matrix(matrix) => matrix,
+            Self::Temperature(kelvin) => {
+                let [r, g, b] = white_point(kelvin);
+                let [r0, g0, b0] = white_point(NEUTRAL_TEMPERATURE);
+
+                let mut matrix = IDENTITY_MATRIX;
+                matrix[0] = r / r0;
+                matrix[6] = g / g0;
+                matrix[12] = b / b0;
+                matrix
+            }
+        }
+    }
+
+    pub fn color_filter(&self) -> ColorFilter {
+        color_filters::matrix_row_major(&self.matrix(), None)
+    }
+
+    ///
+    /// Draw whatever follows on `canvas` through the filter, until
+    /// restoring to the returned save count.
+    ///
+    pub fn begin(&self, canvas: &Canvas) -> usize {
+        let mut paint = Paint::default();
+        paint.set_color_filter(self.color_filter());
+        canvas.save_layer(&SaveLayerRec::default().paint(&paint))
+    }
+
+    ///
+    /// The filter of the same kind as this one which leaves colors as they
+    /// are, for easing in from (or out to) no filter.
+    ///
+    fn neutral(&self) -> Self {
+        match self {
+            Self::Matrix(_) => Self::Matrix(IDENTITY_MATRIX),
+            Self::Temperature(_) => Self::Temperature(NEUTRAL_TEMPERATURE),
+        }
+    }
+}
+
+impl Lerp for PostFilter {
+    ///
+    /// Temperatures ease along the temperature scale,
+    /// anything else from one matrix to the other.
+    ///
+    fn lerp(&self, to: &Self, t: f64) -> Self {
+        match (self, to) {
+            (Self::Temperature(from), Self::Temperature(to)) => Self::Temperature(from.lerp(to, t)),
+            _ => {
+                let (from, to) = (self.matrix(), to.matrix());
+                Self::Matrix(std::array::from_fn(|i| from[i].lerp(&to[i], t)))
+            }
+        }
+    }
+}
+
+///
+/// The color of white light at `kelvin`, each channel in `0.0..=1.0`.
+///
+/// Tanner Helland's fit of the blackbody curve, good from 1000K to 40000K.
+///
+fn white_point(kelvin: f32) -> [f32; 3] {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = match t <= 66.0 {
+        true => 255.0,
+        false => 329.69873 * (t - 60.0).powf(-0.13320476),
+    };
+    let g = match t <= 66.0 {
+        true => 99.4708 * t.ln() - 161.11957,
+        false => 288.12216 * (t - 60.0).powf(-0.075514846),
+    };
+    let b = match t {
+        t if t >= 66.0 => 255.0,
+        t if t <= 19.0 => 0.0,
+        t => 138.51773 * (t - 10.0).ln() - 305.0448,
+    };
+
+    [r, g, b].map(|channel| (channel / 255.0).clamp(0.0, 1.0))
+}
+
+///
+/// A surface's post filter, possibly on its way from another.
+///
+#[derive(Debug, Default)]
+pub struct PostFilterState {
+    target: Option<PostFilter>,
+    transition: Option<(PostFilter, Animation)>,
+}
+
+impl PostFilterState {
+    ///
+    /// Switch straight to `filter`.
+    ///
+    pub fn set(&mut self, filter: Option<PostFilter>) {
+        self.target = filter;
+        self.transition = None;
+    }
+
+    ///
+    /// Ease from the filter as it is at `now` to `filter`.
+    ///
+    pub fn transition(
+        &mut self,
+        filter: Option<PostFilter>,
+        duration: Duration,
+        easing: Easing,
+        now: Instant,
+    ) {
+        let from = self.value(now);
+        let Some(from) = from.or_else(|| filter.map(|filter| filter.neutral())) else {
+            // From nothing to nothing.
+            return;
+        };
+
+        self.target = filter;
+        self.transition
+            .replace((from, Animation::starting_at(now, duration, easing)));
+    }
+
+    pub fn target(&self) -> Option<PostFilter> {
+        self.target
+    }
+
+    pub fn value(&self, now: Instant) -> Option<PostFilter> {
+        let Some((from, animation)) = &self.transition else {
+            return self.target;
+        };
+
+        if animation.is_finished(now) {
+            return self.target;
+        }
+
+        let to = self.target.unwrap_or_else(|| from.neutral());
+        Some(from.lerp(&to, animation.progress(now)))
+    }
+
+    pub fn is_animating(&self, now: Instant) -> bool {
+        self.transition
+            .as_ref()
+            .is_some_and(|(_, animation)| !animation.is_finished(now))
+    }
+
+    ///
+    /// Draw whatever follows on `canvas` through the filter as it is at
+    /// `now`, until restoring to the returned save count.
+    ///
+    pub fn begin(&self, canvas: &Canvas, now: Instant) -> Option<usize> {
+        Some(self.value(now)?.begin(canvas))
+    }
+}
@@ -0,0 +1,291 @@
+//!
+//! Phased (acquire / draw / present) rendering.
+//!
+
+use std::{
+    any::Any,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use skia_safe::{Canvas, Color4f, Rect};
+
+use crate::util::SharedClock;
+
+///
+/// Timestamps recorded over the lifetime of a [Frame], by
+/// the client's clock (see [super::GraphicsSurface::set_clock]).
+///
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTimings {
+    /// Number of this frame on its surface, see [super::GraphicsSurface::presented_frames].
+    pub frame: u64,
+
+    /// When [super::GraphicsSurface::begin_frame] was called.
+    pub begun: Instant,
+
+    /// When the backend finished acquiring an image to draw into.
+    pub acquired: Instant,
+
+    /// When drawing was marked as finished (see [Frame::mark_drawn]).
+    pub drawn: Option<Instant>,
+
+    /// When the frame was submitted for presentation.
+    pub presented: Option<Instant>,
+
+    /// Whether the swapchain was recreated (e.g. for a resize) before acquiring.
+    pub swapchain_recreated: bool,
+
+    /// Whether the acquired image was suboptimal for the surface,
+    /// so that the swapchain gets recreated next frame.
+    pub suboptimal: bool,
+
+    /// How long an earlier frame took on the GPU, if the backend times them.
+    pub gpu: GpuTimings,
+}
+
+///
+/// GPU-side durations of a frame, measured with timestamp queries.
+///
+/// They're read back without waiting, once the GPU is done with the frame,
+/// so they're reported with a later one (usually the next, or the one after).
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GpuTimings {
+    /// Not asked for, or not supported by the backend.
+    #[default]
+    Off,
+    /// Asked for, but the device can't time graphics work.
+    Unavailable,
+    /// No earlier frame has finished on the GPU since the last measurement.
+    Pending,
+    Measured {
+        /// From the start of the frame's work to the end of Skia's.
+        render: Duration,
+        /// From the start of the frame's work to just before presenting.
+        total: Duration,
+    },
+}
+
+impl GpuTimings {
+    pub fn is_available(&self) -> bool {
+        matches!(self, Self::Pending | Self::Measured { .. })
+    }
+}
+
+impl FrameTimings {
+    pub fn new(frame: u64, begun: Instant) -> Self {
+        Self {
+            frame,
+            begun,
+            acquired: begun,
+            drawn: None,
+            presented: None,
+            swapchain_recreated: false,
+            suboptimal: false,
+            gpu: GpuTimings::Off,
+        }
+    }
+}
+
+///
+/// What a frame's image is cleared to before drawing.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearBehavior {
+    Color(Color4f),
+    ///
+    /// Don't clear at all: the callback must cover every pixel (e.g. with a
+    /// full-surface shader fill), as the image still holds an older frame.
+    ///
+    None,
+}
+
+impl Default for ClearBehavior {
+    fn default() -> Self {
+        Self::Color(Color4f::new(1.0, 1.0, 1.0, 1.0))
+    }
+}
+
+///
+/// How a frame is set up before it's handed over for drawing.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameOptions {
+    pub clear: ClearBehavior,
+
+    ///
+    /// Regions (in logical pixels) which changed this frame: only those are
+    /// cleared. Everything else still holds an older frame (not necessarily
+    /// the last one), which the callback must draw over. `None` clears the
+    /// whole image.
+    ///
+    pub damage: Option<Vec<Rect>>,
+}
+
+impl FrameOptions {
+    pub fn clear(clear: ClearBehavior) -> Self {
+        Self {
+            clear,
+            damage: None,
+        }
+    }
+
+    ///
+    /// Clear `canvas` (already scaled to logical pixels) as asked for.
+    ///
+    pub fn apply(&self, canvas: &Canvas) {
+        let ClearBehavior::Color(color) = self.clear else {
+            return;
+        };
+
+        let Some(damage) = &self.damage else {
+            canvas.clear(color);
+            return;
+        };
+
+        for rect in damage {
+            canvas.save();
+            canvas.clip_rect(rect, None, false);
+            canvas.clear(color);
+            canvas.restore();
+        }
+    }
+}
+
+///
+/// Backend-specific part of a [Frame].
+///
+/// Implementors must cleanly discard the acquired image when
+/// dropped without [GraphicsFrame::present] being called.
+///
+pub trait GraphicsFrame {
+    fn canvas(&mut self) -> &skia_safe::Canvas;
+
+    fn gr_context(&mut self) -> Option<&mut skia_safe::gpu::DirectContext>;
+
+    ///
+    /// Make [GraphicsFrame::present] wait until the GPU has finished drawing,
+    /// rather than just ordering presentation after it.
+    ///
+    fn finish_before_present(&mut self) {}
+
+    fn present(self: Box<Self>) -> Result<(), Box<dyn Any>>;
+}
+
+///
+/// An acquired, ready-to-draw frame.
+///
+/// Dropping a frame without calling [Frame::present]
+/// discards everything drawn into it.
+///
+pub struct Frame<'a> {
+    inner: Box<dyn GraphicsFrame + 'a>,
+    timings: FrameTimings,
+    /// What the rest of `timings` is read from.
+    clock: SharedClock,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(inner: impl GraphicsFrame + 'a, timings: FrameTimings, clock: SharedClock) -> Self {
+        Self {
+            inner: Box::new(inner),
+            timings,
+            clock,
+        }
+    }
+
+    pub fn canvas(&mut self) -> &skia_safe::Canvas {
+        self.inner.canvas()
+    }
+
+    ///
+    /// The GPU context backing this frame, if any.
+    ///
+    pub fn gr_context(&mut self) -> Option<&mut skia_safe::gpu::DirectContext> {
+        self.inner.gr_context()
+    }
+
+    pub fn timings(&self) -> &FrameTimings {
+        &self.timings
+    }
+
+    ///
+    /// Don't hand the frame to the compositor until it's completely drawn.
+    ///
+    /// Only backends which don't order presentation after drawing on the GPU
+    /// do anything here, with a CPU/GPU sync, so it's only worth it for frames
+    /// which matter more than usual, like the one which maps a surface.
+    ///
+    pub fn finish_before_present(&mut self) {
+        self.inner.finish_before_present()
+    }
+
+    ///
+    /// Record that all drawing for this frame has finished.
+    ///
+    pub fn mark_drawn(&mut self) {
+        self.timings.drawn.replace(self.clock.now());
+    }
+
+    ///
+    /// Submit and present this frame.
+    ///
+    pub fn present(mut self) -> Result<FrameTimings, Box<dyn Any>> {
+        if self.timings.drawn.is_none() {
+            self.mark_drawn();
+        }
+
+        let mut timings = self.timings;
+        self.inner.present()?;
+        timings.presented.replace(self.clock.now());
+
+        Ok(timings)
+    }
+}
+
+///
+/// The payload of a panic caught in a render callback.
+///
+/// The frame being drawn when it happened was discarded, not presented.
+///
+pub struct CallbackPanic(pub Box<dyn Any + Send>);
+
+impl CallbackPanic {
+    ///
+    /// The panic message, if it was a string (as with `panic!("...")`).
+    ///
+    pub fn message(&self) -> Option<&str> {
+        self.0
+            .downcast_ref::<&'static str>()
+            .copied()
+            .or_else(|| self.0.downcast_ref::<String>().map(String::as_str))
+    }
+
+    pub fn into_payload(self) -> Box<dyn Any + Send> {
+        self.0
+    }
+}
+
+impl fmt::Debug for CallbackPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CallbackPanic")
+            .field(&self.message())
+            .finish()
+    }
+}
+
+impl fmt::Display for CallbackPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message().unwrap_or("<non-string payload>"))
+    }
+}
+
+///
+/// A render was asked for whilst the surface was suspended,
+/// see [crate::app::AvySurfaceHandle::suspend].
+///
+/// Backends turn it into their own "suspended" error.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurfaceSuspended;
@@ -0,0 +1,609 @@
+//!
+//! Images decoded off the event loop, on a small pool of worker threads.
+//!
+//! [ImageCache::load_async] hands back an [ImageHandle] straight away, which
+//! draws nothing (or a placeholder) until its image is decoded, and then
+//! marks the surfaces it's drawn on dirty (through [AvyProxy]) so that they
+//! repaint with it.
+//!
+//! Images are cached for as long as a handle to them is alive. Dropping
+//! every handle to an image before it's decoded cancels the decode.
+//!
+//! Surfaces are drawn in sRGB, so images with an embedded ICC profile for
+//! another color space (e.g. Display P3 photos) are converted to it as they're
+//! decoded, rather than drawn as if they were sRGB (oversaturated), see
+//! [ImageHandle::color].
+//!
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    io,
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex, Weak},
+};
+
+use skia_safe::{
+    image::CachingHint, Canvas, Color, ColorSpace, Data, FilterMode, Image, MipmapMode, Paint,
+    Rect, SamplingOptions,
+};
+use smallvec::SmallVec;
+use thiserror::Error;
+use wayland_backend::client::ObjectId;
+
+use crate::proxy::AvyProxy;
+
+///
+/// Worker threads used by [ImageCache::new].
+///
+pub const DEFAULT_WORKERS: usize = 2;
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("Could not read the image: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not decode the image.")]
+    Decode,
+}
+
+///
+/// Where an image comes from.
+///
+#[derive(Debug, Clone)]
+pub enum ImageSource {
+    Path(PathBuf),
+    /// An encoded image (PNG, JPEG, ...).
+    Encoded(Arc<[u8]>),
+}
+
+impl ImageSource {
+    fn key(&self) -> SourceKey {
+        match self {
+            Self::Path(path) => SourceKey::Path(path.clone()),
+            Self::Encoded(data) => {
+                let mut hasher = DefaultHasher::new();
+                data.hash(&mut hasher);
+                SourceKey::Encoded(hasher.finish(), data.len())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum SourceKey {
+    Path(PathBuf),
+    /// Hash and length of the encoded bytes.
+    Encoded(u64, usize),
+}
+
+///
+/// How to load an image, see [ImageCache::load_async_with].
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct LoadOptions {
+    ///
+    /// Ignore the image's ICC profile and take its colors as sRGB, for
+    /// files with a broken one.
+    ///
+    pub assume_srgb: bool,
+}
+
+///
+/// How a decoded image's colors were handled, see [ImageHandle::color].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageColor {
+    /// Untagged or tagged as sRGB, so drawn as it is.
+    Srgb,
+    /// Tagged with another color space by its ICC profile, and converted to sRGB.
+    Converted,
+    /// Tagged with another color space, but taken as sRGB
+    /// anyway, see [LoadOptions::assume_srgb].
+    AssumedSrgb,
+}
+
+///
+/// `image`, in sRGB, and what it took to get it there.
+///
+fn manage_color(image: Image, options: LoadOptions) -> Option<(Image, ImageColor)> {
+    let tagged = image
+        .image_info()
+        .color_space()
+        .filter(|color_space| !color_space.is_srgb());
+
+    if tagged.is_none() {
+        return Some((image, ImageColor::Srgb));
+    }
+
+    if options.assume_srgb {
+        let image = image.reinterpret_color_space(ColorSpace::new_srgb())?;
+        return Some((image, ImageColor::AssumedSrgb));
+    }
+
+    let image = image.make_color_space(None, ColorSpace::new_srgb())?;
+    Some((image, ImageColor::Converted))
+}
+
+///
+/// Where an [ImageHandle]'s image is at.
+///
+#[derive(Clone)]
+pub enum ImageState {
+    Loading,
+    Ready(Image),
+    Failed(Arc<ImageError>),
+}
+
+///
+/// Turns encoded bytes into an image, on a worker thread.
+///
+/// The image it returns should be decoded already (i.e. a raster image),
+/// not lazily decoded when first drawn on the event loop.
+///
+pub type Decoder = dyn Fn(&[u8]) -> Option<Image> + Send + Sync;
+
+///
+/// The default [Decoder]: Skia's codecs, forced into a raster image.
+///
+/// The image is tagged with the color space of the ICC profile embedded in
+/// the file, if any, for the cache to convert it from.
+///
+pub fn decode(data: &[u8]) -> Option<Image> {
+    Image::from_encoded(Data::new_copy(data))
+        .and_then(|image| image.make_raster_image(None, CachingHint::Allow))
+}
+
+struct Slot {
+    state: ImageState,
+    color: Option<ImageColor>,
+    /// Surfaces to mark dirty once the image is decoded (or has failed to).
+    surfaces: SmallVec<[ObjectId; 2]>,
+}
+
+///
+/// An image which may still be decoding.
+///
+/// Cheap to clone; clones share the image. Drop every handle to an image
+/// whilst it's decoding to cancel the decode.
+///
+#[derive(Clone)]
+pub struct ImageHandle {
+    slot: Arc<Mutex<Slot>>,
+}
+
+impl ImageHandle {
+    pub fn state(&self) -> ImageState {
+        self.slot.lock().unwrap().state.clone()
+    }
+
+    ///
+    /// The image, if it's been decoded.
+    ///
+    pub fn image(&self) -> Option<Image> {
+        match &self.slot.lock().unwrap().state {
+            ImageState::Ready(image) => Some(image.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(self.slot.lock().unwrap().state, ImageState::Ready(_))
+    }
+
+    ///
+    /// Whether the image's colors were converted from those of its
+    /// ICC profile, once it's decoded.
+    ///
+    pub fn color(&self) -> Option<ImageColor> {
+        self.slot.lock().unwrap().color
+    }
+
+    ///
+    /// Mark `surface` dirty when the image is decoded (or fails to be),
+    /// so that it's drawn again with it. Does nothing if it's done already.
+    ///
+    pub fn repaint_on_ready(&self, surface: ObjectId) -> &Self {
+        let mut slot = self.slot.lock().unwrap();
+        if matches!(slot.state, ImageState::Loading) && !slot.surfaces.contains(&surface) {
+            slot.surfaces.push(surface);
+        }
+
+        self
+    }
+
+    ///
+    /// Draw the image stretched over `dest`, or `placeholder` (if any)
+    /// whilst it's loading or if it failed to.
+    ///
+    pub fn draw(&self, canvas: &Canvas, dest: Rect, placeholder: Option<Color>) {
+        match self.image() {
+            Some(image) => {
+                let sampling = SamplingOptions::new(FilterMode::Linear, MipmapMode::None);
+                canvas.draw_image_rect_with_sampling_options(
+                    &image,
+                    None,
+                    dest,
+                    sampling,
+                    &Paint::default(),
+                );
+            }
+            None => {
+                if let Some(color) = placeholder {
+                    let mut paint = Paint::default();
+                    paint.set_color(color);
+                    canvas.draw_rect(dest, &paint);
+                }
+            }
+        }
+    }
+}
+
+struct Job {
+    slot: Weak<Mutex<Slot>>,
+    source: ImageSource,
+    options: LoadOptions,
+}
+
+struct Queue {
+    jobs: VecDeque<Job>,
+    /// Worker threads spawned so far.
+    workers: usize,
+    shutdown: bool,
+}
+
+///
+/// State shared between the cache and its workers.
+///
+struct Pool {
+    queue: Mutex<Queue>,
+    wake: Condvar,
+    max_workers: usize,
+    decoder: Arc<Decoder>,
+    proxy: AvyProxy,
+}
+
+impl Pool {
+    fn submit(self: &Arc<Self>, job: Job) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.jobs.push_back(job);
+
+        // Workers are only spawned once there's work for them.
+        if queue.workers < self.max_workers {
+            let pool = self.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("avy-image-decode-{}", queue.workers))
+                .spawn(move || pool.work());
+
+            match spawned {
+                Ok(_) => queue.workers += 1,
+                Err(err) => log::warn!("Could not spawn an image decoding thread: {err}"),
+            }
+        }
+
+        self.wake.notify_one();
+    }
+
+    fn work(&self) {
+        loop {
+            let job = {
+                let mut queue = self.queue.lock().unwrap();
+                loop {
+                    if queue.shutdown {
+                        return;
+                    }
+
+                    if let Some(job) = queue.jobs.pop_front() {
+                        break job;
+                    }
+
+                    queue = self.wake.wait(queue).unwrap();
+                }
+            };
+
+            self.run(job);
+        }
+    }
+
+    fn run(&self, job: Job) {
+        // Every handle was dropped whilst this was queued.
+        let Some(slot) = job.slot.upgrade() else {
+            return;
+        };
+
+        let data = match job.source {
+            ImageSource::Path(path) => std::fs::read(path).map(Arc::from),
+            ImageSource::Encoded(data) => Ok(data),
+        };
+
+        // Or whilst the file was being read.
+        if Arc::strong_count(&slot) == 1 {
+            return;
+        }
+
+        let decoded = data
+            .map(|data| (self.decoder)(&data).and_then(|image| manage_color(image, job.options)));
+
+        let (state, color) = match decoded {
+            Ok(Some((image, color))) => (ImageState::Ready(image), Some(color)),
+            Ok(None) => (ImageState::Failed(Arc::new(ImageError::Decode)), None),
+            Err(err) => (ImageState::Failed(Arc::new(ImageError::Io(err))), None),
+        };
+
+        let surfaces = {
+            let mut slot = slot.lock().unwrap();
+            slot.state = state;
+            slot.color = color;
+            std::mem::take(&mut slot.surfaces)
+        };
+
+        for surface in surfaces {
+            if self.proxy.mark_dirty(surface).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+///
+/// Decoded images by source, see the [module docs](self).
+///
+pub struct ImageCache {
+    images: HashMap<(SourceKey, LoadOptions), Weak<Mutex<Slot>>>,
+    pool: Arc<Pool>,
+}
+
+impl ImageCache {
+    ///
+    /// A cache decoding on up to [DEFAULT_WORKERS] threads, marking
+    /// surfaces dirty through `proxy` (see [crate::AvyClient::proxy]).
+    ///
+    pub fn new(proxy: AvyProxy) -> Self {
+        Self::with_decoder(proxy, DEFAULT_WORKERS, decode)
+    }
+
+    ///
+    /// A cache decoding on up to `workers` threads (at least one), with
+    /// `decoder` rather than Skia's codecs.
+    ///
+    pub fn with_decoder(
+        proxy: AvyProxy,
+        workers: usize,
+        decoder: impl Fn(&[u8]) -> Option<Image> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            images: HashMap::new(),
+            pool: Arc::new(Pool {
+                queue: Mutex::new(Queue {
+                    jobs: VecDeque::new(),
+                    workers: 0,
+                    shutdown: false,
+                }),
+                wake: Condvar::new(),
+                max_workers: workers.max(1),
+                decoder: Arc::new(decoder),
+                proxy,
+            }),
+        }
+    }
+
+    ///
+    /// A handle to `source`'s image, queued to be decoded on a worker
+    /// thread unless a live handle to it already exists.
+    ///
+    /// Images which failed to decode are tried again.
+    ///
+    pub fn load_async(&mut self, source: ImageSource) -> ImageHandle {
+        self.load_async_with(source, LoadOptions::default())
+    }
+
+    ///
+    /// Like [ImageCache::load_async], with `options`. The same source loaded
+    /// with different options is decoded (and cached) separately.
+    ///
+    pub fn load_async_with(&mut self, source: ImageSource, options: LoadOptions) -> ImageHandle {
+        let key = (source.key(), options);
+        if let Some(slot) = self.images.get(&key).and_then(Weak::upgrade) {
+            if !matches!(slot.lock().unwrap().state, ImageState::Failed(_)) {
+                return ImageHandle { slot };
+            }
+        }
+
+        self.images.retain(|_, slot| slot.strong_count() > 0);
+
+        let slot = Arc::new(Mutex::new(Slot {
+            state: ImageState::Loading,
+            color: None,
+            surfaces: SmallVec::new(),
+        }));
+
+        self.images.insert(key, Arc::downgrade(&slot));
+        self.pool.submit(Job {
+            slot: Arc::downgrade(&slot),
+            source,
+            options,
+        });
+
+        ImageHandle { slot }
+    }
+
+    ///
+    /// Images with a live handle (decoded or not).
+    ///
+    pub fn len(&self) -> usize {
+        self.images
+            .values()
+            .filter(|slot| slot.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for ImageCache {
+    fn drop(&mut self) {
+        // Decodes in progress finish; queued ones are dropped, and stay loading.
+        let mut queue = self.pool.queue.lock().unwrap();
+        queue.shutdown = true;
+        queue.jobs.clear();
+        self.pool.wake.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use skia_safe::{surfaces, AlphaType, ColorType, EncodedImageFormat, ImageInfo};
+
+    use super::*;
+    use crate::proxy::ProxyQueue;
+
+    /// A flag one thread waits on for another to set.
+    #[derive(Default)]
+    struct Gate {
+        open: Mutex<bool>,
+        opened: Condvar,
+    }
+
+    impl Gate {
+        fn open(&self) {
+            *self.open.lock().unwrap() = true;
+            self.opened.notify_all();
+        }
+
+        fn wait(&self) {
+            let open = self.open.lock().unwrap();
+            drop(self.opened.wait_while(open, |open| !*open).unwrap());
+        }
+    }
+
+    fn solid(color: Color) -> Image {
+        let mut surface = surfaces::raster_n32_premul((4, 4)).unwrap();
+        surface.canvas().clear(color);
+        surface.image_snapshot()
+    }
+
+    /// Draw `handle` over an 8×8 frame, returning the color in its middle.
+    fn render(handle: &ImageHandle) -> Color {
+        let mut surface = surfaces::raster_n32_premul((8, 8)).unwrap();
+        surface.canvas().clear(Color::TRANSPARENT);
+        handle.draw(surface.canvas(), Rect::from_wh(8.0, 8.0), Some(Color::BLUE));
+
+        let image = surface.image_snapshot();
+        image.peek_pixels().unwrap().get_color((4, 4))
+    }
+
+    /// A PNG filled with `rgb`, tagged (by an ICC profile) with `color_space`, if any.
+    fn png(rgb: [u8; 3], color_space: Option<ColorSpace>) -> Vec<u8> {
+        let info = ImageInfo::new((2, 2), ColorType::RGBA8888, AlphaType::Opaque, color_space);
+        let pixels = [rgb[0], rgb[1], rgb[2], 255].repeat(4);
+        let image = Image::from_raster_data(&info, Data::new_copy(&pixels), 8).unwrap();
+
+        image
+            .encode(None, EncodedImageFormat::PNG, None)
+            .unwrap()
+            .to_vec()
+    }
+
+    /// The stored (not color managed) RGB of `image`'s first pixel.
+    fn rgb(image: &Image) -> [u8; 3] {
+        let info = ImageInfo::new((1, 1), ColorType::RGBA8888, AlphaType::Premul, None);
+        let mut pixel = [0u8; 4];
+        assert!(image.read_pixels(&info, &mut pixel, 4, (0, 0), CachingHint::Allow));
+        [pixel[0], pixel[1], pixel[2]]
+    }
+
+    /// Within a couple of steps, for 8-bit rounding on each side of the conversion.
+    fn assert_rgb_close(actual: [u8; 3], expected: [u8; 3]) {
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(&actual, expected)| actual.abs_diff(expected) <= 2);
+        assert!(close, "{actual:?} isn't close to {expected:?}");
+    }
+
+    /// Load `data` as the cache's workers do.
+    fn load(data: &[u8], options: LoadOptions) -> (Image, ImageColor) {
+        decode(data)
+            .and_then(|image| manage_color(image, options))
+            .unwrap()
+    }
+
+    #[test]
+    fn images_tagged_with_another_color_space_are_converted_to_srgb() {
+        // Linear sRGB: the sRGB primaries without its transfer function, so
+        // stored values brighten as they're encoded into sRGB.
+        let data = png([32, 128, 224], Some(ColorSpace::new_srgb_linear()));
+        let (image, color) = load(&data, LoadOptions::default());
+
+        assert_eq!(color, ImageColor::Converted);
+        assert!(image.color_space().is_some_and(|space| space.is_srgb()));
+        assert_rgb_close(rgb(&image), [99, 188, 241]);
+    }
+
+    #[test]
+    fn images_assumed_srgb_keep_their_stored_colors() {
+        let data = png([32, 128, 224], Some(ColorSpace::new_srgb_linear()));
+        let options = LoadOptions { assume_srgb: true };
+        let (image, color) = load(&data, options);
+
+        assert_eq!(color, ImageColor::AssumedSrgb);
+        assert_rgb_close(rgb(&image), [32, 128, 224]);
+    }
+
+    #[test]
+    fn untagged_images_are_drawn_as_they_are() {
+        let (image, color) = load(&png([32, 128, 224], None), LoadOptions::default());
+
+        assert_eq!(color, ImageColor::Srgb);
+        assert_rgb_close(rgb(&image), [32, 128, 224]);
+    }
+
+    /// Wait (for a while) for an invocation, i.e. a surface marked dirty, to be queued.
+    fn wait_for_repaint(queue: &ProxyQueue) {
+        let channel = queue.channel.as_ref().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while channel.try_recv().is_err() {
+            assert!(Instant::now() < deadline, "the image never got decoded");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn slow_decodes_draw_the_placeholder_until_they_land() {
+        let queue = ProxyQueue::new();
+        let started = Arc::new(Gate::default());
+        let release = Arc::new(Gate::default());
+
+        let mut cache = ImageCache::with_decoder(queue.proxy.clone(), 1, {
+            let (started, release) = (started.clone(), release.clone());
+            move |_| {
+                started.open();
+                release.wait();
+                Some(solid(Color::RED))
+            }
+        });
+
+        let handle = cache.load_async(ImageSource::Encoded(Arc::from([0u8; 4].as_slice())));
+        handle.repaint_on_ready(ObjectId::null());
+
+        // The decoder is stuck partway, yet frames still draw (the placeholder).
+        started.wait();
+        assert!(matches!(handle.state(), ImageState::Loading));
+        assert_eq!(handle.color(), None);
+        assert_eq!(render(&handle), Color::BLUE);
+        assert!(queue.channel.as_ref().unwrap().try_recv().is_err());
+
+        release.open();
+        wait_for_repaint(&queue);
+
+        assert!(handle.is_ready());
+        assert_eq!(handle.color(), Some(ImageColor::Srgb));
+        assert_eq!(render(&handle), Color::RED);
+    }
+}
@@ -0,0 +1,523 @@
+//!
+//! A single line of text within a fixed width, which either clips,
+//! ellipsizes or scrolls (marquee) when it doesn't fit, as for window
+//! titles in a taskbar.
+//!
+
+use std::time::{Duration, Instant};
+
+use skia_safe::{Canvas, Font, Paint, Rect};
+
+use crate::util::DirtyFlag;
+
+use super::{picture::CachedPicture, strip::truncate, RenderContext};
+
+///
+/// Space (in logical pixels) between the end of the text and its repeat, whilst scrolling.
+///
+pub const MARQUEE_GAP: f32 = 32.0;
+
+///
+/// What a [Label] does when its text is wider than the space it's given.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowBehavior {
+    Clip,
+    /// Shorten the text, ending it with an ellipsis.
+    Ellipsis,
+    ///
+    /// Scroll the text through, at `speed` logical pixels per second,
+    /// resting at the start for `pause` before each pass.
+    ///
+    Marquee {
+        speed: f32,
+        pause: Duration,
+    },
+}
+
+impl Default for OverflowBehavior {
+    fn default() -> Self {
+        Self::Ellipsis
+    }
+}
+
+///
+/// A line of text, drawn within whatever rect it's given.
+///
+/// Scrolling labels mark their dirty flag (see [Label::track_dirty]) after each
+/// frame, for as long as they overflow and are visible; labels which fit cost
+/// nothing between changes.
+///
+pub struct Label {
+    text: String,
+    font: Font,
+    paint: Paint,
+    overflow: OverflowBehavior,
+    natural_width: f32,
+
+    /// Width truncated for, and the shortened text.
+    ellipsized: Option<(f32, String)>,
+    /// The whole line, recorded once for scrolling.
+    marquee: Option<CachedPicture>,
+    /// When the current run of scrolling began.
+    marquee_started: Option<Instant>,
+
+    visible: bool,
+    dirty: Option<DirtyFlag>,
+}
+
+impl Label {
+    pub fn new(text: impl Into<String>, font: &Font, paint: &Paint) -> Self {
+        let text = text.into();
+
+        Self {
+            natural_width: font.measure_str(&text, Some(paint)).0,
+            text,
+            font: font.clone(),
+            paint: paint.clone(),
+            overflow: OverflowBehavior::default(),
+            ellipsized: None,
+            marquee: None,
+            marquee_started: None,
+            visible: true,
+            dirty: None,
+        }
+    }
+
+    pub fn with_overflow(mut self, overflow: OverflowBehavior) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    ///
+    /// Mark `dirty` when the label changes, and whilst it scrolls.
+    ///
+    pub fn track_dirty(mut self, dirty: DirtyFlag) -> Self {
+        self.dirty.replace(dirty);
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        if text == self.text {
+            return;
+        }
+
+        self.natural_width = self.font.measure_str(&text, Some(&self.paint)).0;
+        self.text = text;
+        self.ellipsized = None;
+        self.marquee = None;
+        self.marquee_started = None;
+        self.mark_dirty();
+    }
+
+    pub fn set_overflow(&mut self, overflow: OverflowBehavior) {
+        if overflow != self.overflow {
+            self.overflow = overflow;
+            self.marquee_started = None;
+            self.mark_dirty();
+        }
+    }
+
+    ///
+    /// Whether the label is on screen: hidden labels stop scrolling (and
+    /// so stop asking for frames), then start over once shown again.
+    ///
+    pub fn set_visible(&mut self, visible: bool) {
+        if visible == self.visible {
+            return;
+        }
+
+        self.visible = visible;
+        self.marquee_started = None;
+
+        if visible {
+            self.mark_dirty();
+        }
+    }
+
+    ///
+    /// Width of the whole text, in logical pixels.
+    ///
+    pub fn natural_width(&self) -> f32 {
+        self.natural_width
+    }
+
+    ///
+    /// Whether the text is wider than `width`.
+    ///
+    pub fn overflows(&self, width: f32) -> bool {
+        overflows(self.natural_width, width)
+    }
+
+    ///
+    /// Whether drawing within `width` scrolls, and so keeps asking for frames.
+    ///
+    pub fn is_scrolling(&self, width: f32) -> bool {
+        self.visible
+            && self.overflows(width)
+            && matches!(self.overflow, OverflowBehavior::Marquee { speed, .. } if speed > 0.0)
+    }
+
+    ///
+    /// The area to hit test the label by, when drawn in `rect`: all of it,
+    /// wherever the text has scrolled to.
+    ///
+    pub fn hit_rect(&self, rect: impl AsRef<Rect>) -> Rect {
+        *rect.as_ref()
+    }
+
+    ///
+    /// Draw the label in `rect`, with scrolling at where it is at `time`
+    /// (e.g. [crate::app::GroupTick::time]).
+    ///
+    pub fn draw(
+        &mut self,
+        canvas: &Canvas,
+        context: &RenderContext,
+        rect: impl AsRef<Rect>,
+        time: Instant,
+    ) {
+        let rect = *rect.as_ref();
+        if !self.visible || rect.is_empty() {
+            return;
+        }
+
+        // Centre the line vertically.
+        let (_, metrics) = self.font.metrics();
+        let baseline = rect.center_y() - (metrics.ascent + metrics.descent) / 2.0;
+
+        // Right-to-left text sits against (and scrolls from) the right edge.
+        let start = if context.is_rtl() {
+            rect.right - self.natural_width.min(rect.width())
+        } else {
+            rect.left
+        };
+
+        if !self.overflows(rect.width()) {
+            self.marquee_started = None;
+            canvas.draw_str(&self.text, (start, baseline), &self.font, &self.paint);
+            return;
+        }
+
+        canvas.save();
+        canvas.clip_rect(rect, None, true);
+
+        match self.overflow {
+            OverflowBehavior::Clip => {
+                canvas.draw_str(&self.text, (start, baseline), &self.font, &self.paint);
+            }
+            OverflowBehavior::Ellipsis => {
+                let text = self.ellipsized(rect.width());
+                canvas.draw_str(text, (rect.left, baseline), &self.font, &self.paint);
+            }
+            OverflowBehavior::Marquee { speed, pause } => {
+                let started = *self.marquee_started.get_or_insert(time);
+                let offset = marquee_offset(
+                    time.saturating_duration_since(started),
+                    self.natural_width,
+                    speed,
+                    pause,
+                );
+
+                let (direction, left) = if context.is_rtl() {
+                    (-1.0, rect.right - self.natural_width)
+                } else {
+                    (1.0, rect.left)
+                };
+
+                let period = self.natural_width + MARQUEE_GAP;
+                let first = left - direction * offset;
+                let positions = [
+                    (first, baseline).into(),
+                    (first + direction * period, baseline).into(),
+                ];
+
+                self.marquee_picture()
+                    .draw_at(canvas, context, &positions, None);
+
+                self.mark_dirty();
+            }
+        }
+
+        canvas.restore();
+    }
+
+    fn ellipsized(&mut self, width: f32) -> &str {
+        if self.ellipsized.as_ref().map(|(for_width, _)| *for_width) != Some(width) {
+            let (text, _) = truncate(&self.text, &self.font, &self.paint, width);
+            self.ellipsized.replace((width, text));
+        }
+
+        &self.ellipsized.as_ref().unwrap().1
+    }
+
+    ///
+    /// The whole line, with its baseline at 0, recorded once.
+    ///
+    fn marquee_picture(&mut self) -> &mut CachedPicture {
+        let (_, metrics) = self.font.metrics();
+        let bounds = Rect::from_ltrb(0.0, metrics.top, self.natural_width, metrics.bottom);
+
+        let (text, font, paint) = (self.text.clone(), self.font.clone(), self.paint.clone());
+        self.marquee.get_or_insert_with(move || {
+            CachedPicture::new(bounds, move |canvas| {
+                canvas.draw_str(&text, (0.0, 0.0), &font, &paint);
+            })
+        })
+    }
+
+    fn mark_dirty(&self) {
+        if let Some(dirty) = &self.dirty {
+            dirty.mark();
+        }
+    }
+}
+
+///
+/// Whether text `natural_width` wide overflows `width`, give or take rounding.
+///
+fn overflows(natural_width: f32, width: f32) -> bool {
+    natural_width > width + 0.5
+}
+
+///
+/// How far a marquee has scrolled `elapsed` into its run: it rests for
+/// `pause`, then scrolls one whole period (text and gap) on, and repeats.
+///
+fn marquee_offset(elapsed: Duration, natural_width: f32, speed: f32, pause: Duration) -> f32 {
+    if speed <= 0.0 {
+        return 0.0;
+    }
+
+    let period = natural_width + MARQUEE_GAP;
+    let scroll = period as f64 / speed as f64;
+    let cycle = pause.as_secs_f64() + scroll;
+
+    let into_cycle = elapsed.as_secs_f64() % cycle;
+    let scrolling = (into_cycle - pause.as_secs_f64()).max(0.0);
+
+    (scrolling * speed as f64) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::{surfaces, Color, FontMgr, FontStyle};
+
+    use super::*;
+    use crate::{
+        graphics::SharedContext,
+        util::{SizeSnapshot, Transform},
+    };
+
+    const TEXT: &str = "The quick brown fox jumps over the lazy dog";
+
+    const WIDTH: i32 = 400;
+    const HEIGHT: i32 = 40;
+
+    fn font() -> Font {
+        let typeface = FontMgr::new()
+            .legacy_make_typeface(None, FontStyle::normal())
+            .expect("no system font to draw with");
+        Font::new(typeface, 16.0)
+    }
+
+    fn label(overflow: OverflowBehavior) -> Label {
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true).set_color(Color::BLACK);
+        Label::new(TEXT, &font(), &paint).with_overflow(overflow)
+    }
+
+    fn context() -> RenderContext {
+        let size = SizeSnapshot {
+            logical: (WIDTH as u32, HEIGHT as u32),
+            physical: (WIDTH as f64, HEIGHT as f64),
+            scale: 1.0,
+            transform: Transform::Normal,
+            generation: 0,
+        };
+
+        RenderContext::new(1, &size, &SharedContext::default())
+    }
+
+    ///
+    /// The columns `label` puts ink in, drawn in `rect` at `time`.
+    ///
+    fn inked_columns(label: &mut Label, rect: Rect, time: Instant) -> Vec<i32> {
+        let mut surface = surfaces::raster_n32_premul((WIDTH, HEIGHT)).unwrap();
+        surface.canvas().clear(Color::TRANSPARENT);
+        label.draw(surface.canvas(), &context(), rect, time);
+
+        let pixmap = surface.peek_pixels().unwrap();
+        (0..WIDTH)
+            .filter(|&x| (0..HEIGHT).any(|y| pixmap.get_color((x, y)).a() > 0))
+            .collect()
+    }
+
+    #[test]
+    fn labels_are_measured_as_their_font_measures_them() {
+        let mut label = label(OverflowBehavior::Clip);
+        let natural = label.natural_width();
+        assert_eq!(natural, font().measure_str(TEXT, None).0);
+
+        assert!(!label.overflows(natural));
+        assert!(!label.overflows(natural - 0.5));
+        assert!(label.overflows(natural - 1.0));
+
+        label.set_text("The quick");
+        assert!(label.natural_width() < natural);
+        assert_eq!(
+            label.natural_width(),
+            font().measure_str("The quick", None).0
+        );
+    }
+
+    #[test]
+    fn labels_which_fit_are_drawn_whole_whatever_their_overflow() {
+        let marquee = OverflowBehavior::Marquee {
+            speed: 30.0,
+            pause: Duration::ZERO,
+        };
+
+        for overflow in [OverflowBehavior::Clip, OverflowBehavior::Ellipsis, marquee] {
+            let mut label = label(overflow);
+            let rect = Rect::from_xywh(10.0, 0.0, label.natural_width() + 20.0, HEIGHT as f32);
+            assert!(!label.is_scrolling(rect.width()));
+
+            let columns = inked_columns(&mut label, rect, Instant::now());
+            let (first, last) = (columns[0] as f32, *columns.last().unwrap() as f32);
+
+            // Ink spans about the natural width, give or take side bearings.
+            let inked = last + 1.0 - first;
+            assert!(
+                (inked - label.natural_width()).abs() < 4.0,
+                "{overflow:?}: {inked} inked of {}",
+                label.natural_width()
+            );
+        }
+    }
+
+    #[test]
+    fn clipped_labels_are_cut_off_at_the_edge() {
+        let mut label = label(OverflowBehavior::Clip);
+        let rect = Rect::from_xywh(10.0, 0.0, 100.0, HEIGHT as f32);
+        assert!(label.overflows(rect.width()));
+        assert!(!label.is_scrolling(rect.width()));
+
+        let columns = inked_columns(&mut label, rect, Instant::now());
+
+        // Drawn up to the edge, mid-glyph, and not a column past it.
+        assert!(columns.iter().all(|&x| (10..110).contains(&x)));
+        assert!(columns.iter().any(|&x| x >= 100), "{columns:?}");
+
+        // The text itself is kept whole.
+        assert_eq!(label.text(), TEXT);
+    }
+
+    #[test]
+    fn ellipsized_labels_are_shortened_to_fit() {
+        let mut label = label(OverflowBehavior::Ellipsis);
+        let paint = label.paint.clone();
+
+        for width in [60.0, 100.0, 200.0] {
+            let shortened = label.ellipsized(width).to_owned();
+            let kept = shortened.strip_suffix('…').expect("no ellipsis");
+
+            assert!(TEXT.starts_with(kept), "{shortened:?}");
+            assert!(!kept.ends_with(' '), "{shortened:?}");
+            assert!(font().measure_str(&shortened, Some(&paint)).0 <= width);
+
+            // As much as fits: the next longer prefix wouldn't.
+            let longer = TEXT
+                .char_indices()
+                .map(|(index, c)| TEXT[..index + c.len_utf8()].trim_end())
+                .find(|prefix| prefix.len() > kept.len());
+            if let Some(longer) = longer {
+                let longer = format!("{longer}…");
+                assert!(font().measure_str(&longer, Some(&paint)).0 > width);
+            }
+        }
+
+        // Not even the ellipsis fits.
+        assert_eq!(label.ellipsized(1.0), "");
+
+        // Redone when the text changes.
+        let before = label.ellipsized(100.0).to_owned();
+        label.set_text("Jackdaws love my big sphinx of quartz");
+        assert_ne!(label.ellipsized(100.0), before);
+        assert!(label.ellipsized(100.0).starts_with("Jackdaws"));
+
+        let rect = Rect::from_xywh(10.0, 0.0, 100.0, HEIGHT as f32);
+        let columns = inked_columns(&mut label, rect, Instant::now());
+        assert!(columns.iter().all(|&x| (10..110).contains(&x)));
+    }
+
+    #[test]
+    fn marquees_scroll_only_when_they_overflow() {
+        let dirty = DirtyFlag::default();
+        let marquee = OverflowBehavior::Marquee {
+            speed: 40.0,
+            pause: Duration::from_secs(1),
+        };
+        let mut label = label(marquee).track_dirty(dirty.clone());
+        let rect = Rect::from_xywh(10.0, 0.0, 100.0, HEIGHT as f32);
+
+        assert!(label.is_scrolling(rect.width()));
+        assert!(!label.is_scrolling(label.natural_width()));
+
+        let start = Instant::now();
+        dirty.take();
+        let resting = inked_columns(&mut label, rect, start);
+        assert!(dirty.take(), "asks for the next frame");
+
+        // Rests at the start for the pause, then moves on at `speed`.
+        assert_eq!(
+            inked_columns(&mut label, rect, start + Duration::from_millis(900)),
+            resting
+        );
+        let moved = inked_columns(&mut label, rect, start + Duration::from_millis(1500));
+        assert_ne!(moved, resting);
+        assert!(moved.iter().all(|&x| (10..110).contains(&x)));
+
+        // Hidden ones stop.
+        label.set_visible(false);
+        assert!(!label.is_scrolling(rect.width()));
+        dirty.take();
+        assert!(inked_columns(&mut label, rect, start).is_empty());
+        assert!(!dirty.is_dirty());
+
+        // So do ones which don't move.
+        label.set_visible(true);
+        label.set_overflow(OverflowBehavior::Marquee {
+            speed: 0.0,
+            pause: Duration::ZERO,
+        });
+        assert!(!label.is_scrolling(rect.width()));
+    }
+
+    #[test]
+    fn marquees_rest_then_scroll_a_whole_period() {
+        let pause = Duration::from_secs(1);
+        let (width, speed) = (168.0, 50.0);
+        // (168 + 32) / 50: four seconds scrolling, after a second's rest.
+        let period = width + MARQUEE_GAP;
+
+        let at = |millis| marquee_offset(Duration::from_millis(millis), width, speed, pause);
+
+        assert_eq!(at(0), 0.0);
+        assert_eq!(at(999), 0.0);
+        assert_eq!(at(2000), 50.0);
+        assert!((at(4999) - period).abs() < 0.1);
+        assert_eq!(at(5000), 0.0);
+        assert_eq!(at(7000), 50.0);
+
+        assert_eq!(
+            marquee_offset(Duration::from_secs(3), width, 0.0, pause),
+            0.0
+        );
+    }
+}
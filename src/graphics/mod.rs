@@ -2,20 +2,87 @@
 //! Support for various graphics backends.
 //!
 
-use std::any::Any;
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
 
-use smithay_client_toolkit::reexports::client::protocol::wl_display::WlDisplay;
+use skia_safe::PixelGeometry;
+use smithay_client_toolkit::reexports::client::protocol::{
+    wl_display::WlDisplay,
+    wl_output::{Subpixel, Transform},
+};
 
 use crate::{
-    util::{AsAny, Size},
+    util::{AsAny, SharedClock, SizeSnapshot},
     wayland::surface::AvySurface,
 };
 
+pub mod blur;
+pub mod color;
+pub mod context;
+pub mod draw;
+pub mod filter;
+pub mod frame;
+pub mod image_cache;
+pub mod label;
+pub mod paints;
+pub mod path_cache;
+pub mod picture;
+pub mod quality;
+pub mod raw_window;
+pub mod shader;
+pub mod snapshot;
+pub mod static_buffer;
+pub mod strip;
+pub mod text;
+#[cfg(feature = "text-cache")]
+pub mod text_cache;
 pub mod vulkan;
 
+pub use color::{ColorResolver, Palette, SemanticRole};
+pub use context::{RenderContext, SharedContext};
+pub use filter::PostFilter;
+pub use frame::{
+    CallbackPanic, ClearBehavior, Frame, FrameOptions, FrameTimings, GpuTimings, GraphicsFrame,
+    SurfaceSuspended,
+};
+pub use image_cache::{ImageCache, ImageColor, ImageHandle, ImageState, LoadOptions};
+pub use label::{Label, OverflowBehavior};
+pub use path_cache::PathCache;
+pub use picture::CachedPicture;
+pub use quality::AdaptiveQuality;
+pub use raw_window::AvySurfaceWindow;
+pub use shader::{EffectError, EffectGraph};
+pub use snapshot::FrameSnapshot;
+pub use strip::{Segment, StripLayout};
+pub use text::{BaselineGrid, FontFeatures, TextLayout, TextSpan};
+#[cfg(feature = "text-cache")]
+pub use text_cache::{ShapedRun, TextCache, TextCacheStats};
+
+///
+/// Serializes rendering on surfaces which share a GPU device (or Skia
+/// context), as those mustn't be used from several threads at once.
+///
+pub type DeviceLock = Arc<Mutex<()>>;
+
+///
+/// How much cached memory to give back, see [GraphicsSurface::trim_memory].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrimLevel {
+    /// Only resources which haven't been used for a while.
+    Light,
+    /// Every resource which isn't in use right now.
+    Moderate,
+    /// Everything which can be recreated, as when suspending (but keeping the swapchain).
+    Aggressive,
+}
+
 pub trait GraphicsBackend {
     type Surface: GraphicsSurface;
-    type Error: std::error::Error + AsAny;
+    type Error: std::error::Error + AsAny + From<CallbackPanic> + From<SurfaceSuspended>;
 
     fn for_surface(
         &self,
@@ -24,10 +91,248 @@ pub trait GraphicsBackend {
     ) -> Result<Self::Surface, Self::Error>;
 }
 
-pub trait GraphicsSurface: Send{
+pub trait GraphicsSurface: Send {
+    ///
+    /// Acquire the next image to draw into, cleared as set by `options`.
+    ///
+    /// Returns `Ok(None)` if no image could be acquired this
+    /// time around (e.g. the swapchain was out of date).
+    ///
+    fn begin_frame(
+        &mut self,
+        size: &SizeSnapshot,
+        options: &FrameOptions,
+    ) -> Result<Option<Frame<'_>>, Box<dyn Any>>;
+
+    ///
+    /// Draw and present a whole frame in one go.
+    ///
+    /// If `callback` panics, the frame is discarded and a
+    /// [CallbackPanic] is returned in place of the backend's error.
+    ///
     fn render(
         &mut self,
-        size: &Size,
+        size: &SizeSnapshot,
         callback: &mut dyn FnMut(&skia_safe::Canvas),
-    ) -> Result<(), Box<dyn Any>>;
+    ) -> Result<(), Box<dyn Any>> {
+        let Some(frame) = self.begin_frame(size, &FrameOptions::default())? else {
+            return Ok(());
+        };
+
+        draw_and_present(frame, callback, || {}).map(|_| ())
+    }
+
+    ///
+    /// Set the subpixel layout used for anti-aliasing text.
+    ///
+    fn set_pixel_geometry(&mut self, _geometry: PixelGeometry) {}
+
+    ///
+    /// Read [FrameTimings] from `clock`, the client's (see [crate::AvyClient::clock]),
+    /// rather than the system's.
+    ///
+    fn set_clock(&mut self, _clock: SharedClock) {}
+
+    ///
+    /// Free as many GPU resources as possible (swapchain, caches, ...)
+    /// whilst keeping the device around so that resuming is quick.
+    ///
+    fn suspend(&mut self) -> Result<(), Box<dyn Any>> {
+        Ok(())
+    }
+
+    ///
+    /// Recreate whatever [GraphicsSurface::suspend] freed, at the given size.
+    ///
+    fn resume(&mut self, _size: &SizeSnapshot) -> Result<(), Box<dyn Any>> {
+        Ok(())
+    }
+
+    fn is_suspended(&self) -> bool {
+        false
+    }
+
+    ///
+    /// The lock held whilst rendering this surface, shared with every
+    /// other surface rendering with the same device.
+    ///
+    fn device_lock(&self) -> DeviceLock;
+
+    ///
+    /// Give back cached resources (e.g. textures of images no longer drawn), which
+    /// are otherwise kept around for as long as they fit in the cache.
+    ///
+    fn trim_memory(&mut self, _level: TrimLevel) {}
+
+    ///
+    /// How many bytes the backend's resource cache holds, if it has one.
+    ///
+    fn cached_bytes(&self) -> Option<usize> {
+        None
+    }
+
+    ///
+    /// Wait for the GPU to be done with every frame submitted so far.
+    ///
+    fn finish(&mut self) -> Result<(), Box<dyn Any>> {
+        Ok(())
+    }
+
+    ///
+    /// Let go of the underlying Wayland surface for good, so that another
+    /// backend can take it over (see [crate::AvyClient::rebind_backend]).
+    ///
+    /// Rendering afterwards must fail with an error, rather than touch the surface.
+    ///
+    fn detach(&mut self) -> Result<(), Box<dyn Any>>;
+
+    ///
+    /// How many frames this surface has successfully presented.
+    ///
+    /// Frames are numbered from 1, so the frame currently
+    /// being drawn is number `presented_frames() + 1`.
+    ///
+    fn presented_frames(&self) -> u64;
+
+    ///
+    /// Human-readable name of this backend, for debugging.
+    ///
+    fn backend_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+///
+/// Draw `callback` into `frame`, then run `before_present` and present it.
+///
+/// If `callback` panics, the frame is discarded and a [CallbackPanic] returned.
+///
+pub fn draw_and_present(
+    mut frame: Frame<'_>,
+    callback: &mut dyn FnMut(&skia_safe::Canvas),
+    before_present: impl FnOnce(),
+) -> Result<FrameTimings, Box<dyn Any>> {
+    let canvas = frame.canvas();
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| callback(canvas))) {
+        drop(frame);
+        return Err(Box::new(CallbackPanic(payload)));
+    }
+
+    before_present();
+    frame.present()
+}
+
+///
+/// The subpixel layout of an output, as seen by its (possibly rotated) contents.
+///
+/// The output's `transform` flips (around the vertical axis) and then turns
+/// the panel counter-clockwise, so e.g. a panel with horizontal RGB stripes
+/// turned by 90° shows them bottom to top, which is [PixelGeometry::BGRV].
+///
+pub fn pixel_geometry_for(subpixel: Subpixel, transform: Transform) -> PixelGeometry {
+    // Along which axis the stripes run, and whether they run from blue to red.
+    let (mut vertical, mut reversed) = match subpixel {
+        Subpixel::HorizontalRgb => (false, false),
+        Subpixel::HorizontalBgr => (false, true),
+        Subpixel::VerticalRgb => (true, false),
+        Subpixel::VerticalBgr => (true, true),
+        _ => return PixelGeometry::Unknown,
+    };
+
+    let (flipped, quarter_turns) = match transform {
+        Transform::Normal => (false, 0),
+        Transform::_90 => (false, 1),
+        Transform::_180 => (false, 2),
+        Transform::_270 => (false, 3),
+        Transform::Flipped => (true, 0),
+        Transform::Flipped90 => (true, 1),
+        Transform::Flipped180 => (true, 2),
+        Transform::Flipped270 => (true, 3),
+        _ => (false, 0),
+    };
+
+    if flipped && !vertical {
+        reversed = !reversed;
+    }
+
+    for _ in 0..quarter_turns {
+        // Turning counter-clockwise, left to right becomes bottom to top,
+        // and top to bottom becomes left to right.
+        if !vertical {
+            reversed = !reversed;
+        }
+        vertical = !vertical;
+    }
+
+    match (vertical, reversed) {
+        (false, false) => PixelGeometry::RGBH,
+        (false, true) => PixelGeometry::BGRH,
+        (true, false) => PixelGeometry::RGBV,
+        (true, true) => PixelGeometry::BGRV,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unrotated_outputs_keep_their_layout() {
+        assert_eq!(
+            pixel_geometry_for(Subpixel::HorizontalRgb, Transform::Normal),
+            PixelGeometry::RGBH
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::VerticalBgr, Transform::Normal),
+            PixelGeometry::BGRV
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::Unknown, Transform::_90),
+            PixelGeometry::Unknown
+        );
+    }
+
+    #[test]
+    fn quarter_turns_either_way_differ() {
+        assert_eq!(
+            pixel_geometry_for(Subpixel::HorizontalRgb, Transform::_90),
+            PixelGeometry::BGRV
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::HorizontalRgb, Transform::_270),
+            PixelGeometry::RGBV
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::VerticalRgb, Transform::_90),
+            PixelGeometry::RGBH
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::VerticalRgb, Transform::_270),
+            PixelGeometry::BGRH
+        );
+    }
+
+    #[test]
+    fn half_turns_and_flips_reverse_the_order() {
+        assert_eq!(
+            pixel_geometry_for(Subpixel::HorizontalRgb, Transform::_180),
+            PixelGeometry::BGRH
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::VerticalRgb, Transform::_180),
+            PixelGeometry::BGRV
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::HorizontalRgb, Transform::Flipped),
+            PixelGeometry::BGRH
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::VerticalRgb, Transform::Flipped),
+            PixelGeometry::RGBV
+        );
+        assert_eq!(
+            pixel_geometry_for(Subpixel::HorizontalRgb, Transform::Flipped90),
+            PixelGeometry::RGBV
+        );
+    }
 }
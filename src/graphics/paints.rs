@@ -0,0 +1,430 @@
+//!
+//! Ready-made paints in AvdanOS' style, so that components share one look
+//! (and one copy of each shader) rather than each rolling their own.
+//!
+//! Colors come from the frame's palette, so theme changes carry through.
+//! Gradients are dithered and noise is sized in physical pixels, so that
+//! neither bands nor blurs at high scales.
+//!
+
+use std::{f32::consts::TAU, sync::Once};
+
+use skia_safe::{
+    canvas::SaveLayerRec, gradient_shader, image_filters, Canvas, Color4f, ImageFilter, Paint,
+    Point, RRect, Rect, TileMode,
+};
+
+use super::{color::SemanticRole, shader::EffectGraph, RenderContext};
+
+///
+/// How fast [brand_gradient] and [brand_sweep] turn, in turns per second.
+///
+const GRADIENT_SPEED: f32 = 0.05;
+
+///
+/// How much of the accent is mixed with white for the gradients' highlight.
+///
+const HIGHLIGHT_MIX: f32 = 0.35;
+
+///
+/// Opacity of [FrostedGlass]'s tint over the blurred backdrop.
+///
+pub const FROSTED_TINT_ALPHA: f32 = 0.55;
+
+///
+/// Film-grain-like noise, in `tint`, changing `24` times a second.
+///
+/// Given coordinates in physical pixels, for one grain per pixel whatever the scale.
+///
+const NOISE_SKSL: &str = r#"
+uniform float4 tint;
+uniform float time;
+
+float hash(float2 p) {
+    p = fract(p * float2(123.34, 456.21));
+    p += dot(p, p + 45.32);
+    return fract(p.x * p.y);
+}
+
+half4 main(float2 coord) {
+    float2 cell = floor(coord);
+    float n = hash(cell + floor(time * 24.0));
+    return half4(tint.rgb * tint.a, tint.a) * half(n);
+}
+"#;
+
+fn mix(a: Color4f, b: Color4f, t: f32) -> Color4f {
+    Color4f::new(
+        a.r + (b.r - a.r) * t,
+        a.g + (b.g - a.g) * t,
+        a.b + (b.b - a.b) * t,
+        a.a + (b.a - a.a) * t,
+    )
+}
+
+///
+/// The accent, its highlight, and the accent again, so that gradients loop seamlessly.
+///
+fn brand_colors(context: &RenderContext) -> [Color4f; 3] {
+    let accent = context.resolve_color(SemanticRole::Accent);
+    let highlight = mix(accent, Color4f::new(1.0, 1.0, 1.0, accent.a), HIGHLIGHT_MIX);
+
+    [accent, highlight, accent]
+}
+
+fn dithered(shader: Option<skia_safe::Shader>) -> Paint {
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    paint.set_dither(true);
+    paint.set_shader(shader);
+    paint
+}
+
+///
+/// A linear gradient across `bounds` in the accent colors,
+/// slowly turning as `time` (in seconds) goes by.
+///
+pub fn brand_gradient(context: &RenderContext, time: f32, bounds: Rect) -> Paint {
+    let angle = time * GRADIENT_SPEED * TAU;
+    let center = bounds.center();
+    let reach = bounds.width().hypot(bounds.height()) / 2.0;
+    let offset = Point::new(angle.cos() * reach, angle.sin() * reach);
+
+    let colors = brand_colors(context);
+    let shader = gradient_shader::linear(
+        (center - offset, center + offset),
+        &colors[..],
+        None,
+        TileMode::Clamp,
+        None,
+        None,
+    );
+
+    dithered(shader)
+}
+
+///
+/// Like [brand_gradient], but sweeping around the middle of `bounds`.
+///
+pub fn brand_sweep(context: &RenderContext, time: f32, bounds: Rect) -> Paint {
+    let start = (time * GRADIENT_SPEED * 360.0) % 360.0;
+    let colors = brand_colors(context);
+
+    let shader = gradient_shader::sweep(
+        bounds.center(),
+        &colors[..],
+        None,
+        TileMode::Repeat,
+        (start, start + 360.0),
+        None,
+        None,
+    );
+
+    dithered(shader)
+}
+
+///
+/// A blurred backdrop with a translucent tint on top, see [frosted_glass].
+///
+pub struct FrostedGlass {
+    backdrop: Option<ImageFilter>,
+    tint: Paint,
+}
+
+impl FrostedGlass {
+    ///
+    /// Blurs whatever has been drawn so far, where it's applied.
+    ///
+    pub fn backdrop(&self) -> Option<&ImageFilter> {
+        self.backdrop.as_ref()
+    }
+
+    pub fn tint(&self) -> &Paint {
+        &self.tint
+    }
+
+    ///
+    /// Frost `rrect`: blur what's behind it, then tint it.
+    ///
+    pub fn draw(&self, canvas: &Canvas, rrect: &RRect) {
+        let save_count = canvas.save();
+        canvas.clip_rrect(rrect, None, true);
+
+        if let Some(backdrop) = &self.backdrop {
+            canvas.save_layer(&SaveLayerRec::default().backdrop(backdrop));
+            canvas.restore();
+        }
+
+        canvas.draw_rrect(rrect, &self.tint);
+        canvas.restore_to_count(save_count);
+    }
+}
+
+///
+/// Frosted glass: what's behind blurred by `blur_sigma` (in logical
+/// pixels, which the canvas' scale carries over), tinted with `tint`.
+///
+pub fn frosted_glass(context: &RenderContext, blur_sigma: f32, tint: SemanticRole) -> FrostedGlass {
+    let backdrop = image_filters::blur(
+        (blur_sigma, blur_sigma),
+        TileMode::Clamp,
+        None,
+        image_filters::CropRect::default(),
+    );
+
+    let mut color = context.resolve_color(tint);
+    color.a *= FROSTED_TINT_ALPHA;
+
+    let mut paint = Paint::new(color, None);
+    paint.set_anti_alias(true);
+
+    FrostedGlass {
+        backdrop,
+        tint: paint,
+    }
+}
+
+///
+/// Animated grain in the accent color, to lay over flat fills.
+///
+/// The shader is compiled once per process, the first time it's used.
+///
+pub fn animated_noise(context: &RenderContext, time: f32) -> Paint {
+    static LOGGED: Once = Once::new();
+
+    let tint = context.resolve_color(SemanticRole::Accent);
+    let shader = EffectGraph::compile(NOISE_SKSL, &[]).and_then(|graph| {
+        graph
+            .uniform("tint", &[tint.r, tint.g, tint.b, tint.a])
+            .uniform("time", &[time])
+            .physical_pixels(context)
+            .build()
+    });
+
+    let mut paint = Paint::default();
+    match shader {
+        Ok(shader) => {
+            paint.set_shader(shader);
+        }
+        Err(err) => LOGGED.call_once(|| log::error!("Could not make the noise shader: {err}")),
+    }
+
+    paint
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::{surfaces, Color};
+
+    use super::*;
+    use crate::{
+        graphics::SharedContext,
+        util::{SizeSnapshot, Transform},
+    };
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    ///
+    /// Most a channel may differ by where one side is dithered, or rounded
+    /// differently on its way through a layer.
+    ///
+    const DITHER_TOLERANCE: u8 = 2;
+
+    const BACKDROP: Color = Color::from_rgb(40, 90, 160);
+
+    fn context(scale: f64) -> RenderContext {
+        let size = SizeSnapshot {
+            logical: (WIDTH, HEIGHT),
+            physical: (WIDTH as f64 * scale, HEIGHT as f64 * scale),
+            scale,
+            transform: Transform::Normal,
+            generation: 0,
+        };
+
+        RenderContext::new(1, &size, &SharedContext::default())
+    }
+
+    fn bounds() -> Rect {
+        Rect::from_wh(WIDTH as f32, HEIGHT as f32)
+    }
+
+    ///
+    /// The pixels `draw` leaves over [BACKDROP], at `scale`.
+    ///
+    fn render(scale: f64, draw: impl Fn(&Canvas, &RenderContext)) -> Vec<u8> {
+        let context = context(scale);
+        let (width, height) = context.physical_size;
+        let mut surface = surfaces::raster_n32_premul((width as i32, height as i32)).unwrap();
+
+        let canvas = surface.canvas();
+        canvas.clear(BACKDROP);
+        canvas.scale((scale as f32, scale as f32));
+        draw(canvas, &context);
+
+        let image = surface.image_snapshot();
+        let pixmap = image.peek_pixels().unwrap();
+        pixmap.bytes().unwrap().to_vec()
+    }
+
+    fn pixel(pixels: &[u8], (x, y): (u32, u32)) -> [u8; 4] {
+        let at = ((y * WIDTH + x) * 4) as usize;
+        pixels[at..at + 4].try_into().unwrap()
+    }
+
+    fn worst_difference(a: &[u8], b: &[u8]) -> u8 {
+        assert_eq!(a.len(), b.len());
+        a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap()
+    }
+
+    fn assert_close(actual: [u8; 4], expected: Color, at: (u32, u32)) {
+        // N32 is BGRA or RGBA depending on the platform: compare as colors.
+        let expected = [expected.r(), expected.g(), expected.b(), expected.a()];
+        let actual = match skia_safe::ColorType::n32() {
+            skia_safe::ColorType::BGRA8888 => [actual[2], actual[1], actual[0], actual[3]],
+            _ => actual,
+        };
+
+        let worst = actual
+            .iter()
+            .zip(&expected)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap();
+        assert!(
+            worst <= DITHER_TOLERANCE,
+            "{actual:?} at {at:?} isn't close to {expected:?}"
+        );
+    }
+
+    #[test]
+    fn gradients_match_their_undithered_golden() {
+        let context = context(1.0);
+        let accent = context.resolve_color(SemanticRole::Accent);
+        let highlight = mix(accent, Color4f::new(1.0, 1.0, 1.0, accent.a), HIGHLIGHT_MIX);
+
+        // Not turned yet: from the left, through the highlight in the middle, to the right.
+        let golden = render(1.0, |canvas, _| {
+            let reach = bounds().width().hypot(bounds().height()) / 2.0;
+            let center = bounds().center();
+            let shader = gradient_shader::linear(
+                (
+                    center - Point::new(reach, 0.0),
+                    center + Point::new(reach, 0.0),
+                ),
+                &[accent, highlight, accent][..],
+                None,
+                TileMode::Clamp,
+                None,
+                None,
+            );
+
+            let mut paint = Paint::default();
+            paint.set_shader(shader);
+            canvas.draw_paint(&paint);
+        });
+
+        let pixels = render(1.0, |canvas, context| {
+            canvas.draw_paint(&brand_gradient(context, 0.0, bounds()));
+        });
+
+        let worst = worst_difference(&pixels, &golden);
+        assert!(worst <= DITHER_TOLERANCE, "differs by {worst}");
+
+        let middle = (WIDTH / 2, HEIGHT / 2);
+        assert_close(pixel(&pixels, middle), highlight.to_color(), middle);
+    }
+
+    #[test]
+    fn gradients_loop_seamlessly() {
+        let turn = 1.0 / GRADIENT_SPEED;
+
+        let paints: [fn(&RenderContext, f32, Rect) -> Paint; 2] = [brand_gradient, brand_sweep];
+        for paint in paints {
+            let golden = render(1.0, |canvas, context| {
+                canvas.draw_paint(&paint(context, 0.0, bounds()));
+            });
+
+            let pixels = render(1.0, |canvas, context| {
+                canvas.draw_paint(&paint(context, turn, bounds()));
+            });
+
+            let worst = worst_difference(&pixels, &golden);
+            assert!(worst <= DITHER_TOLERANCE, "differs by {worst}");
+
+            // A quarter of a turn later, it has moved (half a turn
+            // flips the linear gradient onto itself, colors being symmetric).
+            let pixels = render(1.0, |canvas, context| {
+                canvas.draw_paint(&paint(context, turn / 4.0, bounds()));
+            });
+            assert!(worst_difference(&pixels, &golden) > DITHER_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn frosted_glass_tints_a_flat_backdrop_evenly() {
+        let context = context(2.0);
+        let mut tint = context.resolve_color(SemanticRole::Surface);
+        tint.a *= FROSTED_TINT_ALPHA;
+
+        let rrect = RRect::new_rect_xy(Rect::from_xywh(8.0, 8.0, 40.0, 24.0), 6.0, 6.0);
+        let golden = render(2.0, |_, _| {});
+        let pixels = render(2.0, |canvas, context| {
+            // Little enough that the blur never reaches past the canvas.
+            frosted_glass(context, 2.0, SemanticRole::Surface).draw(canvas, &rrect);
+        });
+
+        // What a blurred flat color is: the same flat color, under the tint.
+        let backdrop = Color4f::from(BACKDROP);
+        let expected = mix(backdrop, Color4f { a: 1.0, ..tint }, tint.a).to_color();
+
+        // Clipped to the rounded rectangle, anti-aliased edges aside.
+        let inner = rrect.with_inset((1.0, 1.0));
+        let outer = rrect.with_outset((1.0, 1.0));
+
+        for y in 0..HEIGHT * 2 {
+            for x in 0..WIDTH * 2 {
+                let covered = Rect::from_xywh(x as f32 / 2.0, y as f32 / 2.0, 0.5, 0.5);
+                let at = ((y * WIDTH * 2 + x) * 4) as usize;
+
+                if !covered.intersects(outer.rect()) {
+                    assert_eq!(pixels[at..at + 4], golden[at..at + 4], "at {x}, {y}");
+                } else if inner.contains(covered) {
+                    let actual = pixels[at..at + 4].try_into().unwrap();
+                    assert_close(actual, expected, (x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_grains_are_one_physical_pixel_whatever_the_scale() {
+        // The same physical canvas, drawn on at one and at two logical pixels per physical.
+        let golden = render(2.0, |canvas, _| {
+            canvas.reset_matrix();
+            canvas.draw_paint(&animated_noise(&context(1.0), 0.0));
+        });
+        let pixels = render(2.0, |canvas, context| {
+            canvas.draw_paint(&animated_noise(context, 0.0));
+        });
+
+        assert!(pixels == golden);
+    }
+
+    #[test]
+    fn noise_changes_24_times_a_second() {
+        let golden = render(1.0, |canvas, context| {
+            canvas.draw_paint(&animated_noise(context, 0.0));
+        });
+
+        let same = render(1.0, |canvas, context| {
+            canvas.draw_paint(&animated_noise(context, 0.5 / 24.0));
+        });
+        assert!(same == golden);
+
+        let next = render(1.0, |canvas, context| {
+            canvas.draw_paint(&animated_noise(context, 1.0 / 24.0));
+        });
+        assert!(next != golden);
+    }
+}
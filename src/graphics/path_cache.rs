@@ -0,0 +1,314 @@
+//!
+//! Vector shapes (the logo, workspace pill outlines, ...) built once in
+//! logical pixels, and kept in device space at the surface's scale.
+//!
+//! Handing Skia the same path every frame (rather than a freshly built one)
+//! also lets it reuse its tessellation of it, on the GPU.
+//!
+//! Constructors for shapes common in the shell ([pill], [squircle] and
+//! [notch_cutout]) live here too, so that components share the path math.
+//!
+
+use std::{collections::HashMap, hash::Hash};
+
+use skia_safe::{
+    AlphaType, Canvas, ColorType, Image, ImageInfo, Matrix, Paint, PaintStyle, Path, PathOp, Point,
+    RRect, Rect,
+};
+
+use super::RenderContext;
+
+///
+/// Shapes with more verbs than this are rasterized once into a coverage
+/// mask (per scale) when filled, if masks are enabled.
+///
+pub const DEFAULT_MASK_THRESHOLD: usize = 256;
+
+///
+/// Points sampled along each quarter of a [squircle].
+///
+const SQUIRCLE_SEGMENTS: usize = 24;
+
+///
+/// A cached shape, and its device-space forms at the scale it was last drawn at.
+///
+struct CachedPath {
+    /// In logical pixels.
+    logical: Path,
+    scale: f64,
+    device: Option<Path>,
+    /// Coverage of the device path, and its top-left corner in physical pixels.
+    mask: Option<(Image, Point)>,
+}
+
+impl CachedPath {
+    fn new(logical: Path) -> Self {
+        Self {
+            logical,
+            scale: 0.0,
+            device: None,
+            mask: None,
+        }
+    }
+
+    ///
+    /// The device-space path, rebuilt first if it was made at another scale.
+    ///
+    fn device(&mut self, scale: f64) -> &Path {
+        if self.device.is_none() || self.scale != scale {
+            let matrix = Matrix::scale((scale as f32, scale as f32));
+            self.device.replace(self.logical.with_transform(&matrix));
+            self.scale = scale;
+            self.mask = None;
+        }
+
+        self.device.as_ref().unwrap()
+    }
+
+    ///
+    /// The coverage mask of the device path, rasterized first if needed.
+    ///
+    fn mask(&mut self, canvas: &Canvas, scale: f64) -> Option<&(Image, Point)> {
+        self.device(scale);
+        if self.mask.is_none() {
+            let device = self.device.as_ref()?;
+            let bounds = device.bounds().round_out();
+            if bounds.is_empty() {
+                return None;
+            }
+
+            let info = ImageInfo::new(
+                (bounds.width(), bounds.height()),
+                ColorType::Alpha8,
+                AlphaType::Premul,
+                None,
+            );
+
+            // Made through the target canvas, so that GPU canvases get a GPU image.
+            let mut surface = canvas.new_surface(&info, None)?;
+            let mut coverage = Paint::default();
+            coverage.set_anti_alias(true);
+
+            surface
+                .canvas()
+                .translate((-bounds.left as f32, -bounds.top as f32))
+                .draw_path(device, &coverage);
+
+            let origin = Point::new(bounds.left as f32, bounds.top as f32);
+            self.mask.replace((surface.image_snapshot(), origin));
+        }
+
+        self.mask.as_ref()
+    }
+}
+
+///
+/// Shapes by ID (any hashable key), each built once from a logical-space
+/// [Path] and drawn from device space.
+///
+/// Device-space paths are rebuilt whenever the surface's scale changes.
+///
+pub struct PathCache<K = u64> {
+    paths: HashMap<K, CachedPath>,
+    mask_threshold: Option<usize>,
+}
+
+impl<K> Default for PathCache<K> {
+    fn default() -> Self {
+        Self {
+            paths: HashMap::new(),
+            mask_threshold: None,
+        }
+    }
+}
+
+impl<K: Hash + Eq> PathCache<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Fill shapes with more than `threshold` verbs from a coverage mask,
+    /// rasterized once per scale, rather than from their path.
+    ///
+    /// Only filled, anti-aliased shapes are drawn from masks; see
+    /// [DEFAULT_MASK_THRESHOLD] for a start.
+    ///
+    pub fn with_mask_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.mask_threshold = threshold;
+        self
+    }
+
+    ///
+    /// Add (or replace) the shape `id`, described in logical pixels.
+    ///
+    pub fn insert(&mut self, id: K, logical: Path) {
+        self.paths.insert(id, CachedPath::new(logical));
+    }
+
+    ///
+    /// Add the shape `id` built by `build`, unless it's already there.
+    ///
+    pub fn get_or_insert_with(&mut self, id: K, build: impl FnOnce() -> Path) -> &Path {
+        &self
+            .paths
+            .entry(id)
+            .or_insert_with(|| CachedPath::new(build()))
+            .logical
+    }
+
+    pub fn contains(&self, id: &K) -> bool {
+        self.paths.contains_key(id)
+    }
+
+    ///
+    /// The shape `id`, in logical pixels.
+    ///
+    pub fn logical(&self, id: &K) -> Option<&Path> {
+        self.paths.get(id).map(|path| &path.logical)
+    }
+
+    pub fn remove(&mut self, id: &K) -> Option<Path> {
+        self.paths.remove(id).map(|path| path.logical)
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    ///
+    /// Throw away every device-space path and mask, e.g. to free memory
+    /// whilst the surface is hidden. They're rebuilt as they're drawn.
+    ///
+    pub fn trim(&mut self) {
+        for path in self.paths.values_mut() {
+            path.device = None;
+            path.mask = None;
+        }
+    }
+
+    ///
+    /// Draw the shape `id` where it was described, with `paint`.
+    ///
+    /// Does nothing if there's no such shape.
+    ///
+    pub fn draw(&mut self, canvas: &Canvas, context: &RenderContext, id: &K, paint: &Paint) {
+        self.draw_at(canvas, context, id, (0.0, 0.0), paint)
+    }
+
+    ///
+    /// Draw the shape `id` offset by `position` (in logical pixels), with `paint`.
+    ///
+    pub fn draw_at(
+        &mut self,
+        canvas: &Canvas,
+        context: &RenderContext,
+        id: &K,
+        position: impl Into<Point>,
+        paint: &Paint,
+    ) {
+        let mask_threshold = self.mask_threshold;
+        let Some(path) = self.paths.get_mut(id) else {
+            return;
+        };
+
+        let scale = context.scale;
+        let use_mask = mask_threshold.is_some_and(|threshold| {
+            paint.style() == PaintStyle::Fill
+                && paint.is_anti_alias()
+                && path.logical.count_verbs() > threshold
+        });
+
+        canvas.save();
+        canvas.translate(position.into());
+        canvas.scale((1.0 / scale as f32, 1.0 / scale as f32));
+
+        let mask = match use_mask {
+            true => path.mask(canvas, scale).cloned(),
+            false => None,
+        };
+
+        match mask {
+            // Alpha-only images are drawn in the paint's color (or shader).
+            Some((mask, origin)) => {
+                canvas.draw_image(mask, origin, Some(paint));
+            }
+            None => {
+                canvas.draw_path(path.device(scale), paint);
+            }
+        }
+
+        canvas.restore();
+    }
+}
+
+///
+/// A rect with fully rounded ends: its shorter sides are semicircles.
+///
+pub fn pill(rect: impl AsRef<Rect>) -> Path {
+    let rect = rect.as_ref();
+    let radius = rect.width().min(rect.height()) / 2.0;
+
+    Path::rrect(RRect::new_rect_xy(rect, radius, radius), None)
+}
+
+///
+/// The superellipse `|x|ⁿ + |y|ⁿ = 1` stretched over `rect`: 2 makes an
+/// ellipse, higher exponents get ever closer to the rect (4 to 5 is the
+/// usual icon squircle).
+///
+pub fn squircle(rect: impl AsRef<Rect>, exponent: f32) -> Path {
+    let rect = rect.as_ref();
+    let exponent = exponent.max(0.1);
+    let (center, radii) = (rect.center(), (rect.width() / 2.0, rect.height() / 2.0));
+
+    let points: Vec<Point> = (0..SQUIRCLE_SEGMENTS * 4)
+        .map(|i| {
+            let angle = i as f32 / (SQUIRCLE_SEGMENTS * 4) as f32 * std::f32::consts::TAU;
+            let (sin, cos) = angle.sin_cos();
+            let curve = |t: f32| t.signum() * t.abs().powf(2.0 / exponent);
+
+            Point::new(
+                center.x + radii.0 * curve(cos),
+                center.y + radii.1 * curve(sin),
+            )
+        })
+        .collect();
+
+    Path::polygon(&points, true, None, None)
+}
+
+///
+/// `rect` with a notch cut out of the middle of its top edge, `notch_width`
+/// wide and `notch_depth` deep, its bottom corners rounded by `radius` (as
+/// for a panel around a camera cutout).
+///
+pub fn notch_cutout(
+    rect: impl AsRef<Rect>,
+    notch_width: f32,
+    notch_depth: f32,
+    radius: f32,
+) -> Path {
+    let rect = rect.as_ref();
+    let outline = Path::rect(rect, None);
+
+    // Reaching past the top edge, so only its bottom corners show.
+    let center = rect.center_x();
+    let notch = Rect::from_ltrb(
+        center - notch_width / 2.0,
+        rect.top - radius,
+        center + notch_width / 2.0,
+        rect.top + notch_depth,
+    );
+    let notch = Path::rrect(RRect::new_rect_xy(notch, radius, radius), None);
+
+    outline.op(&notch, PathOp::Difference).unwrap_or(outline)
+}
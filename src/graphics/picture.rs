@@ -0,0 +1,341 @@
+//!
+//! Record-once, replay-many drawing for repeated elements
+//! (tray icons, workspace dots, ...).
+//!
+
+use skia_safe::{
+    AlphaType, Canvas, ColorType, Image, ImageInfo, Matrix, Paint, Picture, PictureRecorder, Point,
+    Rect,
+};
+
+use crate::util::DirtyFlag;
+
+use super::RenderContext;
+
+///
+/// Replays with more (approximate) operations than this are rasterized
+/// once into an image, if rasterization is enabled.
+///
+pub const DEFAULT_RASTER_THRESHOLD: usize = 64;
+
+///
+/// A drawable recorded into an [skia_safe::Picture], which can then be
+/// stamped out at any number of positions.
+///
+/// The recording is made in physical pixels, and is redone
+/// automatically whenever the surface's scale factor changes.
+///
+pub struct CachedPicture {
+    /// Area covered by the drawable, in logical pixels.
+    bounds: Rect,
+    record: Box<dyn FnMut(&Canvas) + Send>,
+
+    picture: Option<Picture>,
+    recorded_scale: f64,
+    /// Rasterized recording, and its top-left corner in physical pixels.
+    raster: Option<(Image, Point)>,
+    raster_threshold: Option<usize>,
+
+    dirty: Option<DirtyFlag>,
+}
+
+impl CachedPicture {
+    ///
+    /// Create a picture covering `bounds` (in logical pixels), drawn by `record`.
+    ///
+    /// Nothing is recorded until the picture is first drawn.
+    ///
+    pub fn new(bounds: Rect, record: impl FnMut(&Canvas) + Send + 'static) -> Self {
+        Self {
+            bounds,
+            record: Box::new(record),
+            picture: None,
+            recorded_scale: 0.0,
+            raster: None,
+            raster_threshold: None,
+            dirty: None,
+        }
+    }
+
+    ///
+    /// Rasterize the recording to an image once replaying it
+    /// would cost more than `threshold` operations.
+    ///
+    /// When drawing into a GPU canvas, the image lives on the GPU.
+    ///
+    pub fn with_raster_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.raster_threshold = threshold;
+        self.raster = None;
+        self
+    }
+
+    ///
+    /// Mark `dirty` whenever this picture's contents change.
+    ///
+    /// Pass the flag of the surface this picture is drawn on (see
+    /// [crate::app::AvySurfaceHandle::dirty_flag]).
+    ///
+    pub fn track_dirty(mut self, dirty: DirtyFlag) -> Self {
+        self.dirty.replace(dirty);
+        self
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    ///
+    /// Throw away the recording, so the drawable gets called again on
+    /// the next draw.
+    ///
+    pub fn invalidate(&mut self) {
+        self.picture = None;
+        self.raster = None;
+
+        if let Some(dirty) = &self.dirty {
+            dirty.mark();
+        }
+    }
+
+    ///
+    /// Replace the drawable (and, optionally, its bounds).
+    ///
+    pub fn set_drawable(
+        &mut self,
+        bounds: Option<Rect>,
+        record: impl FnMut(&Canvas) + Send + 'static,
+    ) {
+        if let Some(bounds) = bounds {
+            self.bounds = bounds;
+        }
+
+        self.record = Box::new(record);
+        self.invalidate();
+    }
+
+    ///
+    /// The current recording, re-recording it first if it's
+    /// missing or was made at a different scale.
+    ///
+    pub fn picture(&mut self, context: &RenderContext) -> Option<&Picture> {
+        if self.picture.is_none() || self.recorded_scale != context.scale {
+            self.rerecord(context.scale);
+        }
+
+        self.picture.as_ref()
+    }
+
+    ///
+    /// Draw this picture once, with its origin at `position` (in logical pixels).
+    ///
+    pub fn draw(
+        &mut self,
+        canvas: &Canvas,
+        context: &RenderContext,
+        position: impl Into<Point>,
+        paint: Option<&Paint>,
+    ) {
+        self.draw_at(canvas, context, &[position.into()], paint)
+    }
+
+    ///
+    /// Draw this picture at each of `positions` (in logical pixels).
+    ///
+    pub fn draw_at(
+        &mut self,
+        canvas: &Canvas,
+        context: &RenderContext,
+        positions: &[Point],
+        paint: Option<&Paint>,
+    ) {
+        if positions.is_empty() || self.picture(context).is_none() {
+            return;
+        }
+
+        let scale = self.recorded_scale as f32;
+        self.rasterize_if_expensive(canvas);
+
+        for position in positions {
+            canvas.save();
+            canvas.translate(*position);
+            canvas.scale((1.0 / scale, 1.0 / scale));
+
+            match (&self.raster, &self.picture) {
+                (Some((image, origin)), _) => {
+                    canvas.draw_image(image, *origin, paint);
+                }
+                (None, Some(picture)) => {
+                    canvas.draw_picture(picture, None, paint);
+                }
+                (None, None) => {}
+            }
+
+            canvas.restore();
+        }
+    }
+
+    fn physical_bounds(&self, scale: f32) -> Rect {
+        Rect::from_ltrb(
+            self.bounds.left * scale,
+            self.bounds.top * scale,
+            self.bounds.right * scale,
+            self.bounds.bottom * scale,
+        )
+    }
+
+    fn rerecord(&mut self, scale: f64) {
+        let bounds = self.physical_bounds(scale as f32);
+
+        let mut recorder = PictureRecorder::new();
+        let canvas = recorder.begin_recording(bounds, None);
+        canvas.scale((scale as f32, scale as f32));
+        (self.record)(canvas);
+
+        // Damage was already reported by [CachedPicture::invalidate],
+        // and a scale change repaints the whole surface anyway.
+        self.picture = recorder.finish_recording_as_picture(Some(&bounds));
+        self.recorded_scale = scale;
+        self.raster = None;
+    }
+
+    fn rasterize_if_expensive(&mut self, canvas: &Canvas) {
+        let (Some(threshold), None, Some(picture)) =
+            (self.raster_threshold, &self.raster, &self.picture)
+        else {
+            return;
+        };
+
+        if picture.approximate_op_count() <= threshold {
+            return;
+        }
+
+        let bounds = self.physical_bounds(self.recorded_scale as f32).round_out();
+        let info = ImageInfo::new(
+            (bounds.width(), bounds.height()),
+            ColorType::N32,
+            AlphaType::Premul,
+            canvas.image_info().color_space(),
+        );
+
+        // Made through the target canvas, so that GPU canvases get a GPU image.
+        let Some(mut surface) = canvas.new_surface(&info, None) else {
+            return;
+        };
+
+        let matrix = Matrix::translate((-bounds.left as f32, -bounds.top as f32));
+        surface.canvas().draw_picture(picture, Some(&matrix), None);
+        let origin = Point::new(bounds.left as f32, bounds.top as f32);
+        self.raster.replace((surface.image_snapshot(), origin));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use skia_safe::{surfaces, Color};
+
+    use super::*;
+    use crate::{
+        graphics::SharedContext,
+        util::{SizeSnapshot, Transform},
+    };
+
+    fn context(scale: f64) -> RenderContext {
+        let size = SizeSnapshot {
+            logical: (64, 16),
+            physical: (64.0 * scale, 16.0 * scale),
+            scale,
+            transform: Transform::Normal,
+            generation: 0,
+        };
+
+        RenderContext::new(1, &size, &SharedContext::default())
+    }
+
+    /// A 4×4 red square, which counts how often it's recorded.
+    fn square(recordings: &Arc<AtomicUsize>) -> CachedPicture {
+        let recordings = recordings.clone();
+
+        CachedPicture::new(Rect::from_wh(4.0, 4.0), move |canvas| {
+            recordings.fetch_add(1, Ordering::Relaxed);
+            canvas.draw_rect(
+                Rect::from_wh(4.0, 4.0),
+                Paint::default().set_color(Color::RED),
+            );
+        })
+    }
+
+    fn pixel(surface: &mut skia_safe::Surface, x: i32, y: i32) -> Color {
+        let image = surface.image_snapshot();
+        let pixmap = image.peek_pixels().unwrap();
+        pixmap.get_color((x, y))
+    }
+
+    #[test]
+    fn records_once_and_replays_at_every_position() {
+        let recordings = Arc::new(AtomicUsize::new(0));
+        let mut picture = square(&recordings);
+        let mut surface = surfaces::raster_n32_premul((64, 16)).unwrap();
+        let context = context(1.0);
+
+        let positions = [
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 0.0),
+            Point::new(20.0, 8.0),
+        ];
+        picture.draw_at(surface.canvas(), &context, &positions, None);
+        picture.draw_at(surface.canvas(), &context, &positions, None);
+
+        assert_eq!(recordings.load(Ordering::Relaxed), 1);
+        for (x, y) in [(1, 1), (11, 1), (21, 9)] {
+            assert_eq!(pixel(&mut surface, x, y), Color::RED);
+        }
+        assert_eq!(pixel(&mut surface, 6, 1), Color::TRANSPARENT);
+    }
+
+    #[test]
+    fn rerecords_when_the_scale_changes() {
+        let recordings = Arc::new(AtomicUsize::new(0));
+        let mut picture = square(&recordings);
+
+        picture.picture(&context(1.0));
+        picture.picture(&context(1.0));
+        assert_eq!(recordings.load(Ordering::Relaxed), 1);
+
+        let recorded = picture.picture(&context(2.0)).unwrap().cull_rect();
+        assert_eq!(recordings.load(Ordering::Relaxed), 2);
+        assert_eq!(recorded, Rect::from_wh(8.0, 8.0));
+    }
+
+    #[test]
+    fn invalidating_marks_the_surface_dirty() {
+        let recordings = Arc::new(AtomicUsize::new(0));
+        let dirty = DirtyFlag::default();
+        let mut picture = square(&recordings).track_dirty(dirty.clone());
+
+        picture.picture(&context(1.0));
+        dirty.take();
+
+        picture.invalidate();
+        assert!(dirty.take());
+
+        picture.picture(&context(1.0));
+        assert_eq!(recordings.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn rasterizes_expensive_recordings() {
+        let recordings = Arc::new(AtomicUsize::new(0));
+        let mut picture = square(&recordings).with_raster_threshold(Some(0));
+        let mut surface = surfaces::raster_n32_premul((64, 16)).unwrap();
+
+        picture.draw(surface.canvas(), &context(1.0), (10.0, 0.0), None);
+
+        assert!(picture.raster.is_some());
+        assert_eq!(pixel(&mut surface, 11, 1), Color::RED);
+    }
+}
@@ -0,0 +1,306 @@
+//!
+//! Adaptive render quality: surfaces whose frames keep taking longer than
+//! their output's refresh interval are rendered at a fraction of their scale
+//! (and scaled back up by the viewport), rather than dropping frames.
+//!
+//! See [crate::app::AvySurfaceHandle::set_adaptive_quality].
+//!
+
+use std::time::Duration;
+
+use super::{FrameTimings, GpuTimings};
+
+///
+/// Bounds and pacing of adaptive render quality.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveQuality {
+    /// Lowest fraction of the scale to render at.
+    pub min: f64,
+    /// Highest fraction of the scale to render at, usually `1.0`.
+    pub max: f64,
+    /// How much the quality changes by at once.
+    pub step: f64,
+    /// Consecutive frames over budget before lowering the quality.
+    pub overrun_frames: u32,
+    /// Consecutive frames within [AdaptiveQuality::headroom] of the
+    /// budget before raising the quality again.
+    pub headroom_frames: u32,
+    /// Fraction of the budget frames must fit in to raise the quality.
+    pub headroom: f64,
+}
+
+impl Default for AdaptiveQuality {
+    fn default() -> Self {
+        Self {
+            min: 0.5,
+            max: 1.0,
+            step: 0.1,
+            overrun_frames: 8,
+            headroom_frames: 120,
+            headroom: 0.6,
+        }
+    }
+}
+
+///
+/// Longest wait for headroom, however often raising the quality backfires.
+///
+const MAX_HEADROOM_BACKOFF: u32 = 16;
+
+///
+/// Picks the render quality of a surface from how long its frames take.
+///
+#[derive(Debug, Clone)]
+pub struct QualityController {
+    config: AdaptiveQuality,
+    quality: f64,
+    overruns: u32,
+    within_headroom: u32,
+    /// Multiplies [AdaptiveQuality::headroom_frames]: doubled whenever raising
+    /// the quality is soon undone, so that it settles rather than oscillates.
+    backoff: u32,
+    /// Whether the last change raised the quality, and nothing was measured at it yet.
+    raised: bool,
+}
+
+impl QualityController {
+    pub fn new(config: AdaptiveQuality) -> Self {
+        let max = config.max.clamp(0.1, 1.0);
+        let config = AdaptiveQuality {
+            min: config.min.clamp(0.1, max),
+            max,
+            step: config.step.max(0.01),
+            ..config
+        };
+
+        Self {
+            quality: config.max,
+            config,
+            overruns: 0,
+            within_headroom: 0,
+            backoff: 1,
+            raised: false,
+        }
+    }
+
+    pub fn config(&self) -> AdaptiveQuality {
+        self.config
+    }
+
+    pub fn quality(&self) -> f64 {
+        self.quality
+    }
+
+    ///
+    /// Count a frame which took `frame_time`, against `budget`. Returns
+    /// the quality to render at from now on, if it changed.
+    ///
+    pub fn record(&mut self, frame_time: Duration, budget: Duration) -> Option<f64> {
+        if frame_time > budget {
+            self.within_headroom = 0;
+            self.overruns += 1;
+
+            if self.raised {
+                // Raising it made frames late again.
+                self.backoff = (self.backoff * 2).min(MAX_HEADROOM_BACKOFF);
+                self.raised = false;
+            }
+
+            if self.overruns < self.config.overrun_frames || self.quality <= self.config.min {
+                return None;
+            }
+
+            self.overruns = 0;
+            self.quality = (self.quality - self.config.step).max(self.config.min);
+            return Some(self.quality);
+        }
+
+        self.overruns = 0;
+
+        if frame_time.as_secs_f64() > budget.as_secs_f64() * self.config.headroom {
+            self.within_headroom = 0;
+            return None;
+        }
+
+        self.within_headroom += 1;
+        self.raised = false;
+
+        let needed = self.config.headroom_frames.saturating_mul(self.backoff);
+        if self.within_headroom < needed || self.quality >= self.config.max {
+            return None;
+        }
+
+        self.within_headroom = 0;
+        self.quality = (self.quality + self.config.step).min(self.config.max);
+        self.raised = true;
+        Some(self.quality)
+    }
+}
+
+///
+/// How long `timings`' frame took: the longer of the time spent drawing
+/// it on the CPU and, if measured, on the GPU.
+///
+pub fn frame_cost(timings: &FrameTimings) -> Option<Duration> {
+    let drawn = timings.drawn.or(timings.presented)?;
+    let cpu = drawn.saturating_duration_since(timings.acquired);
+
+    Some(match timings.gpu {
+        GpuTimings::Measured { total, .. } => cpu.max(total),
+        _ => cpu,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 60Hz output's refresh interval.
+    const BUDGET: Duration = Duration::from_micros(16_667);
+
+    ///
+    /// Feed `frames` frames to `controller`, each taking what `frame_time`
+    /// says it takes at the quality it's drawn at. Returns the quality each
+    /// frame was drawn at.
+    ///
+    fn run(
+        controller: &mut QualityController,
+        frames: usize,
+        mut frame_time: impl FnMut(usize, f64) -> Duration,
+    ) -> Vec<f64> {
+        (0..frames)
+            .map(|frame| {
+                let quality = controller.quality();
+                controller.record(frame_time(frame, quality), BUDGET);
+                quality
+            })
+            .collect()
+    }
+
+    ///
+    /// Frames which are drawn at a different quality from the one before.
+    ///
+    fn changes(qualities: &[f64]) -> Vec<usize> {
+        (1..qualities.len())
+            .filter(|&frame| qualities[frame] != qualities[frame - 1])
+            .collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    ///
+    /// Time spent per frame grows with the pixels drawn, the square of the quality.
+    ///
+    fn pixel_bound(full: Duration) -> impl FnMut(usize, f64) -> Duration {
+        move |_, quality| full.mul_f64(quality * quality)
+    }
+
+    #[test]
+    fn settles_on_the_best_quality_which_keeps_up() {
+        // 25ms at full quality, 20.25ms at 0.9 and 16ms at 0.8.
+        let mut controller = QualityController::new(AdaptiveQuality::default());
+        let qualities = run(
+            &mut controller,
+            10_000,
+            pixel_bound(Duration::from_millis(25)),
+        );
+
+        // Down a step every eight late frames, then not again.
+        assert_eq!(changes(&qualities), [8, 16]);
+        assert!(close(controller.quality(), 0.8));
+    }
+
+    #[test]
+    fn stays_at_full_quality_when_frames_fit() {
+        let mut controller = QualityController::new(AdaptiveQuality::default());
+        let qualities = run(
+            &mut controller,
+            10_000,
+            pixel_bound(Duration::from_millis(12)),
+        );
+
+        assert!(changes(&qualities).is_empty());
+        assert_eq!(controller.quality(), 1.0);
+    }
+
+    #[test]
+    fn scattered_late_frames_are_ridden_out() {
+        // 14 to 19ms at full quality: often late, but never eight frames in a row.
+        let mut seed = 0x2545_f491_u32;
+        let mut controller = QualityController::new(AdaptiveQuality::default());
+        let qualities = run(&mut controller, 10_000, |frame, quality| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let late = frame % 8 != 7 && seed % 3 == 0;
+            let millis = if late { 19.0 } else { 14.0 };
+            Duration::from_secs_f64(millis / 1000.0 * quality * quality)
+        });
+
+        assert!(changes(&qualities).is_empty());
+    }
+
+    #[test]
+    fn recovers_once_frames_get_cheap_again() {
+        let mut controller = QualityController::new(AdaptiveQuality::default());
+        let qualities = run(&mut controller, 6_000, |frame, quality| {
+            // Heavy for a while (only keeping up at half quality), then light.
+            let full = if frame < 1_000 { 60.0 } else { 8.0 };
+            Duration::from_secs_f64(full / 1000.0 * quality * quality)
+        });
+
+        assert!(close(qualities[999], 0.5), "{}", qualities[999]);
+        assert_eq!(controller.quality(), 1.0);
+
+        // Up a step per 120 frames with room to spare, not all at once.
+        let raised = changes(&qualities)
+            .into_iter()
+            .filter(|&frame| frame > 1_000)
+            .collect::<Vec<_>>();
+        assert_eq!(raised.len(), 5);
+        assert!(raised.windows(2).all(|pair| pair[1] - pair[0] == 120));
+    }
+
+    #[test]
+    fn backs_off_rather_than_oscillating_over_a_cliff() {
+        // Cheap up to 0.7, then far over budget (e.g. once a texture no
+        // longer fits in memory): raising the quality always backfires.
+        let cliff = |_, quality: f64| {
+            if quality < 0.75 {
+                Duration::from_millis(8)
+            } else {
+                Duration::from_millis(30)
+            }
+        };
+
+        let mut controller = QualityController::new(AdaptiveQuality::default());
+        let qualities = run(&mut controller, 60_000, cliff);
+
+        // Each retry waits twice as long as the last, up to 16 times 120 frames.
+        let raises: Vec<_> = changes(&qualities)
+            .into_iter()
+            .filter(|&frame| {
+                qualities[frame] > qualities[frame - 1] && close(qualities[frame], 0.8)
+            })
+            .collect();
+        let waits: Vec<_> = raises.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert!(waits.len() > 4, "{raises:?}");
+        assert!(waits.windows(2).all(|pair| pair[1] >= pair[0]), "{waits:?}");
+        assert!(
+            waits.iter().rev().take(3).all(|&wait| wait >= 16 * 120),
+            "{waits:?}"
+        );
+
+        // So nearly every frame is drawn at the quality which keeps up.
+        let settled = &qualities[10_000..];
+        let kept_up = settled
+            .iter()
+            .filter(|&&quality| close(quality, 0.7))
+            .count();
+        assert!(kept_up as f64 / settled.len() as f64 > 0.99, "{kept_up}");
+        assert!(settled.iter().all(|&quality| quality >= 0.7 - 1e-9));
+    }
+}
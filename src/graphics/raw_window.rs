@@ -0,0 +1,194 @@
+//!
+//! Surfaces drawn into by renderers of their own (wgpu, OpenGL, ...) rather
+//! than by a [super::GraphicsBackend], through `raw-window-handle`.
+//!
+//! Enable the `raw-window-handle` feature for version 0.6 of its traits,
+//! and `raw-window-handle-05` for version 0.5.
+//!
+
+use std::sync::{Arc, RwLock};
+
+use smithay_client_toolkit::reexports::client::{
+    protocol::{wl_display::WlDisplay, wl_surface::WlSurface},
+    Proxy,
+};
+use wayland_backend::client::ObjectId;
+
+use crate::{
+    app::SurfaceShared,
+    util::{Size, SizeSnapshot},
+    wayland::surface::events::{Subscription, SurfaceEvent},
+};
+
+///
+/// A registered surface, handed over to another renderer with
+/// [crate::app::RegisteredSurface::into_raw_window].
+///
+/// [crate::AvyClient] keeps handling configures and scale changes, and
+/// input keeps flowing through the surface's handler traits. The renderer
+/// draws at [SizeSnapshot::physical_size], redrawing when told to through
+/// [AvySurfaceWindow::on_resize], and calls [AvySurfaceWindow::prepare_present]
+/// right before presenting each frame.
+///
+/// The handles stay valid for as long as the surface does: the renderer's
+/// own surface must be dropped before the Avy surface is destroyed.
+///
+#[derive(Clone)]
+pub struct AvySurfaceWindow {
+    wl_display: WlDisplay,
+    wl_surface: WlSurface,
+    size: Arc<RwLock<Size>>,
+    state: Arc<SurfaceShared>,
+}
+
+impl AvySurfaceWindow {
+    pub(crate) fn new(
+        wl_display: WlDisplay,
+        wl_surface: WlSurface,
+        size: Arc<RwLock<Size>>,
+        state: Arc<SurfaceShared>,
+    ) -> Self {
+        Self {
+            wl_display,
+            wl_surface,
+            size,
+            state,
+        }
+    }
+
+    ///
+    /// The surface's ID, as used by [crate::AvyClient]'s methods.
+    ///
+    pub fn id(&self) -> ObjectId {
+        self.wl_surface.id()
+    }
+
+    pub fn wl_surface(&self) -> &WlSurface {
+        &self.wl_surface
+    }
+
+    pub fn wl_display(&self) -> &WlDisplay {
+        &self.wl_display
+    }
+
+    pub fn size(&self) -> SizeSnapshot {
+        self.size.read().unwrap().snapshot()
+    }
+
+    ///
+    /// Call `callback` with the surface's new size whenever it's configured
+    /// or its scale changes, on the event loop, until the returned
+    /// [Subscription] is dropped.
+    ///
+    pub fn on_resize(
+        &self,
+        mut callback: impl FnMut(SizeSnapshot) + Send + 'static,
+    ) -> Subscription {
+        let size = self.size.clone();
+
+        self.state.events.subscribe(move |event| match event {
+            SurfaceEvent::Configured { .. } | SurfaceEvent::ScaleChanged { .. } => {
+                callback(size.read().unwrap().snapshot())
+            }
+            _ => {}
+        })
+    }
+
+    ///
+    /// All of the surface's [SurfaceEvent]s, see [crate::app::AvySurfaceHandle::on_event].
+    ///
+    pub fn on_event(&self, callback: impl FnMut(SurfaceEvent) + Send + 'static) -> Subscription {
+        self.state.events.subscribe(callback)
+    }
+
+    ///
+    /// Get the surface ready for a frame drawn at `size` (as from
+    /// [AvySurfaceWindow::size]), about to be presented: set up the viewport
+    /// for it, and acknowledge the configure it was drawn for.
+    ///
+    pub fn prepare_present(&self, size: &SizeSnapshot) {
+        self.state
+            .prepare_present_at(size.logical_size(), size.physical_size());
+    }
+
+    ///
+    /// Say whether the frame [AvySurfaceWindow::prepare_present] was called
+    /// for was committed. Until then, the viewport is set up again for every
+    /// frame, in case the last one never made it.
+    ///
+    pub fn presented(&self, committed: bool) {
+        self.state.viewport.lock().unwrap().presented(committed);
+    }
+
+    fn surface_ptr(&self) -> *mut std::ffi::c_void {
+        self.wl_surface.id().as_ptr().cast()
+    }
+
+    fn display_ptr(&self) -> *mut std::ffi::c_void {
+        self.wl_display.id().as_ptr().cast()
+    }
+}
+
+#[cfg(feature = "raw-window-handle")]
+mod rwh_06 {
+    use std::ptr::NonNull;
+
+    use raw_window_handle::{
+        DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+        RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle, WindowHandle,
+    };
+
+    use super::AvySurfaceWindow;
+
+    impl HasWindowHandle for AvySurfaceWindow {
+        fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+            // Null once the surface is destroyed.
+            let surface = NonNull::new(self.surface_ptr()).ok_or(HandleError::Unavailable)?;
+            let raw = RawWindowHandle::Wayland(WaylandWindowHandle::new(surface));
+
+            // SAFETY: The surface lives until destroyed through the client,
+            // which the handle's user is told to outlive.
+            Ok(unsafe { WindowHandle::borrow_raw(raw) })
+        }
+    }
+
+    impl HasDisplayHandle for AvySurfaceWindow {
+        fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+            let display = NonNull::new(self.display_ptr()).ok_or(HandleError::Unavailable)?;
+            let raw = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(display));
+
+            // SAFETY: The display lives for as long as the connection.
+            Ok(unsafe { DisplayHandle::borrow_raw(raw) })
+        }
+    }
+}
+
+#[cfg(feature = "raw-window-handle-05")]
+mod rwh_05 {
+    use raw_window_handle_05::{
+        HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+        WaylandDisplayHandle, WaylandWindowHandle,
+    };
+
+    use super::AvySurfaceWindow;
+
+    ///
+    /// SAFETY: The pointers stay valid for as long as the surface
+    /// (and connection) do, see [AvySurfaceWindow].
+    ///
+    unsafe impl HasRawWindowHandle for AvySurfaceWindow {
+        fn raw_window_handle(&self) -> RawWindowHandle {
+            let mut handle = WaylandWindowHandle::empty();
+            handle.surface = self.surface_ptr();
+            RawWindowHandle::Wayland(handle)
+        }
+    }
+
+    unsafe impl HasRawDisplayHandle for AvySurfaceWindow {
+        fn raw_display_handle(&self) -> RawDisplayHandle {
+            let mut handle = WaylandDisplayHandle::empty();
+            handle.display = self.display_ptr();
+            RawDisplayHandle::Wayland(handle)
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//!
+//! Building shaders from SkSL runtime effects by name, rather than by
+//! hand-packed uniform bytes and positional children.
+//!
+//! Compiled effects are cached for the life of the process, keyed by
+//! their source, so building the same effect every frame is cheap.
+//!
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Mutex, OnceLock},
+};
+
+use skia_safe::{
+    runtime_effect::{ChildPtr, ChildType},
+    Data, Matrix, RuntimeEffect, Shader,
+};
+use thiserror::Error;
+
+use super::RenderContext;
+
+#[derive(Debug, Clone, Error)]
+pub enum EffectError {
+    #[error("Could not compile the effect: {0}")]
+    Compile(String),
+    #[error(
+        "The effect's children don't match those declared: undeclared [{}], not in the SkSL [{}]",
+        names(.undeclared),
+        names(.missing)
+    )]
+    Declaration {
+        /// In the SkSL, but not declared.
+        undeclared: Vec<String>,
+        /// Declared, but not in the SkSL.
+        missing: Vec<String>,
+    },
+    #[error("Children left unbound: [{}], unknown children bound: [{}]", names(.unbound), names(.unknown))]
+    Children {
+        unbound: Vec<String>,
+        unknown: Vec<String>,
+    },
+    #[error("Child {name:?} is a {expected:?}, but was bound to a {bound:?}")]
+    ChildType {
+        name: String,
+        expected: ChildType,
+        bound: ChildType,
+    },
+    #[error("The effect has no uniform {0:?}")]
+    UnknownUniform(String),
+    #[error("Uniform {name:?} takes {expected} bytes, but was given {given}")]
+    UniformSize {
+        name: String,
+        expected: usize,
+        given: usize,
+    },
+    #[error("Uniforms left unset: [{}]", names(.0))]
+    UnsetUniforms(Vec<String>),
+    #[error("Skia could not make the shader")]
+    Shader,
+}
+
+fn names(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("{name:?}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+///
+/// A compiled [RuntimeEffect], shared between threads.
+///
+struct SharedEffect(RuntimeEffect);
+
+///
+/// SAFETY: Runtime effects are immutable once compiled, and
+/// Skia reference counts them atomically, so sharing one
+/// between threads (to make shaders from) is fine.
+///
+unsafe impl Send for SharedEffect {}
+unsafe impl Sync for SharedEffect {}
+
+type EffectCache = HashMap<u64, Result<SharedEffect, String>>;
+
+///
+/// Compile `sksl` as a shader, or reuse the effect it was compiled to before.
+///
+/// Failures are cached as well, so broken SkSL isn't compiled every frame.
+///
+fn compile_cached(sksl: &str) -> Result<RuntimeEffect, EffectError> {
+    static CACHE: OnceLock<Mutex<EffectCache>> = OnceLock::new();
+
+    let mut hasher = DefaultHasher::new();
+    sksl.hash(&mut hasher);
+    let key = hasher.finish();
+
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+
+    // Check the source as well, in case two ever hash the same.
+    let cached = cache.get(&key).filter(|cached| match cached {
+        Ok(effect) => effect.0.source() == sksl,
+        Err(_) => true,
+    });
+
+    if cached.is_none() {
+        let compiled = RuntimeEffect::make_for_shader(sksl, None).map(SharedEffect);
+        cache.insert(key, compiled);
+    }
+
+    match &cache[&key] {
+        Ok(effect) => Ok(effect.0.clone()),
+        Err(err) => Err(EffectError::Compile(err.clone())),
+    }
+}
+
+///
+/// Builds a shader from an SkSL runtime effect, with its uniforms and
+/// child shaders (e.g. an image to blur, or noise to distort) set by name.
+///
+/// ```ignore
+/// let shader = EffectGraph::compile(SKSL, &["background"])?
+///     .child("background", image_shader)
+///     .uniform("time", &[time])
+///     .build()?;
+/// ```
+///
+/// Mistakes are collected whilst building, and reported by [EffectGraph::build].
+///
+#[derive(Clone)]
+pub struct EffectGraph {
+    effect: RuntimeEffect,
+    uniforms: Vec<u8>,
+    /// By uniform, in the effect's order.
+    set_uniforms: Vec<bool>,
+    /// By child, in the effect's order.
+    children: Vec<Option<ChildPtr>>,
+    unknown_children: Vec<String>,
+    error: Option<EffectError>,
+    local_matrix: Option<Matrix>,
+    /// Set by [EffectGraph::physical_pixels].
+    scale: Option<f64>,
+}
+
+impl EffectGraph {
+    ///
+    /// Compile (or fetch from the cache) `sksl`, whose child
+    /// shaders (`uniform shader name;`) must be exactly `children`.
+    ///
+    pub fn compile(sksl: &str, children: &[&str]) -> Result<Self, EffectError> {
+        let effect = compile_cached(sksl)?;
+
+        let undeclared: Vec<_> = effect
+            .children()
+            .iter()
+            .map(|child| child.name())
+            .filter(|name| !children.contains(name))
+            .map(str::to_owned)
+            .collect();
+
+        let missing: Vec<_> = children
+            .iter()
+            .filter(|name| effect.find_child(name).is_none())
+            .map(|name| name.to_string())
+            .collect();
+
+        if !undeclared.is_empty() || !missing.is_empty() {
+            return Err(EffectError::Declaration {
+                undeclared,
+                missing,
+            });
+        }
+
+        Ok(Self {
+            uniforms: vec![0; effect.uniform_size()],
+            set_uniforms: vec![false; effect.uniforms().len()],
+            children: vec![None; effect.children().len()],
+            unknown_children: Vec::new(),
+            error: None,
+            local_matrix: None,
+            scale: None,
+            effect,
+        })
+    }
+
+    pub fn effect(&self) -> &RuntimeEffect {
+        &self.effect
+    }
+
+    ///
+    /// Bind the child shader (or color filter, or blender) `name`.
+    ///
+    pub fn child(mut self, name: &str, child: impl Into<ChildPtr>) -> Self {
+        let child = child.into();
+
+        let Some(declared) = self.effect.find_child(name) else {
+            self.unknown_children.push(name.to_string());
+            return self;
+        };
+
+        if declared.ty() != child.ty() {
+            self.error.get_or_insert(EffectError::ChildType {
+                name: name.to_string(),
+                expected: declared.ty(),
+                bound: child.ty(),
+            });
+        }
+
+        let index = declared.index();
+        self.children[index] = Some(child);
+        self
+    }
+
+    ///
+    /// Set the float uniform (or vector, or matrix, or array of them) `name`.
+    ///
+    pub fn uniform(self, name: &str, values: &[f32]) -> Self {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        self.uniform_bytes(name, &bytes)
+    }
+
+    ///
+    /// Set the integer uniform (or vector, or array of them) `name`.
+    ///
+    pub fn uniform_ints(self, name: &str, values: &[i32]) -> Self {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        self.uniform_bytes(name, &bytes)
+    }
+
+    fn uniform_bytes(mut self, name: &str, bytes: &[u8]) -> Self {
+        let Some(index) = self
+            .effect
+            .uniforms()
+            .iter()
+            .position(|uniform| uniform.name() == name)
+        else {
+            self.error
+                .get_or_insert(EffectError::UnknownUniform(name.to_string()));
+            return self;
+        };
+
+        let uniform = &self.effect.uniforms()[index];
+        if uniform.size_in_bytes() != bytes.len() {
+            self.error.get_or_insert(EffectError::UniformSize {
+                name: name.to_string(),
+                expected: uniform.size_in_bytes(),
+                given: bytes.len(),
+            });
+            return self;
+        }
+
+        let offset = uniform.offset();
+        self.uniforms[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.set_uniforms[index] = true;
+        self
+    }
+
+    ///
+    /// Transform the shader's coordinates, given in logical pixels.
+    ///
+    pub fn local_matrix(mut self, matrix: Matrix) -> Self {
+        self.local_matrix.replace(matrix);
+        self
+    }
+
+    ///
+    /// Hand the shader coordinates in physical pixels (at `context`'s scale) rather
+    /// than logical ones, e.g. for noise with a grain of one pixel at any scale.
+    ///
+    /// Applies before [EffectGraph::local_matrix].
+    ///
+    pub fn physical_pixels(mut self, context: &RenderContext) -> Self {
+        self.scale.replace(context.scale);
+        self
+    }
+
+    ///
+    /// Make the shader, once every uniform is set and every child bound.
+    ///
+    pub fn build(self) -> Result<Shader, EffectError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let unbound: Vec<_> = self
+            .effect
+            .children()
+            .iter()
+            .filter(|child| self.children[child.index()].is_none())
+            .map(|child| child.name().to_string())
+            .collect();
+
+        if !unbound.is_empty() || !self.unknown_children.is_empty() {
+            return Err(EffectError::Children {
+                unbound,
+                unknown: self.unknown_children,
+            });
+        }
+
+        let unset: Vec<_> = self
+            .effect
+            .uniforms()
+            .iter()
+            .zip(&self.set_uniforms)
+            .filter(|(_, set)| !**set)
+            .map(|(uniform, _)| uniform.name().to_string())
+            .collect();
+
+        if !unset.is_empty() {
+            return Err(EffectError::UnsetUniforms(unset));
+        }
+
+        let matrix = match (self.local_matrix, self.scale) {
+            (matrix, None) => matrix,
+            (matrix, Some(scale)) => {
+                let to_physical = Matrix::scale((1.0 / scale as f32, 1.0 / scale as f32));
+                Some(matrix.unwrap_or_default() * to_physical)
+            }
+        };
+
+        let children: Vec<_> = self.children.into_iter().flatten().collect();
+
+        self.effect
+            .make_shader(Data::new_copy(&self.uniforms), &children, matrix.as_ref())
+            .ok_or(EffectError::Shader)
+    }
+}
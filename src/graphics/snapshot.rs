@@ -0,0 +1,178 @@
+//!
+//! Copies of what a surface last presented, for drawing into other
+//! surfaces (e.g. a preview of the dock inside settings).
+//!
+//! Each surface has a device of its own for now, so frames are read back
+//! into raster images, which draw on any context. Frames are only copied
+//! whilst someone is asking for them.
+//!
+
+use std::{sync::Mutex, time::Instant};
+
+use skia_safe::{images, AlphaType, Canvas, ColorType, ConditionallySend, Data, Image, ImageInfo};
+
+use crate::wayland::surface::events::{Subscribers, Subscription};
+
+///
+/// Most copies of a surface's frames kept around at once: one for the
+/// latest snapshot, and the rest for readers still drawing older ones.
+///
+pub const SNAPSHOT_TARGETS: usize = 3;
+
+///
+/// A presented frame, see [crate::app::AvySurfaceHandle::snapshot].
+///
+#[derive(Debug, Clone)]
+pub struct FrameSnapshot {
+    pub frame: u64,
+    /// At the surface's physical size.
+    pub image: Image,
+    /// When the frame was drawn, just before being presented.
+    pub captured: Instant,
+}
+
+///
+/// A surface's latest snapshot, and who's subscribed to new ones.
+///
+#[derive(Default)]
+pub struct Snapshots {
+    targets: Mutex<SnapshotTargets>,
+    subscribers: Subscribers<FrameSnapshot>,
+}
+
+impl Snapshots {
+    ///
+    /// The latest snapshot, asking for a fresh one of the next frame if it's
+    /// older than `presented` (the last presented frame).
+    ///
+    pub fn latest(&self, presented: Option<u64>) -> Option<FrameSnapshot> {
+        self.targets.lock().unwrap().latest(presented)
+    }
+
+    pub fn subscribe(&self, callback: impl FnMut(FrameSnapshot) + Send + 'static) -> Subscription {
+        self.subscribers.subscribe(callback)
+    }
+
+    ///
+    /// Copy what's been drawn on `canvas` (at `physical_size`) as `frame`,
+    /// captured at `now`, if anyone wants it, and hand it to the subscribers.
+    ///
+    pub fn capture(&self, canvas: &Canvas, frame: u64, physical_size: (f64, f64), now: Instant) {
+        let mut targets = self.targets.lock().unwrap();
+        if !targets.requested && self.subscribers.is_empty() {
+            return;
+        }
+
+        let snapshot = targets.capture(canvas, frame, physical_size, now);
+        drop(targets);
+
+        // Unlocked, so that subscribers may ask for the latest snapshot.
+        if let Some(snapshot) = snapshot {
+            self.subscribers.emit(snapshot);
+        }
+    }
+}
+
+///
+/// The latest snapshot, and the buffers frames are copied into.
+///
+#[derive(Default)]
+struct SnapshotTargets {
+    latest: Option<FrameSnapshot>,
+    /// Copy targets: frames are never handed out from the swapchain's own
+    /// images, which are reused as soon as they're presented.
+    targets: Vec<Data>,
+    /// Take a snapshot of the next frame, even if nobody's subscribed.
+    requested: bool,
+}
+
+impl SnapshotTargets {
+    fn latest(&mut self, presented: Option<u64>) -> Option<FrameSnapshot> {
+        let stale = match (&self.latest, presented) {
+            (Some(latest), Some(presented)) => latest.frame < presented,
+            (None, _) => true,
+            (Some(_), None) => false,
+        };
+
+        if stale {
+            self.requested = true;
+        }
+
+        self.latest.clone()
+    }
+
+    ///
+    /// Copy `canvas` into a free target, as the latest snapshot.
+    ///
+    /// Skipped (keeping the previous snapshot) when every copy target is
+    /// still being drawn from, so readers never hold the surface up.
+    ///
+    fn capture(
+        &mut self,
+        canvas: &Canvas,
+        frame: u64,
+        physical_size: (f64, f64),
+        now: Instant,
+    ) -> Option<FrameSnapshot> {
+        let (width, height) = (physical_size.0 as i32, physical_size.1 as i32);
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+
+        let info = ImageInfo::new((width, height), ColorType::N32, AlphaType::Premul, None);
+        let row_bytes = info.min_row_bytes();
+        let len = info.compute_min_byte_size();
+
+        // Targets of another size (from before a resize) are no use anymore.
+        self.targets.retain(|target| target.size() == len);
+
+        let Some(target) = self.free_target(len) else {
+            log::debug!("Every snapshot target is in use, skipped frame {frame}");
+            return None;
+        };
+
+        // SAFETY: Only the pool references the target (see [SnapshotTargets::free_target]),
+        // so nothing can observe it being written.
+        let pixels =
+            unsafe { std::slice::from_raw_parts_mut(target.as_bytes().as_ptr() as *mut u8, len) };
+
+        if !canvas.read_pixels(&info, pixels, row_bytes, (0, 0)) {
+            log::debug!("Could not read frame {frame} back for a snapshot");
+            return None;
+        }
+
+        let image = images::raster_from_data(&info, target, row_bytes)?;
+        let snapshot = FrameSnapshot {
+            frame,
+            image,
+            captured: now,
+        };
+
+        self.latest.replace(snapshot.clone());
+        self.requested = false;
+        Some(snapshot)
+    }
+
+    ///
+    /// A target of `len` bytes which no image still uses, making one if there's room.
+    ///
+    fn free_target(&mut self, len: usize) -> Option<Data> {
+        // Only unique once no image made from it (including the latest) is left.
+        let free = self
+            .targets
+            .iter()
+            .find(|target| target.can_send())
+            .cloned();
+        if free.is_some() {
+            return free;
+        }
+
+        if self.targets.len() == SNAPSHOT_TARGETS {
+            return None;
+        }
+
+        let target = Data::new_zero_initialized(len);
+        self.targets.push(target.clone());
+        Some(target)
+    }
+}
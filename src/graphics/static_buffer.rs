@@ -0,0 +1,338 @@
+//!
+//! Surfaces whose contents never change (e.g. wallpapers) drawn once on the
+//! CPU into a shared-memory buffer, which stays attached for as long as the
+//! pixels are needed, so that the GPU backend can let go of its swapchain.
+//!
+
+use std::{num::NonZeroUsize, panic, thread};
+
+use skia_safe::{surfaces, AlphaType, Borrows, Canvas, ColorType, ImageInfo, Surface};
+use smithay_client_toolkit::{
+    error::GlobalError,
+    reexports::client::protocol::{wl_shm, wl_surface::WlSurface},
+    registry::ProvidesBoundGlobal,
+    shm::{
+        slot::{ActivateSlotError, Buffer, CreateBufferError, SlotPool},
+        CreatePoolError,
+    },
+};
+use thiserror::Error;
+
+use crate::util::SizeSnapshot;
+
+#[derive(Debug, Error)]
+pub enum StaticBufferError {
+    #[error("Could not allocate shared memory: {0}")]
+    Pool(#[from] CreatePoolError),
+    #[error("Could not create the buffer: {0}")]
+    Buffer(#[from] CreateBufferError),
+    #[error("Could not attach the buffer: {0}")]
+    Attach(#[from] ActivateSlotError),
+    #[error("Skia could not draw into the buffer.")]
+    Draw,
+}
+
+///
+/// Fewest rows [band_count] gives a band, below which
+/// a thread costs more than it saves.
+///
+pub const MIN_BAND_ROWS: usize = 128;
+
+///
+/// `wl_shm`, for making pools away from [crate::AvyClient]'s [smithay_client_toolkit::shm::Shm].
+///
+struct BoundShm(wl_shm::WlShm);
+
+impl ProvidesBoundGlobal<wl_shm::WlShm, 1> for BoundShm {
+    fn bound_global(&self) -> Result<wl_shm::WlShm, GlobalError> {
+        Ok(self.0.clone())
+    }
+}
+
+///
+/// A CPU-drawn frame in shared memory, see [crate::app::AvySurfaceHandle::render_static].
+///
+pub(crate) struct StaticBuffer {
+    buffer: Buffer,
+    physical_size: (i32, i32),
+    /// Holds [StaticBuffer::buffer].
+    _pool: SlotPool,
+}
+
+impl StaticBuffer {
+    ///
+    /// Draw a frame at `size` with `draw`, the canvas already scaled to logical pixels.
+    ///
+    pub(crate) fn draw(
+        wl_shm: &wl_shm::WlShm,
+        size: &SizeSnapshot,
+        draw: &mut dyn FnMut(&Canvas),
+    ) -> Result<Self, StaticBufferError> {
+        Self::draw_with(wl_shm, size, |pixels, (width, height)| {
+            let mut skia = wrap(pixels, width, height).ok_or(StaticBufferError::Draw)?;
+
+            let canvas = skia.canvas();
+            size.apply_to_canvas(canvas);
+            draw(canvas);
+
+            Ok(())
+        })
+    }
+
+    ///
+    /// Like [StaticBuffer::draw], but drawn with `draw` in bands, one on each
+    /// core, see [draw_in_bands]. Then `finish` draws over the whole frame.
+    ///
+    pub(crate) fn draw_banded(
+        wl_shm: &wl_shm::WlShm,
+        size: &SizeSnapshot,
+        draw: &(dyn Fn(&Canvas) + Sync),
+        finish: &mut dyn FnMut(&Canvas),
+    ) -> Result<Self, StaticBufferError> {
+        Self::draw_with(wl_shm, size, |pixels, (width, height)| {
+            let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+            let bands = band_count(height, cores);
+            if !draw_in_bands(pixels, (width, height), bands, size, draw) {
+                return Err(StaticBufferError::Draw);
+            }
+
+            let mut skia = wrap(pixels, width, height).ok_or(StaticBufferError::Draw)?;
+            let canvas = skia.canvas();
+            size.apply_to_canvas(canvas);
+            finish(canvas);
+
+            Ok(())
+        })
+    }
+
+    ///
+    /// Make a buffer at `size`, and have `draw` fill in its pixels (given with
+    /// the buffer's size in physical pixels).
+    ///
+    fn draw_with(
+        wl_shm: &wl_shm::WlShm,
+        size: &SizeSnapshot,
+        draw: impl FnOnce(&mut [u8], (i32, i32)) -> Result<(), StaticBufferError>,
+    ) -> Result<Self, StaticBufferError> {
+        let (width, height) = size.physical_size();
+        let (width, height) = ((width as i32).max(1), (height as i32).max(1));
+        let stride = width * 4;
+
+        let mut pool = SlotPool::new((stride * height) as usize, &BoundShm(wl_shm.clone()))?;
+        let (buffer, pixels) =
+            pool.create_buffer(width, height, stride, wl_shm::Format::Argb8888)?;
+
+        draw(pixels, (width, height))?;
+
+        Ok(Self {
+            buffer,
+            physical_size: (width, height),
+            _pool: pool,
+        })
+    }
+
+    ///
+    /// Attach (and damage) the buffer, ready for the next commit.
+    ///
+    pub(crate) fn attach(&self, wl_surface: &WlSurface) -> Result<(), StaticBufferError> {
+        self.buffer.attach_to(wl_surface)?;
+
+        let (width, height) = self.physical_size;
+        wl_surface.damage_buffer(0, 0, width, height);
+
+        Ok(())
+    }
+}
+
+///
+/// How many bands [draw_in_bands] should split `height` rows into on `cores`
+/// cores: one on each, unless that leaves them under [MIN_BAND_ROWS] rows.
+///
+pub fn band_count(height: i32, cores: usize) -> usize {
+    cores.min(height.max(0) as usize / MIN_BAND_ROWS).max(1)
+}
+
+///
+/// Draw `width` by `height` pixels (BGRA, premultiplied) at `size` with `draw`,
+/// in `bands` horizontal bands of equal height (but the last), each drawn
+/// straight into its rows of `pixels`, on a thread of its own.
+///
+/// `draw` must draw the same thing for every band: each sees the whole
+/// frame's coordinates, with the rows above its band off the top of its
+/// canvas, so that edges and shaders come out as they would in one go.
+///
+/// The threads are spawned for the frame, rather than kept in a pool: frames
+/// drawn this way are rare (a wallpaper changing, say), spawning a thread
+/// takes microseconds against the milliseconds a band takes to draw, and a
+/// pool would keep threads around for nothing in between.
+///
+/// Returns whether Skia could draw into every band. A panic in
+/// `draw` carries on once every band is done.
+///
+pub fn draw_in_bands(
+    pixels: &mut [u8],
+    (width, height): (i32, i32),
+    bands: usize,
+    size: &SizeSnapshot,
+    draw: &(dyn Fn(&Canvas) + Sync),
+) -> bool {
+    let row_bytes = width as usize * 4;
+    let band_rows = (height.max(1) as usize).div_ceil(bands.max(1));
+
+    let draw_band = |top: usize, pixels: &mut [u8]| {
+        let rows = pixels.len() / row_bytes;
+        let mut skia = wrap(pixels, width, rows as i32)?;
+
+        // Rows above the band are off the top of its canvas.
+        let canvas = skia.canvas();
+        canvas.translate((0.0, -(top as f32)));
+        size.apply_to_canvas(canvas);
+        draw(canvas);
+
+        Some(())
+    };
+
+    if bands <= 1 {
+        return draw_band(0, pixels).is_some();
+    }
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = pixels
+            .chunks_mut(band_rows * row_bytes)
+            .enumerate()
+            .map(|(band, pixels)| scope.spawn(move || draw_band(band * band_rows, pixels)))
+            .collect();
+
+        // Not short-circuiting, so every worker is joined.
+        workers.into_iter().fold(true, |drawn, worker| {
+            let band = worker
+                .join()
+                .unwrap_or_else(|payload| panic::resume_unwind(payload));
+            drawn && band.is_some()
+        })
+    })
+}
+
+///
+/// Wrap `width` by `height` pixels of a buffer, for Skia to draw into.
+///
+fn wrap(pixels: &mut [u8], width: i32, height: i32) -> Option<Borrows<'_, Surface>> {
+    // Argb8888 is stored little-endian.
+    let info = ImageInfo::new(
+        (width, height),
+        ColorType::BGRA8888,
+        AlphaType::Premul,
+        None,
+    );
+
+    surfaces::wrap_pixels(&info, pixels, (width * 4) as usize, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::{gradient_shader, Color, Paint, Rect, TileMode};
+
+    use crate::util::Transform;
+
+    use super::*;
+
+    const WIDTH: i32 = 600;
+    const HEIGHT: i32 = 500;
+
+    ///
+    /// Most a channel of a pixel on an anti-aliased edge may differ by.
+    ///
+    const AA_TOLERANCE: u8 = 2;
+
+    fn size() -> SizeSnapshot {
+        SizeSnapshot {
+            logical: (300, 250),
+            physical: (WIDTH as f64, HEIGHT as f64),
+            scale: 2.0,
+            transform: Transform::Normal,
+            generation: 0,
+        }
+    }
+
+    fn render(bands: usize, draw: &(dyn Fn(&Canvas) + Sync)) -> Vec<u8> {
+        let mut pixels = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        assert!(draw_in_bands(
+            &mut pixels,
+            (WIDTH, HEIGHT),
+            bands,
+            &size(),
+            draw
+        ));
+        pixels
+    }
+
+    fn fills(canvas: &Canvas) {
+        canvas.clear(Color::from_rgb(30, 30, 30));
+
+        let mut paint = Paint::default();
+        paint.set_color(Color::from_rgb(200, 60, 60));
+        // Across every band boundary (at 62.5, 125 and 187.5 with four bands).
+        canvas.draw_rect(Rect::from_xywh(10.0, 40.0, 200.0, 170.0), &paint);
+
+        paint.set_color(Color::from_argb(128, 60, 200, 60));
+        canvas.draw_rect(Rect::from_xywh(100.0, 120.0, 190.0, 10.0), &paint);
+    }
+
+    fn edges(canvas: &Canvas) {
+        let shader = gradient_shader::linear(
+            ((0.0, 0.0), (0.0, 250.0)),
+            [Color::from_rgb(20, 40, 90), Color::from_rgb(230, 140, 60)].as_ref(),
+            None,
+            TileMode::Clamp,
+            None,
+            None,
+        )
+        .unwrap();
+        let mut paint = Paint::default();
+        paint.set_shader(shader);
+        canvas.draw_paint(&paint);
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true).set_color(Color::WHITE);
+        canvas.draw_circle((150.0, 125.0), 80.3, &paint);
+
+        paint.set_color(Color::from_argb(160, 20, 20, 20));
+        canvas.save();
+        canvas.rotate(17.0, Some((150.0, 62.5).into()));
+        canvas.draw_rect(Rect::from_xywh(60.0, 40.0, 180.0, 45.0), &paint);
+        canvas.restore();
+    }
+
+    #[test]
+    fn axis_aligned_fills_match_bit_for_bit() {
+        let golden = render(1, &fills);
+
+        for bands in [2, 3, 4, 7] {
+            assert!(render(bands, &fills) == golden, "{bands} bands differ");
+        }
+    }
+
+    #[test]
+    fn anti_aliased_edges_and_shaders_match_closely() {
+        let golden = render(1, &edges);
+
+        for bands in [2, 3, 4, 7] {
+            let worst = render(bands, &edges)
+                .iter()
+                .zip(&golden)
+                .map(|(a, b)| a.abs_diff(*b))
+                .max()
+                .unwrap();
+            assert!(worst <= AA_TOLERANCE, "{bands} bands differ by {worst}");
+        }
+    }
+
+    #[test]
+    fn bands_are_kept_to_a_useful_height() {
+        assert_eq!(band_count(2160, 4), 4);
+        assert_eq!(band_count(2160, 32), 2160 / MIN_BAND_ROWS);
+        assert_eq!(band_count(300, 8), 2);
+        assert_eq!(band_count(100, 8), 1);
+        assert_eq!(band_count(0, 8), 1);
+    }
+}
@@ -0,0 +1,482 @@
+//!
+//! A horizontal strip of text, icons and spacing, as in a status bar:
+//! laid out within a width, truncated when it doesn't fit, and drawn in one go.
+//!
+
+use skia_safe::{Canvas, Contains, Font, Image, Paint, Point, Rect};
+
+use crate::locale::TextDirection;
+
+use super::RenderContext;
+
+const ELLIPSIS: &str = "…";
+
+///
+/// What a [Segment] shows.
+///
+#[derive(Debug, Clone)]
+pub enum SegmentContent {
+    Text {
+        text: String,
+        font: Font,
+        paint: Paint,
+    },
+    /// An image, drawn at `size` (in logical pixels), centred vertically.
+    Icon {
+        image: Image,
+        size: (f32, f32),
+    },
+    /// Takes up a share (`flex`) of whatever width is left over.
+    Spacer {
+        flex: f32,
+    },
+    FixedGap(f32),
+}
+
+///
+/// One piece of a [StripLayout].
+///
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// What hit tests report the segment by, or `None` for spacing.
+    pub id: Option<u32>,
+    pub content: SegmentContent,
+
+    /// When the strip runs out of space, text in lower-priority
+    /// segments is truncated first.
+    pub priority: i32,
+}
+
+impl Segment {
+    pub fn new(id: u32, content: SegmentContent) -> Self {
+        Self {
+            id: Some(id),
+            content,
+            priority: 0,
+        }
+    }
+
+    pub fn text(id: u32, text: impl Into<String>, font: &Font, paint: &Paint) -> Self {
+        Self::new(
+            id,
+            SegmentContent::Text {
+                text: text.into(),
+                font: font.clone(),
+                paint: paint.clone(),
+            },
+        )
+    }
+
+    pub fn icon(id: u32, image: &Image, size: (f32, f32)) -> Self {
+        Self::new(
+            id,
+            SegmentContent::Icon {
+                image: image.clone(),
+                size,
+            },
+        )
+    }
+
+    pub fn spacer(flex: f32) -> Self {
+        Self::spacing(SegmentContent::Spacer { flex })
+    }
+
+    pub fn gap(width: f32) -> Self {
+        Self::spacing(SegmentContent::FixedGap(width))
+    }
+
+    fn spacing(content: SegmentContent) -> Self {
+        Self {
+            id: None,
+            content,
+            priority: 0,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn natural_width(&self) -> f32 {
+        match &self.content {
+            SegmentContent::Text { text, font, paint } => font.measure_str(text, Some(paint)).0,
+            SegmentContent::Icon { size, .. } => size.0,
+            SegmentContent::Spacer { .. } => 0.0,
+            SegmentContent::FixedGap(width) => *width,
+        }
+    }
+}
+
+///
+/// Where a [Segment] ended up.
+///
+#[derive(Debug, Clone)]
+pub struct PlacedSegment {
+    /// See [Segment::id].
+    pub id: Option<u32>,
+    pub rect: Rect,
+
+    /// Text to draw, if it's a text segment: shortened (with an
+    /// ellipsis) if it was truncated, and empty if it didn't fit at all.
+    pub text: Option<String>,
+    pub truncated: bool,
+}
+
+///
+/// The result of [StripLayout::layout], in the coordinates of the
+/// rectangle the strip was laid out in.
+///
+#[derive(Debug, Clone, Default)]
+pub struct StripPlacement {
+    pub segments: Vec<PlacedSegment>,
+}
+
+impl StripPlacement {
+    ///
+    /// The id of the (non-spacing) segment under `point`, if any, e.g. a
+    /// [crate::util::SurfacePoint] from a pointer event, for a strip laid
+    /// out in the surface's coordinates.
+    ///
+    pub fn hit_test(&self, point: impl Into<Point>) -> Option<u32> {
+        let point = point.into();
+
+        self.hit_rects()
+            .find(|(_, rect)| rect.contains(point))
+            .map(|(id, _)| id)
+    }
+
+    ///
+    /// Hit rectangle of each (non-spacing) segment, by id.
+    ///
+    pub fn hit_rects(&self) -> impl Iterator<Item = (u32, Rect)> + '_ {
+        self.segments
+            .iter()
+            .filter(|segment| !segment.rect.is_empty())
+            .filter_map(|segment| Some((segment.id?, segment.rect)))
+    }
+
+    pub fn rect_of(&self, id: u32) -> Option<Rect> {
+        self.segments
+            .iter()
+            .find(|segment| segment.id == Some(id))
+            .map(|segment| segment.rect)
+    }
+}
+
+///
+/// Segments laid out side by side, e.g. `icon, text, spacer, clock`.
+///
+/// Spacers and gaps have no id, and never show up in hit tests.
+///
+#[derive(Debug, Clone, Default)]
+pub struct StripLayout {
+    segments: Vec<Segment>,
+    rtl: bool,
+}
+
+impl StripLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// An empty strip laid out in the direction of `context`'s locale,
+    /// i.e. from the right for right-to-left locales.
+    ///
+    pub fn for_context(context: &RenderContext) -> Self {
+        Self::new().direction(context.direction)
+    }
+
+    pub fn with(mut self, segment: Segment) -> Self {
+        self.segments.push(segment);
+        self
+    }
+
+    pub fn push(&mut self, segment: Segment) -> &mut Self {
+        self.segments.push(segment);
+        self
+    }
+
+    ///
+    /// Lay segments out from right to left, mirroring the whole strip.
+    ///
+    /// Text within a segment is still drawn as is.
+    ///
+    pub fn rtl(mut self, rtl: bool) -> Self {
+        self.rtl = rtl;
+        self
+    }
+
+    pub fn direction(self, direction: TextDirection) -> Self {
+        self.rtl(direction.is_rtl())
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    ///
+    /// Place each segment within `rect` (in logical pixels).
+    ///
+    pub fn layout(&self, rect: impl AsRef<Rect>) -> StripPlacement {
+        let rect = rect.as_ref();
+        let mut widths = self
+            .segments
+            .iter()
+            .map(Segment::natural_width)
+            .collect::<Vec<_>>();
+        let mut texts = self
+            .segments
+            .iter()
+            .map(|segment| match &segment.content {
+                SegmentContent::Text { text, .. } => Some(text.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut truncated = vec![false; self.segments.len()];
+
+        let total = widths.iter().sum::<f32>();
+        let mut overflow = total - rect.width();
+
+        if overflow > 0.0 {
+            // Lowest priority first, and the later of two equal ones first.
+            let mut order = (0..self.segments.len()).collect::<Vec<_>>();
+            order.sort_by_key(|&index| (self.segments[index].priority, usize::MAX - index));
+
+            for index in order {
+                if overflow <= 0.0 {
+                    break;
+                }
+
+                let SegmentContent::Text { text, font, paint } = &self.segments[index].content
+                else {
+                    continue;
+                };
+
+                let available = (widths[index] - overflow).max(0.0);
+                let (shortened, width) = truncate(text, font, paint, available);
+
+                overflow -= widths[index] - width;
+                widths[index] = width;
+                texts[index] = Some(shortened);
+                truncated[index] = true;
+            }
+        }
+
+        // Share what's left between the spacers.
+        let leftover = (rect.width() - widths.iter().sum::<f32>()).max(0.0);
+        let total_flex = self
+            .segments
+            .iter()
+            .map(|segment| match segment.content {
+                SegmentContent::Spacer { flex } => flex.max(0.0),
+                _ => 0.0,
+            })
+            .sum::<f32>();
+
+        if total_flex > 0.0 {
+            for (segment, width) in self.segments.iter().zip(widths.iter_mut()) {
+                if let SegmentContent::Spacer { flex } = segment.content {
+                    *width = leftover * flex.max(0.0) / total_flex;
+                }
+            }
+        }
+
+        let mut x = 0.0;
+        let segments = self
+            .segments
+            .iter()
+            .zip(widths)
+            .zip(texts.into_iter().zip(truncated))
+            .map(|((segment, width), (text, truncated))| {
+                let left = if self.rtl {
+                    rect.right - x - width
+                } else {
+                    rect.left + x
+                };
+                x += width;
+
+                PlacedSegment {
+                    id: segment.id,
+                    rect: Rect::from_xywh(left, rect.top, width, rect.height()),
+                    text,
+                    truncated,
+                }
+            })
+            .collect();
+
+        StripPlacement { segments }
+    }
+
+    ///
+    /// Lay the strip out within `rect` and draw it, returning where
+    /// each segment went (e.g. for hit testing).
+    ///
+    pub fn draw(&self, canvas: &Canvas, rect: impl AsRef<Rect>) -> StripPlacement {
+        let placement = self.layout(rect);
+
+        for (segment, placed) in self.segments.iter().zip(&placement.segments) {
+            if placed.rect.is_empty() {
+                continue;
+            }
+
+            match &segment.content {
+                SegmentContent::Text { font, paint, .. } => {
+                    let Some(text) = placed.text.as_deref().filter(|text| !text.is_empty()) else {
+                        continue;
+                    };
+
+                    // Centre the line vertically.
+                    let (_, metrics) = font.metrics();
+                    let baseline =
+                        placed.rect.center_y() - (metrics.ascent + metrics.descent) / 2.0;
+
+                    canvas.draw_str(text, (placed.rect.left, baseline), font, paint);
+                }
+                SegmentContent::Icon { image, size } => {
+                    let top = placed.rect.center_y() - size.1 / 2.0;
+                    let dst = Rect::from_xywh(placed.rect.left, top, size.0, size.1);
+
+                    canvas.draw_image_rect(image, None, dst, &Paint::default());
+                }
+                SegmentContent::Spacer { .. } | SegmentContent::FixedGap(_) => {}
+            }
+        }
+
+        placement
+    }
+}
+
+///
+/// The longest prefix of `text` which, followed by an ellipsis, fits within
+/// `available`, and its width. Empty if not even the ellipsis fits.
+///
+pub(super) fn truncate(text: &str, font: &Font, paint: &Paint, available: f32) -> (String, f32) {
+    let measure = |text: &str| font.measure_str(text, Some(paint)).0;
+
+    let ellipsis = measure(ELLIPSIS);
+    if ellipsis > available {
+        return (String::new(), 0.0);
+    }
+
+    let boundaries = text
+        .char_indices()
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    // The first `keep` characters, then an ellipsis.
+    let shorten = |keep: usize| {
+        let end = boundaries.get(keep).copied().unwrap_or(text.len());
+        format!("{}{ELLIPSIS}", text[..end].trim_end())
+    };
+
+    // Binary search for the number of characters to keep.
+    let (mut fits, mut too_long) = (0, boundaries.len());
+    while fits < too_long {
+        let keep = (fits + too_long + 1) / 2;
+
+        if measure(&shorten(keep)) <= available {
+            fits = keep;
+        } else {
+            too_long = keep - 1;
+        }
+    }
+
+    let shortened = shorten(fits);
+    let width = measure(&shortened);
+
+    (shortened, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::surfaces;
+
+    use super::*;
+    use crate::util::SurfacePoint;
+
+    fn icon(id: u32, width: f32) -> Segment {
+        let image = surfaces::raster_n32_premul((1, 1))
+            .unwrap()
+            .image_snapshot();
+        Segment::icon(id, &image, (width, 10.0))
+    }
+
+    /// `icon(10), gap(5), spacer, icon(20)` in a 100×20 strip at (10, 0).
+    fn strip(rtl: bool) -> StripPlacement {
+        StripLayout::new()
+            .with(icon(1, 10.0))
+            .with(Segment::gap(5.0))
+            .with(Segment::spacer(1.0))
+            .with(icon(2, 20.0))
+            .rtl(rtl)
+            .layout(Rect::from_xywh(10.0, 0.0, 100.0, 20.0))
+    }
+
+    fn lefts(placement: &StripPlacement) -> Vec<f32> {
+        placement
+            .segments
+            .iter()
+            .map(|segment| segment.rect.left)
+            .collect()
+    }
+
+    #[test]
+    fn spacers_take_up_the_leftover_width() {
+        let placement = strip(false);
+
+        assert_eq!(lefts(&placement), [10.0, 20.0, 25.0, 90.0]);
+        assert_eq!(placement.segments[2].rect.width(), 65.0);
+        assert_eq!(
+            placement.rect_of(2),
+            Some(Rect::from_xywh(90.0, 0.0, 20.0, 20.0))
+        );
+    }
+
+    #[test]
+    fn right_to_left_mirrors_the_strip() {
+        let placement = strip(true);
+
+        assert_eq!(lefts(&placement), [100.0, 95.0, 30.0, 10.0]);
+    }
+
+    #[test]
+    fn direction_lays_out_like_rtl() {
+        let laid_out = |direction| {
+            StripLayout::new()
+                .with(icon(1, 10.0))
+                .with(Segment::spacer(1.0))
+                .direction(direction)
+                .layout(Rect::from_xywh(0.0, 0.0, 100.0, 20.0))
+        };
+
+        assert_eq!(lefts(&laid_out(TextDirection::Ltr)), [0.0, 10.0]);
+        assert_eq!(lefts(&laid_out(TextDirection::Rtl)), [90.0, 0.0]);
+    }
+
+    #[test]
+    fn hits_map_back_to_segment_ids_and_skip_spacing() {
+        let placement = strip(false);
+
+        assert_eq!(placement.hit_test(SurfacePoint::new(15.0, 5.0)), Some(1));
+        assert_eq!(placement.hit_test(SurfacePoint::new(22.0, 5.0)), None);
+        assert_eq!(placement.hit_test(SurfacePoint::new(50.0, 5.0)), None);
+        assert_eq!(placement.hit_test(SurfacePoint::new(95.0, 5.0)), Some(2));
+        assert_eq!(placement.hit_test(SurfacePoint::new(95.0, 25.0)), None);
+
+        let ids = placement.hit_rects().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2]);
+    }
+
+    #[test]
+    fn nothing_is_truncated_when_it_fits() {
+        let placement = strip(false);
+
+        assert!(placement.segments.iter().all(|segment| !segment.truncated));
+        assert!(placement
+            .segments
+            .iter()
+            .all(|segment| segment.text.is_none()));
+    }
+}
@@ -0,0 +1,450 @@
+//!
+//! Text laid out from styled spans, through Skia's paragraph layout, with
+//! control over OpenType features: e.g. tabular figures, so that a clock's
+//! digits all take the same width rather than jittering as they change.
+//!
+//! Features are shaped by HarfBuzz, which leaves alone text whose font lacks
+//! them. Layouts note which features went unsupported (see
+//! [TextLayout::unsupported_features]) rather than failing.
+//!
+//! A [BaselineGrid] lines up layouts set in different fonts (or sizes) on a
+//! common baseline.
+//!
+
+use skia_safe::{
+    textlayout::{
+        FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, TextRange, TextStyle,
+    },
+    Canvas, Color4f, FontMgr, Paint, Point, Typeface,
+};
+use smallvec::SmallVec;
+
+///
+/// Digits of equal width, e.g. for clocks and counters.
+///
+pub const TABULAR_FIGURES: [u8; 4] = *b"tnum";
+
+///
+/// Digits of cap height, rather than old-style figures with ascenders and descenders.
+///
+pub const LINING_FIGURES: [u8; 4] = *b"lnum";
+
+///
+/// OpenType features to shape a span with, by tag.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FontFeatures(SmallVec<[([u8; 4], i32); 2]>);
+
+impl FontFeatures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tabular_figures(self) -> Self {
+        self.with(TABULAR_FIGURES, 1)
+    }
+
+    pub fn lining_figures(self) -> Self {
+        self.with(LINING_FIGURES, 1)
+    }
+
+    ///
+    /// Set feature `tag` to `value`: `1` turns most features on and `0` off,
+    /// others pick an alternate.
+    ///
+    pub fn with(mut self, tag: [u8; 4], value: i32) -> Self {
+        match self.0.iter_mut().find(|(existing, _)| *existing == tag) {
+            Some((_, existing)) => *existing = value,
+            None => self.0.push((tag, value)),
+        }
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ([u8; 4], i32)> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+///
+/// A run of text in one style.
+///
+#[derive(Debug, Clone)]
+pub struct TextSpan {
+    pub text: String,
+    /// In logical pixels, see [crate::graphics::RenderContext::font_size].
+    pub size: f32,
+    /// Font families to try in turn, or none for the default.
+    pub families: Vec<String>,
+    pub color: Color4f,
+    pub features: FontFeatures,
+}
+
+impl TextSpan {
+    pub fn new(text: impl Into<String>, size: f32) -> Self {
+        Self {
+            text: text.into(),
+            size,
+            families: Vec::new(),
+            color: Color4f::new(0.0, 0.0, 0.0, 1.0),
+            features: FontFeatures::default(),
+        }
+    }
+
+    pub fn with_families(mut self, families: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.families = families.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_color(mut self, color: Color4f) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_features(mut self, features: FontFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    fn text_style(&self) -> TextStyle {
+        let mut style = TextStyle::new();
+        style
+            .set_font_size(self.size)
+            .set_foreground_paint(&Paint::new(self.color, None));
+
+        if !self.families.is_empty() {
+            style.set_font_families(self.families.as_slice());
+        }
+
+        for (tag, value) in self.features.iter() {
+            style.add_font_feature(String::from_utf8_lossy(&tag), value);
+        }
+
+        style
+    }
+}
+
+///
+/// The system's fonts, to lay text out with.
+///
+pub fn system_fonts() -> FontCollection {
+    let mut fonts = FontCollection::new();
+    fonts.set_default_font_manager(FontMgr::new(), None);
+    fonts
+}
+
+///
+/// Spans laid out together, on one or more lines.
+///
+pub struct TextLayout {
+    paragraph: Paragraph,
+    /// Features asked for which some font used doesn't have.
+    unsupported: SmallVec<[[u8; 4]; 2]>,
+}
+
+impl TextLayout {
+    ///
+    /// Lay `spans` out with `fonts` on a single line, as wide as they need.
+    ///
+    /// `style` sets the direction and alignment, e.g.
+    /// [crate::graphics::RenderContext::paragraph_style].
+    ///
+    pub fn new(spans: &[TextSpan], fonts: &FontCollection, style: &ParagraphStyle) -> Self {
+        let mut builder = ParagraphBuilder::new(style, fonts.clone());
+        for span in spans {
+            builder.push_style(&span.text_style());
+            builder.add_text(&span.text);
+            builder.pop();
+        }
+
+        let mut paragraph = builder.build();
+        paragraph.layout(f32::INFINITY);
+
+        let unsupported = unsupported_features(&paragraph, spans);
+        for tag in &unsupported {
+            log::debug!(
+                "Font lacks OpenType feature {:?}, laying out without it",
+                String::from_utf8_lossy(tag)
+            );
+        }
+
+        Self {
+            paragraph,
+            unsupported,
+        }
+    }
+
+    ///
+    /// Lay the text out again, wrapping it to `width`.
+    ///
+    pub fn layout(&mut self, width: f32) {
+        self.paragraph.layout(width);
+    }
+
+    ///
+    /// Width of the widest line, unwrapped.
+    ///
+    pub fn width(&self) -> f32 {
+        self.paragraph.max_intrinsic_width()
+    }
+
+    pub fn height(&self) -> f32 {
+        self.paragraph.height()
+    }
+
+    ///
+    /// Distance from the top of the layout to its first baseline.
+    ///
+    pub fn baseline(&self) -> f32 {
+        self.paragraph.alphabetic_baseline()
+    }
+
+    ///
+    /// Features asked for which (some of) the text's fonts don't have, and
+    /// which it was laid out without.
+    ///
+    pub fn unsupported_features(&self) -> &[[u8; 4]] {
+        &self.unsupported
+    }
+
+    pub fn paragraph(&self) -> &Paragraph {
+        &self.paragraph
+    }
+
+    ///
+    /// Draw the text with its top left at `origin`.
+    ///
+    pub fn draw(&self, canvas: &Canvas, origin: impl Into<Point>) {
+        self.paragraph.paint(canvas, origin);
+    }
+}
+
+///
+/// Offsets lining up the first baselines of several layouts, e.g. labels
+/// in different fonts on one line.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BaselineGrid {
+    baseline: f32,
+    offsets: Vec<f32>,
+    height: f32,
+}
+
+impl BaselineGrid {
+    ///
+    /// The common baseline is the lowest of the layouts', so none has to
+    /// move above the top of the line.
+    ///
+    pub fn new<'a>(layouts: impl IntoIterator<Item = &'a TextLayout>) -> Self {
+        let metrics = layouts
+            .into_iter()
+            .map(|layout| (layout.baseline(), layout.height()))
+            .collect::<Vec<_>>();
+
+        Self::from_metrics(&metrics)
+    }
+
+    ///
+    /// The grid for layouts with these baselines and heights.
+    ///
+    fn from_metrics(metrics: &[(f32, f32)]) -> Self {
+        let baseline = metrics
+            .iter()
+            .map(|(baseline, _)| *baseline)
+            .fold(0.0, f32::max);
+
+        let offsets = metrics
+            .iter()
+            .map(|(own, _)| baseline - own)
+            .collect::<Vec<_>>();
+
+        let height = metrics
+            .iter()
+            .zip(&offsets)
+            .map(|((_, height), offset)| offset + height)
+            .fold(0.0, f32::max);
+
+        Self {
+            baseline,
+            offsets,
+            height,
+        }
+    }
+
+    ///
+    /// Distance from the top of the line to the common baseline.
+    ///
+    pub fn baseline(&self) -> f32 {
+        self.baseline
+    }
+
+    ///
+    /// How far down to draw each layout (in the order given) from the top of
+    /// the line, for it to sit on the common baseline.
+    ///
+    pub fn offsets(&self) -> &[f32] {
+        &self.offsets
+    }
+
+    pub fn offset(&self, index: usize) -> f32 {
+        self.offsets.get(index).copied().unwrap_or_default()
+    }
+
+    ///
+    /// Height of the line, with every layout on the common baseline.
+    ///
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+}
+
+///
+/// Features `spans` ask for which a font used for them doesn't have.
+///
+fn unsupported_features(paragraph: &Paragraph, spans: &[TextSpan]) -> SmallVec<[[u8; 4]; 2]> {
+    let mut unsupported = SmallVec::new();
+    if spans.iter().all(|span| span.features.is_empty()) {
+        return unsupported;
+    }
+
+    let fonts = paragraph.get_fonts();
+    let mut start = 0;
+    for span in spans {
+        let range: TextRange = start..start + span.text.len();
+        start = range.end;
+
+        let typefaces = fonts
+            .iter()
+            .filter(|font| font.text_range.start < range.end && range.start < font.text_range.end)
+            .map(|font| font.font.typeface())
+            .collect::<Vec<_>>();
+
+        for (tag, _) in span.features.iter() {
+            let missing = typefaces.iter().any(|typeface| !has_feature(typeface, tag));
+            if missing && !unsupported.contains(&tag) {
+                unsupported.push(tag);
+            }
+        }
+    }
+
+    unsupported
+}
+
+///
+/// Whether `typeface` has OpenType feature `tag`, for substitution or positioning.
+///
+fn has_feature(typeface: &Typeface, tag: [u8; 4]) -> bool {
+    [*b"GSUB", *b"GPOS"].into_iter().any(|table| {
+        typeface
+            .copy_table_data(u32::from_be_bytes(table))
+            .is_some_and(|data| feature_tags(data.as_bytes()).contains(&tag))
+    })
+}
+
+///
+/// Tags in the FeatureList of a `GSUB` or `GPOS` table.
+///
+fn feature_tags(table: &[u8]) -> Vec<[u8; 4]> {
+    let u16_at = |offset: usize| {
+        table
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    };
+
+    // After the major and minor version, and the ScriptList's offset.
+    let Some(list) = u16_at(6).filter(|list| *list != 0) else {
+        return Vec::new();
+    };
+    let count = u16_at(list).unwrap_or_default();
+
+    // Each record is a tag, then the offset of its feature table.
+    (0..count)
+        .filter_map(|index| {
+            let record = list + 2 + index * 6;
+            table
+                .get(record..record + 4)
+                .map(|tag| [tag[0], tag[1], tag[2], tag[3]])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `GSUB` table whose FeatureList has `tags`, and nothing else.
+    fn gsub(tags: &[&[u8; 4]]) -> Vec<u8> {
+        // Version 1.0, then the ScriptList, FeatureList and LookupList offsets.
+        let mut table = vec![0, 1, 0, 0, 0, 0, 0, 10, 0, 0];
+        table.extend((tags.len() as u16).to_be_bytes());
+        for tag in tags {
+            table.extend(*tag);
+            table.extend([0, 0]);
+        }
+        table
+    }
+
+    #[test]
+    fn features_replace_earlier_values_of_the_same_tag() {
+        let features = FontFeatures::new()
+            .tabular_figures()
+            .lining_figures()
+            .with(TABULAR_FIGURES, 0);
+
+        assert_eq!(
+            features.iter().collect::<Vec<_>>(),
+            [(TABULAR_FIGURES, 0), (LINING_FIGURES, 1)]
+        );
+        assert!(FontFeatures::new().is_empty());
+    }
+
+    #[test]
+    fn reads_feature_list_tags() {
+        assert_eq!(
+            feature_tags(&gsub(&[b"tnum", b"kern"])),
+            [*b"tnum", *b"kern"]
+        );
+        assert_eq!(feature_tags(&gsub(&[])), Vec::<[u8; 4]>::new());
+    }
+
+    #[test]
+    fn tolerates_broken_tables() {
+        // No FeatureList.
+        assert!(feature_tags(&[0, 1, 0, 0, 0, 0, 0, 0]).is_empty());
+        // Too short for the header.
+        assert!(feature_tags(&[0, 1]).is_empty());
+
+        // Claims more records than it has: only whole ones are read.
+        let mut table = gsub(&[b"tnum", b"lnum"]);
+        table.truncate(table.len() - 4);
+        assert_eq!(feature_tags(&table), [*b"tnum"]);
+    }
+
+    #[test]
+    fn baselines_line_up_on_the_lowest() {
+        // A small label, and a bigger one whose baseline is further down.
+        let grid = BaselineGrid::from_metrics(&[(10.0, 14.0), (24.0, 30.0)]);
+
+        assert_eq!(grid.baseline(), 24.0);
+        assert_eq!(grid.offsets(), [14.0, 0.0]);
+        assert_eq!(grid.height(), 30.0);
+        assert_eq!(grid.offset(5), 0.0);
+    }
+
+    #[test]
+    fn deep_descenders_make_the_line_taller() {
+        // The smaller label has the deeper descender, once moved down.
+        let grid = BaselineGrid::from_metrics(&[(10.0, 30.0), (24.0, 30.0)]);
+
+        assert_eq!(grid.offsets(), [14.0, 0.0]);
+        assert_eq!(grid.height(), 44.0);
+    }
+
+    #[test]
+    fn an_empty_grid_has_no_height() {
+        assert_eq!(BaselineGrid::from_metrics(&[]), BaselineGrid::default());
+    }
+}
@@ -0,0 +1,569 @@
+//!
+//! A glyph cache persisted across runs, so that text-heavy surfaces
+//! don't shape and rasterize the same strings on every launch.
+//!
+//! Shaped runs (glyphs and their positions) and rasterized text masks
+//! are keyed by font fingerprint, size, scale factor and text. The cache
+//! file (under `$XDG_CACHE_HOME/avy`) is memory-mapped when opened, and
+//! only entries actually used are decoded. A font's fingerprint hashes
+//! its file's contents, so entries for a font that has since changed
+//! are never hit again, and age out.
+//!
+//! Entries are evicted least recently used first (counted in runs) once
+//! the cache outgrows its size cap, when it's saved.
+//!
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+use skia_safe::{
+    images, surfaces, typeface::TypefaceId, CachingHint, Canvas, Data, Font, GlyphId, IRect, Image,
+    ImageInfo, Paint, Point, TextBlob,
+};
+
+///
+/// Default size cap, see [TextCache::with_size_cap].
+///
+pub const DEFAULT_TEXT_CACHE_SIZE: usize = 16 * 1024 * 1024;
+
+const MAGIC: &[u8; 8] = b"AVYTXTC1";
+
+/// Magic, then the number of runs the cache has seen.
+const HEADER_LEN: usize = 16;
+
+/// Key, last used run, kind (padded to 4 bytes) and payload length.
+const RECORD_HEADER_LEN: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Kind {
+    Shaped = 0,
+    Mask = 1,
+}
+
+impl Kind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(Self::Shaped),
+            1 => Some(Self::Mask),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Size of all entries, as they'd be saved.
+    pub bytes: usize,
+}
+
+///
+/// Glyphs and their positions (relative to the origin) for a string.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapedRun {
+    pub glyphs: Vec<GlyphId>,
+    pub positions: Vec<Point>,
+    pub advance: f32,
+}
+
+impl ShapedRun {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + self.glyphs.len() * 10);
+        out.extend_from_slice(&self.advance.to_le_bytes());
+        out.extend_from_slice(&(self.glyphs.len() as u32).to_le_bytes());
+
+        for glyph in &self.glyphs {
+            out.extend_from_slice(&glyph.to_le_bytes());
+        }
+
+        for position in &self.positions {
+            out.extend_from_slice(&position.x.to_le_bytes());
+            out.extend_from_slice(&position.y.to_le_bytes());
+        }
+
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+        let advance = reader.f32()?;
+        let count = reader.u32()? as usize;
+
+        let glyphs = (0..count)
+            .map(|_| reader.u16())
+            .collect::<Option<Vec<_>>>()?;
+        let positions = (0..count)
+            .map(|_| Some(Point::new(reader.f32()?, reader.f32()?)))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            glyphs,
+            positions,
+            advance,
+        })
+    }
+}
+
+///
+/// A string rasterized into an alpha mask, drawn in the paint's color.
+///
+#[derive(Debug, Clone)]
+struct Mask {
+    image: Image,
+    /// Offset of the mask from the text's origin, in physical pixels.
+    offset: Point,
+}
+
+impl Mask {
+    fn encode(&self) -> Option<Vec<u8>> {
+        let (width, height) = (self.image.width(), self.image.height());
+        let info = ImageInfo::new_a8((width, height));
+        let mut pixels = vec![0u8; (width * height) as usize];
+
+        if !self.image.read_pixels(
+            &info,
+            &mut pixels,
+            width as usize,
+            (0, 0),
+            CachingHint::Disallow,
+        ) {
+            return None;
+        }
+
+        let mut out = Vec::with_capacity(16 + pixels.len());
+        out.extend_from_slice(&(width as u32).to_le_bytes());
+        out.extend_from_slice(&(height as u32).to_le_bytes());
+        out.extend_from_slice(&self.offset.x.to_le_bytes());
+        out.extend_from_slice(&self.offset.y.to_le_bytes());
+        out.extend_from_slice(&pixels);
+        Some(out)
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+        let width = reader.u32()? as i32;
+        let height = reader.u32()? as i32;
+        let offset = Point::new(reader.f32()?, reader.f32()?);
+        let pixels = reader.bytes((width * height) as usize)?;
+
+        let info = ImageInfo::new_a8((width, height));
+        let image = images::raster_from_data(&info, Data::new_copy(pixels), width as usize)?;
+
+        Some(Self { image, offset })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.bytes(N)?.try_into().ok()
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.array().map(f32::from_le_bytes)
+    }
+}
+
+///
+/// FNV-1a: unlike [std::hash::DefaultHasher], stable across builds,
+/// which the keys saved to disk need.
+///
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> &mut Self {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+
+        self
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+enum Slot {
+    /// In the mapped file.
+    Mapped { offset: usize, len: usize },
+    /// Added this run.
+    Owned(Vec<u8>),
+}
+
+struct Entry {
+    kind: Kind,
+    slot: Slot,
+    last_used: u64,
+}
+
+pub struct TextCache {
+    path: Option<PathBuf>,
+    map: Option<Mmap>,
+    entries: HashMap<u64, Entry>,
+    /// This run's number, for LRU eviction across runs.
+    run: u64,
+    size_cap: usize,
+    fingerprints: HashMap<TypefaceId, u64>,
+    hits: u64,
+    misses: u64,
+}
+
+impl TextCache {
+    ///
+    /// A cache that isn't saved anywhere.
+    ///
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            map: None,
+            entries: HashMap::new(),
+            run: 1,
+            size_cap: DEFAULT_TEXT_CACHE_SIZE,
+            fingerprints: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    ///
+    /// Open the cache called `name` in the user's cache directory.
+    ///
+    pub fn open(name: &str) -> io::Result<Self> {
+        let dir = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?;
+
+        Self::open_at(dir.join("avy").join(format!("{name}.textcache")))
+    }
+
+    ///
+    /// Open the cache at `path`, starting afresh if it doesn't exist
+    /// or isn't a valid cache file.
+    ///
+    pub fn open_at(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut cache = Self::in_memory();
+
+        match File::open(&path) {
+            Ok(file) => {
+                // SAFETY: The file is only ever replaced (by renaming), never
+                // written in place, so the mapping doesn't change under us.
+                let map = unsafe { Mmap::map(&file)? };
+                cache.load(map);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        cache.path.replace(path);
+        Ok(cache)
+    }
+
+    ///
+    /// Evict the least recently used entries, when saving, past `bytes`.
+    ///
+    pub fn with_size_cap(mut self, bytes: usize) -> Self {
+        self.size_cap = bytes;
+        self
+    }
+
+    fn load(&mut self, map: Mmap) {
+        let Some(header) = map.get(..HEADER_LEN) else {
+            return;
+        };
+
+        if &header[..8] != MAGIC {
+            log::warn!("Ignoring an invalid text cache.");
+            return;
+        }
+
+        self.run = Reader(&header[8..]).u64().unwrap_or(0) + 1;
+
+        let mut offset = HEADER_LEN;
+        while let Some(header) = map.get(offset..offset + RECORD_HEADER_LEN) {
+            let mut reader = Reader(header);
+            let (Some(key), Some(last_used), Some([kind, ..]), Some(len)) = (
+                reader.u64(),
+                reader.u64(),
+                reader.array::<4>(),
+                reader.u32(),
+            ) else {
+                break;
+            };
+
+            let len = len as usize;
+            let start = offset + RECORD_HEADER_LEN;
+            let (Some(kind), true) = (Kind::from_u8(kind), start + len <= map.len()) else {
+                log::warn!("Text cache truncated, keeping what came before.");
+                break;
+            };
+
+            self.entries.insert(
+                key,
+                Entry {
+                    kind,
+                    slot: Slot::Mapped { offset: start, len },
+                    last_used,
+                },
+            );
+
+            offset = start + len;
+        }
+
+        self.map.replace(map);
+    }
+
+    pub fn stats(&self) -> TextCacheStats {
+        TextCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            bytes: self
+                .entries
+                .values()
+                .map(|entry| RECORD_HEADER_LEN + self.payload(entry).len())
+                .sum(),
+        }
+    }
+
+    fn payload<'a>(&'a self, entry: &'a Entry) -> &'a [u8] {
+        match &entry.slot {
+            Slot::Owned(bytes) => bytes,
+            Slot::Mapped { offset, len } => self
+                .map
+                .as_ref()
+                .map_or(&[], |map| &map[*offset..offset + len]),
+        }
+    }
+
+    ///
+    /// Identifies a font by its contents, size and the scale it's drawn at.
+    ///
+    fn font_key(&mut self, font: &Font, scale: f32) -> Fnv {
+        let typeface = font.typeface();
+        let fingerprint = *self
+            .fingerprints
+            .entry(typeface.unique_id())
+            .or_insert_with(|| {
+                let mut hash = Fnv::new();
+                hash.write(typeface.family_name().as_bytes());
+
+                if let Some((data, index)) = typeface.to_font_data() {
+                    hash.write(&data).write(&index.to_le_bytes());
+                }
+
+                hash.finish()
+            });
+
+        let mut hash = Fnv::new();
+        hash.write(&fingerprint.to_le_bytes())
+            .write(&font.size().to_le_bytes())
+            .write(&scale.to_le_bytes());
+        hash
+    }
+
+    fn key(&mut self, kind: Kind, text: &str, font: &Font, scale: f32) -> u64 {
+        let mut hash = self.font_key(font, scale);
+        hash.write(&[kind as u8]).write(text.as_bytes()).finish()
+    }
+
+    fn get(&mut self, key: u64, kind: Kind) -> Option<&[u8]> {
+        let run = self.run;
+        let entry = self
+            .entries
+            .get_mut(&key)
+            .filter(|entry| entry.kind == kind);
+
+        let Some(entry) = entry else {
+            self.misses += 1;
+            return None;
+        };
+
+        entry.last_used = run;
+        self.hits += 1;
+
+        let entry = &self.entries[&key];
+        Some(self.payload(entry))
+    }
+
+    fn insert(&mut self, key: u64, kind: Kind, payload: Vec<u8>) {
+        self.entries.insert(
+            key,
+            Entry {
+                kind,
+                slot: Slot::Owned(payload),
+                last_used: self.run,
+            },
+        );
+    }
+
+    ///
+    /// Glyphs and positions for `text`, shaped with `font`.
+    ///
+    pub fn shape(&mut self, text: &str, font: &Font) -> ShapedRun {
+        let key = self.key(Kind::Shaped, text, font, 1.0);
+        if let Some(run) = self.get(key, Kind::Shaped).and_then(ShapedRun::decode) {
+            return run;
+        }
+
+        let glyphs = font.str_to_glyphs_vec(text);
+        let mut positions = vec![Point::default(); glyphs.len()];
+        font.get_pos(&glyphs, &mut positions, None);
+        let (advance, _) = font.measure_str(text, None);
+
+        let run = ShapedRun {
+            glyphs,
+            positions,
+            advance,
+        };
+
+        self.insert(key, Kind::Shaped, run.encode());
+        run
+    }
+
+    fn mask(&mut self, text: &str, font: &Font, scale: f32) -> Option<Mask> {
+        let key = self.key(Kind::Mask, text, font, scale);
+        if let Some(mask) = self.get(key, Kind::Mask).and_then(Mask::decode) {
+            return Some(mask);
+        }
+
+        let run = self.shape(text, font);
+        let blob = TextBlob::from_pos_text(&run.glyphs[..], &run.positions, font)?;
+
+        let bounds = blob.bounds();
+        let bounds = IRect::from_ltrb(
+            (bounds.left * scale).floor() as i32,
+            (bounds.top * scale).floor() as i32,
+            (bounds.right * scale).ceil() as i32,
+            (bounds.bottom * scale).ceil() as i32,
+        );
+
+        let info = ImageInfo::new_a8((bounds.width().max(1), bounds.height().max(1)));
+        let mut surface = surfaces::raster(&info, None, None)?;
+
+        let canvas = surface.canvas();
+        canvas.translate((-bounds.left as f32, -bounds.top as f32));
+        canvas.scale((scale, scale));
+        canvas.draw_text_blob(&blob, (0.0, 0.0), Paint::default().set_anti_alias(true));
+
+        let mask = Mask {
+            image: surface.image_snapshot(),
+            offset: Point::new(bounds.left as f32, bounds.top as f32),
+        };
+
+        if let Some(payload) = mask.encode() {
+            self.insert(key, Kind::Mask, payload);
+        }
+
+        Some(mask)
+    }
+
+    ///
+    /// Draw `text` with its baseline's origin at `origin` (in logical
+    /// pixels), from a mask rasterized at `scale` (see [super::RenderContext::scale]).
+    ///
+    /// Only the paint's color, alpha and blending apply to cached masks.
+    ///
+    pub fn draw_text(
+        &mut self,
+        canvas: &Canvas,
+        text: &str,
+        origin: impl Into<Point>,
+        font: &Font,
+        paint: &Paint,
+        scale: f64,
+    ) {
+        let origin = origin.into();
+        let scale = scale as f32;
+
+        let Some(mask) = self.mask(text, font, scale) else {
+            canvas.draw_str(text, origin, font, paint);
+            return;
+        };
+
+        canvas.save();
+        canvas.translate(origin);
+        canvas.scale((1.0 / scale, 1.0 / scale));
+        canvas.draw_image(&mask.image, mask.offset, Some(paint));
+        canvas.restore();
+    }
+
+    ///
+    /// Write the cache back to its file (if it has one), evicting
+    /// the least recently used entries past the size cap.
+    ///
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.last_used));
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Replaced rather than written in place, as it may be mapped.
+        let temporary = path.with_extension("tmp");
+        let mut out = BufWriter::new(File::create(&temporary)?);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&self.run.to_le_bytes())?;
+
+        let mut size = HEADER_LEN;
+        for (key, entry) in entries {
+            let payload = self.payload(entry);
+            size += RECORD_HEADER_LEN + payload.len();
+            if size > self.size_cap {
+                break;
+            }
+
+            out.write_all(&key.to_le_bytes())?;
+            out.write_all(&entry.last_used.to_le_bytes())?;
+            out.write_all(&[entry.kind as u8, 0, 0, 0])?;
+            out.write_all(&(payload.len() as u32).to_le_bytes())?;
+            out.write_all(payload)?;
+        }
+
+        out.into_inner()?.sync_all()?;
+        std::fs::rename(temporary, path)
+    }
+}
@@ -2,33 +2,88 @@
 //! Support for Vulkan using `vulkano` (for now).
 //!
 
-use std::{any::Any, borrow::BorrowMut, sync::Arc};
+use std::{
+    any::Any,
+    collections::VecDeque,
+    ops::Range,
+    sync::{Arc, Mutex, OnceLock, Weak},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
 use skia_bindings::{GrDirectContext, SkSurface};
-use skia_safe::{gpu::vk::GetProcOf, Color4f};
-use smithay_client_toolkit::reexports::client::{protocol::wl_display::WlDisplay, Proxy};
+use skia_safe::{
+    gpu::{vk::GetProcOf, FlushInfo, PurgeResourceOptions, SyncCpu},
+    surface::BackendSurfaceAccess,
+    PixelGeometry, SurfaceProps,
+};
+use smallvec::SmallVec;
+use smithay_client_toolkit::reexports::client::{
+    protocol::{wl_display::WlDisplay, wl_surface::WlSurface},
+    Proxy,
+};
 use thiserror::Error;
 use vulkano::{
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        CommandBufferExecError, CommandBufferUsage, PrimaryAutoCommandBuffer,
+        PrimaryCommandBufferAbstract,
+    },
     device::{
         physical::{PhysicalDevice, PhysicalDeviceType},
         Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
     },
     image::{view::ImageView, Image, ImageUsage},
-    instance::{Instance, InstanceCreateInfo, InstanceExtensions},
-    swapchain::{Swapchain, SwapchainCreateInfo, SwapchainPresentInfo},
-    sync::{self, GpuFuture},
+    instance::{
+        debug::{
+            DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessengerCallback,
+            DebugUtilsMessengerCallbackData, DebugUtilsMessengerCreateInfo,
+        },
+        Instance, InstanceCreateInfo, InstanceExtensions,
+    },
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    swapchain::{Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo, SwapchainPresentInfo},
+    sync::{future::FenceSignalFuture, GpuFuture, PipelineStage, Sharing},
     Handle, LoadingError, Validated, Version, VulkanError, VulkanLibrary, VulkanObject,
 };
 
 pub const MAX_VK_API_VERSION: Version = Version::major_minor(1, 3);
 
+///
+/// How many frames the CPU may queue up before waiting for the GPU to catch up.
+///
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+///
+/// How many times a frame tries to recreate a lost surface before giving up
+/// with [Error::SurfaceLost].
+///
+pub const MAX_SURFACE_LOST_RETRIES: usize = 3;
+
+///
+/// How long a cached resource must have gone unused for a [TrimLevel::Light] trim to free it.
+///
+pub const LIGHT_TRIM_AGE: Duration = Duration::from_secs(10);
+
+///
+/// Enabled when tracing, see [crate::debug::trace].
+///
+pub const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
 use crate::{
+    debug::{
+        trace::{self, VULKAN_TARGET},
+        StartupTimer,
+    },
     impl_as_any,
-    util::{AsAny, Size},
+    util::{AsAny, SharedClock, SizeSnapshot},
     wayland::surface::AvySurface,
 };
 
-use super::{GraphicsBackend, GraphicsSurface};
+use super::{
+    CallbackPanic, DeviceLock, Frame, FrameOptions, FrameTimings, GpuTimings, GraphicsBackend,
+    GraphicsFrame, GraphicsSurface, TrimLevel,
+};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -41,6 +96,9 @@ pub enum Error {
     #[error("A Vulkan error has occurred: {0}")]
     Vulkan(#[from] VulkanError),
 
+    #[error("Could not submit a command buffer: {0}")]
+    Execute(#[from] CommandBufferExecError),
+
     #[error("Your graphics device does not support B8G8R8A8 format.")]
     UnsupportedBGRA,
 
@@ -49,57 +107,317 @@ pub enum Error {
 
     #[error("An error occurred whilst creating a Skia surface for Vulkan.")]
     SkiaSurfaceError,
+
+    #[error("The surface is suspended, resume it before rendering.")]
+    Suspended,
+
+    #[error("The surface was moved to another backend, render through its new handle.")]
+    Detached,
+
+    #[error("The Vulkan instance could not be created, see the first error.")]
+    InstanceFailed,
+
+    #[error("No graphics device can present to the surface.")]
+    NoDevice,
+
+    #[error("The surface was lost, and could not be recreated after {0} attempts.")]
+    SurfaceLost(usize),
+
+    #[error("The render callback panicked: {0}")]
+    CallbackPanicked(#[from] CallbackPanic),
 }
 
 impl_as_any!(Error);
 
+impl From<SurfaceSuspended> for Error {
+    fn from(_: SurfaceSuspended) -> Self {
+        Self::Suspended
+    }
+}
+
+impl Error {
+    ///
+    /// Whether the surface (or the device presenting it) went away, e.g.
+    /// when moved to another GPU, see [VulkanSurface::recover_surface_lost].
+    ///
+    fn is_surface_lost(&self) -> bool {
+        let err = match self {
+            Error::Vulkan(err) | Error::Validated(Validated::Error(err)) => err,
+            _ => return false,
+        };
+
+        matches!(
+            err,
+            VulkanError::SurfaceLost | VulkanError::InitializationFailed | VulkanError::DeviceLost
+        )
+    }
+
+    fn is_device_lost(&self) -> bool {
+        matches!(
+            self,
+            Error::Vulkan(VulkanError::DeviceLost)
+                | Error::Validated(Validated::Error(VulkanError::DeviceLost))
+        )
+    }
+}
+
+///
+/// The Vulkan instance, being created on a background thread
+/// until a surface first needs it.
+///
+struct VulkanPending {
+    thread: Mutex<Option<JoinHandle<Result<Arc<Instance>, Error>>>>,
+    instance: OnceLock<Arc<Instance>>,
+}
+
+impl VulkanPending {
+    fn spawn(
+        application_name: String,
+        application_version: Version,
+        timer: Option<StartupTimer>,
+        clock: SharedClock,
+    ) -> Self {
+        let thread = std::thread::Builder::new()
+            .name("avy-vulkan-init".to_string())
+            .spawn(move || create_instance(application_name, application_version, timer, clock))
+            .expect("[Vulkan] Could not spawn the instance thread.");
+
+        Self {
+            thread: Mutex::new(Some(thread)),
+            instance: OnceLock::new(),
+        }
+    }
+
+    ///
+    /// The instance, waiting for it to be created if need be.
+    ///
+    fn wait(&self) -> Result<Arc<Instance>, Error> {
+        if let Some(instance) = self.instance.get() {
+            return Ok(instance.clone());
+        }
+
+        let mut thread = self.thread.lock().unwrap();
+
+        // Another surface may have waited whilst we were locking.
+        if let Some(instance) = self.instance.get() {
+            return Ok(instance.clone());
+        }
+
+        let instance = thread
+            .take()
+            .ok_or(Error::InstanceFailed)?
+            .join()
+            .map_err(|_| Error::InstanceFailed)??;
+
+        Ok(self.instance.get_or_init(|| instance).clone())
+    }
+}
+
+fn create_instance(
+    application_name: String,
+    application_version: Version,
+    timer: Option<StartupTimer>,
+    clock: SharedClock,
+) -> Result<Arc<Instance>, Error> {
+    let started = clock.now();
+    let lib = VulkanLibrary::new()?;
+
+    if let Some(timer) = &timer {
+        timer.stage("library load", started);
+    }
+
+    let validation = trace::is_enabled() && validation_available(&lib);
+    let (enabled_layers, debug_utils_messengers) = if validation {
+        let callback = unsafe { DebugUtilsMessengerCallback::new(vulkan_debug_callback) };
+
+        (
+            vec![VALIDATION_LAYER.to_string()],
+            vec![DebugUtilsMessengerCreateInfo {
+                message_severity: DebugUtilsMessageSeverity::ERROR
+                    | DebugUtilsMessageSeverity::WARNING
+                    | DebugUtilsMessageSeverity::INFO,
+                message_type: DebugUtilsMessageType::GENERAL
+                    | DebugUtilsMessageType::VALIDATION
+                    | DebugUtilsMessageType::PERFORMANCE,
+                ..DebugUtilsMessengerCreateInfo::user_callback(callback)
+            }],
+        )
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    let started = clock.now();
+    let instance = Instance::new(
+        lib,
+        InstanceCreateInfo {
+            application_name: Some(application_name),
+            application_version,
+            engine_name: Some(crate::ENGINE_NAME.to_string()),
+            engine_version: crate::ENGINE_VERSION,
+            max_api_version: Some(MAX_VK_API_VERSION),
+            enabled_layers,
+            enabled_extensions: InstanceExtensions {
+                khr_surface: true,
+                khr_wayland_surface: true,
+                khr_get_surface_capabilities2: true,
+                khr_get_physical_device_properties2: true,
+                ext_debug_utils: validation,
+                ..InstanceExtensions::empty()
+            },
+            debug_utils_messengers,
+            ..Default::default()
+        },
+    )?;
+
+    if let Some(timer) = timer {
+        timer.stage("instance", started);
+    }
+
+    Ok(instance)
+}
+
+///
+/// Whether the validation layer, and the extension to hear from it, are installed.
+///
+fn validation_available(lib: &VulkanLibrary) -> bool {
+    let layer = lib
+        .layer_properties()
+        .is_ok_and(|mut layers| layers.any(|layer| layer.name() == VALIDATION_LAYER));
+
+    if !layer {
+        trace::write(
+            VULKAN_TARGET,
+            "WARN",
+            format_args!("{VALIDATION_LAYER} isn't installed, tracing without validation."),
+        );
+    }
+
+    layer && lib.supported_extensions().ext_debug_utils
+}
+
+///
+/// Writes validation messages into the trace, see [crate::debug::trace].
+///
+fn vulkan_debug_callback(
+    severity: DebugUtilsMessageSeverity,
+    ty: DebugUtilsMessageType,
+    data: DebugUtilsMessengerCallbackData<'_>,
+) {
+    let level = if severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+        "ERROR"
+    } else if severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+        "WARN"
+    } else {
+        "INFO"
+    };
+
+    trace::write(
+        VULKAN_TARGET,
+        level,
+        format_args!(
+            "[{ty:?}] [{}] {}",
+            data.message_id_name.unwrap_or("-"),
+            data.message
+        ),
+    );
+}
+
 pub struct Vulkan {
-    instance: Arc<Instance>,
+    instance: VulkanPending,
+    /// Enumerated once, on the first surface.
+    physical_devices: OnceLock<Vec<Arc<PhysicalDevice>>>,
+    surface_props: SurfaceProps,
+    frames_in_flight: usize,
+    gpu_timing: bool,
+    timer: Option<StartupTimer>,
+    /// What startup stages are timed with, see [Vulkan::with_clock].
+    clock: SharedClock,
 }
 
 impl Vulkan {
+    ///
+    /// Start loading Vulkan in the background; this returns straight away,
+    /// so connecting to the compositor can carry on in the meantime.
+    ///
+    /// Loading errors are reported by the first [GraphicsBackend::for_surface].
+    ///
     pub fn new(
         application_name: impl ToString,
         application_version: Version,
     ) -> Result<Self, Error> {
-        let lib = VulkanLibrary::new().expect("[Vulkan] No Vulkan library found.");
-        let instance = Instance::new(
-            lib.clone(),
-            InstanceCreateInfo {
-                application_name: Some(application_name.to_string()),
+        let clock = SharedClock::default();
+        let timer = StartupTimer::start(&clock);
+        trace::init_from_env(&clock);
+
+        Ok(Self {
+            instance: VulkanPending::spawn(
+                application_name.to_string(),
                 application_version,
-                engine_name: Some(crate::ENGINE_NAME.to_string()),
-                engine_version: crate::ENGINE_VERSION,
-                max_api_version: Some(MAX_VK_API_VERSION),
-                enabled_extensions: InstanceExtensions {
-                    khr_surface: true,
-                    khr_wayland_surface: true,
-                    khr_get_surface_capabilities2: true,
-                    khr_get_physical_device_properties2: true,
-                    ext_debug_utils: false,
-                    ..InstanceExtensions::empty()
-                },
-                // debug_utils_messengers: vec![DebugUtilsMessengerCreateInfo::user_callback(
-                //     unsafe {
-                //         DebugUtilsMessengerCallback::new(|sev, ty, data| {
-                //             println!("[VULKAN] [{ty:?}] [{sev:?}] {}", data.message);
-
-                //             data.objects.for_each(|obj| {
-                //                 println!(
-                //                     "\t with {:?} @ {:p} {:?}",
-                //                     obj.object_type,
-                //                     obj.object_handle as *const i8,
-                //                     obj.object_name
-                //                 )
-                //             });
-                //         })
-                //     },
-                // )],
-                ..Default::default()
-            },
-        )?;
+                timer.clone(),
+                clock.clone(),
+            ),
+            physical_devices: OnceLock::new(),
+            surface_props: SurfaceProps::default(),
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+            gpu_timing: false,
+            timer,
+            clock,
+        })
+    }
+
+    ///
+    /// The Vulkan instance, waiting for it to be loaded if need be.
+    ///
+    pub fn instance(&self) -> Result<Arc<Instance>, Error> {
+        self.instance.wait()
+    }
+
+    fn physical_devices(&self, instance: &Arc<Instance>) -> Result<&[Arc<PhysicalDevice>], Error> {
+        if let Some(devices) = self.physical_devices.get() {
+            return Ok(devices);
+        }
+
+        let devices = instance.enumerate_physical_devices()?.collect();
+        Ok(self.physical_devices.get_or_init(|| devices))
+    }
+
+    ///
+    /// Set the initial Skia surface properties for surfaces made by this backend.
+    ///
+    pub fn with_surface_props(mut self, surface_props: SurfaceProps) -> Self {
+        self.surface_props = surface_props;
+        self
+    }
+
+    ///
+    /// Let up to `frames` frames be queued on the GPU before [GraphicsSurface::begin_frame]
+    /// waits for the oldest to finish (at least 1, see [DEFAULT_FRAMES_IN_FLIGHT]).
+    ///
+    /// More frames in flight let the CPU get ahead of the GPU, at the cost of latency.
+    ///
+    pub fn with_frames_in_flight(mut self, frames: usize) -> Self {
+        self.frames_in_flight = frames.max(1);
+        self
+    }
+
+    ///
+    /// Time each frame's work on the GPU, reported through [FrameTimings::gpu]
+    /// (and so [crate::metrics::MetricsRecorder]s).
+    ///
+    /// Devices which can't time graphics work report [GpuTimings::Unavailable].
+    ///
+    pub fn with_gpu_timing(mut self, enabled: bool) -> Self {
+        self.gpu_timing = enabled;
+        self
+    }
 
-        Ok(Self { instance })
+    ///
+    /// Time startup stages (see [crate::debug::TIMING_ENV]) on `clock`, e.g.
+    /// the client's [crate::AvyClient::clock], rather than the system's.
+    ///
+    pub fn with_clock(self, clock: &SharedClock) -> Self {
+        self.clock.adopt(clock);
+        self
     }
 }
 
@@ -112,7 +430,7 @@ impl GraphicsBackend for Vulkan {
         wl_display: &WlDisplay,
         surface: &(impl AvySurface + ?Sized),
     ) -> Result<Self::Surface, Self::Error> {
-        let instance = self.instance.clone();
+        let instance = self.instance()?;
 
         // Create KHR Surface, which supports Wayland surfaces.
         let khr_surface = unsafe {
@@ -124,54 +442,43 @@ impl GraphicsBackend for Vulkan {
             )
         }?;
 
-        // Get our Vulkan Device
-        let device_extensions = DeviceExtensions {
-            khr_swapchain: true,
-            ..Default::default()
-        };
+        let started = self.clock.now();
+        let physical_devices = self.physical_devices(&instance)?;
+        let (physical_device, families) =
+            best_physical_device(physical_devices, &khr_surface, &device_extensions())
+                .ok_or(Error::NoDevice)?;
 
-        let (physical_device, queue_family_i) =
-            best_physical_device(instance.clone(), khr_surface.clone(), &device_extensions);
-
-        let (device, mut queues) = Device::new(
-            physical_device.clone(),
-            DeviceCreateInfo {
-                queue_create_infos: vec![QueueCreateInfo {
-                    queue_family_index: queue_family_i,
-                    ..Default::default()
-                }],
-                enabled_extensions: device_extensions,
-                ..Default::default()
-            },
-        )?;
+        let (device, queues) = create_device(&physical_device, &families)?;
+        record_device(&physical_device);
 
-        let queue = queues.next().unwrap();
+        if let Some(timer) = &self.timer {
+            timer.stage("device", started);
+        }
 
         // Create our Swapchain.
         let capabilities =
             physical_device.surface_capabilities(&khr_surface, Default::default())?;
+        let image_format = bgra_format(&physical_device, &khr_surface)?;
 
-        let (image_format, _) = physical_device
-            .surface_formats(&khr_surface, Default::default())
-            .into_iter()
-            .flatten()
-            .find(|(format, _)| &vulkano::format::Format::B8G8R8A8_UNORM == format)
-            .ok_or(Error::UnsupportedBGRA)?;
-
-        let (width, height) = surface.size_ref().physical_size();
+        let size = surface.size_ref().snapshot();
+        let (width, height) = size.physical_size();
         let (width, height) = (width as u32, height as u32);
 
+        let started = self.clock.now();
+        let swapchain_create_info = SwapchainCreateInfo {
+            min_image_count: capabilities.min_image_count + 1,
+            image_format,
+            image_extent: [width, height],
+            image_usage: ImageUsage::COLOR_ATTACHMENT,
+            image_sharing: families.swapchain_sharing(),
+            composite_alpha: vulkano::swapchain::CompositeAlpha::PreMultiplied,
+            ..Default::default()
+        };
+
         let (swapchain, images) = Swapchain::new(
             device.clone(),
             khr_surface.clone(),
-            SwapchainCreateInfo {
-                min_image_count: capabilities.min_image_count + 1,
-                image_format,
-                image_extent: [width, height],
-                image_usage: ImageUsage::COLOR_ATTACHMENT,
-                composite_alpha: vulkano::swapchain::CompositeAlpha::PreMultiplied,
-                ..Default::default()
-            },
+            swapchain_create_info.clone(),
         )?;
 
         let image_views: Vec<_> = images
@@ -180,151 +487,388 @@ impl GraphicsBackend for Vulkan {
             .map(ImageView::new_default)
             .collect::<Result<_, _>>()?;
 
-        // Create Skia Backend
-        let instance_for_get_proc = instance.clone();
-        let get_proc = |of: GetProcOf| unsafe {
-            let res = match of {
-                skia_safe::gpu::vk::GetProcOf::Instance(raw_instance, name) => instance
-                    .library()
-                    .get_instance_proc_addr(ash::vk::Instance::from_raw(raw_instance as _), name),
-                skia_safe::gpu::vk::GetProcOf::Device(device, name) => {
-                    (instance_for_get_proc.fns().v1_0.get_device_proc_addr)(
-                        ash::vk::Device::from_raw(device as _),
-                        name,
-                    )
-                }
-            };
+        if let Some(timer) = &self.timer {
+            timer.stage("swapchain", started);
+        }
 
-            match res {
-                Some(f) => f as _,
-                None => core::ptr::null(),
-            }
-        };
+        log::debug!(target: VULKAN_TARGET, "Created a swapchain at {width}x{height}");
 
-        let backend_context = unsafe {
-            skia_safe::gpu::vk::BackendContext::new(
-                instance.handle().as_raw() as _,
-                physical_device.handle().as_raw() as _,
-                device.handle().as_raw() as _,
-                (queue.handle().as_raw() as _, queue_family_i as _),
-                &get_proc,
-            )
-        };
+        let gr_context = create_gr_context(&device, &queues, &families)?;
 
-        let gr_context = skia_safe::gpu::direct_contexts::make_vulkan(&backend_context, None)
-            .ok_or(Error::SkiaCreationError)?;
+        let command_buffer_allocator =
+            StandardCommandBufferAllocator::new(device.clone(), Default::default());
+
+        let gpu_timer = self
+            .gpu_timing
+            .then(|| GpuTimer::new(&device, families.graphics, self.frames_in_flight))
+            .flatten();
 
         Ok(VulkanSurface {
+            device_lock: device_lock(&device),
             device: device.clone(),
-            queue,
-            swapchain,
+            queues,
+            families,
+            khr_surface,
+            wl_display: wl_display.clone(),
+            wl_surface: surface.wl_surface().clone(),
+            lost: None,
+            swapchain: Some(swapchain),
+            swapchain_create_info,
+            presented_images: vec![false; images.len()],
             images,
             image_views,
+            detached: false,
+            presented_frames: 0,
+            timer: self.timer.clone(),
             recreate_swapchain: false,
-            previous_frame_end: Some(Box::new(sync::now(device))),
+            size_generation: size.generation,
+            pending_image: None,
+            surface_props: self.surface_props,
+            frames_in_flight: self.frames_in_flight,
+            in_flight: VecDeque::with_capacity(self.frames_in_flight),
+            gpu_timing: self.gpu_timing,
+            gpu_timer,
+            command_buffer_allocator,
             gr_context,
+            clock: self.clock.clone(),
         })
     }
 }
 
 pub struct VulkanSurface {
+    detached: bool,
+    presented_frames: u64,
+    /// Reports the first presented frame, see [crate::debug::TIMING_ENV].
+    timer: Option<StartupTimer>,
     recreate_swapchain: bool,
-    previous_frame_end: Option<Box<dyn GpuFuture>>,
+    /// [SizeSnapshot::generation] the swapchain was made for.
+    size_generation: u64,
+    /// An image acquired for a frame that was discarded before presenting,
+    /// which the graphics queue already waits for.
+    pending_image: Option<(u32, Box<dyn GpuFuture>)>,
+    surface_props: SurfaceProps,
+    frames_in_flight: usize,
+    /// Frames submitted to the GPU, oldest first.
+    in_flight: VecDeque<FenceSignalFuture<Box<dyn GpuFuture>>>,
+    /// Whether [Vulkan::with_gpu_timing] was asked for.
+    gpu_timing: bool,
+    /// `None` if not asked for, or if the device can't time.
+    gpu_timer: Option<GpuTimer>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
     gr_context: skia_safe::RCHandle<GrDirectContext>,
+    /// What [FrameTimings] are read from, see [GraphicsSurface::set_clock].
+    clock: SharedClock,
     image_views: Vec<Arc<ImageView>>,
     images: Vec<Arc<Image>>,
-    swapchain: Arc<Swapchain>,
-    queue: Arc<Queue>,
+    /// Whether each image was handed to Skia for presenting, which left it in
+    /// the layout for that. The others haven't been drawn into yet.
+    presented_images: Vec<bool>,
+    /// `None` whilst suspended.
+    swapchain: Option<Arc<Swapchain>>,
+    swapchain_create_info: SwapchainCreateInfo,
+    khr_surface: Arc<vulkano::swapchain::Surface>,
+    /// Kept to recreate `khr_surface` from, should it be lost.
+    wl_display: WlDisplay,
+    wl_surface: WlSurface,
+    /// Why the surface was lost, if presenting found it so,
+    /// for the next frame to recreate it.
+    lost: Option<Error>,
+    queues: Queues,
+    families: QueueFamilySelection,
+    /// `device`'s, see [device_lock].
+    device_lock: DeviceLock,
     device: Arc<Device>,
 }
 
 ///
-/// SAFETY: Nobody except us can access the gr_context for this surface.
+/// SAFETY: Nobody except us can access the gr_context for this surface,
+/// and it's only ever used behind the surface's mutex (and its device's
+/// [DeviceLock]), so never from two threads at once.
 /// Everything else is Send-able
 ///
 unsafe impl Send for VulkanSurface {}
 
 impl GraphicsSurface for VulkanSurface {
-    fn render(
+    fn begin_frame(
         &mut self,
-        size: &Size,
-        callback: &mut dyn FnMut(&skia_safe::Canvas),
-    ) -> Result<(), Box<dyn Any>> {
-        size.handle_changes(|_| {
-            self.recreate_swapchain = true;
-        });
+        size: &SizeSnapshot,
+        options: &FrameOptions,
+    ) -> Result<Option<Frame<'_>>, Box<dyn Any>> {
+        let started = self.clock.now();
+        let mut timings = FrameTimings::new(self.presented_frames + 1, started);
 
-        if self.recreate_swapchain {
-            self.recreate_swapchain(size)
-                .map_err(Box::new)
-                .map_err(AsAny::as_any)?;
+        if self.detached {
+            return Err(Box::new(Error::Detached).as_any());
         }
 
-        let (image_index, suboptimal, acquire_fut) =
-            match vulkano::swapchain::acquire_next_image(self.swapchain.clone(), None)
-                .map_err(Validated::unwrap)
-            {
-                Ok(r) => r,
-                Err(vulkano::VulkanError::OutOfDate) => {
-                    self.recreate_swapchain = true;
-                    return Ok(());
-                }
-                Err(err) => return Err(Box::new(Error::from(err)).as_any()),
-            };
-
-        if suboptimal {
-            // Recreate swapchain next frame.
-            self.recreate_swapchain = true;
+        // A surface which couldn't be recreated yet has no swapchain either.
+        if self.is_suspended() && self.lost.is_none() {
+            return Err(Box::new(Error::Suspended).as_any());
         }
 
+        let (image_index, acquired) = match self
+            .acquire_recovering(size, &mut timings)
+            .map_err(Box::new)
+            .map_err(AsAny::as_any)?
+        {
+            Some(acquired) => acquired,
+            None => return Ok(None),
+        };
+
+        timings.acquired = self.clock.now();
+
         let image_view = self.image_views.get(image_index as usize).cloned().unwrap();
         let image = image_view.image();
+        let layout = if self.presented_images[image_index as usize] {
+            skia_bindings::VkImageLayout::PRESENT_SRC_KHR
+        } else {
+            skia_bindings::VkImageLayout::UNDEFINED
+        };
 
         let mut skia = self
-            .skia_surface(image, size)
+            .skia_surface(image, layout, size)
             .map_err(Box::new)
             .map_err(AsAny::as_any)?;
         let canvas = skia.canvas();
 
         // Apply fractional scaling (if necessary).
-        size.scale_canvas(canvas);
+        size.apply_to_canvas(canvas);
 
-        canvas.clear(Color4f {
-            r: 1.0,
-            g: 1.0,
-            b: 1.0,
-            a: 1.0,
-        });
+        options.apply(canvas);
 
-        callback(canvas);
+        let gpu_slot = self.begin_gpu_timing(&mut timings);
 
-        drop(skia);
+        Ok(Some(Frame::new(
+            VulkanFrame {
+                surface: self,
+                skia: Some(skia),
+                image_index,
+                acquired: Some(acquired),
+                gpu_slot: gpu_slot.map(|(slot, _)| slot),
+                gpu_futures: gpu_slot.into_iter().map(|(_, begun)| begun).collect(),
+                started,
+            },
+            timings,
+            self.clock.clone(),
+        )))
+    }
+
+    fn set_pixel_geometry(&mut self, geometry: PixelGeometry) {
+        self.surface_props = SurfaceProps::new(self.surface_props.flags(), geometry);
+    }
+
+    fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    fn suspend(&mut self) -> Result<(), Box<dyn Any>> {
+        let Some(swapchain) = self.swapchain.take() else {
+            return Ok(());
+        };
+
+        // Nothing may still be using the swapchain images.
+        self.pending_image.take();
+        self.queues
+            .graphics
+            .with(|mut queue| queue.wait_idle())
+            .map_err(Error::from)
+            .map_err(Box::new)
+            .map_err(AsAny::as_any)?;
+        self.in_flight.clear();
+
+        self.swapchain_create_info = swapchain.create_info();
+        self.image_views.clear();
+        self.images.clear();
+        drop(swapchain);
+
+        self.gr_context.free_gpu_resources();
+
+        Ok(())
+    }
+
+    fn resume(&mut self, size: &SizeSnapshot) -> Result<(), Box<dyn Any>> {
+        if self.detached {
+            return Err(Box::new(Error::Detached).as_any());
+        }
+
+        if self.swapchain.is_some() {
+            return Ok(());
+        }
+
+        self.create_swapchain(size)
+            .map_err(Box::new)
+            .map_err(AsAny::as_any)
+    }
+
+    fn is_suspended(&self) -> bool {
+        self.swapchain.is_none()
+    }
+
+    fn device_lock(&self) -> DeviceLock {
+        self.device_lock.clone()
+    }
+
+    fn trim_memory(&mut self, level: TrimLevel) {
+        match level {
+            TrimLevel::Light => {
+                self.gr_context
+                    .perform_deferred_cleanup(LIGHT_TRIM_AGE, PurgeResourceOptions::AllResources);
+            }
+            TrimLevel::Moderate => {
+                self.gr_context
+                    .perform_deferred_cleanup(Duration::ZERO, PurgeResourceOptions::AllResources)
+                    .purge_unlocked_resources(PurgeResourceOptions::AllResources);
+            }
+            TrimLevel::Aggressive => {
+                // Resources still referenced by queued work are only freed once it's done.
+                self.gr_context.flush_submit_and_sync_cpu();
+                self.gr_context.free_gpu_resources();
+            }
+        }
+    }
 
+    fn cached_bytes(&self) -> Option<usize> {
+        Some(self.gr_context.resource_cache_usage().resource_bytes)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Any>> {
         self.gr_context.flush_submit_and_sync_cpu();
 
-        let fut = self
-            .previous_frame_end
-            .borrow_mut()
-            .take()
-            .unwrap()
-            .join(acquire_fut)
+        while let Some(frame) = self.in_flight.pop_front() {
+            frame
+                .wait(None)
+                .map_err(Error::from)
+                .map_err(Box::new)
+                .map_err(AsAny::as_any)?;
+        }
+
+        Ok(())
+    }
+
+    fn detach(&mut self) -> Result<(), Box<dyn Any>> {
+        self.gr_context.flush_submit_and_sync_cpu();
+        self.suspend()?;
+        self.detached = true;
+
+        Ok(())
+    }
+
+    fn presented_frames(&self) -> u64 {
+        self.presented_frames
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "Vulkan"
+    }
+}
+
+///
+/// A frame drawing into one of a [VulkanSurface]'s swapchain images.
+///
+pub struct VulkanFrame<'a> {
+    surface: &'a mut VulkanSurface,
+    skia: Option<skia_safe::RCHandle<SkSurface>>,
+    image_index: u32,
+    /// The submission waiting for the image, see [VulkanSurface::wait_for_image].
+    acquired: Option<Box<dyn GpuFuture>>,
+    /// The frame's slot in the [GpuTimer], if it's being timed.
+    gpu_slot: Option<u32>,
+    /// Timestamp writes already submitted, to be presented after.
+    gpu_futures: SmallVec<[Box<dyn GpuFuture>; 2]>,
+    /// When the frame was begun, by the system's clock, see [crate::debug::TIMING_ENV].
+    started: Instant,
+}
+
+impl<'a> GraphicsFrame for VulkanFrame<'a> {
+    fn canvas(&mut self) -> &skia_safe::Canvas {
+        self.skia.as_mut().unwrap().canvas()
+    }
+
+    fn gr_context(&mut self) -> Option<&mut skia_safe::gpu::DirectContext> {
+        Some(&mut self.surface.gr_context)
+    }
+
+    fn present(mut self: Box<Self>) -> Result<(), Box<dyn Any>> {
+        let mut skia = self.skia.take().unwrap();
+        let acquired = self.acquired.take().unwrap();
+        let image_index = self.image_index;
+        let surface = &mut *self.surface;
+
+        // Submit (without waiting on the CPU); Skia also moves
+        // the image into the layout needed for presenting.
+        surface.gr_context.flush_surface_with_access(
+            &mut skia,
+            BackendSurfaceAccess::Present,
+            &FlushInfo::default(),
+        );
+        surface.gr_context.submit(SyncCpu::No);
+        surface.presented_images[image_index as usize] = true;
+        drop(skia);
+
+        let timed = self.gpu_slot.zip(surface.gpu_timer.as_ref());
+        if let Some((slot, pool)) = timed.map(|(slot, timer)| (slot, timer.pool.clone())) {
+            match surface.submit_timestamp(pool, slot, GpuTimer::SKIA_DONE) {
+                Ok(fut) => self.gpu_futures.push(fut),
+                Err(err) => {
+                    log::warn!(target: VULKAN_TARGET, "Could not time a frame on the GPU: {err}")
+                }
+            }
+        }
+
+        // An empty submission on the graphics queue, after Skia's: its semaphore
+        // orders presentation after the drawing, and the fence tracks both.
+        let command_buffer = surface
+            .present_command_buffer(self.gpu_slot)
+            .map_err(Box::new)
+            .map_err(AsAny::as_any)?;
+
+        let mut previous = acquired;
+        for fut in self.gpu_futures.drain(..) {
+            previous = previous.join(fut).boxed();
+        }
+
+        let fut = previous
+            .then_execute(surface.queues.graphics.clone(), command_buffer)
+            .map_err(Error::from)
+            .map_err(Box::new)
+            .map_err(AsAny::as_any)?
             .then_swapchain_present(
-                self.queue.clone(),
-                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_index),
+                surface.queues.present.clone(),
+                SwapchainPresentInfo::swapchain_image_index(
+                    surface.swapchain.clone().unwrap(),
+                    image_index,
+                ),
             )
+            .boxed()
             .then_signal_fence_and_flush();
 
         match fut.map_err(Validated::unwrap) {
             Ok(future) => {
-                self.previous_frame_end = Some(future.boxed());
+                surface.in_flight.push_back(future);
+                surface.presented_frames += 1;
+
+                if let (Some(timer), Some(slot)) = (surface.gpu_timer.as_mut(), self.gpu_slot) {
+                    timer.submitted(slot);
+                }
+
+                if surface.presented_frames == 1 {
+                    if let Some(timer) = &surface.timer {
+                        timer.stage("first frame", self.started);
+                    }
+                }
             }
             Err(VulkanError::OutOfDate) => {
-                self.recreate_swapchain = true;
-                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+                surface.recreate_swapchain = true;
             }
             Err(err) => {
-                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
-                return Err(Box::new(Error::from(err)).as_any());
+                let err = Error::from(err);
+
+                if !err.is_surface_lost() {
+                    return Err(Box::new(err).as_any());
+                }
+
+                // Dropped, like an out of date frame; the next one recreates the surface.
+                surface.lost = Some(err);
             }
         }
 
@@ -332,41 +876,493 @@ impl GraphicsSurface for VulkanSurface {
     }
 }
 
-impl VulkanSurface {
-    pub fn recreate_swapchain(&mut self, size: &Size) -> Result<(), Error> {
-        let (width, height) = size.physical_size();
-        let (width, height) = (width as u32, height as u32);
+impl<'a> Drop for VulkanFrame<'a> {
+    fn drop(&mut self) {
+        // Not presented: there's no way to give an image back to the
+        // swapchain, so hold on to it for the next frame instead.
+        if let Some(acquired) = self.acquired.take() {
+            // Whatever was drawn still lands, so leave the image
+            // in a layout the next frame knows it's in.
+            if let Some(mut skia) = self.skia.take() {
+                let gr_context = &mut self.surface.gr_context;
+                gr_context.flush_surface_with_access(
+                    &mut skia,
+                    BackendSurfaceAccess::Present,
+                    &FlushInfo::default(),
+                );
+                gr_context.submit(SyncCpu::No);
+                self.surface.presented_images[self.image_index as usize] = true;
+            }
 
-        let (new_swapchain, new_images) = self.swapchain.recreate(SwapchainCreateInfo {
-            image_extent: [width, height],
-            ..self.swapchain.create_info()
-        })?;
+            self.surface.pending_image = Some((self.image_index, acquired));
+        }
 
-        self.image_views = new_images
-            .iter()
-            .cloned()
-            .map(ImageView::new_default)
-            .collect::<Result<_, _>>()?;
+        // Dropping a submission vulkano isn't tracking with a fence waits
+        // for the whole queue, so track timestamp writes like frames.
+        for fut in self.gpu_futures.drain(..) {
+            if let Ok(fut) = fut.then_signal_fence_and_flush() {
+                self.surface.in_flight.push_back(fut);
+            }
+        }
+    }
+}
 
-        self.swapchain = new_swapchain;
-        self.images = new_images;
+impl Drop for VulkanSurface {
+    fn drop(&mut self) {
+        // The swapchain mustn't go whilst the GPU is still presenting from it.
+        if self.finish().is_err() {
+            log::warn!(
+                target: VULKAN_TARGET,
+                "Could not wait for in-flight frames before dropping the surface."
+            );
+        }
+    }
+}
 
-        self.recreate_swapchain = false;
+impl VulkanSurface {
+    ///
+    /// Forget frames the GPU has finished with, and wait for the
+    /// oldest one if there are already too many in flight.
+    ///
+    fn wait_for_frame_slot(&mut self) -> Result<(), Error> {
+        while let Some(oldest) = self.in_flight.front() {
+            if oldest.is_signaled()? {
+                self.in_flight.pop_front();
+            } else if self.in_flight.len() >= self.frames_in_flight {
+                oldest.wait(None)?;
+                self.in_flight.pop_front();
+            } else {
+                break;
+            }
+        }
 
         Ok(())
     }
 
-    pub fn skia_surface(
+    ///
+    /// Acquire the next swapchain image, recreating the swapchain first if need be.
+    ///
+    fn acquire(
         &mut self,
-        image: &Arc<Image>,
-        size: &Size,
-    ) -> Result<skia_safe::RCHandle<SkSurface>, Error> {
-        let image_info = unsafe {
-            skia_safe::gpu::vk::ImageInfo::new(
-                image.handle().as_raw() as _,
-                Default::default(),
+        size: &SizeSnapshot,
+        timings: &mut FrameTimings,
+    ) -> Result<Option<(u32, Box<dyn GpuFuture>)>, Error> {
+        // Resizes which land whilst this frame is drawn show up as a newer
+        // generation next frame, and get exactly one more recreation.
+        if size.generation != self.size_generation {
+            self.recreate_swapchain = true;
+        }
+
+        if self.recreate_swapchain {
+            // Any image held over from a discarded frame
+            // belongs to the old swapchain.
+            self.pending_image.take();
+
+            self.recreate_swapchain(size)?;
+            timings.swapchain_recreated = true;
+        }
+
+        self.wait_for_frame_slot()?;
+
+        let (image_index, acquire_fut) = match self.pending_image.take() {
+            Some(pending) => return Ok(Some(pending)),
+            None => {
+                let swapchain = self.swapchain.clone().ok_or(Error::Suspended)?;
+
+                match vulkano::swapchain::acquire_next_image(swapchain, None)
+                    .map_err(Validated::unwrap)
+                {
+                    Ok((image_index, suboptimal, acquire_fut)) => {
+                        if suboptimal {
+                            // Recreate swapchain next frame.
+                            self.recreate_swapchain = true;
+                            timings.suboptimal = true;
+                        }
+
+                        (image_index, acquire_fut)
+                    }
+                    Err(vulkano::VulkanError::OutOfDate) => {
+                        self.recreate_swapchain = true;
+                        return Ok(None);
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        };
+
+        match self.wait_for_image(acquire_fut) {
+            Ok(acquired) => Ok(Some((image_index, acquired))),
+            Err(err) => {
+                // The image can't be given back, only dropped along with its swapchain.
+                self.recreate_swapchain = true;
+                Err(err)
+            }
+        }
+    }
+
+    ///
+    /// Make the graphics queue wait for the acquired image, without blocking the CPU.
+    ///
+    /// Skia's work is submitted without waiting on the acquire semaphore, so
+    /// it's waited on in a (flushed) submission of our own ahead of Skia's: a
+    /// semaphore wait covers everything submitted to the queue after it too.
+    ///
+    fn wait_for_image(
+        &self,
+        acquire_fut: SwapchainAcquireFuture,
+    ) -> Result<Box<dyn GpuFuture>, Error> {
+        let command_buffer = self.command_buffer_builder()?.build()?;
+        let acquired = acquire_fut
+            .then_execute(self.queues.graphics.clone(), command_buffer)?
+            .boxed();
+        acquired.flush()?;
+
+        Ok(acquired)
+    }
+
+    ///
+    /// Like [VulkanSurface::acquire], but recreating the surface should it have
+    /// been lost, up to [MAX_SURFACE_LOST_RETRIES] times before giving up.
+    ///
+    fn acquire_recovering(
+        &mut self,
+        size: &SizeSnapshot,
+        timings: &mut FrameTimings,
+    ) -> Result<Option<(u32, Box<dyn GpuFuture>)>, Error> {
+        let mut attempts = 0;
+
+        loop {
+            let err = match self.lost.take() {
+                Some(err) => err,
+                None => match self.acquire(size, timings) {
+                    Err(err) if err.is_surface_lost() => err,
+                    result => return result,
+                },
+            };
+
+            if attempts == MAX_SURFACE_LOST_RETRIES {
+                log::error!(
+                    target: VULKAN_TARGET,
+                    "[Vulkan] Could not recreate the lost surface: {err}"
+                );
+                // Try again next frame.
+                self.lost = Some(err);
+                return Err(Error::SurfaceLost(attempts));
+            }
+
+            attempts += 1;
+            log::warn!(
+                target: VULKAN_TARGET,
+                "[Vulkan] Surface lost ({err}), recreating it \
+                 (attempt {attempts} of {MAX_SURFACE_LOST_RETRIES})."
+            );
+
+            match self.recover_surface_lost(size, err.is_device_lost()) {
+                Ok(()) => timings.swapchain_recreated = true,
+                Err(err) if err.is_surface_lost() => self.lost = Some(err),
+                Err(recovery_err) => {
+                    self.lost = Some(err);
+                    return Err(recovery_err);
+                }
+            }
+        }
+    }
+
+    ///
+    /// Recreate the KHR surface (from the Wayland one) and its swapchain,
+    /// after the surface was lost, e.g. when it moved to another GPU.
+    ///
+    /// The device, and Skia's context with it, is only recreated if it
+    /// was lost too or can't present to the surface anymore.
+    ///
+    fn recover_surface_lost(
+        &mut self,
+        size: &SizeSnapshot,
+        device_lost: bool,
+    ) -> Result<(), Error> {
+        self.pending_image.take();
+
+        while let Some(frame) = self.in_flight.pop_front() {
+            // vulkano panics when dropping a frame it can't wait for.
+            if frame.wait(None).is_err() {
+                std::mem::forget(frame);
+            }
+        }
+
+        self.swapchain.take();
+        self.image_views.clear();
+        self.images.clear();
+
+        let instance = self.device.instance().clone();
+        self.khr_surface = unsafe {
+            vulkano::swapchain::Surface::from_wayland(
+                instance.clone(),
+                self.wl_display.id().as_ptr(),
+                self.wl_surface.id().as_ptr(),
+                None,
+            )
+        }?;
+
+        let can_present = !device_lost
+            && self
+                .device
+                .physical_device()
+                .surface_support(self.families.present, &self.khr_surface)
+                .unwrap_or(false);
+
+        if can_present {
+            self.gr_context.free_gpu_resources();
+        } else {
+            self.migrate_device(&instance)?;
+        }
+
+        self.create_swapchain(size)
+    }
+
+    ///
+    /// Move to whichever device is now best to present the surface with.
+    ///
+    fn migrate_device(&mut self, instance: &Arc<Instance>) -> Result<(), Error> {
+        // Enumerated again, as the surface may have moved to a GPU plugged in since.
+        let physical_devices: Vec<_> = instance.enumerate_physical_devices()?.collect();
+        let (physical_device, families) =
+            best_physical_device(&physical_devices, &self.khr_surface, &device_extensions())
+                .ok_or(Error::NoDevice)?;
+
+        let capabilities =
+            physical_device.surface_capabilities(&self.khr_surface, Default::default())?;
+        let image_format = bgra_format(&physical_device, &self.khr_surface)?;
+
+        let (device, queues) = create_device(&physical_device, &families)?;
+        let gr_context = create_gr_context(&device, &queues, &families)?;
+        record_device(&physical_device);
+
+        log::warn!(
+            target: VULKAN_TARGET,
+            "[Vulkan] Moving the surface from {} to {}, expect a hitch.",
+            self.device.physical_device().properties().device_name,
+            physical_device.properties().device_name,
+        );
+
+        // Skia's old context goes first, whilst its device is still around.
+        self.gr_context.abandon();
+        self.gr_context = gr_context;
+        self.command_buffer_allocator =
+            StandardCommandBufferAllocator::new(device.clone(), Default::default());
+        self.gpu_timer = self
+            .gpu_timing
+            .then(|| GpuTimer::new(&device, families.graphics, self.frames_in_flight))
+            .flatten();
+        self.queues = queues;
+        self.families = families;
+        // Handles hold on to the lock they were made with.
+        adopt_device_lock(&device, &self.device_lock);
+        self.device = device;
+
+        self.swapchain_create_info = SwapchainCreateInfo {
+            min_image_count: capabilities.min_image_count + 1,
+            image_format,
+            image_sharing: families.swapchain_sharing(),
+            ..self.swapchain_create_info.clone()
+        };
+
+        Ok(())
+    }
+
+    fn command_buffer_builder(
+        &self,
+    ) -> Result<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, Error> {
+        Ok(AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queues.graphics.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?)
+    }
+
+    ///
+    /// The (otherwise empty) submission presenting is ordered after, writing
+    /// the last timestamp of the frame in `gpu_slot`, if it's being timed.
+    ///
+    fn present_command_buffer(
+        &self,
+        gpu_slot: Option<u32>,
+    ) -> Result<Arc<PrimaryAutoCommandBuffer>, Error> {
+        let mut builder = self.command_buffer_builder()?;
+
+        if let (Some(timer), Some(slot)) = (&self.gpu_timer, gpu_slot) {
+            // SAFETY: The slot's queries were reset when the frame began.
+            unsafe {
+                builder
+                    .write_timestamp(
+                        timer.pool.clone(),
+                        GpuTimer::query(slot, GpuTimer::PRESENTING),
+                        PipelineStage::BottomOfPipe,
+                    )
+                    .map_err(Validated::<VulkanError>::from)?;
+            }
+        }
+
+        Ok(builder.build()?)
+    }
+
+    ///
+    /// Read back the timings of earlier frames and start timing this one,
+    /// returning its slot and the (already flushed) submission starting it.
+    ///
+    fn begin_gpu_timing(
+        &mut self,
+        timings: &mut FrameTimings,
+    ) -> Option<(u32, Box<dyn GpuFuture>)> {
+        let Some(timer) = self.gpu_timer.as_mut() else {
+            timings.gpu = if self.gpu_timing {
+                GpuTimings::Unavailable
+            } else {
+                GpuTimings::Off
+            };
+
+            return None;
+        };
+
+        timings.gpu = match timer.resolve() {
+            Some((render, total)) => GpuTimings::Measured { render, total },
+            None => GpuTimings::Pending,
+        };
+
+        let slot = timer.next_slot()?;
+        let pool = timer.pool.clone();
+
+        match self.submit_timestamp(pool, slot, GpuTimer::BEGUN) {
+            Ok(fut) => Some((slot, fut)),
+            Err(err) => {
+                log::warn!(target: VULKAN_TARGET, "Could not time a frame on the GPU: {err}");
+                None
+            }
+        }
+    }
+
+    ///
+    /// Submit (straight away) a timestamp write for `query` of the frame in `slot`,
+    /// once the work submitted before has finished. The frame's first also
+    /// resets the slot's queries.
+    ///
+    fn submit_timestamp(
+        &self,
+        pool: Arc<QueryPool>,
+        slot: u32,
+        query: u32,
+    ) -> Result<Box<dyn GpuFuture>, Error> {
+        let mut builder = self.command_buffer_builder()?;
+
+        // SAFETY: The slot isn't pending, so the GPU is done with its queries,
+        // and they're reset (in submission order) before being written.
+        unsafe {
+            if query == GpuTimer::BEGUN {
+                builder
+                    .reset_query_pool(pool.clone(), GpuTimer::queries(slot))
+                    .map_err(Validated::<VulkanError>::from)?;
+            }
+
+            let stage = if query == GpuTimer::BEGUN {
+                PipelineStage::TopOfPipe
+            } else {
+                PipelineStage::BottomOfPipe
+            };
+
+            builder
+                .write_timestamp(pool, GpuTimer::query(slot, query), stage)
+                .map_err(Validated::<VulkanError>::from)?;
+        }
+
+        let fut = builder.build()?.execute(self.queues.graphics.clone())?;
+        fut.flush()?;
+
+        Ok(fut.boxed())
+    }
+
+    ///
+    /// The most frames this surface lets the CPU queue up, see [Vulkan::with_frames_in_flight].
+    ///
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight
+    }
+
+    pub fn recreate_swapchain(&mut self, size: &SizeSnapshot) -> Result<(), Error> {
+        let (width, height) = size.physical_size();
+        let (width, height) = (width as u32, height as u32);
+
+        let swapchain = self.swapchain.as_ref().ok_or(Error::Suspended)?;
+        let (new_swapchain, new_images) = swapchain.recreate(SwapchainCreateInfo {
+            image_extent: [width, height],
+            ..swapchain.create_info()
+        })?;
+
+        self.image_views = new_images
+            .iter()
+            .cloned()
+            .map(ImageView::new_default)
+            .collect::<Result<_, _>>()?;
+
+        self.swapchain_create_info = new_swapchain.create_info();
+        self.swapchain = Some(new_swapchain);
+        self.presented_images = vec![false; new_images.len()];
+        self.images = new_images;
+
+        self.recreate_swapchain = false;
+        self.size_generation = size.generation;
+
+        log::debug!(target: VULKAN_TARGET, "Recreated the swapchain at {width}x{height}");
+        Ok(())
+    }
+
+    ///
+    /// Make a brand new swapchain (e.g. after being suspended).
+    ///
+    fn create_swapchain(&mut self, size: &SizeSnapshot) -> Result<(), Error> {
+        let (width, height) = size.physical_size();
+        let (width, height) = (width as u32, height as u32);
+
+        let (swapchain, images) = Swapchain::new(
+            self.device.clone(),
+            self.khr_surface.clone(),
+            SwapchainCreateInfo {
+                image_extent: [width, height],
+                ..self.swapchain_create_info.clone()
+            },
+        )?;
+
+        self.image_views = images
+            .iter()
+            .cloned()
+            .map(ImageView::new_default)
+            .collect::<Result<_, _>>()?;
+
+        self.swapchain = Some(swapchain);
+        self.presented_images = vec![false; images.len()];
+        self.images = images;
+        self.recreate_swapchain = false;
+        self.size_generation = size.generation;
+
+        log::debug!(target: VULKAN_TARGET, "Created a swapchain at {width}x{height}");
+        Ok(())
+    }
+
+    ///
+    /// Wrap `image`, currently in `layout`, for Skia to draw into. Skia moves
+    /// it from there into the layouts it needs, so that has to be right: a
+    /// swapchain image is never in the one for drawing when it's acquired.
+    ///
+    pub fn skia_surface(
+        &mut self,
+        image: &Arc<Image>,
+        layout: skia_bindings::VkImageLayout,
+        size: &SizeSnapshot,
+    ) -> Result<skia_safe::RCHandle<SkSurface>, Error> {
+        let image_info = unsafe {
+            skia_safe::gpu::vk::ImageInfo::new(
+                image.handle().as_raw() as _,
+                Default::default(),
                 skia_bindings::VkImageTiling::OPTIMAL,
-                skia_bindings::VkImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                layout,
                 skia_safe::gpu::vk::Format::B8G8R8A8_UNORM,
                 1,
                 None,
@@ -387,31 +1383,318 @@ impl VulkanSurface {
             skia_bindings::GrSurfaceOrigin::TopLeft,
             skia_safe::ColorType::BGRA8888,
             None,
-            None,
+            Some(&self.surface_props),
         )
         .ok_or(Error::SkiaSurfaceError)
     }
 }
 
+///
+/// Timestamp queries around each frame's work on the GPU, see [Vulkan::with_gpu_timing].
+///
+/// Each frame in flight gets a slot of [GpuTimer::QUERIES_PER_FRAME] queries,
+/// read back (never waiting) once they're all available.
+///
+struct GpuTimer {
+    pool: Arc<QueryPool>,
+    slots: u32,
+    next_slot: u32,
+    /// Slots of frames submitted to the GPU, oldest first.
+    pending: VecDeque<u32>,
+    /// Nanoseconds per tick.
+    period: f64,
+    /// Timestamps only have this many meaningful bits.
+    mask: u64,
+}
+
+impl GpuTimer {
+    const BEGUN: u32 = 0;
+    const SKIA_DONE: u32 = 1;
+    const PRESENTING: u32 = 2;
+    const QUERIES_PER_FRAME: u32 = 3;
+
+    ///
+    /// `None` if the device can't time work on the graphics queue family.
+    ///
+    fn new(device: &Arc<Device>, graphics_family: u32, frames_in_flight: usize) -> Option<Self> {
+        let physical_device = device.physical_device();
+        let properties = physical_device.properties();
+        let valid_bits = physical_device.queue_family_properties()[graphics_family as usize]
+            .timestamp_valid_bits?;
+
+        if !properties.timestamp_compute_and_graphics {
+            log::info!(
+                target: VULKAN_TARGET,
+                "[Vulkan] The device can't time graphics work, GPU timings are unavailable."
+            );
+            return None;
+        }
+
+        // Room for every frame in flight, plus those whose results haven't been read yet.
+        let slots = frames_in_flight as u32 + 2;
+
+        let pool = QueryPool::new(
+            device.clone(),
+            QueryPoolCreateInfo {
+                query_count: slots * Self::QUERIES_PER_FRAME,
+                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+            },
+        );
+
+        let pool = match pool {
+            Ok(pool) => pool,
+            Err(err) => {
+                log::warn!(
+                    target: VULKAN_TARGET,
+                    "[Vulkan] Could not create a timestamp query pool: {err}"
+                );
+                return None;
+            }
+        };
+
+        Some(Self {
+            pool,
+            slots,
+            next_slot: 0,
+            pending: VecDeque::with_capacity(slots as usize),
+            period: properties.timestamp_period as f64,
+            mask: u64::MAX >> (64 - valid_bits.min(64)),
+        })
+    }
+
+    fn query(slot: u32, query: u32) -> u32 {
+        slot * Self::QUERIES_PER_FRAME + query
+    }
+
+    fn queries(slot: u32) -> Range<u32> {
+        Self::query(slot, 0)..Self::query(slot + 1, 0)
+    }
+
+    ///
+    /// The slot to time the next frame in, unless it's still waiting to be read.
+    ///
+    fn next_slot(&mut self) -> Option<u32> {
+        let slot = self.next_slot;
+        if self.pending.contains(&slot) {
+            return None;
+        }
+
+        self.next_slot = (slot + 1) % self.slots;
+        Some(slot)
+    }
+
+    ///
+    /// The frame in `slot` was submitted with all of its timestamps.
+    ///
+    fn submitted(&mut self, slot: u32) {
+        self.pending.push_back(slot);
+    }
+
+    ///
+    /// The render and total durations of the latest frame the GPU has
+    /// finished since the last call, if any, without waiting.
+    ///
+    fn resolve(&mut self) -> Option<(Duration, Duration)> {
+        let mut latest = None;
+
+        while let Some(&slot) = self.pending.front() {
+            // Each timestamp, followed by whether it's available.
+            let mut results = [0u64; 2 * Self::QUERIES_PER_FRAME as usize];
+
+            let read = self.pool.get_results(
+                Self::queries(slot),
+                &mut results,
+                QueryResultFlags::WITH_AVAILABILITY,
+            );
+
+            if read.is_err() || results.chunks(2).any(|result| result[1] == 0) {
+                break;
+            }
+
+            self.pending.pop_front();
+
+            let elapsed = |to: u32| {
+                let ticks = results[2 * to as usize]
+                    .wrapping_sub(results[2 * Self::BEGUN as usize])
+                    & self.mask;
+                Duration::from_nanos((ticks as f64 * self.period) as u64)
+            };
+
+            latest = Some((elapsed(Self::SKIA_DONE), elapsed(Self::PRESENTING)));
+        }
+
+        latest
+    }
+}
+
+///
+/// Queue families picked for each kind of work.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFamilySelection {
+    pub graphics: u32,
+    pub present: u32,
+
+    /// Whether presentation gets its own queue, even
+    /// if it's in the same family as graphics.
+    pub dedicated_present_queue: bool,
+}
+
+impl QueueFamilySelection {
+    ///
+    /// Pick queue families from their flags and queue counts.
+    ///
+    /// Prefers a present queue separate from the graphics one.
+    ///
+    /// There's no transfer family: uploads and readback go through
+    /// Skia's context, which only ever submits to the graphics queue.
+    ///
+    pub fn select(
+        families: &[(QueueFlags, u32)],
+        supports_present: impl Fn(u32) -> bool,
+    ) -> Option<Self> {
+        let indices = || (0..families.len() as u32).filter(|&i| families[i as usize].1 > 0);
+
+        // Rendering is what matters most, so prefer a graphics
+        // family which can also present as a fallback.
+        let graphics = indices()
+            .filter(|&i| families[i as usize].0.contains(QueueFlags::GRAPHICS))
+            .min_by_key(|&i| !supports_present(i))?;
+
+        let (present, dedicated_present_queue) =
+            match indices().find(|&i| i != graphics && supports_present(i)) {
+                Some(present) => (present, true),
+                None if supports_present(graphics) => (graphics, families[graphics as usize].1 > 1),
+                None => return None,
+            };
+
+        Some(Self {
+            graphics,
+            present,
+            dedicated_present_queue,
+        })
+    }
+
+    ///
+    /// Queue create infos covering every selected family, in family order.
+    ///
+    pub fn queue_create_infos(&self) -> Vec<QueueCreateInfo> {
+        let mut counts = std::collections::BTreeMap::<u32, usize>::new();
+
+        *counts.entry(self.graphics).or_default() += 1;
+        if self.dedicated_present_queue {
+            *counts.entry(self.present).or_default() += 1;
+        }
+
+        counts
+            .into_iter()
+            .map(|(queue_family_index, count)| QueueCreateInfo {
+                queue_family_index,
+                queues: vec![0.5; count],
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    ///
+    /// Swapchain images are used by both the graphics and present
+    /// queues, so have to be shared when they're in different families.
+    ///
+    pub fn swapchain_sharing(&self) -> Sharing<SmallVec<[u32; 4]>> {
+        if self.graphics == self.present {
+            Sharing::Exclusive
+        } else {
+            Sharing::Concurrent([self.graphics, self.present].into_iter().collect())
+        }
+    }
+}
+
+///
+/// The queues a [VulkanSurface] submits work to.
+///
+/// `graphics` and `present` may be the same queue.
+///
+pub struct Queues {
+    pub graphics: Arc<Queue>,
+    pub present: Arc<Queue>,
+}
+
+impl Queues {
+    fn new(
+        families: &QueueFamilySelection,
+        create_infos: &[QueueCreateInfo],
+        created: Vec<Arc<Queue>>,
+    ) -> Self {
+        // Queues come back in the order they were requested.
+        let mut by_family = std::collections::HashMap::<u32, Vec<Arc<Queue>>>::new();
+        let mut created = created.into_iter();
+        for info in create_infos {
+            by_family.insert(
+                info.queue_family_index,
+                created.by_ref().take(info.queues.len()).collect(),
+            );
+        }
+
+        let mut take = |family: u32| by_family.get_mut(&family).unwrap().remove(0);
+
+        let graphics = take(families.graphics);
+        let present = if families.dedicated_present_queue {
+            take(families.present)
+        } else {
+            graphics.clone()
+        };
+
+        Self { graphics, present }
+    }
+}
+
+impl VulkanSurface {
+    pub fn queues(&self) -> &Queues {
+        &self.queues
+    }
+}
+
+///
+/// Pick the device to render `surface` with, probing each one's
+/// queue families on its own thread (drivers can be slow to answer).
+///
 fn best_physical_device(
-    instance: Arc<Instance>,
-    surface: Arc<vulkano::swapchain::Surface>,
+    devices: &[Arc<PhysicalDevice>],
+    surface: &Arc<vulkano::swapchain::Surface>,
     device_extensions: &DeviceExtensions,
-) -> (Arc<PhysicalDevice>, u32) {
-    instance
-        .enumerate_physical_devices()
-        .expect("could not enumerate devices")
-        .filter(|p| p.supported_extensions().contains(device_extensions))
-        .filter_map(|p| {
-            p.queue_family_properties()
-                .iter()
-                .enumerate()
-                .position(|(i, q)| {
-                    q.queue_flags.contains(QueueFlags::GRAPHICS)
-                        && p.surface_support(i as u32, &surface).unwrap_or(false)
-                })
-                .map(|q| (p, q as u32))
+) -> Option<(Arc<PhysicalDevice>, QueueFamilySelection)> {
+    let probe = |p: &Arc<PhysicalDevice>| {
+        if !p.supported_extensions().contains(device_extensions) {
+            return None;
+        }
+
+        let families: Vec<_> = p
+            .queue_family_properties()
+            .iter()
+            .map(|q| (q.queue_flags, q.queue_count))
+            .collect();
+
+        QueueFamilySelection::select(&families, |i| {
+            p.surface_support(i, surface).unwrap_or(false)
         })
+        .map(|selection| (p.clone(), selection))
+    };
+
+    let candidates: Vec<_> = match devices {
+        [device] => probe(device).into_iter().collect(),
+        devices => std::thread::scope(|scope| {
+            devices
+                .iter()
+                .map(|p| scope.spawn(move || probe(p)))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .filter_map(|probing| probing.join().ok().flatten())
+                .collect()
+        }),
+    };
+
+    candidates
+        .into_iter()
         .min_by_key(|(p, _)| match p.properties().device_type {
             PhysicalDeviceType::DiscreteGpu => 0,
             PhysicalDeviceType::IntegratedGpu => 1,
@@ -419,5 +1702,298 @@ fn best_physical_device(
             PhysicalDeviceType::Cpu => 3,
             _ => 4,
         })
-        .expect("no device available")
+}
+
+fn device_extensions() -> DeviceExtensions {
+    DeviceExtensions {
+        khr_swapchain: true,
+        ..Default::default()
+    }
+}
+
+///
+/// Describe `physical_device` in the trace's header, see [crate::debug::trace].
+///
+fn record_device(physical_device: &PhysicalDevice) {
+    if !trace::is_enabled() {
+        return;
+    }
+
+    let properties = physical_device.properties();
+    trace::record_device(format!(
+        "{} ({:?}), driver {} {} (version {:#x}), Vulkan {}",
+        properties.device_name,
+        properties.device_type,
+        properties.driver_name.as_deref().unwrap_or("unknown"),
+        properties.driver_info.as_deref().unwrap_or_default(),
+        properties.driver_version,
+        properties.api_version,
+    ));
+}
+
+///
+/// Every live device's lock, shared by all the surfaces rendering with it.
+///
+pub(crate) struct DeviceLocks<D> {
+    locks: Vec<(Weak<D>, Weak<Mutex<()>>)>,
+}
+
+impl<D> DeviceLocks<D> {
+    pub const fn new() -> Self {
+        Self { locks: Vec::new() }
+    }
+
+    ///
+    /// `device`'s lock, made now if no surface holds on to one.
+    ///
+    pub fn lock_for(&mut self, device: &Arc<D>) -> DeviceLock {
+        self.prune();
+
+        let existing = self
+            .locks
+            .iter()
+            .find(|(other, _)| std::ptr::eq(other.as_ptr(), Arc::as_ptr(device)))
+            .and_then(|(_, lock)| lock.upgrade());
+        if let Some(lock) = existing {
+            return lock;
+        }
+
+        let lock = DeviceLock::default();
+        self.adopt(device, &lock);
+        lock
+    }
+
+    ///
+    /// Make `lock` the one for `device`, e.g. for a device made
+    /// again, which replaces the one `lock` was made for.
+    ///
+    pub fn adopt(&mut self, device: &Arc<D>, lock: &DeviceLock) {
+        self.prune();
+        self.locks
+            .retain(|(other, _)| !std::ptr::eq(other.as_ptr(), Arc::as_ptr(device)));
+        self.locks
+            .push((Arc::downgrade(device), Arc::downgrade(lock)));
+    }
+
+    /// Forget devices which are gone, and locks nobody holds.
+    fn prune(&mut self) {
+        self.locks
+            .retain(|(device, lock)| device.strong_count() > 0 && lock.strong_count() > 0);
+    }
+}
+
+static DEVICE_LOCKS: Mutex<DeviceLocks<Device>> = Mutex::new(DeviceLocks::new());
+
+///
+/// The lock for rendering with `device`, which surfaces (from any
+/// [Vulkan] backend) share as long as they share the device.
+///
+fn device_lock(device: &Arc<Device>) -> DeviceLock {
+    DEVICE_LOCKS.lock().unwrap().lock_for(device)
+}
+
+fn adopt_device_lock(device: &Arc<Device>, lock: &DeviceLock) {
+    DEVICE_LOCKS.lock().unwrap().adopt(device, lock);
+}
+
+fn create_device(
+    physical_device: &Arc<PhysicalDevice>,
+    families: &QueueFamilySelection,
+) -> Result<(Arc<Device>, Queues), Error> {
+    let queue_create_infos = families.queue_create_infos();
+
+    let (device, created_queues) = Device::new(
+        physical_device.clone(),
+        DeviceCreateInfo {
+            queue_create_infos: queue_create_infos.clone(),
+            enabled_extensions: device_extensions(),
+            ..Default::default()
+        },
+    )?;
+
+    let queues = Queues::new(families, &queue_create_infos, created_queues.collect());
+    Ok((device, queues))
+}
+
+fn bgra_format(
+    physical_device: &PhysicalDevice,
+    khr_surface: &vulkano::swapchain::Surface,
+) -> Result<vulkano::format::Format, Error> {
+    physical_device
+        .surface_formats(khr_surface, Default::default())
+        .into_iter()
+        .flatten()
+        .map(|(format, _)| format)
+        .find(|format| &vulkano::format::Format::B8G8R8A8_UNORM == format)
+        .ok_or(Error::UnsupportedBGRA)
+}
+
+fn create_gr_context(
+    device: &Arc<Device>,
+    queues: &Queues,
+    families: &QueueFamilySelection,
+) -> Result<skia_safe::RCHandle<GrDirectContext>, Error> {
+    let instance = device.instance();
+    let physical_device = device.physical_device();
+
+    let get_proc = |of: GetProcOf| unsafe {
+        let res = match of {
+            skia_safe::gpu::vk::GetProcOf::Instance(raw_instance, name) => instance
+                .library()
+                .get_instance_proc_addr(ash::vk::Instance::from_raw(raw_instance as _), name),
+            skia_safe::gpu::vk::GetProcOf::Device(device, name) => {
+                (instance.fns().v1_0.get_device_proc_addr)(
+                    ash::vk::Device::from_raw(device as _),
+                    name,
+                )
+            }
+        };
+
+        match res {
+            Some(f) => f as _,
+            None => core::ptr::null(),
+        }
+    };
+
+    let backend_context = unsafe {
+        skia_safe::gpu::vk::BackendContext::new(
+            instance.handle().as_raw() as _,
+            physical_device.handle().as_raw() as _,
+            device.handle().as_raw() as _,
+            (
+                queues.graphics.handle().as_raw() as _,
+                families.graphics as _,
+            ),
+            &get_proc,
+        )
+    };
+
+    skia_safe::gpu::direct_contexts::make_vulkan(&backend_context, None)
+        .ok_or(Error::SkiaCreationError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAPHICS: QueueFlags = QueueFlags::GRAPHICS.union(QueueFlags::TRANSFER);
+
+    #[test]
+    fn prefers_a_separate_present_family() {
+        let families = [
+            (GRAPHICS, 1),
+            (QueueFlags::TRANSFER, 2),
+            (QueueFlags::COMPUTE, 1),
+        ];
+        let selection = QueueFamilySelection::select(&families, |i| i != 1).unwrap();
+
+        assert_eq!(selection.graphics, 0);
+        assert_eq!(selection.present, 2);
+        assert!(selection.dedicated_present_queue);
+        assert!(matches!(
+            selection.swapchain_sharing(),
+            Sharing::Concurrent(_)
+        ));
+    }
+
+    #[test]
+    fn shares_a_single_queue() {
+        let selection = QueueFamilySelection::select(&[(GRAPHICS, 1)], |_| true).unwrap();
+
+        assert_eq!((selection.graphics, selection.present), (0, 0));
+        assert!(!selection.dedicated_present_queue);
+        assert!(matches!(selection.swapchain_sharing(), Sharing::Exclusive));
+
+        let infos = selection.queue_create_infos();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].queues.len(), 1);
+    }
+
+    #[test]
+    fn takes_a_second_queue_from_the_graphics_family() {
+        let selection = QueueFamilySelection::select(&[(GRAPHICS, 2)], |_| true).unwrap();
+        assert!(selection.dedicated_present_queue);
+
+        let infos = selection.queue_create_infos();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].queues.len(), 2);
+    }
+
+    #[test]
+    fn prefers_a_graphics_family_which_presents() {
+        let families = [(GRAPHICS, 1), (GRAPHICS, 1)];
+        let selection = QueueFamilySelection::select(&families, |i| i == 1).unwrap();
+
+        assert_eq!((selection.graphics, selection.present), (1, 1));
+    }
+
+    #[test]
+    fn skips_empty_families() {
+        let families = [(GRAPHICS, 0), (GRAPHICS, 1)];
+        let selection = QueueFamilySelection::select(&families, |_| true).unwrap();
+
+        assert_eq!(selection.graphics, 1);
+    }
+
+    #[test]
+    fn needs_graphics_and_presentation() {
+        assert_eq!(
+            QueueFamilySelection::select(&[(GRAPHICS, 1)], |_| false),
+            None
+        );
+        assert_eq!(
+            QueueFamilySelection::select(&[(QueueFlags::TRANSFER, 1)], |_| true),
+            None
+        );
+    }
+
+    #[test]
+    fn surfaces_share_their_devices_lock() {
+        let mut locks = DeviceLocks::new();
+        let (first, second) = (Arc::new(0), Arc::new(1));
+
+        let a = locks.lock_for(&first);
+        assert!(Arc::ptr_eq(&a, &locks.lock_for(&first)));
+        assert!(!Arc::ptr_eq(&a, &locks.lock_for(&second)));
+    }
+
+    #[test]
+    fn a_device_made_again_keeps_the_lock() {
+        let mut locks = DeviceLocks::new();
+        let old = Arc::new(0);
+        let lock = locks.lock_for(&old);
+
+        drop(old);
+        let new = Arc::new(0);
+        locks.adopt(&new, &lock);
+
+        assert!(Arc::ptr_eq(&lock, &locks.lock_for(&new)));
+        assert_eq!(locks.locks.len(), 1);
+    }
+
+    #[test]
+    fn handles_on_one_device_never_render_at_once() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let mut locks = DeviceLocks::new();
+        let device = Arc::new(0);
+        let handles = [locks.lock_for(&device), locks.lock_for(&device)];
+        let rendering = Arc::new(AtomicBool::new(false));
+
+        let threads = handles.map(|lock| {
+            let rendering = rendering.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10_000 {
+                    let _device = lock.lock().unwrap();
+                    assert!(!rendering.swap(true, Ordering::SeqCst));
+                    std::hint::spin_loop();
+                    rendering.store(false, Ordering::SeqCst);
+                }
+            })
+        });
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
 }
@@ -0,0 +1,329 @@
+//!
+//! Reacting to the user going idle (and coming back), e.g. to dim a
+//! clock overlay after five minutes, through `ext_idle_notify_v1`.
+//!
+//! The user is idle once every seat has been inactive for the timeout,
+//! and stops being idle as soon as any seat is used again.
+//!
+
+use std::{collections::HashMap, time::Duration};
+
+use smithay_client_toolkit::reexports::{
+    client::{protocol::wl_seat::WlSeat, Connection, QueueHandle},
+    protocols::ext::idle_notify::v1::client::ext_idle_notification_v1::ExtIdleNotificationV1,
+};
+use thiserror::Error;
+
+use crate::{
+    proxy::AvyProxy,
+    wayland::protocol::idle_notify::{IdleNotification, IdleNotifier, IdleNotifyHandler},
+    AvyClient,
+};
+
+#[derive(Debug, Error)]
+pub enum IdleError {
+    #[error("The compositor doesn't support ext_idle_notify_v1.")]
+    Unsupported,
+}
+
+///
+/// What to call when the user goes idle, and when they're back.
+///
+pub struct IdleCallbacks {
+    pub idled: Box<dyn FnMut()>,
+    pub resumed: Box<dyn FnMut()>,
+}
+
+impl IdleCallbacks {
+    pub fn new(idled: impl FnMut() + 'static, resumed: impl FnMut() + 'static) -> Self {
+        Self {
+            idled: Box::new(idled),
+            resumed: Box::new(resumed),
+        }
+    }
+}
+
+enum IdleAction {
+    Callbacks(IdleCallbacks),
+    /// See [AvyClient::throttle_when_idle].
+    Throttle(f64),
+}
+
+struct IdleRegistration {
+    timeout: Duration,
+    action: IdleAction,
+    /// One per seat, with whether that seat is idle.
+    notifications: Vec<(ExtIdleNotificationV1, bool)>,
+    idle: bool,
+}
+
+impl IdleRegistration {
+    fn destroy(&self) {
+        for (notification, _) in &self.notifications {
+            notification.destroy();
+        }
+    }
+}
+
+///
+/// Stops watching for idleness when dropped.
+///
+#[must_use = "Dropping the guard stops watching straight away"]
+pub struct IdleGuard {
+    registration: u64,
+    proxy: AvyProxy,
+}
+
+impl IdleGuard {
+    ///
+    /// Keep watching for as long as the client lives.
+    ///
+    pub fn detach(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for IdleGuard {
+    fn drop(&mut self) {
+        let registration = self.registration;
+
+        // Nothing to clean up if the client is gone already.
+        let _ = self
+            .proxy
+            .invoke(move |app| app.stop_idle_watch(registration));
+    }
+}
+
+///
+/// The client's idle watches.
+///
+pub struct IdleWatches {
+    notifier: Option<IdleNotifier>,
+    registrations: HashMap<u64, IdleRegistration>,
+    next_registration: u64,
+    /// Frame rate render groups are held to whilst the user's idle.
+    throttle: Option<f64>,
+}
+
+impl IdleWatches {
+    pub fn new(notifier: Option<IdleNotifier>) -> Self {
+        Self {
+            notifier,
+            registrations: HashMap::new(),
+            next_registration: 0,
+            throttle: None,
+        }
+    }
+
+    ///
+    /// The lowest frame rate asked for by [AvyClient::throttle_when_idle]
+    /// whilst the user's idle, if they are.
+    ///
+    pub fn throttle(&self) -> Option<f64> {
+        self.throttle
+    }
+}
+
+impl AvyClient {
+    ///
+    /// Call `callbacks.idled` (on the event loop) once the user has been
+    /// inactive for `timeout`, then `callbacks.resumed` when they're back.
+    ///
+    /// Any number of watches, with different timeouts, can run at once.
+    ///
+    pub fn on_idle(
+        &mut self,
+        timeout: Duration,
+        callbacks: IdleCallbacks,
+    ) -> Result<IdleGuard, IdleError> {
+        self.watch_idle(timeout, IdleAction::Callbacks(callbacks))
+    }
+
+    ///
+    /// Hold every render group to at most `fps` frames per second whilst
+    /// the user has been inactive for `timeout`.
+    ///
+    pub fn throttle_when_idle(
+        &mut self,
+        timeout: Duration,
+        fps: f64,
+    ) -> Result<IdleGuard, IdleError> {
+        self.watch_idle(timeout, IdleAction::Throttle(fps))
+    }
+
+    ///
+    /// Whether the user is idle for some throttling
+    /// watch (see [AvyClient::throttle_when_idle]).
+    ///
+    pub fn is_user_idle(&self) -> bool {
+        self.idle.throttle.is_some()
+    }
+
+    fn watch_idle(
+        &mut self,
+        timeout: Duration,
+        action: IdleAction,
+    ) -> Result<IdleGuard, IdleError> {
+        let notifier = self.idle.notifier.as_ref().ok_or(IdleError::Unsupported)?;
+
+        let registration = self.idle.next_registration;
+        self.idle.next_registration += 1;
+
+        let notifications = self
+            .seat_state
+            .seats()
+            .map(|seat| {
+                let notification =
+                    notifier.notify(&seat, timeout, registration, &self.queue_handle);
+                (notification, false)
+            })
+            .collect();
+
+        self.idle.registrations.insert(
+            registration,
+            IdleRegistration {
+                timeout,
+                action,
+                notifications,
+                idle: false,
+            },
+        );
+
+        Ok(IdleGuard {
+            registration,
+            proxy: self.proxy(),
+        })
+    }
+
+    fn stop_idle_watch(&mut self, registration: u64) {
+        let Some(watch) = self.idle.registrations.remove(&registration) else {
+            return;
+        };
+
+        watch.destroy();
+
+        if matches!(watch.action, IdleAction::Throttle(_)) {
+            self.update_idle_throttle();
+        }
+    }
+
+    pub(crate) fn add_idle_seat(&mut self, seat: &WlSeat) {
+        let Some(notifier) = &self.idle.notifier else {
+            return;
+        };
+
+        for (registration, watch) in &mut self.idle.registrations {
+            let notification =
+                notifier.notify(seat, watch.timeout, *registration, &self.queue_handle);
+            watch.notifications.push((notification, false));
+        }
+    }
+
+    pub(crate) fn remove_idle_seat(&mut self, seat: &WlSeat) {
+        let registrations = self.idle.registrations.keys().copied().collect::<Vec<_>>();
+
+        for registration in registrations {
+            let watch = self.idle.registrations.get_mut(&registration).unwrap();
+            watch.notifications.retain(|(notification, _)| {
+                let on_seat = notification
+                    .data::<IdleNotification>()
+                    .is_some_and(|data| &data.seat == seat);
+
+                if on_seat {
+                    notification.destroy();
+                }
+
+                !on_seat
+            });
+
+            // The remaining seats may all be idle now.
+            self.update_idle(registration);
+        }
+    }
+
+    ///
+    /// Fire the watch's callbacks if every seat just went idle,
+    /// or one just stopped being.
+    ///
+    fn update_idle(&mut self, registration: u64) {
+        let Some(watch) = self.idle.registrations.get_mut(&registration) else {
+            return;
+        };
+
+        let idle =
+            !watch.notifications.is_empty() && watch.notifications.iter().all(|(_, idle)| *idle);
+
+        if idle == watch.idle {
+            return;
+        }
+
+        watch.idle = idle;
+
+        let IdleAction::Callbacks(callbacks) = &mut watch.action else {
+            self.update_idle_throttle();
+            return;
+        };
+
+        if idle {
+            (callbacks.idled)();
+        } else {
+            (callbacks.resumed)();
+        }
+    }
+
+    fn update_idle_throttle(&mut self) {
+        self.idle.throttle = self
+            .idle
+            .registrations
+            .values()
+            .filter(|watch| watch.idle)
+            .filter_map(|watch| match watch.action {
+                IdleAction::Throttle(fps) => Some(fps),
+                IdleAction::Callbacks(_) => None,
+            })
+            .reduce(f64::min);
+    }
+
+    fn set_seat_idle(
+        &mut self,
+        notification: &ExtIdleNotificationV1,
+        data: &IdleNotification,
+        idle: bool,
+    ) {
+        let Some(watch) = self.idle.registrations.get_mut(&data.registration) else {
+            return;
+        };
+
+        if let Some((_, seat_idle)) = watch
+            .notifications
+            .iter_mut()
+            .find(|(other, _)| other == notification)
+        {
+            *seat_idle = idle;
+        }
+
+        self.update_idle(data.registration);
+    }
+}
+
+impl IdleNotifyHandler for AvyClient {
+    fn idled(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        notification: &ExtIdleNotificationV1,
+        data: &IdleNotification,
+    ) {
+        self.set_seat_idle(notification, data, true);
+    }
+
+    fn resumed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        notification: &ExtIdleNotificationV1,
+        data: &IdleNotification,
+    ) {
+        self.set_seat_idle(notification, data, false);
+    }
+}
@@ -0,0 +1,6 @@
+//!
+//! Integrations with the rest of the desktop, each behind its own feature.
+//!
+
+#[cfg(feature = "workspaces")]
+pub mod workspaces;
@@ -0,0 +1,251 @@
+//!
+//! Workspaces through Hyprland's IPC sockets: `.socket2.sock` streams events
+//! (one `name>>data` line each), and `.socket.sock` answers requests, in JSON
+//! when prefixed with `j/`.
+//!
+//! The event loop only reads events: the workspaces are fetched again on a
+//! thread of their own, once for however many changes arrived meanwhile.
+//!
+
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::mpsc::{self, TrySendError},
+    thread,
+};
+
+use serde_json::Value;
+use smithay_client_toolkit::reexports::calloop::{
+    channel::{self, Event},
+    generic::Generic,
+    Interest, LoopHandle, Mode, PostAction,
+};
+
+use crate::AvyClient;
+
+use super::{Workspace, WorkspaceError, WorkspaceModel, WorkspaceProvider};
+
+///
+/// Events after which the workspaces are fetched again.
+///
+const WORKSPACE_EVENTS: &[&str] = &[
+    "workspace",
+    "workspacev2",
+    "focusedmon",
+    "focusedmonv2",
+    "createworkspace",
+    "createworkspacev2",
+    "destroyworkspace",
+    "destroyworkspacev2",
+    "moveworkspace",
+    "moveworkspacev2",
+    "renameworkspace",
+    "monitoradded",
+    "monitoraddedv2",
+    "monitorremoved",
+];
+
+pub struct HyprlandWorkspaces {
+    sockets: PathBuf,
+}
+
+impl HyprlandWorkspaces {
+    ///
+    /// Start following Hyprland's workspaces, or `None` if this isn't Hyprland.
+    ///
+    pub fn connect(
+        handle: &LoopHandle<'static, AvyClient>,
+    ) -> io::Result<Option<(Self, WorkspaceModel)>> {
+        let Some(sockets) = socket_dir() else {
+            return Ok(None);
+        };
+
+        let hyprland = Self { sockets };
+        let model = hyprland.fetch()?;
+
+        let events = UnixStream::connect(hyprland.sockets.join(".socket2.sock"))?;
+        events.set_nonblocking(true)?;
+
+        let fetches = Self::start_fetcher(hyprland.sockets.clone(), handle)?;
+        let mut lines = String::new();
+
+        handle
+            .insert_source(
+                Generic::new(events, Interest::READ, Mode::Level),
+                move |_, events, _| {
+                    let mut buffer = [0; 4096];
+                    let mut changed = false;
+
+                    loop {
+                        match (&**events).read(&mut buffer) {
+                            // Hyprland went away.
+                            Ok(0) => return Ok(PostAction::Remove),
+                            Ok(read) => lines.push_str(&String::from_utf8_lossy(&buffer[..read])),
+                            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                            Err(err) => return Err(err),
+                        }
+                    }
+
+                    // Only whole lines; keep the rest for next time.
+                    while let Some(end) = lines.find('\n') {
+                        let line = lines.drain(..=end).collect::<String>();
+                        let event = line.split(">>").next().unwrap_or_default();
+
+                        changed |= WORKSPACE_EVENTS.contains(&event);
+                    }
+
+                    if changed {
+                        match fetches.try_send(()) {
+                            // A fetch which hasn't started yet sees this change too.
+                            Ok(()) | Err(TrySendError::Full(())) => (),
+                            Err(TrySendError::Disconnected(())) => return Ok(PostAction::Remove),
+                        }
+                    }
+
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.error))?;
+
+        Ok(Some((hyprland, model)))
+    }
+
+    ///
+    /// Fetch the workspaces on a thread whenever asked to through the
+    /// returned sender, and hand them to the client on the event loop.
+    ///
+    /// The thread stops once the sender is dropped, or the event loop is.
+    ///
+    fn start_fetcher(
+        sockets: PathBuf,
+        handle: &LoopHandle<'static, AvyClient>,
+    ) -> io::Result<mpsc::SyncSender<()>> {
+        // At most one fetch waits, which every change until it starts shares.
+        let (fetch_tx, fetch_rx) = mpsc::sync_channel(1);
+        let (model_tx, model_rx) = channel::channel();
+
+        handle
+            .insert_source(model_rx, |event, _, app| {
+                if let Event::Msg(model) = event {
+                    app.set_workspace_model(model);
+                }
+            })
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.error))?;
+
+        let fetcher = Self { sockets };
+        thread::spawn(move || {
+            while fetch_rx.recv().is_ok() {
+                let model = match fetcher.fetch() {
+                    Ok(model) => model,
+                    Err(err) => {
+                        log::warn!("Could not fetch Hyprland's workspaces: {err}");
+                        continue;
+                    }
+                };
+
+                if model_tx.send(model).is_err() {
+                    // Event loop is gone.
+                    break;
+                }
+            }
+        });
+
+        Ok(fetch_tx)
+    }
+
+    fn request(&self, request: &str) -> io::Result<String> {
+        let mut socket = UnixStream::connect(self.sockets.join(".socket.sock"))?;
+        socket.write_all(request.as_bytes())?;
+
+        let mut response = String::new();
+        socket.read_to_string(&mut response)?;
+
+        Ok(response)
+    }
+
+    fn request_json(&self, request: &str) -> io::Result<Value> {
+        serde_json::from_str(&self.request(request)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn fetch(&self) -> io::Result<WorkspaceModel> {
+        let workspaces = self.request_json("j/workspaces")?;
+        let monitors = self.request_json("j/monitors")?;
+
+        // The workspace shown on each monitor.
+        let active = monitors
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|monitor| monitor["activeWorkspace"]["id"].as_i64())
+            .collect::<Vec<_>>();
+
+        let mut workspaces = workspaces
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|workspace| {
+                let id = workspace["id"].as_i64()?;
+
+                Some((
+                    id,
+                    Workspace {
+                        id: id.to_string(),
+                        name: workspace["name"].as_str().unwrap_or_default().to_owned(),
+                        active: active.contains(&id),
+                        outputs: workspace["monitor"]
+                            .as_str()
+                            .map(str::to_owned)
+                            .into_iter()
+                            .collect(),
+                    },
+                ))
+            })
+            // Special workspaces (scratchpads) have negative ids.
+            .filter(|(id, _)| *id > 0)
+            .collect::<Vec<_>>();
+
+        workspaces.sort_by_key(|(id, _)| *id);
+
+        Ok(WorkspaceModel {
+            workspaces: workspaces
+                .into_iter()
+                .map(|(_, workspace)| workspace)
+                .collect(),
+        })
+    }
+}
+
+impl WorkspaceProvider for HyprlandWorkspaces {
+    fn name(&self) -> &'static str {
+        "Hyprland IPC"
+    }
+
+    fn activate(&self, id: &str) -> Result<(), WorkspaceError> {
+        if id.parse::<i64>().is_err() {
+            return Err(WorkspaceError::UnknownWorkspace(id.to_owned()));
+        }
+
+        let response = self.request(&format!("dispatch workspace {id}"))?;
+        if response.trim() != "ok" {
+            return Err(WorkspaceError::CannotActivate(id.to_owned()));
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// Where this Hyprland instance keeps its sockets, if this is Hyprland.
+///
+fn socket_dir() -> Option<PathBuf> {
+    let instance = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+
+    // Moved to the runtime directory in Hyprland 0.40.
+    let runtime = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|runtime| PathBuf::from(runtime).join("hypr").join(&instance))
+        .filter(|dir| dir.exists());
+
+    runtime.or_else(|| Some(PathBuf::from("/tmp/hypr").join(&instance)))
+}
@@ -0,0 +1,285 @@
+//!
+//! The compositor's workspaces, for workspace indicators and switchers.
+//!
+//! Whichever of these the desktop offers is used, in order of preference:
+//!
+//! * The `ext_workspace_v1` protocol.
+//! * Hyprland's IPC sockets, once the client has an event loop
+//!   (see [AvyClient::set_loop_handle]).
+//!
+//! With neither, the [WorkspaceModel] just stays empty.
+//!
+
+pub mod hyprland;
+
+use std::collections::HashSet;
+
+use smithay_client_toolkit::reexports::client::{Connection, Proxy, QueueHandle};
+use thiserror::Error;
+use wayland_backend::client::ObjectId;
+
+use crate::{
+    wayland::protocol::ext_workspace::{
+        client::ext_workspace_handle_v1::{State, WorkspaceCapabilities},
+        ExtWorkspace, ExtWorkspaceState, WorkspaceHandler,
+    },
+    AvyClient,
+};
+
+use hyprland::HyprlandWorkspaces;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    /// Identifies the workspace for [AvyClient::activate_workspace].
+    pub id: String,
+    pub name: String,
+    pub active: bool,
+    /// Names of the outputs the workspace can be shown on.
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WorkspaceModel {
+    pub workspaces: Vec<Workspace>,
+}
+
+impl WorkspaceModel {
+    pub fn get(&self, id: &str) -> Option<&Workspace> {
+        self.workspaces.iter().find(|workspace| workspace.id == id)
+    }
+
+    ///
+    /// Active workspaces: usually one per output.
+    ///
+    pub fn active(&self) -> impl Iterator<Item = &Workspace> {
+        self.workspaces.iter().filter(|workspace| workspace.active)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workspaces.is_empty()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WorkspaceError {
+    #[error("The compositor doesn't share its workspaces.")]
+    NoProvider,
+
+    #[error("There's no workspace with the id {0:?}.")]
+    UnknownWorkspace(String),
+
+    #[error("The compositor won't switch to the workspace {0:?}.")]
+    CannotActivate(String),
+
+    #[error("Could not talk to the compositor: {0}")]
+    Ipc(#[from] std::io::Error),
+}
+
+///
+/// Something which knows about the compositor's workspaces.
+///
+/// Providers push new [WorkspaceModel]s to the [AvyClient] as they change.
+///
+pub trait WorkspaceProvider {
+    ///
+    /// Human-readable name of this provider, for debugging.
+    ///
+    fn name(&self) -> &'static str;
+
+    fn activate(&self, id: &str) -> Result<(), WorkspaceError>;
+}
+
+type WorkspacesCallback = Box<dyn FnMut(&WorkspaceModel)>;
+
+///
+/// The client's view of the compositor's workspaces.
+///
+pub struct Workspaces {
+    model: WorkspaceModel,
+    ext: Option<ExtWorkspaceState>,
+    hyprland: Option<HyprlandWorkspaces>,
+    callbacks: Vec<WorkspacesCallback>,
+    /// Surfaces marked dirty whenever the model changes.
+    watchers: HashSet<ObjectId>,
+}
+
+impl Workspaces {
+    pub fn new(ext: Option<ExtWorkspaceState>) -> Self {
+        Self {
+            model: WorkspaceModel::default(),
+            ext,
+            hyprland: None,
+            callbacks: Vec::new(),
+            watchers: HashSet::new(),
+        }
+    }
+
+    pub fn provider(&self) -> Option<&dyn WorkspaceProvider> {
+        if let Some(ext) = &self.ext {
+            return Some(ext);
+        }
+
+        self.hyprland
+            .as_ref()
+            .map(|hyprland| hyprland as &dyn WorkspaceProvider)
+    }
+}
+
+impl WorkspaceProvider for ExtWorkspaceState {
+    fn name(&self) -> &'static str {
+        "ext_workspace_v1"
+    }
+
+    fn activate(&self, id: &str) -> Result<(), WorkspaceError> {
+        let workspace = self
+            .workspaces()
+            .iter()
+            .find(|workspace| model_id(workspace) == id)
+            .ok_or_else(|| WorkspaceError::UnknownWorkspace(id.to_owned()))?;
+
+        if !workspace
+            .capabilities
+            .contains(WorkspaceCapabilities::Activate)
+        {
+            return Err(WorkspaceError::CannotActivate(id.to_owned()));
+        }
+
+        ExtWorkspaceState::activate(self, &workspace.handle);
+        Ok(())
+    }
+}
+
+///
+/// The id a workspace goes by in the [WorkspaceModel]: the compositor's,
+/// or failing that, one which lasts as long as the protocol object.
+///
+fn model_id(workspace: &ExtWorkspace) -> String {
+    workspace
+        .id
+        .clone()
+        .unwrap_or_else(|| workspace.handle.id().to_string())
+}
+
+impl AvyClient {
+    pub fn workspaces(&self) -> &WorkspaceModel {
+        &self.workspaces.model
+    }
+
+    ///
+    /// Name of whatever provides the workspaces, if anything does.
+    ///
+    pub fn workspace_provider(&self) -> Option<&'static str> {
+        self.workspaces.provider().map(WorkspaceProvider::name)
+    }
+
+    ///
+    /// Call `callback` (on the event loop) whenever the workspaces change.
+    ///
+    pub fn on_workspaces_changed(&mut self, callback: impl FnMut(&WorkspaceModel) + 'static) {
+        self.workspaces.callbacks.push(Box::new(callback));
+    }
+
+    ///
+    /// Mark `surface` dirty whenever the workspaces change,
+    /// e.g. for a workspace indicator.
+    ///
+    pub fn watch_workspaces(&mut self, surface: &ObjectId) {
+        self.workspaces.watchers.insert(surface.clone());
+    }
+
+    pub fn unwatch_workspaces(&mut self, surface: &ObjectId) {
+        self.workspaces.watchers.remove(surface);
+    }
+
+    ///
+    /// Ask the compositor to switch to the workspace `id` (see [Workspace::id]).
+    ///
+    pub fn activate_workspace(&self, id: &str) -> Result<(), WorkspaceError> {
+        self.workspaces
+            .provider()
+            .ok_or(WorkspaceError::NoProvider)?
+            .activate(id)
+    }
+
+    ///
+    /// Fall back to Hyprland's IPC, now that there's an
+    /// event loop, if the compositor has no workspace protocol.
+    ///
+    pub(crate) fn start_workspace_fallback(&mut self) {
+        if self.workspaces.provider().is_some() {
+            return;
+        }
+
+        let Some(handle) = self.loop_handle.clone() else {
+            return;
+        };
+
+        match HyprlandWorkspaces::connect(&handle) {
+            Ok(Some((hyprland, model))) => {
+                self.workspaces.hyprland = Some(hyprland);
+                self.set_workspace_model(model);
+            }
+            Ok(None) => {}
+            Err(err) => log::warn!("Could not follow Hyprland's workspaces: {err}"),
+        }
+    }
+
+    pub(crate) fn set_workspace_model(&mut self, model: WorkspaceModel) {
+        if self.workspaces.model == model {
+            return;
+        }
+
+        self.workspaces.model = model;
+
+        for id in &self.workspaces.watchers {
+            if let Some(state) = self.surface_shared.get(id) {
+                state.dirty.mark();
+            }
+        }
+
+        let model = &self.workspaces.model;
+        for callback in &mut self.workspaces.callbacks {
+            callback(model);
+        }
+    }
+}
+
+impl WorkspaceHandler for AvyClient {
+    fn ext_workspace_state(&mut self) -> Option<&mut ExtWorkspaceState> {
+        self.workspaces.ext.as_mut()
+    }
+
+    fn workspaces_changed(&mut self, _: &Connection, _: &QueueHandle<Self>) {
+        let Some(ext) = &self.workspaces.ext else {
+            return;
+        };
+
+        let workspaces = ext
+            .workspaces()
+            .iter()
+            .filter(|workspace| !workspace.state.contains(State::Hidden))
+            .map(|workspace| Workspace {
+                id: model_id(workspace),
+                name: workspace.name.clone(),
+                active: workspace.state.contains(State::Active),
+                outputs: ext
+                    .groups()
+                    .iter()
+                    .filter(|group| group.workspaces.contains(&workspace.handle))
+                    .flat_map(|group| &group.outputs)
+                    .filter_map(|output| self.output_state.info(output)?.name)
+                    .collect(),
+            })
+            .collect();
+
+        self.set_workspace_model(WorkspaceModel { workspaces });
+    }
+
+    fn workspaces_finished(&mut self, _: &Connection, _: &QueueHandle<Self>) {
+        self.workspaces.ext = None;
+        self.set_workspace_model(WorkspaceModel::default());
+
+        // Hyprland's IPC might still know about them.
+        self.start_workspace_fallback();
+    }
+}
@@ -1,11 +1,35 @@
-#![feature(slice_as_chunks)]
+// Builds on stable: anything needing nightly goes behind the `nightly` feature.
+#![cfg_attr(not(feature = "nightly"), forbid(unstable_features))]
 
 pub mod app;
+pub mod avy;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod cursor;
+pub mod debug;
 pub mod util;
 pub mod wayland;
 pub mod graphics;
+pub mod idle;
+pub mod integrations;
+pub mod locale;
+pub mod memory;
+pub mod metrics;
+pub mod proxy;
+#[cfg(feature = "reconnect")]
+pub mod reconnect;
+pub mod run;
+pub mod selection;
+#[cfg(feature = "portal")]
+pub mod settings;
+pub mod shortcuts;
+pub mod shutdown;
+pub mod theme;
+pub mod timer;
+pub mod widgets;
 
 pub use app::AvyClient;
+pub use avy::Avy;
 use vulkano::Version;
 
 pub const ENGINE_NAME: &str = "Avy (Skia)";
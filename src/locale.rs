@@ -0,0 +1,322 @@
+//!
+//! The user's locale, and the text direction that comes with it, so that
+//! layouts can mirror themselves for right-to-left languages.
+//!
+
+use std::{fmt, str::FromStr};
+
+use smithay_client_toolkit::reexports::protocols::xdg::shell::client::xdg_positioner::{
+    Anchor, Gravity,
+};
+use thiserror::Error;
+
+///
+/// Scripts written right to left, as ISO 15924 codes.
+///
+const RTL_SCRIPTS: &[&str] = &[
+    "Adlm", "Arab", "Hebr", "Mand", "Nkoo", "Rohg", "Samr", "Syrc", "Thaa",
+];
+
+///
+/// Languages written right to left unless another script is given.
+///
+const RTL_LANGUAGES: &[&str] = &[
+    "ar", "arc", "ckb", "dv", "fa", "he", "iw", "ps", "sd", "ug", "ur", "yi",
+];
+
+#[derive(Debug, Error)]
+#[error("Not a valid locale: {0:?}")]
+pub struct ParseLocaleError(String);
+
+///
+/// A language, with an optional script and region, e.g. `ar-EG` or `sr-Latn`.
+///
+/// Parsed from BCP 47 tags as well as POSIX locales (`ar_EG.UTF-8`).
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LanguageIdentifier {
+    language: String,
+    script: Option<String>,
+    region: Option<String>,
+}
+
+impl LanguageIdentifier {
+    ///
+    /// The undetermined language (`und`), written left to right.
+    ///
+    pub fn und() -> Self {
+        Self {
+            language: "und".to_string(),
+            script: None,
+            region: None,
+        }
+    }
+
+    ///
+    /// The locale messages are shown in, from `LC_ALL`, `LC_MESSAGES`
+    /// or `LANG` (the first one set), or [LanguageIdentifier::und].
+    ///
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(Self::und)
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    pub fn script(&self) -> Option<&str> {
+        self.script.as_deref()
+    }
+
+    pub fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    ///
+    /// The direction text in this locale is written in: from the
+    /// script if there's one, otherwise from the language.
+    ///
+    pub fn direction(&self) -> TextDirection {
+        let rtl = match &self.script {
+            Some(script) => RTL_SCRIPTS.contains(&script.as_str()),
+            None => RTL_LANGUAGES.contains(&self.language.as_str()),
+        };
+
+        if rtl {
+            TextDirection::Rtl
+        } else {
+            TextDirection::Ltr
+        }
+    }
+}
+
+impl Default for LanguageIdentifier {
+    fn default() -> Self {
+        Self::und()
+    }
+}
+
+impl FromStr for LanguageIdentifier {
+    type Err = ParseLocaleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseLocaleError(s.to_string());
+
+        // Drop POSIX's codeset and modifier (`.UTF-8`, `@euro`).
+        let tag = s.split(['.', '@']).next().unwrap_or_default();
+
+        if tag == "C" || tag == "POSIX" {
+            return Ok(Self::und());
+        }
+
+        let mut subtags = tag.split(['-', '_']);
+
+        let language = subtags
+            .next()
+            .filter(|language| {
+                (2..=3).contains(&language.len())
+                    && language.chars().all(|c| c.is_ascii_alphabetic())
+            })
+            .ok_or_else(invalid)?
+            .to_ascii_lowercase();
+
+        let mut script = None;
+        let mut region = None;
+
+        for subtag in subtags {
+            let alphabetic = subtag.chars().all(|c| c.is_ascii_alphabetic());
+            let numeric = subtag.chars().all(|c| c.is_ascii_digit());
+
+            match subtag.len() {
+                4 if alphabetic && script.is_none() && region.is_none() => {
+                    let (first, rest) = subtag.split_at(1);
+                    script = Some(first.to_ascii_uppercase() + &rest.to_ascii_lowercase());
+                }
+                2 if alphabetic && region.is_none() => {
+                    region = Some(subtag.to_ascii_uppercase());
+                }
+                3 if numeric && region.is_none() => {
+                    region = Some(subtag.to_string());
+                }
+                // Variants and extensions (and whatever follows
+                // them) don't matter here.
+                _ => break,
+            }
+        }
+
+        Ok(Self {
+            language,
+            script,
+            region,
+        })
+    }
+}
+
+impl fmt::Display for LanguageIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.language)?;
+
+        if let Some(script) = &self.script {
+            write!(f, "-{script}")?;
+        }
+
+        if let Some(region) = &self.region {
+            write!(f, "-{region}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TextDirection {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl TextDirection {
+    pub fn is_rtl(&self) -> bool {
+        matches!(self, Self::Rtl)
+    }
+
+    pub fn to_skia(self) -> skia_safe::textlayout::TextDirection {
+        match self {
+            Self::Ltr => skia_safe::textlayout::TextDirection::LTR,
+            Self::Rtl => skia_safe::textlayout::TextDirection::RTL,
+        }
+    }
+
+    ///
+    /// Where popups open by default: below, and towards the end of the line.
+    ///
+    pub fn popup_gravity(&self) -> Gravity {
+        self.mirror_gravity(Gravity::BottomRight)
+    }
+
+    ///
+    /// `gravity` (given for left-to-right) flipped horizontally for right-to-left.
+    ///
+    pub fn mirror_gravity(&self, gravity: Gravity) -> Gravity {
+        if !self.is_rtl() {
+            return gravity;
+        }
+
+        match gravity {
+            Gravity::Left => Gravity::Right,
+            Gravity::Right => Gravity::Left,
+            Gravity::TopLeft => Gravity::TopRight,
+            Gravity::TopRight => Gravity::TopLeft,
+            Gravity::BottomLeft => Gravity::BottomRight,
+            Gravity::BottomRight => Gravity::BottomLeft,
+            other => other,
+        }
+    }
+
+    ///
+    /// `anchor` (given for left-to-right) flipped horizontally for right-to-left.
+    ///
+    pub fn mirror_anchor(&self, anchor: Anchor) -> Anchor {
+        if !self.is_rtl() {
+            return anchor;
+        }
+
+        match anchor {
+            Anchor::Left => Anchor::Right,
+            Anchor::Right => Anchor::Left,
+            Anchor::TopLeft => Anchor::TopRight,
+            Anchor::TopRight => Anchor::TopLeft,
+            Anchor::BottomLeft => Anchor::BottomRight,
+            Anchor::BottomRight => Anchor::BottomLeft,
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(locale: &str) -> LanguageIdentifier {
+        locale.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_bcp47_tags() {
+        let locale = parse("sr-latn-rs");
+
+        assert_eq!(locale.language(), "sr");
+        assert_eq!(locale.script(), Some("Latn"));
+        assert_eq!(locale.region(), Some("RS"));
+        assert_eq!(parse("es-419").region(), Some("419"));
+    }
+
+    #[test]
+    fn parses_posix_locales() {
+        let locale = parse("ar_EG.UTF-8");
+
+        assert_eq!(locale.language(), "ar");
+        assert_eq!(locale.script(), None);
+        assert_eq!(locale.region(), Some("EG"));
+        assert_eq!(parse("de_DE@euro").to_string(), "de-DE");
+        assert_eq!(parse("C.UTF-8"), LanguageIdentifier::und());
+        assert_eq!(parse("POSIX"), LanguageIdentifier::und());
+    }
+
+    #[test]
+    fn ignores_variants_and_extensions() {
+        assert_eq!(parse("de-DE-1996").to_string(), "de-DE");
+        assert_eq!(parse("en-US-u-ca-gregory").to_string(), "en-US");
+    }
+
+    #[test]
+    fn rejects_what_isnt_a_language() {
+        for locale in ["", "e", "english", "12-US", "-US"] {
+            assert!(locale.parse::<LanguageIdentifier>().is_err(), "{locale:?}");
+        }
+    }
+
+    #[test]
+    fn displays_as_a_bcp47_tag() {
+        for tag in ["en", "en-GB", "zh-Hant-TW", "und"] {
+            assert_eq!(parse(tag).to_string(), tag);
+        }
+    }
+
+    #[test]
+    fn direction_comes_from_the_script_then_the_language() {
+        assert_eq!(parse("en-US").direction(), TextDirection::Ltr);
+        assert_eq!(parse("ar-EG").direction(), TextDirection::Rtl);
+        assert_eq!(parse("he_IL.UTF-8").direction(), TextDirection::Rtl);
+        assert_eq!(LanguageIdentifier::und().direction(), TextDirection::Ltr);
+
+        // Azerbaijani is written in Latin letters, unless it's in Arabic ones.
+        assert_eq!(parse("az-Latn").direction(), TextDirection::Ltr);
+        assert_eq!(parse("az-Arab").direction(), TextDirection::Rtl);
+        // Uyghur, usually in Arabic letters, also in Cyrillic ones.
+        assert_eq!(parse("ug").direction(), TextDirection::Rtl);
+        assert_eq!(parse("ug-Cyrl").direction(), TextDirection::Ltr);
+    }
+
+    #[test]
+    fn right_to_left_mirrors_positioners() {
+        let (ltr, rtl) = (TextDirection::Ltr, TextDirection::Rtl);
+
+        assert_eq!(ltr.popup_gravity(), Gravity::BottomRight);
+        assert_eq!(rtl.popup_gravity(), Gravity::BottomLeft);
+
+        assert_eq!(ltr.mirror_gravity(Gravity::TopLeft), Gravity::TopLeft);
+        assert_eq!(rtl.mirror_gravity(Gravity::TopLeft), Gravity::TopRight);
+        assert_eq!(rtl.mirror_gravity(Gravity::Bottom), Gravity::Bottom);
+
+        assert_eq!(ltr.mirror_anchor(Anchor::Right), Anchor::Right);
+        assert_eq!(rtl.mirror_anchor(Anchor::Right), Anchor::Left);
+        assert_eq!(rtl.mirror_anchor(Anchor::BottomLeft), Anchor::BottomRight);
+        assert_eq!(rtl.mirror_anchor(Anchor::Top), Anchor::Top);
+    }
+}
@@ -0,0 +1,332 @@
+//!
+//! Giving cached GPU memory back, once a surface has stopped rendering
+//! for a while or when asked to (e.g. under system memory pressure).
+//!
+//! Skia keeps textures (of icons, glyphs, ...) cached for as long as they
+//! fit, so after a burst of loading, surfaces which then sit still hold on
+//! to memory they may never need again.
+//!
+
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    app::SurfaceShared,
+    debug::IncidentKind,
+    graphics::{GraphicsSurface, TrimLevel},
+    timer::TimerAction,
+    AvyClient,
+};
+
+///
+/// How long a surface must go without presenting a frame before its caches are trimmed.
+///
+pub const DEFAULT_IDLE_TRIM: Duration = Duration::from_secs(30);
+
+///
+/// How an idle surface is trimmed.
+///
+pub const IDLE_TRIM_LEVEL: TrimLevel = TrimLevel::Moderate;
+
+///
+/// Least time between checks of surfaces for having gone idle.
+///
+const IDLE_TRIM_CHECK: Duration = Duration::from_secs(5);
+
+impl AvyClient {
+    pub(crate) fn start_idle_trim(&self) {
+        let timer = self.add_timer(IDLE_TRIM_CHECK, |app| {
+            let next = app.trim_idle_surfaces();
+            TimerAction::Repeat(next.max(IDLE_TRIM_CHECK))
+        });
+
+        if let Err(err) = timer {
+            log::warn!("Cannot trim idle surfaces: {err}");
+        }
+    }
+
+    ///
+    /// Trim every surface which has gone idle since it last presented,
+    /// once per idle period, returning how long until the next one might.
+    ///
+    /// Surfaces presenting from now on can't go idle any sooner than their
+    /// idle period, so that's the longest the client sleeps in between.
+    ///
+    fn trim_idle_surfaces(&mut self) -> Duration {
+        let now = self.clock.now();
+        let mut next = DEFAULT_IDLE_TRIM;
+
+        for (id, state) in &self.surface_shared {
+            let Some(after) = *state.idle_trim_after.lock().unwrap() else {
+                continue;
+            };
+            next = next.min(after);
+
+            let presented = state.last_presented.lock().unwrap().map(|(_, at)| at);
+            let trimmed = state.idle_trimmed.load(Ordering::Acquire);
+            match idle_check(after, presented, trimmed, now) {
+                IdleCheck::Due => {}
+                IdleCheck::In(remaining) => {
+                    next = next.min(remaining);
+                    continue;
+                }
+                IdleCheck::Waiting => continue,
+            }
+
+            let Some(backend) = self.surface_backends.get(id) else {
+                continue;
+            };
+
+            // A surface busy rendering isn't idle, and the event loop mustn't wait on it.
+            let Ok(mut backend) = backend.try_lock() else {
+                continue;
+            };
+
+            trim_memory(state, &mut *backend, IDLE_TRIM_LEVEL, "idle");
+            state.idle_trimmed.store(true, Ordering::Release);
+        }
+
+        next
+    }
+}
+
+///
+/// Whether a surface is due an idle trim.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IdleCheck {
+    ///
+    /// Idle for long enough: trim it now.
+    ///
+    Due,
+
+    ///
+    /// Not idle for long enough yet: it might be after this long.
+    ///
+    In(Duration),
+
+    ///
+    /// Never presented, or already trimmed since it last did:
+    /// nothing to do until it presents again.
+    ///
+    Waiting,
+}
+
+///
+/// Check a surface trimmed after `after` idle, which last presented
+/// at `presented` and may since have been `trimmed`, as of `now`.
+///
+fn idle_check(
+    after: Duration,
+    presented: Option<Instant>,
+    trimmed: bool,
+    now: Instant,
+) -> IdleCheck {
+    let Some(presented) = presented else {
+        return IdleCheck::Waiting;
+    };
+
+    if trimmed {
+        return IdleCheck::Waiting;
+    }
+
+    let idle = now.saturating_duration_since(presented);
+    if idle < after {
+        IdleCheck::In(after - idle)
+    } else {
+        IdleCheck::Due
+    }
+}
+
+///
+/// Trim `backend`'s caches, recording how much that freed as an incident.
+///
+pub(crate) fn trim_memory(
+    state: &SurfaceShared,
+    backend: &mut dyn GraphicsSurface,
+    level: TrimLevel,
+    reason: &str,
+) {
+    let before = backend.cached_bytes();
+    if level >= TrimLevel::Moderate {
+        state.blurs.clear();
+    }
+    backend.trim_memory(level);
+    let after = backend.cached_bytes();
+
+    let frame = state.current_frame();
+    match before.zip(after) {
+        Some((before, after)) => state.record_incident(
+            IncidentKind::Trimmed,
+            frame,
+            format_args!(
+                "{reason}, {level:?}: {:.1} MiB -> {:.1} MiB",
+                mebibytes(before),
+                mebibytes(after)
+            ),
+        ),
+        None => state.record_incident(
+            IncidentKind::Trimmed,
+            frame,
+            format_args!("{reason}, {level:?}"),
+        ),
+    }
+}
+
+fn mebibytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        timer::TimerQueue,
+        util::{SharedClock, TestClock},
+    };
+
+    use super::*;
+
+    ///
+    /// One surface and the idle trim timer, on an event loop which sleeps
+    /// until the timer's next deadline and only then wakes up.
+    ///
+    struct Idle {
+        time: TestClock,
+        clock: SharedClock,
+        timers: TimerQueue<()>,
+        after: Duration,
+        presented: Option<Instant>,
+        trimmed: bool,
+        wakeups: Vec<Duration>,
+        trims: Vec<Duration>,
+    }
+
+    impl Idle {
+        fn new(after: Duration) -> Self {
+            let time = TestClock::new();
+            let clock = SharedClock::new(time.clone());
+            let mut timers = TimerQueue::new();
+            timers.insert(clock.now() + IDLE_TRIM_CHECK, ());
+
+            Self {
+                time,
+                clock,
+                timers,
+                after,
+                presented: None,
+                trimmed: false,
+                wakeups: Vec::new(),
+                trims: Vec::new(),
+            }
+        }
+
+        fn present(&mut self) {
+            self.presented = Some(self.clock.now());
+            self.trimmed = false;
+        }
+
+        /// Sleep until `until` since the start, waking up for each timer on the way.
+        fn sleep_until(&mut self, until: Duration) {
+            loop {
+                let deadline = self.timers.next_deadline().unwrap();
+                let now = self.clock.now();
+                if deadline - now > until - self.time.elapsed() {
+                    self.time.advance(until - self.time.elapsed());
+                    return;
+                }
+
+                self.time.advance(deadline - now);
+                self.wakeups.push(self.time.elapsed());
+
+                for token in self.timers.due(self.clock.now()) {
+                    let callback = self.timers.start(token).unwrap();
+                    let next = self.check().max(IDLE_TRIM_CHECK);
+                    self.timers.finish(callback, Some(self.clock.now() + next));
+                }
+            }
+        }
+
+        /// As [AvyClient::trim_idle_surfaces], for the one surface.
+        fn check(&mut self) -> Duration {
+            let mut next = DEFAULT_IDLE_TRIM.min(self.after);
+
+            match idle_check(self.after, self.presented, self.trimmed, self.clock.now()) {
+                IdleCheck::Due => {
+                    self.trims.push(self.time.elapsed());
+                    self.trimmed = true;
+                }
+                IdleCheck::In(remaining) => next = next.min(remaining),
+                IdleCheck::Waiting => {}
+            }
+
+            next
+        }
+    }
+
+    fn secs(secs: &[u64]) -> Vec<Duration> {
+        secs.iter().copied().map(Duration::from_secs).collect()
+    }
+
+    #[test]
+    fn idle_surfaces_are_trimmed_once_when_their_period_is_up() {
+        let mut idle = Idle::new(DEFAULT_IDLE_TRIM);
+        idle.present();
+        idle.sleep_until(Duration::from_secs(115));
+
+        assert_eq!(idle.trims, secs(&[30]));
+        assert_eq!(idle.wakeups, secs(&[5, 30, 60, 90]));
+    }
+
+    #[test]
+    fn presenting_again_puts_the_wakeup_off() {
+        let mut idle = Idle::new(DEFAULT_IDLE_TRIM);
+        idle.present();
+        idle.sleep_until(Duration::from_secs(20));
+        idle.present();
+        idle.sleep_until(Duration::from_secs(55));
+
+        assert_eq!(idle.trims, secs(&[50]));
+        assert_eq!(idle.wakeups, secs(&[5, 30, 50]));
+    }
+
+    #[test]
+    fn surfaces_which_never_present_only_wake_the_client_each_period() {
+        let mut idle = Idle::new(DEFAULT_IDLE_TRIM);
+        idle.sleep_until(Duration::from_secs(10 * 60));
+
+        assert!(idle.trims.is_empty());
+        assert_eq!(idle.wakeups.len(), 20);
+        for pair in idle.wakeups[1..].windows(2) {
+            assert_eq!(pair[1] - pair[0], DEFAULT_IDLE_TRIM);
+        }
+    }
+
+    #[test]
+    fn short_periods_wake_the_client_no_more_than_each_check() {
+        let mut idle = Idle::new(Duration::from_secs(2));
+        idle.present();
+        idle.sleep_until(Duration::from_secs(60));
+
+        assert_eq!(idle.trims, secs(&[5]));
+        for pair in idle.wakeups.windows(2) {
+            assert_eq!(pair[1] - pair[0], IDLE_TRIM_CHECK);
+        }
+    }
+
+    #[test]
+    fn trimmed_surfaces_wait_for_their_next_present() {
+        let after = Duration::from_secs(30);
+        let presented = Some(Instant::now());
+        let now = presented.unwrap() + after;
+
+        assert_eq!(idle_check(after, None, false, now), IdleCheck::Waiting);
+        assert_eq!(idle_check(after, presented, true, now), IdleCheck::Waiting);
+        assert_eq!(idle_check(after, presented, false, now), IdleCheck::Due);
+        assert_eq!(
+            idle_check(after, presented, false, now - Duration::from_secs(1)),
+            IdleCheck::In(Duration::from_secs(1))
+        );
+    }
+}
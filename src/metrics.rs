@@ -0,0 +1,777 @@
+//!
+//! Machine-readable performance data from real sessions: frame time and
+//! latency histograms, and counts of the things which make frames slow or
+//! lose them, exported as JSON or in Prometheus' text format.
+//!
+//! Histograms have fixed, logarithmically spaced buckets (like
+//! HdrHistogram's), so recording a frame never allocates.
+//!
+
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::reexports::calloop::{
+    signals::{Signal, Signals},
+    LoopHandle,
+};
+
+use crate::{
+    graphics::{FrameTimings, GpuTimings},
+    util::SharedClock,
+    AvyClient,
+};
+
+///
+/// Set to `1` to write every [MetricsRecorder] with a [MetricsConfig::dump_path]
+/// out whenever the process receives `SIGUSR2`.
+///
+pub const METRICS_ON_SIGUSR2_ENV: &str = "AVY_METRICS_ON_SIGUSR2";
+
+///
+/// Values below this (in microseconds) each get a bucket of their own;
+/// above, every power of two is split into this many buckets.
+///
+const SUB_BUCKETS: u64 = 32;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+///
+/// Largest value told apart, in microseconds (about 18 minutes).
+/// Larger ones are counted as this.
+///
+const MAX_VALUE_BITS: u32 = 30;
+
+const BUCKETS: usize = (SUB_BUCKETS * (MAX_VALUE_BITS - SUB_BUCKET_BITS + 1) as u64) as usize;
+
+///
+/// Durations, counted into fixed buckets with a precision of about 3%.
+///
+#[derive(Clone)]
+pub struct Histogram {
+    buckets: Box<[u64; BUCKETS]>,
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: Box::new([0; BUCKETS]),
+            count: 0,
+            sum: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl std::fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.count)
+            .field("p50", &self.percentile(50.0))
+            .field("p99", &self.percentile(99.0))
+            .field("max", &self.max)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn bucket(micros: u64) -> usize {
+        let micros = micros.min((1 << MAX_VALUE_BITS) - 1);
+        if micros < SUB_BUCKETS {
+            return micros as usize;
+        }
+
+        let msb = 63 - micros.leading_zeros();
+        let shift = msb - SUB_BUCKET_BITS;
+        let sub = (micros >> shift) - SUB_BUCKETS;
+
+        (SUB_BUCKETS * (shift as u64 + 1) + sub) as usize
+    }
+
+    ///
+    /// The largest value (in microseconds) counted into `bucket`.
+    ///
+    fn bucket_max(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < SUB_BUCKETS {
+            return bucket;
+        }
+
+        let shift = bucket / SUB_BUCKETS - 1;
+        let sub = bucket % SUB_BUCKETS;
+
+        ((SUB_BUCKETS + sub + 1) << shift) - 1
+    }
+
+    pub fn record(&mut self, value: Duration) {
+        let micros = value.as_micros().min(u64::MAX as u128) as u64;
+
+        self.buckets[Self::bucket(micros)] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_secs_f64(self.sum.as_secs_f64() / count as f64),
+        }
+    }
+
+    ///
+    /// The value `percentile`% of those recorded are at or below
+    /// (to the bucket's precision), e.g. `99.0` for p99.
+    ///
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0) * self.count as f64).ceil() as u64;
+        let rank = rank.max(1);
+
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let value = Duration::from_micros(Self::bucket_max(bucket));
+                return value.min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MetricsConfig {
+    /// Identifies the recorder's data in exports (as the `surface` label).
+    pub name: Option<String>,
+    /// Where to write the JSON export when `SIGUSR2` is received, see [METRICS_ON_SIGUSR2_ENV].
+    pub dump_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    Json,
+    /// Prometheus' text exposition format.
+    Prometheus,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// From beginning a frame to presenting it.
+    pub frame_time: Histogram,
+    /// From beginning a frame to having an image to draw into.
+    pub acquire_latency: Histogram,
+    /// From finishing drawing to presenting.
+    pub present_latency: Histogram,
+    /// The GPU's time drawing a frame, see [crate::graphics::vulkan::Vulkan::with_gpu_timing].
+    pub gpu_render: Histogram,
+    /// The GPU's time on a frame, up to it being ready to present.
+    pub gpu_total: Histogram,
+    /// Whether the GPU times are being measured: `false` if not asked
+    /// for, or if the device can't.
+    pub gpu_timing_available: bool,
+    /// From a pointer button press to the frame drawn in response being on
+    /// screen, if measured, see [crate::app::AvySurfaceHandle::set_measure_input_latency].
+    pub input_to_photon: Histogram,
+
+    pub frames: u64,
+    pub swapchain_recreations: u64,
+    pub suboptimal_frames: u64,
+    pub callback_panics: u64,
+    /// Frames drawn without waiting for a frame callback which didn't come,
+    /// see [crate::app::FallbackCadence].
+    pub fallback_frames: u64,
+    /// Fraction of its scale the surface renders at, whilst adaptive quality
+    /// is on, see [crate::app::AvySurfaceHandle::set_adaptive_quality].
+    pub quality: Option<f64>,
+}
+
+///
+/// How often the event loop woke up, and how long input waited
+/// for it, see [AvyClient::loop_metrics].
+///
+#[derive(Debug, Clone, Copy)]
+pub struct LoopMetrics {
+    pub wakeups: u64,
+    /// Wakeups after which no surface had presented a frame since the last one.
+    pub idle_wakeups: u64,
+    ///
+    /// Longest an input event (pointer, key or touch) waited to be handled,
+    /// e.g. behind a long render on the event loop's thread.
+    ///
+    /// To the millisecond, with the `input-latency` feature, against the system's
+    /// monotonic clock, which compositors timestamp input with as a rule. Otherwise
+    /// (or for a compositor which doesn't) it's only measured against the quickest
+    /// event handled so far, so that it's a lower bound: a delay every event
+    /// waited for doesn't count.
+    ///
+    pub max_input_dispatch_delay: Duration,
+    pub since: Instant,
+    /// Frames presented across all surfaces as of the last wakeup.
+    presented: u64,
+    /// What input timestamps are measured from.
+    epoch: Instant,
+    /// Least difference between `epoch` and an input timestamp so
+    /// far, in milliseconds: that of an event handled right away.
+    input_clock_offset: Option<u32>,
+}
+
+impl LoopMetrics {
+    ///
+    /// Metrics starting over at `now`, on the client's clock.
+    ///
+    pub(crate) fn new(now: Instant) -> Self {
+        Self {
+            wakeups: 0,
+            idle_wakeups: 0,
+            max_input_dispatch_delay: Duration::ZERO,
+            since: now,
+            presented: 0,
+            epoch: now,
+            input_clock_offset: None,
+        }
+    }
+
+    ///
+    /// Idle wakeups per second, from [LoopMetrics::since] until `now`
+    /// (read from the client's [AvyClient::clock]).
+    ///
+    pub fn idle_wakeups_per_second(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.since);
+        self.idle_wakeups as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    ///
+    /// Count a wakeup, after which `presented` frames had been presented in all.
+    ///
+    pub(crate) fn record_wakeup(&mut self, presented: u64) {
+        self.wakeups += 1;
+        if presented == self.presented {
+            self.idle_wakeups += 1;
+        }
+
+        self.presented = presented;
+    }
+
+    pub fn max_input_dispatch_delay_ms(&self) -> f64 {
+        self.max_input_dispatch_delay.as_secs_f64() * 1000.0
+    }
+
+    ///
+    /// Count an input event timestamped `time` (in milliseconds, on
+    /// the compositor's clock), being handled `now` (on the client's).
+    ///
+    pub(crate) fn record_input(&mut self, time: u32, now: Instant) {
+        #[cfg(feature = "input-latency")]
+        if let Some(delay) = monotonic_delay(time) {
+            self.record_input_delay(delay);
+            return;
+        }
+
+        let now = now.saturating_duration_since(self.epoch).as_millis() as u32;
+        if let Some(delay) = self.delay_since_quickest(time, now) {
+            self.record_input_delay(delay);
+        }
+    }
+
+    ///
+    /// How much longer than the quickest event so far the one timestamped
+    /// `time` took to be handled, at `now` (since `epoch`), or `None` if
+    /// it's the quickest yet.
+    ///
+    fn delay_since_quickest(&mut self, time: u32, now: u32) -> Option<u32> {
+        let offset = now.wrapping_sub(time);
+
+        // Both clocks wrap around together, so compare offsets the same way.
+        let baseline = *self.input_clock_offset.get_or_insert(offset);
+        let delay = offset.wrapping_sub(baseline) as i32;
+        if delay < 0 {
+            self.input_clock_offset = Some(offset);
+            return None;
+        }
+
+        Some(delay as u32)
+    }
+
+    fn record_input_delay(&mut self, millis: u32) {
+        let delay = Duration::from_millis(millis as u64);
+        self.max_input_dispatch_delay = self.max_input_dispatch_delay.max(delay);
+    }
+}
+
+///
+/// Longest an input event may plausibly have waited. Timestamps
+/// further off than that are on a clock other than the one expected.
+///
+const MAX_INPUT_DELAY_MS: i32 = 10_000;
+
+///
+/// How long ago `time` was, if it's on the system's monotonic clock.
+///
+#[cfg(feature = "input-latency")]
+fn monotonic_delay(time: u32) -> Option<u32> {
+    use rustix::time::{clock_gettime, ClockId};
+
+    // Not the client's clock: this is only right against the one the
+    // compositor stamped `time` with, so it's read straight from the system.
+    let now = clock_gettime(ClockId::Monotonic);
+    // Truncated to 32 bits, like the timestamps.
+    let now = (now.tv_sec as u64 * 1000 + now.tv_nsec as u64 / 1_000_000) as u32;
+    plausible_delay(time, now)
+}
+
+///
+/// How long before `now` `time` was (both in milliseconds, on the same
+/// clock), unless that's too far off to be right, see [MAX_INPUT_DELAY_MS].
+///
+fn plausible_delay(time: u32, now: u32) -> Option<u32> {
+    let delay = now.wrapping_sub(time) as i32;
+    (0..=MAX_INPUT_DELAY_MS)
+        .contains(&delay)
+        .then_some(delay as u32)
+}
+
+struct RecorderInner {
+    config: MetricsConfig,
+    metrics: Metrics,
+    since: Instant,
+    /// What `since` (and so the export's window) is read from.
+    clock: SharedClock,
+}
+
+///
+/// Accumulates a surface's frame metrics, see [crate::app::AvySurfaceHandle::metrics_recorder].
+///
+/// Can be freely cloned and sent to other threads, e.g. to
+/// export from. Recorders not attached to a surface (e.g. in a
+/// benchmark) are fed with [MetricsRecorder::record_frame].
+///
+#[derive(Clone)]
+pub struct MetricsRecorder(Arc<Mutex<RecorderInner>>);
+
+impl MetricsRecorder {
+    pub fn new(config: MetricsConfig) -> Self {
+        let clock = SharedClock::default();
+
+        Self(Arc::new(Mutex::new(RecorderInner {
+            config,
+            metrics: Metrics::default(),
+            since: clock.now(),
+            clock,
+        })))
+    }
+
+    ///
+    /// Time the recording by `clock` (e.g. the client's, see
+    /// [AvyClient::clock]) rather than the system's, starting over now.
+    ///
+    pub fn with_clock(self, clock: SharedClock) -> Self {
+        {
+            let mut inner = self.inner();
+            inner.since = clock.now();
+            inner.clock = clock;
+        }
+
+        self
+    }
+
+    fn inner(&self) -> MutexGuard<'_, RecorderInner> {
+        self.0.lock().unwrap()
+    }
+
+    pub fn config(&self) -> MetricsConfig {
+        self.inner().config.clone()
+    }
+
+    pub fn record_frame(&self, timings: &FrameTimings) {
+        let mut inner = self.inner();
+        let metrics = &mut inner.metrics;
+
+        metrics.frames += 1;
+        metrics.swapchain_recreations += timings.swapchain_recreated as u64;
+        metrics.suboptimal_frames += timings.suboptimal as u64;
+
+        metrics
+            .acquire_latency
+            .record(timings.acquired - timings.begun);
+
+        if let Some(presented) = timings.presented {
+            metrics.frame_time.record(presented - timings.begun);
+
+            let drawn = timings.drawn.unwrap_or(timings.acquired);
+            metrics
+                .present_latency
+                .record(presented.saturating_duration_since(drawn));
+        }
+
+        metrics.gpu_timing_available = timings.gpu.is_available();
+        if let GpuTimings::Measured { render, total } = timings.gpu {
+            metrics.gpu_render.record(render);
+            metrics.gpu_total.record(total);
+        }
+    }
+
+    pub fn record_input_latency(&self, latency: Duration) {
+        self.inner().metrics.input_to_photon.record(latency);
+    }
+
+    pub fn record_panic(&self) {
+        self.inner().metrics.callback_panics += 1;
+    }
+
+    pub fn record_fallback_frame(&self) {
+        self.inner().metrics.fallback_frames += 1;
+    }
+
+    pub fn record_quality(&self, quality: Option<f64>) {
+        self.inner().metrics.quality = quality;
+    }
+
+    ///
+    /// A copy of what's been recorded so far.
+    ///
+    pub fn snapshot(&self) -> Metrics {
+        self.inner().metrics.clone()
+    }
+
+    ///
+    /// Start over, e.g. between benchmark runs.
+    ///
+    pub fn reset(&self) {
+        let mut inner = self.inner();
+        inner.metrics = Metrics::default();
+        inner.since = inner.clock.now();
+    }
+
+    pub fn export(&self, format: MetricsFormat) -> String {
+        let inner = self.inner();
+        let name = inner.config.name.as_deref().unwrap_or("");
+
+        match format {
+            MetricsFormat::Json => {
+                let elapsed = inner.clock.now().saturating_duration_since(inner.since);
+                json(name, elapsed, &inner.metrics)
+            }
+            MetricsFormat::Prometheus => prometheus(name, &inner.metrics),
+        }
+    }
+
+    ///
+    /// Write the JSON export to `path`, replacing it.
+    ///
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.export(MetricsFormat::Json))
+    }
+
+    ///
+    /// Serve the Prometheus export over HTTP on `address`, from a thread
+    /// of its own, for as long as the process runs.
+    ///
+    #[cfg(feature = "metrics-http")]
+    pub fn serve_http(&self, address: impl std::net::ToSocketAddrs) -> io::Result<()> {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind(address)?;
+        let recorder = self.clone();
+
+        std::thread::Builder::new()
+            .name("avy-metrics-http".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else {
+                        continue;
+                    };
+
+                    // Whatever was asked for, the answer is the same.
+                    let mut request = [0; 1024];
+                    let _ = stream.read(&mut request);
+
+                    let body = recorder.export(MetricsFormat::Prometheus);
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\n\
+                         Content-Type: text/plain; version=0.0.4\r\n\
+                         Content-Length: {}\r\n\
+                         Connection: close\r\n\r\n{body}",
+                        body.len(),
+                    );
+                }
+            })?;
+
+        Ok(())
+    }
+}
+
+const PERCENTILES: [(&str, f64); 3] = [("p50", 50.0), ("p95", 95.0), ("p99", 99.0)];
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn json(name: &str, elapsed: Duration, metrics: &Metrics) -> String {
+    let histogram = |histogram: &Histogram| {
+        let mut out = format!("{{\"count\":{}", histogram.count());
+        for (label, percentile) in PERCENTILES {
+            let _ = write!(
+                out,
+                ",\"{label}_ms\":{:.3}",
+                millis(histogram.percentile(percentile))
+            );
+        }
+
+        let _ = write!(
+            out,
+            ",\"mean_ms\":{:.3},\"max_ms\":{:.3}}}",
+            millis(histogram.mean()),
+            millis(histogram.max()),
+        );
+        out
+    };
+
+    let gpu_histogram = |gpu: &Histogram| {
+        if metrics.gpu_timing_available {
+            histogram(gpu)
+        } else {
+            "null".to_string()
+        }
+    };
+
+    format!(
+        "{{\"surface\":{name:?},\"elapsed_s\":{:.3},\"frames\":{},\
+         \"swapchain_recreations\":{},\"suboptimal_frames\":{},\"callback_panics\":{},\
+         \"fallback_frames\":{},\"quality\":{},\
+         \"frame_time\":{},\"acquire_latency\":{},\"present_latency\":{},\
+         \"gpu_timing_available\":{},\"gpu_render\":{},\"gpu_total\":{},\
+         \"input_to_photon\":{}}}\n",
+        elapsed.as_secs_f64(),
+        metrics.frames,
+        metrics.swapchain_recreations,
+        metrics.suboptimal_frames,
+        metrics.callback_panics,
+        metrics.fallback_frames,
+        metrics
+            .quality
+            .map_or("null".to_string(), |quality| format!("{quality:.2}")),
+        histogram(&metrics.frame_time),
+        histogram(&metrics.acquire_latency),
+        histogram(&metrics.present_latency),
+        metrics.gpu_timing_available,
+        gpu_histogram(&metrics.gpu_render),
+        gpu_histogram(&metrics.gpu_total),
+        histogram(&metrics.input_to_photon),
+    )
+}
+
+fn prometheus(name: &str, metrics: &Metrics) -> String {
+    let mut out = String::new();
+    let labels = format!("surface={name:?}");
+
+    let mut histograms = vec![
+        ("avy_frame_time_seconds", &metrics.frame_time),
+        ("avy_acquire_latency_seconds", &metrics.acquire_latency),
+        ("avy_present_latency_seconds", &metrics.present_latency),
+    ];
+
+    if metrics.gpu_timing_available {
+        histograms.push(("avy_gpu_render_seconds", &metrics.gpu_render));
+        histograms.push(("avy_gpu_total_seconds", &metrics.gpu_total));
+    }
+
+    if metrics.input_to_photon.count() > 0 {
+        histograms.push(("avy_input_to_photon_seconds", &metrics.input_to_photon));
+    }
+
+    for (metric, histogram) in histograms {
+        let _ = writeln!(out, "# TYPE {metric} summary");
+        for (_, percentile) in PERCENTILES {
+            let _ = writeln!(
+                out,
+                "{metric}{{{labels},quantile=\"{}\"}} {}",
+                percentile / 100.0,
+                histogram.percentile(percentile).as_secs_f64(),
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "{metric}_sum{{{labels}}} {}",
+            histogram.sum().as_secs_f64()
+        );
+        let _ = writeln!(out, "{metric}_count{{{labels}}} {}", histogram.count());
+    }
+
+    for (metric, value) in [
+        ("avy_frames_total", metrics.frames),
+        (
+            "avy_swapchain_recreations_total",
+            metrics.swapchain_recreations,
+        ),
+        ("avy_suboptimal_frames_total", metrics.suboptimal_frames),
+        ("avy_callback_panics_total", metrics.callback_panics),
+        ("avy_fallback_frames_total", metrics.fallback_frames),
+    ] {
+        let _ = writeln!(out, "# TYPE {metric} counter");
+        let _ = writeln!(out, "{metric}{{{labels}}} {value}");
+    }
+
+    let _ = writeln!(out, "# TYPE avy_gpu_timing_available gauge");
+    let _ = writeln!(
+        out,
+        "avy_gpu_timing_available{{{labels}}} {}",
+        metrics.gpu_timing_available as u8
+    );
+
+    if let Some(quality) = metrics.quality {
+        let _ = writeln!(out, "# TYPE avy_render_quality gauge");
+        let _ = writeln!(out, "avy_render_quality{{{labels}}} {quality}");
+    }
+
+    out
+}
+
+impl AvyClient {
+    ///
+    /// How often [AvyClient::dispatch] woke up (e.g. under [crate::run::run]),
+    /// and how often for nothing: a client with nothing to draw should
+    /// hardly wake up at all. Also how long input waited to be handled.
+    ///
+    pub fn loop_metrics(&self) -> LoopMetrics {
+        self.loop_metrics
+    }
+
+    pub fn reset_loop_metrics(&mut self) {
+        self.loop_metrics = LoopMetrics {
+            presented: self.loop_metrics.presented,
+            epoch: self.loop_metrics.epoch,
+            input_clock_offset: self.loop_metrics.input_clock_offset,
+            ..LoopMetrics::new(self.clock.now())
+        };
+    }
+
+    pub(crate) fn record_input(&mut self, time: u32) {
+        self.loop_metrics.record_input(time, self.clock.now());
+    }
+
+    pub(crate) fn record_wakeup(&mut self) {
+        let presented = self
+            .surface_shared
+            .values()
+            .filter_map(|state| *state.last_presented.lock().unwrap())
+            .map(|(frame, _)| frame)
+            .sum();
+
+        self.loop_metrics.record_wakeup(presented);
+    }
+
+    ///
+    /// Write out every surface's [MetricsRecorder] (with a [MetricsConfig::dump_path])
+    /// on `SIGUSR2`, if enabled through [METRICS_ON_SIGUSR2_ENV].
+    ///
+    pub(crate) fn install_metrics_signal(handle: &LoopHandle<'static, AvyClient>) {
+        if std::env::var(METRICS_ON_SIGUSR2_ENV).as_deref() != Ok("1") {
+            return;
+        }
+
+        let signals = match Signals::new(&[Signal::SIGUSR2]) {
+            Ok(signals) => signals,
+            Err(err) => {
+                log::warn!("Could not listen for SIGUSR2: {err}");
+                return;
+            }
+        };
+
+        if let Err(err) = handle.insert_source(signals, |_, _, app| {
+            app.write_metrics();
+        }) {
+            log::warn!("Could not listen for SIGUSR2: {err}");
+        }
+    }
+
+    fn write_metrics(&self) {
+        let recorders = self
+            .surface_shared
+            .values()
+            .filter_map(|state| state.metrics.lock().unwrap().clone());
+
+        for recorder in recorders {
+            let Some(path) = recorder.config().dump_path else {
+                continue;
+            };
+
+            if let Err(err) = recorder.write_to(&path) {
+                log::warn!("Could not write metrics to {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_are_measured_against_the_quickest_event() {
+        let mut metrics = LoopMetrics::new(Instant::now());
+
+        assert_eq!(metrics.delay_since_quickest(1000, 50), Some(0));
+        assert_eq!(metrics.delay_since_quickest(1010, 90), Some(30));
+        // Quicker than the first: what the rest are measured against from now on.
+        assert_eq!(metrics.delay_since_quickest(1100, 140), None);
+        assert_eq!(metrics.delay_since_quickest(1200, 250), Some(10));
+    }
+
+    #[test]
+    fn delays_survive_the_timestamps_wrapping_around() {
+        let mut metrics = LoopMetrics::new(Instant::now());
+
+        assert_eq!(metrics.delay_since_quickest(u32::MAX - 5, 100), Some(0));
+        assert_eq!(metrics.delay_since_quickest(4, 120), Some(10));
+    }
+
+    #[test]
+    fn timestamps_on_another_clock_are_implausible() {
+        assert_eq!(plausible_delay(1000, 1012), Some(12));
+        assert_eq!(plausible_delay(u32::MAX, 7), Some(8));
+        assert_eq!(plausible_delay(1000, 999), None);
+        assert_eq!(plausible_delay(1000, 1000 + 60_000), None);
+    }
+
+    #[test]
+    fn the_longest_delay_is_kept() {
+        let mut metrics = LoopMetrics::new(Instant::now());
+        metrics.record_input_delay(30);
+        metrics.record_input_delay(4);
+
+        assert_eq!(metrics.max_input_dispatch_delay_ms(), 30.0);
+    }
+}
@@ -0,0 +1,171 @@
+//!
+//! Running code on the event loop's thread from other threads, which
+//! is the only place [AvyClient] can be used from.
+//!
+
+use smithay_client_toolkit::reexports::calloop::{
+    channel::{self, Channel, Event, Sender},
+    LoopHandle,
+};
+use thiserror::Error;
+use wayland_backend::client::ObjectId;
+
+use crate::{wayland::surface::events::SurfaceEvent, AvyClient};
+
+type Invocation = Box<dyn FnOnce(&mut AvyClient) + Send>;
+
+#[derive(Debug, Error)]
+#[error("The client's event loop is gone.")]
+pub struct Disconnected;
+
+///
+/// Queues work onto the event loop of an [AvyClient], from any thread.
+///
+/// Invocations run on the next loop iteration, in the order they were
+/// made, waking the loop up if it's waiting for events. Proxies are cheap
+/// to clone; get one from [AvyClient::proxy].
+///
+#[derive(Clone)]
+pub struct AvyProxy {
+    sender: Sender<Invocation>,
+}
+
+impl AvyProxy {
+    ///
+    /// Run `callback` on the event loop.
+    ///
+    pub fn invoke(
+        &self,
+        callback: impl FnOnce(&mut AvyClient) + Send + 'static,
+    ) -> Result<(), Disconnected> {
+        self.sender
+            .send(Box::new(callback))
+            .map_err(|_| Disconnected)
+    }
+
+    ///
+    /// Mark `surface` as needing repainting, see [crate::app::AvySurfaceHandle::mark_dirty].
+    ///
+    pub fn mark_dirty(&self, surface: ObjectId) -> Result<(), Disconnected> {
+        self.invoke(move |app| {
+            if let Some(state) = app.surface_shared.get(&surface) {
+                state.dirty.mark();
+            }
+        })
+    }
+
+    ///
+    /// Hand `event` to the subscribers of `surface`'s events
+    /// (see [crate::app::AvySurfaceHandle::on_event]).
+    ///
+    pub fn post_event(&self, surface: ObjectId, event: SurfaceEvent) -> Result<(), Disconnected> {
+        self.invoke(move |app| {
+            if let Some(state) = app.surface_shared.get(&surface) {
+                state.events.emit(event);
+            }
+        })
+    }
+}
+
+///
+/// The receiving end of every [AvyProxy], until it's
+/// inserted into the event loop.
+///
+pub(crate) struct ProxyQueue {
+    pub(crate) proxy: AvyProxy,
+    pub(crate) channel: Option<Channel<Invocation>>,
+}
+
+impl ProxyQueue {
+    pub fn new() -> Self {
+        let (sender, channel) = channel::channel();
+
+        Self {
+            proxy: AvyProxy { sender },
+            channel: Some(channel),
+        }
+    }
+}
+
+impl AvyClient {
+    ///
+    /// Get a proxy for working with this client from other threads.
+    ///
+    /// Invocations made before [AvyClient::set_loop_handle] are
+    /// queued, and run once the client has an event loop.
+    ///
+    pub fn proxy(&self) -> AvyProxy {
+        self.proxy_queue.proxy.clone()
+    }
+
+    pub(crate) fn start_proxy_queue(&mut self, handle: &LoopHandle<'static, AvyClient>) {
+        let Some(channel) = self.proxy_queue.channel.take() else {
+            return;
+        };
+
+        if let Err(err) = handle.insert_source(channel, |event, _, app| {
+            if let Event::Msg(invocation) = event {
+                invocation(app);
+            }
+        }) {
+            log::warn!("Could not run proxy invocations on the event loop: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn queued(queue: &ProxyQueue) -> usize {
+        let channel = queue.channel.as_ref().unwrap();
+        std::iter::from_fn(|| channel.try_recv().ok()).count()
+    }
+
+    #[test]
+    fn invocations_wait_for_the_event_loop() {
+        let queue = ProxyQueue::new();
+        let proxy = queue.proxy.clone();
+
+        proxy.invoke(|_| {}).unwrap();
+        proxy.mark_dirty(ObjectId::null()).unwrap();
+
+        assert_eq!(queued(&queue), 2);
+    }
+
+    #[test]
+    fn clones_share_the_queue_across_threads() {
+        let queue = ProxyQueue::new();
+
+        let senders: Vec<_> = (0..4)
+            .map(|_| {
+                let proxy = queue.proxy.clone();
+                thread::spawn(move || {
+                    for _ in 0..8 {
+                        proxy.invoke(|_| {}).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for sender in senders {
+            sender.join().unwrap();
+        }
+
+        assert_eq!(queued(&queue), 32);
+    }
+
+    #[test]
+    fn invoking_without_the_event_loop_fails() {
+        let mut queue = ProxyQueue::new();
+        let proxy = queue.proxy.clone();
+
+        // As when the event loop, and the channel in it, is dropped.
+        queue.channel.take();
+
+        assert!(matches!(proxy.invoke(|_| {}), Err(Disconnected)));
+        assert!(proxy.mark_dirty(ObjectId::null()).is_err());
+    }
+}
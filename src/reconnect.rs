@@ -0,0 +1,323 @@
+//!
+//! Outliving the compositor: when it crashes and restarts (as it does, whilst
+//! developing one), [run_reconnecting] waits for it to come back and connects
+//! again, rather than exiting, and puts the client's named layers back where
+//! they were with the session restore (see [crate::config::session]).
+//!
+//! Nothing made on the old connection survives it. Every surface comes back
+//! drawn with a graphics backend made from scratch (the old one was set up for
+//! the old connection's display, see [Reconnect::new]), through [Reconnect::on_remap],
+//! and `setup` runs again on the new connection's event loop. Only named layers
+//! come back, see [AvyClient::export_layout].
+//!
+//! Waiting between attempts goes by the client's clock (see [SharedClock::sleep]),
+//! so with a [crate::util::TestClock] it doesn't wait at all.
+//!
+
+use std::{collections::HashMap, fmt, time::Duration};
+
+use crate::{
+    app::AvySurfaceHandle,
+    avy::AvyError,
+    config::{LayoutRestore, LayoutSnapshot},
+    graphics::GraphicsBackend,
+    run::{event_loop, RunError},
+    util::SharedClock,
+    Avy, AvyClient,
+};
+
+type BackendFactory<'a, G> = Box<dyn FnMut() -> Result<G, <G as GraphicsBackend>::Error> + 'a>;
+type LostCallback<'a> = Box<dyn FnMut(&mut AvyClient, &LayoutSnapshot) + 'a>;
+type RemapCallback<'a, G> = Box<dyn FnMut(&mut AvyClient, &str, AvySurfaceHandle<G>) + 'a>;
+
+///
+/// How long to wait between attempts to connect again, see [Reconnect::with_backoff].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Before the first attempt, doubled after every one that fails.
+    pub initial: Duration,
+    pub max: Duration,
+    /// Stop trying (and return the last error) after this long, or never if `None`.
+    pub give_up_after: Option<Duration>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+            give_up_after: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+///
+/// What [run_reconnecting] does once the connection is lost.
+///
+pub struct Reconnect<'a, G: GraphicsBackend> {
+    make_backend: BackendFactory<'a, G>,
+    /// What the surfaces which came back last time are drawn with.
+    backend: Option<G>,
+    logical_size: (u32, u32),
+    backoff: Backoff,
+    on_lost: Option<LostCallback<'a>>,
+    on_remap: Option<RemapCallback<'a, G>>,
+}
+
+impl<'a, G: GraphicsBackend> Reconnect<'a, G> {
+    ///
+    /// Surfaces come back drawn with a backend made by `make_backend` for
+    /// every attempt to connect again, as whatever the old one set up for
+    /// the old connection's display is no use on the new one. `logical_size`
+    /// is the client's default surface size, see [Avy::connect].
+    ///
+    /// The backend is kept until the next time the connection is lost, or
+    /// [run_reconnecting] returns: surfaces handed to [Reconnect::on_remap]
+    /// mustn't be kept any longer.
+    ///
+    pub fn new(
+        make_backend: impl FnMut() -> Result<G, G::Error> + 'a,
+        logical_size: (u32, u32),
+    ) -> Self {
+        Self {
+            make_backend: Box::new(make_backend),
+            backend: None,
+            logical_size,
+            backoff: Backoff::default(),
+            on_lost: None,
+            on_remap: None,
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    ///
+    /// Call `on_lost` with the old client as soon as the connection is lost
+    /// (it can't send any more requests), and the layout that's coming back,
+    /// e.g. to stop whatever feeds its surfaces.
+    ///
+    pub fn on_lost(mut self, on_lost: impl FnMut(&mut AvyClient, &LayoutSnapshot) + 'a) -> Self {
+        self.on_lost.replace(Box::new(on_lost));
+        self
+    }
+
+    ///
+    /// Call `on_remap` with the new client for every surface which came back,
+    /// by name, for the application to draw into from then on.
+    ///
+    /// Runs before `setup`, see [run_reconnecting].
+    ///
+    pub fn on_remap(
+        mut self,
+        on_remap: impl FnMut(&mut AvyClient, &str, AvySurfaceHandle<G>) + 'a,
+    ) -> Self {
+        self.on_remap.replace(Box::new(on_remap));
+        self
+    }
+
+    ///
+    /// Connect again, retrying as set by the backoff, and rebuild `snapshot`
+    /// with a new backend, on a client reading the time from `clock`.
+    ///
+    /// Only once the old client's surfaces are gone, as the last backend goes.
+    ///
+    fn reconnect(
+        &mut self,
+        snapshot: &LayoutSnapshot,
+        clock: &SharedClock,
+    ) -> Result<(Avy, LayoutRestore<G>), RunError>
+    where
+        G::Surface: 'static,
+        G::Error: 'static,
+    {
+        let Self {
+            make_backend,
+            backend,
+            logical_size,
+            backoff,
+            ..
+        } = self;
+
+        retry(backoff, clock, || {
+            let mut avy = Avy::connect(*logical_size).map_err(RunError::Reconnect)?;
+
+            // Whatever the last attempt drew with went with its surfaces.
+            backend.take();
+            let made = make_backend().map_err(|err| RunError::ReconnectBackend(Box::new(err)))?;
+            let backend = backend.insert(made);
+
+            // Before any surface takes the new client's clock.
+            avy.clock.adopt(clock);
+            let restore = avy.restore_layout(snapshot, backend);
+            avy.roundtrip().map_err(RunError::Reconnect)?;
+
+            Ok((avy, restore))
+        })
+    }
+}
+
+///
+/// Call `attempt` until it succeeds, sleeping on `clock` before every
+/// try as set by `backoff`, or return its last error once it gives up.
+///
+fn retry<T, E: fmt::Display>(
+    backoff: &Backoff,
+    clock: &SharedClock,
+    mut attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let started = clock.now();
+    let mut delay = backoff.initial;
+
+    loop {
+        clock.sleep(delay);
+
+        let error = match attempt() {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+
+        let gave_up = backoff.give_up_after.is_some_and(|give_up_after| {
+            clock.now().saturating_duration_since(started) >= give_up_after
+        });
+        if gave_up {
+            return Err(error);
+        }
+
+        delay = (delay * 2).min(backoff.max);
+        log::info!("Could not reconnect ({error}), trying again in {delay:?}.");
+    }
+}
+
+///
+/// Like [crate::run::run], but when the connection is lost without a protocol
+/// error (e.g. as the compositor crashed), connect again and carry on, as set
+/// by `reconnect`.
+///
+/// `setup` runs on every connection's event loop, after the surfaces which
+/// came back were handed to [Reconnect::on_remap].
+///
+pub fn run_reconnecting<G: GraphicsBackend>(
+    mut avy: Avy,
+    mut reconnect: Reconnect<'_, G>,
+    mut setup: impl FnMut(&mut AvyClient) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), RunError>
+where
+    G::Surface: 'static,
+    G::Error: 'static,
+{
+    let mut remapped = HashMap::new();
+
+    loop {
+        let (mut event_loop, mut app) = event_loop(avy)?;
+
+        if let Some(on_remap) = reconnect.on_remap.as_mut() {
+            for (name, handle) in remapped.drain() {
+                on_remap(&mut app, &name, handle);
+            }
+        }
+
+        setup(&mut app).map_err(RunError::Setup)?;
+
+        let lost = loop {
+            if !app.running {
+                break false;
+            }
+
+            match app.dispatch(&mut event_loop, None) {
+                Ok(()) => (),
+                Err(AvyError::Disconnected(_)) => break true,
+                Err(err) => return Err(err.into()),
+            }
+        };
+
+        if !lost {
+            // Surfaces go (and their frames finish) before the backends they were made with.
+            let connection = app.connection();
+            app.shutdown(&connection)?;
+
+            return Ok(());
+        }
+
+        let snapshot = app.export_layout();
+        if let Some(on_lost) = reconnect.on_lost.as_mut() {
+            on_lost(&mut app, &snapshot);
+        }
+
+        let clock = app.clock.clone();
+        let fatal_error = app.fatal_error.take();
+
+        // The old surfaces go before the connection their display belongs to.
+        app.abandon();
+        drop(app);
+        drop(event_loop);
+
+        let (next, restore) = reconnect.reconnect(&snapshot, &clock)?;
+        log::info!(
+            "Reconnected, with {} of {} surfaces back.",
+            restore.surfaces.len(),
+            snapshot.surfaces.len()
+        );
+        for warning in &restore.warnings {
+            log::warn!("{warning:?}");
+        }
+
+        avy = next;
+        avy.fatal_error = fatal_error;
+        remapped = restore.surfaces;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::TestClock;
+
+    use super::*;
+
+    fn backoff(give_up_after: Option<Duration>) -> Backoff {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+            give_up_after,
+        }
+    }
+
+    #[test]
+    fn retries_back_off_on_the_clock() {
+        let time = TestClock::new();
+        let clock = SharedClock::new(time.clone());
+        let mut attempts = Vec::new();
+
+        let result = retry(&backoff(None), &clock, || {
+            attempts.push(time.elapsed().as_millis());
+            if attempts.len() < 6 {
+                Err("not yet")
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(result, Ok(()));
+        // Doubling the delay every time, up to the maximum.
+        assert_eq!(attempts, [100, 300, 700, 1500, 2500, 3500]);
+    }
+
+    #[test]
+    fn retries_give_up_with_the_last_error() {
+        let time = TestClock::new();
+        let clock = SharedClock::new(time.clone());
+        let mut attempts = 0;
+
+        let result = retry(&backoff(Some(Duration::from_secs(1))), &clock, || {
+            attempts += 1;
+            Err::<(), _>(attempts)
+        });
+
+        // At 100, 300, 700 and 1500ms.
+        assert_eq!(result, Err(4));
+        assert_eq!(time.elapsed(), Duration::from_millis(1500));
+    }
+}
@@ -0,0 +1,252 @@
+//!
+//! What small applications (and the examples) have in common: picking an
+//! output and a backend from the command line, and running the event loop
+//! until the client stops [running](AvyClient::running), see [run].
+//!
+//! Recognised flags are `--output <name>` and `--backend <vulkan|shm>`.
+//!
+
+use std::str::FromStr;
+
+use smithay_client_toolkit::{
+    reexports::{
+        calloop::{
+            signals::{Signal, Signals},
+            EventLoop,
+        },
+        client::{backend::WaylandError, protocol::wl_output::WlOutput},
+    },
+    shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer},
+};
+use thiserror::Error;
+
+use crate::{
+    app::{AvySurfaceHandle, StaticRenderError},
+    avy::AvyError,
+    graphics::{GraphicsBackend, RenderContext},
+    util::Size,
+    wayland::surface::layer::AvyLayerParams,
+    Avy, AvyClient,
+};
+
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error("{0} needs a value.")]
+    MissingValue(&'static str),
+    #[error("Unknown argument {0:?}, expected --output <name> or --backend <vulkan|shm>.")]
+    UnknownArgument(String),
+    #[error("Unknown backend {0:?}, expected vulkan or shm.")]
+    UnknownBackend(String),
+    #[error("There is no output named {name:?}, only: {}", available.join(", "))]
+    UnknownOutput {
+        name: String,
+        available: Vec<String>,
+    },
+    #[error(transparent)]
+    Avy(#[from] AvyError),
+    #[error("Could not shut down cleanly: {0}")]
+    Shutdown(#[from] WaylandError),
+    #[error("Could not set up: {0}")]
+    Setup(Box<dyn std::error::Error>),
+    /// See [crate::reconnect::run_reconnecting].
+    #[cfg(feature = "reconnect")]
+    #[error("Could not reconnect to the compositor: {0}")]
+    Reconnect(AvyError),
+    /// See [crate::reconnect::Reconnect::new].
+    #[cfg(feature = "reconnect")]
+    #[error("Could not make a graphics backend to reconnect with: {0}")]
+    ReconnectBackend(Box<dyn std::error::Error>),
+}
+
+///
+/// How frames get to the compositor.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    ///
+    /// Drawn on the GPU, and presented through the backend's swapchain.
+    ///
+    #[default]
+    Vulkan,
+    ///
+    /// Drawn on the CPU into shared memory, with the backend's swapchain
+    /// left suspended, see [AvySurfaceHandle::render_static].
+    ///
+    Shm,
+}
+
+impl FromStr for BackendKind {
+    type Err = RunError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vulkan" => Ok(Self::Vulkan),
+            "shm" => Ok(Self::Shm),
+            _ => Err(RunError::UnknownBackend(s.to_owned())),
+        }
+    }
+}
+
+impl BackendKind {
+    ///
+    /// Draw a frame into `surface` with `callback`, whichever way this backend does.
+    ///
+    pub fn render<G: GraphicsBackend>(
+        self,
+        surface: &AvySurfaceHandle<G>,
+        callback: impl FnMut(&skia_safe::Canvas, &RenderContext),
+    ) -> Result<(), StaticRenderError<G::Error>>
+    where
+        G::Error: 'static,
+    {
+        match self {
+            Self::Vulkan => surface.render(callback).map_err(StaticRenderError::Render),
+            Self::Shm => surface.render_static(callback),
+        }
+    }
+}
+
+///
+/// Options given on the command line.
+///
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    ///
+    /// Name of the output to show surfaces on, rather than the compositor's pick.
+    ///
+    pub output: Option<String>,
+    pub backend: BackendKind,
+}
+
+impl RunOptions {
+    ///
+    /// Options from this process's arguments.
+    ///
+    pub fn from_args() -> Result<Self, RunError> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, RunError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output" => {
+                    let output = args.next().ok_or(RunError::MissingValue("--output"))?;
+                    options.output.replace(output);
+                }
+                "--backend" => {
+                    let backend = args.next().ok_or(RunError::MissingValue("--backend"))?;
+                    options.backend = backend.parse()?;
+                }
+                _ => return Err(RunError::UnknownArgument(arg)),
+            }
+        }
+
+        Ok(options)
+    }
+
+    ///
+    /// The output asked for, or `None` to leave it to the compositor.
+    ///
+    pub fn output(&self, app: &AvyClient) -> Result<Option<WlOutput>, RunError> {
+        let Some(name) = &self.output else {
+            return Ok(None);
+        };
+
+        app.output_by_name(name)
+            .map(Some)
+            .ok_or_else(|| RunError::UnknownOutput {
+                name: name.clone(),
+                available: app.output_names(),
+            })
+    }
+
+    ///
+    /// The output asked for, or every output if none was.
+    ///
+    pub fn outputs(&self, app: &AvyClient) -> Result<Vec<WlOutput>, RunError> {
+        match self.output(app)? {
+            Some(output) => Ok(vec![output]),
+            None => Ok(app.output_state.outputs().collect()),
+        }
+    }
+
+    ///
+    /// A bar `height` logical pixels thick, along `edge` of the output asked
+    /// for, reserving its space.
+    ///
+    pub fn bar<'a>(
+        &self,
+        app: &AvyClient,
+        namespace: &'a str,
+        edge: Anchor,
+        height: u32,
+    ) -> Result<AvyLayerParams<'a>, RunError> {
+        let (anchor, size) = match edge.intersects(Anchor::LEFT | Anchor::RIGHT) {
+            true => (edge | Anchor::TOP | Anchor::BOTTOM, (height, 0)),
+            false => (edge | Anchor::LEFT | Anchor::RIGHT, (0, height)),
+        };
+
+        Ok(AvyLayerParams {
+            layer: Layer::Top,
+            namespace: Some(namespace),
+            output: self.output(app)?,
+            anchor,
+            size: Size::new(size),
+            margin: None,
+            exclusive_zone: Some(height as i32),
+            keyboard_interactivity: KeyboardInteractivity::None,
+            manual_configure_ack: false,
+        })
+    }
+}
+
+///
+/// Run `avy` on an event loop of its own until it stops [running](AvyClient::running)
+/// (or is interrupted), then shut it down.
+///
+/// `setup` runs once the loop is up, for anything needing it (e.g. timers).
+/// Graphics backends must outlive this call.
+///
+pub fn run(
+    avy: Avy,
+    setup: impl FnOnce(&mut AvyClient) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), RunError> {
+    let (mut event_loop, mut app) = event_loop(avy)?;
+
+    setup(&mut app).map_err(RunError::Setup)?;
+
+    // Timers, frame callbacks and proxy invocations all wake the loop,
+    // so it can sleep for as long as none of them is due.
+    while app.running {
+        app.dispatch(&mut event_loop, None)?;
+    }
+
+    // Surfaces go (and their frames finish) before the backends they were made with.
+    let connection = app.connection();
+    app.shutdown(&connection)?;
+
+    Ok(())
+}
+
+///
+/// An event loop of its own for `avy`, which stops the client on SIGINT and SIGTERM.
+///
+pub(crate) fn event_loop(avy: Avy) -> Result<(EventLoop<'static, AvyClient>, AvyClient), RunError> {
+    let event_loop = EventLoop::<AvyClient>::try_new().map_err(AvyError::EventLoop)?;
+    let handle = event_loop.handle();
+    let app = avy.insert_into(&handle)?;
+
+    match Signals::new(&[Signal::SIGINT, Signal::SIGTERM]) {
+        Ok(signals) => {
+            if let Err(err) = handle.insert_source(signals, |_, _, app| app.running = false) {
+                log::warn!("Could not listen for SIGINT and SIGTERM: {err}");
+            }
+        }
+        Err(err) => log::warn!("Could not listen for SIGINT and SIGTERM: {err}"),
+    }
+
+    Ok((event_loop, app))
+}
@@ -0,0 +1,415 @@
+//!
+//! The primary selection (what's selected, pasted with a middle click),
+//! through `zwp_primary_selection_device_manager_v1`.
+//!
+//! Data is read from whoever holds the selection over a pipe, on the event
+//! loop, so reading never blocks it (even when we hold the selection).
+//!
+
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read, Write},
+    os::fd::OwnedFd,
+    sync::Arc,
+};
+
+use smithay_client_toolkit::{
+    data_device_manager::WritePipe,
+    primary_selection::{
+        device::{PrimarySelectionDevice, PrimarySelectionDeviceHandler},
+        selection::{PrimarySelectionSource, PrimarySelectionSourceHandler},
+        PrimarySelectionManagerState,
+    },
+    reexports::{
+        calloop::PostAction,
+        client::{
+            backend::WaylandError, protocol::wl_seat::WlSeat, Connection, Proxy, QueueHandle,
+        },
+        protocols::wp::primary_selection::zv1::client::{
+            zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+            zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+        },
+    },
+};
+use thiserror::Error;
+use wayland_backend::client::ObjectId;
+
+use crate::{debug, AvyClient};
+
+///
+/// Mime types text is offered as, and accepted in (most preferred first).
+///
+pub const TEXT_MIME_TYPES: &[&str] = &[
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+    "TEXT",
+    "STRING",
+];
+
+#[derive(Debug, Error)]
+pub enum SelectionError {
+    #[error("The compositor doesn't support primary selections.")]
+    Unsupported,
+    #[error("There's no seat to hold the selection.")]
+    NoSeat,
+    #[error("No recent enough user action to act on, try again on the next one.")]
+    StaleSerial,
+    #[error("Nothing is selected.")]
+    Empty,
+    #[error("The selection isn't offered in any of the accepted mime types.")]
+    NoMatchingMimeType,
+    #[error("No event loop to read the selection on, see AvyClient::set_loop_handle.")]
+    NoEventLoop,
+    #[error("Could not insert the pipe into the event loop.")]
+    Insert,
+    #[error("The selection isn't valid UTF-8.")]
+    NotText,
+    #[error("Could not read the selection: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not send the request: {0}")]
+    Connection(#[from] WaylandError),
+}
+
+///
+/// What's offered as the selection, the same bytes in each of its mime types.
+///
+#[derive(Debug, Clone)]
+pub struct SelectionData {
+    mime_types: Vec<String>,
+    data: Arc<[u8]>,
+}
+
+impl SelectionData {
+    pub fn new(mime_types: impl IntoIterator<Item = impl ToString>, data: &[u8]) -> Self {
+        Self {
+            mime_types: mime_types
+                .into_iter()
+                .map(|mime| mime.to_string())
+                .collect(),
+            data: data.into(),
+        }
+    }
+
+    ///
+    /// `text`, offered in each of [TEXT_MIME_TYPES].
+    ///
+    pub fn text(text: &str) -> Self {
+        Self::new(TEXT_MIME_TYPES.iter(), text.as_bytes())
+    }
+
+    pub fn mime_types(&self) -> &[String] {
+        &self.mime_types
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+type ReadCallback = Box<dyn FnOnce(&mut AvyClient, Result<(String, Vec<u8>), SelectionError>)>;
+
+///
+/// The client's primary selection devices, and what it's offering, if anything.
+///
+pub struct PrimarySelection {
+    manager: Option<PrimarySelectionManagerState>,
+    /// One per seat.
+    devices: Vec<PrimarySelectionDevice>,
+    /// Whilst the client holds the selection.
+    source: Option<(PrimarySelectionSource, SelectionData)>,
+}
+
+impl PrimarySelection {
+    pub fn new(manager: Option<PrimarySelectionManagerState>) -> Self {
+        Self {
+            manager,
+            devices: Vec::new(),
+            source: None,
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.manager.is_some()
+    }
+
+    fn device(&self) -> Result<&PrimarySelectionDevice, SelectionError> {
+        if !self.is_supported() {
+            return Err(SelectionError::Unsupported);
+        }
+
+        self.devices.first().ok_or(SelectionError::NoSeat)
+    }
+}
+
+impl AvyClient {
+    ///
+    /// Mime types the current primary selection is offered in.
+    ///
+    pub fn primary_mime_types(&self) -> Result<Vec<String>, SelectionError> {
+        if let Some((_, data)) = &self.primary_selection.source {
+            return Ok(data.mime_types.clone());
+        }
+
+        let offer = self
+            .primary_selection
+            .device()?
+            .data()
+            .selection_offer()
+            .ok_or(SelectionError::Empty)?;
+
+        Ok(offer.with_mime_types(<[String]>::to_vec))
+    }
+
+    ///
+    /// Read the primary selection in the first of `accepted` it's offered in, then
+    /// call `callback` (on the event loop) with the mime type picked and the data.
+    ///
+    pub fn read_primary(
+        &mut self,
+        accepted: &[&str],
+        callback: impl FnOnce(&mut AvyClient, Result<(String, Vec<u8>), SelectionError>) + 'static,
+    ) -> Result<(), SelectionError> {
+        // Our own selection needn't make a round trip through the compositor.
+        if let Some((_, data)) = &self.primary_selection.source {
+            let mime = pick_mime_type(accepted, &data.mime_types)?;
+            let data = data.data.to_vec();
+            callback(self, Ok((mime, data)));
+            return Ok(());
+        }
+
+        let offer = self
+            .primary_selection
+            .device()?
+            .data()
+            .selection_offer()
+            .ok_or(SelectionError::Empty)?;
+
+        let mime = offer.with_mime_types(|offered| pick_mime_type(accepted, offered))?;
+
+        let handle = self
+            .loop_handle
+            .as_ref()
+            .ok_or(SelectionError::NoEventLoop)?;
+
+        let pipe = offer.receive(mime.clone())?;
+        self.connection().flush()?;
+
+        let mut callback: Option<ReadCallback> = Some(Box::new(callback));
+        let mut received = Vec::new();
+
+        handle
+            .insert_source(pipe, move |_, file, app| {
+                let mut chunk = [0; 4096];
+                let mut file: &File = file;
+
+                let result = match file.read(&mut chunk) {
+                    Ok(0) => Ok(std::mem::take(&mut received)),
+                    Ok(read) => {
+                        received.extend_from_slice(&chunk[..read]);
+                        return PostAction::Continue;
+                    }
+                    Err(err)
+                        if matches!(err.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock) =>
+                    {
+                        return PostAction::Continue;
+                    }
+                    Err(err) => Err(SelectionError::Io(err)),
+                };
+
+                if let Some(callback) = callback.take() {
+                    callback(app, result.map(|data| (mime.clone(), data)));
+                }
+
+                PostAction::Remove
+            })
+            .map_err(|_| SelectionError::Insert)?;
+
+        Ok(())
+    }
+
+    ///
+    /// Read the primary selection as text, see [AvyClient::read_primary].
+    ///
+    pub fn read_primary_text(
+        &mut self,
+        callback: impl FnOnce(&mut AvyClient, Result<String, SelectionError>) + 'static,
+    ) -> Result<(), SelectionError> {
+        self.read_primary(TEXT_MIME_TYPES, move |app, result| {
+            let text = result
+                .and_then(|(_, data)| String::from_utf8(data).map_err(|_| SelectionError::NotText));
+
+            callback(app, text)
+        })
+    }
+
+    ///
+    /// Hold the primary selection, offering `data`.
+    ///
+    /// `serial` must be that of the input event which made the selection (a key
+    /// press or button press, on a focused surface), or `None` for the last one
+    /// (see [AvyClient::last_serial]). Compositors silently ignore requests with
+    /// stale serials, so those which are known to be (made before keyboard focus
+    /// was lost) fail with [SelectionError::StaleSerial] instead.
+    ///
+    pub fn set_primary(
+        &mut self,
+        data: SelectionData,
+        serial: impl Into<Option<u32>>,
+    ) -> Result<(), SelectionError> {
+        let serial = self.selection_serial(serial.into())?;
+        let device = self.primary_selection.device()?;
+        let Some(manager) = &self.primary_selection.manager else {
+            return Err(SelectionError::Unsupported);
+        };
+
+        let source = manager.create_selection_source(&self.queue_handle, data.mime_types.iter());
+        source.set_selection(device, serial);
+        debug::objects::created(&source.inner().id(), None);
+
+        // Replacing a source we held destroys it.
+        if let Some((old, _)) = self.primary_selection.source.replace((source, data)) {
+            debug::objects::destroyed(&old.inner().id());
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Give up the primary selection, if we hold it. See [AvyClient::set_primary] for `serial`.
+    ///
+    pub fn clear_primary(&mut self, serial: impl Into<Option<u32>>) -> Result<(), SelectionError> {
+        self.primary_selection.device()?;
+        let serial = self.selection_serial(serial.into())?;
+
+        if let Some((source, _)) = self.primary_selection.source.take() {
+            debug::objects::destroyed(&source.inner().id());
+            self.primary_selection.device()?.unset_selection(serial);
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// For text selection to call when the user selects `text` on `surface`, with the
+    /// `serial` of the event which selected it. Becomes the primary selection if the
+    /// surface has keyboard focus, and is ignored otherwise.
+    ///
+    pub fn report_text_selection(
+        &mut self,
+        surface: &ObjectId,
+        text: &str,
+        serial: u32,
+    ) -> Result<(), SelectionError> {
+        if self.keyboard_focus.as_ref() != Some(surface) || text.is_empty() {
+            return Ok(());
+        }
+
+        self.set_primary(SelectionData::text(text), serial)
+    }
+
+    ///
+    /// `serial`, or the last user action's, unless it's known to be stale.
+    ///
+    fn selection_serial(&self, serial: Option<u32>) -> Result<u32, SelectionError> {
+        let serials = self.last_serials();
+        let serial = serial
+            .or_else(|| serials.and_then(|serials| serials.any))
+            .ok_or(SelectionError::StaleSerial)?;
+
+        match serials.is_some_and(|serials| serials.is_stale(serial)) {
+            true => Err(SelectionError::StaleSerial),
+            false => Ok(serial),
+        }
+    }
+
+    pub(crate) fn add_selection_seat(&mut self, seat: &WlSeat) {
+        let Some(manager) = &self.primary_selection.manager else {
+            return;
+        };
+
+        let device = manager.get_selection_device(&self.queue_handle, seat);
+        self.primary_selection.devices.push(device);
+    }
+
+    pub(crate) fn remove_selection_seat(&mut self, seat: &WlSeat) {
+        // Dropping a device destroys it.
+        self.primary_selection
+            .devices
+            .retain(|device| device.data().seat() != seat);
+    }
+}
+
+///
+/// The first of `accepted` in `offered`.
+///
+fn pick_mime_type(accepted: &[&str], offered: &[String]) -> Result<String, SelectionError> {
+    accepted
+        .iter()
+        .find(|accepted| offered.iter().any(|offered| offered == *accepted))
+        .map(|mime| mime.to_string())
+        .ok_or(SelectionError::NoMatchingMimeType)
+}
+
+impl PrimarySelectionDeviceHandler for AvyClient {
+    fn selection(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        device: &ZwpPrimarySelectionDeviceV1,
+    ) {
+        log::trace!("Primary selection changed on {}", device.id());
+    }
+}
+
+impl PrimarySelectionSourceHandler for AvyClient {
+    fn send_request(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        source: &ZwpPrimarySelectionSourceV1,
+        mime: String,
+        write_pipe: WritePipe,
+    ) {
+        let Some((_, data)) = self
+            .primary_selection
+            .source
+            .as_ref()
+            .filter(|(ours, _)| ours.inner() == source)
+        else {
+            return;
+        };
+
+        if !data.mime_types.contains(&mime) {
+            return;
+        }
+
+        // The reader may take its time, so write away from the event loop.
+        let data = data.data.clone();
+        let mut file = File::from(OwnedFd::from(write_pipe));
+        std::thread::spawn(move || {
+            if let Err(err) = file.write_all(&data) {
+                log::debug!("Could not send the primary selection: {err}");
+            }
+        });
+    }
+
+    fn cancelled(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        source: &ZwpPrimarySelectionSourceV1,
+    ) {
+        // Someone else holds the selection now.
+        if self
+            .primary_selection
+            .source
+            .as_ref()
+            .is_some_and(|(ours, _)| ours.inner() == source)
+        {
+            if let Some((ours, _)) = self.primary_selection.source.take() {
+                debug::objects::destroyed(&ours.inner().id());
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//!
+//! Desktop-wide settings, read from (and kept in sync with) the XDG settings
+//! portal, falling back to the environment where it isn't available.
+//!
+
+pub mod portal;
+
+pub use crate::{
+    locale::{LanguageIdentifier, TextDirection},
+    theme::{AccessibilityOptions, Appearance, AppearanceSource, ColorScheme},
+};
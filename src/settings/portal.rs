@@ -0,0 +1,154 @@
+//!
+//! Watches the XDG settings portal for appearance changes.
+//!
+
+use std::thread::spawn;
+
+use skia_safe::Color4f;
+use smithay_client_toolkit::reexports::calloop::{
+    channel::{self, Event},
+    LoopHandle,
+};
+use thiserror::Error;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedValue, Value},
+};
+
+use crate::{
+    theme::{Appearance, AppearanceSource, ColorScheme},
+    AvyClient,
+};
+
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Could not talk to the settings portal: {0}")]
+    DBus(#[from] zbus::Error),
+
+    #[error("Could not insert the settings source into the event loop.")]
+    EventLoop,
+}
+
+enum Change {
+    ColorScheme(ColorScheme),
+    Accent(Option<Color4f>),
+}
+
+///
+/// Read the current appearance from the portal, then keep `AvyClient`
+/// up to date as it changes.
+///
+/// Falls back to [Appearance::from_env] when the portal isn't available.
+///
+pub fn watch(handle: &LoopHandle<'static, AvyClient>, app: &mut AvyClient) -> Result<(), Error> {
+    let proxy = match settings_proxy() {
+        Ok(proxy) => proxy,
+        Err(err) => {
+            log::info!("Settings portal unavailable ({err}), using environment.");
+            app.set_appearance(Appearance::from_env());
+            return Ok(());
+        }
+    };
+
+    app.set_appearance(Appearance {
+        color_scheme: read(&proxy, "color-scheme")
+            .and_then(|value| u32::try_from(value).ok())
+            .map(ColorScheme::from_portal)
+            .unwrap_or_default(),
+        accent: read(&proxy, "accent-color").and_then(accent_from_value),
+        source: AppearanceSource::Portal,
+    });
+
+    let (tx, rx) = channel::channel();
+
+    handle
+        .insert_source(rx, |event, _, app| {
+            let Event::Msg(change) = event else {
+                return;
+            };
+
+            let mut appearance = app.appearance();
+            appearance.source = AppearanceSource::Portal;
+
+            match change {
+                Change::ColorScheme(color_scheme) => appearance.color_scheme = color_scheme,
+                Change::Accent(accent) => appearance.accent = accent,
+            }
+
+            app.set_appearance(appearance);
+        })
+        .map_err(|_| Error::EventLoop)?;
+
+    let signals = proxy.receive_signal("SettingChanged")?;
+
+    spawn(move || {
+        // Keep the proxy alive as long as we listen.
+        let _proxy = proxy;
+
+        for message in signals {
+            let Ok((namespace, key, value)) =
+                message.body().deserialize::<(String, String, OwnedValue)>()
+            else {
+                continue;
+            };
+
+            if namespace != APPEARANCE_NAMESPACE {
+                continue;
+            }
+
+            let change = match key.as_str() {
+                "color-scheme" => Change::ColorScheme(
+                    u32::try_from(value)
+                        .map(ColorScheme::from_portal)
+                        .unwrap_or_default(),
+                ),
+                "accent-color" => Change::Accent(accent_from_value(value)),
+                _ => continue,
+            };
+
+            if tx.send(change).is_err() {
+                // Event loop is gone.
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn settings_proxy() -> Result<Proxy<'static>, zbus::Error> {
+    let connection = Connection::session()?;
+
+    Proxy::new(
+        &connection,
+        "org.freedesktop.portal.Desktop",
+        "/org/freedesktop/portal/desktop",
+        "org.freedesktop.portal.Settings",
+    )
+}
+
+fn read(proxy: &Proxy, key: &str) -> Option<OwnedValue> {
+    let value: OwnedValue = proxy
+        .call("ReadOne", &(APPEARANCE_NAMESPACE, key))
+        .or_else(|_| proxy.call("Read", &(APPEARANCE_NAMESPACE, key)))
+        .ok()?;
+
+    // The deprecated `Read` wraps the value in another variant.
+    match &*value {
+        Value::Value(inner) => inner.try_to_owned().ok(),
+        _ => Some(value),
+    }
+}
+
+fn accent_from_value(value: OwnedValue) -> Option<Color4f> {
+    let (r, g, b) = <(f64, f64, f64)>::try_from(value).ok()?;
+
+    // Out-of-range values mean "no accent colour set".
+    if [r, g, b].iter().any(|c| !(0.0..=1.0).contains(c)) {
+        return None;
+    }
+
+    Some(Color4f::new(r as f32, g as f32, b as f32, 1.0))
+}
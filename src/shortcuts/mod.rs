@@ -0,0 +1,312 @@
+//!
+//! Global shortcuts: key combinations which reach the client
+//! even when none of its surfaces have keyboard focus.
+//!
+//! Wayland has no standard way to do this, so it's up to what the
+//! desktop offers, in order of preference:
+//!
+//! * Hyprland's `hyprland_global_shortcuts_v1`: the user binds keys to the
+//!   shortcut in Hyprland's configuration (`bind = SUPER, space, global, app_id:id`).
+//! * The `org.freedesktop.portal.GlobalShortcuts` portal, with the `portal` feature.
+//!   The portal may ask the user to confirm (or pick) the keys. It's connected to
+//!   in the background, so should it turn out not to be there, its shortcuts are
+//!   dropped (with a warning) and later registrations fail.
+//!
+//! With neither, [AvyClient::register_global_shortcut] fails with
+//! [ShortcutError::Unsupported]. The preferred trigger is only ever a
+//! hint: the keys that end up triggering a shortcut are the user's choice.
+//!
+
+#[cfg(feature = "portal")]
+pub mod portal;
+
+use std::{collections::HashMap, time::Duration};
+
+use smithay_client_toolkit::reexports::client::{Connection, QueueHandle};
+use thiserror::Error;
+
+use crate::{
+    wayland::protocol::hyprland_global_shortcuts::{
+        client::hyprland_global_shortcut_v1::HyprlandGlobalShortcutV1, GlobalShortcutHandler,
+        GlobalShortcutsManager,
+    },
+    AvyClient,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShortcutState {
+    Pressed,
+    Released,
+}
+
+///
+/// A global shortcut being pressed or released.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortcutEvent {
+    pub id: String,
+    pub state: ShortcutState,
+
+    /// When it happened, on a clock picked by the compositor (or portal).
+    ///
+    /// Only differences between timestamps are meaningful.
+    pub timestamp: Duration,
+}
+
+///
+/// What delivers a client's global shortcuts.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShortcutMechanism {
+    Hyprland,
+    Portal,
+}
+
+#[derive(Debug, Error)]
+pub enum ShortcutError {
+    #[error("Global shortcuts aren't supported by this compositor.")]
+    Unsupported,
+
+    #[error("A global shortcut called {0:?} is already registered.")]
+    AlreadyRegistered(String),
+
+    #[error("No event loop to deliver shortcuts on, see AvyClient::set_loop_handle.")]
+    NoEventLoop,
+
+    #[cfg(feature = "portal")]
+    #[error("Could not talk to the global shortcuts portal: {0}")]
+    Portal(#[from] zbus::Error),
+}
+
+type ShortcutCallback = Box<dyn FnMut(&mut AvyClient, &ShortcutEvent)>;
+
+#[cfg_attr(not(feature = "portal"), allow(unused))]
+struct RegisteredShortcut {
+    description: String,
+    preferred_trigger: Option<String>,
+    /// Taken out whilst it runs.
+    callback: Option<ShortcutCallback>,
+    hyprland: Option<HyprlandGlobalShortcutV1>,
+}
+
+///
+/// The client's global shortcuts, and whatever delivers them.
+///
+pub struct GlobalShortcuts {
+    app_id: String,
+    shortcuts: HashMap<String, RegisteredShortcut>,
+    hyprland: Option<(GlobalShortcutsManager, QueueHandle<AvyClient>)>,
+    #[cfg(feature = "portal")]
+    portal: Option<portal::PortalShortcuts>,
+}
+
+impl GlobalShortcuts {
+    pub fn new(hyprland: Option<(GlobalShortcutsManager, QueueHandle<AvyClient>)>) -> Self {
+        // Something stable to bind keys to, in the compositor's configuration.
+        let app_id = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "avy".to_owned());
+
+        Self {
+            app_id,
+            shortcuts: HashMap::new(),
+            hyprland,
+            #[cfg(feature = "portal")]
+            portal: None,
+        }
+    }
+
+    fn mechanism(&self) -> Option<ShortcutMechanism> {
+        if self.hyprland.is_some() {
+            return Some(ShortcutMechanism::Hyprland);
+        }
+
+        #[cfg(feature = "portal")]
+        if self.portal.is_some() {
+            return Some(ShortcutMechanism::Portal);
+        }
+
+        None
+    }
+
+    #[cfg(feature = "portal")]
+    fn rebind_portal(&self) {
+        let Some(portal) = &self.portal else {
+            return;
+        };
+
+        portal.bind(
+            self.shortcuts
+                .iter()
+                .map(|(id, shortcut)| portal::ShortcutSpec {
+                    id: id.clone(),
+                    description: shortcut.description.clone(),
+                    preferred_trigger: shortcut.preferred_trigger.clone(),
+                })
+                .collect(),
+        );
+    }
+}
+
+impl AvyClient {
+    ///
+    /// The application id global shortcuts are registered under,
+    /// which defaults to the executable's name.
+    ///
+    pub fn shortcut_app_id(&self) -> &str {
+        &self.global_shortcuts.app_id
+    }
+
+    ///
+    /// Change the application id global shortcuts are registered under.
+    ///
+    /// Only affects shortcuts registered afterwards.
+    ///
+    pub fn set_shortcut_app_id(&mut self, app_id: impl Into<String>) {
+        self.global_shortcuts.app_id = app_id.into();
+    }
+
+    ///
+    /// Call `callback` (on the event loop) whenever the shortcut is pressed
+    /// or released, whichever surface has keyboard focus.
+    ///
+    /// `preferred_trigger` (e.g. `LOGO+space`) is a suggestion, shown to the
+    /// user: see the [module documentation](crate::shortcuts) for the details.
+    ///
+    pub fn register_global_shortcut(
+        &mut self,
+        id: impl Into<String>,
+        description: impl Into<String>,
+        preferred_trigger: Option<&str>,
+        callback: impl FnMut(&mut AvyClient, &ShortcutEvent) + 'static,
+    ) -> Result<ShortcutMechanism, ShortcutError> {
+        let id = id.into();
+        let description = description.into();
+
+        if self.global_shortcuts.shortcuts.contains_key(&id) {
+            return Err(ShortcutError::AlreadyRegistered(id));
+        }
+
+        #[cfg(feature = "portal")]
+        if self.global_shortcuts.hyprland.is_none() && self.global_shortcuts.portal.is_none() {
+            let handle = self
+                .loop_handle
+                .as_ref()
+                .ok_or(ShortcutError::NoEventLoop)?;
+            self.global_shortcuts.portal = Some(portal::PortalShortcuts::connect(handle)?);
+        }
+
+        let mechanism = self
+            .global_shortcuts
+            .mechanism()
+            .ok_or(ShortcutError::Unsupported)?;
+
+        let hyprland = self
+            .global_shortcuts
+            .hyprland
+            .as_ref()
+            .map(|(manager, qh)| {
+                manager.register(
+                    qh,
+                    &id,
+                    &self.global_shortcuts.app_id,
+                    &description,
+                    preferred_trigger.unwrap_or_default(),
+                )
+            });
+
+        self.global_shortcuts.shortcuts.insert(
+            id,
+            RegisteredShortcut {
+                description,
+                preferred_trigger: preferred_trigger.map(str::to_owned),
+                callback: Some(Box::new(callback)),
+                hyprland,
+            },
+        );
+
+        #[cfg(feature = "portal")]
+        self.global_shortcuts.rebind_portal();
+
+        Ok(mechanism)
+    }
+
+    ///
+    /// Stop listening for a global shortcut.
+    ///
+    /// Returns whether it was registered.
+    ///
+    pub fn unregister_global_shortcut(&mut self, id: &str) -> bool {
+        let Some(shortcut) = self.global_shortcuts.shortcuts.remove(id) else {
+            return false;
+        };
+
+        if let Some(hyprland) = shortcut.hyprland {
+            hyprland.destroy();
+        }
+
+        #[cfg(feature = "portal")]
+        self.global_shortcuts.rebind_portal();
+
+        true
+    }
+
+    ///
+    /// Give up on the portal, and the shortcuts registered through it.
+    ///
+    #[cfg(feature = "portal")]
+    fn portal_unavailable(&mut self, reason: &str) {
+        if self.global_shortcuts.portal.take().is_none() {
+            return;
+        }
+
+        log::warn!(
+            "Global shortcuts portal unavailable ({reason}), dropping {} shortcuts.",
+            self.global_shortcuts.shortcuts.len()
+        );
+        self.global_shortcuts.shortcuts.clear();
+    }
+
+    pub fn global_shortcuts(&self) -> impl Iterator<Item = &str> {
+        self.global_shortcuts.shortcuts.keys().map(String::as_str)
+    }
+
+    fn dispatch_shortcut(&mut self, event: ShortcutEvent) {
+        let Some(mut callback) = self
+            .global_shortcuts
+            .shortcuts
+            .get_mut(&event.id)
+            .and_then(|shortcut| shortcut.callback.take())
+        else {
+            return;
+        };
+
+        callback(self, &event);
+
+        // Put it back, unless the shortcut was unregistered
+        // (or registered again) by the callback.
+        if let Some(shortcut) = self.global_shortcuts.shortcuts.get_mut(&event.id) {
+            if shortcut.callback.is_none() {
+                shortcut.callback = Some(callback);
+            }
+        }
+    }
+}
+
+impl GlobalShortcutHandler for AvyClient {
+    fn global_shortcut(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        id: &str,
+        state: ShortcutState,
+        timestamp: Duration,
+    ) {
+        self.dispatch_shortcut(ShortcutEvent {
+            id: id.to_owned(),
+            state,
+            timestamp,
+        });
+    }
+}
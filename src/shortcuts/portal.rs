@@ -0,0 +1,319 @@
+//!
+//! Global shortcuts through the XDG `GlobalShortcuts` portal.
+//!
+//! The portal has no way to unbind a single shortcut, so whenever the set
+//! changes, the session is closed and a new one bound with every shortcut.
+//! The same happens when the portal restarts.
+//!
+//! Nothing here blocks the event loop: even connecting to the session bus
+//! happens on another thread, and whether the portal is there at all is
+//! only found out afterwards (see [PortalEvent::Unavailable]).
+//!
+
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::spawn,
+    time::Duration,
+};
+
+use smithay_client_toolkit::reexports::calloop::{
+    channel::{self, Event},
+    LoopHandle,
+};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+use crate::AvyClient;
+
+use super::{ShortcutEvent, ShortcutState};
+
+const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SHORTCUTS_INTERFACE: &str = "org.freedesktop.portal.GlobalShortcuts";
+
+///
+/// A shortcut, as handed to the portal.
+///
+#[derive(Debug, Clone)]
+pub struct ShortcutSpec {
+    pub id: String,
+    pub description: String,
+    pub preferred_trigger: Option<String>,
+}
+
+///
+/// What the portal's threads hand to the event loop.
+///
+enum PortalEvent {
+    Shortcut(ShortcutEvent),
+    /// Couldn't talk to the portal, or it doesn't offer global shortcuts.
+    Unavailable(String),
+}
+
+/// The session, shortcut id, timestamp and options of an `Activated` (or `Deactivated`) signal.
+type Activation = (OwnedObjectPath, String, u64, HashMap<String, OwnedValue>);
+
+enum Command {
+    Bind(Vec<ShortcutSpec>),
+    /// The portal restarted, and forgot about our session.
+    Rebind,
+}
+
+///
+/// Talks to the portal from its own threads, handing activations to the event loop.
+///
+pub struct PortalShortcuts {
+    commands: Sender<Command>,
+}
+
+impl PortalShortcuts {
+    ///
+    /// Start talking to the portal, from another thread.
+    ///
+    /// Shortcuts bound before it's connected are bound once it is.
+    ///
+    pub fn connect(handle: &LoopHandle<'static, AvyClient>) -> Result<Self, zbus::Error> {
+        let (events_tx, events_rx) = channel::channel();
+        handle
+            .insert_source(events_rx, |event, _, app| match event {
+                Event::Msg(PortalEvent::Shortcut(event)) => app.dispatch_shortcut(event),
+                Event::Msg(PortalEvent::Unavailable(reason)) => app.portal_unavailable(&reason),
+                Event::Closed => {}
+            })
+            .map_err(|_| zbus::Error::Failure("Could not insert into the event loop.".into()))?;
+
+        let (commands, commands_rx) = mpsc::channel();
+        let watcher = commands.clone();
+
+        spawn(move || {
+            let unavailable = match connect_session(&events_tx, watcher) {
+                Ok(Some((connection, shortcuts))) => {
+                    return run_session(connection, shortcuts, commands_rx);
+                }
+                Ok(None) => "it doesn't offer global shortcuts".to_owned(),
+                Err(err) => err.to_string(),
+            };
+
+            let _ = events_tx.send(PortalEvent::Unavailable(unavailable));
+        });
+
+        Ok(Self { commands })
+    }
+
+    ///
+    /// Replace the bound shortcuts with `shortcuts`.
+    ///
+    pub fn bind(&self, shortcuts: Vec<ShortcutSpec>) {
+        let _ = self.commands.send(Command::Bind(shortcuts));
+    }
+}
+
+///
+/// Connect to the portal and forward its activations, or `None`
+/// if it doesn't offer global shortcuts.
+///
+fn connect_session(
+    events_tx: &channel::Sender<PortalEvent>,
+    commands: Sender<Command>,
+) -> Result<Option<(Connection, Proxy<'static>)>, zbus::Error> {
+    let connection = Connection::session()?;
+    let shortcuts = Proxy::new(&connection, PORTAL_NAME, PORTAL_PATH, SHORTCUTS_INTERFACE)?;
+
+    if let Err(err) = shortcuts.get_property::<u32>("version") {
+        log::info!("Global shortcuts portal unavailable ({err}).");
+        return Ok(None);
+    }
+
+    for (signal, state) in [
+        ("Activated", ShortcutState::Pressed),
+        ("Deactivated", ShortcutState::Released),
+    ] {
+        let signals = shortcuts.receive_signal(signal)?;
+        let events_tx = events_tx.clone();
+
+        spawn(move || {
+            for message in signals {
+                let Ok((_, id, timestamp, _)) = message.body().deserialize::<Activation>() else {
+                    continue;
+                };
+
+                let event = ShortcutEvent {
+                    id,
+                    state,
+                    timestamp: Duration::from_millis(timestamp),
+                };
+
+                if events_tx.send(PortalEvent::Shortcut(event)).is_err() {
+                    // Event loop is gone.
+                    break;
+                }
+            }
+        });
+    }
+
+    watch_owner(&connection, commands)?;
+
+    Ok(Some((connection, shortcuts)))
+}
+
+///
+/// Rebind everything whenever the portal comes back after going away.
+///
+fn watch_owner(connection: &Connection, commands: Sender<Command>) -> Result<(), zbus::Error> {
+    let bus = Proxy::new(
+        connection,
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+    )?;
+    let changes = bus.receive_signal_with_args("NameOwnerChanged", &[(0, PORTAL_NAME)])?;
+
+    spawn(move || {
+        let _bus = bus;
+
+        for message in changes {
+            let Ok((_, _, new_owner)) = message.body().deserialize::<(String, String, String)>()
+            else {
+                continue;
+            };
+
+            if !new_owner.is_empty() && commands.send(Command::Rebind).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn run_session(connection: Connection, shortcuts: Proxy<'static>, commands: Receiver<Command>) {
+    let mut bound = Vec::new();
+    let mut session = None;
+
+    for command in commands {
+        match command {
+            Command::Bind(specs) => bound = specs,
+            // The old session went with the old portal.
+            Command::Rebind => session = None,
+        }
+
+        if let Some(session) = session.take() {
+            close_session(&connection, session);
+        }
+
+        if bound.is_empty() {
+            continue;
+        }
+
+        match bind_session(&connection, &shortcuts, &bound) {
+            Ok(bound_session) => session = Some(bound_session),
+            Err(err) => log::warn!("Could not bind global shortcuts: {err}"),
+        }
+    }
+}
+
+fn bind_session(
+    connection: &Connection,
+    shortcuts: &Proxy<'static>,
+    specs: &[ShortcutSpec],
+) -> Result<OwnedObjectPath, zbus::Error> {
+    let session_token = token("session");
+    let request_token = token("create");
+
+    let results = request(connection, &request_token, || {
+        let options = HashMap::from([
+            ("handle_token", Value::from(request_token.as_str())),
+            ("session_handle_token", Value::from(session_token.as_str())),
+        ]);
+
+        shortcuts.call("CreateSession", &(options,))
+    })?;
+
+    let session = results
+        .get("session_handle")
+        .and_then(|handle| String::try_from(handle.try_clone().ok()?).ok())
+        .and_then(|handle| OwnedObjectPath::try_from(handle).ok())
+        .ok_or_else(|| zbus::Error::Failure("The portal returned no session.".into()))?;
+
+    let specs = specs
+        .iter()
+        .map(|spec| {
+            let mut properties = HashMap::from([("description", Value::from(&*spec.description))]);
+            if let Some(trigger) = &spec.preferred_trigger {
+                properties.insert("preferred_trigger", Value::from(trigger.as_str()));
+            }
+
+            (spec.id.as_str(), properties)
+        })
+        .collect::<Vec<_>>();
+
+    let request_token = token("bind");
+    request(connection, &request_token, || {
+        let options = HashMap::from([("handle_token", Value::from(request_token.as_str()))]);
+        shortcuts.call("BindShortcuts", &(&session, specs, "", options))
+    })?;
+
+    Ok(session)
+}
+
+fn close_session(connection: &Connection, session: OwnedObjectPath) {
+    let result = Proxy::new(
+        connection,
+        PORTAL_NAME,
+        session,
+        "org.freedesktop.portal.Session",
+    )
+    .and_then(|session| session.call::<_, _, ()>("Close", &()));
+
+    if let Err(err) = result {
+        log::debug!("Could not close global shortcuts session: {err}");
+    }
+}
+
+///
+/// Make a portal request through `call`, and wait for its response.
+///
+fn request(
+    connection: &Connection,
+    token: &str,
+    call: impl FnOnce() -> Result<OwnedObjectPath, zbus::Error>,
+) -> Result<HashMap<String, OwnedValue>, zbus::Error> {
+    let sender = connection
+        .unique_name()
+        .map(|name| name.trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default();
+
+    // Listen before calling, so the response can't be missed.
+    let request = Proxy::new(
+        connection,
+        PORTAL_NAME,
+        format!("{PORTAL_PATH}/request/{sender}/{token}"),
+        "org.freedesktop.portal.Request",
+    )?;
+    let mut responses = request.receive_signal("Response")?;
+
+    call()?;
+
+    let message = responses
+        .next()
+        .ok_or_else(|| zbus::Error::Failure("The portal never responded.".into()))?;
+    let (response, results) = message
+        .body()
+        .deserialize::<(u32, HashMap<String, OwnedValue>)>()?;
+
+    match response {
+        0 => Ok(results),
+        1 => Err(zbus::Error::Failure("Cancelled by the user.".into())),
+        _ => Err(zbus::Error::Failure("The portal request failed.".into())),
+    }
+}
+
+fn token(kind: &str) -> String {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+
+    format!("avy_{kind}_{}", NEXT.fetch_add(1, Ordering::Relaxed))
+}
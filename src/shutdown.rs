@@ -0,0 +1,92 @@
+//!
+//! Tearing the client down in an order neither the GPU nor the compositor
+//! trips over: rendering stopped, in-flight frames finished, surfaces
+//! destroyed children first, then everything flushed out to the compositor.
+//!
+
+use smithay_client_toolkit::reexports::client::{backend::WaylandError, Connection, Proxy};
+use wayland_backend::client::ObjectId;
+
+use crate::{debug, wayland::surface::SurfaceKind, AvyClient};
+
+impl AvyClient {
+    ///
+    /// Shut the client down cleanly, before it (and the graphics backends its
+    /// surfaces were made with) are dropped.
+    ///
+    /// Takes the connection rather than the event queue, as by then the
+    /// queue usually lives in the event loop's Wayland source.
+    ///
+    pub fn shutdown(&mut self, connection: &Connection) -> Result<(), WaylandError> {
+        self.teardown();
+        connection.roundtrip()?;
+
+        Ok(())
+    }
+
+    ///
+    /// Tear the client down once the connection is lost, like [AvyClient::shutdown]
+    /// without waiting on a compositor which is no longer there.
+    ///
+    pub fn abandon(&mut self) {
+        self.teardown();
+    }
+
+    fn teardown(&mut self) {
+        self.running = false;
+        self.shut_down = true;
+
+        // Stop the frame scheduler, so nothing gets drawn whilst tearing down.
+        self.render_groups.clear();
+        self.surface_groups.clear();
+
+        for (id, backend) in &self.surface_backends {
+            if backend.lock().unwrap().finish().is_err() {
+                log::warn!("Could not finish the in-flight frames of {id}.");
+            }
+        }
+
+        for id in self.destruction_order() {
+            if let Some(surface) = self.surfaces.get_mut(&id) {
+                let viewport = surface.viewport();
+                viewport.destroy();
+                debug::objects::destroyed(&viewport.id());
+            }
+
+            self.destroy_surface(&id);
+        }
+
+        self.set_cursor_state(Default::default());
+
+        #[cfg(feature = "object-tracker")]
+        self.assert_no_orphans();
+    }
+
+    ///
+    /// Every surface, with those drawn as part of others (subsurfaces) first.
+    ///
+    fn destruction_order(&self) -> Vec<ObjectId> {
+        let (mut order, rest): (Vec<_>, Vec<_>) = self
+            .surfaces
+            .iter()
+            .partition(|(_, surface)| surface.kind() == SurfaceKind::Subsurface);
+
+        order.extend(rest);
+        order.into_iter().map(|(id, _)| id.clone()).collect()
+    }
+}
+
+impl Drop for AvyClient {
+    fn drop(&mut self) {
+        if self.shut_down {
+            return;
+        }
+
+        log::warn!("AvyClient dropped without calling shutdown, tearing down as best as possible.");
+        self.teardown();
+
+        if let Some(backend) = self.wl_display.backend().upgrade() {
+            let _ = backend.flush();
+        }
+    }
+}
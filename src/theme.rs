@@ -0,0 +1,110 @@
+//!
+//! How surfaces are drawn for the desktop: its color scheme and accent, and
+//! the accessibility options colors are adjusted by, see
+//! [crate::graphics::RenderContext].
+//!
+//! With the `portal` feature, these follow the XDG settings portal (see the
+//! `settings` module). Otherwise, they're guessed from the environment.
+//!
+
+use skia_safe::Color4f;
+
+use crate::graphics::color::Palette;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ColorScheme {
+    #[default]
+    NoPreference,
+    Dark,
+    Light,
+}
+
+impl ColorScheme {
+    ///
+    /// Convert from the `org.freedesktop.appearance color-scheme` value.
+    ///
+    pub fn from_portal(value: u32) -> Self {
+        match value {
+            1 => Self::Dark,
+            2 => Self::Light,
+            _ => Self::NoPreference,
+        }
+    }
+
+    pub fn is_dark(&self) -> bool {
+        matches!(self, Self::Dark)
+    }
+}
+
+///
+/// Where an [Appearance] was read from.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AppearanceSource {
+    #[default]
+    Default,
+    Environment,
+    Portal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Appearance {
+    pub color_scheme: ColorScheme,
+    pub accent: Option<Color4f>,
+    pub source: AppearanceSource,
+}
+
+impl Appearance {
+    ///
+    /// Best-effort guess from `GTK_THEME` (e.g. `Adwaita:dark`).
+    ///
+    pub fn from_env() -> Self {
+        let Ok(theme) = std::env::var("GTK_THEME") else {
+            return Self::default();
+        };
+
+        let theme = theme.to_lowercase();
+        let color_scheme = if theme.ends_with(":dark") || theme.ends_with("-dark") {
+            ColorScheme::Dark
+        } else if theme.ends_with(":light") || theme.ends_with("-light") {
+            ColorScheme::Light
+        } else {
+            ColorScheme::NoPreference
+        };
+
+        Self {
+            color_scheme,
+            accent: None,
+            source: AppearanceSource::Environment,
+        }
+    }
+}
+
+///
+/// WCAG AA's minimum contrast ratio for body text.
+///
+pub const DEFAULT_MIN_CONTRAST_RATIO: f32 = 4.5;
+
+///
+/// How colors are adjusted for people who need more contrast,
+/// see [crate::graphics::color::ColorResolver].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccessibilityOptions {
+    pub high_contrast: bool,
+    /// Contrast ratio (from 1 to 21) foreground colors are
+    /// pushed to against their background in high-contrast mode.
+    pub min_contrast_ratio: f32,
+    /// Colors to use instead of the app's own, in high-contrast mode.
+    pub forced_palette: Option<Palette>,
+}
+
+impl Default for AccessibilityOptions {
+    fn default() -> Self {
+        Self {
+            high_contrast: false,
+            min_contrast_ratio: DEFAULT_MIN_CONTRAST_RATIO,
+            forced_palette: None,
+        }
+    }
+}
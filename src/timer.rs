@@ -0,0 +1,452 @@
+//!
+//! Deferred work on the event loop.
+//!
+//! Every timer shares one event loop timer, armed for whichever is due
+//! first, so that the order they run in is decided here (see [TimerQueue]).
+//!
+//! Deadlines are read off the client's clock (see [AvyClient::set_clock]),
+//! so timers wait for a [crate::util::TestClock] to be advanced past them.
+//!
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::reexports::calloop::{
+    timer::{TimeoutAction, Timer},
+    LoopHandle, RegistrationToken,
+};
+use thiserror::Error;
+
+use crate::AvyClient;
+
+type TimerCallback = Box<dyn FnMut(&mut AvyClient) -> TimerAction>;
+
+///
+/// What to do with a timer once its callback has run.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerAction {
+    /// Run the callback again after this long.
+    Repeat(Duration),
+    /// Don't run the callback again.
+    Drop,
+}
+
+///
+/// Identifies a timer, for cancelling it with [AvyClient::cancel_timer].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerToken(u64);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No event loop to run timers on, see AvyClient::set_loop_handle.")]
+    NoEventLoop,
+
+    #[error("Could not insert the timer into the event loop.")]
+    Insert,
+}
+
+///
+/// Timers waiting to run, earliest first, and those added
+/// at the same deadline in the order they were added.
+///
+pub(crate) struct TimerQueue<C> {
+    next_token: u64,
+    /// By deadline, then token (which only grows), to keep ties in order.
+    pending: BTreeMap<(Instant, u64), C>,
+    deadlines: HashMap<u64, Instant>,
+    /// The timer whose callback is running, and whether it was cancelled meanwhile.
+    running: Option<(u64, bool)>,
+}
+
+impl<C> TimerQueue<C> {
+    pub fn new() -> Self {
+        Self {
+            next_token: 0,
+            pending: BTreeMap::new(),
+            deadlines: HashMap::new(),
+            running: None,
+        }
+    }
+
+    pub fn insert(&mut self, deadline: Instant, callback: C) -> TimerToken {
+        let token = self.next_token;
+        self.next_token += 1;
+
+        self.pending.insert((deadline, token), callback);
+        self.deadlines.insert(token, deadline);
+
+        TimerToken(token)
+    }
+
+    ///
+    /// Stop `token`'s timer from running again, including after a
+    /// callback which is running now. Returns whether it was waiting.
+    ///
+    pub fn cancel(&mut self, token: TimerToken) -> bool {
+        if let Some(deadline) = self.deadlines.remove(&token.0) {
+            self.pending.remove(&(deadline, token.0));
+            return true;
+        }
+
+        match &mut self.running {
+            Some((running, cancelled)) if *running == token.0 => {
+                *cancelled = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.keys().next().map(|&(deadline, _)| deadline)
+    }
+
+    ///
+    /// The timers due by `now`, in the order to run them.
+    ///
+    /// Run each with [TimerQueue::start] and [TimerQueue::finish]: one
+    /// cancelled by an earlier one's callback doesn't start. Timers added or
+    /// repeated meanwhile wait for the next call, even if they're due.
+    ///
+    pub fn due(&self, now: Instant) -> Vec<TimerToken> {
+        self.pending
+            .range(..=(now, u64::MAX))
+            .map(|(&(_, token), _)| TimerToken(token))
+            .collect()
+    }
+
+    pub fn start(&mut self, token: TimerToken) -> Option<C> {
+        let deadline = self.deadlines.remove(&token.0)?;
+        self.running = Some((token.0, false));
+
+        self.pending.remove(&(deadline, token.0))
+    }
+
+    ///
+    /// Put the running timer back, to run `again`, unless
+    /// that's `None` or it was cancelled whilst running.
+    ///
+    pub fn finish(&mut self, callback: C, again: Option<Instant>) {
+        let Some((token, cancelled)) = self.running.take() else {
+            return;
+        };
+
+        if let (Some(deadline), false) = (again, cancelled) {
+            self.pending.insert((deadline, token), callback);
+            self.deadlines.insert(token, deadline);
+        }
+    }
+}
+
+///
+/// The client's timers, and the event loop timer waking it for the next one.
+///
+pub(crate) struct Timers {
+    queue: RefCell<TimerQueue<TimerCallback>>,
+    /// Armed for this deadline.
+    wakeup: Cell<Option<(RegistrationToken, Instant)>>,
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self {
+            queue: RefCell::new(TimerQueue::new()),
+            wakeup: Cell::new(None),
+        }
+    }
+}
+
+impl AvyClient {
+    ///
+    /// Give the client access to the event loop it's dispatched from,
+    /// which is needed for timers, [crate::proxy::AvyProxy] invocations (and
+    /// [crate::debug::DUMP_ON_SIGUSR1_ENV] and [crate::metrics::METRICS_ON_SIGUSR2_ENV]),
+    /// as well as some integrations (e.g. Hyprland's workspaces).
+    ///
+    pub fn set_loop_handle(&mut self, handle: LoopHandle<'static, AvyClient>) {
+        Self::install_dump_signal(&handle);
+        Self::install_metrics_signal(&handle);
+        self.start_proxy_queue(&handle);
+        self.loop_handle.replace(handle);
+        self.start_idle_trim();
+
+        #[cfg(feature = "workspaces")]
+        self.start_workspace_fallback();
+    }
+
+    pub fn loop_handle(&self) -> Option<&LoopHandle<'static, AvyClient>> {
+        self.loop_handle.as_ref()
+    }
+
+    ///
+    /// Run `callback` on the event loop after `after` has elapsed.
+    ///
+    /// Timers due at the same time run in the order they were added.
+    ///
+    pub fn add_timer(
+        &self,
+        after: Duration,
+        callback: impl FnMut(&mut AvyClient) -> TimerAction + 'static,
+    ) -> Result<TimerToken, Error> {
+        if self.loop_handle.is_none() {
+            return Err(Error::NoEventLoop);
+        }
+
+        let deadline = self.clock.now() + after;
+        let token = self
+            .timers
+            .queue
+            .borrow_mut()
+            .insert(deadline, Box::new(callback));
+
+        if let Err(err) = self.arm_timer_wakeup() {
+            self.timers.queue.borrow_mut().cancel(token);
+            return Err(err);
+        }
+
+        Ok(token)
+    }
+
+    ///
+    /// Stop a timer from running (again).
+    ///
+    /// Cancelling a timer which already finished does nothing.
+    ///
+    pub fn cancel_timer(&self, token: TimerToken) {
+        if !self.timers.queue.borrow_mut().cancel(token) {
+            return;
+        }
+
+        if let Err(err) = self.arm_timer_wakeup() {
+            log::warn!("Could not wake up for the next timer: {err}");
+        }
+    }
+
+    ///
+    /// Run the timers which came due as the clock jumped ahead (e.g. as a
+    /// [crate::util::TestClock] was advanced), rather than once the event loop
+    /// wakes up for them, and wake up for the next one as far off as it is now.
+    ///
+    pub fn catch_up_timers(&mut self) {
+        self.disarm_timer_wakeup();
+        self.run_due_timers();
+
+        if let Err(err) = self.arm_timer_wakeup() {
+            log::warn!("Could not wake up for the next timer: {err}");
+        }
+    }
+
+    ///
+    /// Run the callbacks of every timer which is due.
+    ///
+    fn run_due_timers(&mut self) {
+        let due = self.timers.queue.borrow().due(self.clock.now());
+
+        for token in due {
+            // The queue isn't borrowed whilst the callback runs, so it can add
+            // and cancel timers (including its own).
+            let Some(mut callback) = self.timers.queue.borrow_mut().start(token) else {
+                continue;
+            };
+
+            let again = match callback(self) {
+                TimerAction::Repeat(after) => Some(self.clock.now() + after),
+                TimerAction::Drop => None,
+            };
+
+            self.timers.queue.borrow_mut().finish(callback, again);
+        }
+    }
+
+    ///
+    /// Make sure the event loop wakes up for the earliest timer, and only then.
+    ///
+    pub(crate) fn arm_timer_wakeup(&self) -> Result<(), Error> {
+        let next = self.timers.queue.borrow().next_deadline();
+        let armed = self.timers.wakeup.get();
+
+        if armed.map(|(_, deadline)| deadline) == next {
+            return Ok(());
+        }
+
+        let handle = self.loop_handle.as_ref().ok_or(Error::NoEventLoop)?;
+        self.disarm_timer_wakeup();
+
+        let Some(deadline) = next else {
+            return Ok(());
+        };
+
+        // However far off it is on the client's clock: a clock which doesn't
+        // keep up with the system's (like a test's) is only checked again then.
+        let delay = deadline.saturating_duration_since(self.clock.now());
+        let registration = handle
+            .insert_source(Timer::from_duration(delay), |_, _, app| {
+                app.timers.wakeup.set(None);
+                app.run_due_timers();
+
+                if let Err(err) = app.arm_timer_wakeup() {
+                    log::warn!("Could not wake up for the next timer: {err}");
+                }
+
+                TimeoutAction::Drop
+            })
+            .map_err(|_| Error::Insert)?;
+
+        self.timers.wakeup.set(Some((registration, deadline)));
+        Ok(())
+    }
+
+    ///
+    /// Stop the event loop waking up for the earliest timer, e.g. to wake up
+    /// for it after a different delay, now that the clock has changed.
+    ///
+    pub(crate) fn disarm_timer_wakeup(&self) {
+        let Some((registration, _)) = self.timers.wakeup.take() else {
+            return;
+        };
+
+        if let Some(handle) = &self.loop_handle {
+            handle.remove(registration);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::{SharedClock, TestClock};
+
+    use super::*;
+
+    /// Run every due timer's callback, which returns when to run it again.
+    fn run_due(
+        queue: &mut TimerQueue<&'static str>,
+        now: Instant,
+        again: impl Fn(&str) -> Option<Instant>,
+    ) -> Vec<&'static str> {
+        let mut ran = Vec::new();
+
+        for token in queue.due(now) {
+            let Some(name) = queue.start(token) else {
+                continue;
+            };
+
+            ran.push(name);
+            queue.finish(name, again(name));
+        }
+
+        ran
+    }
+
+    #[test]
+    fn runs_in_deadline_order() {
+        let start = Instant::now();
+        let mut queue = TimerQueue::new();
+        queue.insert(start + Duration::from_millis(20), "late");
+        queue.insert(start + Duration::from_millis(10), "early");
+        queue.insert(start + Duration::from_millis(30), "not yet");
+
+        assert_eq!(
+            queue.next_deadline(),
+            Some(start + Duration::from_millis(10))
+        );
+        assert_eq!(
+            run_due(&mut queue, start + Duration::from_millis(25), |_| None),
+            ["early", "late"]
+        );
+        assert_eq!(
+            queue.next_deadline(),
+            Some(start + Duration::from_millis(30))
+        );
+    }
+
+    #[test]
+    fn ties_run_in_the_order_added() {
+        let start = Instant::now();
+        let mut queue = TimerQueue::new();
+        for name in ["a", "b", "c"] {
+            queue.insert(start, name);
+        }
+
+        assert_eq!(run_due(&mut queue, start, |_| None), ["a", "b", "c"]);
+        assert_eq!(queue.next_deadline(), None);
+    }
+
+    #[test]
+    fn cancelled_timers_dont_run() {
+        let start = Instant::now();
+        let mut queue = TimerQueue::new();
+        let first = queue.insert(start, "first");
+        queue.insert(start, "second");
+
+        assert!(queue.cancel(first));
+        assert!(!queue.cancel(first));
+        assert_eq!(run_due(&mut queue, start, |_| None), ["second"]);
+    }
+
+    #[test]
+    fn a_timer_cancelled_by_an_earlier_callback_doesnt_start() {
+        let start = Instant::now();
+        let mut queue = TimerQueue::new();
+        let first = queue.insert(start, "first");
+        let second = queue.insert(start, "second");
+
+        let due = queue.due(start);
+        assert_eq!(due, [first, second]);
+
+        let name = queue.start(first).unwrap();
+        queue.cancel(second);
+        queue.finish(name, None);
+
+        assert!(queue.start(second).is_none());
+    }
+
+    #[test]
+    fn repeats_until_cancelled_whilst_running() {
+        let start = Instant::now();
+        let later = start + Duration::from_millis(16);
+        let mut queue = TimerQueue::new();
+        let token = queue.insert(start, "tick");
+
+        assert_eq!(run_due(&mut queue, start, |_| Some(later)), ["tick"]);
+        assert_eq!(queue.next_deadline(), Some(later));
+
+        let name = queue.start(token).unwrap();
+        assert!(queue.cancel(token));
+        queue.finish(name, Some(later));
+
+        assert_eq!(queue.next_deadline(), None);
+    }
+
+    #[test]
+    fn timers_wait_for_a_test_clock() {
+        let time = TestClock::new();
+        let clock = SharedClock::new(time.clone());
+        let mut queue = TimerQueue::new();
+        queue.insert(clock.now() + Duration::from_millis(10), "soon");
+        queue.insert(clock.now() + Duration::from_secs(60), "later");
+
+        // However long the test actually takes.
+        assert!(run_due(&mut queue, clock.now(), |_| None).is_empty());
+
+        time.advance(Duration::from_millis(10));
+        assert_eq!(run_due(&mut queue, clock.now(), |_| None), ["soon"]);
+
+        time.advance(Duration::from_secs(60));
+        assert_eq!(run_due(&mut queue, clock.now(), |_| None), ["later"]);
+    }
+
+    #[test]
+    fn repeating_now_waits_for_the_next_round() {
+        let start = Instant::now();
+        let mut queue = TimerQueue::new();
+        queue.insert(start, "busy");
+
+        assert_eq!(run_due(&mut queue, start, |_| Some(start)), ["busy"]);
+        assert_eq!(run_due(&mut queue, start, |_| None), ["busy"]);
+    }
+}
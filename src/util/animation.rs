@@ -0,0 +1,219 @@
+//!
+//! Time-based animation helpers.
+//!
+
+use std::time::{Duration, Instant};
+
+use super::SharedClock;
+
+///
+/// Easing curve applied to an animation's linear progress.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    ///
+    /// Map linear progress `t` (in `0.0..=1.0`) onto this curve.
+    ///
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+///
+/// A single run of an animation, from its start time over a set duration.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Animation {
+    start: Instant,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Animation {
+    ///
+    /// An animation starting at `clock`'s time now.
+    ///
+    pub fn new(clock: &SharedClock, duration: Duration, easing: Easing) -> Self {
+        Self::starting_at(clock.now(), duration, easing)
+    }
+
+    pub fn starting_at(start: Instant, duration: Duration, easing: Easing) -> Self {
+        Self {
+            start,
+            duration,
+            easing,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    ///
+    /// Eased progress at `now`, in `0.0..=1.0`.
+    ///
+    pub fn progress(&self, now: Instant) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+
+        let elapsed = now.saturating_duration_since(self.start);
+        self.easing
+            .apply(elapsed.as_secs_f64() / self.duration.as_secs_f64())
+    }
+
+    pub fn is_finished(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= self.duration
+    }
+}
+
+///
+/// Linear interpolation between two values.
+///
+pub trait Lerp {
+    fn lerp(&self, to: &Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, to: &Self, t: f64) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, to: &Self, t: f64) -> Self {
+        self + (to - self) * t as f32
+    }
+}
+
+impl Lerp for i32 {
+    fn lerp(&self, to: &Self, t: f64) -> Self {
+        (*self as f64).lerp(&(*to as f64), t).round() as i32
+    }
+}
+
+///
+/// A value which eases towards whatever it's last set to, rather than jumping.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Transition {
+    from: f64,
+    to: f64,
+    duration: Duration,
+    easing: Easing,
+    animation: Option<Animation>,
+}
+
+impl Transition {
+    pub fn new(value: f64, duration: Duration, easing: Easing) -> Self {
+        Self {
+            from: value,
+            to: value,
+            duration,
+            easing,
+            animation: None,
+        }
+    }
+
+    ///
+    /// Head for `target`, from wherever the value is at `now`.
+    ///
+    pub fn set(&mut self, target: f64, now: Instant) {
+        if target == self.to {
+            return;
+        }
+
+        self.from = self.value(now);
+        self.to = target;
+        self.animation
+            .replace(Animation::starting_at(now, self.duration, self.easing));
+    }
+
+    ///
+    /// Jump straight to `value`.
+    ///
+    pub fn jump(&mut self, value: f64) {
+        self.from = value;
+        self.to = value;
+        self.animation = None;
+    }
+
+    pub fn target(&self) -> f64 {
+        self.to
+    }
+
+    pub fn value(&self, now: Instant) -> f64 {
+        match &self.animation {
+            Some(animation) => self.from.lerp(&self.to, animation.progress(now)),
+            None => self.to,
+        }
+    }
+
+    pub fn is_animating(&self, now: Instant) -> bool {
+        self.animation
+            .is_some_and(|animation| !animation.is_finished(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::TestClock;
+
+    use super::*;
+
+    #[test]
+    fn animations_follow_the_clock() {
+        let time = TestClock::new();
+        let clock = SharedClock::new(time.clone());
+        let animation = Animation::new(&clock, Duration::from_millis(200), Easing::Linear);
+
+        assert_eq!(animation.progress(clock.now()), 0.0);
+
+        time.advance(Duration::from_millis(50));
+        assert_eq!(animation.progress(clock.now()), 0.25);
+        assert!(!animation.is_finished(clock.now()));
+
+        time.advance(Duration::from_millis(150));
+        assert_eq!(animation.progress(clock.now()), 1.0);
+        assert!(animation.is_finished(clock.now()));
+    }
+
+    #[test]
+    fn transitions_head_on_from_where_they_are() {
+        let time = TestClock::new();
+        let clock = SharedClock::new(time.clone());
+        let mut transition = Transition::new(0.0, Duration::from_millis(100), Easing::Linear);
+
+        transition.set(1.0, clock.now());
+        time.advance(Duration::from_millis(50));
+        assert_eq!(transition.value(clock.now()), 0.5);
+
+        // Back to where it started, from half-way.
+        transition.set(0.0, clock.now());
+        assert_eq!(transition.value(clock.now()), 0.5);
+
+        time.advance(Duration::from_millis(100));
+        assert_eq!(transition.value(clock.now()), 0.0);
+        assert!(!transition.is_animating(clock.now()));
+    }
+}
@@ -0,0 +1,182 @@
+//!
+//! Where the crate's time-based behavior (animations, timers, render group
+//! ticks and pacing, auto-suspend and idle trimming, rate-limited warnings,
+//! reconnection's backoff) reads the time from, see [Clock]. So do the
+//! timestamps it hands out (frame timings, render incidents and snapshots,
+//! virtual keyboard keys) and its debugging aids: startup timing, traces,
+//! and the windows metrics are rated over.
+//!
+//! The system's clock is only read for times compared against the
+//! compositor's own: presentation timestamps, and input timestamps with
+//! the `input-latency` feature.
+//!
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+///
+/// A monotonic source of time.
+///
+/// [SystemClock] is the real thing, and [TestClock] only moves when told to,
+/// so that tests of animations or timeouts are instant and deterministic.
+/// This is also the extension point for time taken from elsewhere, e.g.
+/// timestamps the compositor provides (presentation times, input events),
+/// for animations to line up with what's actually shown.
+///
+/// Install one with [crate::AvyClient::set_clock].
+///
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    ///
+    /// Nanoseconds since a point fixed for the clock's lifetime (its
+    /// origin), which never go backwards.
+    ///
+    fn monotonic_nanos(&self) -> u64;
+
+    ///
+    /// Wait until `duration` has passed, as far as the clock's concerned.
+    ///
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+///
+/// Origin of every [SystemClock], so that they agree on [Clock::monotonic_nanos].
+///
+static SYSTEM_ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+///
+/// [Instant::now], i.e. the system's monotonic clock.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn monotonic_nanos(&self) -> u64 {
+        let origin = *SYSTEM_ORIGIN.get_or_init(Instant::now);
+        Instant::now().saturating_duration_since(origin).as_nanos() as u64
+    }
+}
+
+///
+/// A clock which stands still until [TestClock::advance]d.
+///
+/// Clones share the time, so a test can keep one to advance whilst
+/// the client reads another.
+///
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    origin: Instant,
+    elapsed: Arc<AtomicU64>,
+}
+
+impl TestClock {
+    ///
+    /// A clock standing at the system's time now.
+    ///
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            elapsed: Arc::default(),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.elapsed
+            .fetch_add(by.as_nanos() as u64, Ordering::AcqRel);
+    }
+
+    ///
+    /// Time since the clock was made, as far as it's concerned.
+    ///
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed.load(Ordering::Acquire))
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+
+    fn monotonic_nanos(&self) -> u64 {
+        self.elapsed.load(Ordering::Acquire)
+    }
+
+    ///
+    /// Doesn't wait, but advances the clock by `duration`.
+    ///
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+///
+/// The client's clock, shared by its surfaces, render contexts and
+/// widgets. Replacing it (see [crate::AvyClient::set_clock]) replaces it
+/// for every clone.
+///
+#[derive(Clone)]
+pub struct SharedClock(Arc<RwLock<Arc<dyn Clock>>>);
+
+impl SharedClock {
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(clock))))
+    }
+
+    pub fn now(&self) -> Instant {
+        self.0.read().unwrap().now()
+    }
+
+    pub fn monotonic_nanos(&self) -> u64 {
+        self.0.read().unwrap().monotonic_nanos()
+    }
+
+    pub fn sleep(&self, duration: Duration) {
+        // Without holding the lock, which would keep the clock from being replaced meanwhile.
+        let clock = self.0.read().unwrap().clone();
+        clock.sleep(duration);
+    }
+
+    pub(crate) fn replace(&self, clock: impl Clock + 'static) {
+        *self.0.write().unwrap() = Arc::new(clock);
+    }
+
+    ///
+    /// Read the time from whatever `other` reads it from, from now on.
+    ///
+    pub(crate) fn adopt(&self, other: &SharedClock) {
+        let clock = other.0.read().unwrap().clone();
+        *self.0.write().unwrap() = clock;
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedClock").field(&self.now()).finish()
+    }
+}
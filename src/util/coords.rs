@@ -0,0 +1,787 @@
+//!
+//! Points in each of the spaces positions come in, so that converting
+//! between them happens in one place rather than ad hoc:
+//!
+//! * [GlobalPoint]: the compositor's layout of all outputs, in logical pixels
+//!   (as outputs report their position in).
+//! * [SurfacePoint]: logical pixels from a surface's top-left corner (as
+//!   pointer and touch events are in, and as hit regions are laid out).
+//! * [BufferPoint]: physical pixels from the top-left of a surface's buffer
+//!   (as drawn into, before the canvas is scaled).
+//! * [DrawingPoint]: logical pixels as drawn, before the canvas is turned by
+//!   the surface's [super::Transform] (see [SizeSnapshot::apply_to_canvas]). The same
+//!   as a [SurfacePoint] unless the surface is turned.
+//!
+
+use smithay_client_toolkit::{output::OutputInfo, shell::wlr_layer::Anchor};
+
+use super::{Insets, SizeSnapshot};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GlobalPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SurfacePoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BufferPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DrawingPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+macro_rules! impl_point {
+    ($point: ident) => {
+        impl $point {
+            pub const fn new(x: f64, y: f64) -> Self {
+                Self { x, y }
+            }
+
+            ///
+            /// How far this is from `other`, along each axis.
+            ///
+            pub fn offset_from(self, other: Self) -> (f64, f64) {
+                (self.x - other.x, self.y - other.y)
+            }
+        }
+
+        impl From<(f64, f64)> for $point {
+            fn from((x, y): (f64, f64)) -> Self {
+                Self { x, y }
+            }
+        }
+
+        impl From<$point> for (f64, f64) {
+            fn from(point: $point) -> Self {
+                (point.x, point.y)
+            }
+        }
+    };
+}
+
+impl_point!(GlobalPoint);
+impl_point!(SurfacePoint);
+impl_point!(BufferPoint);
+impl_point!(DrawingPoint);
+
+///
+/// Drawing is laid out in the same logical pixels as pointer and touch
+/// events, so these hit test it as they are (e.g. with
+/// [crate::graphics::strip::StripPlacement::hit_test]), unless the surface
+/// is turned, see [SurfacePoint::to_drawing_space].
+///
+impl From<SurfacePoint> for skia_safe::Point {
+    fn from(point: SurfacePoint) -> Self {
+        Self::new(point.x as f32, point.y as f32)
+    }
+}
+
+impl From<DrawingPoint> for skia_safe::Point {
+    fn from(point: DrawingPoint) -> Self {
+        Self::new(point.x as f32, point.y as f32)
+    }
+}
+
+///
+/// A rectangle in the global space, in logical pixels.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GlobalRect {
+    pub origin: GlobalPoint,
+    pub size: (f64, f64),
+}
+
+impl GlobalRect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self {
+            origin: GlobalPoint::new(x, y),
+            size: (width, height),
+        }
+    }
+
+    ///
+    /// Whether `point` is inside, counting the top and left edges but not the others.
+    ///
+    pub fn contains(&self, point: GlobalPoint) -> bool {
+        let (x, y) = point.offset_from(self.origin);
+        (0.0..self.size.0).contains(&x) && (0.0..self.size.1).contains(&y)
+    }
+
+    ///
+    /// This rect moved (along each axis) as little as it takes to fit in
+    /// `bounds`, or against their start and cut to size if it's too big.
+    ///
+    pub fn constrain(self, bounds: GlobalRect) -> Self {
+        let axis = |start: f64, length: f64, bounds_start: f64, bounds_length: f64| {
+            let length = length.min(bounds_length);
+            let start = start.min(bounds_start + bounds_length - length);
+            (start.max(bounds_start), length)
+        };
+
+        let (x, width) = axis(self.origin.x, self.size.0, bounds.origin.x, bounds.size.0);
+        let (y, height) = axis(self.origin.y, self.size.1, bounds.origin.y, bounds.size.1);
+        Self::new(x, y, width, height)
+    }
+}
+
+///
+/// Where an output is in the global space, see [OutputGeometry::from_info].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputGeometry {
+    /// Top-left corner, in logical pixels.
+    pub position: (i32, i32),
+    /// In logical pixels.
+    pub size: (i32, i32),
+}
+
+impl OutputGeometry {
+    ///
+    /// `None` until the compositor has sent the output's logical geometry.
+    ///
+    pub fn from_info(info: &OutputInfo) -> Option<Self> {
+        Some(Self {
+            position: info.logical_position?,
+            size: info.logical_size?,
+        })
+    }
+
+    pub fn rect(&self) -> GlobalRect {
+        GlobalRect::new(
+            self.position.0 as f64,
+            self.position.1 as f64,
+            self.size.0 as f64,
+            self.size.1 as f64,
+        )
+    }
+
+    pub fn contains(&self, point: GlobalPoint) -> bool {
+        self.rect().contains(point)
+    }
+
+    ///
+    /// Where a layer surface of `size` (in logical pixels, as configured) is put
+    /// on this output, anchored to `anchor` and `margin` away from those edges.
+    ///
+    /// Doesn't account for space other surfaces' exclusive zones take.
+    ///
+    pub fn layer_rect(&self, anchor: Anchor, margin: Insets, size: (u32, u32)) -> GlobalRect {
+        let (x, width) = place_on_axis(
+            (self.position.0, self.size.0),
+            anchor.contains(Anchor::LEFT),
+            anchor.contains(Anchor::RIGHT),
+            (margin.left, margin.right),
+            size.0,
+        );
+
+        let (y, height) = place_on_axis(
+            (self.position.1, self.size.1),
+            anchor.contains(Anchor::TOP),
+            anchor.contains(Anchor::BOTTOM),
+            (margin.top, margin.bottom),
+            size.1,
+        );
+
+        GlobalRect::new(x, y, width, height)
+    }
+
+    ///
+    /// What's left of this output once layers anchored to `exclusive`
+    /// (anchor, exclusive zone, margin) have taken their exclusive zones,
+    /// as the layer shell lays it out.
+    ///
+    pub fn usable_rect(
+        &self,
+        exclusive: impl IntoIterator<Item = (Anchor, i32, Insets)>,
+    ) -> GlobalRect {
+        let (mut top, mut right, mut bottom, mut left) = (0.0, 0.0, 0.0, 0.0);
+
+        for (anchor, zone, margin) in exclusive {
+            if zone <= 0 {
+                continue;
+            }
+
+            let Some(edge) = exclusive_edge(anchor) else {
+                continue;
+            };
+
+            let zone = zone as f64;
+            if edge == Anchor::TOP {
+                top += zone + margin.top as f64;
+            } else if edge == Anchor::RIGHT {
+                right += zone + margin.right as f64;
+            } else if edge == Anchor::BOTTOM {
+                bottom += zone + margin.bottom as f64;
+            } else {
+                left += zone + margin.left as f64;
+            }
+        }
+
+        // Zones taking more than the whole output leave none of it, rather than
+        // pushing what's left off its far edge.
+        let rect = self.rect();
+        let (left, top) = (left.min(rect.size.0), top.min(rect.size.1));
+        GlobalRect::new(
+            rect.origin.x + left,
+            rect.origin.y + top,
+            (rect.size.0 - left - right).max(0.0),
+            (rect.size.1 - top - bottom).max(0.0),
+        )
+    }
+}
+
+///
+/// The edge a layer anchored to `anchor` takes its exclusive zone from: the
+/// one edge it's anchored to (and maybe both edges across it), if any.
+///
+fn exclusive_edge(anchor: Anchor) -> Option<Anchor> {
+    let vertical = anchor & (Anchor::TOP | Anchor::BOTTOM);
+    let horizontal = anchor & (Anchor::LEFT | Anchor::RIGHT);
+    let across = |edges: Anchor, both: Anchor| edges.is_empty() || edges == both;
+
+    if [Anchor::TOP, Anchor::BOTTOM].contains(&vertical)
+        && across(horizontal, Anchor::LEFT | Anchor::RIGHT)
+    {
+        return Some(vertical);
+    }
+
+    if [Anchor::LEFT, Anchor::RIGHT].contains(&horizontal)
+        && across(vertical, Anchor::TOP | Anchor::BOTTOM)
+    {
+        return Some(horizontal);
+    }
+
+    None
+}
+
+///
+/// Start and length of a layer along one axis of an output, as laid out by
+/// the layer shell: against the anchored edge (less the margin), or centered.
+///
+fn place_on_axis(
+    (output_start, output_length): (i32, i32),
+    start_anchored: bool,
+    end_anchored: bool,
+    (start_margin, end_margin): (i32, i32),
+    length: u32,
+) -> (f64, f64) {
+    let (output_start, output_length) = (output_start as f64, output_length as f64);
+    let (start_margin, end_margin) = (start_margin as f64, end_margin as f64);
+
+    // Zero stretches between both anchored edges.
+    let length = if length == 0 && start_anchored && end_anchored {
+        (output_length - start_margin - end_margin).max(0.0)
+    } else {
+        length as f64
+    };
+
+    let start = match (start_anchored, end_anchored) {
+        (true, false) => output_start + start_margin,
+        (false, true) => output_start + output_length - end_margin - length,
+        (true, true) => {
+            let available = output_length - start_margin - end_margin;
+            output_start + start_margin + (available - length) / 2.0
+        }
+        (false, false) => output_start + (output_length - length) / 2.0,
+    };
+
+    (start, length)
+}
+
+impl GlobalPoint {
+    ///
+    /// This point relative to a surface whose top-left corner is at `surface_origin`.
+    ///
+    pub fn to_surface(self, surface_origin: GlobalPoint) -> SurfacePoint {
+        self.offset_from(surface_origin).into()
+    }
+
+    ///
+    /// The first of `outputs` this point is on.
+    ///
+    pub fn output<'a>(
+        self,
+        outputs: impl IntoIterator<Item = &'a OutputGeometry>,
+    ) -> Option<&'a OutputGeometry> {
+        outputs.into_iter().find(|output| output.contains(self))
+    }
+}
+
+impl SurfacePoint {
+    ///
+    /// This point in the global space, for a surface whose top-left corner is at `surface_origin`.
+    ///
+    pub fn to_global(self, surface_origin: GlobalPoint) -> GlobalPoint {
+        GlobalPoint::new(surface_origin.x + self.x, surface_origin.y + self.y)
+    }
+
+    ///
+    /// This point in the buffer of a surface of `size`.
+    ///
+    pub fn to_buffer(self, size: &SizeSnapshot) -> BufferPoint {
+        let (x_ratio, y_ratio) = buffer_ratio(size);
+        BufferPoint::new(self.x * x_ratio, self.y * y_ratio)
+    }
+
+    ///
+    /// Where this point is in what's drawn on a surface of `size`: the
+    /// buffer pixel it's shown at, before the canvas was scaled and turned.
+    ///
+    pub fn to_drawing_space(self, size: &SizeSnapshot) -> DrawingPoint {
+        let buffer = self.to_buffer(size);
+        let scaled = (buffer.x / size.scale, buffer.y / size.scale);
+        let (width, height) = size.drawing_size();
+
+        size.transform
+            .inverse()
+            .map(scaled, (width as f64, height as f64))
+            .into()
+    }
+}
+
+impl DrawingPoint {
+    ///
+    /// Where this point is shown on a surface of `size`, see [SurfacePoint::to_drawing_space].
+    ///
+    pub fn to_surface(self, size: &SizeSnapshot) -> SurfacePoint {
+        let (width, height) = size.logical;
+        let (x, y) = size
+            .transform
+            .map(self.into(), (width as f64, height as f64));
+
+        BufferPoint::new(x * size.scale, y * size.scale).to_surface(size)
+    }
+}
+
+impl BufferPoint {
+    ///
+    /// This point on a surface of `size`.
+    ///
+    pub fn to_surface(self, size: &SizeSnapshot) -> SurfacePoint {
+        let (x_ratio, y_ratio) = buffer_ratio(size);
+        SurfacePoint::new(self.x / x_ratio, self.y / y_ratio)
+    }
+}
+
+///
+/// Physical pixels per logical pixel along each axis: the buffer is rounded to whole
+/// pixels, so this is what the viewport actually stretches by, rather than the scale.
+///
+fn buffer_ratio(size: &SizeSnapshot) -> (f64, f64) {
+    let ratio = |logical: u32, physical: f64| {
+        if logical == 0 || physical <= 0.0 {
+            size.scale
+        } else {
+            physical / logical as f64
+        }
+    };
+
+    (
+        ratio(size.logical.0, size.physical.0),
+        ratio(size.logical.1, size.physical.1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::{surfaces, Color, Paint, Rect};
+
+    use crate::{util::Transform, wayland::protocol::fractional_scale::ScaleFactor};
+
+    use super::*;
+
+    /// A surface of 40x24 logical pixels at a scale of 1.5.
+    fn size(transform: Transform) -> SizeSnapshot {
+        SizeSnapshot {
+            logical: (40, 24),
+            physical: (60.0, 36.0),
+            scale: 1.5,
+            transform,
+            generation: 0,
+        }
+    }
+
+    fn assert_near(a: (f64, f64), b: (f64, f64), tolerance: f64) {
+        assert!(
+            (a.0 - b.0).abs() <= tolerance && (a.1 - b.1).abs() <= tolerance,
+            "{a:?} isn't within {tolerance} of {b:?}"
+        );
+    }
+
+    ///
+    /// Points go to the drawing space and back, and land where a
+    /// canvas set up by [SizeSnapshot::apply_to_canvas] draws them.
+    ///
+    fn round_trips(transform: Transform) {
+        let size = size(transform);
+
+        for point in [(0.0, 0.0), (3.25, 17.5), (39.0, 1.0), (20.0, 12.0)] {
+            let drawing = SurfacePoint::from(point).to_drawing_space(&size);
+            assert_near(drawing.to_surface(&size).into(), point, 1e-9);
+        }
+
+        let (width, height) = size.drawing_size();
+        let corner = DrawingPoint::new(width as f64, height as f64).to_surface(&size);
+        assert!((0.0..=40.0).contains(&corner.x) && (0.0..=24.0).contains(&corner.y));
+
+        // A 2x2 square at (10, 4) as drawn, found again from where its pixels are.
+        let mut surface = surfaces::raster_n32_premul((60, 36)).unwrap();
+        let canvas = surface.canvas();
+        canvas.clear(Color::TRANSPARENT);
+        size.apply_to_canvas(canvas);
+        canvas.draw_rect(Rect::from_xywh(10.0, 4.0, 2.0, 2.0), &Paint::default());
+
+        let pixmap = surface.peek_pixels().unwrap();
+        let (mut sum, mut count) = ((0.0, 0.0), 0.0);
+        for y in 0..36 {
+            for x in 0..60 {
+                if pixmap.get_color((x, y)).a() == 255 {
+                    sum = (sum.0 + x as f64 + 0.5, sum.1 + y as f64 + 0.5);
+                    count += 1.0;
+                }
+            }
+        }
+        assert!(count > 0.0, "nothing drawn for {transform:?}");
+
+        let center = BufferPoint::new(sum.0 / count, sum.1 / count).to_surface(&size);
+        assert_near(center.to_drawing_space(&size).into(), (11.0, 5.0), 0.34);
+    }
+
+    #[test]
+    fn normal_round_trips() {
+        round_trips(Transform::Normal);
+    }
+
+    #[test]
+    fn rotate_90_round_trips() {
+        round_trips(Transform::Rotate90);
+    }
+
+    #[test]
+    fn rotate_180_round_trips() {
+        round_trips(Transform::Rotate180);
+    }
+
+    #[test]
+    fn rotate_270_round_trips() {
+        round_trips(Transform::Rotate270);
+    }
+
+    #[test]
+    fn flipped_round_trips() {
+        round_trips(Transform::Flipped);
+    }
+
+    #[test]
+    fn flipped_90_round_trips() {
+        round_trips(Transform::Flipped90);
+    }
+
+    #[test]
+    fn flipped_180_round_trips() {
+        round_trips(Transform::Flipped180);
+    }
+
+    #[test]
+    fn flipped_270_round_trips() {
+        round_trips(Transform::Flipped270);
+    }
+
+    #[test]
+    fn quarter_turns_swap_the_drawing_size() {
+        for transform in Transform::ALL {
+            let expected = if transform.is_quarter_turn() {
+                (24, 40)
+            } else {
+                (40, 24)
+            };
+            assert_eq!(size(transform).drawing_size(), expected, "{transform:?}");
+            assert_eq!(transform.inverse().inverse(), transform);
+        }
+    }
+
+    ///
+    /// Every scale the fractional scale protocol can send, from 1 to 3.
+    ///
+    fn fractional_scales() -> impl Iterator<Item = ScaleFactor> {
+        (120..=360).map(ScaleFactor::from_raw)
+    }
+
+    ///
+    /// A surface `logical` big at `scale`, its buffer rounded as [crate::util::Size] rounds it.
+    ///
+    fn scaled(logical: (u32, u32), scale: ScaleFactor, transform: Transform) -> SizeSnapshot {
+        SizeSnapshot {
+            logical,
+            physical: (scale.scale(logical.0), scale.scale(logical.1)),
+            scale: scale.as_f64(),
+            transform,
+            generation: 0,
+        }
+    }
+
+    ///
+    /// Points spread evenly over `(width, height)`, edges included.
+    ///
+    fn grid((width, height): (u32, u32)) -> impl Iterator<Item = (f64, f64)> {
+        (0..=8).flat_map(move |i| {
+            (0..=8).map(move |j| {
+                (
+                    width as f64 * i as f64 / 8.0,
+                    height as f64 * j as f64 / 8.0,
+                )
+            })
+        })
+    }
+
+    #[test]
+    fn global_points_round_trip_through_surfaces() {
+        for origin in [(0.0, 0.0), (1920.0, 0.0), (-1280.0, 360.5), (12.25, -7.75)] {
+            let origin = GlobalPoint::from(origin);
+
+            for point in grid((2560, 1440)) {
+                let global = GlobalPoint::from(point);
+                let surface = global.to_surface(origin);
+
+                assert_near(surface.to_global(origin).into(), point, 1e-9);
+                assert_near(
+                    surface.offset_from(SurfacePoint::default()),
+                    global.offset_from(origin),
+                    1e-9,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_points_round_trip_at_every_fractional_scale() {
+        for scale in fractional_scales() {
+            for logical in [(1, 1), (99, 33), (333, 47), (1920, 1080)] {
+                let size = scaled(logical, scale, Transform::Normal);
+
+                for point in grid(logical) {
+                    let buffer = SurfacePoint::from(point).to_buffer(&size);
+                    assert_near(buffer.to_surface(&size).into(), point, 1e-9);
+                }
+
+                // The far corner is the buffer's, however that was rounded.
+                let corner = SurfacePoint::new(logical.0 as f64, logical.1 as f64);
+                assert_near(corner.to_buffer(&size).into(), size.physical, 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn drawing_points_round_trip_at_every_fractional_scale() {
+        let logical = (333, 47);
+
+        for scale in fractional_scales() {
+            // Rounding the buffer to whole pixels moves its far edge by up to half of one.
+            let slack = 0.5 / scale.as_f64() + 1e-9;
+
+            for transform in Transform::ALL {
+                let size = scaled(logical, scale, transform);
+                let (width, height) = size.drawing_size();
+                let (width, height) = (width as f64, height as f64);
+
+                for point in grid(logical) {
+                    let drawing = SurfacePoint::from(point).to_drawing_space(&size);
+                    assert_near(drawing.to_surface(&size).into(), point, 1e-9);
+
+                    assert!(
+                        (-slack..=width + slack).contains(&drawing.x)
+                            && (-slack..=height + slack).contains(&drawing.y),
+                        "{point:?} is off the drawing at {scale:?}, {transform:?}: {drawing:?}"
+                    );
+                }
+
+                // Corners land on corners.
+                let corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)];
+                for corner in grid(logical).filter(|(x, y)| {
+                    (*x == 0.0 || *x == logical.0 as f64) && (*y == 0.0 || *y == logical.1 as f64)
+                }) {
+                    let drawing = SurfacePoint::from(corner).to_drawing_space(&size);
+                    assert!(
+                        corners.iter().any(|&expected| {
+                            (drawing.x - expected.0).abs() <= slack
+                                && (drawing.y - expected.1).abs() <= slack
+                        }),
+                        "{corner:?} isn't a corner at {scale:?}, {transform:?}: {drawing:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rects_contain_their_top_and_left_edges_only() {
+        let rect = GlobalRect::new(10.0, 20.0, 30.0, 40.0);
+
+        assert!(rect.contains(GlobalPoint::new(10.0, 20.0)));
+        assert!(rect.contains(GlobalPoint::new(39.999, 59.999)));
+        assert!(!rect.contains(GlobalPoint::new(40.0, 30.0)));
+        assert!(!rect.contains(GlobalPoint::new(20.0, 60.0)));
+        assert!(!rect.contains(GlobalPoint::new(9.999, 30.0)));
+
+        // So a point on the seam between two outputs is on exactly one.
+        let outputs = [
+            OutputGeometry {
+                position: (0, 0),
+                size: (1920, 1080),
+            },
+            OutputGeometry {
+                position: (1920, 0),
+                size: (1280, 1024),
+            },
+        ];
+
+        for x in (0..3300).step_by(40) {
+            for y in [0, 500, 1023, 1024, 1079, 1080] {
+                let point = GlobalPoint::new(x as f64, y as f64);
+                let expected = if x < 1920 && y < 1080 {
+                    Some(&outputs[0])
+                } else if (1920..3200).contains(&x) && y < 1024 {
+                    Some(&outputs[1])
+                } else {
+                    None
+                };
+
+                assert_eq!(point.output(&outputs), expected, "{point:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn constrained_rects_fit_and_move_no_more_than_needed() {
+        let bounds = GlobalRect::new(-100.0, 50.0, 400.0, 300.0);
+        let starts = [
+            -600.0, -150.0, -100.0, -20.5, 0.0, 99.0, 250.0, 299.5, 700.0,
+        ];
+        let lengths = [0.0, 1.0, 120.5, 300.0, 400.0, 1000.0];
+
+        let fits = |start: f64, length: f64, bounds_start: f64, bounds_length: f64| {
+            start >= bounds_start && start + length <= bounds_start + bounds_length
+        };
+
+        for (x, y) in starts.iter().flat_map(|&x| starts.map(|y| (x, y))) {
+            for (width, height) in lengths.iter().flat_map(|&w| lengths.map(|h| (w, h))) {
+                let rect = GlobalRect::new(x, y, width, height);
+                let constrained = rect.constrain(bounds);
+                let (cx, cy) = (constrained.origin.x, constrained.origin.y);
+
+                assert!(
+                    fits(cx, constrained.size.0, -100.0, 400.0)
+                        && fits(cy, constrained.size.1, 50.0, 300.0),
+                    "{rect:?} became {constrained:?}"
+                );
+
+                // Only cut down if it can't fit.
+                assert_eq!(constrained.size, (width.min(400.0), height.min(300.0)));
+
+                // Left alone along an axis it already fits along.
+                if fits(x, width, -100.0, 400.0) {
+                    assert_eq!(cx, x, "{rect:?}");
+                }
+                if fits(y, height, 50.0, 300.0) {
+                    assert_eq!(cy, y, "{rect:?}");
+                }
+
+                assert_eq!(constrained.constrain(bounds), constrained);
+            }
+        }
+    }
+
+    #[test]
+    fn layers_are_placed_within_their_output() {
+        let output = OutputGeometry {
+            position: (1920, -200),
+            size: (1280, 1024),
+        };
+        let bounds = output.rect();
+        let margin = Insets::new(5, 10, 15, 20);
+
+        for anchor in (0..16).map(Anchor::from_bits_truncate) {
+            let rect = output.layer_rect(anchor, margin, (300, 100));
+            assert_eq!(rect.constrain(bounds), rect, "{anchor:?}");
+
+            let gaps = |start: f64, length: f64, bounds_start: f64, bounds_length: f64| {
+                (
+                    start - bounds_start,
+                    bounds_start + bounds_length - start - length,
+                )
+            };
+            let (left, right) = gaps(rect.origin.x, rect.size.0, bounds.origin.x, bounds.size.0);
+            let (top, bottom) = gaps(rect.origin.y, rect.size.1, bounds.origin.y, bounds.size.1);
+
+            // Against the anchored edge less its margin, or centered.
+            match (
+                anchor.contains(Anchor::LEFT),
+                anchor.contains(Anchor::RIGHT),
+            ) {
+                (true, false) => assert_eq!(left, 20.0, "{anchor:?}"),
+                (false, true) => assert_eq!(right, 10.0, "{anchor:?}"),
+                (true, true) => assert_eq!(left - 20.0, right - 10.0, "{anchor:?}"),
+                (false, false) => assert_eq!(left, right, "{anchor:?}"),
+            }
+
+            match (
+                anchor.contains(Anchor::TOP),
+                anchor.contains(Anchor::BOTTOM),
+            ) {
+                (true, false) => assert_eq!(top, 5.0, "{anchor:?}"),
+                (false, true) => assert_eq!(bottom, 15.0, "{anchor:?}"),
+                (true, true) => assert_eq!(top - 5.0, bottom - 15.0, "{anchor:?}"),
+                (false, false) => assert_eq!(top, bottom, "{anchor:?}"),
+            }
+        }
+
+        // Zero stretches between both anchored edges, less the margins.
+        assert_eq!(
+            output.layer_rect(Anchor::all(), margin, (0, 0)),
+            GlobalRect::new(1940.0, -195.0, 1250.0, 1004.0)
+        );
+    }
+
+    #[test]
+    fn usable_rects_stay_within_their_output() {
+        let output = OutputGeometry {
+            position: (-1280, 0),
+            size: (1280, 720),
+        };
+        let bounds = output.rect();
+
+        for anchor in (0..16).map(Anchor::from_bits_truncate) {
+            for zone in [-1, 0, 30, 700, 2000] {
+                let usable = output.usable_rect([(anchor, zone, Insets::uniform(4))]);
+                assert_eq!(usable.constrain(bounds), usable, "{anchor:?}, {zone}");
+
+                if zone <= 0 || exclusive_edge(anchor).is_none() {
+                    assert_eq!(usable, bounds, "{anchor:?}, {zone}");
+                }
+            }
+        }
+
+        // Zones on the same edge add up.
+        let usable = output.usable_rect([
+            (Anchor::TOP, 30, Insets::ZERO),
+            (
+                Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+                10,
+                Insets::uniform(2),
+            ),
+            (Anchor::LEFT, 50, Insets::ZERO),
+        ]);
+        assert_eq!(usable, GlobalRect::new(-1230.0, 42.0, 1230.0, 678.0));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+type MarkCallback = Box<dyn Fn() + Send + Sync>;
+
+///
+/// Shared "needs repainting" flag for a surface.
+///
+#[derive(Clone)]
+pub struct DirtyFlag(Arc<Flag>);
+
+struct Flag {
+    dirty: AtomicBool,
+    /// See [DirtyFlag::set_on_mark].
+    on_mark: Mutex<Option<MarkCallback>>,
+}
+
+impl Default for DirtyFlag {
+    ///
+    /// Surfaces start out dirty, they haven't been drawn yet!
+    ///
+    fn default() -> Self {
+        Self(Arc::new(Flag {
+            dirty: AtomicBool::new(true),
+            on_mark: Mutex::new(None),
+        }))
+    }
+}
+
+impl fmt::Debug for DirtyFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DirtyFlag").field(&self.is_dirty()).finish()
+    }
+}
+
+impl DirtyFlag {
+    pub fn mark(&self) {
+        // Sequentially consistent, so that whoever checks the flag after
+        // giving up on it (see [DirtyFlag::set_on_mark]) can't miss a mark.
+        if self.0.dirty.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        if let Some(on_mark) = &*self.0.on_mark.lock().unwrap() {
+            on_mark();
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.0.dirty.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Clear the flag, returning whether it was set.
+    ///
+    pub fn take(&self) -> bool {
+        self.0.dirty.swap(false, Ordering::AcqRel)
+    }
+
+    ///
+    /// Call `on_mark` (on whichever thread marks the flag) whenever it goes
+    /// from clean to dirty, e.g. to wake up whatever waits on it, or stop
+    /// calling anything with `None`.
+    ///
+    pub(crate) fn set_on_mark(&self, on_mark: Option<MarkCallback>) {
+        *self.0.on_mark.lock().unwrap() = on_mark;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[test]
+    fn on_mark_runs_when_the_flag_becomes_dirty() {
+        let dirty = DirtyFlag::default();
+        let marks = Arc::new(AtomicUsize::new(0));
+
+        let counter = marks.clone();
+        dirty.set_on_mark(Some(Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })));
+
+        // Already dirty.
+        dirty.mark();
+        assert_eq!(marks.load(Ordering::SeqCst), 0);
+
+        assert!(dirty.take());
+        dirty.mark();
+        dirty.mark();
+        assert_eq!(marks.load(Ordering::SeqCst), 1);
+
+        dirty.set_on_mark(None);
+        dirty.take();
+        dirty.mark();
+        assert_eq!(marks.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,110 @@
+//!
+//! Physical pixel densities of outputs, for sizing text in millimeters
+//! rather than logical points, so it comes out the same physical size on
+//! every monitor (see [FontScale]).
+//!
+
+use smithay_client_toolkit::output::OutputInfo;
+
+pub const MILLIMETERS_PER_INCH: f64 = 25.4;
+
+///
+/// Densities outside of this range come from outputs reporting bogus physical
+/// sizes (e.g. an aspect ratio in centimeters), rather than real monitors.
+///
+const PLAUSIBLE_DPI: std::ops::RangeInclusive<f64> = 40.0..=1200.0;
+
+///
+/// How dense an output's pixels are, see [OutputDpi::from_info].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputDpi {
+    /// Physical pixels per inch.
+    pub physical: f64,
+    /// Logical pixels per inch, after the output's scale.
+    pub logical: f64,
+}
+
+impl OutputDpi {
+    ///
+    /// The output's density, from its physical size and current mode.
+    ///
+    /// `None` for outputs which don't have a meaningful physical size
+    /// (projectors, virtual outputs), or report an implausible one.
+    ///
+    pub fn from_info(info: &OutputInfo) -> Option<Self> {
+        let (width_mm, height_mm) = info.physical_size;
+        if width_mm <= 0 || height_mm <= 0 {
+            return None;
+        }
+
+        let mode = info.modes.iter().find(|mode| mode.current)?;
+        let (width, height) = mode.dimensions;
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+
+        // The physical size and the mode are both before the output's transform.
+        let horizontal = width as f64 / (width_mm as f64 / MILLIMETERS_PER_INCH);
+        let vertical = height as f64 / (height_mm as f64 / MILLIMETERS_PER_INCH);
+        let physical = (horizontal + vertical) / 2.0;
+
+        if !PLAUSIBLE_DPI.contains(&physical) {
+            return None;
+        }
+
+        // The logical size is after the transform, so compare the longer sides.
+        let physical_per_logical = info
+            .logical_size
+            .map(|(logical_width, logical_height)| logical_width.max(logical_height))
+            .filter(|longest| *longest > 0)
+            .map_or(1.0, |longest| width.max(height) as f64 / longest as f64);
+
+        Some(Self {
+            physical,
+            logical: physical / physical_per_logical,
+        })
+    }
+
+    ///
+    /// How many logical pixels cover `millimeters` on this output.
+    ///
+    pub fn logical_pixels(&self, millimeters: f64) -> f64 {
+        millimeters / MILLIMETERS_PER_INCH * self.logical
+    }
+}
+
+///
+/// How font sizes given to [crate::graphics::RenderContext::font_size] are resolved.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FontScale {
+    /// Sizes are logical pixels, whatever the output.
+    #[default]
+    LogicalPoints,
+    ///
+    /// Each unit of size is this many millimeters on the output the surface is
+    /// (first) on, e.g. [FontScale::TYPOGRAPHIC_POINTS]. Falls back to logical
+    /// points on outputs without a (plausible) physical size.
+    ///
+    PhysicalMillimeters(f64),
+}
+
+impl FontScale {
+    ///
+    /// Sizes as typographic points: 1/72 of an inch.
+    ///
+    pub const TYPOGRAPHIC_POINTS: Self = Self::PhysicalMillimeters(MILLIMETERS_PER_INCH / 72.0);
+
+    ///
+    /// The font size to hand Skia (in logical pixels) for `size`, on an output of `dpi`.
+    ///
+    pub fn resolve(&self, size: f32, dpi: Option<OutputDpi>) -> f32 {
+        match (self, dpi) {
+            (Self::PhysicalMillimeters(millimeters), Some(dpi)) if *millimeters > 0.0 => {
+                dpi.logical_pixels(size as f64 * millimeters) as f32
+            }
+            _ => size,
+        }
+    }
+}
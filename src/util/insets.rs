@@ -0,0 +1,46 @@
+use super::animation::Lerp;
+
+///
+/// Distances from each edge of a rectangle, in logical pixels.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Insets {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+impl Insets {
+    pub const ZERO: Self = Self::uniform(0);
+
+    pub const fn new(top: i32, right: i32, bottom: i32, left: i32) -> Self {
+        Self {
+            top,
+            right,
+            bottom,
+            left,
+        }
+    }
+
+    pub const fn uniform(value: i32) -> Self {
+        Self::new(value, value, value, value)
+    }
+}
+
+impl From<(i32, i32, i32, i32)> for Insets {
+    fn from((top, right, bottom, left): (i32, i32, i32, i32)) -> Self {
+        Self::new(top, right, bottom, left)
+    }
+}
+
+impl Lerp for Insets {
+    fn lerp(&self, to: &Self, t: f64) -> Self {
+        Self {
+            top: self.top.lerp(&to.top, t),
+            right: self.right.lerp(&to.right, t),
+            bottom: self.bottom.lerp(&to.bottom, t),
+            left: self.left.lerp(&to.left, t),
+        }
+    }
+}
@@ -1,13 +1,32 @@
+pub mod animation;
+pub mod clock;
+pub mod coords;
+pub mod dirty;
+pub mod dpi;
+pub mod insets;
 pub mod size;
 
 use std::any::Any;
 
-pub use size::Size;
+pub use clock::{Clock, SharedClock, SystemClock, TestClock};
+pub use coords::{
+    BufferPoint, DrawingPoint, GlobalPoint, GlobalRect, OutputGeometry, SurfacePoint,
+};
+pub use dirty::DirtyFlag;
+pub use insets::Insets;
+pub use size::{ScaleMode, Size, SizeSnapshot, Transform};
 
 pub trait AsAny {
     fn as_any(self: Box<Self>) -> Box<dyn Any>;
     fn as_any_ref(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    ///
+    /// Name of the concrete type, for debugging.
+    ///
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
 #[macro_export]
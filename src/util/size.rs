@@ -1,15 +1,263 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
+use smithay_client_toolkit::reexports::client::protocol::wl_output;
 
-use crate::wayland::protocol::fractional_scale::ScaleFactor;
+use crate::wayland::protocol::fractional_scale::{ScaleError, ScaleFactor};
+
+///
+/// How what's drawn is turned to fit the surface, as outputs are turned
+/// (see [wl_output::Transform]): rotated clockwise, after being flipped
+/// around the vertical axis for the `Flipped` ones.
+///
+/// Drawing is laid out in its own space (see [crate::util::DrawingPoint]),
+/// which for a quarter turn has the surface's width and height swapped.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Transform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Self::Normal,
+        Self::Rotate90,
+        Self::Rotate180,
+        Self::Rotate270,
+        Self::Flipped,
+        Self::Flipped90,
+        Self::Flipped180,
+        Self::Flipped270,
+    ];
+
+    ///
+    /// Whether width and height swap places.
+    ///
+    pub fn is_quarter_turn(&self) -> bool {
+        matches!(
+            self,
+            Self::Rotate90 | Self::Rotate270 | Self::Flipped90 | Self::Flipped270
+        )
+    }
+
+    ///
+    /// The transform undoing this one.
+    ///
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::Rotate90 => Self::Rotate270,
+            Self::Rotate270 => Self::Rotate90,
+            // Half turns and flips undo themselves.
+            other => *other,
+        }
+    }
+
+    ///
+    /// Where `(x, y)` ends up, in a space `to` wide and high once transformed.
+    ///
+    pub fn map(&self, (x, y): (f64, f64), to: (f64, f64)) -> (f64, f64) {
+        let (width, height) = to;
+
+        match self {
+            Self::Normal => (x, y),
+            Self::Rotate90 => (width - y, x),
+            Self::Rotate180 => (width - x, height - y),
+            Self::Rotate270 => (y, height - x),
+            Self::Flipped => (width - x, y),
+            Self::Flipped90 => (width - y, height - x),
+            Self::Flipped180 => (x, height - y),
+            Self::Flipped270 => (y, x),
+        }
+    }
+
+    ///
+    /// [Transform::map] as a matrix, for a canvas.
+    ///
+    pub fn matrix(&self, to: (f64, f64)) -> skia_safe::Matrix {
+        let origin = self.map((0.0, 0.0), to);
+        let x = self.map((1.0, 0.0), to);
+        let y = self.map((0.0, 1.0), to);
+
+        skia_safe::Matrix::new_all(
+            (x.0 - origin.0) as f32,
+            (y.0 - origin.0) as f32,
+            origin.0 as f32,
+            (x.1 - origin.1) as f32,
+            (y.1 - origin.1) as f32,
+            origin.1 as f32,
+            0.0,
+            0.0,
+            1.0,
+        )
+    }
+}
+
+///
+/// Unknown transforms (from newer compositors) are left alone.
+///
+impl From<wl_output::Transform> for Transform {
+    fn from(transform: wl_output::Transform) -> Self {
+        match transform {
+            wl_output::Transform::_90 => Self::Rotate90,
+            wl_output::Transform::_180 => Self::Rotate180,
+            wl_output::Transform::_270 => Self::Rotate270,
+            wl_output::Transform::Flipped => Self::Flipped,
+            wl_output::Transform::Flipped90 => Self::Flipped90,
+            wl_output::Transform::Flipped180 => Self::Flipped180,
+            wl_output::Transform::Flipped270 => Self::Flipped270,
+            _ => Self::Normal,
+        }
+    }
+}
+
+///
+/// How a surface's scale follows the one the compositor prefers for it.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    /// Exactly the preferred scale.
+    #[default]
+    Fractional,
+    ///
+    /// The preferred scale rounded up to a whole number, which the compositor
+    /// scales back down: costlier, but lines drawn on whole logical pixels stay
+    /// aligned to physical ones.
+    ///
+    Integer,
+}
+
+impl ScaleMode {
+    ///
+    /// The scale to draw at, for a `preferred` one.
+    ///
+    pub fn apply(&self, preferred: ScaleFactor) -> ScaleFactor {
+        match self {
+            Self::Fractional => preferred,
+            Self::Integer if preferred.is_integer() => preferred,
+            Self::Integer => ScaleFactor::from_f64(preferred.as_f64().ceil()),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Size {
     logical: (u32, u32),
     scale_factor: Option<ScaleFactor>,
-    has_changed: Arc<AtomicBool>,
+    /// The compositor's preferred scale, before [ScaleMode::apply].
+    preferred_scale: Option<ScaleFactor>,
+    scale_mode: ScaleMode,
+    /// Fraction of the scale to render at, see [Size::set_quality].
+    quality: f64,
+    transform: Transform,
+    /// Bumped whenever the logical size, scale or transform changes.
+    generation: u64,
+}
+
+///
+/// A [Size] as it was at one point in time, taken once per frame so that
+/// the whole frame agrees on it, whatever the event thread does meanwhile.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeSnapshot {
+    pub logical: (u32, u32),
+    pub physical: (f64, f64),
+    pub scale: f64,
+    /// See [Size::set_transform].
+    pub transform: Transform,
+    /// See [Size::generation].
+    pub generation: u64,
+}
+
+impl SizeSnapshot {
+    pub fn logical_size(&self) -> (u32, u32) {
+        self.logical
+    }
+
+    pub fn physical_size(&self) -> (f64, f64) {
+        self.physical
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale
+    }
+
+    ///
+    /// The size drawing is laid out in, in logical pixels: the
+    /// surface's, with width and height swapped for a quarter turn.
+    ///
+    pub fn drawing_size(&self) -> (u32, u32) {
+        let (width, height) = self.logical;
+        if self.transform.is_quarter_turn() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    ///
+    /// Apply scaling transform (if applicable) to Skia canvas.
+    ///
+    pub fn scale_canvas(&self, canvas: &skia_safe::Canvas) {
+        if self.scale != 1.0 {
+            let factor = self.scale as f32;
+            canvas.scale((factor, factor));
+        }
+    }
+
+    ///
+    /// Scale and turn the canvas (see [Size::set_transform]), for drawing in
+    /// logical pixels of [SizeSnapshot::drawing_size].
+    ///
+    pub fn apply_to_canvas(&self, canvas: &skia_safe::Canvas) {
+        self.scale_canvas(canvas);
+
+        if self.transform != Transform::Normal {
+            let (width, height) = self.logical;
+            canvas.concat(&self.transform.matrix((width as f64, height as f64)));
+        }
+    }
+
+    ///
+    /// Call `f` with the turn [SizeSnapshot::apply_to_canvas] made undone,
+    /// so that it draws (or clips) in logical pixels of the surface itself.
+    ///
+    pub fn in_surface_space(&self, canvas: &skia_safe::Canvas, f: impl FnOnce(&skia_safe::Canvas)) {
+        if self.transform == Transform::Normal {
+            return f(canvas);
+        }
+
+        let (width, height) = self.drawing_size();
+        canvas.concat(
+            &self
+                .transform
+                .inverse()
+                .matrix((width as f64, height as f64)),
+        );
+        f(canvas);
+
+        let (width, height) = self.logical;
+        canvas.concat(&self.transform.matrix((width as f64, height as f64)));
+    }
+
+    ///
+    /// Clip the canvas to the buffer's bounds, whatever its transform.
+    ///
+    /// The clip is in physical pixels, rounded as the buffer's own size
+    /// is, so it's exact at fractional scales too.
+    ///
+    pub fn clip_canvas(&self, canvas: &skia_safe::Canvas) {
+        let (width, height) = self.physical;
+        let matrix = canvas.local_to_device();
+
+        canvas.reset_matrix();
+        canvas.clip_irect(skia_safe::IRect::from_wh(width as i32, height as i32), None);
+        canvas.set_matrix(&matrix);
+    }
 }
 
 impl Size {
@@ -17,10 +265,19 @@ impl Size {
         Self {
             logical: logical_size,
             scale_factor: None,
-            has_changed: Arc::new(AtomicBool::new(false)),
+            preferred_scale: None,
+            scale_mode: ScaleMode::default(),
+            quality: 1.0,
+            transform: Transform::Normal,
+            generation: 0,
         }
     }
 
+    pub fn with_scale_mode(mut self, scale_mode: ScaleMode) -> Self {
+        self.set_scale_mode(scale_mode);
+        self
+    }
+
     pub fn logical_size(&self) -> (u32, u32) {
         self.logical
     }
@@ -40,19 +297,111 @@ impl Size {
         }
     }
 
+    ///
+    /// Ratio of physical to logical pixels.
+    ///
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+            .as_ref()
+            .map(ScaleFactor::as_f64)
+            .unwrap_or(1.0)
+    }
+
+    ///
+    /// Counts changes to the size or scale: anything sized after this
+    /// size needs redoing when the generation moves on.
+    ///
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn snapshot(&self) -> SizeSnapshot {
+        SizeSnapshot {
+            logical: self.logical_size(),
+            physical: self.physical_size(),
+            scale: self.scale_factor(),
+            transform: self.transform,
+            generation: self.generation,
+        }
+    }
+
     pub fn resize(&mut self, logical_size: (u32, u32)) {
-        self.logical = logical_size;
-        self.has_changed.store(true, Ordering::Relaxed);
+        if self.logical != logical_size {
+            self.logical = logical_size;
+            self.generation += 1;
+        }
     }
 
-    pub fn rescale(&mut self, scale: ScaleFactor) {
-        self.scale_factor.replace(scale);
-        self.has_changed.store(true, Ordering::Relaxed);
+    ///
+    /// Follow the compositor's `preferred` scale, as the [ScaleMode] says.
+    ///
+    /// Invalid scales (see [ScaleFactor::validate]) are refused, keeping the current one.
+    ///
+    pub fn rescale(&mut self, preferred: ScaleFactor) -> Result<(), ScaleError> {
+        let preferred = preferred.validate()?;
+        self.preferred_scale.replace(preferred);
+
+        let mut scale = self.scale_mode.apply(preferred);
+        if self.quality < 1.0 {
+            let raw = (scale.raw() as f64 * self.quality).round().max(1.0);
+            scale = ScaleFactor::from_raw(raw as u32);
+        }
+
+        if self.scale_factor != Some(scale) {
+            self.scale_factor.replace(scale);
+            self.generation += 1;
+        }
+
+        Ok(())
     }
 
-    pub fn handle_changes(&self, mut handler: impl FnMut(&Self)) {
-        handler(self);
-        self.has_changed.store(false, Ordering::Relaxed);
+    pub fn scale_mode(&self) -> ScaleMode {
+        self.scale_mode
+    }
+
+    ///
+    /// Change how the scale follows the compositor's, rescaling
+    /// straight away if it's already sent one.
+    ///
+    pub fn set_scale_mode(&mut self, scale_mode: ScaleMode) {
+        self.scale_mode = scale_mode;
+
+        if let Some(preferred) = self.preferred_scale {
+            // Already validated.
+            let _ = self.rescale(preferred);
+        }
+    }
+
+    ///
+    /// Render at `quality` (clamped to `0.1..=1.0`) times the scale, for
+    /// the viewport to scale back up: blurrier, but cheaper to draw.
+    ///
+    pub fn set_quality(&mut self, quality: f64) {
+        self.quality = quality.clamp(0.1, 1.0);
+
+        if let Some(preferred) = self.preferred_scale {
+            // Already validated.
+            let _ = self.rescale(preferred);
+        }
+    }
+
+    pub fn quality(&self) -> f64 {
+        self.quality
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    ///
+    /// Turn what's drawn by `transform` to fit the surface, e.g. to lay a
+    /// strip out along a surface which is taller than it's wide.
+    ///
+    pub fn set_transform(&mut self, transform: Transform) {
+        if self.transform != transform {
+            self.transform = transform;
+            self.generation += 1;
+        }
     }
 
     ///
@@ -65,3 +414,77 @@ impl Size {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, RwLock,
+        },
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn only_real_changes_move_the_generation_on() {
+        let mut size = Size::new((100, 50));
+        let start = size.generation();
+
+        size.resize((100, 50));
+        size.rescale(ScaleFactor::from_raw(120)).unwrap();
+        assert_eq!(size.generation(), start + 1, "first scale");
+
+        size.rescale(ScaleFactor::from_raw(120)).unwrap();
+        size.set_scale_mode(ScaleMode::Fractional);
+        assert_eq!(size.generation(), start + 1);
+
+        size.resize((200, 50));
+        size.rescale(ScaleFactor::from_raw(180)).unwrap();
+        assert_eq!(size.generation(), start + 3);
+    }
+
+    #[test]
+    fn refused_scales_leave_the_generation_alone() {
+        let mut size = Size::new((100, 50));
+        size.rescale(ScaleFactor::from_raw(180)).unwrap();
+        let generation = size.generation();
+
+        assert!(size.rescale(ScaleFactor::from_raw(0)).is_err());
+        assert_eq!(size.generation(), generation);
+        assert_eq!(size.snapshot().physical, (150.0, 75.0));
+    }
+
+    #[test]
+    fn snapshots_agree_with_themselves_whilst_resizing() {
+        let size = Arc::new(RwLock::new(Size::new((100, 100))));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let resizer = {
+            let (size, done) = (size.clone(), done.clone());
+            thread::spawn(move || {
+                for i in 0..10_000u32 {
+                    let mut size = size.write().unwrap();
+                    size.resize((100 + i % 7, 100 + i % 5));
+                    size.rescale(ScaleFactor::from_raw(120 + (i % 4) * 30))
+                        .unwrap();
+                }
+                done.store(true, Ordering::Release);
+            })
+        };
+
+        let mut last_generation = 0;
+        while !done.load(Ordering::Acquire) {
+            let snapshot = size.read().unwrap().snapshot();
+            let (width, height) = snapshot.logical;
+            let scale = ScaleFactor::from_f64(snapshot.scale);
+
+            assert_eq!(snapshot.physical, (scale.scale(width), scale.scale(height)));
+            assert!(snapshot.generation >= last_generation);
+            last_generation = snapshot.generation;
+        }
+
+        resizer.join().unwrap();
+    }
+}
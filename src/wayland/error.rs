@@ -0,0 +1,277 @@
+//!
+//! Protocol errors, traced back to whatever made the offending object.
+//!
+//! A protocol error closes the connection, and all Wayland says about it is
+//! an interface, an object id, and a code. Objects the crate makes on behalf
+//! of a surface (its viewport, layer surface, ...) are tracked in an
+//! [ObjectRegistry], so the error can name the surface. Anything else is put
+//! down to the feature binding that interface, see [ObjectOwner].
+//!
+
+use std::{collections::HashMap, fmt::Display, time::Duration};
+
+use smithay_client_toolkit::reexports::{calloop::EventLoop, client::DispatchError};
+use thiserror::Error;
+use wayland_backend::{
+    client::{ObjectId, WaylandError},
+    protocol::ProtocolError as WaylandProtocolError,
+};
+
+use crate::{avy::AvyError, debug::trace::WAYLAND_TARGET, AvyClient};
+
+pub(crate) type FatalErrorCallback = Box<dyn FnMut(&mut AvyClient, &ProtocolError)>;
+
+///
+/// Whatever made the object a protocol error was raised on.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectOwner {
+    ///
+    /// A registered surface, or an object made for one.
+    ///
+    Surface { id: ObjectId, name: Option<String> },
+    ///
+    /// An object bound by one of the crate's features, e.g. `"layer shell"`.
+    ///
+    Feature(&'static str),
+    ///
+    /// Not an object the crate knows about, e.g. one made by the application.
+    ///
+    Unknown,
+}
+
+impl Display for ObjectOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Surface {
+                name: Some(name), ..
+            } => write!(f, "surface \"{name}\""),
+            Self::Surface { id, name: None } => write!(f, "surface {id}"),
+            Self::Feature(feature) => write!(f, "{feature}"),
+            Self::Unknown => write!(f, "an unknown owner"),
+        }
+    }
+}
+
+///
+/// A protocol error raised by the compositor, which has closed the connection.
+///
+#[derive(Debug, Clone, Error)]
+#[error("Protocol error {code} on {interface}#{object_id} (from {object_owner}): {message}")]
+pub struct ProtocolError {
+    pub interface: String,
+    pub object_id: u32,
+    pub object_owner: ObjectOwner,
+    pub code: u32,
+    pub message: String,
+}
+
+///
+/// Objects made for registered surfaces, by the surface they were made for.
+///
+#[derive(Debug, Default)]
+pub struct ObjectRegistry(HashMap<ObjectId, ObjectId>);
+
+impl ObjectRegistry {
+    ///
+    /// Put errors on `object` down to `surface`.
+    ///
+    pub fn track(&mut self, object: ObjectId, surface: ObjectId) {
+        self.0.insert(object, surface);
+    }
+
+    ///
+    /// Forget every object made for `surface`, once it's destroyed.
+    ///
+    pub fn forget_surface(&mut self, surface: &ObjectId) {
+        self.0.retain(|_, owner| owner != surface);
+    }
+
+    ///
+    /// The surface `object_id` (of `interface`) was made for.
+    ///
+    pub fn surface_of(&self, interface: &str, object_id: u32) -> Option<&ObjectId> {
+        self.0.iter().find_map(|(object, surface)| {
+            (object.protocol_id() == object_id && object.interface().name == interface)
+                .then_some(surface)
+        })
+    }
+}
+
+///
+/// The feature objects of `interface` are bound by.
+///
+fn feature_of(interface: &str) -> Option<&'static str> {
+    let feature = match interface {
+        "wl_compositor" | "wl_surface" | "wl_region" | "wl_callback" => "compositor",
+        "wl_subcompositor" | "wl_subsurface" => "subsurfaces",
+        "wl_shm" | "wl_shm_pool" | "wl_buffer" => "shared memory buffers",
+        "wl_output" | "zxdg_output_manager_v1" | "zxdg_output_v1" => "outputs",
+        "wl_seat" | "wl_pointer" | "wl_keyboard" | "wl_touch" => "input",
+        "zwp_relative_pointer_manager_v1" | "zwp_relative_pointer_v1" => "relative pointer",
+        "wp_cursor_shape_manager_v1" | "wp_cursor_shape_device_v1" => "cursor shape",
+        "zwlr_layer_shell_v1" | "zwlr_layer_surface_v1" => "layer shell",
+        "wp_viewporter" | "wp_viewport" => "viewporter",
+        "wp_fractional_scale_manager_v1" | "wp_fractional_scale_v1" => "fractional scale",
+        "hyprland_global_shortcuts_manager_v1" | "hyprland_global_shortcut_v1" => {
+            "global shortcuts"
+        }
+        "ext_idle_notifier_v1" | "ext_idle_notification_v1" => "idle notifications",
+        "zwp_primary_selection_device_manager_v1"
+        | "zwp_primary_selection_device_v1"
+        | "zwp_primary_selection_source_v1"
+        | "zwp_primary_selection_offer_v1" => "primary selection",
+        "zwp_virtual_keyboard_manager_v1" | "zwp_virtual_keyboard_v1" => "virtual keyboard",
+        "ext_workspace_manager_v1"
+        | "ext_workspace_group_handle_v1"
+        | "ext_workspace_handle_v1" => "workspaces",
+        "hyprland_surface_manager_v1" | "hyprland_surface_v1" => "hyprland surface",
+        "wp_presentation" | "wp_presentation_feedback" => "input latency",
+        _ => return None,
+    };
+
+    Some(feature)
+}
+
+impl AvyClient {
+    ///
+    /// Call `on_fatal_error` when the compositor closes the connection with a
+    /// protocol error, e.g. to save state before exiting.
+    ///
+    /// The client has stopped [running](AvyClient::running) by then, and
+    /// can't send any more requests.
+    ///
+    pub fn on_fatal_error(
+        &mut self,
+        on_fatal_error: impl FnMut(&mut AvyClient, &ProtocolError) + 'static,
+    ) {
+        self.fatal_error.replace(Box::new(on_fatal_error));
+    }
+
+    ///
+    /// The protocol error the compositor closed the connection with, if it has.
+    ///
+    pub fn protocol_error(&self) -> Option<ProtocolError> {
+        let error = self.wl_display.backend().upgrade()?.last_error()?;
+        match error {
+            WaylandError::Protocol(error) => Some(self.enrich_protocol_error(error)),
+            WaylandError::Io(_) => None,
+        }
+    }
+
+    ///
+    /// Dispatch `event_loop` once, like [EventLoop::dispatch].
+    ///
+    /// Timers (the crate's own included) are sources of the loop, which wakes
+    /// up for the earliest of them by itself: with a `timeout` of `None`, it
+    /// sleeps until there's something to do. Wakeups are counted in
+    /// [AvyClient::loop_metrics], as is how long input waited for them.
+    ///
+    /// The loop never waits for a surface to finish rendering on another
+    /// thread: changes to its backend (e.g. suspending it) are put off until
+    /// the frame is done, or the next wakeup. Drawing on the loop's own thread
+    /// is kept to [crate::app::INPUT_BUDGET] where it can be.
+    ///
+    /// A protocol error stops the client, goes to [AvyClient::on_fatal_error],
+    /// and is returned as [AvyError::Protocol]. Losing the connection any other
+    /// way (e.g. as the compositor crashed) stops it too, and is returned as
+    /// [AvyError::Disconnected].
+    ///
+    pub fn dispatch(
+        &mut self,
+        event_loop: &mut EventLoop<'static, AvyClient>,
+        timeout: impl Into<Option<Duration>>,
+    ) -> Result<(), AvyError> {
+        let dispatched = event_loop.dispatch(timeout, self);
+        self.record_wakeup();
+        self.flush_backend_ops();
+        self.forget_released_focus();
+
+        match dispatched {
+            Ok(()) => Ok(()),
+            Err(error) => Err(self
+                .connection_failed()
+                .or_else(|| self.disconnected())
+                .unwrap_or(AvyError::EventLoop(error))),
+        }
+    }
+
+    ///
+    /// Turn a failed dispatch of the event queue into an [AvyError],
+    /// going through [AvyClient::connection_failed] if the connection is gone.
+    ///
+    pub(crate) fn dispatch_failed(&mut self, error: DispatchError) -> AvyError {
+        match error {
+            DispatchError::Backend(WaylandError::Protocol(_)) => self
+                .connection_failed()
+                .unwrap_or(AvyError::Dispatch(error)),
+            DispatchError::Backend(WaylandError::Io(_)) => {
+                self.disconnected().unwrap_or(AvyError::Dispatch(error))
+            }
+            error => AvyError::Dispatch(error),
+        }
+    }
+
+    ///
+    /// If the compositor closed the connection with a protocol error: stop,
+    /// log it, and hand it to [AvyClient::on_fatal_error].
+    ///
+    pub(crate) fn connection_failed(&mut self) -> Option<AvyError> {
+        let error = self.protocol_error()?;
+        log::error!(target: WAYLAND_TARGET, "{error}");
+        self.running = false;
+
+        if let Some(mut on_fatal_error) = self.fatal_error.take() {
+            on_fatal_error(self, &error);
+            self.fatal_error.get_or_insert(on_fatal_error);
+        }
+
+        Some(AvyError::Protocol(error))
+    }
+
+    ///
+    /// If the connection is gone without a protocol error (e.g. as the
+    /// compositor crashed): stop, and log it.
+    ///
+    pub(crate) fn disconnected(&mut self) -> Option<AvyError> {
+        let WaylandError::Io(error) = self.wl_display.backend().upgrade()?.last_error()? else {
+            return None;
+        };
+
+        log::error!(target: WAYLAND_TARGET, "Lost the connection to the compositor: {error}");
+        self.running = false;
+
+        Some(AvyError::Disconnected(error))
+    }
+
+    fn enrich_protocol_error(&self, error: WaylandProtocolError) -> ProtocolError {
+        let surface = self
+            .surfaces
+            .keys()
+            .find(|id| {
+                id.protocol_id() == error.object_id && id.interface().name == error.object_interface
+            })
+            .or_else(|| {
+                self.objects
+                    .surface_of(&error.object_interface, error.object_id)
+            });
+
+        let object_owner = match surface {
+            Some(id) => ObjectOwner::Surface {
+                id: id.clone(),
+                name: self.surface_names.get(id).cloned(),
+            },
+            None => feature_of(&error.object_interface)
+                .map(ObjectOwner::Feature)
+                .unwrap_or(ObjectOwner::Unknown),
+        };
+
+        ProtocolError {
+            interface: error.object_interface,
+            object_id: error.object_id,
+            object_owner,
+            code: error.code,
+            message: error.message,
+        }
+    }
+}
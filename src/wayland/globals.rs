@@ -0,0 +1,53 @@
+//!
+//! Which version of each global the client bound, as requests added in
+//! later versions kill the connection when sent to older ones.
+//!
+
+use smithay_client_toolkit::{
+    reexports::{
+        client::Proxy,
+        protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::ZwlrLayerShellV1,
+    },
+    registry::ProvidesBoundGlobal,
+};
+
+use crate::AvyClient;
+
+///
+/// Bound versions of the compositor's globals, `None` for optional ones it doesn't support.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GlobalVersions {
+    pub compositor: u32,
+    pub shm: u32,
+    /// See [crate::wayland::surface::layer::SET_LAYER_SINCE] and
+    /// [crate::wayland::surface::layer::ON_DEMAND_SINCE].
+    pub layer_shell: u32,
+    pub viewporter: u32,
+    pub fractional_scale: u32,
+    pub cursor_shape: Option<u32>,
+}
+
+impl AvyClient {
+    ///
+    /// The version of `zwlr_layer_shell_v1` layers are created with.
+    ///
+    pub fn layer_shell_version(&self) -> u32 {
+        <_ as ProvidesBoundGlobal<ZwlrLayerShellV1, 1>>::bound_global(&self.layer_state)
+            .map_or(1, |layer_shell| layer_shell.version())
+    }
+
+    pub fn global_versions(&self) -> GlobalVersions {
+        GlobalVersions {
+            compositor: self.compositor_state.wl_compositor().version(),
+            shm: self.shm_state.wl_shm().version(),
+            layer_shell: self.layer_shell_version(),
+            viewporter: self.viewporter.version(),
+            fractional_scale: self.fractional_scale.version(),
+            cursor_shape: self
+                .cursor_shape
+                .as_ref()
+                .map(|manager| manager.inner().version()),
+        }
+    }
+}
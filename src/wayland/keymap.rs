@@ -0,0 +1,133 @@
+//!
+//! The keyboard's current XKB keymap.
+//!
+
+use smithay_client_toolkit::seat::keyboard::Keymap;
+
+///
+/// A snapshot of the keymap in use, taken whenever the compositor sends a new one.
+///
+#[derive(Debug, Clone, Default)]
+pub struct KeymapInfo {
+    text: String,
+    layouts: Vec<String>,
+}
+
+impl KeymapInfo {
+    pub fn new(keymap: &Keymap<'_>) -> Self {
+        Self::from_text(keymap.as_string())
+    }
+
+    ///
+    /// Build from a keymap in the XKB text (v1) format.
+    ///
+    pub fn from_text(text: String) -> Self {
+        let layouts = parse_layout_names(&text);
+        Self { text, layouts }
+    }
+
+    ///
+    /// The keymap, in the XKB text (v1) format.
+    ///
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    ///
+    /// Names of the keymap's layouts (groups), in order.
+    ///
+    pub fn layouts(&self) -> &[String] {
+        &self.layouts
+    }
+
+    ///
+    /// Name of the layout at `index`, as given by [KeyboardHandler::update_modifiers]'s `layout`.
+    ///
+    /// [KeyboardHandler::update_modifiers]: super::surface::KeyboardHandler::update_modifiers
+    ///
+    pub fn layout_name(&self, index: u32) -> Option<&str> {
+        self.layouts.get(index as usize).map(String::as_str)
+    }
+}
+
+///
+/// Pull the `name[GroupN] = "..."` entries out of the `xkb_symbols` section.
+///
+fn parse_layout_names(text: &str) -> Vec<String> {
+    let mut names: Vec<(usize, String)> = text
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().strip_prefix("name[")?;
+            let (group, rest) = line.split_once(']')?;
+
+            let group = group
+                .trim()
+                .strip_prefix("Group")
+                .or_else(|| group.trim().strip_prefix("group"))?
+                .parse::<usize>()
+                .ok()?;
+
+            let (_, value) = rest.split_once('=')?;
+            let value = value.trim().trim_end_matches(';').trim().trim_matches('"');
+
+            Some((group, value.to_owned()))
+        })
+        .collect();
+
+    names.sort_by_key(|(group, _)| *group);
+    names.dedup_by_key(|(group, _)| *group);
+    names.into_iter().map(|(_, name)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYMAP: &str = r#"xkb_keymap {
+xkb_keycodes "evdev+aliases(qwerty)" {
+	minimum = 8;
+	indicator 1 = "Caps Lock";
+};
+xkb_types "complete" {
+	type "TWO_LEVEL" {
+		level_name[Level1]= "Base";
+		level_name[Level2]= "Shift";
+	};
+};
+xkb_symbols "pc+us+de:2+inet(evdev)" {
+	name[Group2]="German";
+	name[Group1]="English (US)";
+	key <AE01> { [ 1, exclam ], [ 1, exclam ] };
+};
+};"#;
+
+    #[test]
+    fn reads_layouts_in_group_order() {
+        let info = KeymapInfo::from_text(KEYMAP.to_owned());
+
+        assert_eq!(info.layouts(), ["English (US)", "German"]);
+        assert_eq!(info.layout_name(1), Some("German"));
+        assert_eq!(info.layout_name(2), None);
+    }
+
+    #[test]
+    fn accepts_lowercase_groups_and_spacing() {
+        let text = "xkb_symbols \"pc+fr\" {\n    name[ group1 ] = \"French\" ;\n};";
+
+        assert_eq!(parse_layout_names(text), ["French"]);
+    }
+
+    #[test]
+    fn keeps_the_first_name_for_a_group() {
+        let text = "name[Group1]=\"First\";\nname[Group1]=\"Second\";";
+
+        assert_eq!(parse_layout_names(text), ["First"]);
+    }
+
+    #[test]
+    fn ignores_everything_else() {
+        assert!(parse_layout_names("").is_empty());
+        assert!(parse_layout_names("name[Level1]=\"Base\";\nname[Group]=\"?\";").is_empty());
+        assert!(parse_layout_names("level_name[Level1]= \"Base\";").is_empty());
+    }
+}
@@ -1,2 +1,8 @@
+pub mod error;
+pub mod globals;
+pub mod keymap;
+pub mod pointer;
 pub mod protocol;
+pub mod seat;
+pub mod serial;
 pub mod surface;
@@ -0,0 +1,198 @@
+//!
+//! Thinning out pointer frames: high-rate mice send batches of motion
+//! events where most surfaces only care about the last position.
+//!
+
+use smithay_client_toolkit::seat::pointer::{AxisScroll, PointerEvent, PointerEventKind};
+
+///
+/// Which pointer events a surface gets of each frame,
+/// see [crate::app::AvySurfaceHandle::set_motion_coalescing].
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MotionCoalescing {
+    /// Every event, as sent: what drawing apps need.
+    #[default]
+    Off,
+    ///
+    /// Runs of motion events collapse into their last one, and runs of scroll
+    /// events (from the same source) into their sum. Other events, and the
+    /// order of everything, are kept.
+    ///
+    PerFrame,
+}
+
+///
+/// `events` (all for the same surface), coalesced as by [MotionCoalescing::PerFrame].
+///
+pub fn coalesce(events: &[PointerEvent]) -> Vec<PointerEvent> {
+    let mut coalesced: Vec<PointerEvent> = Vec::with_capacity(events.len());
+
+    for event in events {
+        let Some(last) = coalesced.last_mut() else {
+            coalesced.push(event.clone());
+            continue;
+        };
+
+        match (&mut last.kind, &event.kind) {
+            (PointerEventKind::Motion { .. }, PointerEventKind::Motion { .. }) => {
+                *last = event.clone();
+            }
+            (
+                PointerEventKind::Axis {
+                    time,
+                    horizontal,
+                    vertical,
+                    source,
+                },
+                PointerEventKind::Axis {
+                    time: next_time,
+                    horizontal: next_horizontal,
+                    vertical: next_vertical,
+                    source: next_source,
+                },
+            ) if source == next_source => {
+                *time = *next_time;
+                merge(horizontal, next_horizontal);
+                merge(vertical, next_vertical);
+                last.position = event.position;
+            }
+            _ => coalesced.push(event.clone()),
+        }
+    }
+
+    coalesced
+}
+
+fn merge(scroll: &mut AxisScroll, next: &AxisScroll) {
+    scroll.absolute += next.absolute;
+    scroll.discrete += next.discrete;
+    scroll.stop |= next.stop;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use smithay_client_toolkit::reexports::client::{
+        protocol::{wl_pointer::AxisSource, wl_surface::WlSurface},
+        Connection, Proxy,
+    };
+
+    use super::*;
+
+    /// A surface which isn't backed by anything, to address events to.
+    fn surface() -> WlSurface {
+        let (socket, _) = UnixStream::pair().unwrap();
+        let connection = Connection::from_socket(socket).unwrap();
+        WlSurface::inert(connection.backend().downgrade())
+    }
+
+    fn motion(surface: &WlSurface, x: f64, time: u32) -> PointerEvent {
+        PointerEvent {
+            surface: surface.clone(),
+            position: (x, 0.0),
+            kind: PointerEventKind::Motion { time },
+        }
+    }
+
+    fn scroll(surface: &WlSurface, steps: i32, source: AxisSource) -> PointerEvent {
+        PointerEvent {
+            surface: surface.clone(),
+            position: (0.0, 0.0),
+            kind: PointerEventKind::Axis {
+                time: 0,
+                horizontal: AxisScroll::default(),
+                vertical: AxisScroll {
+                    absolute: steps as f64 * 15.0,
+                    discrete: steps,
+                    stop: false,
+                },
+                source: Some(source),
+            },
+        }
+    }
+
+    fn press(surface: &WlSurface) -> PointerEvent {
+        PointerEvent {
+            surface: surface.clone(),
+            position: (0.0, 0.0),
+            kind: PointerEventKind::Press {
+                time: 0,
+                button: 0x110,
+                serial: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn motion_runs_collapse_into_the_last_one() {
+        let surface = surface();
+        let events: Vec<_> = (0..8).map(|x| motion(&surface, x as f64, x)).collect();
+
+        let coalesced = coalesce(&events);
+
+        assert_eq!(coalesced.len(), 1);
+        assert_eq!(coalesced[0].position, (7.0, 0.0));
+        assert!(matches!(
+            coalesced[0].kind,
+            PointerEventKind::Motion { time: 7 }
+        ));
+    }
+
+    #[test]
+    fn other_events_break_runs_and_keep_their_place() {
+        let surface = surface();
+        let events = [
+            motion(&surface, 1.0, 1),
+            motion(&surface, 2.0, 2),
+            press(&surface),
+            motion(&surface, 3.0, 3),
+            motion(&surface, 4.0, 4),
+        ];
+
+        let coalesced = coalesce(&events);
+
+        let positions: Vec<_> = coalesced.iter().map(|event| event.position.0).collect();
+        assert_eq!(positions, [2.0, 0.0, 4.0]);
+        assert!(matches!(coalesced[1].kind, PointerEventKind::Press { .. }));
+    }
+
+    #[test]
+    fn scrolls_from_one_source_are_summed() {
+        let surface = surface();
+        let events = [
+            scroll(&surface, 1, AxisSource::Wheel),
+            scroll(&surface, 2, AxisSource::Wheel),
+            scroll(&surface, -1, AxisSource::Wheel),
+        ];
+
+        let coalesced = coalesce(&events);
+
+        assert_eq!(coalesced.len(), 1);
+        let PointerEventKind::Axis { vertical, .. } = &coalesced[0].kind else {
+            panic!("Expected a scroll, got {:?}", coalesced[0].kind);
+        };
+        assert_eq!(vertical.discrete, 2);
+        assert_eq!(vertical.absolute, 30.0);
+    }
+
+    #[test]
+    fn scrolls_from_different_sources_are_kept_apart() {
+        let surface = surface();
+        let events = [
+            scroll(&surface, 1, AxisSource::Wheel),
+            scroll(&surface, 1, AxisSource::Finger),
+        ];
+
+        assert_eq!(coalesce(&events).len(), 2);
+    }
+
+    #[test]
+    fn nothing_to_coalesce_is_kept_as_is() {
+        let surface = surface();
+
+        assert!(coalesce(&[]).is_empty());
+        assert_eq!(coalesce(&[press(&surface)]).len(), 1);
+    }
+}
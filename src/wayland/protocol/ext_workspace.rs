@@ -0,0 +1,312 @@
+//!
+//! `ext_workspace_v1`, which lists the compositor's workspaces (in groups,
+//! each shown on a set of outputs) and lets clients switch between them.
+//!
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::client::{
+        event_created_child,
+        globals::{BindError, GlobalList},
+        protocol::wl_output::WlOutput,
+        Connection, Dispatch, Proxy, QueueHandle, WEnum,
+    },
+};
+
+#[allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#[allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#[allow(missing_docs, clippy::all)]
+pub mod client {
+    use smithay_client_toolkit::reexports::client as wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use smithay_client_toolkit::reexports::client as wayland_client;
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/ext-workspace-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/ext-workspace-v1.xml");
+}
+
+use client::{
+    ext_workspace_group_handle_v1::{self, ExtWorkspaceGroupHandleV1},
+    ext_workspace_handle_v1::{self, ExtWorkspaceHandleV1},
+    ext_workspace_manager_v1::{self, ExtWorkspaceManagerV1},
+};
+
+///
+/// A group of workspaces, shown on a set of outputs.
+///
+#[derive(Debug, Clone)]
+pub struct WorkspaceGroup {
+    pub handle: ExtWorkspaceGroupHandleV1,
+    pub outputs: Vec<WlOutput>,
+    pub workspaces: Vec<ExtWorkspaceHandleV1>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtWorkspace {
+    pub handle: ExtWorkspaceHandleV1,
+    /// Stable id, if the compositor hands one out.
+    pub id: Option<String>,
+    pub name: String,
+    /// Position in the group's grid, if it has one.
+    pub coordinates: Vec<u32>,
+    pub state: ext_workspace_handle_v1::State,
+    pub capabilities: ext_workspace_handle_v1::WorkspaceCapabilities,
+}
+
+///
+/// Everything the compositor told us about its workspaces.
+///
+/// Changes arrive in batches, each completed by
+/// [WorkspaceHandler::workspaces_changed].
+///
+#[derive(Debug)]
+pub struct ExtWorkspaceState {
+    manager: ExtWorkspaceManagerV1,
+    groups: Vec<WorkspaceGroup>,
+    workspaces: Vec<ExtWorkspace>,
+}
+
+impl ExtWorkspaceState {
+    pub fn bind<State: Dispatch<ExtWorkspaceManagerV1, GlobalData> + 'static>(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<State>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+
+        Ok(Self {
+            manager,
+            groups: Vec::new(),
+            workspaces: Vec::new(),
+        })
+    }
+
+    pub fn groups(&self) -> &[WorkspaceGroup] {
+        &self.groups
+    }
+
+    pub fn workspaces(&self) -> &[ExtWorkspace] {
+        &self.workspaces
+    }
+
+    ///
+    /// Ask the compositor to switch to `workspace`.
+    ///
+    pub fn activate(&self, workspace: &ExtWorkspaceHandleV1) {
+        workspace.activate();
+        self.manager.commit();
+    }
+
+    fn workspace_mut(&mut self, handle: &ExtWorkspaceHandleV1) -> Option<&mut ExtWorkspace> {
+        self.workspaces
+            .iter_mut()
+            .find(|workspace| &workspace.handle == handle)
+    }
+
+    fn group_mut(&mut self, handle: &ExtWorkspaceGroupHandleV1) -> Option<&mut WorkspaceGroup> {
+        self.groups.iter_mut().find(|group| &group.handle == handle)
+    }
+}
+
+pub trait WorkspaceHandler: Sized {
+    ///
+    /// The state, unless [WorkspaceHandler::workspaces_finished] dropped it.
+    ///
+    fn ext_workspace_state(&mut self) -> Option<&mut ExtWorkspaceState>;
+
+    ///
+    /// A batch of changes to workspaces (or their groups) is complete.
+    ///
+    fn workspaces_changed(&mut self, conn: &Connection, qh: &QueueHandle<Self>);
+
+    ///
+    /// The compositor stopped sharing its workspaces, and destroyed the
+    /// manager: drop the [ExtWorkspaceState], whose handles are all stale.
+    ///
+    fn workspaces_finished(&mut self, conn: &Connection, qh: &QueueHandle<Self>);
+}
+
+impl<State> Dispatch<ExtWorkspaceManagerV1, GlobalData, State> for ExtWorkspaceState
+where
+    State: Dispatch<ExtWorkspaceManagerV1, GlobalData>
+        + Dispatch<ExtWorkspaceGroupHandleV1, ()>
+        + Dispatch<ExtWorkspaceHandleV1, ()>
+        + WorkspaceHandler
+        + 'static,
+{
+    fn event(
+        state: &mut State,
+        _: &ExtWorkspaceManagerV1,
+        event: ext_workspace_manager_v1::Event,
+        _: &GlobalData,
+        conn: &Connection,
+        qhandle: &QueueHandle<State>,
+    ) {
+        if let ext_workspace_manager_v1::Event::Finished = event {
+            log::debug!("The compositor stopped sending workspace events.");
+            state.workspaces_finished(conn, qhandle);
+            return;
+        }
+
+        let Some(workspaces) = state.ext_workspace_state() else {
+            return;
+        };
+
+        match event {
+            ext_workspace_manager_v1::Event::WorkspaceGroup { workspace_group } => {
+                workspaces.groups.push(WorkspaceGroup {
+                    handle: workspace_group,
+                    outputs: Vec::new(),
+                    workspaces: Vec::new(),
+                });
+            }
+            ext_workspace_manager_v1::Event::Workspace { workspace } => {
+                workspaces.workspaces.push(ExtWorkspace {
+                    handle: workspace,
+                    id: None,
+                    name: String::new(),
+                    coordinates: Vec::new(),
+                    state: ext_workspace_handle_v1::State::empty(),
+                    capabilities: ext_workspace_handle_v1::WorkspaceCapabilities::empty(),
+                });
+            }
+            ext_workspace_manager_v1::Event::Done => state.workspaces_changed(conn, qhandle),
+            event => log::debug!("Ignoring an unknown workspace manager event: {event:?}"),
+        }
+    }
+
+    event_created_child!(State, ExtWorkspaceManagerV1, [
+        ext_workspace_manager_v1::EVT_WORKSPACE_GROUP_OPCODE => (ExtWorkspaceGroupHandleV1, ()),
+        ext_workspace_manager_v1::EVT_WORKSPACE_OPCODE => (ExtWorkspaceHandleV1, ()),
+    ]);
+}
+
+impl<State> Dispatch<ExtWorkspaceGroupHandleV1, (), State> for ExtWorkspaceState
+where
+    State: Dispatch<ExtWorkspaceGroupHandleV1, ()> + WorkspaceHandler,
+{
+    fn event(
+        state: &mut State,
+        proxy: &ExtWorkspaceGroupHandleV1,
+        event: ext_workspace_group_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<State>,
+    ) {
+        if let ext_workspace_group_handle_v1::Event::Removed = event {
+            if let Some(workspaces) = state.ext_workspace_state() {
+                workspaces.groups.retain(|group| &group.handle != proxy);
+            }
+
+            proxy.destroy();
+            return;
+        }
+
+        let Some(group) = state
+            .ext_workspace_state()
+            .and_then(|workspaces| workspaces.group_mut(proxy))
+        else {
+            return;
+        };
+
+        match event {
+            ext_workspace_group_handle_v1::Event::OutputEnter { output } => {
+                group.outputs.push(output);
+            }
+            ext_workspace_group_handle_v1::Event::OutputLeave { output } => {
+                group.outputs.retain(|other| other != &output);
+            }
+            ext_workspace_group_handle_v1::Event::WorkspaceEnter { workspace } => {
+                group.workspaces.push(workspace);
+            }
+            ext_workspace_group_handle_v1::Event::WorkspaceLeave { workspace } => {
+                group.workspaces.retain(|other| other != &workspace);
+            }
+            // We never create workspaces.
+            ext_workspace_group_handle_v1::Event::Capabilities { .. } => {}
+            event => log::debug!("Ignoring an unknown workspace group event: {event:?}"),
+        }
+    }
+}
+
+impl<State> Dispatch<ExtWorkspaceHandleV1, (), State> for ExtWorkspaceState
+where
+    State: Dispatch<ExtWorkspaceHandleV1, ()> + WorkspaceHandler,
+{
+    fn event(
+        state: &mut State,
+        proxy: &ExtWorkspaceHandleV1,
+        event: ext_workspace_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<State>,
+    ) {
+        if let ext_workspace_handle_v1::Event::Removed = event {
+            if let Some(workspaces) = state.ext_workspace_state() {
+                workspaces
+                    .workspaces
+                    .retain(|workspace| &workspace.handle != proxy);
+                for group in &mut workspaces.groups {
+                    group.workspaces.retain(|other| other != proxy);
+                }
+            }
+
+            proxy.destroy();
+            return;
+        }
+
+        let Some(workspace) = state
+            .ext_workspace_state()
+            .and_then(|workspaces| workspaces.workspace_mut(proxy))
+        else {
+            return;
+        };
+
+        match event {
+            ext_workspace_handle_v1::Event::Id { id } => workspace.id = Some(id),
+            ext_workspace_handle_v1::Event::Name { name } => workspace.name = name,
+            ext_workspace_handle_v1::Event::Coordinates { coordinates } => {
+                workspace.coordinates = coordinates
+                    .chunks_exact(4)
+                    .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+                    .collect();
+            }
+            ext_workspace_handle_v1::Event::State { state } => {
+                workspace.state = match state {
+                    WEnum::Value(state) => state,
+                    WEnum::Unknown(bits) => {
+                        ext_workspace_handle_v1::State::from_bits_truncate(bits)
+                    }
+                };
+            }
+            ext_workspace_handle_v1::Event::Capabilities { capabilities } => {
+                workspace.capabilities = match capabilities {
+                    WEnum::Value(capabilities) => capabilities,
+                    WEnum::Unknown(bits) => {
+                        ext_workspace_handle_v1::WorkspaceCapabilities::from_bits_truncate(bits)
+                    }
+                };
+            }
+            event => log::debug!("Ignoring an unknown workspace event: {event:?}"),
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_ext_workspace {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::ext_workspace::client::ext_workspace_manager_v1::ExtWorkspaceManagerV1: smithay_client_toolkit::globals::GlobalData
+        ] => $crate::wayland::protocol::ext_workspace::ExtWorkspaceState);
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::ext_workspace::client::ext_workspace_group_handle_v1::ExtWorkspaceGroupHandleV1: ()
+        ] => $crate::wayland::protocol::ext_workspace::ExtWorkspaceState);
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::ext_workspace::client::ext_workspace_handle_v1::ExtWorkspaceHandleV1: ()
+        ] => $crate::wayland::protocol::ext_workspace::ExtWorkspaceState);
+    };
+}
@@ -1,10 +1,15 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
 use smithay_client_toolkit::{
     globals::GlobalData,
     reexports::{
         client::{
             globals::{BindError, GlobalList},
             protocol::wl_surface::WlSurface,
-            Dispatch, QueueHandle,
+            Dispatch, Proxy, QueueHandle,
         },
         protocols::wp::fractional_scale::v1::client::{
             self as fractional_scale, wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
@@ -12,11 +17,33 @@ use smithay_client_toolkit::{
         },
     },
 };
+use thiserror::Error;
+
+use crate::debug;
+
+///
+/// How often, at most, scales the compositor shouldn't have sent are warned about.
+///
+pub const INVALID_SCALE_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// When the last warning was, and how many invalid scales came since.
+static INVALID_SCALE_WARNED: Mutex<Option<(Instant, u32)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ScaleError {
+    #[error("A scale of 0 would leave nothing to draw into.")]
+    Zero,
+    #[error("A scale of {numerator}/120 is above the maximum of {}/120.", ScaleFactor::MAX.raw())]
+    TooLarge { numerator: u32 },
+}
 
 ///
 /// Represents a valid fractional scale.
 ///
-#[derive(Clone, Copy)]
+/// Stored as the protocol sends it, in 120ths, so that the integer
+/// methods ([ScaleFactor::scale_ceil] and co.) are exact.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ScaleFactor(u32);
 
 impl std::fmt::Debug for ScaleFactor {
@@ -33,12 +60,109 @@ impl ScaleFactor {
     ///
     pub const DENOMINATOR: f64 = 120.0;
 
+    const DENOMINATOR_INT: u64 = 120;
+
+    ///
+    /// The largest scale taken from the compositor, 10x: anything above
+    /// is a compositor bug, and would make for absurdly large buffers.
+    ///
+    pub const MAX: Self = Self(10 * Self::DENOMINATOR_INT as u32);
+
+    ///
+    /// A scale of `numerator / 120`, as sent by the compositor.
+    ///
+    /// Not checked: see [ScaleFactor::try_new] and [ScaleFactor::validate].
+    ///
+    pub const fn from_raw(numerator: u32) -> Self {
+        Self(numerator)
+    }
+
+    ///
+    /// A scale of `numerator / 120`, if it's above 0 and at most [ScaleFactor::MAX].
+    ///
+    pub const fn try_new(numerator: u32) -> Result<Self, ScaleError> {
+        Self(numerator).validate()
+    }
+
+    ///
+    /// This scale, if it's one surfaces can be drawn at (see [ScaleFactor::try_new]).
+    ///
+    pub const fn validate(self) -> Result<Self, ScaleError> {
+        match self.0 {
+            0 => Err(ScaleError::Zero),
+            numerator if numerator > Self::MAX.0 => Err(ScaleError::TooLarge { numerator }),
+            _ => Ok(self),
+        }
+    }
+
+    ///
+    /// The closest scale the protocol can express to `scale`.
+    ///
+    pub fn from_f64(scale: f64) -> Self {
+        Self((scale * Self::DENOMINATOR).round() as u32)
+    }
+
+    ///
+    /// The scale's numerator, over [ScaleFactor::DENOMINATOR].
+    ///
+    pub const fn raw(&self) -> u32 {
+        self.0
+    }
+
     pub fn as_f64(&self) -> f64 {
         self.0 as f64 / Self::DENOMINATOR
     }
 
+    ///
+    /// Whether the scale is a whole number, so that
+    /// logical pixels map to whole physical pixels.
+    ///
+    pub const fn is_integer(&self) -> bool {
+        self.0 as u64 % Self::DENOMINATOR_INT == 0
+    }
+
     pub fn scale<T: Into<f64>>(&self, dim: T) -> f64 {
-        (dim.into() * self.as_f64()).round() // Round half away from zero.
+        // Dividing last, so that halves (e.g. 99 * 260/120 = 214.5) are exact.
+        (dim.into() * self.0 as f64 / Self::DENOMINATOR).round() // Round half away from zero.
+    }
+
+    ///
+    /// `dim` scaled up, rounding up to a whole physical pixel.
+    ///
+    pub const fn scale_ceil(&self, dim: u32) -> u32 {
+        let scaled = dim as u64 * self.0 as u64;
+        scaled.div_ceil(Self::DENOMINATOR_INT) as u32
+    }
+
+    ///
+    /// `dim` scaled up, rounding down to a whole physical pixel.
+    ///
+    pub const fn scale_floor(&self, dim: u32) -> u32 {
+        (dim as u64 * self.0 as u64 / Self::DENOMINATOR_INT) as u32
+    }
+
+    ///
+    /// Logical to physical pixels, rounding half away from zero (as [ScaleFactor::scale]).
+    ///
+    pub const fn to_physical(&self, logical: u32) -> u32 {
+        let scaled = logical as u64 * self.0 as u64;
+        ((scaled + Self::DENOMINATOR_INT / 2) / Self::DENOMINATOR_INT) as u32
+    }
+
+    ///
+    /// Physical to logical pixels, rounding half away from zero.
+    ///
+    /// For scales of 1 or more, this undoes [ScaleFactor::to_physical] exactly.
+    /// Below 1, several logical sizes share a physical one.
+    ///
+    pub const fn to_logical(&self, physical: u32) -> u32 {
+        if self.0 == 0 {
+            return 0;
+        }
+
+        let scaled = physical as u64 * Self::DENOMINATOR_INT;
+        let numerator = self.0 as u64;
+        ((scaled + numerator / 2) / numerator) as u32
     }
 }
 
@@ -56,6 +180,10 @@ impl FractionalScaleManager {
         Ok(Self { manager })
     }
 
+    pub fn version(&self) -> u32 {
+        self.manager.version()
+    }
+
     pub fn fractional_scaling<State: Dispatch<WpFractionalScaleV1, FractionalScale> + 'static>(
         &self,
         surface: &WlSurface,
@@ -64,8 +192,12 @@ impl FractionalScaleManager {
         let data = FractionalScale {
             surface: surface.clone(),
         };
-        self.manager
-            .get_fractional_scale(surface, queue_handle, data)
+        let fractional_scale = self
+            .manager
+            .get_fractional_scale(surface, queue_handle, data);
+
+        debug::objects::created(&fractional_scale.id(), Some(&surface.id()));
+        fractional_scale
     }
 }
 
@@ -81,6 +213,11 @@ pub trait FractionalScaleHandler: Sized {
         surface: &WlSurface,
         factor: ScaleFactor,
     );
+
+    ///
+    /// The time, for rate-limiting warnings about invalid scales.
+    ///
+    fn now(&self) -> Instant;
 }
 
 impl<State> Dispatch<WpFractionalScaleV1, FractionalScale, State> for FractionalScale
@@ -96,7 +233,11 @@ where
         qhandle: &QueueHandle<State>,
     ) {
         if let fractional_scale::wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
-            state.scale_factor_changed(conn, qhandle, &data.surface, ScaleFactor(scale));
+            // Keep the previous scale (or none) rather than draw at a broken one.
+            match ScaleFactor::try_new(scale) {
+                Ok(factor) => state.scale_factor_changed(conn, qhandle, &data.surface, factor),
+                Err(err) => warn_invalid_scale(&data.surface, err, state.now()),
+            }
             return;
         }
 
@@ -104,6 +245,42 @@ where
     }
 }
 
+///
+/// Warn about the compositor sending an invalid scale (at `now`),
+/// at most once every [INVALID_SCALE_WARNING_INTERVAL].
+///
+fn warn_invalid_scale(surface: &WlSurface, err: ScaleError, now: Instant) {
+    let mut warned = INVALID_SCALE_WARNED.lock().unwrap();
+
+    if let Some(suppressed) = should_warn(&mut warned, now) {
+        log::warn!(
+            "The compositor sent an invalid preferred scale for {}, keeping the previous one: \
+             {err} ({suppressed} more ignored since the last warning)",
+            surface.id()
+        );
+    }
+}
+
+///
+/// Whether to warn about an invalid scale arriving `now`, given when the last
+/// warning was: if so, with how many were ignored since, otherwise counting this one.
+///
+fn should_warn(warned: &mut Option<(Instant, u32)>, now: Instant) -> Option<u32> {
+    match warned.as_mut() {
+        Some((last, suppressed))
+            if now.saturating_duration_since(*last) < INVALID_SCALE_WARNING_INTERVAL =>
+        {
+            *suppressed += 1;
+            None
+        }
+        _ => {
+            let suppressed = warned.map_or(0, |(_, suppressed)| suppressed);
+            warned.replace((now, 0));
+            Some(suppressed)
+        }
+    }
+}
+
 impl<State> Dispatch<WpFractionalScaleManagerV1, GlobalData, State> for FractionalScaleManager
 where
     State: Dispatch<WpFractionalScaleManagerV1, GlobalData> + FractionalScaleHandler,
@@ -131,3 +308,150 @@ macro_rules! delegate_fractional_scale {
         ] => $crate::wayland::protocol::fractional_scale::FractionalScale);
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every scale the compositor may send, from 1/120 to [ScaleFactor::MAX].
+    fn every_scale() -> impl Iterator<Item = ScaleFactor> {
+        (1..=ScaleFactor::MAX.raw()).map(ScaleFactor::from_raw)
+    }
+
+    #[test]
+    fn whole_scales_are_integers() {
+        assert!(ScaleFactor::from_raw(120).is_integer());
+        assert!(ScaleFactor::from_raw(240).is_integer());
+        assert!(!ScaleFactor::from_raw(150).is_integer());
+        assert!(!ScaleFactor::from_raw(180).is_integer());
+    }
+
+    #[test]
+    fn ceil_and_floor_are_exact() {
+        let scale = ScaleFactor::from_f64(1.25);
+
+        // 101 * 1.25 = 126.25
+        assert_eq!(scale.scale_ceil(101), 127);
+        assert_eq!(scale.scale_floor(101), 126);
+        // 1920 * 1.25 = 2400, with nothing to round.
+        assert_eq!(scale.scale_ceil(1920), 2400);
+        assert_eq!(scale.scale_floor(1920), 2400);
+    }
+
+    #[test]
+    fn ceil_and_floor_agree_on_whole_products() {
+        for scale in every_scale() {
+            for dim in [0, 1, 7, 120, 1080, 3840] {
+                let exact = dim as u64 * scale.raw() as u64 % 120 == 0;
+                assert_eq!(
+                    scale.scale_ceil(dim) == scale.scale_floor(dim),
+                    exact,
+                    "{dim} at {scale:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn to_physical_rounds_half_away_from_zero() {
+        let scale = ScaleFactor::from_f64(1.5);
+
+        // 5 * 1.5 = 7.5
+        assert_eq!(scale.to_physical(5), 8);
+        assert_eq!(scale.to_physical(4), 6);
+
+        for scale in every_scale() {
+            for logical in [0, 1, 3, 5, 99, 1000] {
+                assert_eq!(
+                    scale.to_physical(logical) as f64,
+                    scale.scale(logical),
+                    "{logical} at {scale:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn to_logical_undoes_to_physical_from_a_scale_of_one() {
+        for scale in every_scale().filter(|scale| scale.raw() >= 120) {
+            for logical in 0..=2000 {
+                assert_eq!(
+                    scale.to_logical(scale.to_physical(logical)),
+                    logical,
+                    "{logical} at {scale:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn large_sizes_dont_overflow() {
+        let dim = u32::MAX / 10;
+
+        assert_eq!(ScaleFactor::MAX.scale_floor(dim), dim * 10);
+        assert_eq!(ScaleFactor::MAX.scale_ceil(dim), dim * 10);
+        assert_eq!(ScaleFactor::MAX.to_logical(dim * 10), dim);
+    }
+
+    #[test]
+    fn from_f64_rounds_to_the_nearest_120th() {
+        assert_eq!(ScaleFactor::from_f64(1.0).raw(), 120);
+        assert_eq!(ScaleFactor::from_f64(1.25).raw(), 150);
+        // 1.333... * 120 = 160
+        assert_eq!(ScaleFactor::from_f64(4.0 / 3.0).raw(), 160);
+        assert_eq!(ScaleFactor::from_f64(1.0042).raw(), 121);
+    }
+
+    #[test]
+    fn rejects_zero_and_absurd_scales() {
+        assert_eq!(ScaleFactor::try_new(0), Err(ScaleError::Zero));
+        assert_eq!(
+            ScaleFactor::try_new(100_000),
+            Err(ScaleError::TooLarge { numerator: 100_000 })
+        );
+        assert_eq!(
+            ScaleFactor::try_new(ScaleFactor::MAX.raw() + 1),
+            Err(ScaleError::TooLarge { numerator: 1201 })
+        );
+        assert_eq!(
+            ScaleFactor::from_f64(0.001).validate(),
+            Err(ScaleError::Zero)
+        );
+    }
+
+    #[test]
+    fn accepts_odd_but_valid_scales() {
+        for numerator in [1, 119, 120, 121, ScaleFactor::MAX.raw()] {
+            assert_eq!(
+                ScaleFactor::try_new(numerator).map(|scale| scale.raw()),
+                Ok(numerator)
+            );
+        }
+
+        // Still draws into at least a pixel.
+        let tiny = ScaleFactor::try_new(1).unwrap();
+        assert_eq!(tiny.scale_ceil(1), 1);
+        assert_eq!(tiny.scale_floor(1), 0);
+    }
+
+    #[test]
+    fn invalid_scale_warnings_are_rate_limited() {
+        let start = Instant::now();
+        let mut warned = None;
+
+        assert_eq!(should_warn(&mut warned, start), Some(0));
+        assert_eq!(
+            should_warn(&mut warned, start + Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(
+            should_warn(&mut warned, start + Duration::from_secs(2)),
+            None
+        );
+
+        // Reporting the two ignored since.
+        let later = start + INVALID_SCALE_WARNING_INTERVAL;
+        assert_eq!(should_warn(&mut warned, later), Some(2));
+        assert_eq!(should_warn(&mut warned, later), None);
+    }
+}
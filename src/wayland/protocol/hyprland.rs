@@ -0,0 +1,190 @@
+//!
+//! Hyprland's `hyprland_surface_v1`, for properties the compositor applies
+//! to a whole surface (such as its opacity, blur behind it included), rather
+//! than drawing tricks.
+//!
+//! Support is detected by the `hyprland_surface_manager_v1` global being
+//! advertised, whichever compositor that is.
+//!
+
+use std::sync::Mutex;
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::client::{
+        globals::{BindError, GlobalList},
+        protocol::wl_surface::WlSurface,
+        Dispatch, QueueHandle,
+    },
+};
+use thiserror::Error;
+
+use crate::util::DirtyFlag;
+
+#[allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#[allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#[allow(missing_docs, clippy::all)]
+pub mod client {
+    use smithay_client_toolkit::reexports::client as wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use smithay_client_toolkit::reexports::client as wayland_client;
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/hyprland-surface-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/hyprland-surface-v1.xml");
+}
+
+use client::{
+    hyprland_surface_manager_v1::HyprlandSurfaceManagerV1, hyprland_surface_v1::HyprlandSurfaceV1,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum HyprlandError {
+    #[error("The compositor doesn't support hyprland_surface_v1.")]
+    Unsupported,
+    #[error("Opacity must be between 0 and 1, not {0}.")]
+    OutOfRange(f32),
+}
+
+#[derive(Debug)]
+pub struct HyprlandSurfaceManager {
+    manager: HyprlandSurfaceManagerV1,
+}
+
+impl HyprlandSurfaceManager {
+    pub fn new<State: Dispatch<HyprlandSurfaceManagerV1, GlobalData> + 'static>(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<State>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    ///
+    /// The Hyprland object for `surface`. Each surface can only ever have one.
+    ///
+    pub fn get_surface<State: Dispatch<HyprlandSurfaceV1, ()> + 'static>(
+        &self,
+        surface: &WlSurface,
+        queue_handle: &QueueHandle<State>,
+    ) -> HyprlandSurfaceV1 {
+        self.manager.get_hyprland_surface(surface, queue_handle, ())
+    }
+}
+
+///
+/// A surface's Hyprland-specific properties, see
+/// [crate::app::AvySurfaceHandle::hyprland].
+///
+/// Everything fails with [HyprlandError::Unsupported] on compositors
+/// without `hyprland_surface_v1`.
+///
+#[derive(Default)]
+pub struct HyprlandSurface {
+    object: Mutex<Option<(HyprlandSurfaceV1, DirtyFlag)>>,
+    /// As last sent to the compositor.
+    opacity: Mutex<Option<f32>>,
+}
+
+impl HyprlandSurface {
+    ///
+    /// Start using `object`, committing changes by marking `dirty`.
+    ///
+    pub(crate) fn bind(&self, object: HyprlandSurfaceV1, dirty: DirtyFlag) {
+        self.object.lock().unwrap().replace((object, dirty));
+    }
+
+    ///
+    /// Destroy the Hyprland object, before its surface is.
+    ///
+    pub(crate) fn destroy(&self) {
+        if let Some((object, _)) = self.object.lock().unwrap().take() {
+            object.destroy();
+        }
+
+        self.opacity.lock().unwrap().take();
+    }
+
+    ///
+    /// Whether the compositor supports (and so honours) these properties.
+    ///
+    pub fn is_supported(&self) -> bool {
+        self.object.lock().unwrap().is_some()
+    }
+
+    ///
+    /// Multiply the opacity of the whole surface (and of the blur behind it)
+    /// by `opacity`, from the next frame on.
+    ///
+    pub fn set_opacity(&self, opacity: f32) -> Result<(), HyprlandError> {
+        // Anything else is a protocol error.
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err(HyprlandError::OutOfRange(opacity));
+        }
+
+        let object = self.object.lock().unwrap();
+        let (object, dirty) = object.as_ref().ok_or(HyprlandError::Unsupported)?;
+
+        object.set_opacity(opacity as f64);
+        self.opacity.lock().unwrap().replace(opacity);
+        dirty.mark();
+
+        Ok(())
+    }
+
+    ///
+    /// The opacity the compositor was last asked for, or `None` if it hasn't
+    /// been (or can't be, see [HyprlandSurface::is_supported]).
+    ///
+    pub fn opacity(&self) -> Option<f32> {
+        *self.opacity.lock().unwrap()
+    }
+}
+
+impl<State> Dispatch<HyprlandSurfaceManagerV1, GlobalData, State> for HyprlandSurfaceManager
+where
+    State: Dispatch<HyprlandSurfaceManagerV1, GlobalData>,
+{
+    fn event(
+        _: &mut State,
+        _: &HyprlandSurfaceManagerV1,
+        _: <HyprlandSurfaceManagerV1 as smithay_client_toolkit::reexports::client::Proxy>::Event,
+        _: &GlobalData,
+        _: &smithay_client_toolkit::reexports::client::Connection,
+        _: &QueueHandle<State>,
+    ) {
+        unimplemented!("No events for HyprlandSurfaceManagerV1")
+    }
+}
+
+impl<State> Dispatch<HyprlandSurfaceV1, (), State> for HyprlandSurface
+where
+    State: Dispatch<HyprlandSurfaceV1, ()>,
+{
+    fn event(
+        _: &mut State,
+        _: &HyprlandSurfaceV1,
+        _: <HyprlandSurfaceV1 as smithay_client_toolkit::reexports::client::Proxy>::Event,
+        _: &(),
+        _: &smithay_client_toolkit::reexports::client::Connection,
+        _: &QueueHandle<State>,
+    ) {
+        unimplemented!("No events for HyprlandSurfaceV1")
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_hyprland_surface {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::hyprland::client::hyprland_surface_manager_v1::HyprlandSurfaceManagerV1: smithay_client_toolkit::globals::GlobalData
+        ] => $crate::wayland::protocol::hyprland::HyprlandSurfaceManager);
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::hyprland::client::hyprland_surface_v1::HyprlandSurfaceV1: ()
+        ] => $crate::wayland::protocol::hyprland::HyprlandSurface);
+    };
+}
@@ -0,0 +1,168 @@
+//!
+//! Hyprland's `hyprland_global_shortcuts_v1`, which lets clients react
+//! to key combinations bound in the compositor's configuration.
+//!
+
+use std::time::Duration;
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::client::{
+        globals::{BindError, GlobalList},
+        Dispatch, QueueHandle,
+    },
+};
+
+use crate::shortcuts::ShortcutState;
+
+#[allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#[allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#[allow(missing_docs, clippy::all)]
+pub mod client {
+    use smithay_client_toolkit::reexports::client as wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use smithay_client_toolkit::reexports::client as wayland_client;
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/hyprland-global-shortcuts-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/hyprland-global-shortcuts-v1.xml");
+}
+
+use client::{
+    hyprland_global_shortcut_v1::{self, HyprlandGlobalShortcutV1},
+    hyprland_global_shortcuts_manager_v1::HyprlandGlobalShortcutsManagerV1,
+};
+
+#[derive(Debug)]
+pub struct GlobalShortcutsManager {
+    manager: HyprlandGlobalShortcutsManagerV1,
+}
+
+impl GlobalShortcutsManager {
+    pub fn new<State: Dispatch<HyprlandGlobalShortcutsManagerV1, GlobalData> + 'static>(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<State>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    ///
+    /// Register a shortcut, which the user then binds to keys in Hyprland's
+    /// configuration (`bind = SUPER, space, global, app_id:id`).
+    ///
+    /// The compositor kills the connection if `app_id` and `id`
+    /// have already been registered, by any client.
+    ///
+    pub fn register<State: Dispatch<HyprlandGlobalShortcutV1, GlobalShortcut> + 'static>(
+        &self,
+        queue_handle: &QueueHandle<State>,
+        id: &str,
+        app_id: &str,
+        description: &str,
+        trigger_description: &str,
+    ) -> HyprlandGlobalShortcutV1 {
+        self.manager.register_shortcut(
+            id.to_owned(),
+            app_id.to_owned(),
+            description.to_owned(),
+            trigger_description.to_owned(),
+            queue_handle,
+            GlobalShortcut { id: id.to_owned() },
+        )
+    }
+}
+
+pub struct GlobalShortcut {
+    id: String,
+}
+
+pub trait GlobalShortcutHandler: Sized {
+    ///
+    /// The shortcut registered as `id` was pressed or released, at `timestamp`
+    /// (on the compositor's clock).
+    ///
+    fn global_shortcut(
+        &mut self,
+        connection: &smithay_client_toolkit::reexports::client::Connection,
+        qh: &QueueHandle<Self>,
+        id: &str,
+        state: ShortcutState,
+        timestamp: Duration,
+    );
+}
+
+impl<State> Dispatch<HyprlandGlobalShortcutV1, GlobalShortcut, State> for GlobalShortcut
+where
+    State: Dispatch<HyprlandGlobalShortcutV1, GlobalShortcut> + GlobalShortcutHandler,
+{
+    fn event(
+        state: &mut State,
+        _: &HyprlandGlobalShortcutV1,
+        event: hyprland_global_shortcut_v1::Event,
+        data: &GlobalShortcut,
+        conn: &smithay_client_toolkit::reexports::client::Connection,
+        qhandle: &QueueHandle<State>,
+    ) {
+        let (shortcut_state, timestamp) = match event {
+            hyprland_global_shortcut_v1::Event::Pressed {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+            } => (
+                ShortcutState::Pressed,
+                timestamp(tv_sec_hi, tv_sec_lo, tv_nsec),
+            ),
+            hyprland_global_shortcut_v1::Event::Released {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+            } => (
+                ShortcutState::Released,
+                timestamp(tv_sec_hi, tv_sec_lo, tv_nsec),
+            ),
+            event => {
+                log::debug!("Unknown global shortcut event {event:?}.");
+                return;
+            }
+        };
+
+        state.global_shortcut(conn, qhandle, &data.id, shortcut_state, timestamp);
+    }
+}
+
+impl<State> Dispatch<HyprlandGlobalShortcutsManagerV1, GlobalData, State> for GlobalShortcutsManager
+where
+    State: Dispatch<HyprlandGlobalShortcutsManagerV1, GlobalData> + GlobalShortcutHandler,
+{
+    fn event(
+        _: &mut State,
+        _: &HyprlandGlobalShortcutsManagerV1,
+        _: <HyprlandGlobalShortcutsManagerV1 as smithay_client_toolkit::reexports::client::Proxy>::Event,
+        _: &GlobalData,
+        _: &smithay_client_toolkit::reexports::client::Connection,
+        _: &QueueHandle<State>,
+    ) {
+        unimplemented!("No events for HyprlandGlobalShortcutsManagerV1")
+    }
+}
+
+fn timestamp(tv_sec_hi: u32, tv_sec_lo: u32, tv_nsec: u32) -> Duration {
+    Duration::new(((tv_sec_hi as u64) << 32) | tv_sec_lo as u64, tv_nsec)
+}
+
+#[macro_export]
+macro_rules! delegate_hyprland_global_shortcuts {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::hyprland_global_shortcuts::client::hyprland_global_shortcuts_manager_v1::HyprlandGlobalShortcutsManagerV1: smithay_client_toolkit::globals::GlobalData
+        ] => $crate::wayland::protocol::hyprland_global_shortcuts::GlobalShortcutsManager);
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::hyprland_global_shortcuts::client::hyprland_global_shortcut_v1::HyprlandGlobalShortcutV1: $crate::wayland::protocol::hyprland_global_shortcuts::GlobalShortcut
+        ] => $crate::wayland::protocol::hyprland_global_shortcuts::GlobalShortcut);
+    };
+}
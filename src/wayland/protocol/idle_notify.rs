@@ -0,0 +1,139 @@
+//!
+//! `ext_idle_notify_v1`, which tells clients when the user has been
+//! inactive on a seat for a while (and when they're back).
+//!
+
+use std::time::Duration;
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::{
+        client::{
+            globals::{BindError, GlobalList},
+            protocol::wl_seat::WlSeat,
+            Connection, Dispatch, Proxy, QueueHandle,
+        },
+        protocols::ext::idle_notify::v1::client::{
+            ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+            ext_idle_notifier_v1::ExtIdleNotifierV1,
+        },
+    },
+};
+
+#[derive(Debug)]
+pub struct IdleNotifier {
+    notifier: ExtIdleNotifierV1,
+}
+
+impl IdleNotifier {
+    pub fn new<State: Dispatch<ExtIdleNotifierV1, GlobalData> + 'static>(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<State>,
+    ) -> Result<Self, BindError> {
+        let notifier = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { notifier })
+    }
+
+    ///
+    /// Get notified when `seat` has been inactive for at least `timeout`.
+    ///
+    /// `registration` is handed back with the notification's events.
+    ///
+    pub fn notify<State: Dispatch<ExtIdleNotificationV1, IdleNotification> + 'static>(
+        &self,
+        seat: &WlSeat,
+        timeout: Duration,
+        registration: u64,
+        queue_handle: &QueueHandle<State>,
+    ) -> ExtIdleNotificationV1 {
+        let timeout = timeout.as_millis().min(u32::MAX as u128) as u32;
+
+        self.notifier.get_idle_notification(
+            timeout,
+            seat,
+            queue_handle,
+            IdleNotification {
+                registration,
+                seat: seat.clone(),
+            },
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct IdleNotification {
+    pub registration: u64,
+    pub seat: WlSeat,
+}
+
+pub trait IdleNotifyHandler: Sized {
+    ///
+    /// The seat was inactive for the notification's whole timeout.
+    ///
+    fn idled(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        notification: &ExtIdleNotificationV1,
+        data: &IdleNotification,
+    );
+
+    ///
+    /// The user is back, after [IdleNotifyHandler::idled].
+    ///
+    fn resumed(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        notification: &ExtIdleNotificationV1,
+        data: &IdleNotification,
+    );
+}
+
+impl<State> Dispatch<ExtIdleNotificationV1, IdleNotification, State> for IdleNotification
+where
+    State: Dispatch<ExtIdleNotificationV1, IdleNotification> + IdleNotifyHandler,
+{
+    fn event(
+        state: &mut State,
+        proxy: &ExtIdleNotificationV1,
+        event: <ExtIdleNotificationV1 as Proxy>::Event,
+        data: &IdleNotification,
+        conn: &Connection,
+        qhandle: &QueueHandle<State>,
+    ) {
+        match event {
+            ext_idle_notification_v1::Event::Idled => state.idled(conn, qhandle, proxy, data),
+            ext_idle_notification_v1::Event::Resumed => state.resumed(conn, qhandle, proxy, data),
+            event => log::debug!("Ignoring an unknown idle notification event: {event:?}"),
+        }
+    }
+}
+
+impl<State> Dispatch<ExtIdleNotifierV1, GlobalData, State> for IdleNotifier
+where
+    State: Dispatch<ExtIdleNotifierV1, GlobalData> + IdleNotifyHandler,
+{
+    fn event(
+        _: &mut State,
+        _: &ExtIdleNotifierV1,
+        _: <ExtIdleNotifierV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<State>,
+    ) {
+        unimplemented!("No events for ExtIdleNotifierV1")
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_idle_notify {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay_client_toolkit::reexports::protocols::ext::idle_notify::v1::client::ext_idle_notifier_v1::ExtIdleNotifierV1: smithay_client_toolkit::globals::GlobalData
+        ] => $crate::wayland::protocol::idle_notify::IdleNotifier);
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay_client_toolkit::reexports::protocols::ext::idle_notify::v1::client::ext_idle_notification_v1::ExtIdleNotificationV1: $crate::wayland::protocol::idle_notify::IdleNotification
+        ] => $crate::wayland::protocol::idle_notify::IdleNotification);
+    };
+}
@@ -1,2 +1,12 @@
+#[cfg(feature = "workspaces")]
+pub mod ext_workspace;
 pub mod fractional_scale;
+#[cfg(feature = "hyprland-surface")]
+pub mod hyprland;
+pub mod hyprland_global_shortcuts;
+pub mod idle_notify;
+#[cfg(feature = "input-latency")]
+pub mod presentation;
 pub mod viewporter;
+#[cfg(feature = "virtual-keyboard")]
+pub mod virtual_keyboard;
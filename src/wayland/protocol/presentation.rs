@@ -0,0 +1,339 @@
+//!
+//! `wp_presentation`, for measuring input-to-photon latency: from a pointer
+//! button being pressed, to the compositor presenting the frame drawn in
+//! response, see [crate::app::AvySurfaceHandle::set_measure_input_latency].
+//!
+//! Presses are timestamped as they're dispatched. The next frame presented
+//! on the surface after a press asks the compositor for feedback, and its
+//! presentation timestamp closes the loop. When several presses land between
+//! two frames, the frame is put down to the earliest of them (and consumes
+//! them all), so a burst of clicks counts once, at its worst.
+//!
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::reexports::{
+    client::{
+        globals::{BindError, GlobalList},
+        protocol::wl_surface::WlSurface,
+        Dispatch, QueueHandle,
+    },
+    protocols::wp::presentation_time::client::{
+        wp_presentation::{self, WpPresentation},
+        wp_presentation_feedback::{self, WpPresentationFeedback},
+    },
+};
+use thiserror::Error;
+
+use crate::{
+    debug::trace::WAYLAND_TARGET,
+    metrics::{Histogram, MetricsRecorder},
+    AvyClient,
+};
+
+///
+/// Presses waiting for a frame, at most. Older ones are dropped, e.g. when
+/// clicking away at a surface which doesn't redraw.
+///
+const MAX_PENDING_PRESSES: usize = 32;
+
+/// `clk_id` before the compositor has sent it.
+const UNKNOWN_CLOCK: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum LatencyError {
+    #[error("The compositor doesn't support wp_presentation.")]
+    Unsupported,
+}
+
+///
+/// The clock presentation timestamps are in, as announced by the compositor.
+///
+#[derive(Debug, Clone)]
+pub struct PresentationClock(Arc<AtomicU32>);
+
+impl PresentationClock {
+    ///
+    /// How long ago the timestamp `presented` (on this clock) was.
+    ///
+    fn elapsed_since(&self, presented: Duration) -> Option<Duration> {
+        use rustix::time::{clock_gettime, ClockId};
+
+        // The `CLOCK_*` constants, as sent in `clk_id`.
+        let clock = match self.0.load(Ordering::Acquire) {
+            0 => ClockId::Realtime,
+            1 => ClockId::Monotonic,
+            4 => ClockId::MonotonicRaw,
+            _ => return None,
+        };
+
+        let now = clock_gettime(clock);
+        let now = Duration::new(now.tv_sec as u64, now.tv_nsec as u32);
+        Some(now.saturating_sub(presented))
+    }
+}
+
+#[derive(Debug)]
+pub struct PresentationManager {
+    presentation: WpPresentation,
+    clock: PresentationClock,
+}
+
+impl PresentationManager {
+    pub fn new<State: Dispatch<WpPresentation, PresentationClock> + 'static>(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<State>,
+    ) -> Result<Self, BindError> {
+        let clock = PresentationClock(Arc::new(AtomicU32::new(UNKNOWN_CLOCK)));
+        let presentation = globals.bind(queue_handle, 1..=1, clock.clone())?;
+        Ok(Self {
+            presentation,
+            clock,
+        })
+    }
+}
+
+///
+/// What a surface asks the compositor for feedback with.
+///
+struct LatencyProbe {
+    presentation: WpPresentation,
+    clock: PresentationClock,
+    surface: WlSurface,
+    queue_handle: QueueHandle<AvyClient>,
+}
+
+///
+/// Input-to-photon latency measured so far, see
+/// [crate::app::AvySurfaceHandle::latency_report].
+///
+#[derive(Debug, Clone, Default)]
+pub struct LatencyReport {
+    pub last: Option<Duration>,
+    pub samples: Histogram,
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(last) = self.last else {
+            return write!(f, "input-to-photon: no samples");
+        };
+
+        write!(
+            f,
+            "input-to-photon: {:.1?} (p50 {:.1?}, p99 {:.1?}, n={})",
+            last,
+            self.samples.percentile(50.0),
+            self.samples.percentile(99.0),
+            self.samples.count(),
+        )
+    }
+}
+
+///
+/// A surface's input-to-photon measurements.
+///
+#[derive(Default)]
+pub struct InputLatency {
+    enabled: AtomicBool,
+    probe: Mutex<Option<LatencyProbe>>,
+    /// Presses no presented frame has been put down to yet, oldest first.
+    presses: Mutex<VecDeque<Instant>>,
+    report: Mutex<LatencyReport>,
+}
+
+impl InputLatency {
+    ///
+    /// Ask `manager` for feedback on the frames presented on `surface`.
+    ///
+    pub(crate) fn bind(
+        &self,
+        manager: &PresentationManager,
+        surface: WlSurface,
+        queue_handle: QueueHandle<AvyClient>,
+    ) {
+        self.probe.lock().unwrap().replace(LatencyProbe {
+            presentation: manager.presentation.clone(),
+            clock: manager.clock.clone(),
+            surface,
+            queue_handle,
+        });
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.probe.lock().unwrap().is_some()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) -> Result<(), LatencyError> {
+        if enabled && !self.is_supported() {
+            return Err(LatencyError::Unsupported);
+        }
+
+        self.enabled.store(enabled, Ordering::Release);
+        if !enabled {
+            self.presses.lock().unwrap().clear();
+        }
+
+        Ok(())
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    ///
+    /// A pointer button was pressed on the surface, just now.
+    ///
+    pub(crate) fn press(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut presses = self.presses.lock().unwrap();
+        if presses.len() == MAX_PENDING_PRESSES {
+            presses.pop_front();
+        }
+        // Not the client's clock: presses are measured against the
+        // compositor's presentation timestamps, see [LatencyFeedback].
+        presses.push_back(Instant::now());
+    }
+
+    ///
+    /// A frame is about to be committed: if presses are waiting for one,
+    /// ask for feedback on it, put down to the earliest of them.
+    ///
+    /// Samples also go to `metrics`, see [crate::metrics::Metrics::input_to_photon].
+    ///
+    pub(crate) fn before_present(self: &Arc<Self>, metrics: Option<MetricsRecorder>) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        // Consumes every press waiting.
+        let Some(press) = self.presses.lock().unwrap().drain(..).next() else {
+            return;
+        };
+
+        if let Some(probe) = &*self.probe.lock().unwrap() {
+            probe.presentation.feedback(
+                &probe.surface,
+                &probe.queue_handle,
+                LatencyFeedback {
+                    press,
+                    clock: probe.clock.clone(),
+                    latency: self.clone(),
+                    metrics,
+                },
+            );
+        }
+    }
+
+    fn record(&self, latency: Duration) {
+        let mut report = self.report.lock().unwrap();
+        report.last.replace(latency);
+        report.samples.record(latency);
+    }
+}
+
+///
+/// What a frame's feedback is for.
+///
+pub struct LatencyFeedback {
+    press: Instant,
+    clock: PresentationClock,
+    latency: Arc<InputLatency>,
+    metrics: Option<MetricsRecorder>,
+}
+
+impl<State> Dispatch<WpPresentation, PresentationClock, State> for PresentationManager
+where
+    State: Dispatch<WpPresentation, PresentationClock>,
+{
+    fn event(
+        _: &mut State,
+        _: &WpPresentation,
+        event: <WpPresentation as smithay_client_toolkit::reexports::client::Proxy>::Event,
+        clock: &PresentationClock,
+        _: &smithay_client_toolkit::reexports::client::Connection,
+        _: &QueueHandle<State>,
+    ) {
+        if let wp_presentation::Event::ClockId { clk_id } = event {
+            clock.0.store(clk_id, Ordering::Release);
+        }
+    }
+}
+
+impl<State> Dispatch<WpPresentationFeedback, LatencyFeedback, State> for InputLatency
+where
+    State: Dispatch<WpPresentationFeedback, LatencyFeedback>,
+{
+    fn event(
+        _: &mut State,
+        _: &WpPresentationFeedback,
+        event: <WpPresentationFeedback as smithay_client_toolkit::reexports::client::Proxy>::Event,
+        data: &LatencyFeedback,
+        _: &smithay_client_toolkit::reexports::client::Connection,
+        _: &QueueHandle<State>,
+    ) {
+        match event {
+            wp_presentation_feedback::Event::Presented {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+                ..
+            } => {
+                let seconds = ((tv_sec_hi as u64) << 32) | tv_sec_lo as u64;
+                let presented = Duration::new(seconds, tv_nsec);
+
+                let Some(ago) = data.clock.elapsed_since(presented) else {
+                    log::debug!(
+                        target: WAYLAND_TARGET,
+                        "Not measuring latency against presentation clock {}",
+                        data.clock.0.load(Ordering::Acquire)
+                    );
+                    return;
+                };
+
+                // `ago` is on the compositor's presentation clock, so it's only
+                // comparable with the system's (as is the press), whatever
+                // clock the client runs animations on.
+                let Some(photon) = Instant::now().checked_sub(ago) else {
+                    return;
+                };
+
+                let latency = photon.saturating_duration_since(data.press);
+                data.latency.record(latency);
+                if let Some(metrics) = &data.metrics {
+                    metrics.record_input_latency(latency);
+                }
+            }
+            // Discarded: the frame never made it to the screen,
+            // so the press goes unmeasured.
+            _ => {}
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_presentation {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay_client_toolkit::reexports::protocols::wp::presentation_time::client::wp_presentation::WpPresentation: $crate::wayland::protocol::presentation::PresentationClock
+        ] => $crate::wayland::protocol::presentation::PresentationManager);
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay_client_toolkit::reexports::protocols::wp::presentation_time::client::wp_presentation_feedback::WpPresentationFeedback: $crate::wayland::protocol::presentation::LatencyFeedback
+        ] => $crate::wayland::protocol::presentation::InputLatency);
+    };
+}
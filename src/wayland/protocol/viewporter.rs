@@ -4,12 +4,14 @@ use smithay_client_toolkit::{
         client::{
             globals::{BindError, GlobalList},
             protocol::wl_surface::WlSurface,
-            Dispatch, QueueHandle,
+            Dispatch, Proxy, QueueHandle,
         },
         protocols::wp::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
     },
 };
 
+use crate::debug;
+
 pub struct Viewporter(WpViewporter);
 
 impl Viewporter {
@@ -21,18 +23,25 @@ impl Viewporter {
         Ok(Self(wp_viewporter))
     }
 
+    pub fn version(&self) -> u32 {
+        self.0.version()
+    }
+
     pub fn get_viewport<State: Dispatch<WpViewport, Viewport> + 'static>(
         &self,
         surface: &WlSurface,
         qh: &QueueHandle<State>,
     ) -> WpViewport {
-        self.0.get_viewport(
+        let viewport = self.0.get_viewport(
             surface,
             qh,
             Viewport {
                 surface: surface.clone(),
             },
-        )
+        );
+
+        debug::objects::created(&viewport.id(), Some(&surface.id()));
+        viewport
     }
 }
 
@@ -0,0 +1,313 @@
+//!
+//! `zwp_virtual_keyboard_v1`, which lets clients (e.g. an on-screen
+//! keyboard) type into other clients as if through a physical keyboard.
+//!
+
+use std::{collections::HashMap, fs::File, io, io::Write, os::fd::AsFd, time::Instant};
+
+use smithay_client_toolkit::{
+    globals::GlobalData,
+    reexports::client::{
+        globals::{BindError, GlobalList},
+        protocol::{
+            wl_keyboard::{KeyState, KeymapFormat},
+            wl_seat::WlSeat,
+        },
+        Dispatch, QueueHandle,
+    },
+    seat::keyboard::Keysym,
+};
+use thiserror::Error;
+
+use crate::{util::SharedClock, wayland::surface::layer::LayerError};
+
+#[allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#[allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#[allow(missing_docs, clippy::all)]
+pub mod client {
+    use smithay_client_toolkit::reexports::client as wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use smithay_client_toolkit::reexports::client as wayland_client;
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/virtual-keyboard-unstable-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/virtual-keyboard-unstable-v1.xml");
+}
+
+use client::{
+    zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+    zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("The compositor doesn't support zwp_virtual_keyboard_manager_v1.")]
+    Unsupported,
+
+    #[error("There's no seat to create a virtual keyboard for.")]
+    NoSeat,
+
+    #[error("Keys can't be sent before a keymap is uploaded.")]
+    NoKeymap,
+
+    #[error("Could not upload the keymap: {0}")]
+    Keymap(#[from] io::Error),
+
+    #[error("Could not create the on-screen keyboard's layer: {0}")]
+    Layer(#[from] LayerError),
+}
+
+#[derive(Debug)]
+pub struct VirtualKeyboardManager {
+    manager: ZwpVirtualKeyboardManagerV1,
+}
+
+impl VirtualKeyboardManager {
+    pub fn new<State: Dispatch<ZwpVirtualKeyboardManagerV1, GlobalData> + 'static>(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<State>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    ///
+    /// Create a virtual keyboard typing as `seat`, timestamping keys by `clock`.
+    ///
+    /// Compositors may kill the connection of clients they
+    /// don't trust with one (`unauthorized`).
+    ///
+    pub fn create_keyboard<State: Dispatch<ZwpVirtualKeyboardV1, GlobalData> + 'static>(
+        &self,
+        seat: &WlSeat,
+        queue_handle: &QueueHandle<State>,
+        clock: &SharedClock,
+    ) -> VirtualKeyboard {
+        VirtualKeyboard {
+            keyboard: self
+                .manager
+                .create_virtual_keyboard(seat, queue_handle, GlobalData),
+            has_keymap: false,
+            origin: clock.now(),
+            clock: clock.clone(),
+        }
+    }
+}
+
+pub struct VirtualKeyboard {
+    keyboard: ZwpVirtualKeyboardV1,
+    has_keymap: bool,
+    /// Timestamps are in milliseconds since this, as the protocol leaves the base up to us.
+    origin: Instant,
+    clock: SharedClock,
+}
+
+impl VirtualKeyboard {
+    ///
+    /// Upload an XKB keymap (in text form) for keys to be sent against,
+    /// replacing the previous one.
+    ///
+    pub fn upload_keymap(&mut self, keymap: &str) -> Result<(), Error> {
+        let fd = rustix::fs::memfd_create(
+            c"avy-virtual-keyboard",
+            rustix::fs::MemfdFlags::CLOEXEC | rustix::fs::MemfdFlags::ALLOW_SEALING,
+        )
+        .map_err(io::Error::from)?;
+
+        let mut file = File::from(fd);
+        file.write_all(keymap.as_bytes())?;
+        // Compositors read the keymap as a C string.
+        file.write_all(&[0])?;
+
+        // Only an optimisation, so errors are ignored.
+        let _ = rustix::fs::fcntl_add_seals(
+            &file,
+            rustix::fs::SealFlags::SHRINK
+                | rustix::fs::SealFlags::GROW
+                | rustix::fs::SealFlags::WRITE
+                | rustix::fs::SealFlags::SEAL,
+        );
+
+        self.keyboard.keymap(
+            KeymapFormat::XkbV1 as u32,
+            file.as_fd(),
+            keymap.len() as u32 + 1,
+        );
+
+        self.has_keymap = true;
+        Ok(())
+    }
+
+    pub fn has_keymap(&self) -> bool {
+        self.has_keymap
+    }
+
+    ///
+    /// Press or release the key with the evdev code `key`
+    /// (the keymap's keycode, minus 8).
+    ///
+    pub fn key(&self, key: u32, pressed: bool) -> Result<(), Error> {
+        if !self.has_keymap {
+            return Err(Error::NoKeymap);
+        }
+
+        let state = if pressed {
+            KeyState::Pressed
+        } else {
+            KeyState::Released
+        };
+
+        self.keyboard.key(self.time(), key, state as u32);
+        Ok(())
+    }
+
+    pub fn modifiers(
+        &self,
+        depressed: u32,
+        latched: u32,
+        locked: u32,
+        group: u32,
+    ) -> Result<(), Error> {
+        if !self.has_keymap {
+            return Err(Error::NoKeymap);
+        }
+
+        self.keyboard.modifiers(depressed, latched, locked, group);
+        Ok(())
+    }
+
+    fn time(&self) -> u32 {
+        self.clock
+            .now()
+            .saturating_duration_since(self.origin)
+            .as_millis() as u32
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        self.keyboard.destroy();
+    }
+}
+
+///
+/// The first keycode handed out by [KeysymKeymap], as XKB reserves 0-7.
+///
+const MIN_KEYCODE: u32 = 8;
+
+///
+/// The last keycode X11 clients (through Xwayland) can make sense of.
+///
+const MAX_KEYCODE: u32 = 255;
+
+///
+/// A keymap built up as keysyms are typed, each getting a keycode of its
+/// own, so that any keysym can be sent whatever the user's layout.
+///
+#[derive(Debug, Default)]
+pub struct KeysymKeymap {
+    keycodes: HashMap<Keysym, u32>,
+}
+
+impl KeysymKeymap {
+    ///
+    /// The evdev code to send `keysym` as, and whether the keymap
+    /// changed (and must be uploaded again) to make room for it.
+    ///
+    pub fn key_for(&mut self, keysym: Keysym) -> (u32, bool) {
+        if let Some(keycode) = self.keycodes.get(&keysym) {
+            return (keycode - MIN_KEYCODE, false);
+        }
+
+        // Start over once out of keycodes, rather than failing.
+        if self.keycodes.len() as u32 > MAX_KEYCODE - MIN_KEYCODE - 1 {
+            self.keycodes.clear();
+        }
+
+        // Keycode 8 is evdev's KEY_RESERVED, so start at 9.
+        let keycode = MIN_KEYCODE + 1 + self.keycodes.len() as u32;
+        self.keycodes.insert(keysym, keycode);
+
+        (keycode - MIN_KEYCODE, true)
+    }
+
+    ///
+    /// The keymap in XKB's text format, for [VirtualKeyboard::upload_keymap].
+    ///
+    pub fn to_xkb(&self) -> String {
+        let mut keycodes: Vec<_> = self.keycodes.iter().collect();
+        keycodes.sort_by_key(|(_, keycode)| **keycode);
+
+        let max = keycodes
+            .last()
+            .map_or(MIN_KEYCODE + 1, |(_, keycode)| **keycode);
+
+        let mut names = String::new();
+        let mut symbols = String::new();
+
+        for (keysym, keycode) in keycodes {
+            names.push_str(&format!("    <K{keycode}> = {keycode};\n"));
+            symbols.push_str(&format!(
+                "    key <K{keycode}> {{ [ 0x{:x} ] }};\n",
+                keysym.raw()
+            ));
+        }
+
+        format!(
+            "xkb_keymap {{\n\
+             xkb_keycodes \"avy\" {{\n    minimum = {MIN_KEYCODE};\n    maximum = {max};\n{names}}};\n\
+             xkb_types \"avy\" {{ include \"complete\" }};\n\
+             xkb_compatibility \"avy\" {{ include \"complete\" }};\n\
+             xkb_symbols \"avy\" {{\n{symbols}}};\n\
+             }};\n"
+        )
+    }
+}
+
+impl<State> Dispatch<ZwpVirtualKeyboardManagerV1, GlobalData, State> for VirtualKeyboardManager
+where
+    State: Dispatch<ZwpVirtualKeyboardManagerV1, GlobalData>,
+{
+    fn event(
+        _: &mut State,
+        _: &ZwpVirtualKeyboardManagerV1,
+        _: <ZwpVirtualKeyboardManagerV1 as smithay_client_toolkit::reexports::client::Proxy>::Event,
+        _: &GlobalData,
+        _: &smithay_client_toolkit::reexports::client::Connection,
+        _: &QueueHandle<State>,
+    ) {
+        unimplemented!("No events for ZwpVirtualKeyboardManagerV1")
+    }
+}
+
+impl<State> Dispatch<ZwpVirtualKeyboardV1, GlobalData, State> for VirtualKeyboardManager
+where
+    State: Dispatch<ZwpVirtualKeyboardV1, GlobalData>,
+{
+    fn event(
+        _: &mut State,
+        _: &ZwpVirtualKeyboardV1,
+        _: <ZwpVirtualKeyboardV1 as smithay_client_toolkit::reexports::client::Proxy>::Event,
+        _: &GlobalData,
+        _: &smithay_client_toolkit::reexports::client::Connection,
+        _: &QueueHandle<State>,
+    ) {
+        unimplemented!("No events for ZwpVirtualKeyboardV1")
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_virtual_keyboard {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::virtual_keyboard::client::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1: smithay_client_toolkit::globals::GlobalData
+        ] => $crate::wayland::protocol::virtual_keyboard::VirtualKeyboardManager);
+        smithay_client_toolkit::reexports::client::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocol::virtual_keyboard::client::zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1: smithay_client_toolkit::globals::GlobalData
+        ] => $crate::wayland::protocol::virtual_keyboard::VirtualKeyboardManager);
+    };
+}
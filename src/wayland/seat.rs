@@ -0,0 +1,245 @@
+//!
+//! Which input devices are available, for clients which may
+//! have to run without some (or any) of them.
+//!
+
+use smithay_client_toolkit::{
+    reexports::{
+        client::{
+            protocol::{
+                wl_keyboard::WlKeyboard, wl_pointer::WlPointer, wl_seat::WlSeat, wl_touch::WlTouch,
+            },
+            Proxy,
+        },
+        protocols::wp::relative_pointer::zv1::client::zwp_relative_pointer_v1::ZwpRelativePointerV1,
+    },
+    seat::Capability,
+};
+use thiserror::Error;
+
+use crate::AvyClient;
+
+use super::globals::GlobalVersions;
+
+///
+/// The input devices the client currently has, and the globals it bound.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub pointer: bool,
+    pub keyboard: bool,
+    pub touch: bool,
+    /// Relative pointer motion (needs both a pointer and compositor support).
+    pub relative_pointer: bool,
+    pub globals: GlobalVersions,
+}
+
+///
+/// What a single seat advertises.
+///
+#[derive(Debug, Clone)]
+pub struct SeatCapabilities {
+    pub seat: WlSeat,
+    pub name: Option<String>,
+    pub pointer: bool,
+    pub keyboard: bool,
+    pub touch: bool,
+}
+
+///
+/// An input device which may or may not be around.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputDevice {
+    Pointer,
+    Keyboard,
+    Touch,
+    RelativePointer,
+}
+
+impl TryFrom<Capability> for InputDevice {
+    type Error = UnknownCapability;
+
+    fn try_from(capability: Capability) -> Result<Self, Self::Error> {
+        match capability {
+            Capability::Pointer => Ok(Self::Pointer),
+            Capability::Keyboard => Ok(Self::Keyboard),
+            Capability::Touch => Ok(Self::Touch),
+            other => Err(UnknownCapability(other)),
+        }
+    }
+}
+
+///
+/// A seat capability newer than the client, which it ignores.
+///
+#[derive(Debug, Clone, Copy, Error)]
+#[error("Unknown seat capability {0:?}.")]
+pub struct UnknownCapability(pub Capability);
+
+///
+/// A device object, which the compositor must be told the client is done with.
+///
+pub(crate) trait ReleaseDevice {
+    fn release_device(self);
+}
+
+impl ReleaseDevice for WlPointer {
+    fn release_device(self) {
+        // Only there since version 3, before which the object just leaks.
+        if self.version() >= 3 {
+            self.release();
+        }
+    }
+}
+
+impl ReleaseDevice for WlKeyboard {
+    fn release_device(self) {
+        if self.version() >= 3 {
+            self.release();
+        }
+    }
+}
+
+impl ReleaseDevice for WlTouch {
+    fn release_device(self) {
+        if self.version() >= 3 {
+            self.release();
+        }
+    }
+}
+
+impl ReleaseDevice for ZwpRelativePointerV1 {
+    fn release_device(self) {
+        self.destroy();
+    }
+}
+
+///
+/// Take the device out of `slot` and release it, returning whether there was one.
+///
+pub(crate) fn release_device<D: ReleaseDevice>(slot: &mut Option<D>) -> bool {
+    let Some(device) = slot.take() else {
+        return false;
+    };
+
+    device.release_device();
+    true
+}
+
+#[derive(Debug, Clone, Copy, Error)]
+#[error("No {0:?} is available on any seat.")]
+pub struct NoSuchCapability(pub InputDevice);
+
+impl AvyClient {
+    ///
+    /// The input devices the client is currently listening to,
+    /// and the versions of the globals it bound.
+    ///
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            pointer: self.pointer.is_some(),
+            keyboard: self.keyboard.is_some(),
+            touch: self.touch.is_some(),
+            relative_pointer: self.relative_pointer.is_some(),
+            globals: self.global_versions(),
+        }
+    }
+
+    ///
+    /// What each seat advertises, whether or not the client is using it.
+    ///
+    pub fn seat_capabilities(&self) -> Vec<SeatCapabilities> {
+        self.seat_state
+            .seats()
+            .filter_map(|seat| {
+                let info = self.seat_state.info(&seat)?;
+
+                Some(SeatCapabilities {
+                    seat,
+                    name: info.name,
+                    pointer: info.has_pointer,
+                    keyboard: info.has_keyboard,
+                    touch: info.has_touch,
+                })
+            })
+            .collect()
+    }
+
+    pub fn pointer(&self) -> Result<&WlPointer, NoSuchCapability> {
+        self.pointer
+            .as_ref()
+            .ok_or(NoSuchCapability(InputDevice::Pointer))
+    }
+
+    pub fn keyboard(&self) -> Result<&WlKeyboard, NoSuchCapability> {
+        self.keyboard
+            .as_ref()
+            .ok_or(NoSuchCapability(InputDevice::Keyboard))
+    }
+
+    pub fn touch(&self) -> Result<&WlTouch, NoSuchCapability> {
+        self.touch
+            .as_ref()
+            .ok_or(NoSuchCapability(InputDevice::Touch))
+    }
+
+    pub fn relative_pointer(&self) -> Result<&ZwpRelativePointerV1, NoSuchCapability> {
+        self.relative_pointer
+            .as_ref()
+            .ok_or(NoSuchCapability(InputDevice::RelativePointer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[test]
+    fn capabilities_map_to_their_devices() {
+        let devices = [Capability::Pointer, Capability::Keyboard, Capability::Touch]
+            .map(|capability| InputDevice::try_from(capability).unwrap());
+
+        assert_eq!(
+            devices,
+            [
+                InputDevice::Pointer,
+                InputDevice::Keyboard,
+                InputDevice::Touch
+            ]
+        );
+    }
+
+    /// Notes its name once released.
+    struct Device(&'static str, Rc<RefCell<Vec<&'static str>>>);
+
+    impl ReleaseDevice for Device {
+        fn release_device(self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn lost_devices_are_released_once() {
+        let released = Rc::new(RefCell::new(Vec::new()));
+        let mut pointer = None;
+        let mut keyboard = None;
+
+        // Pointer and keyboard come, the pointer goes (twice, as
+        // seats can repeat themselves), then comes back and the seat goes.
+        pointer.replace(Device("pointer", released.clone()));
+        keyboard.replace(Device("keyboard", released.clone()));
+        assert!(release_device(&mut pointer));
+        assert!(!release_device(&mut pointer));
+        assert_eq!(*released.borrow(), ["pointer"]);
+
+        pointer.replace(Device("pointer again", released.clone()));
+        assert!(release_device(&mut keyboard));
+        assert!(release_device(&mut pointer));
+
+        assert_eq!(*released.borrow(), ["pointer", "keyboard", "pointer again"]);
+        assert!(pointer.is_none() && keyboard.is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//!
+//! The serials of the last user actions on each seat.
+//!
+//! Requests acting on the user's behalf (setting selections, grabs, ...)
+//! have to carry the serial of the input event which triggered them, and
+//! compositors ignore those with a serial they don't consider recent.
+//!
+
+use std::collections::HashMap;
+
+use smithay_client_toolkit::reexports::client::{protocol::wl_seat::WlSeat, Proxy};
+use wayland_backend::client::ObjectId;
+
+use crate::AvyClient;
+
+///
+/// Which kind of user action a serial comes from.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialKind {
+    PointerButton,
+    KeyPress,
+    TouchDown,
+    /// Whichever of the above came last.
+    Any,
+}
+
+///
+/// The serials of the last user actions on a seat.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LastSerials {
+    pub pointer_button: Option<u32>,
+    pub key_press: Option<u32>,
+    pub touch_down: Option<u32>,
+    pub any: Option<u32>,
+    /// Serial of the last keyboard leave: the user actions before it
+    /// no longer count for requests needing keyboard focus.
+    focus_lost: Option<u32>,
+}
+
+impl LastSerials {
+    pub fn get(&self, kind: SerialKind) -> Option<u32> {
+        match kind {
+            SerialKind::PointerButton => self.pointer_button,
+            SerialKind::KeyPress => self.key_press,
+            SerialKind::TouchDown => self.touch_down,
+            SerialKind::Any => self.any,
+        }
+    }
+
+    ///
+    /// Whether `serial` predates the seat's last loss of keyboard focus,
+    /// so that compositors will ignore requests needing focus made with it.
+    ///
+    pub fn is_stale(&self, serial: u32) -> bool {
+        self.focus_lost.is_some_and(|lost| !is_after(serial, lost))
+    }
+
+    fn record(&mut self, kind: SerialKind, serial: u32) {
+        match kind {
+            SerialKind::PointerButton => self.pointer_button = Some(serial),
+            SerialKind::KeyPress => self.key_press = Some(serial),
+            SerialKind::TouchDown => self.touch_down = Some(serial),
+            SerialKind::Any => {}
+        }
+
+        self.any = Some(serial);
+    }
+}
+
+///
+/// [LastSerials] for each seat, see [AvyClient::last_serial].
+///
+#[derive(Debug, Default)]
+pub struct Serials {
+    seats: HashMap<ObjectId, LastSerials>,
+    /// The seat the user acted on last.
+    latest: Option<ObjectId>,
+}
+
+impl AvyClient {
+    ///
+    /// The serial of the last user action of `kind`, on the seat the
+    /// user acted on last, for requests which need one.
+    ///
+    pub fn last_serial(&self, kind: SerialKind) -> Option<u32> {
+        self.last_serials()?.get(kind)
+    }
+
+    ///
+    /// The serials of the last user actions on the seat the user acted on last.
+    ///
+    pub fn last_serials(&self) -> Option<&LastSerials> {
+        let seat = self.serials.latest.as_ref()?;
+        self.serials.seats.get(seat)
+    }
+
+    pub fn seat_serials(&self, seat: &WlSeat) -> Option<&LastSerials> {
+        self.serials.seats.get(&seat.id())
+    }
+
+    pub(crate) fn record_serial(&mut self, seat: &WlSeat, kind: SerialKind, serial: u32) {
+        let id = seat.id();
+        self.serials
+            .seats
+            .entry(id.clone())
+            .or_default()
+            .record(kind, serial);
+        self.serials.latest.replace(id);
+    }
+
+    pub(crate) fn record_focus_lost(&mut self, seat: &WlSeat, serial: u32) {
+        let serials = self.serials.seats.entry(seat.id()).or_default();
+        serials.focus_lost = Some(serial);
+        serials.key_press = None;
+    }
+
+    pub(crate) fn remove_serial_seat(&mut self, seat: &WlSeat) {
+        let id = seat.id();
+        self.serials.seats.remove(&id);
+        if self.serials.latest.as_ref() == Some(&id) {
+            self.serials.latest.take();
+        }
+    }
+}
+
+///
+/// Whether `serial` came after `other`, allowing for the counter wrapping.
+///
+fn is_after(serial: u32, other: u32) -> bool {
+    (serial.wrapping_sub(other) as i32) > 0
+}
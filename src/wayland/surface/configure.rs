@@ -0,0 +1,105 @@
+//!
+//! Acknowledging configure events by hand, so that the compositor never
+//! shows a buffer which doesn't match the size it asked for.
+//!
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+///
+/// A configure event which hasn't been acknowledged yet.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingConfigure {
+    pub serial: u32,
+
+    /// Size asked for by the compositor, in logical pixels.
+    ///
+    /// A zero on either axis leaves that axis up to the client.
+    pub size: (u32, u32),
+}
+
+impl PendingConfigure {
+    ///
+    /// Whether a buffer drawn at `logical_size` fits this configure.
+    ///
+    pub fn fits(&self, logical_size: (u32, u32)) -> bool {
+        let fits = |asked: u32, actual: u32| asked == 0 || asked == actual;
+        fits(self.size.0, logical_size.0) && fits(self.size.1, logical_size.1)
+    }
+}
+
+///
+/// The configure a surface has yet to acknowledge, and how to acknowledge it.
+///
+/// Only surfaces which opted into manual acks have one of these (see
+/// [super::AvySurface::configure_ack]). Clones share the same pending configure.
+///
+#[derive(Clone)]
+pub struct ConfigureAck {
+    pending: Arc<Mutex<Option<PendingConfigure>>>,
+    ack: Arc<dyn Fn(u32) + Send + Sync>,
+}
+
+impl ConfigureAck {
+    ///
+    /// `ack` sends the protocol's `ack_configure` request for a serial.
+    ///
+    pub fn new(ack: impl Fn(u32) + Send + Sync + 'static) -> Self {
+        Self {
+            pending: Default::default(),
+            ack: Arc::new(ack),
+        }
+    }
+
+    pub fn pending(&self) -> Option<PendingConfigure> {
+        *self.pending.lock().unwrap()
+    }
+
+    ///
+    /// Remember a configure which just arrived.
+    ///
+    /// Only the latest configure needs acknowledging,
+    /// so this replaces any older one.
+    ///
+    pub(crate) fn stash(&self, configure: PendingConfigure) {
+        self.pending.lock().unwrap().replace(configure);
+    }
+
+    ///
+    /// Acknowledge the pending configure, if there is one.
+    ///
+    /// This must be followed by a commit of a buffer of the configured size.
+    ///
+    pub fn ack(&self) -> Option<PendingConfigure> {
+        let configure = self.pending.lock().unwrap().take()?;
+        (self.ack)(configure.serial);
+        Some(configure)
+    }
+
+    ///
+    /// Acknowledge the pending configure, if a buffer
+    /// drawn at `logical_size` fits it.
+    ///
+    pub fn ack_if_fits(&self, logical_size: (u32, u32)) -> Option<PendingConfigure> {
+        let mut pending = self.pending.lock().unwrap();
+
+        if !pending.is_some_and(|configure| configure.fits(logical_size)) {
+            return None;
+        }
+
+        let configure = pending.take()?;
+        (self.ack)(configure.serial);
+        Some(configure)
+    }
+}
+
+impl fmt::Debug for ConfigureAck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigureAck")
+            .field("pending", &self.pending())
+            .finish_non_exhaustive()
+    }
+}
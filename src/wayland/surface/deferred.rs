@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::{
+    reexports::client::{
+        protocol::{wl_keyboard::WlKeyboard, wl_surface::WlSurface},
+        Connection, QueueHandle,
+    },
+    seat::keyboard::{KeyEvent, Keysym, Modifiers},
+};
+
+use crate::AvyClient;
+
+use super::AvySurface;
+
+///
+/// Upper bound on the number of events kept for a single
+/// surface which hasn't been registered yet.
+///
+pub const MAX_DEFERRED_EVENTS: usize = 64;
+
+///
+/// Upper bound on the number of unregistered surfaces events are kept for,
+/// the oldest making way for the next.
+///
+pub const MAX_DEFERRED_SURFACES: usize = 8;
+
+///
+/// How long events are kept for a surface which doesn't get registered.
+///
+pub const DEFERRED_EVENTS_EXPIRE: Duration = Duration::from_secs(5);
+
+///
+/// Events kept for a surface which hasn't been registered yet.
+///
+#[derive(Debug, Clone)]
+pub struct DeferredEvents<E = DeferredKeyboardEvent> {
+    /// When the first of them arrived.
+    pub since: Instant,
+    pub events: Vec<E>,
+}
+
+///
+/// Keep `event` for the unregistered surface `id`, dropping what's been
+/// kept too long, or for too many surfaces.
+///
+pub(crate) fn defer<K: Eq + Hash + Clone + Debug, E>(
+    deferred: &mut HashMap<K, DeferredEvents<E>>,
+    id: K,
+    event: E,
+    now: Instant,
+) {
+    deferred.retain(|id, events| {
+        let expired = now.saturating_duration_since(events.since) >= DEFERRED_EVENTS_EXPIRE;
+        if expired {
+            log::debug!("Dropping keyboard events for {id:?}, which never got registered");
+        }
+        !expired
+    });
+
+    if !deferred.contains_key(&id) && deferred.len() >= MAX_DEFERRED_SURFACES {
+        let oldest = deferred
+            .iter()
+            .min_by_key(|(_, events)| events.since)
+            .map(|(id, _)| id.clone());
+        if let Some(oldest) = oldest {
+            deferred.remove(&oldest);
+        }
+    }
+
+    let events = deferred.entry(id).or_insert_with(|| DeferredEvents {
+        since: now,
+        events: Vec::new(),
+    });
+    if events.events.len() < MAX_DEFERRED_EVENTS {
+        events.events.push(event);
+    }
+}
+
+///
+/// A keyboard event which arrived for a surface before it was registered
+/// with [AvyClient], to be replayed once it is.
+///
+#[derive(Debug, Clone)]
+pub enum DeferredKeyboardEvent {
+    Enter {
+        keyboard: WlKeyboard,
+        surface: WlSurface,
+        serial: u32,
+        raw: Vec<u32>,
+        keysyms: Vec<Keysym>,
+    },
+    PressKey {
+        keyboard: WlKeyboard,
+        serial: u32,
+        event: KeyEvent,
+    },
+    ReleaseKey {
+        keyboard: WlKeyboard,
+        serial: u32,
+        event: KeyEvent,
+    },
+    UpdateModifiers {
+        keyboard: WlKeyboard,
+        serial: u32,
+        modifiers: Modifiers,
+        layout: u32,
+    },
+}
+
+impl DeferredKeyboardEvent {
+    pub fn deliver(
+        self,
+        surface: &mut dyn AvySurface,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+    ) {
+        match self {
+            Self::Enter {
+                keyboard,
+                surface: wl_surface,
+                serial,
+                raw,
+                keysyms,
+            } => surface.enter(conn, qh, &keyboard, &wl_surface, serial, &raw, &keysyms),
+            Self::PressKey {
+                keyboard,
+                serial,
+                event,
+            } => surface.press_key(conn, qh, &keyboard, serial, event),
+            Self::ReleaseKey {
+                keyboard,
+                serial,
+                event,
+            } => surface.release_key(conn, qh, &keyboard, serial, event),
+            Self::UpdateModifiers {
+                keyboard,
+                serial,
+                modifiers,
+                layout,
+            } => surface.update_modifiers(conn, qh, &keyboard, serial, modifiers, layout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_per_surface_are_capped() {
+        let mut deferred = HashMap::new();
+        let now = Instant::now();
+
+        for event in 0..MAX_DEFERRED_EVENTS + 10 {
+            defer(&mut deferred, 1, event, now);
+        }
+
+        assert_eq!(deferred[&1].events.len(), MAX_DEFERRED_EVENTS);
+        assert_eq!(deferred[&1].events[0], 0);
+    }
+
+    #[test]
+    fn oldest_surface_makes_way() {
+        let mut deferred = HashMap::new();
+        let start = Instant::now();
+
+        for id in 0..MAX_DEFERRED_SURFACES + 1 {
+            defer(
+                &mut deferred,
+                id,
+                (),
+                start + Duration::from_millis(id as u64),
+            );
+        }
+
+        assert_eq!(deferred.len(), MAX_DEFERRED_SURFACES);
+        assert!(!deferred.contains_key(&0));
+        assert!(deferred.contains_key(&MAX_DEFERRED_SURFACES));
+    }
+
+    #[test]
+    fn unregistered_surfaces_expire() {
+        let mut deferred = HashMap::new();
+        let start = Instant::now();
+
+        defer(&mut deferred, 1, (), start);
+        defer(&mut deferred, 2, (), start + DEFERRED_EVENTS_EXPIRE);
+
+        assert!(!deferred.contains_key(&1));
+        assert_eq!(deferred[&2].events.len(), 1);
+    }
+}
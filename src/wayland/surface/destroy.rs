@@ -0,0 +1,294 @@
+//!
+//! Destroying registered surfaces, possibly from their own input handlers.
+//!
+//! A handler can't destroy its surface there and then, as the rest of the
+//! events being dispatched (e.g. the remainder of a pointer frame) may still
+//! be for it. Instead, it requests destruction through [DestroyRequests]:
+//! the surface is destroyed once the client is done dispatching, and any
+//! events left for it in the meantime are dropped.
+//!
+
+use std::sync::{Arc, Mutex};
+
+use wayland_backend::client::ObjectId;
+
+use crate::{debug, AvyClient};
+
+///
+/// Surfaces waiting to be destroyed, see [AvyClient::destroy_requests].
+///
+#[derive(Debug, Clone, Default)]
+pub struct DestroyRequests(Arc<Mutex<Vec<ObjectId>>>);
+
+impl DestroyRequests {
+    ///
+    /// Destroy `surface` once the input event being handled has been dispatched.
+    ///
+    pub fn request(&self, surface: ObjectId) {
+        let mut requests = self.0.lock().unwrap();
+        if !requests.contains(&surface) {
+            requests.push(surface);
+        }
+    }
+
+    pub fn is_requested(&self, surface: &ObjectId) -> bool {
+        self.0.lock().unwrap().contains(surface)
+    }
+
+    fn take(&self) -> Vec<ObjectId> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl AvyClient {
+    ///
+    /// A handle for input handlers to request that surfaces be destroyed.
+    ///
+    /// Requests are carried out at the end of the input event being
+    /// handled. From other threads, use [crate::proxy::AvyProxy] to call
+    /// [AvyClient::destroy_surface] instead.
+    ///
+    pub fn destroy_requests(&self) -> DestroyRequests {
+        self.destroy_requests.clone()
+    }
+
+    ///
+    /// Destroy a registered surface, along with its backend.
+    ///
+    /// Renders through its handles fail from then on.
+    ///
+    pub fn destroy_surface(&mut self, id: &ObjectId) {
+        let Some(surface) = self.surfaces.remove(id) else {
+            return;
+        };
+
+        self.leave_render_group(id);
+
+        if let Some(backend) = self.surface_backends.remove(id) {
+            // Waits for any render in progress.
+            if backend.lock().unwrap().detach().is_err() {
+                log::warn!("Could not cleanly detach the backend of {id}.");
+            }
+        }
+
+        if let Some(state) = self.surface_shared.remove(id) {
+            if let Some(token) = state.auto_suspend_timer.lock().unwrap().take() {
+                self.cancel_timer(token);
+            }
+
+            // Destroyed along with the surface, but handles may outlive it.
+            state.viewport.lock().unwrap().forget();
+
+            // Before the surface it extends.
+            #[cfg(feature = "hyprland-surface")]
+            state.hyprland.destroy();
+        }
+
+        self.surface_outputs.remove(id);
+        self.surface_names.remove(id);
+        self.surface_registry.remove(id);
+        self.leave_edge_layout(id);
+        self.objects.forget_surface(id);
+        self.deferred_keyboard_events.remove(id);
+        self.overlays.remove(id);
+        self.subsurface_stacks.remove(id);
+
+        if self.keyboard_focus.as_ref() == Some(id) {
+            self.keyboard_focus.take();
+            self.pressed_keys.clear();
+        }
+
+        if self.exclusive_keyboard.as_ref() == Some(id) {
+            self.exclusive_keyboard.take();
+        }
+
+        self.active_touches.retain(|_, surface| surface != id);
+
+        #[cfg(feature = "workspaces")]
+        self.unwatch_workspaces(id);
+
+        // Destroys the Wayland objects.
+        drop(surface);
+        debug::objects::surface_destroyed(id);
+    }
+
+    ///
+    /// Carry out the destruction requested (through
+    /// [AvyClient::destroy_requests]) whilst dispatching.
+    ///
+    pub(crate) fn destroy_requested_surfaces(&mut self) {
+        for id in self.destroy_requests.take() {
+            self.destroy_surface(&id);
+        }
+    }
+
+    ///
+    /// Whether events for `id` should be dropped, as it's about to be destroyed.
+    ///
+    pub(crate) fn is_destroying(&self, id: &ObjectId) -> bool {
+        self.destroy_requests.is_requested(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Read},
+        os::unix::net::UnixStream,
+    };
+
+    use smithay_client_toolkit::reexports::{
+        client::{
+            delegate_noop,
+            protocol::{
+                wl_compositor::WlCompositor, wl_registry::WlRegistry, wl_surface::WlSurface,
+            },
+            Connection, EventQueue, Proxy, QueueHandle,
+        },
+        protocols::wp::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+    };
+
+    use crate::app::ViewportSync;
+
+    use super::*;
+
+    struct State;
+
+    delegate_noop!(State: ignore WlRegistry);
+    delegate_noop!(State: WlCompositor);
+    delegate_noop!(State: ignore WlSurface);
+    delegate_noop!(State: WpViewporter);
+    delegate_noop!(State: WpViewport);
+
+    ///
+    /// A connection to nobody: requests end up in `server`, for
+    /// [Client::sent] to count.
+    ///
+    struct Client {
+        connection: Connection,
+        server: UnixStream,
+        qh: QueueHandle<State>,
+        compositor: WlCompositor,
+        viewporter: WpViewporter,
+        _queue: EventQueue<State>,
+    }
+
+    impl Client {
+        fn new() -> Self {
+            let (socket, server) = UnixStream::pair().unwrap();
+            let connection = Connection::from_socket(socket).unwrap();
+            let queue = connection.new_event_queue();
+            let qh = queue.handle();
+
+            let registry = connection.display().get_registry(&qh, ());
+            let compositor = registry.bind::<WlCompositor, _, _>(1, 4, &qh, ());
+            let viewporter = registry.bind::<WpViewporter, _, _>(2, 1, &qh, ());
+
+            let mut client = Self {
+                connection,
+                server,
+                qh,
+                compositor,
+                viewporter,
+                _queue: queue,
+            };
+            client.sent();
+            client
+        }
+
+        fn surface(&self) -> WlSurface {
+            self.compositor.create_surface(&self.qh, ())
+        }
+
+        ///
+        /// How many bytes of requests were sent since the last call.
+        ///
+        fn sent(&mut self) -> usize {
+            self.connection.flush().unwrap();
+            self.server.set_nonblocking(true).unwrap();
+
+            let mut sent = 0;
+            let mut buffer = [0; 4096];
+            loop {
+                match self.server.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => sent += read,
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) => panic!("{err}"),
+                }
+            }
+
+            sent
+        }
+    }
+
+    #[test]
+    fn surfaces_are_destroyed_in_the_order_requested() {
+        let client = Client::new();
+        let surfaces: Vec<_> = (0..3).map(|_| client.surface().id()).collect();
+        let requests = DestroyRequests::default();
+
+        requests.request(surfaces[2].clone());
+        requests.request(surfaces[0].clone());
+        requests.request(surfaces[1].clone());
+
+        assert_eq!(
+            requests.take(),
+            [
+                surfaces[2].clone(),
+                surfaces[0].clone(),
+                surfaces[1].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn requesting_twice_destroys_once() {
+        let client = Client::new();
+        let surface = client.surface().id();
+        let other = client.surface().id();
+        let requests = DestroyRequests::default();
+
+        requests.request(surface.clone());
+        requests.request(other.clone());
+        requests.request(surface.clone());
+        assert!(requests.is_requested(&surface));
+        assert_eq!(requests.take(), [surface.clone(), other]);
+
+        // Once carried out, there's nothing left to destroy, nor events to drop.
+        assert!(!requests.is_requested(&surface));
+        assert!(requests.take().is_empty());
+    }
+
+    #[test]
+    fn requests_are_shared_between_handles() {
+        let client = Client::new();
+        let surface = client.surface().id();
+        let requests = DestroyRequests::default();
+
+        requests.clone().request(surface.clone());
+        assert!(requests.is_requested(&surface));
+        assert_eq!(requests.take(), [surface]);
+    }
+
+    #[test]
+    fn a_forgotten_viewport_is_never_touched() {
+        let mut client = Client::new();
+        let wl_surface = client.surface();
+        let viewport = client.viewporter.get_viewport(&wl_surface, &client.qh, ());
+        let mut sync = ViewportSync::new(viewport);
+        client.sent();
+
+        sync.sync((100, 50), (150.0, 75.0));
+        sync.presented(true);
+        assert!(client.sent() > 0);
+
+        // As when its surface is destroyed, whilst handles still hold on to it.
+        sync.forget();
+        sync.sync((200, 100), (300.0, 150.0));
+        sync.presented(true);
+        sync.sync((100, 50), (150.0, 75.0));
+        sync.presented(false);
+        assert_eq!(client.sent(), 0);
+    }
+}
@@ -0,0 +1,316 @@
+//!
+//! Lifetime events of a registered surface, for application code
+//! to subscribe to through [crate::app::AvySurfaceHandle::on_event].
+//!
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    thread::{self, ThreadId},
+};
+
+use crate::wayland::protocol::fractional_scale::ScaleFactor;
+
+///
+/// Something which happened to a surface.
+///
+#[derive(Debug, Clone)]
+pub enum SurfaceEvent {
+    /// The compositor configured the surface, at this logical size.
+    Configured {
+        size: (u32, u32),
+    },
+    ScaleChanged {
+        factor: ScaleFactor,
+    },
+    /// `name` is `None` for outputs which don't advertise one.
+    EnteredOutput {
+        name: Option<String>,
+    },
+    LeftOutput {
+        name: Option<String>,
+    },
+    /// The compositor closed the surface: it won't be shown again.
+    Closed,
+    /// The surface was moved to a new graphics backend,
+    /// see [crate::AvyClient::rebind_backend].
+    BackendRecreated,
+    /// The surface went on (or off) screen, i.e. onto its first
+    /// output (or off its last one).
+    VisibilityChanged {
+        visible: bool,
+    },
+    /// The refresh rate (in Hz) of the output the surface is drawn in step
+    /// with changed, see [crate::app::AvySurfaceHandle::refresh_rate].
+    RefreshRateChanged {
+        refresh_rate: Option<f32>,
+    },
+    /// The surface gave keyboard focus back, see
+    /// [crate::app::AvySurfaceHandle::release_keyboard_focus].
+    KeyboardFocusReleased,
+}
+
+type Callback<E> = Box<dyn FnMut(E) + Send>;
+
+struct Subscriber<E> {
+    id: u64,
+    /// Only read with `callback` locked, so that no call starts once it's cleared.
+    active: AtomicBool,
+    callback: Mutex<Callback<E>>,
+    /// The thread running the callback, if it is.
+    running_on: Mutex<Option<ThreadId>>,
+}
+
+type SubscriberList<E> = Arc<Mutex<Vec<Arc<Subscriber<E>>>>>;
+
+///
+/// Callbacks subscribed to events of type `E`.
+///
+pub struct Subscribers<E> {
+    next_id: AtomicU64,
+    subscribers: SubscriberList<E>,
+}
+
+///
+/// A surface's event subscribers.
+///
+pub type SurfaceEvents = Subscribers<SurfaceEvent>;
+
+impl<E> Default for Subscribers<E> {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            subscribers: Arc::default(),
+        }
+    }
+}
+
+impl<E: Clone + 'static> Subscribers<E> {
+    pub fn subscribe(&self, callback: impl FnMut(E) + Send + 'static) -> Subscription {
+        let subscriber = Arc::new(Subscriber {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            active: AtomicBool::new(true),
+            callback: Mutex::new(Box::new(callback) as Callback<E>),
+            running_on: Mutex::new(None),
+        });
+
+        self.subscribers.lock().unwrap().push(subscriber.clone());
+
+        let weak_subscriber = Arc::downgrade(&subscriber);
+        let weak_subscribers = Arc::downgrade(&self.subscribers);
+
+        Subscription {
+            unsubscribe: Some(Box::new(move || {
+                unsubscribe(&weak_subscriber, &weak_subscribers)
+            })),
+        }
+    }
+
+    ///
+    /// Hand `event` to every subscriber, in the order they subscribed.
+    ///
+    /// [SurfaceEvent]s are emitted on the event loop. The subscriber list isn't
+    /// locked whilst callbacks run, so they may subscribe or unsubscribe freely.
+    ///
+    pub fn emit(&self, event: E) {
+        let subscribers = self.subscribers.lock().unwrap().clone();
+
+        for subscriber in subscribers {
+            // A callback which (somehow) emits on its own surface skips itself.
+            let Ok(mut callback) = subscriber.callback.try_lock() else {
+                continue;
+            };
+
+            if !subscriber.active.load(Ordering::Acquire) {
+                continue;
+            }
+
+            subscriber.set_running_on(Some(thread::current().id()));
+            callback(event.clone());
+            subscriber.set_running_on(None);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.lock().unwrap().is_empty()
+    }
+}
+
+impl<E> Subscriber<E> {
+    fn set_running_on(&self, thread: Option<ThreadId>) {
+        *self
+            .running_on
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = thread;
+    }
+
+    fn is_running_on(&self, thread: ThreadId) -> bool {
+        *self
+            .running_on
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            == Some(thread)
+    }
+}
+
+fn unsubscribe<E>(
+    subscriber: &Weak<Subscriber<E>>,
+    subscribers: &Weak<Mutex<Vec<Arc<Subscriber<E>>>>>,
+) {
+    let Some(subscriber) = subscriber.upgrade() else {
+        return;
+    };
+
+    subscriber.active.store(false, Ordering::Release);
+
+    // Wait for a call running on another thread to finish. One running on
+    // this thread is the callback unsubscribing itself, which can't be waited on.
+    if !subscriber.is_running_on(thread::current().id()) {
+        drop(subscriber.callback.lock());
+    }
+
+    if let Some(subscribers) = subscribers.upgrade() {
+        subscribers
+            .lock()
+            .unwrap()
+            .retain(|other| other.id != subscriber.id);
+    }
+}
+
+///
+/// Keeps a [SurfaceEvent] callback (or one for other [Subscribers]) subscribed.
+/// Dropping it unsubscribes.
+///
+/// Once unsubscribed, the callback is never called again. Should it be
+/// running on another thread at the time, unsubscribing waits for that call
+/// to finish, so it mustn't happen whilst holding anything the callback waits
+/// for. A callback may unsubscribe itself (it then finishes as usual).
+///
+#[must_use = "dropping a Subscription unsubscribes straight away"]
+pub struct Subscription {
+    unsubscribe: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl Subscription {
+    ///
+    /// Stay subscribed for as long as the surface lives.
+    ///
+    pub fn detach(mut self) {
+        self.unsubscribe.take();
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(unsubscribe) = self.unsubscribe.take() {
+            unsubscribe();
+        }
+    }
+}
+
+impl<E> std::fmt::Debug for Subscribers<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscribers")
+            .field("subscribers", &self.subscribers.lock().unwrap().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::AtomicUsize,
+            mpsc::{self, RecvTimeoutError},
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn unsubscribing_waits_for_a_call_on_another_thread() {
+        let subscribers = Arc::new(Subscribers::<u32>::default());
+        let (started_tx, started) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let subscription = subscribers.subscribe({
+            let finished = finished.clone();
+            move |_| {
+                started_tx.send(()).unwrap();
+                release_rx.lock().unwrap().recv().unwrap();
+                finished.store(true, Ordering::Release);
+            }
+        });
+
+        let emitter = {
+            let subscribers = subscribers.clone();
+            thread::spawn(move || subscribers.emit(1))
+        };
+        started.recv().unwrap();
+
+        let (dropped_tx, dropped) = mpsc::channel();
+        let dropper = {
+            let finished = finished.clone();
+            thread::spawn(move || {
+                drop(subscription);
+                dropped_tx.send(finished.load(Ordering::Acquire)).unwrap();
+            })
+        };
+
+        assert_eq!(
+            dropped.recv_timeout(Duration::from_millis(50)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        release.send(()).unwrap();
+        assert_eq!(dropped.recv(), Ok(true));
+
+        emitter.join().unwrap();
+        dropper.join().unwrap();
+    }
+
+    #[test]
+    fn nothing_is_called_once_unsubscribed() {
+        let subscribers = Subscribers::<u32>::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let subscription = subscribers.subscribe({
+            let calls = calls.clone();
+            move |_| {
+                calls.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        subscribers.emit(1);
+        drop(subscription);
+        subscribers.emit(2);
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert!(subscribers.is_empty());
+    }
+
+    #[test]
+    fn a_callback_can_unsubscribe_itself() {
+        let subscribers = Subscribers::<u32>::default();
+        let subscription = Arc::new(Mutex::new(None::<Subscription>));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let own = subscribers.subscribe({
+            let (subscription, calls) = (subscription.clone(), calls.clone());
+            move |_| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                subscription.lock().unwrap().take();
+            }
+        });
+        subscription.lock().unwrap().replace(own);
+
+        subscribers.emit(1);
+        subscribers.emit(2);
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
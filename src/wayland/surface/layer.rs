@@ -1,48 +1,273 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
 
 use smithay_client_toolkit::{
     reexports::{
         client::{
             protocol::{wl_output::WlOutput, wl_surface::WlSurface},
-            EventQueue,
+            Connection, EventQueue, Proxy, QueueHandle,
+        },
+        protocols::wp::{
+            fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
+            viewporter::client::wp_viewport::WpViewport,
         },
-        protocols::wp::viewporter::client::wp_viewport::WpViewport,
+        protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1,
+    },
+    seat::{keyboard::KeyEvent, pointer::PointerEvent},
+    shell::{
+        wlr_layer::{self, SurfaceKind},
+        WaylandSurface,
     },
-    shell::{wlr_layer, WaylandSurface},
 };
+use thiserror::Error;
 
 use crate::{
     app::{AvyClient, RegisteredSurface},
-    impl_as_any,
-    util::Size,
+    debug, impl_as_any,
+    util::{
+        animation::{Animation, Easing, Lerp},
+        DrawingPoint, GlobalRect, Insets, OutputGeometry, SharedClock, Size, SurfacePoint,
+    },
 };
 
-use super::{AvySurface, InputHandler, KeyboardHandler, PointerHandler, TouchHandler};
+use super::{
+    configure::{ConfigureAck, PendingConfigure},
+    AvySurface, InputHandler, KeyboardHandler, PointerHandler, TouchHandler,
+};
+
+///
+/// The `zwlr_layer_shell_v1` version [AvyLayerController::set_layer] needs.
+///
+pub const SET_LAYER_SINCE: u32 = zwlr_layer_surface_v1::REQ_SET_LAYER_SINCE;
+
+///
+/// The `zwlr_layer_shell_v1` version [wlr_layer::KeyboardInteractivity::OnDemand] needs.
+///
+pub const ON_DEMAND_SINCE: u32 = 4;
+
+#[derive(Debug, Clone, Copy, Error)]
+pub enum LayerError {
+    #[error(
+        "{feature} needs zwlr_layer_shell_v1 version {needs_version}, \
+         but the compositor only supports version {bound_version}."
+    )]
+    Unsupported {
+        feature: &'static str,
+        needs_version: u32,
+        bound_version: u32,
+    },
+}
+
+fn require_version(
+    bound_version: u32,
+    feature: &'static str,
+    needs_version: u32,
+) -> Result<(), LayerError> {
+    if bound_version < needs_version {
+        return Err(LayerError::Unsupported {
+            feature,
+            needs_version,
+            bound_version,
+        });
+    }
+
+    Ok(())
+}
+
+fn require_keyboard_interactivity(
+    bound_version: u32,
+    keyboard_interactivity: wlr_layer::KeyboardInteractivity,
+) -> Result<(), LayerError> {
+    if keyboard_interactivity == wlr_layer::KeyboardInteractivity::OnDemand {
+        require_version(
+            bound_version,
+            "On-demand keyboard interactivity",
+            ON_DEMAND_SINCE,
+        )?;
+    }
+
+    Ok(())
+}
+
+///
+/// The version of the layer shell `layer` was created with.
+///
+fn shell_version(layer: &wlr_layer::LayerSurface) -> u32 {
+    match layer.kind() {
+        SurfaceKind::Wlr(wlr) => wlr.version(),
+        _ => unreachable!("Unknown layer surface kind"),
+    }
+}
 
 pub struct AvyLayerParams<'a> {
     pub layer: wlr_layer::Layer,
     pub namespace: Option<&'a str>,
-    pub output: Option<&'a WlOutput>,
+    pub output: Option<WlOutput>,
 
     pub anchor: wlr_layer::Anchor,
     pub size: Size,
     pub margin: Option<(i32, i32, i32, i32)>,
+    pub exclusive_zone: Option<i32>,
     pub keyboard_interactivity: wlr_layer::KeyboardInteractivity,
+
+    /// Leave acknowledging configures to the app (or the next frame drawn at
+    /// the configured size), see [AvyLayerController::ack_configure].
+    pub manual_configure_ack: bool,
 }
 
 pub struct AvyLayer {
     layer: wlr_layer::LayerSurface,
     viewport: WpViewport,
+    fractional_scale: WpFractionalScaleV1,
     size: Arc<RwLock<Size>>,
+    /// The logical size asked for, kept on axes the compositor leaves to us.
+    requested_size: (u32, u32),
+    state: Arc<Mutex<LayerState>>,
+    qh: QueueHandle<AvyClient>,
+    namespace: Option<String>,
+    /// The output asked for, which can't change without recreating the layer.
+    output: Option<WlOutput>,
+    configure_ack: Option<ConfigureAck>,
+}
+
+///
+/// Runtime-changeable layer properties, shared between
+/// an [AvyLayer] and its [AvyLayerController]s.
+///
+pub struct LayerState {
+    layer: wlr_layer::Layer,
+    anchor: wlr_layer::Anchor,
+    margin: Insets,
+    exclusive_zone: i32,
+    keyboard_interactivity: wlr_layer::KeyboardInteractivity,
+    /// Stopped being [wlr_layer::KeyboardInteractivity::Exclusive], see
+    /// [AvyLayer::take_exclusive_keyboard_released].
+    exclusive_keyboard_released: bool,
+    track_exclusive_zone: bool,
+    margin_animation: Option<MarginAnimation>,
+    /// The client's, which margin animations go by.
+    clock: SharedClock,
+    on_configure: Option<Box<dyn FnMut(PendingConfigure) + Send>>,
+    on_input: Option<Box<dyn FnMut(LayerInput) + Send>>,
+}
+
+///
+/// Input for a layer surface, see [AvyLayerController::set_on_input].
+///
+/// Positions are in logical pixels, relative to the surface. `drawing` is the
+/// same position in the drawing space, turned by the surface's
+/// [crate::util::Transform] to match what was drawn under it.
+///
+#[derive(Debug, Clone)]
+pub enum LayerInput {
+    Pointer {
+        event: PointerEvent,
+        drawing: DrawingPoint,
+    },
+    KeyboardEnter,
+    KeyboardLeave,
+    KeyPress(KeyEvent),
+    KeyRelease(KeyEvent),
+    TouchDown {
+        id: i32,
+        position: SurfacePoint,
+        drawing: DrawingPoint,
+    },
+    TouchMotion {
+        id: i32,
+        position: SurfacePoint,
+        drawing: DrawingPoint,
+    },
+    TouchUp {
+        id: i32,
+    },
+    TouchCancel,
+}
+
+struct MarginAnimation {
+    from: Insets,
+    to: Insets,
+    animation: Animation,
+    on_complete: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl LayerState {
+    ///
+    /// The exclusive zone to hand to the compositor,
+    /// taking margin tracking into account.
+    ///
+    fn effective_exclusive_zone(&self) -> i32 {
+        if !self.track_exclusive_zone || self.exclusive_zone <= 0 {
+            return self.exclusive_zone;
+        }
+
+        use wlr_layer::Anchor;
+        let anchor = self.anchor;
+        let edge_margin = if anchor.contains(Anchor::TOP) && !anchor.contains(Anchor::BOTTOM) {
+            self.margin.top
+        } else if anchor.contains(Anchor::BOTTOM) && !anchor.contains(Anchor::TOP) {
+            self.margin.bottom
+        } else if anchor.contains(Anchor::LEFT) && !anchor.contains(Anchor::RIGHT) {
+            self.margin.left
+        } else if anchor.contains(Anchor::RIGHT) && !anchor.contains(Anchor::LEFT) {
+            self.margin.right
+        } else {
+            0
+        };
+
+        (self.exclusive_zone + edge_margin).max(0)
+    }
+
+    fn set_keyboard_interactivity(
+        &mut self,
+        keyboard_interactivity: wlr_layer::KeyboardInteractivity,
+    ) {
+        use wlr_layer::KeyboardInteractivity::Exclusive;
+
+        if keyboard_interactivity == Exclusive {
+            self.exclusive_keyboard_released = false;
+        } else if self.keyboard_interactivity == Exclusive {
+            self.exclusive_keyboard_released = true;
+        }
+
+        self.keyboard_interactivity = keyboard_interactivity;
+    }
+}
+
+impl AvyLayerParams<'_> {
+    ///
+    /// Check that the compositor's layer shell (at `layer_shell_version`,
+    /// see [AvyClient::layer_shell_version]) supports these parameters.
+    ///
+    pub fn validate(&self, layer_shell_version: u32) -> Result<(), LayerError> {
+        require_keyboard_interactivity(layer_shell_version, self.keyboard_interactivity)
+    }
 }
 
 impl_as_any!(AvyLayer);
 
+impl Drop for AvyLayer {
+    fn drop(&mut self) {
+        // Before the surface they extend, which goes with the last
+        // [wlr_layer::LayerSurface] (e.g. once `layer` is dropped).
+        self.fractional_scale.destroy();
+        self.viewport.destroy();
+        debug::objects::destroyed(&self.fractional_scale.id());
+        debug::objects::destroyed(&self.viewport.id());
+    }
+}
+
 impl AvySurface for AvyLayer {
     fn wl_surface(&self) -> &WlSurface {
         self.layer.wl_surface()
     }
 
+    fn kind(&self) -> super::SurfaceKind {
+        super::SurfaceKind::Layer
+    }
+
     fn viewport(&mut self) -> &mut WpViewport {
         &mut self.viewport
     }
@@ -50,16 +275,100 @@ impl AvySurface for AvyLayer {
     fn size(&self) -> &Arc<RwLock<Size>> {
         &self.size
     }
+
+    fn debug_name(&self) -> Option<String> {
+        self.namespace
+            .as_ref()
+            .map(|namespace| format!("layer:{namespace}"))
+    }
+
+    fn configure_ack(&self) -> Option<&ConfigureAck> {
+        self.configure_ack.as_ref()
+    }
+
+    fn map_after_first_frame(&self) -> bool {
+        true
+    }
+
+    ///
+    /// The compositor's size on each axis it gave one for, and ours where it sent 0.
+    ///
+    fn configured_size(&self, suggested: (u32, u32)) -> (u32, u32) {
+        merge_configured_size(suggested, self.requested_size)
+    }
+
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<AvyClient>,
+        configure: PendingConfigure,
+    ) {
+        if let Some(on_configure) = self.state.lock().unwrap().on_configure.as_mut() {
+            on_configure(configure);
+        }
+    }
+
+    fn frame(&mut self, _: &Connection, qh: &QueueHandle<AvyClient>, _: u32) {
+        let on_complete = {
+            let mut state = self.state.lock().unwrap();
+            let Some(animation) = state.margin_animation.as_ref() else {
+                return;
+            };
+
+            let now = state.clock.now();
+            let finished = animation.animation.is_finished(now);
+            let margin = if finished {
+                animation.to
+            } else {
+                animation
+                    .from
+                    .lerp(&animation.to, animation.animation.progress(now))
+            };
+
+            LayerTransaction::new()
+                .margin(margin)
+                .apply(&self.layer, &mut state);
+
+            if finished {
+                state
+                    .margin_animation
+                    .take()
+                    .and_then(|animation| animation.on_complete)
+            } else {
+                let wl_surface = self.layer.wl_surface();
+                let callback = wl_surface.frame(qh, wl_surface.clone());
+                debug::objects::created(&callback.id(), Some(&wl_surface.id()));
+                None
+            }
+        };
+
+        self.layer.commit();
+
+        if let Some(on_complete) = on_complete {
+            on_complete();
+        }
+    }
 }
 
 impl InputHandler for AvyLayer {}
 
 impl AvyLayer {
+    ///
+    /// Create a layer surface and register it with `app`.
+    ///
+    /// Fails if the compositor's layer shell is too old for `params`
+    /// (see [AvyLayerParams::validate]), before anything is created.
+    ///
     pub fn build<'a>(
         app: &'a mut AvyClient,
         event_queue: &mut EventQueue<AvyClient>,
         params: AvyLayerParams,
-    ) -> RegisteredSurface<'a> {
+    ) -> Result<RegisteredSurface<'a>, LayerError> {
+        #[cfg(feature = "config")]
+        let params = crate::config::rules::apply_to_params(app, params);
+
+        params.validate(app.layer_shell_version())?;
+
         let qh = &event_queue.handle();
 
         // Setup layer surface.
@@ -69,7 +378,7 @@ impl AvyLayer {
             wl_surface.clone(),
             params.layer,
             params.namespace,
-            params.output,
+            params.output.as_ref(),
         );
 
         layer.set_anchor(params.anchor);
@@ -79,26 +388,446 @@ impl AvyLayer {
 
         layer.set_keyboard_interactivity(params.keyboard_interactivity);
 
-        if let Some((top, right, bottom, left)) = params.margin {
-            layer.set_margin(top, right, bottom, left);
+        // Left unset, these are up to the compositor's defaults.
+        let margin = params.margin.map(Insets::from);
+        if let Some(margin) = margin {
+            layer.set_margin(margin.top, margin.right, margin.bottom, margin.left);
+        }
+        let margin = margin.unwrap_or_default();
+
+        let exclusive_zone = params.exclusive_zone;
+        if let Some(exclusive_zone) = exclusive_zone {
+            layer.set_exclusive_zone(exclusive_zone);
         }
+        let exclusive_zone = exclusive_zone.unwrap_or_default();
 
         // Use fractional scaling.
-        app.fractional_scale.fractional_scaling(&wl_surface, qh);
+        let fractional_scale = app.fractional_scale.fractional_scaling(&wl_surface, qh);
+
+        // Before registering, whose roundtrip may already raise errors on them.
+        if let SurfaceKind::Wlr(wlr) = layer.kind() {
+            app.objects.track(wlr.id(), wl_surface.id());
+        }
+        app.objects.track(fractional_scale.id(), wl_surface.id());
 
         // Make a viewport for the surface.
         let viewport = app.viewporter.get_viewport(&wl_surface, qh);
 
-        let registered_surface = app.register_surface(
+        // Holds the bare protocol object, which (unlike the [wlr_layer::LayerSurface])
+        // doesn't keep the layer alive.
+        let configure_ack = params.manual_configure_ack.then(|| {
+            let wlr = match layer.kind() {
+                SurfaceKind::Wlr(wlr) => wlr.clone(),
+                _ => unreachable!("Unknown layer surface kind"),
+            };
+
+            ConfigureAck::new(move |serial| wlr.ack_configure(serial))
+        });
+
+        let exclusive_keyboard =
+            params.keyboard_interactivity == wlr_layer::KeyboardInteractivity::Exclusive;
+
+        let registered_surface = app.register(
             AvyLayer {
                 layer: layer.clone(),
                 viewport,
+                fractional_scale,
                 size: Arc::new(RwLock::new(params.size)),
+                requested_size: (width, height),
+                state: Arc::new(Mutex::new(LayerState {
+                    layer: params.layer,
+                    anchor: params.anchor,
+                    margin,
+                    exclusive_zone,
+                    keyboard_interactivity: params.keyboard_interactivity,
+                    exclusive_keyboard_released: false,
+                    track_exclusive_zone: false,
+                    margin_animation: None,
+                    clock: app.clock.clone(),
+                    on_configure: None,
+                    on_input: None,
+                })),
+                qh: qh.clone(),
+                namespace: params.namespace.map(str::to_owned),
+                output: params.output.clone(),
+                configure_ack,
             },
+            None,
             event_queue,
         );
 
-        registered_surface
+        // Whatever the compositor does with focus, keys typed into
+        // an exclusive layer (a lock screen, a launcher) stay there.
+        if exclusive_keyboard {
+            return Ok(registered_surface.with_exclusive_keyboard());
+        }
+
+        Ok(registered_surface)
+    }
+
+    pub fn controller(&self) -> AvyLayerController {
+        AvyLayerController {
+            layer: self.layer.clone(),
+            size: self.size.clone(),
+            state: self.state.clone(),
+            qh: self.qh.clone(),
+            output: self.output.clone(),
+            requested_size: self.requested_size,
+            configure_ack: self.configure_ack.clone(),
+        }
+    }
+
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+}
+
+///
+/// A batch of layer property changes, applied together in one commit.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LayerTransaction {
+    anchor: Option<wlr_layer::Anchor>,
+    margin: Option<Insets>,
+    exclusive_zone: Option<i32>,
+    keyboard_interactivity: Option<wlr_layer::KeyboardInteractivity>,
+}
+
+impl LayerTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn anchor(mut self, anchor: wlr_layer::Anchor) -> Self {
+        self.anchor.replace(anchor);
+        self
+    }
+
+    pub fn margin(mut self, margin: Insets) -> Self {
+        self.margin.replace(margin);
+        self
+    }
+
+    pub fn exclusive_zone(mut self, exclusive_zone: i32) -> Self {
+        self.exclusive_zone.replace(exclusive_zone);
+        self
+    }
+
+    pub fn keyboard_interactivity(
+        mut self,
+        keyboard_interactivity: wlr_layer::KeyboardInteractivity,
+    ) -> Self {
+        self.keyboard_interactivity.replace(keyboard_interactivity);
+        self
+    }
+
+    ///
+    /// Send the changes to the compositor, without committing.
+    ///
+    fn apply(self, layer: &wlr_layer::LayerSurface, state: &mut LayerState) {
+        if let Some(anchor) = self.anchor {
+            state.anchor = anchor;
+            layer.set_anchor(anchor);
+        }
+
+        if let Some(margin) = self.margin {
+            state.margin = margin;
+            layer.set_margin(margin.top, margin.right, margin.bottom, margin.left);
+        }
+
+        if let Some(exclusive_zone) = self.exclusive_zone {
+            state.exclusive_zone = exclusive_zone;
+        }
+
+        let moved = self.margin.is_some() || self.anchor.is_some();
+        if self.exclusive_zone.is_some() || (moved && state.track_exclusive_zone) {
+            layer.set_exclusive_zone(state.effective_exclusive_zone());
+        }
+
+        if let Some(keyboard_interactivity) = self.keyboard_interactivity {
+            state.set_keyboard_interactivity(keyboard_interactivity);
+            layer.set_keyboard_interactivity(keyboard_interactivity);
+        }
+    }
+}
+
+///
+/// Changes an [AvyLayer]'s properties at runtime.
+///
+/// Can be freely cloned and sent to other threads, but every change commits
+/// the layer's `wl_surface` there and then, from the calling thread. Made off
+/// the event loop, a change can be committed along with whatever a frame drawn
+/// on the event loop has set so far, so send changes to a layer drawn on the
+/// event loop through [crate::proxy::AvyProxy] instead.
+///
+#[derive(Clone)]
+pub struct AvyLayerController {
+    layer: wlr_layer::LayerSurface,
+    size: Arc<RwLock<Size>>,
+    state: Arc<Mutex<LayerState>>,
+    qh: QueueHandle<AvyClient>,
+    output: Option<WlOutput>,
+    requested_size: (u32, u32),
+    configure_ack: Option<ConfigureAck>,
+}
+
+impl AvyLayerController {
+    pub fn layer(&self) -> wlr_layer::Layer {
+        self.state.lock().unwrap().layer
+    }
+
+    pub fn anchor(&self) -> wlr_layer::Anchor {
+        self.state.lock().unwrap().anchor
+    }
+
+    pub fn margin(&self) -> Insets {
+        self.state.lock().unwrap().margin
+    }
+
+    pub fn exclusive_zone(&self) -> i32 {
+        self.state.lock().unwrap().exclusive_zone
+    }
+
+    pub fn keyboard_interactivity(&self) -> wlr_layer::KeyboardInteractivity {
+        self.state.lock().unwrap().keyboard_interactivity
+    }
+
+    ///
+    /// The logical size the layer was built with, zero on axes
+    /// left to the compositor.
+    ///
+    pub fn requested_size(&self) -> (u32, u32) {
+        self.requested_size
+    }
+
+    ///
+    /// The output the layer was created on, or `None` if left to the compositor.
+    ///
+    pub fn output(&self) -> Option<&WlOutput> {
+        self.output.as_ref()
+    }
+
+    ///
+    /// Where the layer is in the global space, when on `output`, from its anchor,
+    /// margin and configured size, e.g. to place popups or follow drags across outputs.
+    ///
+    pub fn surface_global_rect(&self, output: &OutputGeometry) -> GlobalRect {
+        let (anchor, margin) = {
+            let state = self.state.lock().unwrap();
+            (state.anchor, state.margin)
+        };
+        let size = self.size.read().unwrap().logical_size();
+
+        output.layer_rect(anchor, margin, size)
+    }
+
+    ///
+    /// The version of `zwlr_layer_shell_v1` the layer was created with.
+    ///
+    pub fn shell_version(&self) -> u32 {
+        shell_version(&self.layer)
+    }
+
+    ///
+    /// Apply a transaction and commit it.
+    ///
+    /// Nothing is sent if the compositor doesn't support part of it.
+    ///
+    pub fn commit(&self, transaction: LayerTransaction) -> Result<(), LayerError> {
+        if let Some(keyboard_interactivity) = transaction.keyboard_interactivity {
+            require_keyboard_interactivity(self.shell_version(), keyboard_interactivity)?;
+        }
+
+        self.commit_unchecked(transaction);
+        Ok(())
+    }
+
+    fn commit_unchecked(&self, transaction: LayerTransaction) {
+        let mut state = self.state.lock().unwrap();
+
+        // Explicit margins win over any running animation.
+        if transaction.margin.is_some() {
+            state.margin_animation.take();
+        }
+
+        transaction.apply(&self.layer, &mut state);
+        self.layer.commit();
+    }
+
+    pub fn set_anchor(&self, anchor: wlr_layer::Anchor) {
+        self.commit_unchecked(LayerTransaction::new().anchor(anchor));
+    }
+
+    pub fn set_margin(&self, margin: impl Into<Insets>) {
+        self.commit_unchecked(LayerTransaction::new().margin(margin.into()));
+    }
+
+    pub fn set_exclusive_zone(&self, exclusive_zone: i32) {
+        self.commit_unchecked(LayerTransaction::new().exclusive_zone(exclusive_zone));
+    }
+
+    ///
+    /// Fails for [wlr_layer::KeyboardInteractivity::OnDemand]
+    /// before version [ON_DEMAND_SINCE] of the layer shell.
+    ///
+    pub fn set_keyboard_interactivity(
+        &self,
+        keyboard_interactivity: wlr_layer::KeyboardInteractivity,
+    ) -> Result<(), LayerError> {
+        self.commit(LayerTransaction::new().keyboard_interactivity(keyboard_interactivity))
+    }
+
+    ///
+    /// Give keyboard focus back, if the layer took it on demand: its
+    /// interactivity goes to none, which has the compositor move focus away,
+    /// and straight back to on-demand, so that it takes focus when next clicked.
+    ///
+    /// Whether the layer was on-demand, i.e. anything was sent.
+    ///
+    pub fn release_keyboard_focus(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.keyboard_interactivity != wlr_layer::KeyboardInteractivity::OnDemand {
+            return false;
+        }
+
+        // Each its own commit, as the compositor only sees the state it's committed.
+        for keyboard_interactivity in [
+            wlr_layer::KeyboardInteractivity::None,
+            wlr_layer::KeyboardInteractivity::OnDemand,
+        ] {
+            LayerTransaction::new()
+                .keyboard_interactivity(keyboard_interactivity)
+                .apply(&self.layer, &mut state);
+            self.layer.commit();
+        }
+
+        true
+    }
+
+    ///
+    /// Move the layer to another layer of the shell, e.g. from
+    /// [wlr_layer::Layer::Top] to [wlr_layer::Layer::Overlay].
+    ///
+    /// Fails before version [SET_LAYER_SINCE] of the layer shell.
+    ///
+    pub fn set_layer(&self, layer: wlr_layer::Layer) -> Result<(), LayerError> {
+        require_version(self.shell_version(), "Changing layers", SET_LAYER_SINCE)?;
+
+        self.state.lock().unwrap().layer = layer;
+        self.layer.set_layer(layer);
+        self.layer.commit();
+
+        Ok(())
+    }
+
+    ///
+    /// Make the exclusive zone follow the margin on the anchored edge,
+    /// so that windows move along with an animated layer.
+    ///
+    pub fn set_track_exclusive_zone(&self, track: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.track_exclusive_zone = track;
+
+        self.layer
+            .set_exclusive_zone(state.effective_exclusive_zone());
+        self.layer.commit();
+    }
+
+    ///
+    /// Animate the margin from its current value to `target`,
+    /// cancelling any margin animation already running.
+    ///
+    pub fn animate_margin(
+        &self,
+        target: impl Into<Insets>,
+        duration: Duration,
+        easing: Easing,
+        on_complete: impl FnOnce() + Send + 'static,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let from = state.margin;
+
+        state.margin_animation.replace(MarginAnimation {
+            from,
+            to: target.into(),
+            animation: Animation::starting_at(state.clock.now(), duration, easing),
+            on_complete: Some(Box::new(on_complete)),
+        });
+
+        let wl_surface = self.layer.wl_surface();
+        let callback = wl_surface.frame(&self.qh, wl_surface.clone());
+        debug::objects::created(&callback.id(), Some(&wl_surface.id()));
+        self.layer.commit();
+    }
+
+    pub fn is_animating_margin(&self) -> bool {
+        self.state.lock().unwrap().margin_animation.is_some()
+    }
+
+    ///
+    /// Call `on_configure` whenever the compositor configures the layer
+    /// (on the event loop's thread).
+    ///
+    pub fn set_on_configure(&self, on_configure: impl FnMut(PendingConfigure) + Send + 'static) {
+        self.state
+            .lock()
+            .unwrap()
+            .on_configure
+            .replace(Box::new(on_configure));
+    }
+
+    ///
+    /// Call `on_input` with the pointer, keyboard and touch input the layer
+    /// gets (on the event loop's thread).
+    ///
+    pub fn set_on_input(&self, on_input: impl FnMut(LayerInput) + Send + 'static) {
+        self.state
+            .lock()
+            .unwrap()
+            .on_input
+            .replace(Box::new(on_input));
+    }
+
+    ///
+    /// The configure waiting to be acknowledged, if the layer
+    /// was built with [AvyLayerParams::manual_configure_ack].
+    ///
+    pub fn pending_configure(&self) -> Option<PendingConfigure> {
+        self.configure_ack.as_ref()?.pending()
+    }
+
+    ///
+    /// Acknowledge the pending configure, once a frame has been drawn at the new size.
+    ///
+    /// The next commit must carry that frame. Rendering through the surface's
+    /// [crate::app::AvySurfaceHandle] does this by itself, right before presenting.
+    ///
+    pub fn ack_configure(&self) -> Option<PendingConfigure> {
+        self.configure_ack.as_ref()?.ack()
+    }
+}
+
+impl AvyLayer {
+    ///
+    /// Whether the layer's keyboard interactivity changed away from
+    /// [wlr_layer::KeyboardInteractivity::Exclusive] since last asked,
+    /// so that it should stop getting every key event.
+    ///
+    pub(crate) fn take_exclusive_keyboard_released(&self) -> bool {
+        std::mem::take(&mut self.state.lock().unwrap().exclusive_keyboard_released)
+    }
+
+    fn input(&self, input: LayerInput) {
+        // Not called with the state locked, so it can use the layer's controller.
+        let on_input = self.state.lock().unwrap().on_input.take();
+        if let Some(mut on_input) = on_input {
+            on_input(input);
+            self.state.lock().unwrap().on_input.get_or_insert(on_input);
+        }
+    }
+
+    fn to_drawing_space(&self, position: SurfacePoint) -> DrawingPoint {
+        position.to_drawing_space(&self.size.read().unwrap().snapshot())
     }
 }
 
@@ -114,6 +843,7 @@ impl KeyboardHandler for AvyLayer {
         raw: &[u32],
         keysyms: &[smithay_client_toolkit::seat::keyboard::Keysym],
     ) {
+        self.input(LayerInput::KeyboardEnter);
     }
 
     fn leave(
@@ -124,6 +854,7 @@ impl KeyboardHandler for AvyLayer {
         surface: &smithay_client_toolkit::reexports::client::protocol::wl_surface::WlSurface,
         serial: u32,
     ) {
+        self.input(LayerInput::KeyboardLeave);
     }
 
     fn press_key(
@@ -134,6 +865,7 @@ impl KeyboardHandler for AvyLayer {
         serial: u32,
         event: smithay_client_toolkit::seat::keyboard::KeyEvent,
     ) {
+        self.input(LayerInput::KeyPress(event));
     }
 
     fn release_key(
@@ -144,6 +876,7 @@ impl KeyboardHandler for AvyLayer {
         serial: u32,
         event: smithay_client_toolkit::seat::keyboard::KeyEvent,
     ) {
+        self.input(LayerInput::KeyRelease(event));
     }
 
     fn update_modifiers(
@@ -171,7 +904,13 @@ impl TouchHandler for AvyLayer {
         id: i32,
         position: (f64, f64),
     ) {
-        println!("Touch down: {position:?}")
+        let position = SurfacePoint::from(position);
+        let drawing = self.to_drawing_space(position);
+        self.input(LayerInput::TouchDown {
+            id,
+            position,
+            drawing,
+        });
     }
 
     fn up(
@@ -183,6 +922,7 @@ impl TouchHandler for AvyLayer {
         time: u32,
         id: i32,
     ) {
+        self.input(LayerInput::TouchUp { id });
     }
 
     fn motion(
@@ -194,7 +934,13 @@ impl TouchHandler for AvyLayer {
         id: i32,
         position: (f64, f64),
     ) {
-        println!("Touch move: {position:?}")
+        let position = SurfacePoint::from(position);
+        let drawing = self.to_drawing_space(position);
+        self.input(LayerInput::TouchMotion {
+            id,
+            position,
+            drawing,
+        });
     }
 
     fn shape(
@@ -224,10 +970,29 @@ impl TouchHandler for AvyLayer {
         qh: &smithay_client_toolkit::reexports::client::QueueHandle<AvyClient>,
         touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
     ) {
+        self.input(LayerInput::TouchCancel);
     }
 }
 
 #[allow(unused)]
+///
+/// `suggested` on each axis the compositor gave a size for, and `requested` where it sent 0.
+///
+fn merge_configured_size(suggested: (u32, u32), requested: (u32, u32)) -> (u32, u32) {
+    let merge = |suggested: u32, requested: u32| {
+        if suggested == 0 {
+            requested
+        } else {
+            suggested
+        }
+    };
+
+    (
+        merge(suggested.0, requested.0),
+        merge(suggested.1, requested.1),
+    )
+}
+
 impl PointerHandler for AvyLayer {
     fn pointer_frame(
         &mut self,
@@ -236,5 +1001,81 @@ impl PointerHandler for AvyLayer {
         pointer: &smithay_client_toolkit::reexports::client::protocol::wl_pointer::WlPointer,
         events: &[smithay_client_toolkit::seat::pointer::PointerEvent],
     ) {
+        for event in events {
+            let drawing = self.to_drawing_space(SurfacePoint::from(event.position));
+            self.input(LayerInput::Pointer {
+                event: event.clone(),
+                drawing,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wlr_layer::KeyboardInteractivity;
+
+    use super::*;
+
+    fn state(keyboard_interactivity: KeyboardInteractivity) -> LayerState {
+        LayerState {
+            layer: wlr_layer::Layer::Overlay,
+            anchor: wlr_layer::Anchor::empty(),
+            margin: Insets::default(),
+            exclusive_zone: 0,
+            keyboard_interactivity,
+            exclusive_keyboard_released: false,
+            track_exclusive_zone: false,
+            margin_animation: None,
+            clock: SharedClock::default(),
+            on_configure: None,
+            on_input: None,
+        }
+    }
+
+    #[test]
+    fn releases_the_keyboard_when_no_longer_exclusive() {
+        let mut state = state(KeyboardInteractivity::Exclusive);
+
+        state.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
+        assert!(state.exclusive_keyboard_released);
+    }
+
+    #[test]
+    fn keeps_the_keyboard_when_exclusive_again() {
+        let mut state = state(KeyboardInteractivity::Exclusive);
+
+        state.set_keyboard_interactivity(KeyboardInteractivity::None);
+        state.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+        assert!(!state.exclusive_keyboard_released);
+    }
+
+    #[test]
+    fn only_exclusive_layers_release_the_keyboard() {
+        let mut state = state(KeyboardInteractivity::OnDemand);
+
+        state.set_keyboard_interactivity(KeyboardInteractivity::None);
+        assert!(!state.exclusive_keyboard_released);
+    }
+
+    #[test]
+    fn configures_keep_the_requested_size_on_axes_left_at_zero() {
+        let requested = (400, 30);
+
+        assert_eq!(merge_configured_size((0, 0), requested), (400, 30));
+        // Anchored left and right, e.g. a bar: the compositor picks the width.
+        assert_eq!(merge_configured_size((1920, 0), requested), (1920, 30));
+        // Anchored top and bottom, e.g. a side panel: it picks the height.
+        assert_eq!(merge_configured_size((0, 1080), requested), (400, 1080));
+        assert_eq!(merge_configured_size((800, 600), requested), (800, 600));
+    }
+
+    #[test]
+    fn configures_of_the_same_size_keep_it() {
+        let requested = (400, 30);
+        let first = merge_configured_size((1920, 0), requested);
+
+        assert_eq!(merge_configured_size((1920, 0), requested), first);
+        assert_eq!(merge_configured_size(first, requested), first);
     }
 }
@@ -0,0 +1,355 @@
+//!
+//! Layers laid out against the edges of their output together, so that a
+//! client's own bars, docks and sidebars don't overlap each other (compositors
+//! only keep windows clear of exclusive zones).
+//!
+//! Layers join with [AvyClient::join_edge_layout], taking an edge with a
+//! thickness and a priority. Each is anchored to its edge and stretched along
+//! it, and its margins are worked out (see [solve]) so that it sits inside the
+//! layers with a higher priority on its own edge, and clear of those on the
+//! edges across it. The layout is redone whenever a layer joins or leaves, or
+//! one's size or output changes, with one transaction for each layer which moves.
+//!
+
+use std::{cmp::Reverse, collections::HashMap};
+
+use smithay_client_toolkit::{
+    reexports::client::protocol::wl_output::WlOutput, shell::wlr_layer::Anchor,
+};
+use wayland_backend::client::ObjectId;
+
+use crate::{
+    util::{GlobalRect, Insets},
+    AvyClient,
+};
+
+use super::{layer::LayerTransaction, AvySurface, SurfaceKind, WrongKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LayoutEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl LayoutEdge {
+    ///
+    /// Anchors for a layer spanning the whole edge.
+    ///
+    pub fn anchor(self) -> Anchor {
+        match self {
+            Self::Top => Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+            Self::Bottom => Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT,
+            Self::Left => Anchor::LEFT | Anchor::TOP | Anchor::BOTTOM,
+            Self::Right => Anchor::RIGHT | Anchor::TOP | Anchor::BOTTOM,
+        }
+    }
+
+    ///
+    /// The edges a layer on this one reaches across to.
+    ///
+    fn across(self) -> [Self; 2] {
+        match self {
+            Self::Top | Self::Bottom => [Self::Left, Self::Right],
+            Self::Left | Self::Right => [Self::Top, Self::Bottom],
+        }
+    }
+
+    ///
+    /// How far a layer of `size` reaches in from this edge.
+    ///
+    fn depth(self, (width, height): (u32, u32)) -> u32 {
+        match self {
+            Self::Top | Self::Bottom => height,
+            Self::Left | Self::Right => width,
+        }
+    }
+
+    fn of(self, insets: &Insets) -> i32 {
+        match self {
+            Self::Top => insets.top,
+            Self::Bottom => insets.bottom,
+            Self::Left => insets.left,
+            Self::Right => insets.right,
+        }
+    }
+
+    fn of_mut(self, insets: &mut Insets) -> &mut i32 {
+        match self {
+            Self::Top => &mut insets.top,
+            Self::Bottom => &mut insets.bottom,
+            Self::Left => &mut insets.left,
+            Self::Right => &mut insets.right,
+        }
+    }
+}
+
+///
+/// A layer's place in the layout, see [AvyClient::join_edge_layout].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeSlot {
+    pub edge: LayoutEdge,
+    /// How far the layer reaches in from its edge, in logical pixels, or
+    /// `None` for as far as it's configured to.
+    pub thickness: Option<u32>,
+    ///
+    /// Layers with a higher priority are closer to their edge, and have the
+    /// corners they share with the edges across theirs. Ties go to the
+    /// layer which joined first.
+    ///
+    pub priority: i32,
+}
+
+///
+/// Margins of layers on the same output, taking an edge each with a thickness
+/// and a priority (in the order they joined), and how much of each edge of the
+/// output they take in all.
+///
+pub fn solve(slots: &[(LayoutEdge, u32, i32)]) -> (Vec<Insets>, Insets) {
+    let mut order: Vec<usize> = (0..slots.len()).collect();
+    order.sort_by_key(|&index| Reverse(slots[index].2));
+
+    let mut margins = vec![Insets::ZERO; slots.len()];
+    let mut taken = Insets::ZERO;
+
+    for index in order {
+        let (edge, thickness, _) = slots[index];
+        let margin = &mut margins[index];
+
+        *edge.of_mut(margin) = edge.of(&taken);
+        for across in edge.across() {
+            *across.of_mut(margin) = across.of(&taken);
+        }
+
+        *edge.of_mut(&mut taken) += thickness as i32;
+    }
+
+    (margins, taken)
+}
+
+///
+/// Every layer in the layout, see the [module docs](self).
+///
+#[derive(Debug, Default)]
+pub struct EdgeLayout {
+    /// In the order they joined.
+    members: Vec<(ObjectId, EdgeSlot)>,
+    /// Margin and thickness each layer was last committed with.
+    applied: HashMap<ObjectId, (Insets, u32)>,
+    /// How much of each edge the layers take, by output (`None`
+    /// for layers whose output isn't known yet).
+    taken: HashMap<Option<WlOutput>, Insets>,
+}
+
+impl EdgeLayout {
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.members.iter().any(|(member, _)| member == id)
+    }
+
+    pub fn slot(&self, id: &ObjectId) -> Option<EdgeSlot> {
+        self.members
+            .iter()
+            .find(|(member, _)| member == id)
+            .map(|(_, slot)| *slot)
+    }
+
+    ///
+    /// How much of each edge of `output` the layers take.
+    ///
+    pub fn taken(&self, output: &WlOutput) -> Insets {
+        self.taken
+            .get(&Some(output.clone()))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl AvyClient {
+    ///
+    /// Lay out the layer `id` against an edge of its output, with the others
+    /// that joined, see [EdgeSlot]. Joining again moves it to `slot`.
+    ///
+    /// The layer's anchor, margin and exclusive zone are the layout's from then on.
+    ///
+    pub fn join_edge_layout(&mut self, id: &ObjectId, slot: EdgeSlot) -> Result<(), WrongKind> {
+        if self.layer(id).is_none() {
+            return Err(WrongKind {
+                expected: SurfaceKind::Layer,
+                found: self.surface_kind(id).unwrap_or_default(),
+            });
+        }
+
+        let members = &mut self.edge_layout.members;
+        match members.iter_mut().find(|(member, _)| member == id) {
+            Some((_, joined)) => *joined = slot,
+            None => members.push((id.clone(), slot)),
+        }
+
+        self.relayout_edges();
+        Ok(())
+    }
+
+    ///
+    /// Take the layer `id` out of the layout, making room for the others.
+    /// It's left where it is.
+    ///
+    pub fn leave_edge_layout(&mut self, id: &ObjectId) {
+        let members = &mut self.edge_layout.members;
+        let Some(index) = members.iter().position(|(member, _)| member == id) else {
+            return;
+        };
+
+        members.remove(index);
+        self.edge_layout.applied.remove(id);
+        self.relayout_edges();
+    }
+
+    ///
+    /// What's left of `output` for popups and the like, once the layers in the
+    /// layout, and the client's other named layers (see [AvyClient::usable_rect]),
+    /// have taken their edges. `None` until its geometry is known.
+    ///
+    pub fn usable_area(&self, output: &WlOutput) -> Option<GlobalRect> {
+        let rect = self.usable_rect(output)?;
+        let taken = self.edge_layout.taken(output);
+
+        Some(GlobalRect::new(
+            rect.origin.x + taken.left as f64,
+            rect.origin.y + taken.top as f64,
+            (rect.size.0 - (taken.left + taken.right) as f64).max(0.0),
+            (rect.size.1 - (taken.top + taken.bottom) as f64).max(0.0),
+        ))
+    }
+
+    ///
+    /// Lay the layout out again if `id` is in it, e.g. as it was
+    /// configured to another size, or moved to another output.
+    ///
+    pub(crate) fn update_edge_layout(&mut self, id: &ObjectId) {
+        if self.edge_layout.contains(id) {
+            self.relayout_edges();
+        }
+    }
+
+    ///
+    /// Work out every layer's margins, and commit those which changed.
+    ///
+    fn relayout_edges(&mut self) {
+        // Layers gone since (not through the crate) drop out.
+        let members: Vec<_> = self
+            .edge_layout
+            .members
+            .iter()
+            .filter_map(|(id, slot)| Some((id.clone(), *slot, self.layer(id)?.controller())))
+            .collect();
+
+        let mut outputs: HashMap<Option<WlOutput>, Vec<_>> = HashMap::new();
+        for (id, slot, controller) in members {
+            let output = controller
+                .output()
+                .cloned()
+                .or_else(|| self.surface_outputs.get(&id)?.first().cloned());
+
+            let thickness = slot.thickness.unwrap_or_else(|| {
+                let size = self.surfaces[&id].size_ref().logical_size();
+                slot.edge.depth(size)
+            });
+
+            outputs
+                .entry(output)
+                .or_default()
+                .push((id, slot, thickness, controller));
+        }
+
+        self.edge_layout.taken.clear();
+        for (output, layers) in outputs {
+            let slots: Vec<_> = layers
+                .iter()
+                .map(|(_, slot, thickness, _)| (slot.edge, *thickness, slot.priority))
+                .collect();
+            let (margins, taken) = solve(&slots);
+
+            for ((id, slot, thickness, controller), margin) in layers.into_iter().zip(margins) {
+                let applied = (margin, thickness);
+                if self.edge_layout.applied.get(&id) == Some(&applied) {
+                    continue;
+                }
+
+                let transaction = LayerTransaction::new()
+                    .anchor(slot.edge.anchor())
+                    .margin(margin)
+                    .exclusive_zone(thickness as i32);
+
+                match controller.commit(transaction) {
+                    Ok(()) => {
+                        self.edge_layout.applied.insert(id, applied);
+                    }
+                    Err(err) => log::warn!("Could not lay out {id}: {err}"),
+                }
+            }
+
+            self.edge_layout.taken.insert(output, taken);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use LayoutEdge::*;
+
+    #[test]
+    fn higher_priorities_are_closer_to_their_edge() {
+        let (margins, taken) = solve(&[(Top, 30, 0), (Top, 40, 5)]);
+
+        assert_eq!(margins, [Insets::new(40, 0, 0, 0), Insets::ZERO]);
+        assert_eq!(taken, Insets::new(70, 0, 0, 0));
+    }
+
+    #[test]
+    fn the_higher_priority_owns_the_corner() {
+        let (margins, _) = solve(&[(Top, 30, 1), (Left, 200, 0)]);
+        assert_eq!(margins, [Insets::ZERO, Insets::new(30, 0, 0, 0)]);
+
+        let (margins, _) = solve(&[(Top, 30, 0), (Left, 200, 1)]);
+        assert_eq!(margins, [Insets::new(0, 0, 0, 200), Insets::ZERO]);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_to_join() {
+        let (margins, taken) = solve(&[(Bottom, 48, 0), (Bottom, 32, 0)]);
+
+        assert_eq!(margins, [Insets::ZERO, Insets::new(0, 0, 48, 0)]);
+        assert_eq!(taken, Insets::new(0, 0, 80, 0));
+    }
+
+    #[test]
+    fn zero_thickness_takes_nothing() {
+        let (margins, taken) = solve(&[(Top, 0, 5), (Top, 30, 0), (Left, 200, -1)]);
+
+        assert_eq!(
+            margins,
+            [Insets::ZERO, Insets::ZERO, Insets::new(30, 0, 0, 0)]
+        );
+        assert_eq!(taken, Insets::new(30, 0, 0, 200));
+    }
+
+    #[test]
+    fn a_bar_a_dock_and_a_sidebar_dont_overlap() {
+        let (margins, taken) = solve(&[(Top, 32, 2), (Bottom, 64, 1), (Right, 300, 0)]);
+
+        assert_eq!(
+            margins,
+            [Insets::ZERO, Insets::ZERO, Insets::new(32, 0, 64, 0)]
+        );
+        assert_eq!(taken, Insets::new(32, 300, 64, 0));
+    }
+
+    #[test]
+    fn nothing_to_lay_out() {
+        assert_eq!(solve(&[]), (vec![], Insets::ZERO));
+    }
+}
@@ -0,0 +1,168 @@
+//!
+//! Mirrored surfaces: what one surface draws, shown on another output too
+//! (e.g. a presenter's slides on the projector), without drawing it twice.
+//!
+//! The mirror is a layer surface of its own on the target output, which
+//! copies in the source's latest frame (see [crate::app::AvySurfaceHandle::on_new_frame])
+//! rather than calling a render callback, fitted as by [FitMode::Contain]
+//! and letterboxed in black. Surfaces each have a device of their own for
+//! now, so frames go through a CPU copy.
+//!
+//! Mirrors are drawn in a render group of their own (see [AvyClient::render_group]),
+//! paced by their output, so they show a frame at most one refresh of their
+//! output after the source presents it, see [AvySurfaceHandle::mirror_lag].
+//!
+
+use std::sync::{Arc, Mutex};
+
+use skia_safe::{Color, FilterMode, MipmapMode, Paint, SamplingOptions};
+use smithay_client_toolkit::{
+    reexports::client::{protocol::wl_output::WlOutput, EventQueue},
+    shell::wlr_layer,
+};
+use thiserror::Error;
+
+use crate::{
+    app::{AvySurfaceHandle, RenderGroupId},
+    graphics::GraphicsBackend,
+    util::Size,
+    widgets::FitMode,
+    AvyClient,
+};
+
+use super::layer::{AvyLayer, AvyLayerParams, LayerError};
+
+///
+/// Namespace of mirror layers, for compositor rules to match on.
+///
+pub const MIRROR_NAMESPACE: &str = "avy-mirror";
+
+#[derive(Debug, Error)]
+pub enum MirrorError<E: std::error::Error + 'static> {
+    #[error("Could not create the mirror's layer: {0}")]
+    Layer(#[from] LayerError),
+    #[error("Could not set up the mirror's backend: {0}")]
+    Backend(E),
+}
+
+impl AvyClient {
+    ///
+    /// Show what `source` draws on `target_output` too, on a new layer surface.
+    ///
+    /// Layer sources are mirrored with the same layer, anchor, size and margins
+    /// (but no exclusive zone), anything else fills the output. The
+    /// mirror follows its own output's scale, takes no input, and is destroyed
+    /// like any other surface, independently of the source.
+    ///
+    /// The source only copies its frames out whilst it has mirrors (or other
+    /// [AvySurfaceHandle::on_new_frame] subscribers).
+    ///
+    pub fn mirror_surface<G: GraphicsBackend + 'static>(
+        &mut self,
+        source: &AvySurfaceHandle<G>,
+        target_output: &WlOutput,
+        event_queue: &mut EventQueue<AvyClient>,
+        backend: &G,
+    ) -> Result<AvySurfaceHandle<G>, MirrorError<G::Error>>
+    where
+        G::Surface: 'static,
+    {
+        let params = match self.layer_controller(&source.id()) {
+            Some(controller) => AvyLayerParams {
+                layer: controller.layer(),
+                namespace: Some(MIRROR_NAMESPACE),
+                output: Some(target_output.clone()),
+                anchor: controller.anchor(),
+                size: Size::new(controller.requested_size()),
+                margin: {
+                    let margin = controller.margin();
+                    Some((margin.top, margin.right, margin.bottom, margin.left))
+                },
+                exclusive_zone: Some(0),
+                keyboard_interactivity: wlr_layer::KeyboardInteractivity::None,
+                manual_configure_ack: false,
+            },
+            None => AvyLayerParams {
+                layer: wlr_layer::Layer::Overlay,
+                namespace: Some(MIRROR_NAMESPACE),
+                output: Some(target_output.clone()),
+                anchor: wlr_layer::Anchor::all(),
+                size: Size::new((0, 0)),
+                margin: None,
+                exclusive_zone: Some(-1),
+                keyboard_interactivity: wlr_layer::KeyboardInteractivity::None,
+                manual_configure_ack: false,
+            },
+        };
+
+        let mirror = AvyLayer::build(self, event_queue, params)?
+            .make_backend(backend)
+            .map_err(MirrorError::Backend)?;
+
+        let latest = Arc::new(Mutex::new(source.snapshot_frame()));
+        let subscription = source.on_new_frame({
+            let latest = latest.clone();
+            let dirty = mirror.dirty_flag();
+            move |snapshot| {
+                latest.lock().unwrap().replace(snapshot);
+                dirty.mark();
+            }
+        });
+
+        // Nothing copied yet: have the source draw a frame to show.
+        if latest.lock().unwrap().is_none() {
+            source.mark_dirty();
+        }
+
+        let group = self.mirror_group();
+        let handle = mirror.clone();
+        self.render_group(group)
+            .unwrap()
+            .join(&mirror, move |canvas, context, _| {
+                // Dropped with the mirror, which stops the source copying frames for it.
+                let _ = &subscription;
+
+                canvas.clear(Color::BLACK);
+
+                let Some(snapshot) = latest.lock().unwrap().clone() else {
+                    return;
+                };
+
+                let (width, height) = context.drawing_size;
+                let image = (
+                    snapshot.image.width() as f32,
+                    snapshot.image.height() as f32,
+                );
+                let dest = FitMode::Contain.dest_rect(image, (width as f32, height as f32));
+
+                let sampling = SamplingOptions::new(FilterMode::Linear, MipmapMode::None);
+                canvas.draw_image_rect_with_sampling_options(
+                    &snapshot.image,
+                    None,
+                    dest,
+                    sampling,
+                    &Paint::default(),
+                );
+
+                handle.set_mirrored(snapshot.captured);
+            });
+
+        Ok(mirror)
+    }
+
+    ///
+    /// The render group every mirror is drawn in, made on first use
+    /// (or again, if it was removed).
+    ///
+    fn mirror_group(&mut self) -> RenderGroupId {
+        if let Some(group) = self.mirror_group {
+            if self.render_groups.contains_key(&group) {
+                return group;
+            }
+        }
+
+        let group = self.create_render_group().id();
+        self.mirror_group = Some(group);
+        group
+    }
+}
@@ -4,17 +4,106 @@ use smithay_client_toolkit::reexports::{
     client::{protocol::wl_surface::WlSurface, Connection, QueueHandle},
     protocols::wp::viewporter::client::wp_viewport::WpViewport,
 };
+use thiserror::Error;
+use wayland_backend::client::ObjectId;
 
 use crate::{
     util::{AsAny, Size},
+    wayland::{keymap::KeymapInfo, seat::Capabilities},
     AvyClient,
 };
 
+pub mod configure;
+pub mod deferred;
+pub mod destroy;
+pub mod events;
 pub mod layer;
+pub mod layout;
+pub mod mirror;
+#[cfg(feature = "virtual-keyboard")]
+pub mod osk;
+pub mod registry;
+pub mod subsurface;
+
+use configure::{ConfigureAck, PendingConfigure};
+use layer::{AvyLayer, AvyLayerController};
+use subsurface::{AvySubsurface, AvySubsurfaceController};
+
+///
+/// Which of the crate's surface types a surface is, see [AvySurface::kind].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SurfaceKind {
+    /// An [AvyLayer].
+    Layer,
+    /// An [AvySubsurface].
+    Subsurface,
+    /// Anything else, e.g. surface types of the application's own.
+    #[default]
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("Expected a surface of kind {expected:?}, but it's of kind {found:?}.")]
+pub struct WrongKind {
+    pub expected: SurfaceKind,
+    pub found: SurfaceKind,
+}
+
+///
+/// A surface's controller, by kind, for its handles to hand out.
+///
+#[derive(Clone, Default)]
+pub enum SurfaceController {
+    Layer(AvyLayerController),
+    Subsurface(AvySubsurfaceController),
+    #[default]
+    Other,
+}
+
+impl SurfaceController {
+    pub fn kind(&self) -> SurfaceKind {
+        match self {
+            Self::Layer(_) => SurfaceKind::Layer,
+            Self::Subsurface(_) => SurfaceKind::Subsurface,
+            Self::Other => SurfaceKind::Other,
+        }
+    }
+
+    pub fn layer(&self) -> Result<AvyLayerController, WrongKind> {
+        match self {
+            Self::Layer(controller) => Ok(controller.clone()),
+            _ => Err(self.wrong_kind(SurfaceKind::Layer)),
+        }
+    }
+
+    pub fn subsurface(&self) -> Result<AvySubsurfaceController, WrongKind> {
+        match self {
+            Self::Subsurface(controller) => Ok(controller.clone()),
+            _ => Err(self.wrong_kind(SurfaceKind::Subsurface)),
+        }
+    }
+
+    fn wrong_kind(&self, expected: SurfaceKind) -> WrongKind {
+        WrongKind {
+            expected,
+            found: self.kind(),
+        }
+    }
+}
 
 pub trait AvySurface: AsAny + InputHandler {
     fn wl_surface(&self) -> &WlSurface;
 
+    ///
+    /// Which of the crate's surface types this is, to branch on without
+    /// downcasting. Surface types outside the crate are [SurfaceKind::Other].
+    ///
+    fn kind(&self) -> SurfaceKind {
+        SurfaceKind::Other
+    }
+
     fn size(&self) -> &Arc<RwLock<Size>>;
 
     fn size_ref(&self) -> RwLockReadGuard<'_, Size> {
@@ -26,6 +115,135 @@ pub trait AvySurface: AsAny + InputHandler {
     }
 
     fn viewport(&mut self) -> &mut WpViewport;
+
+    ///
+    /// Called when a frame callback requested on this surface is done.
+    ///
+    #[allow(unused)]
+    fn frame(&mut self, conn: &Connection, qh: &QueueHandle<AvyClient>, time: u32) {}
+
+    ///
+    /// Name to show for this surface when debugging,
+    /// unless one was given to [AvyClient::register_surface].
+    ///
+    fn debug_name(&self) -> Option<String> {
+        None
+    }
+
+    ///
+    /// The input devices available to the client changed,
+    /// e.g. a mouse was plugged in or the last keyboard removed.
+    ///
+    #[allow(unused)]
+    fn capabilities_changed(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        capabilities: Capabilities,
+    ) {
+    }
+
+    ///
+    /// Whether the first frame must be completely drawn before the surface
+    /// is mapped, see [crate::app::AvySurfaceHandle::map_after_first_frame].
+    ///
+    fn map_after_first_frame(&self) -> bool {
+        false
+    }
+
+    ///
+    /// Set when this surface acknowledges configure events by hand, rather
+    /// than as soon as they arrive.
+    ///
+    /// The pending configure is acknowledged right before presenting the
+    /// first frame drawn at the configured size, or whenever [ConfigureAck::ack]
+    /// is called.
+    ///
+    fn configure_ack(&self) -> Option<&ConfigureAck> {
+        None
+    }
+
+    ///
+    /// The logical size to adopt for a configure suggesting `suggested`,
+    /// e.g. keeping the surface's own size on axes the compositor left to it (0).
+    ///
+    fn configured_size(&self, suggested: (u32, u32)) -> (u32, u32) {
+        suggested
+    }
+
+    ///
+    /// The compositor configured this surface. Its size has already been updated.
+    ///
+    #[allow(unused)]
+    fn configure(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        configure: PendingConfigure,
+    ) {
+    }
+}
+
+impl AvyClient {
+    ///
+    /// The kind of the registered surface `id`, see [AvySurface::kind].
+    ///
+    pub fn surface_kind(&self, id: &ObjectId) -> Option<SurfaceKind> {
+        Some(self.surfaces.get(id)?.kind())
+    }
+
+    ///
+    /// The registered surface `id`, if it's a layer.
+    ///
+    pub fn layer(&self, id: &ObjectId) -> Option<&AvyLayer> {
+        let surface = self.surfaces.get(id)?;
+        match surface.kind() {
+            SurfaceKind::Layer => surface.as_any_ref().downcast_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn layer_mut(&mut self, id: &ObjectId) -> Option<&mut AvyLayer> {
+        let surface = self.surfaces.get_mut(id)?;
+        match surface.kind() {
+            SurfaceKind::Layer => surface.as_any_mut().downcast_mut(),
+            _ => None,
+        }
+    }
+
+    ///
+    /// The registered surface `id`, if it's a subsurface.
+    ///
+    pub fn subsurface(&self, id: &ObjectId) -> Option<&AvySubsurface> {
+        let surface = self.surfaces.get(id)?;
+        match surface.kind() {
+            SurfaceKind::Subsurface => surface.as_any_ref().downcast_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn subsurface_mut(&mut self, id: &ObjectId) -> Option<&mut AvySubsurface> {
+        let surface = self.surfaces.get_mut(id)?;
+        match surface.kind() {
+            SurfaceKind::Subsurface => surface.as_any_mut().downcast_mut(),
+            _ => None,
+        }
+    }
+
+    ///
+    /// The controller of the registered surface `id`, by its kind.
+    ///
+    pub fn surface_controller(&self, id: &ObjectId) -> Option<SurfaceController> {
+        let controller = match self.surface_kind(id)? {
+            SurfaceKind::Layer => SurfaceController::Layer(self.layer(id)?.controller()),
+            SurfaceKind::Subsurface => {
+                SurfaceController::Subsurface(self.subsurface(id)?.controller())
+            }
+            _ => SurfaceController::Other,
+        };
+
+        Some(controller)
+    }
 }
 
 pub trait InputHandler: KeyboardHandler + TouchHandler + PointerHandler {}
@@ -79,6 +297,24 @@ pub trait KeyboardHandler {
         modifiers: smithay_client_toolkit::seat::keyboard::Modifiers,
         layout: u32,
     );
+
+    ///
+    /// The seat's keymap changed, e.g. the layout was switched or a
+    /// different keyboard was plugged in.
+    ///
+    /// Keys held across the change have already been released (through
+    /// [KeyboardHandler::release_key], with a serial of 0), so anything
+    /// translated under the old keymap can be refreshed.
+    ///
+    #[allow(unused)]
+    fn keymap_changed(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        keymap: &KeymapInfo,
+    ) {
+    }
 }
 
 pub trait TouchHandler {
@@ -151,3 +387,48 @@ pub trait PointerHandler {
         events: &[smithay_client_toolkit::seat::pointer::PointerEvent],
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn other_controllers_are_neither_layers_nor_subsurfaces() {
+        let controller = SurfaceController::Other;
+
+        assert_eq!(controller.kind(), SurfaceKind::Other);
+        assert_eq!(
+            controller.layer().err(),
+            Some(WrongKind {
+                expected: SurfaceKind::Layer,
+                found: SurfaceKind::Other,
+            })
+        );
+        assert_eq!(
+            controller.subsurface().err(),
+            Some(WrongKind {
+                expected: SurfaceKind::Subsurface,
+                found: SurfaceKind::Other,
+            })
+        );
+    }
+
+    #[test]
+    fn surfaces_are_other_unless_they_say_so() {
+        assert_eq!(SurfaceKind::default(), SurfaceKind::Other);
+        assert_eq!(SurfaceController::default().kind(), SurfaceKind::Other);
+    }
+
+    #[test]
+    fn wrong_kinds_name_both_kinds() {
+        let err = WrongKind {
+            expected: SurfaceKind::Layer,
+            found: SurfaceKind::Subsurface,
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "Expected a surface of kind Layer, but it's of kind Subsurface."
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//!
+//! On-screen keyboards: a layer along the bottom of the screen which
+//! never takes the keyboard, typing into the focused client through a
+//! virtual keyboard instead.
+//!
+
+use smithay_client_toolkit::{
+    reexports::client::{
+        protocol::{wl_output::WlOutput, wl_seat::WlSeat},
+        EventQueue,
+    },
+    seat::keyboard::Keysym,
+    shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer},
+};
+use wayland_backend::client::ObjectId;
+
+use crate::{
+    app::{AvyClient, RegisteredSurface},
+    util::Size,
+    wayland::protocol::virtual_keyboard::{Error, KeysymKeymap, VirtualKeyboard},
+};
+
+use super::layer::{AvyLayer, AvyLayerParams};
+
+pub struct OskLayerParams<'a> {
+    pub namespace: Option<&'a str>,
+    pub output: Option<WlOutput>,
+    /// The seat to type as, defaulting to the first one.
+    pub seat: Option<WlSeat>,
+    /// The keyboard spans the width of the output.
+    pub height: u32,
+    /// Whether to push other surfaces (e.g. windows) out of the keyboard's way.
+    pub exclusive: bool,
+}
+
+///
+/// Sends keys typed on an on-screen keyboard's surface, see [OskLayer::build].
+///
+pub struct OskLayer {
+    surface: ObjectId,
+    keyboard: VirtualKeyboard,
+    keymap: KeysymKeymap,
+}
+
+impl OskLayer {
+    ///
+    /// Create the virtual keyboard, along with a layer for drawing the on-screen one.
+    ///
+    /// Fails with [Error::Unsupported] if the compositor doesn't
+    /// support virtual keyboards, in which case no layer is created.
+    ///
+    pub fn build<'a>(
+        app: &'a mut AvyClient,
+        event_queue: &mut EventQueue<AvyClient>,
+        params: OskLayerParams,
+    ) -> Result<(Self, RegisteredSurface<'a>), Error> {
+        let qh = &event_queue.handle();
+
+        let manager = app.virtual_keyboard.as_ref().ok_or(Error::Unsupported)?;
+        let seat = params
+            .seat
+            .or_else(|| app.seat_state.seats().next())
+            .ok_or(Error::NoSeat)?;
+
+        let keyboard = manager.create_keyboard(&seat, qh, &app.clock);
+
+        let registered_surface = AvyLayer::build(
+            app,
+            event_queue,
+            AvyLayerParams {
+                layer: Layer::Top,
+                namespace: params.namespace,
+                output: params.output,
+                anchor: Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT,
+                size: Size::new((0, params.height)),
+                margin: None,
+                exclusive_zone: params.exclusive.then_some(params.height as i32),
+                // Taking focus would take it away from the client being typed into.
+                keyboard_interactivity: KeyboardInteractivity::None,
+                manual_configure_ack: false,
+            },
+        )?;
+
+        let osk = Self {
+            surface: registered_surface.id().clone(),
+            keyboard,
+            keymap: KeysymKeymap::default(),
+        };
+
+        Ok((osk, registered_surface))
+    }
+
+    ///
+    /// The on-screen keyboard's surface.
+    ///
+    pub fn surface(&self) -> &ObjectId {
+        &self.surface
+    }
+
+    ///
+    /// Press or release the key for `keysym` in the focused client.
+    ///
+    /// Keysyms not typed before are added to the keymap, which is
+    /// uploaded again, so typing a new one costs a round of keymap
+    /// updates in the compositor and focused client.
+    ///
+    pub fn send_key(&mut self, keysym: Keysym, pressed: bool) -> Result<(), Error> {
+        let (key, changed) = self.keymap.key_for(keysym);
+
+        if changed || !self.keyboard.has_keymap() {
+            self.keyboard.upload_keymap(&self.keymap.to_xkb())?;
+        }
+
+        self.keyboard.key(key, pressed)
+    }
+
+    ///
+    /// Press and release the key for `keysym`.
+    ///
+    pub fn tap_key(&mut self, keysym: Keysym) -> Result<(), Error> {
+        self.send_key(keysym, true)?;
+        self.send_key(keysym, false)
+    }
+}
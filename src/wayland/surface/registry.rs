@@ -0,0 +1,296 @@
+//!
+//! Surfaces by name, for components sharing a client (a bar, a launcher,
+//! notifications...) to find each other's surfaces, e.g. so that the
+//! launcher keeps clear of the bar's exclusive zone.
+//!
+//! Surfaces get a name when registered (see [AvyClient::register_surface]),
+//! explicitly or from their layer namespace (as `layer:{namespace}`), and
+//! lose it when destroyed. Names are unique within a client.
+//!
+
+use std::collections::HashMap;
+
+use smithay_client_toolkit::{
+    reexports::client::protocol::wl_output::WlOutput, shell::wlr_layer::Anchor,
+};
+use thiserror::Error;
+use wayland_backend::client::ObjectId;
+
+use crate::{
+    util::{GlobalRect, Insets, OutputGeometry},
+    AvyClient,
+};
+
+use super::{
+    events::{Subscribers, Subscription},
+    AvySurface, SurfaceKind,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Another surface is already named {0:?}.")]
+pub struct NameTaken(pub String);
+
+///
+/// A named surface, as it is now, see [AvyClient::surface_by_name].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedSurface {
+    pub id: ObjectId,
+    pub name: String,
+    pub kind: SurfaceKind,
+    /// Where the surface is in the global space, for layers on an
+    /// output whose geometry is known.
+    pub geometry: Option<GlobalRect>,
+    /// Zero for anything but layers.
+    pub exclusive_zone: i32,
+    /// Empty for anything but layers.
+    pub anchor: Anchor,
+    pub margin: Insets,
+    /// The output the surface was created on, or else the first it's on.
+    pub output: Option<WlOutput>,
+}
+
+#[derive(Debug, Clone)]
+pub enum RegistryEvent {
+    /// A surface was named, or its geometry, exclusive zone or output changed.
+    Changed(NamedSurface),
+    /// A named surface was destroyed.
+    Removed { id: ObjectId, name: String },
+}
+
+///
+/// Every named surface of a client.
+///
+#[derive(Debug, Default)]
+pub struct SurfaceRegistry {
+    ids: HashMap<String, ObjectId>,
+    /// As last announced, so that only actual changes are.
+    surfaces: HashMap<ObjectId, NamedSurface>,
+    subscribers: Subscribers<RegistryEvent>,
+}
+
+impl SurfaceRegistry {
+    pub fn id(&self, name: &str) -> Option<&ObjectId> {
+        self.ids.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.ids.contains_key(name)
+    }
+
+    ///
+    /// Names, and the surfaces they're of, in no particular order.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ObjectId)> {
+        self.ids.iter().map(|(name, id)| (name.as_str(), id))
+    }
+
+    fn name(&self, id: &ObjectId) -> Option<&str> {
+        self.surfaces.get(id).map(|surface| surface.name.as_str())
+    }
+
+    ///
+    /// Take `surface`'s name, announcing it unless nothing changed.
+    ///
+    fn update(&mut self, surface: NamedSurface) {
+        if self.surfaces.get(&surface.id) == Some(&surface) {
+            return;
+        }
+
+        self.ids.insert(surface.name.clone(), surface.id.clone());
+        self.surfaces.insert(surface.id.clone(), surface.clone());
+        self.subscribers.emit(RegistryEvent::Changed(surface));
+    }
+
+    pub(crate) fn remove(&mut self, id: &ObjectId) {
+        let Some(surface) = self.surfaces.remove(id) else {
+            return;
+        };
+
+        self.ids.remove(&surface.name);
+        self.subscribers.emit(RegistryEvent::Removed {
+            id: surface.id,
+            name: surface.name,
+        });
+    }
+}
+
+impl AvyClient {
+    ///
+    /// Name the registered surface `id` (replacing any name it had), for
+    /// others to find with [AvyClient::surface_by_name].
+    ///
+    pub fn name_surface(&mut self, id: &ObjectId, name: &str) -> Result<(), NameTaken> {
+        match self.surface_registry.id(name) {
+            Some(named) if named == id => return Ok(()),
+            Some(_) => return Err(NameTaken(name.to_owned())),
+            None => (),
+        }
+
+        let Some(surface) = self.named_surface(id, name.to_owned()) else {
+            return Ok(());
+        };
+
+        if let Some(previous) = self.surface_registry.name(id).map(str::to_owned) {
+            self.surface_registry.ids.remove(&previous);
+        }
+
+        self.surface_names.insert(id.clone(), name.to_owned());
+        self.surface_registry.update(surface);
+
+        Ok(())
+    }
+
+    pub fn surface_by_name(&self, name: &str) -> Option<NamedSurface> {
+        let id = self.surface_registry.id(name)?;
+        self.named_surface(id, name.to_owned())
+    }
+
+    ///
+    /// Every named surface, by name.
+    ///
+    pub fn named_surfaces(&self) -> Vec<NamedSurface> {
+        let mut surfaces: Vec<_> = self
+            .surface_registry
+            .iter()
+            .filter_map(|(name, id)| self.named_surface(id, name.to_owned()))
+            .collect();
+
+        surfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        surfaces
+    }
+
+    ///
+    /// Call `callback` whenever a surface is named, or destroyed, or a named
+    /// surface's geometry, exclusive zone or output changes (as configured by
+    /// the compositor), until the returned [Subscription] is dropped.
+    ///
+    pub fn on_surface_registry(
+        &self,
+        callback: impl FnMut(RegistryEvent) + Send + 'static,
+    ) -> Subscription {
+        self.surface_registry.subscribers.subscribe(callback)
+    }
+
+    ///
+    /// What's left of `output` once the client's named layers have taken their
+    /// exclusive zones, or `None` until its geometry is known.
+    ///
+    /// Layers in the edge layout are left to [AvyClient::usable_area].
+    ///
+    pub fn usable_rect(&self, output: &WlOutput) -> Option<GlobalRect> {
+        let geometry = OutputGeometry::from_info(&self.output_state.info(output)?)?;
+
+        let exclusive = self
+            .surface_registry
+            .surfaces
+            .values()
+            .filter(|surface| surface.output.as_ref() == Some(output))
+            .filter(|surface| !self.edge_layout.contains(&surface.id))
+            .map(|surface| (surface.anchor, surface.exclusive_zone, surface.margin));
+
+        Some(geometry.usable_rect(exclusive))
+    }
+
+    ///
+    /// Where a popup wanting `rect` goes on `output`: moved as little as it
+    /// takes to stay on the output and clear of the exclusive zones of the
+    /// client's layers (e.g. the bar it pops out of), see [AvyClient::usable_area].
+    ///
+    /// As margins of a layer anchored to the top and left edges of `output`.
+    ///
+    pub fn place_popup(&self, output: &WlOutput, rect: GlobalRect) -> Option<Insets> {
+        let geometry = OutputGeometry::from_info(&self.output_state.info(output)?)?;
+        let placed = rect.constrain(self.usable_area(output)?);
+
+        Some(Insets {
+            top: (placed.origin.y - geometry.position.1 as f64).round() as i32,
+            left: (placed.origin.x - geometry.position.0 as f64).round() as i32,
+            ..Insets::default()
+        })
+    }
+
+    ///
+    /// Name a newly registered surface: `name`, or its layer namespace.
+    ///
+    /// Fails (leaving the surface unnamed) if another surface took that first.
+    ///
+    pub(crate) fn register_name(
+        &mut self,
+        id: &ObjectId,
+        name: Option<&str>,
+    ) -> Result<(), NameTaken> {
+        let name = match name {
+            Some(name) => name.to_owned(),
+            None => {
+                let Some(layer) = self.layer(id) else {
+                    return Ok(());
+                };
+                let Some(name) = layer.debug_name() else {
+                    return Ok(());
+                };
+                name
+            }
+        };
+
+        if self.surface_registry.contains(&name) {
+            return Err(NameTaken(name));
+        }
+
+        if let Some(surface) = self.named_surface(id, name) {
+            self.surface_registry.update(surface);
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Announce changes to the named surface `id`, if it is one.
+    ///
+    pub(crate) fn update_named_surface(&mut self, id: &ObjectId) {
+        let Some(name) = self.surface_registry.name(id).map(str::to_owned) else {
+            return;
+        };
+
+        if let Some(surface) = self.named_surface(id, name) {
+            self.surface_registry.update(surface);
+        }
+    }
+
+    fn named_surface(&self, id: &ObjectId, name: String) -> Option<NamedSurface> {
+        let kind = self.surface_kind(id)?;
+        let first_output = || self.surface_outputs.get(id)?.first().cloned();
+
+        let Some(layer) = self.layer(id) else {
+            return Some(NamedSurface {
+                id: id.clone(),
+                name,
+                kind,
+                geometry: None,
+                exclusive_zone: 0,
+                anchor: Anchor::empty(),
+                margin: Insets::default(),
+                output: first_output(),
+            });
+        };
+
+        let controller = layer.controller();
+        let output = controller.output().cloned().or_else(first_output);
+        let geometry = output
+            .as_ref()
+            .and_then(|output| self.output_state.info(output))
+            .and_then(|info| OutputGeometry::from_info(&info))
+            .map(|geometry| controller.surface_global_rect(&geometry));
+
+        Some(NamedSurface {
+            id: id.clone(),
+            name,
+            kind,
+            geometry,
+            exclusive_zone: controller.exclusive_zone(),
+            anchor: controller.anchor(),
+            margin: controller.margin(),
+            output,
+        })
+    }
+}
@@ -0,0 +1,893 @@
+//!
+//! Subsurfaces: surfaces drawn as part of another (their parent), e.g. a
+//! badge over an icon, each with its own backend.
+//!
+//! Siblings (and their parent) are stacked in an order the app controls
+//! through [AvySubsurfaceController]; the client keeps track of it so that
+//! overlapping siblings can be hit-tested topmost first.
+//!
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use smithay_client_toolkit::reexports::{
+    client::{
+        protocol::{wl_subsurface::WlSubsurface, wl_surface::WlSurface},
+        Connection, EventQueue, Proxy, QueueHandle,
+    },
+    protocols::wp::{
+        fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
+        viewporter::client::wp_viewport::WpViewport,
+    },
+};
+use thiserror::Error;
+use wayland_backend::client::ObjectId;
+
+use crate::{
+    app::{AvyClient, RegisteredSurface},
+    debug, impl_as_any,
+    util::Size,
+};
+
+use super::{AvySurface, InputHandler, KeyboardHandler, PointerHandler, SurfaceKind, TouchHandler};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("The compositor doesn't support wl_subcompositor.")]
+    Unsupported,
+
+    #[error("The parent surface isn't registered with the client.")]
+    UnknownParent,
+}
+
+pub struct AvySubsurfaceParams {
+    pub parent: ObjectId,
+    /// Relative to the parent's top-left corner, in logical pixels.
+    pub position: (i32, i32),
+    pub size: Size,
+    /// Whether commits wait for the parent's, see [AvySubsurfaceController::set_sync].
+    pub sync: bool,
+}
+
+struct StackEntry {
+    id: ObjectId,
+    wl_surface: WlSurface,
+    position: (i32, i32),
+    size: Arc<RwLock<Size>>,
+    sync: bool,
+}
+
+impl StackEntry {
+    fn contains(&self, (x, y): (f64, f64)) -> bool {
+        let (width, height) = self.size.read().unwrap().logical_size();
+        let (left, top) = (self.position.0 as f64, self.position.1 as f64);
+
+        x >= left && y >= top && x < left + width as f64 && y < top + height as f64
+    }
+}
+
+///
+/// A parent surface's subsurfaces, and how they (and it) are stacked.
+///
+pub struct SubsurfaceStack {
+    parent: StackEntry,
+    /// In the order they were created in.
+    children: Vec<StackEntry>,
+    /// Bottom to top, the parent included.
+    order: Vec<ObjectId>,
+}
+
+impl SubsurfaceStack {
+    fn new(parent: &dyn AvySurface) -> Self {
+        let id = parent.wl_surface().id();
+
+        Self {
+            parent: StackEntry {
+                id: id.clone(),
+                wl_surface: parent.wl_surface().clone(),
+                position: (0, 0),
+                size: parent.size().clone(),
+                sync: false,
+            },
+            children: Vec::new(),
+            order: vec![id],
+        }
+    }
+
+    fn entry(&self, id: &ObjectId) -> Option<&StackEntry> {
+        if &self.parent.id == id {
+            return Some(&self.parent);
+        }
+
+        self.children.iter().find(|child| &child.id == id)
+    }
+
+    fn child_mut(&mut self, id: &ObjectId) -> Option<&mut StackEntry> {
+        self.children.iter_mut().find(|child| &child.id == id)
+    }
+
+    ///
+    /// Move `id` right above (or below) `sibling` in [SubsurfaceStack::order].
+    ///
+    fn restack(&mut self, id: &ObjectId, sibling: &ObjectId, above: bool) {
+        self.order.retain(|other| other != id);
+
+        let Some(index) = self.order.iter().position(|other| other == sibling) else {
+            return;
+        };
+
+        let index = if above { index + 1 } else { index };
+        self.order.insert(index, id.clone());
+    }
+
+    fn remove(&mut self, id: &ObjectId) {
+        self.children.retain(|child| &child.id != id);
+        self.order.retain(|other| other != id);
+    }
+
+    ///
+    /// The parent and its subsurfaces, topmost first.
+    ///
+    pub fn hit_test_order(&self) -> Vec<ObjectId> {
+        self.order.iter().rev().cloned().collect()
+    }
+
+    ///
+    /// The topmost of the parent and its subsurfaces under `point`
+    /// (in the parent's logical coordinates), if any.
+    ///
+    pub fn surface_at(&self, point: (f64, f64)) -> Option<ObjectId> {
+        self.order
+            .iter()
+            .rev()
+            .filter_map(|id| self.entry(id))
+            .find(|entry| entry.contains(point))
+            .map(|entry| entry.id.clone())
+    }
+
+    ///
+    /// Commit the subsurfaces in sync mode, in the order they were created,
+    /// so that their state is applied along with the parent's next commit.
+    ///
+    pub fn commit_children(&self) {
+        for child in self.children.iter().filter(|child| child.sync) {
+            child.wl_surface.commit();
+        }
+    }
+}
+
+pub struct AvySubsurface {
+    subsurface: WlSubsurface,
+    wl_surface: WlSurface,
+    viewport: WpViewport,
+    fractional_scale: WpFractionalScaleV1,
+    size: Arc<RwLock<Size>>,
+    stack: Arc<Mutex<SubsurfaceStack>>,
+}
+
+impl_as_any!(AvySubsurface);
+
+impl AvySubsurface {
+    ///
+    /// Create a subsurface of `params.parent`, placed above it and its other subsurfaces.
+    ///
+    pub fn build<'a>(
+        app: &'a mut AvyClient,
+        event_queue: &mut EventQueue<AvyClient>,
+        params: AvySubsurfaceParams,
+    ) -> Result<RegisteredSurface<'a>, Error> {
+        let qh = &event_queue.handle();
+
+        let subcompositor = app.subcompositor.as_ref().ok_or(Error::Unsupported)?;
+        let parent = app
+            .surfaces
+            .get(&params.parent)
+            .ok_or(Error::UnknownParent)?;
+
+        let (subsurface, wl_surface) =
+            subcompositor.create_subsurface(parent.wl_surface().clone(), qh);
+        let id = wl_surface.id();
+
+        let (x, y) = params.position;
+        subsurface.set_position(x, y);
+
+        if params.sync {
+            subsurface.set_sync();
+        } else {
+            subsurface.set_desync();
+        }
+
+        // Use fractional scaling.
+        let fractional_scale = app.fractional_scale.fractional_scaling(&wl_surface, qh);
+        app.objects.track(fractional_scale.id(), id.clone());
+
+        let viewport = app.viewporter.get_viewport(&wl_surface, qh);
+        let size = Arc::new(RwLock::new(params.size));
+
+        // New subsurfaces go on top of their siblings.
+        let stack = app
+            .subsurface_stacks
+            .entry(params.parent.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(SubsurfaceStack::new(parent.as_ref()))))
+            .clone();
+
+        {
+            let mut stack = stack.lock().unwrap();
+            stack.children.push(StackEntry {
+                id: id.clone(),
+                wl_surface: wl_surface.clone(),
+                position: params.position,
+                size: size.clone(),
+                sync: params.sync,
+            });
+            stack.order.push(id);
+        }
+
+        Ok(app.register(
+            AvySubsurface {
+                subsurface,
+                wl_surface,
+                viewport,
+                fractional_scale,
+                size,
+                stack,
+            },
+            None,
+            event_queue,
+        ))
+    }
+
+    pub fn controller(&self) -> AvySubsurfaceController {
+        AvySubsurfaceController {
+            subsurface: self.subsurface.clone(),
+            id: self.wl_surface.id(),
+            stack: self.stack.clone(),
+        }
+    }
+}
+
+impl Drop for AvySubsurface {
+    fn drop(&mut self) {
+        self.stack.lock().unwrap().remove(&self.wl_surface.id());
+        self.fractional_scale.destroy();
+        self.viewport.destroy();
+        debug::objects::destroyed(&self.fractional_scale.id());
+        debug::objects::destroyed(&self.viewport.id());
+        self.subsurface.destroy();
+        self.wl_surface.destroy();
+    }
+}
+
+///
+/// Positions and stacks an [AvySubsurface] at runtime.
+///
+/// Like all subsurface state, changes take effect on the parent's next
+/// commit. Can be freely cloned and sent to other threads.
+///
+#[derive(Clone)]
+pub struct AvySubsurfaceController {
+    subsurface: WlSubsurface,
+    id: ObjectId,
+    stack: Arc<Mutex<SubsurfaceStack>>,
+}
+
+impl AvySubsurfaceController {
+    pub fn position(&self) -> (i32, i32) {
+        let stack = self.stack.lock().unwrap();
+        stack.entry(&self.id).map_or((0, 0), |entry| entry.position)
+    }
+
+    pub fn set_position(&self, (x, y): (i32, i32)) {
+        let mut stack = self.stack.lock().unwrap();
+        if let Some(entry) = stack.child_mut(&self.id) {
+            entry.position = (x, y);
+            self.subsurface.set_position(x, y);
+        }
+    }
+
+    ///
+    /// Stack this subsurface right above `sibling`, another subsurface of
+    /// the same parent or the parent itself.
+    ///
+    /// Returns `false` if `sibling` isn't either.
+    ///
+    pub fn place_above(&self, sibling: &ObjectId) -> bool {
+        self.place(sibling, true)
+    }
+
+    ///
+    /// Stack this subsurface right below `sibling`, see [AvySubsurfaceController::place_above].
+    ///
+    pub fn place_below(&self, sibling: &ObjectId) -> bool {
+        self.place(sibling, false)
+    }
+
+    fn place(&self, sibling: &ObjectId, above: bool) -> bool {
+        let mut stack = self.stack.lock().unwrap();
+        if sibling == &self.id {
+            return false;
+        }
+
+        let Some(entry) = stack.entry(sibling) else {
+            return false;
+        };
+
+        if above {
+            self.subsurface.place_above(&entry.wl_surface);
+        } else {
+            self.subsurface.place_below(&entry.wl_surface);
+        }
+
+        stack.restack(&self.id, sibling, above);
+        true
+    }
+
+    ///
+    /// In sync mode, the subsurface's commits are held back until its
+    /// parent commits, so that they're shown together. Otherwise, they
+    /// apply straight away.
+    ///
+    pub fn set_sync(&self, sync: bool) {
+        let mut stack = self.stack.lock().unwrap();
+        if let Some(entry) = stack.child_mut(&self.id) {
+            entry.sync = sync;
+
+            if sync {
+                self.subsurface.set_sync();
+            } else {
+                self.subsurface.set_desync();
+            }
+        }
+    }
+
+    pub fn is_sync(&self) -> bool {
+        let stack = self.stack.lock().unwrap();
+        stack.entry(&self.id).is_some_and(|entry| entry.sync)
+    }
+}
+
+///
+/// The client's subsurface stacks, by parent.
+///
+pub type SubsurfaceStacks = HashMap<ObjectId, Arc<Mutex<SubsurfaceStack>>>;
+
+impl AvyClient {
+    ///
+    /// Get a controller for a registered subsurface.
+    ///
+    pub fn subsurface_controller(&self, id: &ObjectId) -> Option<AvySubsurfaceController> {
+        self.subsurface(id).map(AvySubsurface::controller)
+    }
+
+    pub fn subsurface_stack(&self, parent: &ObjectId) -> Option<Arc<Mutex<SubsurfaceStack>>> {
+        self.subsurface_stacks.get(parent).cloned()
+    }
+
+    ///
+    /// Commit `parent`'s subsurfaces in sync mode, see [SubsurfaceStack::commit_children].
+    ///
+    pub fn commit_children(&self, parent: &ObjectId) {
+        if let Some(stack) = self.subsurface_stacks.get(parent) {
+            stack.lock().unwrap().commit_children();
+        }
+    }
+
+    ///
+    /// Commit `parent` along with its subsurfaces in sync mode
+    /// (and any restacking), children first.
+    ///
+    pub fn commit_with_children(&self, parent: &ObjectId) {
+        let Some(surface) = self.surfaces.get(parent) else {
+            return;
+        };
+
+        self.commit_children(parent);
+        surface.wl_surface().commit();
+    }
+
+    ///
+    /// The surface under `point` (in `parent`'s logical coordinates),
+    /// out of `parent` and its subsurfaces, topmost first.
+    ///
+    pub fn surface_at(&self, parent: &ObjectId, point: (f64, f64)) -> Option<ObjectId> {
+        match self.subsurface_stacks.get(parent) {
+            Some(stack) => stack.lock().unwrap().surface_at(point),
+            None => {
+                let size = self.surfaces.get(parent)?.size_ref().logical_size();
+                let (x, y) = point;
+                let inside = x >= 0.0 && y >= 0.0 && x < size.0 as f64 && y < size.1 as f64;
+                inside.then(|| parent.clone())
+            }
+        }
+    }
+}
+
+impl AvySurface for AvySubsurface {
+    fn wl_surface(&self) -> &WlSurface {
+        &self.wl_surface
+    }
+
+    fn kind(&self) -> SurfaceKind {
+        SurfaceKind::Subsurface
+    }
+
+    fn viewport(&mut self) -> &mut WpViewport {
+        &mut self.viewport
+    }
+
+    fn size(&self) -> &Arc<RwLock<Size>> {
+        &self.size
+    }
+
+    fn debug_name(&self) -> Option<String> {
+        Some("subsurface".to_string())
+    }
+}
+
+impl InputHandler for AvySubsurface {}
+
+#[allow(unused)]
+impl KeyboardHandler for AvySubsurface {
+    fn enter(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        surface: &WlSurface,
+        serial: u32,
+        raw: &[u32],
+        keysyms: &[smithay_client_toolkit::seat::keyboard::Keysym],
+    ) {
+    }
+
+    fn leave(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        surface: &WlSurface,
+        serial: u32,
+    ) {
+    }
+
+    fn press_key(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        serial: u32,
+        event: smithay_client_toolkit::seat::keyboard::KeyEvent,
+    ) {
+    }
+
+    fn release_key(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        serial: u32,
+        event: smithay_client_toolkit::seat::keyboard::KeyEvent,
+    ) {
+    }
+
+    fn update_modifiers(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        serial: u32,
+        modifiers: smithay_client_toolkit::seat::keyboard::Modifiers,
+        layout: u32,
+    ) {
+    }
+}
+
+#[allow(unused)]
+impl TouchHandler for AvySubsurface {
+    fn down(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        serial: u32,
+        time: u32,
+        surface: WlSurface,
+        id: i32,
+        position: (f64, f64),
+    ) {
+    }
+
+    fn up(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        serial: u32,
+        time: u32,
+        id: i32,
+    ) {
+    }
+
+    fn motion(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        time: u32,
+        id: i32,
+        position: (f64, f64),
+    ) {
+    }
+
+    fn shape(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        id: i32,
+        major: f64,
+        minor: f64,
+    ) {
+    }
+
+    fn orientation(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        id: i32,
+        orientation: f64,
+    ) {
+    }
+
+    fn cancel(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+    ) {
+    }
+}
+
+#[allow(unused)]
+impl PointerHandler for AvySubsurface {
+    fn pointer_frame(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        pointer: &smithay_client_toolkit::reexports::client::protocol::wl_pointer::WlPointer,
+        events: &[smithay_client_toolkit::seat::pointer::PointerEvent],
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Read},
+        os::unix::net::UnixStream,
+    };
+
+    use smithay_client_toolkit::reexports::client::{
+        delegate_noop,
+        protocol::{
+            wl_compositor::WlCompositor, wl_registry::WlRegistry, wl_subcompositor::WlSubcompositor,
+        },
+    };
+
+    use super::*;
+
+    struct State;
+
+    delegate_noop!(State: ignore WlRegistry);
+    delegate_noop!(State: WlCompositor);
+    delegate_noop!(State: WlSubcompositor);
+    delegate_noop!(State: ignore WlSurface);
+    delegate_noop!(State: WlSubsurface);
+
+    // Request opcodes, in the order the protocol declares them.
+    const COMMIT: u16 = 6;
+    const SET_POSITION: u16 = 1;
+    const PLACE_ABOVE: u16 = 2;
+    const PLACE_BELOW: u16 = 3;
+    const SET_SYNC: u16 = 4;
+    const SET_DESYNC: u16 = 5;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Request {
+        object: u32,
+        opcode: u16,
+        args: Vec<u32>,
+    }
+
+    ///
+    /// A connection to nobody: requests end up in `server`, for
+    /// [Client::requests] to read back as a compositor would.
+    ///
+    struct Client {
+        connection: Connection,
+        server: UnixStream,
+        qh: QueueHandle<State>,
+        compositor: WlCompositor,
+        subcompositor: WlSubcompositor,
+        _queue: EventQueue<State>,
+    }
+
+    impl Client {
+        fn new() -> Self {
+            let (socket, server) = UnixStream::pair().unwrap();
+            let connection = Connection::from_socket(socket).unwrap();
+            let queue = connection.new_event_queue();
+            let qh = queue.handle();
+
+            let registry = connection.display().get_registry(&qh, ());
+            let compositor = registry.bind::<WlCompositor, _, _>(1, 4, &qh, ());
+            let subcompositor = registry.bind::<WlSubcompositor, _, _>(2, 1, &qh, ());
+
+            let mut client = Self {
+                connection,
+                server,
+                qh,
+                compositor,
+                subcompositor,
+                _queue: queue,
+            };
+            client.requests();
+            client
+        }
+
+        ///
+        /// A stack for a new parent surface `size` big.
+        ///
+        fn stack(&self, size: (u32, u32)) -> Arc<Mutex<SubsurfaceStack>> {
+            let wl_surface = self.compositor.create_surface(&self.qh, ());
+            let id = wl_surface.id();
+
+            Arc::new(Mutex::new(SubsurfaceStack {
+                parent: StackEntry {
+                    id: id.clone(),
+                    wl_surface,
+                    position: (0, 0),
+                    size: Arc::new(RwLock::new(Size::new(size))),
+                    sync: false,
+                },
+                children: Vec::new(),
+                order: vec![id],
+            }))
+        }
+
+        ///
+        /// A subsurface `size` big at `position`, on top of `stack`, as
+        /// [AvySubsurface::build] makes them.
+        ///
+        fn child(
+            &self,
+            stack: &Arc<Mutex<SubsurfaceStack>>,
+            position: (i32, i32),
+            size: (u32, u32),
+            sync: bool,
+        ) -> AvySubsurfaceController {
+            let mut locked = stack.lock().unwrap();
+            let wl_surface = self.compositor.create_surface(&self.qh, ());
+            let subsurface = self.subcompositor.get_subsurface(
+                &wl_surface,
+                &locked.parent.wl_surface,
+                &self.qh,
+                (),
+            );
+            let id = wl_surface.id();
+
+            subsurface.set_position(position.0, position.1);
+            locked.children.push(StackEntry {
+                id: id.clone(),
+                wl_surface,
+                position,
+                size: Arc::new(RwLock::new(Size::new(size))),
+                sync,
+            });
+            locked.order.push(id.clone());
+
+            AvySubsurfaceController {
+                subsurface,
+                id,
+                stack: stack.clone(),
+            }
+        }
+
+        ///
+        /// The requests sent since the last call.
+        ///
+        fn requests(&mut self) -> Vec<Request> {
+            self.connection.flush().unwrap();
+            self.server.set_nonblocking(true).unwrap();
+
+            let mut bytes = Vec::new();
+            let mut buffer = [0; 4096];
+            loop {
+                match self.server.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => bytes.extend_from_slice(&buffer[..read]),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) => panic!("{err}"),
+                }
+            }
+
+            let words: Vec<u32> = bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+                .collect();
+
+            let mut requests = Vec::new();
+            let mut rest = &words[..];
+            while let [object, header, ..] = *rest {
+                let len = (header >> 16) as usize / 4;
+                requests.push(Request {
+                    object,
+                    opcode: (header & 0xffff) as u16,
+                    args: rest[2..len].to_vec(),
+                });
+                rest = &rest[len..];
+            }
+
+            requests
+        }
+    }
+
+    #[test]
+    fn positions_are_sent_and_hit_tested() {
+        let mut client = Client::new();
+        let stack = client.stack((100, 50));
+        let child = client.child(&stack, (10, 10), (20, 20), false);
+        let parent = stack.lock().unwrap().parent.id.clone();
+        client.requests();
+
+        assert_eq!(
+            stack.lock().unwrap().surface_at((15.0, 15.0)),
+            Some(child.id.clone())
+        );
+
+        child.set_position((50, -5));
+        assert_eq!(child.position(), (50, -5));
+        assert_eq!(
+            client.requests(),
+            [Request {
+                object: child.subsurface.id().protocol_id(),
+                opcode: SET_POSITION,
+                args: vec![50, -5i32 as u32],
+            }]
+        );
+
+        let stack = stack.lock().unwrap();
+        assert_eq!(stack.surface_at((15.0, 15.0)), Some(parent));
+        assert_eq!(stack.surface_at((55.0, -2.0)), Some(child.id.clone()));
+        // Off the parent, and not over the child either.
+        assert_eq!(stack.surface_at((-1.0, 10.0)), None);
+    }
+
+    #[test]
+    fn restacking_changes_what_is_hit_first() {
+        let mut client = Client::new();
+        let stack = client.stack((100, 50));
+        let bottom = client.child(&stack, (0, 0), (40, 40), false);
+        let top = client.child(&stack, (20, 0), (40, 40), false);
+        let parent = stack.lock().unwrap().parent.id.clone();
+        client.requests();
+
+        let overlap = (30.0, 10.0);
+        assert_eq!(
+            stack.lock().unwrap().surface_at(overlap),
+            Some(top.id.clone())
+        );
+
+        assert!(top.place_below(&bottom.id));
+        assert_eq!(
+            stack.lock().unwrap().hit_test_order(),
+            [bottom.id.clone(), top.id.clone(), parent.clone()]
+        );
+        assert_eq!(
+            stack.lock().unwrap().surface_at(overlap),
+            Some(bottom.id.clone())
+        );
+
+        // Under the parent, it's only hit where the parent isn't.
+        assert!(bottom.place_below(&parent));
+        assert_eq!(
+            stack.lock().unwrap().hit_test_order(),
+            [top.id.clone(), parent.clone(), bottom.id.clone()]
+        );
+        assert_eq!(
+            stack.lock().unwrap().surface_at((10.0, 10.0)),
+            Some(parent.clone())
+        );
+
+        assert!(bottom.place_above(&top.id));
+        assert_eq!(
+            stack.lock().unwrap().hit_test_order(),
+            [bottom.id.clone(), top.id.clone(), parent.clone()]
+        );
+
+        assert_eq!(
+            client.requests(),
+            [
+                Request {
+                    object: top.subsurface.id().protocol_id(),
+                    opcode: PLACE_BELOW,
+                    args: vec![bottom.id.protocol_id()],
+                },
+                Request {
+                    object: bottom.subsurface.id().protocol_id(),
+                    opcode: PLACE_BELOW,
+                    args: vec![parent.protocol_id()],
+                },
+                Request {
+                    object: bottom.subsurface.id().protocol_id(),
+                    opcode: PLACE_ABOVE,
+                    args: vec![top.id.protocol_id()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn placing_against_itself_or_a_stranger_does_nothing() {
+        let mut client = Client::new();
+        let stack = client.stack((100, 50));
+        let child = client.child(&stack, (0, 0), (10, 10), false);
+        let other = client.stack((100, 50));
+        let stranger = other.lock().unwrap().parent.id.clone();
+        client.requests();
+
+        assert!(!child.place_above(&child.id));
+        assert!(!child.place_below(&stranger));
+        assert!(client.requests().is_empty());
+    }
+
+    #[test]
+    fn only_sync_children_are_committed_with_the_parent() {
+        let mut client = Client::new();
+        let stack = client.stack((100, 50));
+        let first = client.child(&stack, (0, 0), (10, 10), true);
+        let desync = client.child(&stack, (10, 0), (10, 10), false);
+        let last = client.child(&stack, (20, 0), (10, 10), true);
+        client.requests();
+
+        let commit = |controller: &AvySubsurfaceController| Request {
+            object: controller.id.protocol_id(),
+            opcode: COMMIT,
+            args: vec![],
+        };
+
+        // In the order they were created in, whatever the stacking.
+        assert!(first.place_above(&last.id));
+        client.requests();
+        stack.lock().unwrap().commit_children();
+        assert_eq!(client.requests(), [commit(&first), commit(&last)]);
+
+        first.set_sync(false);
+        desync.set_sync(true);
+        assert!(!first.is_sync());
+        assert!(desync.is_sync());
+
+        let subsurface = |controller: &AvySubsurfaceController| controller.subsurface.id();
+        assert_eq!(
+            client.requests(),
+            [
+                Request {
+                    object: subsurface(&first).protocol_id(),
+                    opcode: SET_DESYNC,
+                    args: vec![],
+                },
+                Request {
+                    object: subsurface(&desync).protocol_id(),
+                    opcode: SET_SYNC,
+                    args: vec![],
+                },
+            ]
+        );
+
+        stack.lock().unwrap().commit_children();
+        assert_eq!(client.requests(), [commit(&desync), commit(&last)]);
+    }
+}
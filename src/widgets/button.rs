@@ -0,0 +1,150 @@
+//!
+//! A push button with a text label, see [Button].
+//!
+
+use skia_safe::{Canvas, Font, Paint, PaintStyle, RRect, Rect};
+
+use crate::{
+    graphics::{RenderContext, SemanticRole},
+    util::DirtyFlag,
+};
+
+use super::control::{faded, mix, Control, Interaction};
+
+///
+/// Size (in points) of button labels, unless given a font of their own.
+///
+pub const BUTTON_FONT_SIZE: f32 = 14.0;
+
+///
+/// Corner radius of buttons, in logical pixels.
+///
+pub const BUTTON_RADIUS: f32 = 8.0;
+
+type ClickCallback = Box<dyn FnMut() + Send>;
+
+///
+/// A button in the accent color, calling [Button::on_click] when clicked,
+/// tapped or activated from the keyboard.
+///
+pub struct Button {
+    text: String,
+    font: Option<Font>,
+    interaction: Interaction,
+    on_click: Option<ClickCallback>,
+}
+
+impl Button {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            font: None,
+            interaction: Interaction::default(),
+            on_click: None,
+        }
+    }
+
+    ///
+    /// Draw the label with `font`, rather than the default typeface at [BUTTON_FONT_SIZE].
+    ///
+    pub fn with_font(mut self, font: Font) -> Self {
+        self.font.replace(font);
+        self
+    }
+
+    ///
+    /// Mark `dirty` whenever the button needs redrawing.
+    ///
+    pub fn track_dirty(mut self, dirty: DirtyFlag) -> Self {
+        self.interaction.set_dirty(dirty);
+        self
+    }
+
+    pub fn on_click(mut self, on_click: impl FnMut() + Send + 'static) -> Self {
+        self.on_click.replace(Box::new(on_click));
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.interaction.mark_dirty();
+    }
+
+    pub fn draw(&mut self, canvas: &Canvas, context: &RenderContext, bounds: impl AsRef<Rect>) {
+        let bounds = *bounds.as_ref();
+        let now = context.now();
+        let interaction = &self.interaction;
+        let opacity = interaction.opacity();
+
+        let accent = context.resolve_color(SemanticRole::Accent);
+        let on_accent = context.resolve_color(SemanticRole::OnAccent);
+
+        // Lighter when hovered, darker when pressed.
+        let fill = mix(accent, on_accent, 0.12 * interaction.hover_amount(now));
+        let fill = mix(
+            fill,
+            skia_safe::Color4f::new(0.0, 0.0, 0.0, 1.0),
+            0.2 * interaction.press_amount(now),
+        );
+
+        let radius = BUTTON_RADIUS.min(bounds.height() / 2.0);
+        let rrect = RRect::new_rect_xy(bounds, radius, radius);
+
+        let mut paint = Paint::new(faded(fill, opacity), None);
+        paint.set_anti_alias(true);
+        canvas.draw_rrect(rrect, &paint);
+
+        if interaction.is_focused() {
+            let mut ring = Paint::new(faded(accent, opacity), None);
+            ring.set_anti_alias(true);
+            ring.set_style(PaintStyle::Stroke);
+            ring.set_stroke_width(2.0);
+            canvas.draw_rrect(rrect.with_outset((3.0, 3.0)), &ring);
+        }
+
+        let font = self.font.clone().unwrap_or_else(|| {
+            let mut font = Font::default();
+            font.set_size(context.font_size(BUTTON_FONT_SIZE));
+            font
+        });
+
+        let mut text = Paint::new(faded(on_accent, opacity), None);
+        text.set_anti_alias(true);
+
+        let (width, _) = font.measure_str(&self.text, Some(&text));
+        let (_, metrics) = font.metrics();
+        let baseline = bounds.center_y() - (metrics.ascent + metrics.descent) / 2.0;
+
+        canvas.save();
+        canvas.clip_rrect(rrect, None, true);
+        canvas.draw_str(
+            &self.text,
+            (bounds.center_x() - width / 2.0, baseline),
+            &font,
+            &text,
+        );
+        canvas.restore();
+
+        self.interaction.drawn(bounds, context);
+    }
+}
+
+impl Control for Button {
+    fn interaction(&self) -> &Interaction {
+        &self.interaction
+    }
+
+    fn interaction_mut(&mut self) -> &mut Interaction {
+        &mut self.interaction
+    }
+
+    fn activate(&mut self) {
+        if let Some(on_click) = self.on_click.as_mut() {
+            on_click();
+        }
+    }
+}
@@ -0,0 +1,666 @@
+//!
+//! What [super::Button], [super::Toggle] and [super::Slider] have in
+//! common: hover, press, focus and disabled states (eased between, rather
+//! than switched), and turning pointer, touch and key events into them.
+//!
+//! Controls are plain structs: draw them from any render callback, within
+//! whatever bounds suit, and hand them the surface's input events. They
+//! only react to events within the bounds they were last drawn in.
+//!
+
+use std::time::{Duration, Instant};
+
+use skia_safe::{Color4f, Rect};
+use smithay_client_toolkit::seat::{
+    keyboard::Keysym,
+    pointer::{PointerEvent, PointerEventKind},
+};
+
+use crate::{
+    graphics::RenderContext,
+    util::{
+        animation::{Easing, Lerp, Transition},
+        DirtyFlag, SharedClock, SurfacePoint,
+    },
+};
+
+///
+/// How long controls take to ease into a new state.
+///
+pub const STATE_TRANSITION: Duration = Duration::from_millis(120);
+
+///
+/// Opacity of disabled controls.
+///
+pub const DISABLED_OPACITY: f32 = 0.4;
+
+///
+/// The left mouse button, as in `linux/input-event-codes.h`.
+///
+const BTN_LEFT: u32 = 0x110;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Presser {
+    Pointer,
+    Touch(i32),
+}
+
+///
+/// A control's state, as input left it.
+///
+pub struct Interaction {
+    /// Where the control was last drawn, in logical pixels.
+    bounds: Rect,
+    disabled: bool,
+    focused: bool,
+    hovered: bool,
+    pressed: Option<Presser>,
+    /// Where the press (if any) was last seen.
+    press_point: Option<SurfacePoint>,
+
+    hover: Transition,
+    press: Transition,
+    dirty: Option<DirtyFlag>,
+    /// The surface's, as of the last draw, which input eases by.
+    clock: SharedClock,
+}
+
+impl Default for Interaction {
+    fn default() -> Self {
+        Self {
+            bounds: Rect::new_empty(),
+            disabled: false,
+            focused: false,
+            hovered: false,
+            pressed: None,
+            press_point: None,
+            hover: Transition::new(0.0, STATE_TRANSITION, Easing::EaseOut),
+            press: Transition::new(0.0, STATE_TRANSITION, Easing::EaseOut),
+            dirty: None,
+            clock: SharedClock::default(),
+        }
+    }
+}
+
+impl Interaction {
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    pub fn contains(&self, point: SurfacePoint) -> bool {
+        self.bounds.contains(skia_safe::Point::from(point))
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn is_hovered(&self) -> bool {
+        self.hovered
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed.is_some()
+    }
+
+    ///
+    /// How hovered the control looks at `now`, from `0.0` to `1.0`.
+    ///
+    pub fn hover_amount(&self, now: Instant) -> f32 {
+        self.hover.value(now) as f32
+    }
+
+    ///
+    /// How pressed the control looks at `now`, from `0.0` to `1.0`.
+    ///
+    pub fn press_amount(&self, now: Instant) -> f32 {
+        self.press.value(now) as f32
+    }
+
+    ///
+    /// The opacity to draw the control with.
+    ///
+    pub fn opacity(&self) -> f32 {
+        match self.disabled {
+            true => DISABLED_OPACITY,
+            false => 1.0,
+        }
+    }
+
+    pub(crate) fn set_dirty(&mut self, dirty: DirtyFlag) {
+        self.dirty.replace(dirty);
+    }
+
+    pub(crate) fn mark_dirty(&self) {
+        if let Some(dirty) = &self.dirty {
+            dirty.mark();
+        }
+    }
+
+    ///
+    /// Record where the control is being drawn (with `context`), and keep
+    /// the surface redrawing whilst the control eases into its state.
+    ///
+    pub(crate) fn drawn(&mut self, bounds: Rect, context: &RenderContext) {
+        self.bounds = bounds;
+        self.clock = context.clock().clone();
+
+        if self.is_animating(context.now()) {
+            self.mark_dirty();
+        }
+    }
+
+    ///
+    /// The time, on the clock of the surface the control was last drawn on.
+    ///
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    pub(crate) fn is_animating(&self, now: Instant) -> bool {
+        self.hover.is_animating(now) || self.press.is_animating(now)
+    }
+
+    fn set_hovered(&mut self, hovered: bool) {
+        if self.hovered == hovered {
+            return;
+        }
+
+        self.hovered = hovered;
+        self.hover
+            .set(if hovered { 1.0 } else { 0.0 }, self.clock.now());
+        self.mark_dirty();
+    }
+
+    fn set_pressed(&mut self, presser: Option<Presser>, point: Option<SurfacePoint>) {
+        self.press_point = point;
+        if self.pressed == presser {
+            return;
+        }
+
+        self.pressed = presser;
+        self.press
+            .set(if presser.is_some() { 1.0 } else { 0.0 }, self.clock.now());
+        self.mark_dirty();
+    }
+}
+
+///
+/// Input handling shared by every control, see the [module docs](self).
+///
+/// Event methods return whether the event was meant for the control.
+///
+pub trait Control {
+    fn interaction(&self) -> &Interaction;
+
+    fn interaction_mut(&mut self) -> &mut Interaction;
+
+    ///
+    /// A press was let go of over the control, or it was activated from the keyboard.
+    ///
+    fn activate(&mut self);
+
+    ///
+    /// A press on the control moved to `point`, wherever that is.
+    ///
+    #[allow(unused)]
+    fn drag(&mut self, point: SurfacePoint) {}
+
+    ///
+    /// Keys other than space and enter, whilst the control has focus.
+    ///
+    #[allow(unused)]
+    fn key(&mut self, keysym: Keysym) -> bool {
+        false
+    }
+
+    fn is_disabled(&self) -> bool {
+        self.interaction().is_disabled()
+    }
+
+    ///
+    /// Disabled controls are drawn faded, and ignore all input.
+    ///
+    fn set_disabled(&mut self, disabled: bool) {
+        let interaction = self.interaction_mut();
+        if interaction.disabled == disabled {
+            return;
+        }
+
+        interaction.disabled = disabled;
+        interaction.set_hovered(false);
+        interaction.set_pressed(None, None);
+        interaction.mark_dirty();
+    }
+
+    ///
+    /// Give the control keyboard focus (or take it away), for it to be
+    /// activated with space or enter. Only one control per surface should
+    /// have it, and only whilst the surface does.
+    ///
+    fn set_focused(&mut self, focused: bool) {
+        let interaction = self.interaction_mut();
+        if interaction.focused != focused {
+            interaction.focused = focused;
+            interaction.mark_dirty();
+        }
+    }
+
+    fn pointer_motion(&mut self, point: SurfacePoint) -> bool {
+        if self.is_disabled() {
+            return false;
+        }
+
+        let interaction = self.interaction_mut();
+        let inside = interaction.contains(point);
+        interaction.set_hovered(inside);
+
+        if interaction.pressed == Some(Presser::Pointer) {
+            interaction.press_point = Some(point);
+            self.drag(point);
+            return true;
+        }
+
+        inside
+    }
+
+    fn pointer_leave(&mut self) {
+        self.interaction_mut().set_hovered(false);
+    }
+
+    fn pointer_press(&mut self, point: SurfacePoint) -> bool {
+        let interaction = self.interaction_mut();
+        if interaction.disabled || !interaction.contains(point) {
+            return false;
+        }
+
+        interaction.set_pressed(Some(Presser::Pointer), Some(point));
+        self.drag(point);
+        true
+    }
+
+    ///
+    /// Activates the control if the press is let go of over it.
+    ///
+    fn pointer_release(&mut self, point: SurfacePoint) -> bool {
+        let interaction = self.interaction_mut();
+        if interaction.pressed != Some(Presser::Pointer) {
+            return false;
+        }
+
+        let inside = interaction.contains(point);
+        interaction.set_pressed(None, None);
+        if inside {
+            self.activate();
+        }
+
+        true
+    }
+
+    ///
+    /// Handle a pointer event as it comes from [crate::wayland::surface::PointerHandler::pointer_frame].
+    ///
+    /// Only the left button presses.
+    ///
+    fn pointer_event(&mut self, event: &PointerEvent) -> bool {
+        let point = SurfacePoint::from(event.position);
+        match event.kind {
+            PointerEventKind::Enter { .. } | PointerEventKind::Motion { .. } => {
+                self.pointer_motion(point)
+            }
+            PointerEventKind::Leave { .. } => {
+                self.pointer_leave();
+                false
+            }
+            PointerEventKind::Press { button, .. } if button == BTN_LEFT => {
+                self.pointer_press(point)
+            }
+            PointerEventKind::Release { button, .. } if button == BTN_LEFT => {
+                self.pointer_release(point)
+            }
+            _ => false,
+        }
+    }
+
+    fn touch_down(&mut self, id: i32, point: SurfacePoint) -> bool {
+        let interaction = self.interaction_mut();
+        if interaction.disabled || interaction.pressed.is_some() || !interaction.contains(point) {
+            return false;
+        }
+
+        interaction.set_pressed(Some(Presser::Touch(id)), Some(point));
+        self.drag(point);
+        true
+    }
+
+    fn touch_motion(&mut self, id: i32, point: SurfacePoint) -> bool {
+        let interaction = self.interaction_mut();
+        if interaction.pressed != Some(Presser::Touch(id)) {
+            return false;
+        }
+
+        interaction.press_point = Some(point);
+        self.drag(point);
+        true
+    }
+
+    ///
+    /// Activates the control if the touch point was last seen over it.
+    ///
+    fn touch_up(&mut self, id: i32) -> bool {
+        let interaction = self.interaction_mut();
+        if interaction.pressed != Some(Presser::Touch(id)) {
+            return false;
+        }
+
+        let inside = interaction
+            .press_point
+            .is_some_and(|point| interaction.contains(point));
+        interaction.set_pressed(None, None);
+        if inside {
+            self.activate();
+        }
+
+        true
+    }
+
+    fn touch_cancel(&mut self) {
+        let interaction = self.interaction_mut();
+        if matches!(interaction.pressed, Some(Presser::Touch(_))) {
+            interaction.set_pressed(None, None);
+        }
+    }
+
+    ///
+    /// Space and enter activate the control, if it has focus.
+    ///
+    fn key_press(&mut self, keysym: Keysym) -> bool {
+        let interaction = self.interaction();
+        if interaction.disabled || !interaction.focused {
+            return false;
+        }
+
+        match keysym {
+            Keysym::space | Keysym::Return | Keysym::KP_Enter => {
+                self.activate();
+                self.interaction().mark_dirty();
+                true
+            }
+            keysym => self.key(keysym),
+        }
+    }
+}
+
+///
+/// `from` blended towards `to` by `t`.
+///
+pub(crate) fn mix(from: Color4f, to: Color4f, t: f32) -> Color4f {
+    let t = t as f64;
+    Color4f::new(
+        from.r.lerp(&to.r, t),
+        from.g.lerp(&to.g, t),
+        from.b.lerp(&to.b, t),
+        from.a.lerp(&to.a, t),
+    )
+}
+
+///
+/// `color`, its alpha multiplied by `opacity`.
+///
+pub(crate) fn faded(color: Color4f, opacity: f32) -> Color4f {
+    Color4f::new(color.r, color.g, color.b, color.a * opacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{util::TestClock, widgets::Toggle};
+
+    use super::*;
+
+    ///
+    /// A control recording what it's told.
+    ///
+    #[derive(Default)]
+    struct Probe {
+        interaction: Interaction,
+        activations: usize,
+        drags: Vec<(f64, f64)>,
+        keys: Vec<Keysym>,
+    }
+
+    impl Control for Probe {
+        fn interaction(&self) -> &Interaction {
+            &self.interaction
+        }
+
+        fn interaction_mut(&mut self) -> &mut Interaction {
+            &mut self.interaction
+        }
+
+        fn activate(&mut self) {
+            self.activations += 1;
+        }
+
+        fn drag(&mut self, point: SurfacePoint) {
+            self.drags.push(point.into());
+        }
+
+        fn key(&mut self, keysym: Keysym) -> bool {
+            self.keys.push(keysym);
+            keysym == Keysym::Left
+        }
+    }
+
+    const INSIDE: SurfacePoint = SurfacePoint::new(20.0, 15.0);
+    const OUTSIDE: SurfacePoint = SurfacePoint::new(80.0, 15.0);
+
+    ///
+    /// Set `interaction` up as if drawn at (10, 10), 40x20, on `time`.
+    ///
+    fn drawn(interaction: &mut Interaction, time: &TestClock) -> DirtyFlag {
+        let dirty = DirtyFlag::default();
+        dirty.take();
+
+        interaction.bounds = Rect::from_xywh(10.0, 10.0, 40.0, 20.0);
+        interaction.clock = SharedClock::new(time.clone());
+        interaction.set_dirty(dirty.clone());
+        dirty
+    }
+
+    fn probe() -> (Probe, TestClock, DirtyFlag) {
+        let time = TestClock::new();
+        let mut probe = Probe::default();
+        let dirty = drawn(&mut probe.interaction, &time);
+        (probe, time, dirty)
+    }
+
+    #[test]
+    fn hovering_eases_in_and_out() {
+        let (mut probe, time, dirty) = probe();
+
+        assert!(!probe.pointer_motion(OUTSIDE));
+        assert!(!probe.interaction.is_hovered());
+        assert!(!dirty.take());
+
+        assert!(probe.pointer_motion(INSIDE));
+        assert!(probe.interaction.is_hovered());
+        assert!(dirty.take());
+        assert_eq!(probe.interaction.hover_amount(probe.interaction.now()), 0.0);
+
+        time.advance(STATE_TRANSITION / 2);
+        let halfway = probe.interaction.hover_amount(probe.interaction.now());
+        assert!(halfway > 0.0 && halfway < 1.0, "{halfway}");
+
+        time.advance(STATE_TRANSITION);
+        assert_eq!(probe.interaction.hover_amount(probe.interaction.now()), 1.0);
+        assert!(!probe.interaction.is_animating(probe.interaction.now()));
+
+        // Moving within it changes nothing.
+        assert!(probe.pointer_motion(SurfacePoint::new(30.0, 20.0)));
+        assert!(!dirty.take());
+
+        probe.pointer_leave();
+        assert!(!probe.interaction.is_hovered());
+        assert!(dirty.take());
+        time.advance(STATE_TRANSITION);
+        assert_eq!(probe.interaction.hover_amount(probe.interaction.now()), 0.0);
+
+        // Nor is anything pressed by hovering.
+        assert_eq!(probe.activations, 0);
+        assert!(probe.drags.is_empty());
+    }
+
+    #[test]
+    fn pressing_activates_only_if_let_go_over_the_control() {
+        let (mut probe, time, dirty) = probe();
+
+        assert!(!probe.pointer_press(OUTSIDE));
+        assert!(!probe.interaction.is_pressed());
+
+        assert!(probe.pointer_press(INSIDE));
+        assert!(probe.interaction.is_pressed());
+        assert!(dirty.take());
+        time.advance(STATE_TRANSITION);
+        assert_eq!(probe.interaction.press_amount(probe.interaction.now()), 1.0);
+
+        // Dragged about, even off the control, whilst held.
+        assert!(probe.pointer_motion(OUTSIDE));
+        assert!(probe.pointer_release(INSIDE));
+        assert_eq!(probe.activations, 1);
+        assert_eq!(probe.drags, [(20.0, 15.0), (80.0, 15.0)]);
+        assert!(!probe.interaction.is_pressed());
+
+        // Let go of elsewhere: a change of mind.
+        assert!(probe.pointer_press(INSIDE));
+        assert!(probe.pointer_release(OUTSIDE));
+        assert_eq!(probe.activations, 1);
+
+        // Nothing held: not for the control.
+        assert!(!probe.pointer_release(INSIDE));
+        assert_eq!(probe.activations, 1);
+    }
+
+    #[test]
+    fn touches_activate_where_they_were_last_seen() {
+        let (mut probe, _, _) = probe();
+
+        assert!(probe.touch_down(1, INSIDE));
+        // One press at a time.
+        assert!(!probe.touch_down(2, INSIDE));
+
+        assert!(!probe.touch_motion(2, OUTSIDE));
+        assert!(probe.touch_motion(1, OUTSIDE));
+        assert!(probe.touch_up(1));
+        assert_eq!(probe.activations, 0);
+
+        assert!(probe.touch_down(3, INSIDE));
+        assert!(probe.touch_motion(3, SurfacePoint::new(45.0, 25.0)));
+        assert!(!probe.touch_up(4));
+        assert!(probe.touch_up(3));
+        assert_eq!(probe.activations, 1);
+
+        assert!(probe.touch_down(5, INSIDE));
+        probe.touch_cancel();
+        assert!(!probe.interaction.is_pressed());
+        assert!(!probe.touch_up(5));
+        assert_eq!(probe.activations, 1);
+    }
+
+    #[test]
+    fn toggles_flip_when_activated() {
+        let time = TestClock::new();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        let mut toggle = Toggle::new(false).on_change(move |on| recorded.lock().unwrap().push(on));
+        let dirty = drawn(toggle.interaction_mut(), &time);
+
+        assert!(toggle.pointer_press(INSIDE));
+        assert!(toggle.pointer_release(INSIDE));
+        assert!(toggle.is_on());
+        assert!(dirty.take());
+
+        toggle.set_focused(true);
+        assert!(toggle.key_press(Keysym::space));
+        assert!(!toggle.is_on());
+
+        // Set by the app: no callback.
+        toggle.set_on(true);
+        assert!(toggle.is_on());
+        toggle.set_on(true);
+
+        assert!(toggle.touch_down(0, INSIDE));
+        assert!(toggle.touch_up(0));
+        assert!(!toggle.is_on());
+
+        assert_eq!(*changes.lock().unwrap(), [true, false, false]);
+    }
+
+    #[test]
+    fn disabled_controls_ignore_input() {
+        let (mut probe, time, dirty) = probe();
+        assert!(probe.pointer_motion(INSIDE));
+        assert!(probe.pointer_press(INSIDE));
+        probe.set_focused(true);
+        dirty.take();
+
+        probe.set_disabled(true);
+        assert!(probe.is_disabled());
+        assert_eq!(probe.interaction.opacity(), DISABLED_OPACITY);
+        assert!(dirty.take());
+
+        // What it was in the middle of is dropped, not finished.
+        assert!(!probe.interaction.is_hovered());
+        assert!(!probe.interaction.is_pressed());
+        time.advance(STATE_TRANSITION);
+        assert_eq!(probe.interaction.press_amount(probe.interaction.now()), 0.0);
+        assert!(!probe.pointer_release(INSIDE));
+
+        assert!(!probe.pointer_motion(INSIDE));
+        assert!(!probe.pointer_press(INSIDE));
+        assert!(!probe.touch_down(0, INSIDE));
+        assert!(!probe.key_press(Keysym::Return));
+        assert!(!probe.key_press(Keysym::Left));
+        assert_eq!(probe.activations, 0);
+        assert!(probe.keys.is_empty());
+
+        probe.set_disabled(false);
+        assert_eq!(probe.interaction.opacity(), 1.0);
+        assert!(probe.pointer_press(INSIDE));
+        assert!(probe.pointer_release(INSIDE));
+        assert_eq!(probe.activations, 1);
+    }
+
+    #[test]
+    fn focused_controls_are_activated_from_the_keyboard() {
+        let (mut probe, _, dirty) = probe();
+
+        assert!(!probe.key_press(Keysym::space));
+        assert_eq!(probe.activations, 0);
+
+        probe.set_focused(true);
+        assert!(probe.interaction.is_focused());
+        assert!(dirty.take());
+
+        for keysym in [Keysym::space, Keysym::Return, Keysym::KP_Enter] {
+            assert!(probe.key_press(keysym), "{keysym:?}");
+            assert!(dirty.take());
+        }
+        assert_eq!(probe.activations, 3);
+
+        // Anything else is up to the control.
+        assert!(probe.key_press(Keysym::Left));
+        assert!(!probe.key_press(Keysym::a));
+        assert_eq!(probe.keys, [Keysym::Left, Keysym::a]);
+        assert_eq!(probe.activations, 3);
+
+        probe.set_focused(false);
+        assert!(!probe.key_press(Keysym::Return));
+        assert_eq!(probe.activations, 3);
+    }
+}
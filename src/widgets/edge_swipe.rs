@@ -0,0 +1,698 @@
+//!
+//! Swiping in from a screen edge on touch screens, e.g. up from the bottom
+//! to reveal the dock.
+//!
+//! Touches only reach the surface they land on, so each edge (per output)
+//! gets a thin, invisible layer of its own on the overlay layer. Once a
+//! touch lands on it, the compositor keeps sending its motion there for as
+//! long as it's down, however far it goes.
+//!
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+    time::Duration,
+};
+
+use smithay_client_toolkit::{
+    compositor::Region,
+    error::GlobalError,
+    reexports::{
+        client::{
+            protocol::{wl_output::WlOutput, wl_shm, wl_surface::WlSurface},
+            Connection, EventQueue, Proxy, QueueHandle,
+        },
+        protocols::wp::viewporter::client::wp_viewport::WpViewport,
+    },
+    shell::{
+        wlr_layer::{Anchor, KeyboardInteractivity, Layer, LayerSurface},
+        WaylandSurface,
+    },
+    shm::{
+        slot::{Buffer, CreateBufferError, SlotPool},
+        CreatePoolError,
+    },
+};
+use thiserror::Error;
+use wayland_backend::client::ObjectId;
+
+use crate::{
+    debug, impl_as_any,
+    proxy::AvyProxy,
+    util::{
+        animation::{Easing, Lerp},
+        coords::SurfacePoint,
+        Insets, Size,
+    },
+    wayland::{
+        seat::Capabilities,
+        surface::{
+            configure::PendingConfigure, layer::AvyLayerController, AvySurface, InputHandler,
+            KeyboardHandler, PointerHandler, TouchHandler,
+        },
+    },
+    AvyClient,
+};
+
+///
+/// How far back (in milliseconds) motion counts towards a swipe's velocity.
+///
+const VELOCITY_WINDOW: u32 = 100;
+
+#[derive(Debug, Error)]
+pub enum EdgeSwipeError {
+    #[error("Could not create the edge's input region: {0}")]
+    Region(#[from] GlobalError),
+    #[error("Could not allocate the edge's buffer: {0}")]
+    Pool(#[from] CreatePoolError),
+    #[error("Could not create the edge's buffer: {0}")]
+    Buffer(#[from] CreateBufferError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SwipeEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl SwipeEdge {
+    ///
+    /// Anchors for a strip spanning the whole edge.
+    ///
+    fn anchor(self) -> Anchor {
+        match self {
+            Self::Top => Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+            Self::Bottom => Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT,
+            Self::Left => Anchor::LEFT | Anchor::TOP | Anchor::BOTTOM,
+            Self::Right => Anchor::RIGHT | Anchor::TOP | Anchor::BOTTOM,
+        }
+    }
+
+    ///
+    /// The strip's size, `0` being stretched along the edge.
+    ///
+    fn strip_size(self, thickness: u32) -> (u32, u32) {
+        match self {
+            Self::Top | Self::Bottom => (0, thickness),
+            Self::Left | Self::Right => (thickness, 0),
+        }
+    }
+
+    ///
+    /// How far (in logical pixels) `position` is from `start`, away from the edge.
+    ///
+    fn inward(self, start: SurfacePoint, position: SurfacePoint) -> f64 {
+        let (x, y) = position.offset_from(start);
+        match self {
+            Self::Top => y,
+            Self::Bottom => -y,
+            Self::Left => x,
+            Self::Right => -x,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeSwipeEvent {
+    /// How far the swipe has come, from `0.0` (at the edge) to `1.0`
+    /// ([EdgeSwipeOptions::distance] away).
+    Progress(f32),
+    /// Let go far or fast enough: whatever it reveals should be shown.
+    Completed,
+    /// Let go too early (or taken over by the compositor): go back.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EdgeSwipeOptions {
+    /// How thick the strip along the edge is, in logical pixels.
+    ///
+    /// Kept to a pixel or two: touches on it don't reach what's below.
+    pub thickness: u32,
+    /// How far (in logical pixels) to swipe for a progress of `1.0`.
+    pub distance: f64,
+    /// Progress past which letting go completes the swipe.
+    pub threshold: f32,
+    /// Speed (in logical pixels per second, away from the edge) past which letting
+    /// go completes the swipe, however far it got; flicking back as fast cancels it.
+    pub fling_velocity: f64,
+}
+
+impl Default for EdgeSwipeOptions {
+    fn default() -> Self {
+        Self {
+            thickness: 2,
+            distance: 120.0,
+            threshold: 0.5,
+            fling_velocity: 600.0,
+        }
+    }
+}
+
+struct Swipe {
+    /// The touch point swiping.
+    id: i32,
+    start: SurfacePoint,
+    progress: f32,
+    /// Recent distances from the edge, with the times they were reached at.
+    samples: VecDeque<(f64, u32)>,
+}
+
+impl Swipe {
+    fn sample(&mut self, inward: f64, time: u32) {
+        self.samples.push_back((inward, time));
+
+        while let Some(&(_, oldest)) = self.samples.front() {
+            if time.wrapping_sub(oldest) <= VELOCITY_WINDOW || self.samples.len() <= 2 {
+                break;
+            }
+
+            self.samples.pop_front();
+        }
+    }
+
+    ///
+    /// Away from the edge, in logical pixels per second.
+    ///
+    fn velocity(&self) -> f64 {
+        let (Some(&(from, from_time)), Some(&(to, to_time))) =
+            (self.samples.front(), self.samples.back())
+        else {
+            return 0.0;
+        };
+
+        let elapsed = to_time.wrapping_sub(from_time);
+        if elapsed == 0 {
+            return 0.0;
+        }
+
+        (to - from) / (elapsed as f64 / 1000.0)
+    }
+}
+
+type EventCallback = Box<dyn FnMut(EdgeSwipeEvent) + Send>;
+
+struct EdgeSwipeShared {
+    edge: SwipeEdge,
+    options: Mutex<EdgeSwipeOptions>,
+    swipe: Mutex<Option<Swipe>>,
+    on_event: Mutex<Option<EventCallback>>,
+}
+
+impl EdgeSwipeShared {
+    fn swipe(&self) -> MutexGuard<'_, Option<Swipe>> {
+        self.swipe.lock().unwrap()
+    }
+
+    fn down(&self, id: i32, time: u32, position: SurfacePoint) {
+        let mut swipe = self.swipe();
+
+        // One finger at a time.
+        if swipe.is_some() {
+            return;
+        }
+
+        let mut samples = VecDeque::new();
+        samples.push_back((0.0, time));
+
+        swipe.replace(Swipe {
+            id,
+            start: position,
+            progress: 0.0,
+            samples,
+        });
+        drop(swipe);
+
+        self.notify(EdgeSwipeEvent::Progress(0.0));
+    }
+
+    fn motion(&self, id: i32, time: u32, position: SurfacePoint) {
+        let distance = self.options.lock().unwrap().distance;
+
+        let mut guard = self.swipe();
+        let Some(swipe) = guard.as_mut().filter(|swipe| swipe.id == id) else {
+            return;
+        };
+
+        let inward = self.edge.inward(swipe.start, position);
+        swipe.sample(inward, time);
+
+        let progress = (inward / distance.max(1.0)).clamp(0.0, 1.0) as f32;
+        if progress == swipe.progress {
+            return;
+        }
+
+        swipe.progress = progress;
+        drop(guard);
+
+        self.notify(EdgeSwipeEvent::Progress(progress));
+    }
+
+    fn up(&self, id: i32) {
+        let options = *self.options.lock().unwrap();
+
+        let swipe = {
+            let mut swipe = self.swipe();
+            if swipe.as_ref().map_or(true, |swipe| swipe.id != id) {
+                return;
+            }
+
+            swipe.take().unwrap()
+        };
+        let velocity = swipe.velocity();
+
+        let completed = if velocity.abs() >= options.fling_velocity {
+            velocity > 0.0
+        } else {
+            swipe.progress >= options.threshold
+        };
+
+        self.notify(if completed {
+            EdgeSwipeEvent::Completed
+        } else {
+            EdgeSwipeEvent::Cancelled
+        });
+    }
+
+    fn cancel(&self) {
+        let cancelled = self.swipe().take().is_some();
+        if cancelled {
+            self.notify(EdgeSwipeEvent::Cancelled);
+        }
+    }
+
+    fn notify(&self, event: EdgeSwipeEvent) {
+        // Not called with the lock held, so that the callback may replace itself.
+        let Some(mut on_event) = self.on_event.lock().unwrap().take() else {
+            return;
+        };
+
+        on_event(event);
+
+        let mut slot = self.on_event.lock().unwrap();
+        if slot.is_none() {
+            slot.replace(on_event);
+        }
+    }
+}
+
+///
+/// The invisible strip along the edge, see [EdgeSwipeDetector].
+///
+struct EdgeStrip {
+    layer: LayerSurface,
+    viewport: WpViewport,
+    size: Arc<RwLock<Size>>,
+    /// A single transparent pixel, stretched over the strip.
+    buffer: Buffer,
+    attached: bool,
+    /// Holds [EdgeStrip::buffer].
+    _pool: SlotPool,
+    /// Set as the input region whilst there's no touch screen to swipe on.
+    empty_region: Region,
+    shared: Arc<EdgeSwipeShared>,
+}
+
+impl EdgeStrip {
+    ///
+    /// Only take input whilst there's a touch screen. Input regions cover
+    /// pointers as well, so the strip would otherwise be a dead zone for
+    /// the mouse along the edge.
+    ///
+    fn set_touch_input(&self, touch: bool) {
+        let region = (!touch).then(|| self.empty_region.wl_region());
+        self.layer.wl_surface().set_input_region(region);
+    }
+}
+
+impl Drop for EdgeStrip {
+    fn drop(&mut self) {
+        self.viewport.destroy();
+        debug::objects::destroyed(&self.viewport.id());
+        // The region goes with the strip.
+        debug::objects::destroyed(&self.empty_region.wl_region().id());
+    }
+}
+
+impl_as_any!(EdgeStrip);
+
+impl AvySurface for EdgeStrip {
+    fn wl_surface(&self) -> &WlSurface {
+        self.layer.wl_surface()
+    }
+
+    fn viewport(&mut self) -> &mut WpViewport {
+        &mut self.viewport
+    }
+
+    fn size(&self) -> &Arc<RwLock<Size>> {
+        &self.size
+    }
+
+    fn debug_name(&self) -> Option<String> {
+        Some(format!("edge-swipe:{:?}", self.shared.edge))
+    }
+
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<AvyClient>,
+        configure: PendingConfigure,
+    ) {
+        let (width, height) = configure.size;
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        self.viewport.set_destination(width as i32, height as i32);
+
+        // Attached once: the compositor holds on to it from then on.
+        if !self.attached {
+            let wl_surface = self.layer.wl_surface();
+
+            match self.buffer.attach_to(wl_surface) {
+                Ok(()) => {
+                    wl_surface.damage_buffer(0, 0, 1, 1);
+                    self.attached = true;
+                }
+                Err(err) => log::warn!("Could not attach the edge's buffer: {err}"),
+            }
+        }
+
+        self.layer.commit();
+    }
+
+    fn capabilities_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<AvyClient>,
+        capabilities: Capabilities,
+    ) {
+        self.set_touch_input(capabilities.touch);
+        self.layer.commit();
+    }
+}
+
+impl InputHandler for EdgeStrip {}
+
+#[allow(unused)]
+impl TouchHandler for EdgeStrip {
+    fn down(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        serial: u32,
+        time: u32,
+        surface: WlSurface,
+        id: i32,
+        position: (f64, f64),
+    ) {
+        self.shared.down(id, time, position.into());
+    }
+
+    fn up(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        serial: u32,
+        time: u32,
+        id: i32,
+    ) {
+        self.shared.up(id);
+    }
+
+    fn motion(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        time: u32,
+        id: i32,
+        position: (f64, f64),
+    ) {
+        self.shared.motion(id, time, position.into());
+    }
+
+    fn shape(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        id: i32,
+        major: f64,
+        minor: f64,
+    ) {
+    }
+
+    fn orientation(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+        id: i32,
+        orientation: f64,
+    ) {
+    }
+
+    fn cancel(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        touch: &smithay_client_toolkit::reexports::client::protocol::wl_touch::WlTouch,
+    ) {
+        self.shared.cancel();
+    }
+}
+
+///
+/// Pointers only get here along the edge of a touch screen, and go straight through.
+///
+#[allow(unused)]
+impl PointerHandler for EdgeStrip {
+    fn pointer_frame(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        pointer: &smithay_client_toolkit::reexports::client::protocol::wl_pointer::WlPointer,
+        events: &[smithay_client_toolkit::seat::pointer::PointerEvent],
+    ) {
+    }
+}
+
+#[allow(unused)]
+impl KeyboardHandler for EdgeStrip {
+    fn enter(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        surface: &WlSurface,
+        serial: u32,
+        raw: &[u32],
+        keysyms: &[smithay_client_toolkit::seat::keyboard::Keysym],
+    ) {
+    }
+
+    fn leave(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        surface: &WlSurface,
+        serial: u32,
+    ) {
+    }
+
+    fn press_key(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        serial: u32,
+        event: smithay_client_toolkit::seat::keyboard::KeyEvent,
+    ) {
+    }
+
+    fn release_key(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        serial: u32,
+        event: smithay_client_toolkit::seat::keyboard::KeyEvent,
+    ) {
+    }
+
+    fn update_modifiers(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<AvyClient>,
+        keyboard: &smithay_client_toolkit::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        serial: u32,
+        modifiers: smithay_client_toolkit::seat::keyboard::Modifiers,
+        layout: u32,
+    ) {
+    }
+}
+
+///
+/// Detects swipes in from an edge of an output, see the [module docs](self).
+///
+/// The edge's strip is destroyed along with the detector.
+///
+pub struct EdgeSwipeDetector {
+    shared: Arc<EdgeSwipeShared>,
+    surface: ObjectId,
+    proxy: AvyProxy,
+}
+
+impl EdgeSwipeDetector {
+    ///
+    /// Start detecting swipes in from `edge` of `output` (or
+    /// whichever output the compositor picks, if `None`).
+    ///
+    pub fn new(
+        app: &mut AvyClient,
+        event_queue: &mut EventQueue<AvyClient>,
+        output: Option<&WlOutput>,
+        edge: SwipeEdge,
+        options: EdgeSwipeOptions,
+    ) -> Result<Self, EdgeSwipeError> {
+        let qh = &event_queue.handle();
+
+        let empty_region = Region::new(&app.compositor_state)?;
+
+        let mut pool = SlotPool::new(4, &app.shm_state)?;
+        let (buffer, pixels) = pool.create_buffer(1, 1, 4, wl_shm::Format::Argb8888)?;
+        pixels.fill(0);
+
+        let wl_surface = app.compositor_state.create_surface(qh);
+        let layer = app.layer_state.create_layer_surface(
+            qh,
+            wl_surface,
+            Layer::Overlay,
+            Some("avy-edge-swipe"),
+            output,
+        );
+
+        let thickness = options.thickness.max(1);
+        let (width, height) = edge.strip_size(thickness);
+
+        layer.set_anchor(edge.anchor());
+        layer.set_size(width, height);
+        // Right at the edge, whatever else reserves space along it.
+        layer.set_exclusive_zone(-1);
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+
+        let viewport = app.viewporter.get_viewport(layer.wl_surface(), qh);
+        debug::objects::created(
+            &empty_region.wl_region().id(),
+            Some(&layer.wl_surface().id()),
+        );
+
+        let shared = Arc::new(EdgeSwipeShared {
+            edge,
+            options: Mutex::new(options),
+            swipe: Mutex::new(None),
+            on_event: Mutex::new(None),
+        });
+
+        let strip = EdgeStrip {
+            layer,
+            viewport,
+            size: Arc::new(RwLock::new(Size::new((width, height)))),
+            buffer,
+            attached: false,
+            _pool: pool,
+            empty_region,
+            shared: shared.clone(),
+        };
+
+        strip.set_touch_input(app.capabilities().touch);
+
+        let surface = app.register(strip, None, event_queue).id().clone();
+
+        Ok(Self {
+            shared,
+            surface,
+            proxy: app.proxy(),
+        })
+    }
+
+    pub fn edge(&self) -> SwipeEdge {
+        self.shared.edge
+    }
+
+    ///
+    /// The strip along the edge.
+    ///
+    pub fn surface(&self) -> &ObjectId {
+        &self.surface
+    }
+
+    pub fn options(&self) -> EdgeSwipeOptions {
+        *self.shared.options.lock().unwrap()
+    }
+
+    ///
+    /// Takes effect from the next swipe, apart from [EdgeSwipeOptions::thickness].
+    ///
+    pub fn set_options(&self, options: EdgeSwipeOptions) {
+        *self.shared.options.lock().unwrap() = options;
+    }
+
+    ///
+    /// Whether a swipe is under way.
+    ///
+    pub fn is_swiping(&self) -> bool {
+        self.shared.swipe().is_some()
+    }
+
+    ///
+    /// Call `on_event` (on the event loop) as swipes progress, and when they end.
+    ///
+    pub fn on_event(&self, on_event: impl FnMut(EdgeSwipeEvent) + Send + 'static) {
+        self.shared
+            .on_event
+            .lock()
+            .unwrap()
+            .replace(Box::new(on_event));
+    }
+}
+
+impl Drop for EdgeSwipeDetector {
+    fn drop(&mut self) {
+        let surface = self.surface.clone();
+
+        // Nothing to clean up once the client is gone.
+        let _ = self.proxy.invoke(move |app| app.destroy_surface(&surface));
+    }
+}
+
+///
+/// A callback for [EdgeSwipeDetector::on_event] sliding `layer` in along with
+/// the swipe: its margin follows the swipe from `hidden` to `shown`, then
+/// settles on either (over `settle`) once the swipe ends.
+///
+pub fn reveal_layer(
+    layer: AvyLayerController,
+    hidden: Insets,
+    shown: Insets,
+    settle: Duration,
+) -> impl FnMut(EdgeSwipeEvent) + Send + 'static {
+    move |event| match event {
+        EdgeSwipeEvent::Progress(progress) => {
+            layer.set_margin(hidden.lerp(&shown, progress as f64));
+        }
+        EdgeSwipeEvent::Completed => {
+            layer.animate_margin(shown, settle, Easing::EaseOut, || {});
+        }
+        EdgeSwipeEvent::Cancelled => {
+            layer.animate_margin(hidden, settle, Easing::EaseOut, || {});
+        }
+    }
+}
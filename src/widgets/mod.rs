@@ -0,0 +1,29 @@
+//!
+//! Ready-made building blocks for common shell components.
+//!
+
+#[cfg(feature = "controls")]
+pub mod button;
+#[cfg(feature = "controls")]
+pub mod control;
+pub mod edge_swipe;
+pub mod overlay;
+#[cfg(feature = "controls")]
+pub mod slider;
+pub mod text_field;
+#[cfg(feature = "controls")]
+pub mod toggle;
+pub mod wallpaper;
+
+#[cfg(feature = "controls")]
+pub use button::Button;
+#[cfg(feature = "controls")]
+pub use control::{Control, Interaction};
+pub use edge_swipe::{EdgeSwipeDetector, EdgeSwipeEvent, EdgeSwipeOptions, SwipeEdge};
+pub use overlay::{DismissReason, OverlayController, OverlayOptions, OverlayState};
+#[cfg(feature = "controls")]
+pub use slider::Slider;
+pub use text_field::{TextField, TextFieldAction};
+#[cfg(feature = "controls")]
+pub use toggle::Toggle;
+pub use wallpaper::{FitMode, ImageSource, Wallpaper};
@@ -0,0 +1,762 @@
+//!
+//! Launcher-style overlays: hidden until summoned, then holding the
+//! keyboard until dismissed (with Escape, by losing focus, or by the app).
+//!
+//! Showing one takes several steps which are easy to get out of step (and
+//! leave the keyboard stuck on a hidden surface): mapping the surface,
+//! asking the compositor for exclusive keyboard interactivity, routing keys
+//! to it within the app, and fading it in. [OverlayController] does them
+//! all, and undoes them in turn when dismissing.
+//!
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::{
+    seat::keyboard::{KeyEvent, Keysym},
+    shell::wlr_layer::KeyboardInteractivity,
+};
+use wayland_backend::client::ObjectId;
+
+use crate::{
+    app::AvySurfaceHandle,
+    graphics::GraphicsBackend,
+    proxy::AvyProxy,
+    timer::TimerAction,
+    util::{
+        animation::{Animation, Easing, Lerp},
+        SharedClock,
+    },
+    wayland::surface::layer::AvyLayerController,
+    AvyClient,
+};
+
+///
+/// How often the surface is redrawn whilst fading.
+///
+const FADE_TICK: Duration = Duration::from_millis(16);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DismissReason {
+    /// [OverlayController::dismiss] was called.
+    Requested,
+    /// Escape was pressed, see [OverlayOptions::dismiss_on_escape].
+    Escape,
+    /// The surface lost keyboard focus, see [OverlayOptions::dismiss_on_focus_loss].
+    FocusLost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayState {
+    Hidden,
+    /// Fading in.
+    Showing,
+    Shown,
+    /// Fading out, before being hidden.
+    Dismissing(DismissReason),
+}
+
+impl OverlayState {
+    ///
+    /// Whether the overlay is on its way in, or already there.
+    ///
+    pub fn is_shown(&self) -> bool {
+        matches!(self, Self::Showing | Self::Shown)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OverlayOptions {
+    /// How long fading in (or out) takes.
+    pub fade: Duration,
+    pub dismiss_on_escape: bool,
+    pub dismiss_on_focus_loss: bool,
+}
+
+impl Default for OverlayOptions {
+    fn default() -> Self {
+        Self {
+            fade: Duration::from_millis(150),
+            dismiss_on_escape: true,
+            dismiss_on_focus_loss: true,
+        }
+    }
+}
+
+///
+/// What an overlay needs from its surface's handle, whatever its backend.
+///
+trait OverlayTarget: Send {
+    fn map(&self);
+    fn unmap(&self);
+    fn mark_dirty(&self);
+}
+
+impl<G: GraphicsBackend> OverlayTarget for AvySurfaceHandle<G>
+where
+    G::Error: 'static,
+{
+    fn map(&self) {
+        if self.is_suspended() {
+            if let Err(err) = self.resume() {
+                log::warn!("Could not map overlay: {err}");
+            }
+        }
+    }
+
+    fn unmap(&self) {
+        if let Err(err) = self.suspend(true) {
+            log::warn!("Could not unmap overlay: {err}");
+        }
+    }
+
+    fn mark_dirty(&self) {
+        AvySurfaceHandle::mark_dirty(self);
+    }
+}
+
+struct Fade {
+    from: f32,
+    to: f32,
+    animation: Animation,
+}
+
+pub(crate) struct OverlayInner {
+    surface: ObjectId,
+    target: Box<dyn OverlayTarget>,
+    layer: Option<AvyLayerController>,
+    options: OverlayOptions,
+    state: OverlayState,
+    fade: Option<Fade>,
+    clock: SharedClock,
+    /// Bumped on every show and dismiss, so that the timer
+    /// driving an interrupted fade knows to stop.
+    generation: u64,
+}
+
+impl OverlayInner {
+    fn opacity(&self, now: Instant) -> f32 {
+        match &self.fade {
+            Some(fade) => fade.from.lerp(&fade.to, fade.animation.progress(now)),
+            None if self.state == OverlayState::Shown => 1.0,
+            None => 0.0,
+        }
+    }
+
+    ///
+    /// Start fading towards `to`, from wherever the current fade got to.
+    ///
+    fn fade_to(&mut self, to: f32, easing: Easing) -> u64 {
+        let now = self.clock.now();
+        let from = self.opacity(now);
+        let duration = self.options.fade.mul_f32((to - from).abs());
+
+        self.fade.replace(Fade {
+            from,
+            to,
+            animation: Animation::starting_at(now, duration, easing),
+        });
+
+        self.generation += 1;
+        self.generation
+    }
+}
+
+type StateCallback = Box<dyn FnMut(OverlayState) + Send>;
+
+pub(crate) struct OverlayShared {
+    inner: Mutex<OverlayInner>,
+    on_change: Mutex<Option<StateCallback>>,
+}
+
+///
+/// The client's overlays, by surface, for dismissing them on Escape or focus loss.
+///
+pub(crate) type Overlays = HashMap<ObjectId, Weak<OverlayShared>>;
+
+///
+/// Shows and dismisses a surface as a launcher-style overlay.
+///
+/// The surface starts out hidden. Render callbacks should draw it
+/// at [OverlayController::opacity], which animates whilst fading.
+///
+/// Can be freely cloned and sent to other threads.
+///
+#[derive(Clone)]
+pub struct OverlayController {
+    shared: Arc<OverlayShared>,
+    proxy: AvyProxy,
+}
+
+impl OverlayController {
+    pub fn new<G: GraphicsBackend + 'static>(
+        app: &mut AvyClient,
+        handle: &AvySurfaceHandle<G>,
+        options: OverlayOptions,
+    ) -> Self
+    where
+        G::Error: 'static,
+    {
+        let surface = handle.id();
+        let layer = app.layer_controller(&surface);
+
+        if let Some(layer) = &layer {
+            // Only on-demand interactivity can be unsupported.
+            let _ = layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        }
+
+        OverlayTarget::unmap(handle);
+
+        let shared = Arc::new(OverlayShared {
+            inner: Mutex::new(OverlayInner {
+                surface: surface.clone(),
+                target: Box::new(handle.clone()),
+                layer,
+                options,
+                state: OverlayState::Hidden,
+                fade: None,
+                clock: app.clock().clone(),
+                generation: 0,
+            }),
+            on_change: Mutex::new(None),
+        });
+
+        app.overlays.insert(surface, Arc::downgrade(&shared));
+
+        Self {
+            shared,
+            proxy: app.proxy(),
+        }
+    }
+
+    fn inner(&self) -> MutexGuard<'_, OverlayInner> {
+        self.shared.inner.lock().unwrap()
+    }
+
+    pub fn state(&self) -> OverlayState {
+        self.inner().state
+    }
+
+    pub fn options(&self) -> OverlayOptions {
+        self.inner().options
+    }
+
+    pub fn set_options(&self, options: OverlayOptions) {
+        self.inner().options = options;
+    }
+
+    ///
+    /// The opacity to draw the overlay at, now.
+    ///
+    pub fn opacity(&self) -> f32 {
+        let inner = self.inner();
+        inner.opacity(inner.clock.now())
+    }
+
+    ///
+    /// Call `on_change` (on the event loop, or on the thread
+    /// calling show or dismiss) whenever the state changes.
+    ///
+    pub fn on_change(&self, on_change: impl FnMut(OverlayState) + Send + 'static) {
+        self.shared
+            .on_change
+            .lock()
+            .unwrap()
+            .replace(Box::new(on_change));
+    }
+
+    ///
+    /// Map the surface, give it the keyboard and fade it in.
+    ///
+    pub fn show(&self) {
+        let mut inner = self.inner();
+        if inner.state.is_shown() {
+            return;
+        }
+
+        inner.target.map();
+
+        if let Some(layer) = &inner.layer {
+            // Only on-demand interactivity can be unsupported.
+            let _ = layer.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+        }
+
+        let generation = inner.fade_to(1.0, Easing::EaseOut);
+        inner.state = OverlayState::Showing;
+        let surface = inner.surface.clone();
+        drop(inner);
+
+        let overlay = self.clone();
+        let invoked = self.proxy.invoke(move |app| {
+            app.set_exclusive_keyboard(Some(surface));
+            app.drive_overlay_fade(overlay, generation);
+        });
+
+        if invoked.is_err() {
+            log::warn!("Could not show overlay: the client is gone.");
+        }
+
+        self.notify(OverlayState::Showing);
+    }
+
+    ///
+    /// Give the keyboard back, fade the overlay out and unmap it.
+    ///
+    pub fn dismiss(&self) {
+        self.dismiss_with(DismissReason::Requested);
+    }
+
+    pub fn toggle(&self) {
+        if self.state().is_shown() {
+            self.dismiss();
+        } else {
+            self.show();
+        }
+    }
+
+    fn dismiss_with(&self, reason: DismissReason) {
+        let mut inner = self.inner();
+        if !inner.state.is_shown() {
+            return;
+        }
+
+        if let Some(layer) = &inner.layer {
+            // Only on-demand interactivity can be unsupported.
+            let _ = layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        }
+
+        // From the opacity it's shown at, before the state says otherwise.
+        let generation = inner.fade_to(0.0, Easing::EaseIn);
+        let state = OverlayState::Dismissing(reason);
+        inner.state = state;
+        let surface = inner.surface.clone();
+        drop(inner);
+
+        let overlay = self.clone();
+        let invoked = self.proxy.invoke(move |app| {
+            if app.exclusive_keyboard.as_ref() == Some(&surface) {
+                app.set_exclusive_keyboard(None);
+            }
+
+            app.drive_overlay_fade(overlay, generation);
+        });
+
+        if invoked.is_err() {
+            log::warn!("Could not dismiss overlay: the client is gone.");
+        }
+
+        self.notify(state);
+    }
+
+    ///
+    /// Redraw for the fade, then settle once it's over.
+    ///
+    /// Returns whether the fade is still going.
+    ///
+    fn tick_fade(&self, generation: u64) -> bool {
+        let mut inner = self.inner();
+
+        // Superseded by a later show or dismiss.
+        if inner.generation != generation {
+            return false;
+        }
+
+        inner.target.mark_dirty();
+
+        let finished = inner
+            .fade
+            .as_ref()
+            .map_or(true, |fade| fade.animation.is_finished(inner.clock.now()));
+
+        if !finished {
+            return true;
+        }
+
+        inner.fade.take();
+
+        let state = match inner.state {
+            OverlayState::Showing => OverlayState::Shown,
+            OverlayState::Dismissing(_) => {
+                inner.target.unmap();
+                OverlayState::Hidden
+            }
+            _ => return false,
+        };
+
+        inner.state = state;
+        drop(inner);
+
+        self.notify(state);
+        false
+    }
+
+    fn notify(&self, state: OverlayState) {
+        // Not called with the lock held, so that the
+        // callback may show or dismiss the overlay again.
+        let Some(mut on_change) = self.shared.on_change.lock().unwrap().take() else {
+            return;
+        };
+
+        on_change(state);
+
+        let mut slot = self.shared.on_change.lock().unwrap();
+        if slot.is_none() {
+            slot.replace(on_change);
+        }
+    }
+}
+
+impl AvyClient {
+    fn drive_overlay_fade(&mut self, overlay: OverlayController, generation: u64) {
+        if !overlay.tick_fade(generation) {
+            return;
+        }
+
+        let ticking = overlay.clone();
+        let timer = self.add_timer(FADE_TICK, move |_| {
+            if ticking.tick_fade(generation) {
+                TimerAction::Repeat(FADE_TICK)
+            } else {
+                TimerAction::Drop
+            }
+        });
+
+        if let Err(err) = timer {
+            log::warn!("Could not animate overlay: {err}");
+
+            // Skip the fade rather than getting stuck half-way.
+            let mut inner = overlay.inner();
+            let clock = inner.clock.clone();
+            if let Some(fade) = inner.fade.as_mut() {
+                fade.animation = Animation::new(&clock, Duration::ZERO, Easing::Linear);
+            }
+            drop(inner);
+
+            overlay.tick_fade(generation);
+        }
+    }
+
+    fn overlay(&mut self, surface: &ObjectId) -> Option<OverlayController> {
+        let Some(shared) = self.overlays.get(surface)?.upgrade() else {
+            self.overlays.remove(surface);
+            return None;
+        };
+
+        Some(OverlayController {
+            shared,
+            proxy: self.proxy(),
+        })
+    }
+
+    ///
+    /// Dismiss an overlay when Escape is pressed in it.
+    ///
+    /// Returns whether the key was used up doing so.
+    ///
+    pub(crate) fn overlay_key_pressed(&mut self, surface: &ObjectId, event: &KeyEvent) -> bool {
+        if event.keysym != Keysym::Escape {
+            return false;
+        }
+
+        let Some(overlay) = self.overlay(surface) else {
+            return false;
+        };
+
+        if !overlay.state().is_shown() || !overlay.options().dismiss_on_escape {
+            return false;
+        }
+
+        overlay.dismiss_with(DismissReason::Escape);
+        true
+    }
+
+    pub(crate) fn overlay_focus_lost(&mut self, surface: &ObjectId) {
+        let Some(overlay) = self.overlay(surface) else {
+            return;
+        };
+
+        if overlay.state().is_shown() && overlay.options().dismiss_on_focus_loss {
+            overlay.dismiss_with(DismissReason::FocusLost);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{proxy::ProxyQueue, util::TestClock};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Call {
+        Map,
+        Unmap,
+        Dirty,
+    }
+
+    #[derive(Clone, Default)]
+    struct Target(Arc<Mutex<Vec<Call>>>);
+
+    impl Target {
+        fn take(&self) -> Vec<Call> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl OverlayTarget for Target {
+        fn map(&self) {
+            self.0.lock().unwrap().push(Call::Map);
+        }
+
+        fn unmap(&self) {
+            self.0.lock().unwrap().push(Call::Unmap);
+        }
+
+        fn mark_dirty(&self) {
+            self.0.lock().unwrap().push(Call::Dirty);
+        }
+    }
+
+    // Fades are scaled (by how far they have to go) through an f32, so
+    // may run a hair over: the tests tick past the end to settle them.
+    const FADE: Duration = Duration::from_millis(150);
+
+    struct Fixture {
+        overlay: OverlayController,
+        target: Target,
+        time: TestClock,
+        changes: Arc<Mutex<Vec<OverlayState>>>,
+        // Holds on to the receiving end, so that invocations can be queued.
+        _queue: ProxyQueue,
+    }
+
+    impl Fixture {
+        ///
+        /// A hidden overlay, as [OverlayController::new] leaves it.
+        ///
+        fn new() -> Self {
+            let time = TestClock::new();
+            let target = Target::default();
+            let queue = ProxyQueue::new();
+
+            let overlay = OverlayController {
+                shared: Arc::new(OverlayShared {
+                    inner: Mutex::new(OverlayInner {
+                        surface: ObjectId::null(),
+                        target: Box::new(target.clone()),
+                        layer: None,
+                        options: OverlayOptions {
+                            fade: FADE,
+                            ..Default::default()
+                        },
+                        state: OverlayState::Hidden,
+                        fade: None,
+                        clock: SharedClock::new(time.clone()),
+                        generation: 0,
+                    }),
+                    on_change: Mutex::new(None),
+                }),
+                proxy: queue.proxy.clone(),
+            };
+
+            let changes = Arc::new(Mutex::new(Vec::new()));
+            let recorded = changes.clone();
+            overlay.on_change(move |state| recorded.lock().unwrap().push(state));
+
+            Self {
+                overlay,
+                target,
+                time,
+                changes,
+                _queue: queue,
+            }
+        }
+
+        fn generation(&self) -> u64 {
+            self.overlay.inner().generation
+        }
+
+        ///
+        /// Let the fade run for `by`, then tick it as the timer would.
+        ///
+        fn tick(&self, by: Duration) -> bool {
+            self.time.advance(by);
+            self.overlay.tick_fade(self.generation())
+        }
+
+        fn changes(&self) -> Vec<OverlayState> {
+            std::mem::take(&mut self.changes.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn showing_maps_the_surface_then_fades_it_in() {
+        let fixture = Fixture::new();
+        let overlay = &fixture.overlay;
+        assert_eq!(overlay.state(), OverlayState::Hidden);
+        assert_eq!(overlay.opacity(), 0.0);
+
+        overlay.show();
+        assert_eq!(fixture.target.take(), [Call::Map]);
+        assert_eq!(overlay.state(), OverlayState::Showing);
+        assert_eq!(overlay.opacity(), 0.0);
+
+        assert!(fixture.tick(FADE / 2));
+        let opacity = overlay.opacity();
+        assert!(opacity > 0.0 && opacity < 1.0, "{opacity}");
+        assert_eq!(fixture.target.take(), [Call::Dirty]);
+
+        assert!(!fixture.tick(FADE));
+        assert_eq!(overlay.state(), OverlayState::Shown);
+        assert_eq!(overlay.opacity(), 1.0);
+        assert_eq!(fixture.target.take(), [Call::Dirty]);
+        assert_eq!(
+            fixture.changes(),
+            [OverlayState::Showing, OverlayState::Shown]
+        );
+
+        // Already there: nothing to map, nor fade.
+        let generation = fixture.generation();
+        overlay.show();
+        assert_eq!(fixture.generation(), generation);
+        assert!(fixture.target.take().is_empty());
+        assert!(fixture.changes().is_empty());
+    }
+
+    #[test]
+    fn dismissing_fades_out_then_unmaps() {
+        let fixture = Fixture::new();
+        let overlay = &fixture.overlay;
+        overlay.show();
+        fixture.tick(FADE * 2);
+        fixture.target.take();
+        fixture.changes();
+
+        overlay.dismiss();
+        assert_eq!(
+            overlay.state(),
+            OverlayState::Dismissing(DismissReason::Requested)
+        );
+        assert_eq!(overlay.opacity(), 1.0);
+
+        // Still drawn whilst fading out.
+        assert!(fixture.tick(FADE / 2));
+        assert_eq!(fixture.target.take(), [Call::Dirty]);
+
+        assert!(!fixture.tick(FADE));
+        assert_eq!(overlay.state(), OverlayState::Hidden);
+        assert_eq!(overlay.opacity(), 0.0);
+        assert_eq!(fixture.target.take(), [Call::Dirty, Call::Unmap]);
+        assert_eq!(
+            fixture.changes(),
+            [
+                OverlayState::Dismissing(DismissReason::Requested),
+                OverlayState::Hidden
+            ]
+        );
+
+        // Already gone.
+        overlay.dismiss();
+        assert_eq!(overlay.state(), OverlayState::Hidden);
+        assert!(fixture.target.take().is_empty());
+        assert!(fixture.changes().is_empty());
+    }
+
+    #[test]
+    fn escape_and_focus_loss_are_told_apart() {
+        let fixture = Fixture::new();
+        let overlay = &fixture.overlay;
+
+        overlay.show();
+        overlay.dismiss_with(DismissReason::Escape);
+        assert_eq!(
+            overlay.state(),
+            OverlayState::Dismissing(DismissReason::Escape)
+        );
+
+        overlay.show();
+        overlay.dismiss_with(DismissReason::FocusLost);
+        assert_eq!(
+            overlay.state(),
+            OverlayState::Dismissing(DismissReason::FocusLost)
+        );
+    }
+
+    #[test]
+    fn a_later_show_or_dismiss_takes_over_the_fade() {
+        let fixture = Fixture::new();
+        let overlay = &fixture.overlay;
+
+        overlay.show();
+        let showing = fixture.generation();
+        fixture.tick(FADE / 3);
+        let opacity = overlay.opacity();
+
+        overlay.toggle();
+        let dismissing = fixture.generation();
+        assert_ne!(showing, dismissing);
+        assert_eq!(
+            overlay.state(),
+            OverlayState::Dismissing(DismissReason::Requested)
+        );
+
+        // Fades out from wherever fading in got to, not from fully shown.
+        assert_eq!(overlay.opacity(), opacity);
+        fixture.target.take();
+
+        // The timer driving the fade in stops, and leaves the surface alone.
+        assert!(!overlay.tick_fade(showing));
+        assert!(fixture.target.take().is_empty());
+
+        // Less to fade, so it takes less time.
+        fixture.time.advance(FADE.mul_f32(opacity));
+        assert!(!overlay.tick_fade(dismissing));
+        assert_eq!(overlay.state(), OverlayState::Hidden);
+        assert_eq!(fixture.target.take(), [Call::Dirty, Call::Unmap]);
+
+        overlay.toggle();
+        assert_eq!(overlay.state(), OverlayState::Showing);
+        assert_eq!(fixture.target.take(), [Call::Map]);
+        assert_eq!(
+            fixture.changes(),
+            [
+                OverlayState::Showing,
+                OverlayState::Dismissing(DismissReason::Requested),
+                OverlayState::Hidden,
+                OverlayState::Showing
+            ]
+        );
+    }
+
+    #[test]
+    fn on_change_may_show_the_overlay_again() {
+        let fixture = Fixture::new();
+        let overlay = fixture.overlay.clone();
+
+        let reopened = Arc::new(Mutex::new(false));
+        let reopen = reopened.clone();
+        let again = overlay.clone();
+        overlay.on_change(move |state| {
+            let mut reopen = reopen.lock().unwrap();
+            if state == OverlayState::Hidden && !*reopen {
+                *reopen = true;
+                again.show();
+            }
+        });
+
+        overlay.show();
+        fixture.tick(FADE * 2);
+        overlay.dismiss();
+        fixture.tick(FADE * 2);
+
+        assert!(*reopened.lock().unwrap());
+        assert_eq!(overlay.state(), OverlayState::Showing);
+        assert_eq!(
+            fixture.target.take(),
+            [Call::Map, Call::Dirty, Call::Dirty, Call::Unmap, Call::Map]
+        );
+    }
+}
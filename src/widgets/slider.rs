@@ -0,0 +1,218 @@
+//!
+//! A horizontal slider picking a value from `0.0` to `1.0`, see [Slider].
+//!
+
+use skia_safe::{Canvas, Paint, PaintStyle, RRect, Rect};
+use smithay_client_toolkit::seat::keyboard::Keysym;
+
+use crate::{
+    graphics::{RenderContext, SemanticRole},
+    util::{DirtyFlag, SurfacePoint},
+};
+
+use super::control::{faded, mix, Control, Interaction};
+
+///
+/// Thickness of the track, in logical pixels.
+///
+const TRACK_THICKNESS: f32 = 4.0;
+
+///
+/// Radius of the thumb at rest, in logical pixels. It grows when hovered or pressed.
+///
+const THUMB_RADIUS: f32 = 8.0;
+
+///
+/// How far the arrow keys move the slider, by default.
+///
+pub const DEFAULT_STEP: f32 = 0.05;
+
+type ChangeCallback = Box<dyn FnMut(f32) + Send>;
+
+///
+/// A slider, dragged (or tapped) along its track, or moved with the arrow,
+/// home and end keys whilst focused. The track runs across the middle of
+/// its bounds, inset by the thumb's radius on both ends.
+///
+pub struct Slider {
+    value: f32,
+    step: f32,
+    /// Whether the track ran right to left when last drawn.
+    rtl: bool,
+    interaction: Interaction,
+    on_change: Option<ChangeCallback>,
+}
+
+impl Slider {
+    pub fn new(value: f32) -> Self {
+        Self {
+            value: value.clamp(0.0, 1.0),
+            step: DEFAULT_STEP,
+            rtl: false,
+            interaction: Interaction::default(),
+            on_change: None,
+        }
+    }
+
+    ///
+    /// How far each arrow key press moves the slider.
+    ///
+    pub fn with_step(mut self, step: f32) -> Self {
+        self.step = step.clamp(0.0, 1.0);
+        self
+    }
+
+    ///
+    /// Mark `dirty` whenever the slider needs redrawing.
+    ///
+    pub fn track_dirty(mut self, dirty: DirtyFlag) -> Self {
+        self.interaction.set_dirty(dirty);
+        self
+    }
+
+    ///
+    /// Call `on_change` with the new value whenever the user moves the slider.
+    ///
+    pub fn on_change(mut self, on_change: impl FnMut(f32) + Send + 'static) -> Self {
+        self.on_change.replace(Box::new(on_change));
+        self
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    ///
+    /// Move the slider to `value`, without calling [Slider::on_change].
+    ///
+    pub fn set_value(&mut self, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+        if self.value != value {
+            self.value = value;
+            self.interaction.mark_dirty();
+        }
+    }
+
+    ///
+    /// The track within `bounds`: from where `0.0` sits to where `1.0` does.
+    ///
+    fn track(bounds: Rect) -> (f32, f32) {
+        let inset = THUMB_RADIUS.min(bounds.width() / 2.0);
+        (bounds.left + inset, bounds.right - inset)
+    }
+
+    ///
+    /// Move to `value`, telling [Slider::on_change] if it changed.
+    ///
+    fn change(&mut self, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+        if self.value == value {
+            return;
+        }
+
+        self.set_value(value);
+        if let Some(on_change) = self.on_change.as_mut() {
+            on_change(value);
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &Canvas, context: &RenderContext, bounds: impl AsRef<Rect>) {
+        let bounds = *bounds.as_ref();
+        let now = context.now();
+        let interaction = &self.interaction;
+        let opacity = interaction.opacity();
+        self.rtl = context.is_rtl();
+
+        let accent = context.resolve_color(SemanticRole::Accent);
+        let border = context.resolve_color(SemanticRole::Border);
+        let surface = context.resolve_color(SemanticRole::Surface);
+
+        let (start, end) = Self::track(bounds);
+        let position = match self.rtl {
+            true => end - (end - start) * self.value,
+            false => start + (end - start) * self.value,
+        };
+
+        let center_y = bounds.center_y();
+        let half = TRACK_THICKNESS / 2.0;
+        let track = |left: f32, right: f32| {
+            let rect = Rect::from_ltrb(left, center_y - half, right, center_y + half);
+            RRect::new_rect_xy(rect, half, half)
+        };
+
+        let mut paint = Paint::new(faded(border, opacity), None);
+        paint.set_anti_alias(true);
+        canvas.draw_rrect(track(start, end), &paint);
+
+        // The filled part runs from the start of the line.
+        let filled = match self.rtl {
+            true => track(position, end),
+            false => track(start, position),
+        };
+        paint.set_color4f(faded(accent, opacity), None);
+        canvas.draw_rrect(filled, &paint);
+
+        let grow = interaction
+            .hover_amount(now)
+            .max(interaction.press_amount(now));
+        let radius = THUMB_RADIUS * (1.0 + 0.25 * grow);
+        paint.set_color4f(faded(mix(surface, accent, 0.1 * grow), opacity), None);
+        canvas.draw_circle((position, center_y), radius, &paint);
+
+        let ring_color = match interaction.is_focused() {
+            true => accent,
+            false => border,
+        };
+        let mut ring = Paint::new(faded(ring_color, opacity), None);
+        ring.set_anti_alias(true);
+        ring.set_style(PaintStyle::Stroke);
+        ring.set_stroke_width(if interaction.is_focused() { 2.0 } else { 1.0 });
+        canvas.draw_circle((position, center_y), radius, &ring);
+
+        self.interaction.drawn(bounds, context);
+    }
+}
+
+impl Control for Slider {
+    fn interaction(&self) -> &Interaction {
+        &self.interaction
+    }
+
+    fn interaction_mut(&mut self) -> &mut Interaction {
+        &mut self.interaction
+    }
+
+    ///
+    /// The value follows presses as they move, so there's nothing left to do on release.
+    ///
+    fn activate(&mut self) {}
+
+    fn drag(&mut self, point: SurfacePoint) {
+        let (start, end) = Self::track(self.interaction.bounds());
+        if end <= start {
+            return;
+        }
+
+        let value = (point.x as f32 - start) / (end - start);
+        self.change(if self.rtl { 1.0 - value } else { value });
+    }
+
+    fn key(&mut self, keysym: Keysym) -> bool {
+        // Left and right follow the track, which is flipped for right-to-left locales.
+        let (back, forward) = match self.rtl {
+            true => (Keysym::Right, Keysym::Left),
+            false => (Keysym::Left, Keysym::Right),
+        };
+
+        let value = match keysym {
+            keysym if keysym == back || keysym == Keysym::Down => self.value - self.step,
+            keysym if keysym == forward || keysym == Keysym::Up => self.value + self.step,
+            Keysym::Home => 0.0,
+            Keysym::End => 1.0,
+            _ => return false,
+        };
+
+        self.change(value);
+        true
+    }
+}
@@ -0,0 +1,924 @@
+//!
+//! A single line text field, e.g. for a launcher's search box: editing with
+//! the keyboard (by grapheme, or by word with control held), selection,
+//! undo, and composition through an input method, see [TextField].
+//!
+//! Like the controls, a text field is a plain struct: draw it from a render
+//! callback, and hand it the surface's key events whilst it's focused. It
+//! doesn't reach the clipboard itself: copying and pasting are handed back
+//! as [TextFieldAction]s, for [crate::AvyClient::set_primary] and
+//! [crate::AvyClient::read_primary_text] (or anything else) to carry out.
+//!
+
+use std::ops::Range;
+
+use skia_safe::{Canvas, Font, Paint, Rect};
+use smithay_client_toolkit::seat::keyboard::{KeyEvent, Keysym, Modifiers};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    app::AvySurfaceHandle,
+    graphics::{RenderContext, SemanticRole},
+    util::DirtyFlag,
+};
+
+///
+/// Edits kept for [TextField::undo], at most.
+///
+pub const MAX_UNDO: usize = 100;
+
+///
+/// Width of the caret, in logical pixels.
+///
+const CARET_WIDTH: f32 = 1.5;
+
+///
+/// Space kept between the caret and the ends of the field whilst scrolling, in logical pixels.
+///
+const SCROLL_MARGIN: f32 = 8.0;
+
+type ChangeCallback = Box<dyn FnMut(&str) + Send>;
+type ReleaseFocus = Box<dyn FnMut() + Send>;
+
+///
+/// What a key press asks of whoever owns the field, see [TextField::key_press].
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextFieldAction {
+    /// Not a key for the field, e.g. for the surface to handle instead.
+    Ignored,
+    /// The field handled it (and marked itself dirty, if anything changed).
+    Handled,
+    /// Put this text (cut or copied) on the clipboard.
+    Copy(String),
+    /// Read the clipboard, and hand its text to [TextField::paste].
+    Paste,
+    /// Enter was pressed.
+    Submit,
+}
+
+///
+/// Text being composed by an input method, shown at the caret until it's committed.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Preedit {
+    text: String,
+    /// The input method's cursor within `text`, in bytes, if it shows one.
+    cursor: Option<Range<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snapshot {
+    text: String,
+    caret: usize,
+    anchor: usize,
+}
+
+///
+/// An editable line of text, see the [module docs](self).
+///
+/// Positions are byte offsets into the text, always on grapheme cluster
+/// boundaries (so that e.g. an emoji with a skin tone is deleted whole).
+///
+pub struct TextField {
+    text: String,
+    /// Where the caret is.
+    caret: usize,
+    /// The other end of the selection: the same as `caret` when nothing is selected.
+    anchor: usize,
+    preedit: Preedit,
+
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Whether the last edit was typing, which the next can be undone along with.
+    typing: bool,
+
+    font: Font,
+    focused: bool,
+    /// How far the text is scrolled left to keep the caret in view.
+    scroll: f32,
+    /// Where the field was last drawn.
+    bounds: Rect,
+    dirty: Option<DirtyFlag>,
+    on_change: Option<ChangeCallback>,
+    release_focus: Option<ReleaseFocus>,
+}
+
+impl TextField {
+    pub fn new(font: &Font) -> Self {
+        Self {
+            text: String::new(),
+            caret: 0,
+            anchor: 0,
+            preedit: Preedit::default(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            typing: false,
+            font: font.clone(),
+            focused: false,
+            scroll: 0.0,
+            bounds: Rect::new_empty(),
+            dirty: None,
+            on_change: None,
+            release_focus: None,
+        }
+    }
+
+    ///
+    /// Give `surface`'s keyboard focus back (see
+    /// [AvySurfaceHandle::release_keyboard_focus]) whenever the field loses
+    /// focus, e.g. to Escape, so that an on-demand layer (a bar's search field)
+    /// doesn't hold on to it once done.
+    ///
+    pub fn release_focus_of<G: Send + 'static>(mut self, surface: &AvySurfaceHandle<G>) -> Self {
+        let surface = surface.clone();
+        self.release_focus.replace(Box::new(move || {
+            if let Err(err) = surface.release_keyboard_focus() {
+                log::debug!("Not releasing keyboard focus: {err}");
+            }
+        }));
+        self
+    }
+
+    ///
+    /// Mark `dirty` whenever the field needs redrawing.
+    ///
+    pub fn track_dirty(mut self, dirty: DirtyFlag) -> Self {
+        self.dirty.replace(dirty);
+        self
+    }
+
+    ///
+    /// Call `on_change` with the new text whenever the user edits it.
+    ///
+    pub fn on_change(mut self, on_change: impl FnMut(&str) + Send + 'static) -> Self {
+        self.on_change.replace(Box::new(on_change));
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    ///
+    /// Replace the text, without calling [TextField::on_change], and
+    /// forgetting what could be undone. The caret goes to the end.
+    ///
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.caret = self.text.len();
+        self.anchor = self.caret;
+        self.preedit = Preedit::default();
+        self.undo.clear();
+        self.redo.clear();
+        self.typing = false;
+        self.mark_dirty();
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    ///
+    /// The selected range of the text, empty if nothing is.
+    ///
+    pub fn selection(&self) -> Range<usize> {
+        self.caret.min(self.anchor)..self.caret.max(self.anchor)
+    }
+
+    pub fn selected_text(&self) -> &str {
+        &self.text[self.selection()]
+    }
+
+    ///
+    /// Select from `anchor` to `caret`, each moved back to a grapheme boundary if need be.
+    ///
+    pub fn select(&mut self, anchor: usize, caret: usize) {
+        self.anchor = self.boundary_at(anchor);
+        self.caret = self.boundary_at(caret);
+        self.typing = false;
+        self.mark_dirty();
+    }
+
+    pub fn select_all(&mut self) {
+        self.select(0, self.text.len());
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    ///
+    /// Show or hide the caret, e.g. as the surface gains or loses keyboard focus.
+    ///
+    /// Losing it gives the surface's focus back, see [TextField::release_focus_of].
+    ///
+    pub fn set_focused(&mut self, focused: bool) {
+        if self.focused == focused {
+            return;
+        }
+
+        self.focused = focused;
+        self.mark_dirty();
+
+        if !focused {
+            if let Some(release_focus) = self.release_focus.as_mut() {
+                release_focus();
+            }
+        }
+    }
+
+    ///
+    /// Handle a key press (or repeat), with `modifiers` as they are.
+    ///
+    pub fn key_press(&mut self, event: &KeyEvent, modifiers: Modifiers) -> TextFieldAction {
+        let extend = modifiers.shift;
+        let by_word = modifiers.ctrl;
+
+        match event.keysym {
+            Keysym::Left | Keysym::KP_Left => {
+                // Without shift, a selection collapses to its start first.
+                let to = match (by_word, extend || self.selection().is_empty()) {
+                    (true, _) => self.word_before(self.caret),
+                    (false, true) => self.grapheme_before(self.caret),
+                    (false, false) => self.selection().start,
+                };
+                self.move_caret(to, extend);
+            }
+            Keysym::Right | Keysym::KP_Right => {
+                let to = match (by_word, extend || self.selection().is_empty()) {
+                    (true, _) => self.word_after(self.caret),
+                    (false, true) => self.grapheme_after(self.caret),
+                    (false, false) => self.selection().end,
+                };
+                self.move_caret(to, extend);
+            }
+            Keysym::Home | Keysym::KP_Home => self.move_caret(0, extend),
+            Keysym::End | Keysym::KP_End => self.move_caret(self.text.len(), extend),
+            Keysym::BackSpace => {
+                let from = match by_word {
+                    true => self.word_before(self.caret),
+                    false => self.grapheme_before(self.caret),
+                };
+                self.delete_towards(from);
+            }
+            Keysym::Delete | Keysym::KP_Delete => {
+                let to = match by_word {
+                    true => self.word_after(self.caret),
+                    false => self.grapheme_after(self.caret),
+                };
+                self.delete_towards(to);
+            }
+            Keysym::Return | Keysym::KP_Enter => return TextFieldAction::Submit,
+            Keysym::Escape if self.focused => self.set_focused(false),
+            keysym if modifiers.ctrl => return self.shortcut(keysym, modifiers),
+            _ => {
+                let Some(text) = event.utf8.as_deref() else {
+                    return TextFieldAction::Ignored;
+                };
+
+                if modifiers.alt || modifiers.logo || text.chars().any(char::is_control) {
+                    return TextFieldAction::Ignored;
+                }
+
+                self.type_text(text);
+            }
+        }
+
+        TextFieldAction::Handled
+    }
+
+    fn shortcut(&mut self, keysym: Keysym, modifiers: Modifiers) -> TextFieldAction {
+        match keysym {
+            Keysym::a | Keysym::A => self.select_all(),
+            Keysym::c | Keysym::C | Keysym::Insert => match self.selection().is_empty() {
+                true => return TextFieldAction::Handled,
+                false => return TextFieldAction::Copy(self.selected_text().to_owned()),
+            },
+            Keysym::x | Keysym::X => {
+                if self.selection().is_empty() {
+                    return TextFieldAction::Handled;
+                }
+
+                let cut = self.selected_text().to_owned();
+                self.replace_selection("", false);
+                return TextFieldAction::Copy(cut);
+            }
+            Keysym::v | Keysym::V => return TextFieldAction::Paste,
+            Keysym::z | Keysym::Z if modifiers.shift => self.redo(),
+            Keysym::z | Keysym::Z => self.undo(),
+            Keysym::y | Keysym::Y => self.redo(),
+            _ => return TextFieldAction::Ignored,
+        }
+
+        TextFieldAction::Handled
+    }
+
+    ///
+    /// Insert `text` (read from the clipboard) in place of the selection.
+    ///
+    pub fn paste(&mut self, text: &str) {
+        // Pasting multiple lines into a single one joins them.
+        let text: String = text
+            .chars()
+            .map(|c| if c == '\n' { ' ' } else { c })
+            .filter(|c| !c.is_control())
+            .collect();
+
+        self.replace_selection(&text, false);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo(&mut self) {
+        let Some(snapshot) = self.undo.pop() else {
+            return;
+        };
+
+        let current = self.snapshot();
+        self.redo.push(current);
+        self.restore(snapshot);
+    }
+
+    pub fn redo(&mut self) {
+        let Some(snapshot) = self.redo.pop() else {
+            return;
+        };
+
+        let current = self.snapshot();
+        self.undo.push(current);
+        self.restore(snapshot);
+    }
+
+    ///
+    /// Show `text` being composed by the input method at the caret, with
+    /// its cursor at `cursor` (byte offsets into `text`, as sent by
+    /// `zwp_text_input_v3.preedit_string`), if it has one. Committing it
+    /// replaces the selection.
+    ///
+    /// An empty `text` clears it.
+    ///
+    pub fn set_preedit(&mut self, text: &str, cursor: Option<Range<usize>>) {
+        let cursor = cursor.filter(|cursor| {
+            cursor.start <= cursor.end
+                && cursor.end <= text.len()
+                && text.is_char_boundary(cursor.start)
+                && text.is_char_boundary(cursor.end)
+        });
+
+        self.preedit = Preedit {
+            text: text.to_owned(),
+            cursor,
+        };
+        self.mark_dirty();
+    }
+
+    pub fn preedit(&self) -> &str {
+        &self.preedit.text
+    }
+
+    ///
+    /// Insert `text` committed by the input method in place of the
+    /// selection, replacing the text being composed.
+    ///
+    pub fn commit_string(&mut self, text: &str) {
+        self.preedit = Preedit::default();
+        self.replace_selection(text, false);
+    }
+
+    ///
+    /// Delete `before` bytes before the selection and `after` bytes after it, as
+    /// asked by the input method (`zwp_text_input_v3.delete_surrounding_text`).
+    ///
+    pub fn delete_surrounding(&mut self, before: usize, after: usize) {
+        let selection = self.selection();
+        let start = self.boundary_at(selection.start.saturating_sub(before));
+        let end = self.boundary_at((selection.end + after).min(self.text.len()));
+
+        self.edit(start..end, "", false);
+    }
+
+    ///
+    /// The text, caret and selection anchor, for the input method
+    /// (`zwp_text_input_v3.set_surrounding_text`).
+    ///
+    pub fn surrounding_text(&self) -> (&str, usize, usize) {
+        (&self.text, self.caret, self.anchor)
+    }
+
+    ///
+    /// Where the caret was last drawn, in logical pixels, e.g. for the input
+    /// method to place its candidates next to (`set_cursor_rectangle`).
+    ///
+    pub fn caret_rect(&self) -> Rect {
+        let x =
+            self.bounds.left + self.x_of(&self.display_text(), self.display_caret()) - self.scroll;
+        let (top, bottom) = self.line_extent();
+        Rect::from_xywh(x, top, CARET_WIDTH, bottom - top)
+    }
+
+    ///
+    /// Put the caret at the grapheme boundary nearest `x` (in logical pixels, as
+    /// for the bounds it was last drawn in), selecting up to it if `extend`.
+    ///
+    pub fn press(&mut self, x: f32, extend: bool) {
+        let offset = x - self.bounds.left + self.scroll;
+        let to = self
+            .boundaries()
+            .min_by(|a, b| {
+                let a = (self.x_of(&self.text, *a) - offset).abs();
+                let b = (self.x_of(&self.text, *b) - offset).abs();
+                a.total_cmp(&b)
+            })
+            .unwrap_or(0);
+
+        self.move_caret(to, extend);
+    }
+
+    ///
+    /// Select up to the grapheme boundary nearest `x` whilst dragging.
+    ///
+    pub fn drag(&mut self, x: f32) {
+        self.press(x, true);
+    }
+
+    pub fn draw(&mut self, canvas: &Canvas, context: &RenderContext, bounds: impl AsRef<Rect>) {
+        let bounds = *bounds.as_ref();
+        self.bounds = bounds;
+
+        let text = self.display_text();
+        let caret_x = self.x_of(&text, self.display_caret());
+
+        // Keep the caret in view, and no more scrolled than it takes.
+        let width = bounds.width();
+        let text_width = self.x_of(&text, text.len());
+        let margin = SCROLL_MARGIN.min(width / 2.0);
+        if caret_x - self.scroll > width - margin {
+            self.scroll = caret_x - width + margin;
+        } else if caret_x - self.scroll < margin {
+            self.scroll = caret_x - margin;
+        }
+        self.scroll = self.scroll.min(text_width + CARET_WIDTH - width).max(0.0);
+
+        let (top, bottom) = self.line_extent();
+        let origin = bounds.left - self.scroll;
+
+        canvas.save();
+        canvas.clip_rect(bounds, None, true);
+
+        let text_color = context.resolve_color(SemanticRole::Text);
+        let accent = context.resolve_color(SemanticRole::Accent);
+
+        let mut paint = Paint::new(text_color, None);
+        paint.set_anti_alias(true);
+
+        if self.preedit.text.is_empty() && !self.selection().is_empty() {
+            let selection = self.selection();
+            let mut highlight = Paint::new(accent, None);
+            highlight.set_alpha_f(if self.focused { 0.35 } else { 0.15 });
+
+            let left = origin + self.x_of(&text, selection.start);
+            let right = origin + self.x_of(&text, selection.end);
+            canvas.draw_rect(Rect::from_ltrb(left, top, right, bottom), &highlight);
+        }
+
+        let baseline = self.baseline();
+        canvas.draw_str(&text, (origin, baseline), &self.font, &paint);
+
+        // Text being composed is underlined, as it's not part of the text yet.
+        if !self.preedit.text.is_empty() {
+            let start = origin + self.x_of(&text, self.caret);
+            let end = origin + self.x_of(&text, self.caret + self.preedit.text.len());
+            let underline = Rect::from_ltrb(start, baseline + 1.0, end, baseline + 2.0);
+            canvas.draw_rect(underline, &paint);
+        }
+
+        if self.focused {
+            let caret = Rect::from_xywh(origin + caret_x, top, CARET_WIDTH, bottom - top);
+            canvas.draw_rect(caret, &Paint::new(accent, None));
+        }
+
+        canvas.restore();
+    }
+
+    ///
+    /// The text as shown: with the text being composed (if any) at the caret.
+    ///
+    fn display_text(&self) -> String {
+        let mut text = self.text.clone();
+        text.insert_str(self.caret, &self.preedit.text);
+        text
+    }
+
+    ///
+    /// Where the caret is within [TextField::display_text].
+    ///
+    fn display_caret(&self) -> usize {
+        match &self.preedit.cursor {
+            Some(cursor) => self.caret + cursor.end,
+            None => self.caret + self.preedit.text.len(),
+        }
+    }
+
+    fn x_of(&self, text: &str, index: usize) -> f32 {
+        self.font.measure_str(&text[..index], None).0
+    }
+
+    ///
+    /// Top and bottom of the line within the bounds, centered vertically.
+    ///
+    fn line_extent(&self) -> (f32, f32) {
+        let height = self.font.spacing();
+        let top = self.bounds.center_y() - height / 2.0;
+        (top, top + height)
+    }
+
+    fn baseline(&self) -> f32 {
+        let (top, _) = self.line_extent();
+        let (_, metrics) = self.font.metrics();
+        top - metrics.ascent
+    }
+
+    ///
+    /// Move the caret to `to`, selecting along the way if `extend`.
+    ///
+    fn move_caret(&mut self, to: usize, extend: bool) {
+        self.caret = to;
+        if !extend {
+            self.anchor = to;
+        }
+
+        self.typing = false;
+        self.mark_dirty();
+    }
+
+    ///
+    /// Delete the selection, or else from the caret to `to`.
+    ///
+    fn delete_towards(&mut self, to: usize) {
+        match self.selection().is_empty() {
+            true => self.edit(self.caret.min(to)..self.caret.max(to), "", false),
+            false => self.replace_selection("", false),
+        }
+    }
+
+    fn type_text(&mut self, text: &str) {
+        self.replace_selection(text, true);
+    }
+
+    fn replace_selection(&mut self, text: &str, typing: bool) {
+        self.edit(self.selection(), text, typing);
+    }
+
+    ///
+    /// Replace `range` with `text`, leaving the caret after it, as one step to
+    /// undo (along with the typing before it, if it's typing too).
+    ///
+    fn edit(&mut self, range: Range<usize>, text: &str, typing: bool) {
+        if range.is_empty() && text.is_empty() {
+            return;
+        }
+
+        if !(typing && self.typing) {
+            let snapshot = self.snapshot();
+            self.undo.push(snapshot);
+            if self.undo.len() > MAX_UNDO {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+        self.typing = typing;
+
+        self.text.replace_range(range.clone(), text);
+        self.caret = range.start + text.len();
+        self.anchor = self.caret;
+
+        self.changed();
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            text: self.text.clone(),
+            caret: self.caret,
+            anchor: self.anchor,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.text = snapshot.text;
+        self.caret = snapshot.caret;
+        self.anchor = snapshot.anchor;
+        self.typing = false;
+
+        self.changed();
+    }
+
+    fn changed(&mut self) {
+        self.mark_dirty();
+        if let Some(on_change) = self.on_change.as_mut() {
+            on_change(&self.text);
+        }
+    }
+
+    fn mark_dirty(&self) {
+        if let Some(dirty) = &self.dirty {
+            dirty.mark();
+        }
+    }
+
+    ///
+    /// Every grapheme boundary of the text, from its start to its end.
+    ///
+    fn boundaries(&self) -> impl Iterator<Item = usize> + '_ {
+        self.text
+            .grapheme_indices(true)
+            .map(|(boundary, _)| boundary)
+            .chain(std::iter::once(self.text.len()))
+    }
+
+    ///
+    /// `index`, or the grapheme boundary before it.
+    ///
+    fn boundary_at(&self, index: usize) -> usize {
+        let index = index.min(self.text.len());
+        self.boundaries()
+            .take_while(|boundary| *boundary <= index)
+            .last()
+            .unwrap_or(0)
+    }
+
+    ///
+    /// The grapheme boundary before the boundary `index`.
+    ///
+    fn grapheme_before(&self, index: usize) -> usize {
+        self.text[..index]
+            .graphemes(true)
+            .next_back()
+            .map_or(0, |grapheme| index - grapheme.len())
+    }
+
+    ///
+    /// The grapheme boundary after the boundary `index`.
+    ///
+    fn grapheme_after(&self, index: usize) -> usize {
+        self.text[index..]
+            .graphemes(true)
+            .next()
+            .map_or(self.text.len(), |grapheme| index + grapheme.len())
+    }
+
+    ///
+    /// The start of the word before `index` (or that it's in).
+    ///
+    fn word_before(&self, index: usize) -> usize {
+        let before = &self.text[..index];
+        let end = before.trim_end_matches(|c: char| !is_word(c)).len();
+        before[..end].trim_end_matches(is_word).len()
+    }
+
+    ///
+    /// The end of the word after `index` (or that it's in).
+    ///
+    fn word_after(&self, index: usize) -> usize {
+        let after = &self.text[index..];
+        let start = after.len() - after.trim_start_matches(|c: char| !is_word(c)).len();
+        let end = after[start..].trim_start_matches(is_word);
+        self.text.len() - end.len()
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL: Modifiers = Modifiers {
+        ctrl: true,
+        alt: false,
+        shift: false,
+        caps_lock: false,
+        logo: false,
+        num_lock: false,
+    };
+    const SHIFT: Modifiers = Modifiers {
+        shift: true,
+        ..NONE
+    };
+    const CTRL_SHIFT: Modifiers = Modifiers {
+        shift: true,
+        ..CTRL
+    };
+    const NONE: Modifiers = Modifiers {
+        ctrl: false,
+        ..CTRL
+    };
+
+    fn field(text: &str) -> TextField {
+        let mut field = TextField::new(&Font::default());
+        field.set_text(text);
+        field
+    }
+
+    fn key(field: &mut TextField, keysym: Keysym, modifiers: Modifiers) -> TextFieldAction {
+        let event = KeyEvent {
+            time: 0,
+            raw_code: 0,
+            keysym,
+            utf8: None,
+        };
+        field.key_press(&event, modifiers)
+    }
+
+    fn type_text(field: &mut TextField, text: &str) {
+        for c in text.chars() {
+            let event = KeyEvent {
+                time: 0,
+                raw_code: 0,
+                keysym: Keysym::NoSymbol,
+                utf8: Some(c.to_string()),
+            };
+            assert_eq!(field.key_press(&event, NONE), TextFieldAction::Handled);
+        }
+    }
+
+    #[test]
+    fn typing_and_moving_the_caret() {
+        let mut field = field("");
+        type_text(&mut field, "helo");
+        key(&mut field, Keysym::Left, NONE);
+        type_text(&mut field, "l");
+
+        assert_eq!(field.text(), "hello");
+        assert_eq!(field.caret(), 4);
+
+        key(&mut field, Keysym::Home, NONE);
+        assert_eq!(field.caret(), 0);
+        key(&mut field, Keysym::End, NONE);
+        assert_eq!(field.caret(), 5);
+    }
+
+    #[test]
+    fn moving_and_deleting_by_word() {
+        let mut field = field("open the pod_bay doors");
+
+        key(&mut field, Keysym::Left, CTRL);
+        assert_eq!(field.caret(), 17);
+        key(&mut field, Keysym::Left, CTRL);
+        assert_eq!(field.caret(), 9);
+
+        key(&mut field, Keysym::BackSpace, CTRL);
+        assert_eq!(field.text(), "open pod_bay doors");
+        assert_eq!(field.caret(), 5);
+
+        key(&mut field, Keysym::Delete, CTRL);
+        assert_eq!(field.text(), "open  doors");
+        assert_eq!(field.caret(), 5);
+    }
+
+    #[test]
+    fn selecting_with_shift() {
+        let mut field = field("hello world");
+
+        key(&mut field, Keysym::Left, CTRL_SHIFT);
+        assert_eq!(field.selection(), 6..11);
+        assert_eq!(field.selected_text(), "world");
+
+        key(&mut field, Keysym::Left, SHIFT);
+        assert_eq!(field.selection(), 5..11);
+        assert_eq!(field.caret(), 5);
+
+        // Without shift, the selection collapses to its end.
+        key(&mut field, Keysym::Right, NONE);
+        assert_eq!(field.selection(), 11..11);
+        assert_eq!(field.caret(), 11);
+
+        key(&mut field, Keysym::Left, CTRL_SHIFT);
+        type_text(&mut field, "there");
+        assert_eq!(field.text(), "hello there");
+        assert!(field.selection().is_empty());
+    }
+
+    #[test]
+    fn a_selection_collapses_towards_the_arrow() {
+        let mut field = field("hello");
+        field.select(1, 4);
+
+        key(&mut field, Keysym::Left, NONE);
+        assert_eq!((field.caret(), field.selection()), (1, 1..1));
+
+        field.select(1, 4);
+        key(&mut field, Keysym::Right, NONE);
+        assert_eq!((field.caret(), field.selection()), (4, 4..4));
+    }
+
+    #[test]
+    fn cutting_copying_and_pasting() {
+        let mut field = field("copy me");
+        key(&mut field, Keysym::a, CTRL);
+
+        assert_eq!(
+            key(&mut field, Keysym::c, CTRL),
+            TextFieldAction::Copy("copy me".to_owned())
+        );
+        assert_eq!(
+            key(&mut field, Keysym::x, CTRL),
+            TextFieldAction::Copy("copy me".to_owned())
+        );
+        assert_eq!(field.text(), "");
+
+        assert_eq!(key(&mut field, Keysym::v, CTRL), TextFieldAction::Paste);
+        field.paste("two\nlines");
+        assert_eq!(field.text(), "two lines");
+        assert_eq!(field.caret(), 9);
+    }
+
+    #[test]
+    fn typing_is_undone_at_once() {
+        let mut field = field("");
+        type_text(&mut field, "abc");
+        key(&mut field, Keysym::BackSpace, NONE);
+        assert_eq!(field.text(), "ab");
+
+        key(&mut field, Keysym::z, CTRL);
+        assert_eq!((field.text(), field.caret()), ("abc", 3));
+        key(&mut field, Keysym::z, CTRL);
+        assert_eq!((field.text(), field.caret()), ("", 0));
+        assert!(!field.can_undo());
+
+        key(&mut field, Keysym::z, CTRL_SHIFT);
+        assert_eq!(field.text(), "abc");
+    }
+
+    #[test]
+    fn emoji_are_deleted_whole() {
+        // A thumbs up with a skin tone, a family joined with zero width
+        // joiners, a flag, and an e with a combining acute accent.
+        let thumbs_up = "\u{1F44D}\u{1F3FD}";
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let flag = "\u{1F1EC}\u{1F1E7}";
+        let accented = "e\u{0301}";
+
+        let mut field = field(&format!("{thumbs_up}{family}{flag}{accented}"));
+
+        key(&mut field, Keysym::BackSpace, NONE);
+        assert_eq!(field.text(), format!("{thumbs_up}{family}{flag}"));
+        key(&mut field, Keysym::BackSpace, NONE);
+        assert_eq!(field.text(), format!("{thumbs_up}{family}"));
+
+        key(&mut field, Keysym::Home, NONE);
+        key(&mut field, Keysym::Delete, NONE);
+        assert_eq!(field.text(), family);
+        assert_eq!(field.caret(), 0);
+
+        key(&mut field, Keysym::Right, NONE);
+        assert_eq!(field.caret(), family.len());
+        key(&mut field, Keysym::BackSpace, NONE);
+        assert_eq!((field.text(), field.caret()), ("", 0));
+    }
+
+    #[test]
+    fn selections_snap_to_graphemes() {
+        let flags = "\u{1F1EC}\u{1F1E7}\u{1F1EB}\u{1F1F7}";
+        let mut field = field(flags);
+
+        // Halfway through the first flag, and inside the second's first letter.
+        field.select(4, 10);
+        assert_eq!(field.selection(), 0..8);
+        assert_eq!(field.selected_text(), "\u{1F1EC}\u{1F1E7}");
+    }
+
+    #[test]
+    fn committing_replaces_the_selection() {
+        let mut field = field("naive");
+        field.select(2, 3);
+        field.set_preedit("\u{0131}", None);
+        assert_eq!(field.preedit(), "\u{0131}");
+
+        field.commit_string("\u{00EF}");
+        assert_eq!(field.text(), "na\u{00EF}ve");
+        assert_eq!(field.preedit(), "");
+        assert_eq!(field.caret(), 4);
+    }
+
+    #[test]
+    fn changes_are_reported() {
+        let changes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = changes.clone();
+        let mut field = field("").on_change(move |text| seen.lock().unwrap().push(text.to_owned()));
+
+        type_text(&mut field, "hi");
+        key(&mut field, Keysym::Left, NONE);
+        key(&mut field, Keysym::BackSpace, NONE);
+
+        assert_eq!(*changes.lock().unwrap(), ["h", "hi", "i"]);
+    }
+}
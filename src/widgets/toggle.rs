@@ -0,0 +1,150 @@
+//!
+//! An on/off switch, see [Toggle].
+//!
+
+use skia_safe::{Canvas, Paint, PaintStyle, RRect, Rect};
+
+use crate::{
+    graphics::{RenderContext, SemanticRole},
+    util::{
+        animation::{Easing, Transition},
+        DirtyFlag,
+    },
+};
+
+use super::control::{faded, mix, Control, Interaction, STATE_TRANSITION};
+
+///
+/// Gap between the knob and the edge of the track, in logical pixels.
+///
+const KNOB_INSET: f32 = 3.0;
+
+type ChangeCallback = Box<dyn FnMut(bool) + Send>;
+
+///
+/// A switch which flips when clicked, tapped or activated from the
+/// keyboard, its knob sliding across. Drawn as a pill filling its bounds.
+///
+pub struct Toggle {
+    on: bool,
+    /// Where the knob is, from `0.0` (off) to `1.0` (on).
+    knob: Transition,
+    interaction: Interaction,
+    on_change: Option<ChangeCallback>,
+}
+
+impl Toggle {
+    pub fn new(on: bool) -> Self {
+        Self {
+            on,
+            knob: Transition::new(
+                if on { 1.0 } else { 0.0 },
+                STATE_TRANSITION * 2,
+                Easing::EaseInOut,
+            ),
+            interaction: Interaction::default(),
+            on_change: None,
+        }
+    }
+
+    ///
+    /// Mark `dirty` whenever the toggle needs redrawing.
+    ///
+    pub fn track_dirty(mut self, dirty: DirtyFlag) -> Self {
+        self.interaction.set_dirty(dirty);
+        self
+    }
+
+    ///
+    /// Call `on_change` with the new state whenever the user flips the toggle.
+    ///
+    pub fn on_change(mut self, on_change: impl FnMut(bool) + Send + 'static) -> Self {
+        self.on_change.replace(Box::new(on_change));
+        self
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    ///
+    /// Flip the toggle to `on` (sliding across), without calling [Toggle::on_change].
+    ///
+    pub fn set_on(&mut self, on: bool) {
+        if self.on == on {
+            return;
+        }
+
+        self.on = on;
+        let now = self.interaction.now();
+        self.knob.set(if on { 1.0 } else { 0.0 }, now);
+        self.interaction.mark_dirty();
+    }
+
+    pub fn draw(&mut self, canvas: &Canvas, context: &RenderContext, bounds: impl AsRef<Rect>) {
+        let bounds = *bounds.as_ref();
+        let now = context.now();
+        let interaction = &self.interaction;
+        let opacity = interaction.opacity();
+
+        let accent = context.resolve_color(SemanticRole::Accent);
+        let border = context.resolve_color(SemanticRole::Border);
+        let surface = context.resolve_color(SemanticRole::Surface);
+
+        let mut position = self.knob.value(now) as f32;
+        // On is to the start of the line, for right-to-left locales.
+        if context.is_rtl() {
+            position = 1.0 - position;
+        }
+
+        let radius = bounds.height() / 2.0;
+        let track = RRect::new_rect_xy(bounds, radius, radius);
+        let fill = mix(border, accent, self.knob.value(now) as f32);
+        let fill = mix(fill, surface, 0.15 * interaction.hover_amount(now));
+
+        let mut paint = Paint::new(faded(fill, opacity), None);
+        paint.set_anti_alias(true);
+        canvas.draw_rrect(track, &paint);
+
+        // Squashed a little whilst pressed.
+        let knob_radius =
+            (radius - KNOB_INSET).max(0.0) * (1.0 - 0.1 * interaction.press_amount(now));
+        let travel = (bounds.width() - 2.0 * radius).max(0.0);
+        let center = (bounds.left + radius + travel * position, bounds.center_y());
+
+        let mut knob = Paint::new(faded(surface, opacity), None);
+        knob.set_anti_alias(true);
+        canvas.draw_circle(center, knob_radius, &knob);
+
+        if interaction.is_focused() {
+            let mut ring = Paint::new(faded(accent, opacity), None);
+            ring.set_anti_alias(true);
+            ring.set_style(PaintStyle::Stroke);
+            ring.set_stroke_width(2.0);
+            canvas.draw_rrect(track.with_outset((3.0, 3.0)), &ring);
+        }
+
+        self.interaction.drawn(bounds, context);
+        if self.knob.is_animating(now) {
+            self.interaction.mark_dirty();
+        }
+    }
+}
+
+impl Control for Toggle {
+    fn interaction(&self) -> &Interaction {
+        &self.interaction
+    }
+
+    fn interaction_mut(&mut self) -> &mut Interaction {
+        &mut self.interaction
+    }
+
+    fn activate(&mut self) {
+        self.set_on(!self.on);
+
+        if let Some(on_change) = self.on_change.as_mut() {
+            on_change(self.on);
+        }
+    }
+}
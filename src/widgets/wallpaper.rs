@@ -0,0 +1,653 @@
+//!
+//! A wallpaper: an image (or a plain color) on the background layer of
+//! every output, fitted as set by [FitMode].
+//!
+//! The image is decoded once, into a raster image shared by every output.
+//! Outputs are drawn with [AvySurfaceHandle::render_static], so the backend
+//! only holds on to a swapchain whilst swapping images (which cross-fades),
+//! and are only redrawn when their size or scale changes.
+//!
+
+use std::{
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use skia_safe::{
+    image::CachingHint, BlendMode, Canvas, Color, Data, FilterMode, Image, Matrix, MipmapMode,
+    Paint, Rect, SamplingOptions, TileMode,
+};
+use smithay_client_toolkit::{
+    reexports::client::{protocol::wl_output::WlOutput, EventQueue},
+    shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer},
+};
+use thiserror::Error;
+
+use crate::{
+    app::AvySurfaceHandle,
+    graphics::{GraphicsBackend, RenderContext},
+    proxy::AvyProxy,
+    timer::{TimerAction, TimerToken},
+    util::Size,
+    wayland::surface::{
+        events::{Subscription, SurfaceEvent},
+        layer::{AvyLayer, AvyLayerParams, LayerError},
+    },
+    AvyClient,
+};
+
+///
+/// How long swapping images cross-fades for, by default.
+///
+pub const DEFAULT_TRANSITION: Duration = Duration::from_millis(400);
+
+///
+/// How often the cross-fade is drawn.
+///
+const FADE_FRAME: Duration = Duration::from_millis(16);
+
+#[derive(Debug, Error)]
+pub enum WallpaperError {
+    #[error("Could not read the image: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not decode the image.")]
+    Decode,
+    #[error("Could not create the wallpaper's layer: {0}")]
+    Layer(#[from] LayerError),
+    #[error("Could not set up the wallpaper's backend: {0}")]
+    Backend(Box<dyn std::error::Error>),
+}
+
+///
+/// How the image is fitted to the output.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitMode {
+    /// Scaled to cover the whole output, cropping whatever overflows.
+    #[default]
+    Cover,
+    /// Scaled to fit inside the output, the fallback color filling the rest.
+    Contain,
+    /// At its own size (a pixel per physical pixel), centered.
+    Center,
+    /// At its own size, repeated from the top-left corner.
+    Tile,
+}
+
+impl FitMode {
+    ///
+    /// Where an image of `image` size goes on a surface of `surface` size
+    /// (in the same units), or its first tile for [FitMode::Tile].
+    ///
+    /// Images which are empty (or surfaces, as yet) get an empty rect.
+    ///
+    pub fn dest_rect(self, image: (f32, f32), surface: (f32, f32)) -> Rect {
+        let ((width, height), (surface_width, surface_height)) = (image, surface);
+        if width <= 0.0 || height <= 0.0 || surface_width <= 0.0 || surface_height <= 0.0 {
+            return Rect::new_empty();
+        }
+
+        let scale = match self {
+            Self::Cover => (surface_width / width).max(surface_height / height),
+            Self::Contain => (surface_width / width).min(surface_height / height),
+            Self::Center => 1.0,
+            Self::Tile => return Rect::from_wh(width, height),
+        };
+
+        let (width, height) = (width * scale, height * scale);
+        Rect::from_xywh(
+            (surface_width - width) / 2.0,
+            (surface_height - height) / 2.0,
+            width,
+            height,
+        )
+    }
+}
+
+///
+/// What to show.
+///
+#[derive(Debug, Clone)]
+pub enum ImageSource {
+    Path(PathBuf),
+    /// An encoded image (PNG, JPEG, ...).
+    Encoded(Vec<u8>),
+    Image(Image),
+    /// Just the fallback color, see [Wallpaper::set_fallback].
+    Color(Color),
+}
+
+impl ImageSource {
+    ///
+    /// The image, decoded into memory (so that it isn't decoded again for
+    /// every output and every frame), and the fallback color it comes with.
+    ///
+    fn decode(self) -> Result<(Option<Image>, Option<Color>), WallpaperError> {
+        let image = match self {
+            Self::Path(path) => decode(&std::fs::read(path)?)?,
+            Self::Encoded(data) => decode(&data)?,
+            Self::Image(image) => image
+                .make_raster_image(None, CachingHint::Allow)
+                .ok_or(WallpaperError::Decode)?,
+            Self::Color(color) => return Ok((None, Some(color))),
+        };
+
+        Ok((Some(image), None))
+    }
+}
+
+fn decode(data: &[u8]) -> Result<Image, WallpaperError> {
+    Image::from_encoded(Data::new_copy(data))
+        .and_then(|image| image.make_raster_image(None, CachingHint::Allow))
+        .ok_or(WallpaperError::Decode)
+}
+
+///
+/// The image being faded out, see [Wallpaper::set_image].
+///
+struct Fade {
+    from: Option<Image>,
+    start: Instant,
+    duration: Duration,
+}
+
+struct WallpaperState {
+    image: Option<Image>,
+    fit: FitMode,
+    fallback: Color,
+    fade: Option<Fade>,
+}
+
+impl WallpaperState {
+    ///
+    /// How far the cross-fade has come, if one is under way.
+    ///
+    fn fade_progress(&self, now: Instant) -> Option<f32> {
+        let fade = self.fade.as_ref()?;
+        let elapsed = now.saturating_duration_since(fade.start);
+        let progress = elapsed.as_secs_f32() / fade.duration.as_secs_f32();
+
+        Some(progress.min(1.0))
+    }
+
+    fn paint(&self, canvas: &Canvas, context: &RenderContext) {
+        canvas.draw_color(self.fallback, BlendMode::Src);
+
+        match (self.fade.as_ref(), self.fade_progress(context.now())) {
+            (Some(fade), Some(progress)) => {
+                if let Some(from) = &fade.from {
+                    self.paint_image(canvas, context, from, 1.0);
+                }
+
+                match &self.image {
+                    Some(image) => self.paint_image(canvas, context, image, progress),
+                    // Fading to the fallback color.
+                    None => {
+                        let fallback = self.fallback.with_a(alpha(progress));
+                        canvas.draw_color(fallback, BlendMode::SrcOver);
+                    }
+                }
+            }
+            _ => {
+                if let Some(image) = &self.image {
+                    self.paint_image(canvas, context, image, 1.0);
+                }
+            }
+        }
+    }
+
+    fn paint_image(&self, canvas: &Canvas, context: &RenderContext, image: &Image, opacity: f32) {
+        let (width, height) = context.drawing_size;
+        let surface = (width as f32, height as f32);
+        let scale = context.scale as f32;
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_alpha(alpha(opacity));
+        let sampling = SamplingOptions::new(FilterMode::Linear, MipmapMode::None);
+
+        // A pixel of the image per physical pixel, where it's left at its own size.
+        let natural = match self.fit {
+            FitMode::Center | FitMode::Tile => {
+                (image.width() as f32 / scale, image.height() as f32 / scale)
+            }
+            FitMode::Cover | FitMode::Contain => (image.width() as f32, image.height() as f32),
+        };
+
+        if self.fit == FitMode::Tile {
+            let matrix = Matrix::scale((1.0 / scale, 1.0 / scale));
+            let Some(shader) =
+                image.to_shader((TileMode::Repeat, TileMode::Repeat), sampling, &matrix)
+            else {
+                return;
+            };
+
+            paint.set_shader(shader);
+            canvas.draw_rect(Rect::from_wh(surface.0, surface.1), &paint);
+            return;
+        }
+
+        let dest = self.fit.dest_rect(natural, surface);
+        canvas.draw_image_rect_with_sampling_options(image, None, dest, sampling, &paint);
+    }
+}
+
+fn alpha(opacity: f32) -> u8 {
+    (opacity.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+///
+/// The wallpaper on one output.
+///
+struct WallpaperOutput<G> {
+    output: WlOutput,
+    handle: AvySurfaceHandle<G>,
+    /// Redraws on configures and scale changes.
+    _subscription: Subscription,
+}
+
+///
+/// A wallpaper on the background layer of each output, see the [module docs](self).
+///
+/// Its layers are destroyed along with it.
+///
+pub struct Wallpaper<G> {
+    state: Arc<Mutex<WallpaperState>>,
+    outputs: Vec<WallpaperOutput<G>>,
+    transition: Duration,
+    fade_timer: Option<TimerToken>,
+    proxy: AvyProxy,
+}
+
+impl<G> Wallpaper<G>
+where
+    G: GraphicsBackend + 'static,
+    G::Error: 'static,
+    AvySurfaceHandle<G>: Send,
+{
+    ///
+    /// Show `source` fitted by `fit` on every output, drawn through `backend`.
+    ///
+    /// An image which can't be loaded is logged, and leaves the fallback
+    /// color (black, unless `source` is a color) showing instead.
+    ///
+    pub fn new(
+        app: &mut AvyClient,
+        event_queue: &mut EventQueue<AvyClient>,
+        backend: &G,
+        source: ImageSource,
+        fit: FitMode,
+    ) -> Result<Self, WallpaperError> {
+        let (image, fallback) = source.decode().unwrap_or_else(|err| {
+            log::warn!("Could not load the wallpaper, showing the fallback color: {err}");
+            (None, None)
+        });
+
+        let state = WallpaperState {
+            image,
+            fit,
+            fallback: fallback.unwrap_or(Color::BLACK),
+            fade: None,
+        };
+
+        let mut wallpaper = Self {
+            state: Arc::new(Mutex::new(state)),
+            outputs: Vec::new(),
+            transition: DEFAULT_TRANSITION,
+            fade_timer: None,
+            proxy: app.proxy(),
+        };
+
+        let outputs: Vec<_> = app.output_state.outputs().collect();
+        for output in outputs {
+            wallpaper.add_output(app, event_queue, backend, output)?;
+        }
+
+        Ok(wallpaper)
+    }
+
+    ///
+    /// How long [Wallpaper::set_image] cross-fades for, zero to swap straight away.
+    ///
+    pub fn with_transition(mut self, transition: Duration) -> Self {
+        self.transition = transition;
+        self
+    }
+
+    ///
+    /// Show the wallpaper on `output` too, e.g. one plugged in since.
+    ///
+    pub fn add_output(
+        &mut self,
+        app: &mut AvyClient,
+        event_queue: &mut EventQueue<AvyClient>,
+        backend: &G,
+        output: WlOutput,
+    ) -> Result<(), WallpaperError> {
+        if self.outputs.iter().any(|ours| ours.output == output) {
+            return Ok(());
+        }
+
+        let handle = AvyLayer::build(
+            app,
+            event_queue,
+            AvyLayerParams {
+                layer: Layer::Background,
+                namespace: Some("avy-wallpaper"),
+                output: Some(output.clone()),
+                anchor: Anchor::all(),
+                // Stretched over the whole output.
+                size: Size::new((0, 0)),
+                margin: None,
+                // Under everything else's exclusive zones.
+                exclusive_zone: Some(-1),
+                keyboard_interactivity: KeyboardInteractivity::None,
+                manual_configure_ack: false,
+            },
+        )?
+        .make_backend(backend)
+        .map_err(|err| WallpaperError::Backend(Box::new(err)))?;
+
+        let subscription = handle.on_event({
+            let (handle, state) = (handle.clone(), self.state.clone());
+            move |event| {
+                if matches!(
+                    event,
+                    SurfaceEvent::Configured { .. } | SurfaceEvent::ScaleChanged { .. }
+                ) {
+                    paint_static(&handle, &state);
+                }
+            }
+        });
+
+        self.outputs.push(WallpaperOutput {
+            output,
+            handle,
+            _subscription: subscription,
+        });
+
+        Ok(())
+    }
+
+    ///
+    /// Take the wallpaper off `output`, e.g. one unplugged since.
+    ///
+    pub fn remove_output(&mut self, app: &mut AvyClient, output: &WlOutput) {
+        let Some(index) = self.outputs.iter().position(|ours| &ours.output == output) else {
+            return;
+        };
+
+        let removed = self.outputs.remove(index);
+        app.destroy_surface(&removed.handle.id());
+    }
+
+    pub fn fit(&self) -> FitMode {
+        self.state.lock().unwrap().fit
+    }
+
+    pub fn set_fit(&self, fit: FitMode) {
+        self.state.lock().unwrap().fit = fit;
+        self.repaint();
+    }
+
+    pub fn fallback(&self) -> Color {
+        self.state.lock().unwrap().fallback
+    }
+
+    ///
+    /// The color shown without an image, and around images which don't
+    /// cover the whole output.
+    ///
+    pub fn set_fallback(&self, color: Color) {
+        self.state.lock().unwrap().fallback = color;
+        self.repaint();
+    }
+
+    ///
+    /// Swap to `source`, cross-fading from the current image (unless the
+    /// transition is zero, see [Wallpaper::with_transition]).
+    ///
+    /// The current image stays if `source` can't be loaded.
+    ///
+    pub fn set_image(
+        &mut self,
+        app: &mut AvyClient,
+        source: ImageSource,
+    ) -> Result<(), WallpaperError> {
+        let (image, fallback) = source.decode()?;
+
+        {
+            let mut state = self.state.lock().unwrap();
+            let from = std::mem::replace(&mut state.image, image);
+            if let Some(fallback) = fallback {
+                state.fallback = fallback;
+            }
+
+            state.fade = (!self.transition.is_zero()).then(|| Fade {
+                from,
+                start: app.clock().now(),
+                duration: self.transition,
+            });
+        }
+
+        if let Some(token) = self.fade_timer.take() {
+            app.cancel_timer(token);
+        }
+
+        if self.transition.is_zero() {
+            self.repaint();
+            return Ok(());
+        }
+
+        let handles: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|output| output.handle.clone())
+            .collect();
+        let state = self.state.clone();
+        let clock = app.clock().clone();
+
+        let timer = app.add_timer(Duration::ZERO, move |_| {
+            let progress = state.lock().unwrap().fade_progress(clock.now());
+            if progress.is_some_and(|progress| progress < 1.0) {
+                for handle in &handles {
+                    let drawn = handle
+                        .render(|canvas, context| state.lock().unwrap().paint(canvas, context));
+                    if let Err(err) = drawn {
+                        log::warn!("Could not draw the wallpaper's transition: {err}");
+                    }
+                }
+
+                return TimerAction::Repeat(FADE_FRAME);
+            }
+
+            // Back to the static fast path.
+            state.lock().unwrap().fade.take();
+            for handle in &handles {
+                paint_static(handle, &state);
+            }
+
+            TimerAction::Drop
+        });
+
+        match timer {
+            Ok(token) => {
+                self.fade_timer.replace(token);
+            }
+            Err(err) => {
+                log::debug!("Not cross-fading the wallpaper: {err}");
+                self.state.lock().unwrap().fade.take();
+                self.repaint();
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Redraw every output, e.g. after changing how the wallpaper looks.
+    ///
+    fn repaint(&self) {
+        for output in &self.outputs {
+            paint_static(&output.handle, &self.state);
+        }
+    }
+}
+
+fn paint_static<G>(handle: &AvySurfaceHandle<G>, state: &Mutex<WallpaperState>)
+where
+    G: GraphicsBackend,
+    G::Error: 'static,
+{
+    let drawn =
+        handle.render_static(|canvas, context| state.lock().unwrap().paint(canvas, context));
+    if let Err(err) = drawn {
+        log::warn!("Could not draw the wallpaper: {err}");
+    }
+}
+
+impl<G> Drop for Wallpaper<G> {
+    fn drop(&mut self) {
+        let surfaces: Vec<_> = self
+            .outputs
+            .drain(..)
+            .map(|output| output.handle.id())
+            .collect();
+        let fade_timer = self.fade_timer.take();
+
+        // Nothing to clean up once the client is gone.
+        let _ = self.proxy.invoke(move |app| {
+            if let Some(token) = fade_timer {
+                app.cancel_timer(token);
+            }
+
+            for surface in &surfaces {
+                app.destroy_surface(surface);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::Contains;
+
+    use super::*;
+
+    /// A 1920×1080 output.
+    const OUTPUT: (f32, f32) = (1920.0, 1080.0);
+
+    #[test]
+    fn cover_fills_the_output_and_crops_the_overflow() {
+        // Twice as wide as it's tall: scaled to the output's height.
+        let dest = FitMode::Cover.dest_rect((960.0, 480.0), OUTPUT);
+
+        assert_eq!(dest, Rect::from_xywh(-120.0, 0.0, 2160.0, 1080.0));
+        assert!(dest.contains(Rect::from_wh(OUTPUT.0, OUTPUT.1)));
+    }
+
+    #[test]
+    fn contain_fits_inside_the_output() {
+        // Square: scaled to the output's height, with bars either side.
+        let dest = FitMode::Contain.dest_rect((540.0, 540.0), OUTPUT);
+
+        assert_eq!(dest, Rect::from_xywh(420.0, 0.0, 1080.0, 1080.0));
+        assert!(Rect::from_wh(OUTPUT.0, OUTPUT.1).contains(dest));
+    }
+
+    #[test]
+    fn cover_and_contain_agree_on_the_output_aspect_ratio() {
+        let image = (960.0, 540.0);
+        let whole = Rect::from_wh(OUTPUT.0, OUTPUT.1);
+
+        assert_eq!(FitMode::Cover.dest_rect(image, OUTPUT), whole);
+        assert_eq!(FitMode::Contain.dest_rect(image, OUTPUT), whole);
+    }
+
+    #[test]
+    fn center_keeps_the_image_size() {
+        assert_eq!(
+            FitMode::Center.dest_rect((100.0, 50.0), OUTPUT),
+            Rect::from_xywh(910.0, 515.0, 100.0, 50.0)
+        );
+        // Larger than the output: cropped on every side.
+        assert_eq!(
+            FitMode::Center.dest_rect((4000.0, 2000.0), OUTPUT),
+            Rect::from_xywh(-1040.0, -460.0, 4000.0, 2000.0)
+        );
+    }
+
+    #[test]
+    fn tiles_start_at_the_top_left() {
+        assert_eq!(
+            FitMode::Tile.dest_rect((64.0, 32.0), OUTPUT),
+            Rect::from_wh(64.0, 32.0)
+        );
+    }
+
+    #[test]
+    fn empty_images_and_surfaces_get_empty_rects() {
+        for fit in [
+            FitMode::Cover,
+            FitMode::Contain,
+            FitMode::Center,
+            FitMode::Tile,
+        ] {
+            assert!(fit.dest_rect((0.0, 100.0), OUTPUT).is_empty());
+            assert!(fit.dest_rect((100.0, 100.0), (0.0, 0.0)).is_empty());
+        }
+    }
+
+    #[test]
+    fn fades_progress_until_done() {
+        let start = Instant::now();
+        let mut state = WallpaperState {
+            image: None,
+            fit: FitMode::Cover,
+            fallback: Color::BLACK,
+            fade: None,
+        };
+        assert_eq!(state.fade_progress(start), None);
+
+        state.fade = Some(Fade {
+            from: None,
+            start,
+            duration: Duration::from_secs(2),
+        });
+        assert_eq!(state.fade_progress(start), Some(0.0));
+        assert_eq!(
+            state.fade_progress(start + Duration::from_millis(500)),
+            Some(0.25)
+        );
+        assert_eq!(
+            state.fade_progress(start + Duration::from_secs(3)),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn opacity_maps_to_alpha() {
+        assert_eq!(alpha(0.0), 0);
+        assert_eq!(alpha(0.5), 128);
+        assert_eq!(alpha(1.0), 255);
+        assert_eq!(alpha(2.0), 255);
+        assert_eq!(alpha(-1.0), 0);
+    }
+
+    #[test]
+    fn undecodable_images_are_refused() {
+        let decoded = ImageSource::Encoded(b"not an image".to_vec()).decode();
+
+        assert!(matches!(decoded, Err(WallpaperError::Decode)));
+    }
+
+    #[test]
+    fn colors_need_no_decoding() {
+        let (image, fallback) = ImageSource::Color(Color::RED).decode().unwrap();
+
+        assert!(image.is_none());
+        assert_eq!(fallback, Some(Color::RED));
+    }
+}